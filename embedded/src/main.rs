@@ -6,6 +6,7 @@
 extern crate alloc;
 
 use alloc::string::ToString;
+use alloc::{format, vec};
 use core::alloc::Layout;
 use core::panic::PanicInfo;
 use core::str::FromStr;
@@ -14,6 +15,16 @@ use alloc_cortex_m::CortexMHeap;
 use cortex_m::asm;
 use cortex_m_rt::entry;
 use cortex_m_semihosting::{debug, hprintln};
+use miniscript::bitcoin::psbt::Psbt;
+use miniscript::bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+use miniscript::bitcoin::sighash::SighashCache;
+use miniscript::bitcoin::{
+    absolute, ecdsa, transaction, Amount, OutPoint, PublicKey, ScriptBuf, Sequence, Transaction,
+    TxIn, TxOut, Witness,
+};
+use miniscript::descriptor::{DefiniteDescriptorKey, DescriptorPublicKey};
+use miniscript::plan::Assets;
+use miniscript::psbt::{PsbtExt, PsbtSighashMsg};
 
 // this is the allocator the application will use
 #[global_allocator]
@@ -52,6 +63,50 @@ fn main() -> ! {
 
     // Estimate the satisfaction cost
     assert_eq!(desc.max_weight_to_satisfy().unwrap().to_wu(), 288);
+
+    // Plan a spend with the only key we have, then sign and finalize a PSBT
+    let secp = Secp256k1::new();
+    let sk = SecretKey::from_slice(&[1; 32]).unwrap();
+    let pk = PublicKey::new(sk.public_key(&secp));
+    let desc = miniscript::Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+        "wsh(or_d(pk({}),and_v(v:pk(0250863ad64a87ae8a2fe83c1af1a8403cb53f53e486d8511dad8a04887e5b2352),older(144))))",
+        pk
+    ))
+    .unwrap();
+    let assets = Assets::new().add(DescriptorPublicKey::from_str(&pk.to_string()).unwrap());
+    let plan = desc.clone().plan(&assets).unwrap();
+    hprintln!("satisfaction weight {}", plan.satisfaction_weight()).unwrap();
+    assert_eq!(plan.satisfaction_weight(), 78);
+
+    let tx = Transaction {
+        version: transaction::Version::TWO,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::MAX,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(90_000),
+            script_pubkey: desc.script_pubkey(),
+        }],
+    };
+    let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+    psbt.inputs[0].witness_utxo =
+        Some(TxOut { value: Amount::from_sat(100_000), script_pubkey: desc.script_pubkey() });
+    psbt.update_input_with_descriptor(0, &desc).unwrap();
+    plan.update_psbt_input(&mut psbt.inputs[0]);
+
+    let mut cache = SighashCache::new(&psbt.unsigned_tx);
+    let msg = match psbt.sighash_msg(0, &mut cache, None).unwrap() {
+        PsbtSighashMsg::SegwitV0Sighash(sighash) => Message::from(sighash),
+        _ => unreachable!("wsh inputs have segwit v0 sighashes"),
+    };
+    let sig = ecdsa::Signature::sighash_all(secp.sign_ecdsa(&msg, &sk));
+    psbt.inputs[0].partial_sigs.insert(pk, sig);
+    psbt.finalize_mut(&secp).unwrap();
+    assert!(psbt.inputs[0].final_script_witness.is_some());
     // end miniscript test
 
     // exit QEMU
@@ -57,7 +57,7 @@ fn main() {
 
     // Descriptor type and version should match respectively for taproot
     let desc_type = desc.desc_type();
-    assert_eq!(desc_type, DescriptorType::Tr);
+    assert_eq!(desc_type, DescriptorType::TrScript);
     assert_eq!(desc_type.segwit_version().unwrap(), WitnessVersion::V1);
 
     if let Descriptor::Tr(ref p) = desc {
@@ -0,0 +1,133 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Miniscript macros
+//!
+//! Macros parsing and type-checking descriptors and Miniscripts at compile
+//! time, so that typos in hard-coded descriptors are caught by the compiler
+//! rather than when the descriptors are parsed at runtime.
+//!
+//! The macros use the `miniscript` crate to parse their input, which is why
+//! they are in this separate crate rather than re-exported by `miniscript`:
+//! the two crates would otherwise depend on each other.
+
+#![warn(missing_docs)]
+#![deny(unsafe_code)]
+
+use core::str::FromStr;
+
+use miniscript::{
+    BareCtx, Descriptor, DescriptorPublicKey, Legacy, Miniscript, ScriptContext, Segwitv0, Tap,
+};
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::{parse_macro_input, Ident, LitStr, Token};
+
+/// Parses a descriptor with [`DescriptorPublicKey`] keys, failing to compile
+/// if it is invalid.
+///
+/// The descriptor is checked exactly as by `Descriptor::from_str`, which it
+/// expands to.
+///
+/// # Examples
+///
+/// ```
+/// use miniscript_macros::descriptor;
+///
+/// let desc = descriptor!("wpkh(02e96fe52ef0e22d2f131dd425ce1893073a3c6ad20e8cac36726393dfb4856a4c)");
+/// assert_eq!(desc.desc_type(), miniscript::descriptor::DescriptorType::Wpkh);
+/// ```
+///
+/// ```compile_fail
+/// use miniscript_macros::descriptor;
+///
+/// // Missing closing parenthesis
+/// let desc = descriptor!("wpkh(02e96fe52ef0e22d2f131dd425ce1893073a3c6ad20e8cac36726393dfb4856a4c");
+/// ```
+#[proc_macro]
+pub fn descriptor(input: TokenStream) -> TokenStream {
+    let lit = parse_macro_input!(input as LitStr);
+    if let Err(e) = Descriptor::<DescriptorPublicKey>::from_str(&lit.value()) {
+        return error(&lit, "descriptor", e);
+    }
+    quote! {
+        <::miniscript::Descriptor<::miniscript::DescriptorPublicKey> as ::core::str::FromStr>
+            ::from_str(#lit)
+            .expect("descriptor checked at compile time")
+    }
+    .into()
+}
+
+/// Parses a Miniscript with [`DescriptorPublicKey`] keys in the given script
+/// context, one of `BareCtx`, `Legacy`, `Segwitv0` and `Tap`, failing to
+/// compile if it is invalid.
+///
+/// The Miniscript is checked exactly as by `Miniscript::from_str`, which it
+/// expands to.
+///
+/// # Examples
+///
+/// ```
+/// use miniscript_macros::miniscript;
+///
+/// let ms = miniscript!(Segwitv0, "and_v(v:pk(02e96fe52ef0e22d2f131dd425ce1893073a3c6ad20e8cac36726393dfb4856a4c),older(144))");
+/// assert_eq!(ms.max_satisfaction_witness_elements().unwrap(), 2);
+/// ```
+///
+/// ```compile_fail
+/// use miniscript_macros::miniscript;
+///
+/// // `older` alone does not require a signature
+/// let ms = miniscript!(Segwitv0, "older(144)");
+/// ```
+#[proc_macro]
+pub fn miniscript(input: TokenStream) -> TokenStream {
+    let MiniscriptInput { ctx, lit } = parse_macro_input!(input as MiniscriptInput);
+    let s = lit.value();
+    let checked = match ctx.to_string().as_str() {
+        "BareCtx" => check_miniscript::<BareCtx>(&s),
+        "Legacy" => check_miniscript::<Legacy>(&s),
+        "Segwitv0" => check_miniscript::<Segwitv0>(&s),
+        "Tap" => check_miniscript::<Tap>(&s),
+        _ => {
+            let msg = "expected one of `BareCtx`, `Legacy`, `Segwitv0` or `Tap`";
+            return syn::Error::new(ctx.span(), msg).to_compile_error().into();
+        }
+    };
+    if let Err(e) = checked {
+        return error(&lit, "Miniscript", e);
+    }
+    quote! {
+        <::miniscript::Miniscript<::miniscript::DescriptorPublicKey, ::miniscript::#ctx> as ::core::str::FromStr>
+            ::from_str(#lit)
+            .expect("Miniscript checked at compile time")
+    }
+    .into()
+}
+
+// The input of the `miniscript!` macro, a script context and a string
+struct MiniscriptInput {
+    ctx: Ident,
+    lit: LitStr,
+}
+
+impl Parse for MiniscriptInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let ctx = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let lit = input.parse()?;
+        Ok(MiniscriptInput { ctx, lit })
+    }
+}
+
+// Checks that `s` is a valid Miniscript in the context `Ctx`
+fn check_miniscript<Ctx: ScriptContext>(s: &str) -> Result<(), miniscript::Error> {
+    Miniscript::<DescriptorPublicKey, Ctx>::from_str(s).map(|_| ())
+}
+
+// A compile error pointing at the string which failed to parse
+fn error(lit: &LitStr, what: &str, e: miniscript::Error) -> TokenStream {
+    syn::Error::new(lit.span(), format!("invalid {}: {}", what, e))
+        .to_compile_error()
+        .into()
+}
@@ -297,6 +297,127 @@ deep_taproot_bigtree!(parse_descriptor_tr_deep_bigtree_g_100, 100);
 deep_taproot_bigtree!(parse_descriptor_tr_deep_bigtree_h_128, 128);
 // taproot trees are not allowed to be 129 deep
 
+mod trusted_parse_benches {
+    use super::*;
+
+    // Descriptors as a wallet would store them, with their checksums
+    fn wallet_descriptors() -> Vec<String> {
+        let tr = format!(
+            "tr(02b35c601492528601122c0807fa1f8bf987b9704dff438b2524d979b954e206fb,{})",
+            generate_balanced_tree_str(100, |l, r| format!("{{{l},{r}}}"))
+        );
+        let wsh =
+            format!("wsh({})", generate_balanced_tree_str(40, |l, r| format!("or_i({l},{r})")));
+        [tr, wsh]
+            .iter()
+            .map(|s| Desc::from_str(s).unwrap().to_string())
+            .collect()
+    }
+
+    #[bench]
+    pub fn parse_wallet_descriptors(bh: &mut Bencher) {
+        let descs = wallet_descriptors();
+        bh.iter(|| {
+            for desc in &descs {
+                black_box(Desc::from_str(desc).unwrap());
+            }
+        })
+    }
+
+    #[bench]
+    pub fn parse_wallet_descriptors_trusted(bh: &mut Bencher) {
+        let descs = wallet_descriptors();
+        bh.iter(|| {
+            for desc in &descs {
+                black_box(Desc::from_str_trusted(desc).unwrap());
+            }
+        })
+    }
+}
+
+mod satisfy_benches {
+    use super::*;
+    use crate::descriptor::DefiniteDescriptorKey;
+    use crate::plan::Assets;
+    use crate::prelude::*;
+
+    /// Generates a `wsh(thresh(k,...))` descriptor over `n` keys, along with assets
+    /// for all the keys.
+    fn thresh_desc(k: usize, n: usize) -> (Descriptor<DefiniteDescriptorKey>, Assets) {
+        let keys = (1..=n as u32).map(keygen).collect::<Vec<_>>();
+        let children = keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| {
+                if i == 0 {
+                    format!("pk({})", key)
+                } else {
+                    format!("s:pk({})", key)
+                }
+            })
+            .collect::<Vec<_>>();
+        let desc = Desc::from_str(&format!("wsh(thresh({},{}))", k, children.join(",")))
+            .unwrap()
+            .at_derivation_index(0)
+            .unwrap();
+        let assets = Assets::new().add(keys);
+        (desc, assets)
+    }
+
+    macro_rules! thresh_plan {
+        ($name:ident, $k:expr, $n:expr) => {
+            #[bench]
+            fn $name(bh: &mut Bencher) {
+                let (desc, assets) = thresh_desc($k, $n);
+                bh.iter(|| black_box(desc.clone().plan(&assets).unwrap()))
+            }
+        };
+    }
+
+    thresh_plan!(plan_thresh_a_1_of_3, 1, 3);
+    thresh_plan!(plan_thresh_b_2_of_3, 2, 3);
+    thresh_plan!(plan_thresh_c_3_of_5, 3, 5);
+    thresh_plan!(plan_thresh_d_10_of_20, 10, 20);
+    thresh_plan!(plan_thresh_e_20_of_40, 20, 40);
+
+    /// Generates a `wsh(thresh(k,...))` descriptor over `n` keys, every other one
+    /// of which is timelocked, along with assets for all the keys and timelocks.
+    fn thresh_mixed_desc(k: usize, n: usize) -> (Descriptor<DefiniteDescriptorKey>, Assets) {
+        let keys = (1..=n as u32).map(keygen).collect::<Vec<_>>();
+        let children = keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| match (i, i % 2) {
+                (0, _) => format!("pk({})", key),
+                (_, 0) => format!("s:pk({})", key),
+                _ => format!("a:andor(pk({}),n:after({}),0)", key, 100 + i),
+            })
+            .collect::<Vec<_>>();
+        let desc = Desc::from_str(&format!("wsh(thresh({},{}))", k, children.join(",")))
+            .unwrap()
+            .at_derivation_index(0)
+            .unwrap();
+        let assets = Assets::new()
+            .add(keys)
+            .after(bitcoin::absolute::LockTime::from_height(100 + n as u32).unwrap());
+        (desc, assets)
+    }
+
+    macro_rules! thresh_mixed_plan {
+        ($name:ident, $k:expr, $n:expr) => {
+            #[bench]
+            fn $name(bh: &mut Bencher) {
+                let (desc, assets) = thresh_mixed_desc($k, $n);
+                bh.iter(|| black_box(desc.clone().plan(&assets).unwrap()))
+            }
+        };
+    }
+
+    thresh_mixed_plan!(plan_thresh_mixed_a_1_of_3, 1, 3);
+    thresh_mixed_plan!(plan_thresh_mixed_b_2_of_4, 2, 4);
+    thresh_mixed_plan!(plan_thresh_mixed_c_10_of_20, 10, 20);
+}
+
 #[cfg(feature = "compiler")]
 mod compiler_benches {
     use super::*;
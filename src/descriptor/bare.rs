@@ -9,43 +9,45 @@
 
 use core::fmt;
 
+use bitcoin::hashes::hash160;
 use bitcoin::script::{self, PushBytes};
-use bitcoin::{Address, Network, ScriptBuf, Weight};
+use bitcoin::{opcodes, Address, Network, ScriptBuf, Weight};
 
 use crate::descriptor::{write_descriptor, DefiniteDescriptorKey};
 use crate::expression::{self, FromTree};
 use crate::miniscript::context::{ScriptContext, ScriptContextError};
+use crate::miniscript::extension::{Extension, NoExt};
 use crate::miniscript::satisfy::{Placeholder, Satisfaction, Witness};
-use crate::plan::AssetProvider;
+use crate::plan::{AssetProvider, MissingAsset, MissingAssets};
 use crate::policy::{semantic, Liftable};
 use crate::prelude::*;
-use crate::util::{varint_len, witness_to_scriptsig};
+use crate::util::{take_script_bytes, varint_len, witness_to_scriptsig};
 use crate::{
-    BareCtx, Error, ForEachKey, FromStrKey, Miniscript, MiniscriptKey, Satisfier, ToPublicKey,
-    TranslateErr, Translator,
+    BareCtx, Error, ForEachKey, FromStrKey, Miniscript, MiniscriptKey, Satisfier, SatisfyError,
+    ToPublicKey, TranslateErr, Translator,
 };
 
 /// Create a Bare Descriptor. That is descriptor that is
 /// not wrapped in sh or wsh. This covers the Pk descriptor
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
-pub struct Bare<Pk: MiniscriptKey> {
+pub struct Bare<Pk: MiniscriptKey, Ext: Extension = NoExt> {
     /// underlying miniscript
-    ms: Miniscript<Pk, BareCtx>,
+    ms: Miniscript<Pk, BareCtx, Ext>,
 }
 
-impl<Pk: MiniscriptKey> Bare<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> Bare<Pk, Ext> {
     /// Create a new raw descriptor
-    pub fn new(ms: Miniscript<Pk, BareCtx>) -> Result<Self, Error> {
+    pub fn new(ms: Miniscript<Pk, BareCtx, Ext>) -> Result<Self, Error> {
         // do the top-level checks
         BareCtx::top_level_checks(&ms)?;
         Ok(Self { ms })
     }
 
     /// get the inner
-    pub fn into_inner(self) -> Miniscript<Pk, BareCtx> { self.ms }
+    pub fn into_inner(self) -> Miniscript<Pk, BareCtx, Ext> { self.ms }
 
     /// get the inner
-    pub fn as_inner(&self) -> &Miniscript<Pk, BareCtx> { &self.ms }
+    pub fn as_inner(&self) -> &Miniscript<Pk, BareCtx, Ext> { &self.ms }
 
     /// Checks whether the descriptor is safe.
     pub fn sanity_check(&self) -> Result<(), Error> {
@@ -71,7 +73,7 @@ impl<Pk: MiniscriptKey> Bare<Pk> {
         // scriptSig varint difference between non-satisfied (0) and satisfied
         let scriptsig_varint_diff = varint_len(scriptsig_size) - varint_len(0);
         Weight::from_vb((scriptsig_varint_diff + scriptsig_size) as u64)
-            .ok_or(Error::CouldNotSatisfy)
+            .ok_or(Error::Satisfy(SatisfyError::CouldNotSatisfy))
     }
 
     /// Computes an upper bound on the weight of a satisfying witness to the
@@ -93,18 +95,38 @@ impl<Pk: MiniscriptKey> Bare<Pk> {
     }
 
     /// Converts the keys in the script from one type to another.
-    pub fn translate_pk<T>(&self, t: &mut T) -> Result<Bare<T::TargetPk>, TranslateErr<T::Error>>
+    pub fn translate_pk<T>(
+        &self,
+        t: &mut T,
+    ) -> Result<Bare<T::TargetPk, Ext>, TranslateErr<T::Error>>
     where
         T: Translator<Pk>,
     {
         Bare::new(self.ms.translate_pk(t)?).map_err(TranslateErr::OuterError)
     }
+
+    /// Substitutes all raw public key hashes in the script with the public keys
+    /// as provided by map, failing with the set of unresolved hashes if any are missing.
+    pub fn resolve_raw_pkh(
+        &self,
+        pk_map: &BTreeMap<hash160::Hash, Pk>,
+    ) -> Result<Bare<Pk, Ext>, BTreeSet<hash160::Hash>> {
+        Ok(Bare { ms: self.ms.resolve_raw_pkh(pk_map)? })
+    }
 }
 
-impl<Pk: MiniscriptKey + ToPublicKey> Bare<Pk> {
+impl<Pk: MiniscriptKey + ToPublicKey, Ext: Extension> Bare<Pk, Ext> {
     /// Obtains the corresponding script pubkey for this descriptor.
     pub fn script_pubkey(&self) -> ScriptBuf { self.ms.encode() }
 
+    /// Computes the script pubkey like [`Bare::script_pubkey`], writing it
+    /// into `script` to reuse its allocation.
+    pub fn script_pubkey_into(&self, script: &mut ScriptBuf) {
+        let mut bytes = take_script_bytes(script);
+        self.ms.encode_to(&mut bytes);
+        *script = ScriptBuf::from(bytes);
+    }
+
     /// Obtains the underlying miniscript for this descriptor.
     pub fn inner_script(&self) -> ScriptBuf { self.script_pubkey() }
 
@@ -160,13 +182,21 @@ impl Bare<DefiniteDescriptorKey> {
     {
         self.ms.build_template_mall(provider)
     }
+
+    /// Returns the assets missing from `provider` for each candidate spending path
+    pub fn missing_assets<P>(&self, provider: &P) -> MissingAssets<DefiniteDescriptorKey>
+    where
+        P: AssetProvider<DefiniteDescriptorKey>,
+    {
+        self.ms.missing_assets(provider)
+    }
 }
 
-impl<Pk: MiniscriptKey> fmt::Debug for Bare<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> fmt::Debug for Bare<Pk, Ext> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "{:?}", self.ms) }
 }
 
-impl<Pk: MiniscriptKey> fmt::Display for Bare<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> fmt::Display for Bare<Pk, Ext> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write_descriptor!(f, "{}", self.ms) }
 }
 
@@ -174,15 +204,15 @@ impl<Pk: MiniscriptKey> Liftable<Pk> for Bare<Pk> {
     fn lift(&self) -> Result<semantic::Policy<Pk>, Error> { self.ms.lift() }
 }
 
-impl<Pk: FromStrKey> FromTree for Bare<Pk> {
+impl<Pk: FromStrKey, Ext: Extension> FromTree for Bare<Pk, Ext> {
     fn from_tree(root: expression::TreeIterItem) -> Result<Self, Error> {
-        let sub = Miniscript::<Pk, BareCtx>::from_tree(root)?;
+        let sub = Miniscript::<Pk, BareCtx, Ext>::from_tree(root)?;
         BareCtx::top_level_checks(&sub)?;
         Bare::new(sub)
     }
 }
 
-impl<Pk: FromStrKey> core::str::FromStr for Bare<Pk> {
+impl<Pk: FromStrKey, Ext: Extension> core::str::FromStr for Bare<Pk, Ext> {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let top = expression::Tree::from_str(s)?;
@@ -190,7 +220,7 @@ impl<Pk: FromStrKey> core::str::FromStr for Bare<Pk> {
     }
 }
 
-impl<Pk: MiniscriptKey> ForEachKey<Pk> for Bare<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> ForEachKey<Pk> for Bare<Pk, Ext> {
     fn for_each_key<'a, F: FnMut(&'a Pk) -> bool>(&'a self, pred: F) -> bool {
         self.ms.for_each_key(pred)
     }
@@ -274,6 +304,18 @@ impl<Pk: MiniscriptKey + ToPublicKey> Pkh<Pk> {
         addr.script_pubkey()
     }
 
+    /// Computes the script pubkey like [`Pkh::script_pubkey`], writing it
+    /// into `script` to reuse its allocation.
+    pub fn script_pubkey_into(&self, script: &mut ScriptBuf) {
+        *script = script::Builder::from(take_script_bytes(script))
+            .push_opcode(opcodes::all::OP_DUP)
+            .push_opcode(opcodes::all::OP_HASH160)
+            .push_slice(self.pk.to_public_key().pubkey_hash())
+            .push_opcode(opcodes::all::OP_EQUALVERIFY)
+            .push_opcode(opcodes::all::OP_CHECKSIG)
+            .into_script();
+    }
+
     /// Obtains the corresponding script pubkey for this descriptor.
     pub fn address(&self, network: Network) -> Address {
         Address::p2pkh(self.pk.to_public_key(), network)
@@ -303,7 +345,7 @@ impl<Pk: MiniscriptKey + ToPublicKey> Pkh<Pk> {
             let witness = vec![];
             Ok((witness, script_sig))
         } else {
-            Err(Error::MissingSig(self.pk.to_public_key()))
+            Err(Error::Satisfy(SatisfyError::MissingSig(self.pk.to_public_key())))
         }
     }
 
@@ -350,6 +392,15 @@ impl Pkh<DefiniteDescriptorKey> {
     {
         self.plan_satisfaction(provider)
     }
+
+    /// Returns the assets missing from `provider` for each candidate spending path
+    pub fn missing_assets<P>(&self, provider: &P) -> MissingAssets<DefiniteDescriptorKey>
+    where
+        P: AssetProvider<DefiniteDescriptorKey>,
+    {
+        let available = provider.provider_lookup_ecdsa_sig(&self.pk);
+        MissingAssets::requires(MissingAsset::Signature(self.pk.clone()), available)
+    }
 }
 
 impl<Pk: MiniscriptKey> fmt::Debug for Pkh<Pk> {
@@ -0,0 +1,903 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Compact Binary Encoding
+//!
+//! Implements a versioned binary encoding for descriptors over
+//! [`DescriptorPublicKey`]s. The encoding is much smaller than the string
+//! form, mostly because extended keys are stored as their raw 78-byte
+//! serialization rather than in base58, and it can be decoded without
+//! any string tokenization.
+//!
+//! The format is a version byte followed by a descriptor type tag and the
+//! descriptor's contents. Miniscripts and taproot trees are stored as a node
+//! count followed by their nodes in post-order, so that both encoding and
+//! decoding are non-recursive. Integers are stored as Bitcoin `CompactSize`
+//! values, except for derivation path steps and fingerprints which are stored
+//! as fixed-width little-endian and big-endian values respectively.
+//!
+//! Decoding runs the same context checks as parsing from a string, and on
+//! top of those rejects multipath keys with fewer than two derivation paths or
+//! with paths of differing lengths, and miniscripts which do not pass the
+//! [`ExtParams::sane`] checks. A successfully decoded descriptor is therefore
+//! always sane, and `Descriptor::from_bytes(&desc.to_bytes())` gives back
+//! `desc` for every sane descriptor. Insane descriptors, such as ones parsed
+//! with [`Miniscript::from_str_ext`], can be encoded but not decoded.
+//!
+
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error;
+
+use bitcoin::bip32;
+use bitcoin::hashes::{hash160, ripemd160, sha256, Hash};
+use bitcoin::secp256k1::XOnlyPublicKey;
+
+use super::{
+    DerivPaths, Descriptor, DescriptorMultiXKey, DescriptorPublicKey, DescriptorXKey, ShInner,
+    SinglePub, SinglePubKey, SortedMultiVec, TapTree, Wildcard, WshInner,
+};
+use crate::iter::TreeLike;
+use crate::miniscript::analyzable::ExtParams;
+use crate::miniscript::context::ScriptContext;
+use crate::miniscript::decode::Terminal;
+use crate::prelude::*;
+use crate::sync::Arc;
+use crate::{hash256, AbsLockTime, Error, Miniscript, RelLockTime, Threshold};
+
+/// The current version of the binary encoding.
+pub const BINARY_ENCODING_VERSION: u8 = 0;
+
+// Descriptor type tags.
+const DESC_BARE: u8 = 0;
+const DESC_PKH: u8 = 1;
+const DESC_WPKH: u8 = 2;
+const DESC_SH: u8 = 3;
+const DESC_SH_WPKH: u8 = 4;
+const DESC_SH_WSH: u8 = 5;
+const DESC_SH_SORTEDMULTI: u8 = 6;
+const DESC_SH_WSH_SORTEDMULTI: u8 = 7;
+const DESC_WSH: u8 = 8;
+const DESC_WSH_SORTEDMULTI: u8 = 9;
+const DESC_TR: u8 = 10;
+
+// Key type tags.
+const KEY_COMPRESSED: u8 = 0;
+const KEY_UNCOMPRESSED: u8 = 1;
+const KEY_X_ONLY: u8 = 2;
+const KEY_XPUB: u8 = 3;
+const KEY_MULTI_XPUB: u8 = 4;
+
+// Taproot tree node tags.
+const TREE_LEAF: u8 = 0;
+const TREE_BRANCH: u8 = 1;
+
+// Miniscript fragment tags.
+const MS_TRUE: u8 = 0;
+const MS_FALSE: u8 = 1;
+const MS_PK_K: u8 = 2;
+const MS_PK_H: u8 = 3;
+const MS_RAW_PK_H: u8 = 4;
+const MS_AFTER: u8 = 5;
+const MS_OLDER: u8 = 6;
+const MS_SHA256: u8 = 7;
+const MS_HASH256: u8 = 8;
+const MS_RIPEMD160: u8 = 9;
+const MS_HASH160: u8 = 10;
+const MS_ALT: u8 = 11;
+const MS_SWAP: u8 = 12;
+const MS_CHECK: u8 = 13;
+const MS_DUP_IF: u8 = 14;
+const MS_VERIFY: u8 = 15;
+const MS_NON_ZERO: u8 = 16;
+const MS_ZERO_NOT_EQUAL: u8 = 17;
+const MS_AND_V: u8 = 18;
+const MS_AND_B: u8 = 19;
+const MS_AND_OR: u8 = 20;
+const MS_OR_B: u8 = 21;
+const MS_OR_D: u8 = 22;
+const MS_OR_C: u8 = 23;
+const MS_OR_I: u8 = 24;
+const MS_THRESH: u8 = 25;
+const MS_MULTI: u8 = 26;
+const MS_MULTI_A: u8 = 27;
+
+/// An error decoding a descriptor from its binary encoding.
+#[derive(Debug)]
+pub enum BinaryDecodeError {
+    /// The encoding version is not supported by this library.
+    UnsupportedVersion(u8),
+    /// The data ended before the descriptor was complete.
+    UnexpectedEnd,
+    /// There was data left over after the descriptor was complete.
+    TrailingBytes(usize),
+    /// A type tag was not recognized.
+    InvalidTag {
+        /// What kind of object the tag was for.
+        kind: &'static str,
+        /// The unrecognized tag.
+        tag: u8,
+    },
+    /// A length or count was out of range.
+    InvalidLength(u64),
+    /// A node count did not match the structure of the encoded tree.
+    MalformedTree,
+    /// A public key or extended public key could not be decoded.
+    InvalidKey(&'static str),
+    /// The decoded data did not form a valid descriptor.
+    Descriptor(Error),
+}
+
+impl fmt::Display for BinaryDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BinaryDecodeError::UnsupportedVersion(v) => {
+                write!(f, "unsupported binary encoding version {}", v)
+            }
+            BinaryDecodeError::UnexpectedEnd => f.write_str("unexpected end of data"),
+            BinaryDecodeError::TrailingBytes(n) => write!(f, "{} trailing bytes", n),
+            BinaryDecodeError::InvalidTag { kind, tag } => {
+                write!(f, "invalid {} tag {}", kind, tag)
+            }
+            BinaryDecodeError::InvalidLength(n) => write!(f, "invalid length {}", n),
+            BinaryDecodeError::MalformedTree => f.write_str("malformed tree encoding"),
+            BinaryDecodeError::InvalidKey(kind) => write!(f, "invalid {}", kind),
+            BinaryDecodeError::Descriptor(ref e) => e.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for BinaryDecodeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            BinaryDecodeError::Descriptor(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[doc(hidden)]
+impl From<Error> for BinaryDecodeError {
+    fn from(e: Error) -> Self { BinaryDecodeError::Descriptor(e) }
+}
+
+impl Descriptor<DescriptorPublicKey> {
+    /// Serializes the descriptor in its compact binary encoding.
+    ///
+    /// See [`Descriptor::from_bytes`] for the inverse operation.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = Vec::with_capacity(128);
+        w.push(BINARY_ENCODING_VERSION);
+        match *self {
+            Descriptor::Bare(ref bare) => {
+                w.push(DESC_BARE);
+                encode_ms(&mut w, bare.as_inner());
+            }
+            Descriptor::Pkh(ref pkh) => {
+                w.push(DESC_PKH);
+                encode_key(&mut w, pkh.as_inner());
+            }
+            Descriptor::Wpkh(ref wpkh) => {
+                w.push(DESC_WPKH);
+                encode_key(&mut w, wpkh.as_inner());
+            }
+            Descriptor::Sh(ref sh) => match *sh.as_inner() {
+                ShInner::Wsh(ref wsh) => match *wsh.as_inner() {
+                    WshInner::SortedMulti(ref smv) => {
+                        w.push(DESC_SH_WSH_SORTEDMULTI);
+                        encode_sortedmulti(&mut w, smv);
+                    }
+                    WshInner::Ms(ref ms) => {
+                        w.push(DESC_SH_WSH);
+                        encode_ms(&mut w, ms);
+                    }
+                },
+                ShInner::Wpkh(ref wpkh) => {
+                    w.push(DESC_SH_WPKH);
+                    encode_key(&mut w, wpkh.as_inner());
+                }
+                ShInner::SortedMulti(ref smv) => {
+                    w.push(DESC_SH_SORTEDMULTI);
+                    encode_sortedmulti(&mut w, smv);
+                }
+                ShInner::Ms(ref ms) => {
+                    w.push(DESC_SH);
+                    encode_ms(&mut w, ms);
+                }
+            },
+            Descriptor::Wsh(ref wsh) => match *wsh.as_inner() {
+                WshInner::SortedMulti(ref smv) => {
+                    w.push(DESC_WSH_SORTEDMULTI);
+                    encode_sortedmulti(&mut w, smv);
+                }
+                WshInner::Ms(ref ms) => {
+                    w.push(DESC_WSH);
+                    encode_ms(&mut w, ms);
+                }
+            },
+            Descriptor::Tr(ref tr) => {
+                w.push(DESC_TR);
+                encode_key(&mut w, tr.internal_key());
+                match *tr.tap_tree() {
+                    Some(ref tree) => encode_tap_tree(&mut w, tree),
+                    None => write_compact_size(&mut w, 0),
+                }
+            }
+        }
+        w
+    }
+
+    /// Parses a descriptor from its compact binary encoding.
+    ///
+    /// The decoded descriptor is subject to the same checks as one parsed from
+    /// a string, and its miniscripts must also pass the [`ExtParams::sane`]
+    /// checks.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BinaryDecodeError> {
+        let mut r = Reader { data: bytes };
+        let version = r.read_u8()?;
+        if version != BINARY_ENCODING_VERSION {
+            return Err(BinaryDecodeError::UnsupportedVersion(version));
+        }
+
+        let desc = match r.read_u8()? {
+            DESC_BARE => Descriptor::new_bare(decode_ms(&mut r)?)?,
+            DESC_PKH => Descriptor::new_pkh(decode_key(&mut r)?)?,
+            DESC_WPKH => Descriptor::new_wpkh(decode_key(&mut r)?)?,
+            DESC_SH => Descriptor::new_sh(decode_ms(&mut r)?)?,
+            DESC_SH_WPKH => Descriptor::new_sh_wpkh(decode_key(&mut r)?)?,
+            DESC_SH_WSH => Descriptor::new_sh_wsh(decode_ms(&mut r)?)?,
+            DESC_SH_SORTEDMULTI => {
+                let (k, pks) = decode_keys(&mut r)?;
+                Descriptor::new_sh_sortedmulti(k, pks)?
+            }
+            DESC_SH_WSH_SORTEDMULTI => {
+                let (k, pks) = decode_keys(&mut r)?;
+                Descriptor::new_sh_wsh_sortedmulti(k, pks)?
+            }
+            DESC_WSH => Descriptor::new_wsh(decode_ms(&mut r)?)?,
+            DESC_WSH_SORTEDMULTI => {
+                let (k, pks) = decode_keys(&mut r)?;
+                Descriptor::new_wsh_sortedmulti(k, pks)?
+            }
+            DESC_TR => {
+                let internal_key = decode_key(&mut r)?;
+                let tree = decode_tap_tree(&mut r)?;
+                Descriptor::new_tr(internal_key, tree)?
+            }
+            tag => return Err(BinaryDecodeError::InvalidTag { kind: "descriptor", tag }),
+        };
+
+        if !r.data.is_empty() {
+            return Err(BinaryDecodeError::TrailingBytes(r.data.len()));
+        }
+        Ok(desc)
+    }
+}
+
+/// Cursor over a byte slice being decoded.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn read_slice(&mut self, n: usize) -> Result<&'a [u8], BinaryDecodeError> {
+        if self.data.len() < n {
+            return Err(BinaryDecodeError::UnexpectedEnd);
+        }
+        let (ret, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(ret)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], BinaryDecodeError> {
+        let mut ret = [0; N];
+        ret.copy_from_slice(self.read_slice(N)?);
+        Ok(ret)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, BinaryDecodeError> { Ok(self.read_array::<1>()?[0]) }
+
+    fn read_compact_size(&mut self) -> Result<u64, BinaryDecodeError> {
+        match self.read_u8()? {
+            0xfd => Ok(u16::from_le_bytes(self.read_array()?).into()),
+            0xfe => Ok(u32::from_le_bytes(self.read_array()?).into()),
+            0xff => Ok(u64::from_le_bytes(self.read_array()?)),
+            n => Ok(n.into()),
+        }
+    }
+
+    /// Reads a `CompactSize` which is used as a length or count, and so must not
+    /// exceed the number of remaining bytes.
+    fn read_len(&mut self) -> Result<usize, BinaryDecodeError> {
+        let n = self.read_compact_size()?;
+        match usize::try_from(n) {
+            Ok(len) if len <= self.data.len() => Ok(len),
+            _ => Err(BinaryDecodeError::InvalidLength(n)),
+        }
+    }
+
+    /// Reads a `CompactSize` which is a value rather than a length, such as a
+    /// threshold, to be validated by the caller.
+    fn read_usize(&mut self) -> Result<usize, BinaryDecodeError> {
+        let n = self.read_compact_size()?;
+        usize::try_from(n).map_err(|_| BinaryDecodeError::InvalidLength(n))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, BinaryDecodeError> {
+        let n = self.read_compact_size()?;
+        u32::try_from(n).map_err(|_| BinaryDecodeError::InvalidLength(n))
+    }
+}
+
+fn write_compact_size(w: &mut Vec<u8>, n: u64) {
+    if n < 0xfd {
+        w.push(n as u8);
+    } else if n <= 0xffff {
+        w.push(0xfd);
+        w.extend_from_slice(&(n as u16).to_le_bytes());
+    } else if n <= 0xffff_ffff {
+        w.push(0xfe);
+        w.extend_from_slice(&(n as u32).to_le_bytes());
+    } else {
+        w.push(0xff);
+        w.extend_from_slice(&n.to_le_bytes());
+    }
+}
+
+fn encode_path(w: &mut Vec<u8>, path: &bip32::DerivationPath) {
+    write_compact_size(w, path.len() as u64);
+    for child in path {
+        w.extend_from_slice(&u32::from(*child).to_le_bytes());
+    }
+}
+
+fn decode_path(r: &mut Reader) -> Result<bip32::DerivationPath, BinaryDecodeError> {
+    let len = r.read_len()?;
+    let mut path = Vec::with_capacity(len);
+    for _ in 0..len {
+        path.push(bip32::ChildNumber::from(u32::from_le_bytes(r.read_array()?)));
+    }
+    Ok(path.into())
+}
+
+fn encode_origin(w: &mut Vec<u8>, origin: &Option<(bip32::Fingerprint, bip32::DerivationPath)>) {
+    match *origin {
+        Some((ref fingerprint, ref path)) => {
+            w.push(1);
+            w.extend_from_slice(fingerprint.as_bytes());
+            encode_path(w, path);
+        }
+        None => w.push(0),
+    }
+}
+
+fn decode_origin(
+    r: &mut Reader,
+) -> Result<Option<(bip32::Fingerprint, bip32::DerivationPath)>, BinaryDecodeError> {
+    match r.read_u8()? {
+        0 => Ok(None),
+        1 => {
+            let fingerprint = bip32::Fingerprint::from(r.read_array::<4>()?);
+            Ok(Some((fingerprint, decode_path(r)?)))
+        }
+        tag => Err(BinaryDecodeError::InvalidTag { kind: "key origin", tag }),
+    }
+}
+
+fn encode_wildcard(w: &mut Vec<u8>, wildcard: Wildcard) {
+    w.push(match wildcard {
+        Wildcard::None => 0,
+        Wildcard::Unhardened => 1,
+        Wildcard::Hardened => 2,
+    });
+}
+
+fn decode_wildcard(r: &mut Reader) -> Result<Wildcard, BinaryDecodeError> {
+    match r.read_u8()? {
+        0 => Ok(Wildcard::None),
+        1 => Ok(Wildcard::Unhardened),
+        2 => Ok(Wildcard::Hardened),
+        tag => Err(BinaryDecodeError::InvalidTag { kind: "wildcard", tag }),
+    }
+}
+
+fn encode_key(w: &mut Vec<u8>, pk: &DescriptorPublicKey) {
+    match *pk {
+        DescriptorPublicKey::Single(ref single) => {
+            match single.key {
+                SinglePubKey::FullKey(ref key) if key.compressed => w.push(KEY_COMPRESSED),
+                SinglePubKey::FullKey(..) => w.push(KEY_UNCOMPRESSED),
+                SinglePubKey::XOnly(..) => w.push(KEY_X_ONLY),
+            }
+            encode_origin(w, &single.origin);
+            match single.key {
+                SinglePubKey::FullKey(ref key) => w.extend_from_slice(&key.to_bytes()),
+                SinglePubKey::XOnly(ref key) => w.extend_from_slice(&key.serialize()),
+            }
+        }
+        DescriptorPublicKey::XPub(ref xpub) => {
+            w.push(KEY_XPUB);
+            encode_origin(w, &xpub.origin);
+            w.extend_from_slice(&xpub.xkey.encode());
+            encode_path(w, &xpub.derivation_path);
+            encode_wildcard(w, xpub.wildcard);
+        }
+        DescriptorPublicKey::MultiXPub(ref xpub) => {
+            w.push(KEY_MULTI_XPUB);
+            encode_origin(w, &xpub.origin);
+            w.extend_from_slice(&xpub.xkey.encode());
+            let paths = xpub.derivation_paths.paths();
+            write_compact_size(w, paths.len() as u64);
+            for path in paths {
+                encode_path(w, path);
+            }
+            encode_wildcard(w, xpub.wildcard);
+        }
+    }
+}
+
+fn decode_key(r: &mut Reader) -> Result<DescriptorPublicKey, BinaryDecodeError> {
+    let tag = r.read_u8()?;
+    let origin = decode_origin(r)?;
+    match tag {
+        KEY_COMPRESSED | KEY_UNCOMPRESSED => {
+            let len = if tag == KEY_COMPRESSED { 33 } else { 65 };
+            let key = bitcoin::PublicKey::from_slice(r.read_slice(len)?)
+                .map_err(|_| BinaryDecodeError::InvalidKey("public key"))?;
+            if key.compressed != (tag == KEY_COMPRESSED) {
+                return Err(BinaryDecodeError::InvalidKey("public key"));
+            }
+            Ok(DescriptorPublicKey::Single(SinglePub {
+                origin,
+                key: SinglePubKey::FullKey(key),
+            }))
+        }
+        KEY_X_ONLY => {
+            let key = XOnlyPublicKey::from_slice(r.read_slice(32)?)
+                .map_err(|_| BinaryDecodeError::InvalidKey("x-only public key"))?;
+            Ok(DescriptorPublicKey::Single(SinglePub { origin, key: SinglePubKey::XOnly(key) }))
+        }
+        KEY_XPUB => {
+            let xkey = bip32::Xpub::decode(r.read_slice(78)?)
+                .map_err(|_| BinaryDecodeError::InvalidKey("extended public key"))?;
+            let derivation_path = decode_path(r)?;
+            let wildcard = decode_wildcard(r)?;
+            Ok(DescriptorPublicKey::XPub(DescriptorXKey {
+                origin,
+                xkey,
+                derivation_path,
+                wildcard,
+            }))
+        }
+        KEY_MULTI_XPUB => {
+            let xkey = bip32::Xpub::decode(r.read_slice(78)?)
+                .map_err(|_| BinaryDecodeError::InvalidKey("extended public key"))?;
+            let n_paths = r.read_len()?;
+            let mut paths = Vec::with_capacity(n_paths);
+            for _ in 0..n_paths {
+                paths.push(decode_path(r)?);
+            }
+            if !is_multipath(&paths) {
+                return Err(BinaryDecodeError::InvalidKey("multipath extended public key"));
+            }
+            let derivation_paths = DerivPaths::new(paths).expect("not empty");
+            let wildcard = decode_wildcard(r)?;
+            Ok(DescriptorPublicKey::MultiXPub(DescriptorMultiXKey {
+                origin,
+                xkey,
+                derivation_paths,
+                wildcard,
+            }))
+        }
+        tag => Err(BinaryDecodeError::InvalidTag { kind: "key", tag }),
+    }
+}
+
+fn encode_keys(w: &mut Vec<u8>, k: usize, pks: &[DescriptorPublicKey]) {
+    write_compact_size(w, k as u64);
+    write_compact_size(w, pks.len() as u64);
+    for pk in pks {
+        encode_key(w, pk);
+    }
+}
+
+// Whether `paths` are the paths of a multipath key parsed from a string: a
+// single path would be an `XPub`, and all the paths are the same except at
+// one step.
+fn is_multipath(paths: &[bip32::DerivationPath]) -> bool {
+    let first = match paths {
+        [first, _, ..] if !first.is_empty() => first,
+        _ => return false,
+    };
+    let mut multi_step = None;
+    paths[1..].iter().all(|path| {
+        path.len() == first.len()
+            && first
+                .into_iter()
+                .zip(path)
+                .enumerate()
+                .all(|(i, (a, b))| a == b || *multi_step.get_or_insert(i) == i)
+    })
+}
+
+fn decode_keys(r: &mut Reader) -> Result<(usize, Vec<DescriptorPublicKey>), BinaryDecodeError> {
+    let k = r.read_usize()?;
+    let n = r.read_len()?;
+    let mut pks = Vec::with_capacity(n);
+    for _ in 0..n {
+        pks.push(decode_key(r)?);
+    }
+    Ok((k, pks))
+}
+
+fn encode_sortedmulti<Ctx: ScriptContext>(
+    w: &mut Vec<u8>,
+    smv: &SortedMultiVec<DescriptorPublicKey, Ctx>,
+) {
+    encode_keys(w, smv.k(), smv.pks());
+}
+
+fn encode_ms<Ctx: ScriptContext>(w: &mut Vec<u8>, ms: &Miniscript<DescriptorPublicKey, Ctx>) {
+    write_compact_size(w, ms.pre_order_iter().count() as u64);
+    for item in ms.post_order_iter() {
+        match item.node.node {
+            Terminal::True => w.push(MS_TRUE),
+            Terminal::False => w.push(MS_FALSE),
+            Terminal::PkK(ref pk) => {
+                w.push(MS_PK_K);
+                encode_key(w, pk);
+            }
+            Terminal::PkH(ref pk) => {
+                w.push(MS_PK_H);
+                encode_key(w, pk);
+            }
+            Terminal::RawPkH(ref hash) => {
+                w.push(MS_RAW_PK_H);
+                w.extend_from_slice(hash.as_byte_array());
+            }
+            Terminal::After(n) => {
+                w.push(MS_AFTER);
+                write_compact_size(w, n.to_consensus_u32().into());
+            }
+            Terminal::Older(n) => {
+                w.push(MS_OLDER);
+                write_compact_size(w, n.to_consensus_u32().into());
+            }
+            Terminal::Sha256(ref h) => {
+                w.push(MS_SHA256);
+                w.extend_from_slice(h.as_byte_array());
+            }
+            Terminal::Hash256(ref h) => {
+                w.push(MS_HASH256);
+                w.extend_from_slice(h.as_byte_array());
+            }
+            Terminal::Ripemd160(ref h) => {
+                w.push(MS_RIPEMD160);
+                w.extend_from_slice(h.as_byte_array());
+            }
+            Terminal::Hash160(ref h) => {
+                w.push(MS_HASH160);
+                w.extend_from_slice(h.as_byte_array());
+            }
+            Terminal::Alt(..) => w.push(MS_ALT),
+            Terminal::Swap(..) => w.push(MS_SWAP),
+            Terminal::Check(..) => w.push(MS_CHECK),
+            Terminal::DupIf(..) => w.push(MS_DUP_IF),
+            Terminal::Verify(..) => w.push(MS_VERIFY),
+            Terminal::NonZero(..) => w.push(MS_NON_ZERO),
+            Terminal::ZeroNotEqual(..) => w.push(MS_ZERO_NOT_EQUAL),
+            Terminal::AndV(..) => w.push(MS_AND_V),
+            Terminal::AndB(..) => w.push(MS_AND_B),
+            Terminal::AndOr(..) => w.push(MS_AND_OR),
+            Terminal::OrB(..) => w.push(MS_OR_B),
+            Terminal::OrD(..) => w.push(MS_OR_D),
+            Terminal::OrC(..) => w.push(MS_OR_C),
+            Terminal::OrI(..) => w.push(MS_OR_I),
+            Terminal::Thresh(ref thresh) => {
+                w.push(MS_THRESH);
+                write_compact_size(w, thresh.k() as u64);
+                write_compact_size(w, thresh.n() as u64);
+            }
+            Terminal::Multi(ref thresh) => {
+                w.push(MS_MULTI);
+                encode_keys(w, thresh.k(), thresh.data());
+            }
+            Terminal::MultiA(ref thresh) => {
+                w.push(MS_MULTI_A);
+                encode_keys(w, thresh.k(), thresh.data());
+            }
+            Terminal::Ext(ref e) => match *e {},
+        }
+    }
+}
+
+fn decode_ms<Ctx: ScriptContext>(
+    r: &mut Reader,
+) -> Result<Miniscript<DescriptorPublicKey, Ctx>, BinaryDecodeError> {
+    let n_nodes = r.read_len()?;
+    let mut stack = Vec::<Arc<Miniscript<DescriptorPublicKey, Ctx>>>::with_capacity(n_nodes);
+    let pop = |stack: &mut Vec<_>| stack.pop().ok_or(BinaryDecodeError::MalformedTree);
+    for _ in 0..n_nodes {
+        let term = match r.read_u8()? {
+            MS_TRUE => Terminal::True,
+            MS_FALSE => Terminal::False,
+            MS_PK_K => Terminal::PkK(decode_key(r)?),
+            MS_PK_H => Terminal::PkH(decode_key(r)?),
+            MS_RAW_PK_H => Terminal::RawPkH(hash160::Hash::from_byte_array(r.read_array()?)),
+            MS_AFTER => Terminal::After(
+                AbsLockTime::from_consensus(r.read_u32()?)
+                    .map_err(|e| BinaryDecodeError::Descriptor(Error::AbsoluteLockTime(e)))?,
+            ),
+            MS_OLDER => Terminal::Older(
+                RelLockTime::from_consensus(r.read_u32()?)
+                    .map_err(|e| BinaryDecodeError::Descriptor(Error::RelativeLockTime(e)))?,
+            ),
+            MS_SHA256 => Terminal::Sha256(sha256::Hash::from_byte_array(r.read_array()?)),
+            MS_HASH256 => Terminal::Hash256(hash256::Hash::from_byte_array(r.read_array()?)),
+            MS_RIPEMD160 => Terminal::Ripemd160(ripemd160::Hash::from_byte_array(r.read_array()?)),
+            MS_HASH160 => Terminal::Hash160(hash160::Hash::from_byte_array(r.read_array()?)),
+            MS_ALT => Terminal::Alt(pop(&mut stack)?),
+            MS_SWAP => Terminal::Swap(pop(&mut stack)?),
+            MS_CHECK => Terminal::Check(pop(&mut stack)?),
+            MS_DUP_IF => Terminal::DupIf(pop(&mut stack)?),
+            MS_VERIFY => Terminal::Verify(pop(&mut stack)?),
+            MS_NON_ZERO => Terminal::NonZero(pop(&mut stack)?),
+            MS_ZERO_NOT_EQUAL => Terminal::ZeroNotEqual(pop(&mut stack)?),
+            tag @ (MS_AND_V | MS_AND_B | MS_OR_B | MS_OR_D | MS_OR_C | MS_OR_I) => {
+                let right = pop(&mut stack)?;
+                let left = pop(&mut stack)?;
+                match tag {
+                    MS_AND_V => Terminal::AndV(left, right),
+                    MS_AND_B => Terminal::AndB(left, right),
+                    MS_OR_B => Terminal::OrB(left, right),
+                    MS_OR_D => Terminal::OrD(left, right),
+                    MS_OR_C => Terminal::OrC(left, right),
+                    _ => Terminal::OrI(left, right),
+                }
+            }
+            MS_AND_OR => {
+                let c = pop(&mut stack)?;
+                let b = pop(&mut stack)?;
+                let a = pop(&mut stack)?;
+                Terminal::AndOr(a, b, c)
+            }
+            MS_THRESH => {
+                let k = r.read_usize()?;
+                let n = usize::try_from(r.read_compact_size()?)
+                    .ok()
+                    .filter(|&n| n <= stack.len())
+                    .ok_or(BinaryDecodeError::MalformedTree)?;
+                let subs = stack.split_off(stack.len() - n);
+                Terminal::Thresh(Threshold::new(k, subs).map_err(Error::Threshold)?)
+            }
+            MS_MULTI => {
+                let (k, pks) = decode_keys(r)?;
+                Terminal::Multi(Threshold::new(k, pks).map_err(Error::Threshold)?)
+            }
+            MS_MULTI_A => {
+                let (k, pks) = decode_keys(r)?;
+                Terminal::MultiA(Threshold::new(k, pks).map_err(Error::Threshold)?)
+            }
+            tag => return Err(BinaryDecodeError::InvalidTag { kind: "miniscript", tag }),
+        };
+        stack.push(Arc::new(Miniscript::from_ast(term)?));
+    }
+
+    let ms = match (stack.pop(), stack.is_empty()) {
+        (Some(ms), true) => Arc::try_unwrap(ms).unwrap_or_else(|ms| (*ms).clone()),
+        _ => return Err(BinaryDecodeError::MalformedTree),
+    };
+    for node in ms.pre_order_iter() {
+        Ctx::check_global_validity(node).map_err(Error::ContextError)?;
+    }
+    ms.ext_check(&ExtParams::sane())
+        .map_err(Error::AnalysisError)?;
+    Ok(ms)
+}
+
+fn encode_tap_tree(w: &mut Vec<u8>, tree: &TapTree<DescriptorPublicKey>) {
+    // Collect the nodes in post-order without recursing.
+    let mut post_order = vec![];
+    let mut stack = vec![(tree, false)];
+    while let Some((node, visited)) = stack.pop() {
+        match *node {
+            TapTree::Tree { ref left, ref right, .. } if !visited => {
+                stack.push((node, true));
+                stack.push((right, false));
+                stack.push((left, false));
+            }
+            _ => post_order.push(node),
+        }
+    }
+
+    write_compact_size(w, post_order.len() as u64);
+    for node in post_order {
+        match *node {
+            TapTree::Tree { .. } => w.push(TREE_BRANCH),
+            TapTree::Leaf(ref ms) => {
+                w.push(TREE_LEAF);
+                encode_ms(w, ms);
+            }
+        }
+    }
+}
+
+fn decode_tap_tree(
+    r: &mut Reader,
+) -> Result<Option<TapTree<DescriptorPublicKey>>, BinaryDecodeError> {
+    let n_nodes = r.read_len()?;
+    if n_nodes == 0 {
+        return Ok(None);
+    }
+
+    let mut stack = Vec::with_capacity(n_nodes);
+    for _ in 0..n_nodes {
+        match r.read_u8()? {
+            TREE_LEAF => stack.push(TapTree::Leaf(Arc::new(decode_ms(r)?))),
+            TREE_BRANCH => {
+                let right = stack.pop().ok_or(BinaryDecodeError::MalformedTree)?;
+                let left = stack.pop().ok_or(BinaryDecodeError::MalformedTree)?;
+                stack.push(TapTree::combine(left, right));
+            }
+            tag => return Err(BinaryDecodeError::InvalidTag { kind: "taproot tree", tag }),
+        }
+    }
+
+    match (stack.pop(), stack.is_empty()) {
+        (Some(tree), true) => Ok(Some(tree)),
+        _ => Err(BinaryDecodeError::MalformedTree),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+    use crate::AnalysisError;
+
+    fn roundtrip(s: &str) -> Vec<u8> {
+        let desc = Descriptor::<DescriptorPublicKey>::from_str(s).unwrap();
+        let bytes = desc.to_bytes();
+        assert_eq!(Descriptor::from_bytes(&bytes).unwrap(), desc);
+        bytes
+    }
+
+    #[test]
+    fn binary_roundtrip() {
+        roundtrip("pkh(020000000000000000000000000000000000000000000000000000000000000002)");
+        roundtrip("pk(04a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd5b8dec5235a0fa8722476c7709c02559e3aa73aa03918ba2d492eea75abea235)");
+        roundtrip("wpkh([d34db33f/84'/0'/0']tpubDBrgjcxBxnXyL575sHdkpKohWu5qHKoQ7TJXKNrYznh5fVEGBv89hA8ENW7A8MFVpFUSvgLqc4Nj1WZcpePX6rrxviVtPowvMuGF5rdT2Vi/0/*)");
+        roundtrip("sh(wpkh(03a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd))");
+        roundtrip("sh(sortedmulti(1,03a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd,036d2b085e9e382ed10b69fc311a03f8641ccfff21574de0927513a49d9a688a00))");
+        roundtrip("wsh(sortedmulti(1,03a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd,036d2b085e9e382ed10b69fc311a03f8641ccfff21574de0927513a49d9a688a00))");
+        roundtrip("sh(wsh(sortedmulti(1,03a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd,036d2b085e9e382ed10b69fc311a03f8641ccfff21574de0927513a49d9a688a00)))");
+        roundtrip("wsh(andor(pk(03a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd),older(1008),thresh(2,pk(036d2b085e9e382ed10b69fc311a03f8641ccfff21574de0927513a49d9a688a00),s:pk(03f28773c2d975288bc7d1d205c3748651b075fbc6610e58cddeeddf8f19405aa8),sln:after(500000))))");
+        roundtrip("sh(wsh(or_d(multi(2,xpub661MyMwAqRbcFW31YEwpkMuc5THy2PSt5bDMsktWQcFF8syAmRUapSCGu8ED9W6oDMSgv6Zz8idoc4a6mr8BDzTJY47LJhkJ8UB7WEGuduB/<0;1>/*,xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL/<0;1>/*),and_v(v:sha256(e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855),and_v(v:hash160(b472a266d0bd89c13706a4132ccfb16f7c3b9fcb),pk(03f28773c2d975288bc7d1d205c3748651b075fbc6610e58cddeeddf8f19405aa8))))))");
+        roundtrip("sh(and_v(v:pk(03a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd),or_b(pk(036d2b085e9e382ed10b69fc311a03f8641ccfff21574de0927513a49d9a688a00),s:pk(03f28773c2d975288bc7d1d205c3748651b075fbc6610e58cddeeddf8f19405aa8))))");
+        // A thresh at the root, and so at the end of the encoding
+        roundtrip("wsh(thresh(2,pk(03a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd),s:pk(036d2b085e9e382ed10b69fc311a03f8641ccfff21574de0927513a49d9a688a00)))");
+        roundtrip("tr(a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd,thresh(2,pk(6d2b085e9e382ed10b69fc311a03f8641ccfff21574de0927513a49d9a688a00),s:pk(f28773c2d975288bc7d1d205c3748651b075fbc6610e58cddeeddf8f19405aa8)))");
+        roundtrip("tr(a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd)");
+        roundtrip("tr([d34db33f/86'/0'/0']xpub661MyMwAqRbcFW31YEwpkMuc5THy2PSt5bDMsktWQcFF8syAmRUapSCGu8ED9W6oDMSgv6Zz8idoc4a6mr8BDzTJY47LJhkJ8UB7WEGuduB/0/*,{pk(a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd),{multi_a(1,6d2b085e9e382ed10b69fc311a03f8641ccfff21574de0927513a49d9a688a00,f28773c2d975288bc7d1d205c3748651b075fbc6610e58cddeeddf8f19405aa8),and_v(v:hash256(e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855),and_v(v:older(144),pk(f28773c2d975288bc7d1d205c3748651b075fbc6610e58cddeeddf8f19405aa8)))}})");
+    }
+
+    #[test]
+    fn binary_compact() {
+        let s = "wpkh([d34db33f/84'/0'/0']tpubDBrgjcxBxnXyL575sHdkpKohWu5qHKoQ7TJXKNrYznh5fVEGBv89hA8ENW7A8MFVpFUSvgLqc4Nj1WZcpePX6rrxviVtPowvMuGF5rdT2Vi/0/*)";
+        let bytes = roundtrip(s);
+        // version, type, key tag, origin (1 + 4 + 1 + 3 * 4), xpub, path (1 + 4), wildcard
+        assert_eq!(bytes.len(), 1 + 1 + 1 + 18 + 78 + 5 + 1);
+        assert!(bytes.len() < s.len());
+    }
+
+    #[test]
+    fn binary_decode_errors() {
+        let desc = Descriptor::<DescriptorPublicKey>::from_str(
+            "wsh(and_v(v:pk(03a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd),older(144)))",
+        )
+        .unwrap();
+        let bytes = desc.to_bytes();
+
+        let mut bad_version = bytes.clone();
+        bad_version[0] = 1;
+        assert!(matches!(
+            Descriptor::from_bytes(&bad_version),
+            Err(BinaryDecodeError::UnsupportedVersion(1))
+        ));
+
+        assert!(matches!(
+            Descriptor::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(BinaryDecodeError::UnexpectedEnd)
+        ));
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(matches!(
+            Descriptor::from_bytes(&trailing),
+            Err(BinaryDecodeError::TrailingBytes(1))
+        ));
+
+        let mut bad_type = bytes.clone();
+        bad_type[1] = 0xaa;
+        assert!(matches!(
+            Descriptor::from_bytes(&bad_type),
+            Err(BinaryDecodeError::InvalidTag { kind: "descriptor", tag: 0xaa })
+        ));
+
+        // Re-interpreting a wsh descriptor as bare fails the bare-context checks.
+        let mut wrong_ctx = bytes;
+        wrong_ctx[1] = DESC_BARE;
+        assert!(matches!(
+            Descriptor::from_bytes(&wrong_ctx),
+            Err(BinaryDecodeError::Descriptor(..))
+        ));
+    }
+
+    #[test]
+    fn binary_decode_insane() {
+        let key = "03a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd";
+        for (ms, err) in [
+            (format!("and_v(v:pk({}),pk({}))", key, key), AnalysisError::RepeatedPubkeys),
+            (
+                "and_v(v:pk(K),c:expr_raw_pkh(b472a266d0bd89c13706a4132ccfb16f7c3b9fcb))"
+                    .replace('K', key),
+                AnalysisError::ContainsRawPkh,
+            ),
+            ("older(144)".to_owned(), AnalysisError::SiglessBranch),
+        ] {
+            let ms = Miniscript::<DescriptorPublicKey, crate::Segwitv0>::from_str_ext(
+                &ms,
+                &ExtParams::allow_all(),
+            )
+            .unwrap();
+            let desc = Descriptor::new_wsh(ms).unwrap();
+            match Descriptor::from_bytes(&desc.to_bytes()) {
+                Err(BinaryDecodeError::Descriptor(Error::AnalysisError(e))) => assert_eq!(e, err),
+                res => panic!("unexpected result {:?}", res),
+            }
+        }
+    }
+
+    #[test]
+    fn binary_decode_bad_multipath() {
+        let xpub = "xpub661MyMwAqRbcFW31YEwpkMuc5THy2PSt5bDMsktWQcFF8syAmRUapSCGu8ED9W6oDMSgv6Zz8idoc4a6mr8BDzTJY47LJhkJ8UB7WEGuduB";
+        let multi = |paths: &[&str]| {
+            let key = DescriptorPublicKey::MultiXPub(DescriptorMultiXKey {
+                origin: None,
+                xkey: bip32::Xpub::from_str(xpub).unwrap(),
+                derivation_paths: DerivPaths::new(
+                    paths.iter().map(|p| p.parse().unwrap()).collect(),
+                )
+                .unwrap(),
+                wildcard: Wildcard::Unhardened,
+            });
+            Descriptor::new_wpkh(key).unwrap().to_bytes()
+        };
+
+        let good = format!("wpkh({}/<0;1>/*)", xpub);
+        let bytes = multi(&["m/0", "m/1"]);
+        assert_eq!(
+            bytes,
+            Descriptor::<DescriptorPublicKey>::from_str(&good)
+                .unwrap()
+                .to_bytes()
+        );
+        assert!(Descriptor::from_bytes(&bytes).is_ok());
+
+        let bytes = multi(&["m/0/5", "m/1/5", "m/2/5"]);
+        assert!(Descriptor::from_bytes(&bytes).is_ok());
+
+        for paths in [
+            &["m/0"][..],
+            &["m/0", "m/1/2"][..],
+            &["m/0/5", "m/1/6"][..],
+            &["m/0/5", "m/1/5", "m/0/6"][..],
+        ] {
+            assert!(matches!(
+                Descriptor::from_bytes(&multi(paths)),
+                Err(BinaryDecodeError::InvalidKey("multipath extended public key"))
+            ));
+        }
+    }
+}
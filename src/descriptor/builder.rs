@@ -0,0 +1,244 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Descriptor builder
+//!
+//! Builds descriptors from keys and Miniscripts rather than from strings,
+//! starting from [`Descriptor::builder`]. Each script is taken as a
+//! [`Miniscript`] of the script context it is used in, so that using, say,
+//! a Taproot Miniscript in a `wsh` descriptor does not compile.
+
+use core::marker::PhantomData;
+
+use super::{Descriptor, TapTree};
+use crate::prelude::*;
+use crate::sync::Arc;
+use crate::{BareCtx, Error, Legacy, Miniscript, MiniscriptKey, Segwitv0, Tap};
+
+/// Builder of descriptors, see [`Descriptor::builder`]
+#[derive(Debug, PartialEq, Eq)]
+pub struct DescriptorBuilder<Pk: MiniscriptKey> {
+    _pk: PhantomData<Pk>,
+}
+
+// Derived `Clone` and `Copy` would require `Pk` to be `Copy`
+impl<Pk: MiniscriptKey> Clone for DescriptorBuilder<Pk> {
+    fn clone(&self) -> Self { *self }
+}
+
+impl<Pk: MiniscriptKey> Copy for DescriptorBuilder<Pk> {}
+
+impl<Pk: MiniscriptKey> DescriptorBuilder<Pk> {
+    /// Builds a bare `pk` descriptor.
+    pub fn pk(self, pk: Pk) -> Descriptor<Pk> { Descriptor::new_pk(pk) }
+
+    /// Builds a `pkh` descriptor.
+    pub fn pkh(self, pk: Pk) -> Result<Descriptor<Pk>, Error> { Descriptor::new_pkh(pk) }
+
+    /// Builds a `wpkh` descriptor.
+    pub fn wpkh(self, pk: Pk) -> Result<Descriptor<Pk>, Error> { Descriptor::new_wpkh(pk) }
+
+    /// Builds a bare descriptor.
+    pub fn bare(self, ms: Miniscript<Pk, BareCtx>) -> Result<Descriptor<Pk>, Error> {
+        Descriptor::new_bare(ms)
+    }
+
+    /// Starts an `sh` descriptor.
+    pub fn sh(self) -> ShBuilder<Pk> { ShBuilder { _pk: PhantomData } }
+
+    /// Starts a `wsh` descriptor.
+    pub fn wsh(self) -> WshBuilder<Pk> { WshBuilder { nested: false, _pk: PhantomData } }
+
+    /// Starts a `tr` descriptor with the given internal key.
+    pub fn tr(self, internal_key: Pk) -> TrBuilder<Pk> {
+        TrBuilder { internal_key, leaves: vec![] }
+    }
+}
+
+/// Builder of `sh` descriptors, see [`DescriptorBuilder::sh`]
+#[derive(Debug, PartialEq, Eq)]
+pub struct ShBuilder<Pk: MiniscriptKey> {
+    _pk: PhantomData<Pk>,
+}
+
+impl<Pk: MiniscriptKey> Clone for ShBuilder<Pk> {
+    fn clone(&self) -> Self { *self }
+}
+
+impl<Pk: MiniscriptKey> Copy for ShBuilder<Pk> {}
+
+impl<Pk: MiniscriptKey> ShBuilder<Pk> {
+    /// Builds an `sh(wpkh)` descriptor.
+    pub fn wpkh(self, pk: Pk) -> Result<Descriptor<Pk>, Error> { Descriptor::new_sh_wpkh(pk) }
+
+    /// Starts an `sh(wsh)` descriptor.
+    pub fn wsh(self) -> WshBuilder<Pk> { WshBuilder { nested: true, _pk: PhantomData } }
+
+    /// Builds an `sh(sortedmulti)` descriptor.
+    pub fn sortedmulti(self, k: usize, pks: Vec<Pk>) -> Result<Descriptor<Pk>, Error> {
+        Descriptor::new_sh_sortedmulti(k, pks)
+    }
+
+    /// Builds an `sh` descriptor with the given redeem script.
+    pub fn miniscript(self, ms: Miniscript<Pk, Legacy>) -> Result<Descriptor<Pk>, Error> {
+        Descriptor::new_sh(ms)
+    }
+}
+
+/// Builder of `wsh` and `sh(wsh)` descriptors, see [`DescriptorBuilder::wsh`]
+#[derive(Debug, PartialEq, Eq)]
+pub struct WshBuilder<Pk: MiniscriptKey> {
+    nested: bool,
+    _pk: PhantomData<Pk>,
+}
+
+impl<Pk: MiniscriptKey> Clone for WshBuilder<Pk> {
+    fn clone(&self) -> Self { *self }
+}
+
+impl<Pk: MiniscriptKey> Copy for WshBuilder<Pk> {}
+
+impl<Pk: MiniscriptKey> WshBuilder<Pk> {
+    /// Builds a `wsh(sortedmulti)` descriptor.
+    pub fn sortedmulti(self, k: usize, pks: Vec<Pk>) -> Result<Descriptor<Pk>, Error> {
+        if self.nested {
+            Descriptor::new_sh_wsh_sortedmulti(k, pks)
+        } else {
+            Descriptor::new_wsh_sortedmulti(k, pks)
+        }
+    }
+
+    /// Builds a `wsh` descriptor with the given witness script.
+    pub fn miniscript(self, ms: Miniscript<Pk, Segwitv0>) -> Result<Descriptor<Pk>, Error> {
+        if self.nested {
+            Descriptor::new_sh_wsh(ms)
+        } else {
+            Descriptor::new_wsh(ms)
+        }
+    }
+}
+
+/// Builder of `tr` descriptors, see [`DescriptorBuilder::tr`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrBuilder<Pk: MiniscriptKey> {
+    internal_key: Pk,
+    leaves: Vec<TapTree<Pk>>,
+}
+
+impl<Pk: MiniscriptKey> TrBuilder<Pk> {
+    /// Adds a script leaf.
+    pub fn leaf(mut self, ms: Miniscript<Pk, Tap>) -> Self {
+        self.leaves.push(TapTree::Leaf(Arc::new(ms)));
+        self
+    }
+
+    /// Adds a subtree, to control the shape of the tree.
+    pub fn subtree(mut self, tree: TapTree<Pk>) -> Self {
+        self.leaves.push(tree);
+        self
+    }
+
+    /// Builds the descriptor.
+    ///
+    /// The leaves and subtrees are placed in a balanced tree, in the order
+    /// they were added.
+    pub fn build(self) -> Result<Descriptor<Pk>, Error> {
+        let mut level = self.leaves;
+        while level.len() > 1 {
+            let mut next = Vec::with_capacity((level.len() + 1) / 2);
+            let mut nodes = level.into_iter();
+            while let Some(left) = nodes.next() {
+                next.push(match nodes.next() {
+                    Some(right) => TapTree::combine(left, right),
+                    None => left,
+                });
+            }
+            level = next;
+        }
+        Descriptor::new_tr(self.internal_key, level.pop())
+    }
+}
+
+impl<Pk: MiniscriptKey> Descriptor<Pk> {
+    /// Starts building a descriptor.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::str::FromStr;
+    /// use miniscript::{Descriptor, Miniscript};
+    ///
+    /// let desc = Descriptor::<String>::builder()
+    ///     .tr("A".to_owned())
+    ///     .leaf(Miniscript::from_str("pk(B)").unwrap())
+    ///     .leaf(Miniscript::from_str("and_v(v:pk(C),older(144))").unwrap())
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(desc.to_string(), "tr(A,{pk(B),and_v(v:pk(C),older(144))})#r34epnug");
+    ///
+    /// let desc = Descriptor::builder().sh().wsh().sortedmulti(1, vec!["A".to_owned()]).unwrap();
+    /// assert_eq!(desc.to_string(), "sh(wsh(sortedmulti(1,A)))#er0xlunx");
+    /// ```
+    pub fn builder() -> DescriptorBuilder<Pk> { DescriptorBuilder { _pk: PhantomData } }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+
+    fn ms<Ctx: crate::ScriptContext>(s: &str) -> Miniscript<String, Ctx> {
+        Miniscript::from_str(s).unwrap()
+    }
+
+    fn desc(s: &str) -> Descriptor<String> { Descriptor::from_str(s).unwrap() }
+
+    #[test]
+    fn builder() {
+        let a = || "A".to_owned();
+        let keys = || vec!["A".to_owned(), "B".to_owned()];
+        let builder = Descriptor::<String>::builder();
+
+        assert_eq!(builder.pk(a()), desc("pk(A)"));
+        assert_eq!(builder.pkh(a()).unwrap(), desc("pkh(A)"));
+        assert_eq!(builder.wpkh(a()).unwrap(), desc("wpkh(A)"));
+        assert_eq!(builder.bare(ms("pk(A)")).unwrap(), desc("pk(A)"));
+        assert_eq!(builder.sh().wpkh(a()).unwrap(), desc("sh(wpkh(A))"));
+        assert_eq!(builder.sh().sortedmulti(1, keys()).unwrap(), desc("sh(sortedmulti(1,A,B))"));
+        assert_eq!(builder.sh().miniscript(ms("pk(A)")).unwrap(), desc("sh(pk(A))"));
+        assert_eq!(builder.wsh().sortedmulti(2, keys()).unwrap(), desc("wsh(sortedmulti(2,A,B))"));
+        assert_eq!(builder.wsh().miniscript(ms("pk(A)")).unwrap(), desc("wsh(pk(A))"));
+        assert_eq!(
+            builder.sh().wsh().sortedmulti(2, keys()).unwrap(),
+            desc("sh(wsh(sortedmulti(2,A,B)))")
+        );
+        assert_eq!(builder.sh().wsh().miniscript(ms("pk(A)")).unwrap(), desc("sh(wsh(pk(A)))"));
+        builder.wsh().sortedmulti(3, keys()).unwrap_err();
+
+        assert_eq!(builder.tr(a()).build().unwrap(), desc("tr(A)"));
+        assert_eq!(builder.tr(a()).leaf(ms("pk(B)")).build().unwrap(), desc("tr(A,pk(B))"));
+        assert_eq!(
+            builder
+                .tr(a())
+                .leaf(ms("pk(B)"))
+                .leaf(ms("pk(C)"))
+                .leaf(ms("pk(D)"))
+                .build()
+                .unwrap(),
+            desc("tr(A,{{pk(B),pk(C)},pk(D)})")
+        );
+        let subtree = TapTree::combine(
+            TapTree::Leaf(Arc::new(ms("pk(C)"))),
+            TapTree::Leaf(Arc::new(ms("pk(D)"))),
+        );
+        assert_eq!(
+            builder
+                .tr(a())
+                .leaf(ms("pk(B)"))
+                .subtree(subtree)
+                .build()
+                .unwrap(),
+            desc("tr(A,{pk(B),{pk(C),pk(D)}})")
+        );
+    }
+}
@@ -49,6 +49,8 @@ pub enum Error {
         /// The length of a valid descriptor checksum.
         expected: usize,
     },
+    /// The string had no checksum, see [`verify`].
+    MissingChecksum,
     /// Checksum was invalid.
     InvalidChecksum {
         /// The checksum in the string.
@@ -67,6 +69,7 @@ impl fmt::Display for Error {
             Error::InvalidChecksumLength { actual, expected } => {
                 write!(f, "invalid checksum (length {}, expected {})", actual, expected)
             }
+            Error::MissingChecksum => f.write_str("missing checksum"),
             Error::InvalidChecksum { actual, expected } => {
                 f.write_str("invalid checksum ")?;
                 for ch in actual {
@@ -84,7 +87,7 @@ impl fmt::Display for Error {
 
 #[cfg(feature = "std")]
 impl std::error::Error for Error {
-    fn cause(&self) -> Option<&dyn std::error::Error> { None }
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> { None }
 }
 
 /// Helper function for `FromStr` for various descriptor types.
@@ -127,6 +130,45 @@ pub fn verify_checksum(s: &str) -> Result<&str, Error> {
     Ok(&s[..last_hash_pos])
 }
 
+/// Whether `s` has the form of a checksum: eight characters of the lowercase
+/// bech32 charset.
+pub(crate) fn is_checksum_like(s: &str) -> bool {
+    s.len() == CHECKSUM_LENGTH
+        && s.chars()
+            .all(|ch| !ch.is_ascii_uppercase() && Fe32::from_char(ch).is_ok())
+}
+
+/// Verifies the checksum of a descriptor string, which must be present, and
+/// returns the descriptor string without the checksum.
+///
+/// Only the character set and the checksum are checked, so this works on any
+/// descriptor string, including descriptor types this library cannot parse.
+pub fn verify(s: &str) -> Result<&str, Error> {
+    let desc = verify_checksum(s)?;
+    if desc.len() == s.len() {
+        return Err(Error::MissingChecksum);
+    }
+    Ok(desc)
+}
+
+/// Appends its checksum to a descriptor string.
+///
+/// If the string already has a checksum, it is verified and the string is
+/// returned as is.
+pub fn append(s: &str) -> Result<String, Error> {
+    let desc = verify_checksum(s)?;
+    let mut eng = Engine::new();
+    eng.input_unchecked(desc.as_bytes());
+    Ok(format!("{}#{}", desc, eng.checksum()))
+}
+
+/// Removes the checksum of a descriptor string, verifying it if it is
+/// present.
+///
+/// This is the same as [`verify_checksum`], named to go with [`verify`] and
+/// [`append`].
+pub fn strip(s: &str) -> Result<&str, Error> { verify_checksum(s) }
+
 /// An engine to compute a checksum from a string.
 pub struct Engine {
     inner: bech32::primitives::checksum::Engine<DescriptorChecksum>,
@@ -308,6 +350,23 @@ mod test {
         );
     }
 
+    #[test]
+    fn checksum_utilities() {
+        // Not a descriptor type this library knows about
+        let desc = "unknown(deadbeef)";
+        let with_checksum = append(desc).unwrap();
+        assert_eq!(append(&with_checksum).unwrap(), with_checksum);
+        assert_eq!(verify(&with_checksum).unwrap(), desc);
+        assert_eq!(strip(&with_checksum).unwrap(), desc);
+        assert_eq!(strip(desc).unwrap(), desc);
+        assert_eq!(verify(desc), Err(Error::MissingChecksum));
+
+        assert_eq!(append("raw(deadbeef)").unwrap(), "raw(deadbeef)#89f8spxm");
+        assert!(append("raw(deadbeef)#89f8spxn").is_err());
+        assert!(verify("raw(deadbeef)#89f8spxn").is_err());
+        assert!(strip("raw(deadbeef)#89f8spxn").is_err());
+    }
+
     #[test]
     fn bip_380_test_vectors_checksum_and_character_set_valid() {
         let tcs = vec![
@@ -0,0 +1,92 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Derivation caching
+//!
+//! Deriving the keys of a descriptor at an index derives each extended key
+//! along its whole derivation path, even though only the last step depends
+//! on the index. When deriving many indices, or many descriptors sharing the
+//! same extended keys, [`DerivationCache`] keeps the keys derived at the
+//! path prefixes so that only the last step is derived each time.
+
+use bitcoin::bip32;
+use bitcoin::secp256k1::{Secp256k1, Verification};
+
+use crate::prelude::*;
+
+/// A cache of extended public keys derived at the prefixes of derivation paths
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DerivationCache {
+    xpubs: BTreeMap<(bip32::Xpub, bip32::DerivationPath), bip32::Xpub>,
+}
+
+impl DerivationCache {
+    /// Creates an empty cache.
+    pub fn new() -> Self { DerivationCache::default() }
+
+    /// The number of derived keys in the cache.
+    pub fn len(&self) -> usize { self.xpubs.len() }
+
+    /// Whether the cache is empty.
+    pub fn is_empty(&self) -> bool { self.xpubs.is_empty() }
+
+    /// Removes all the derived keys from the cache.
+    pub fn clear(&mut self) { self.xpubs.clear() }
+
+    /// Derives `xpub` at `path`, reusing or caching the key derived at all
+    /// but the last step of the path.
+    pub(super) fn derive_pub<C: Verification>(
+        &mut self,
+        secp: &Secp256k1<C>,
+        xpub: &bip32::Xpub,
+        path: &bip32::DerivationPath,
+    ) -> Result<bip32::Xpub, bip32::Error> {
+        let (last, prefix) = match path.as_ref().split_last() {
+            Some(split) => split,
+            None => return Ok(*xpub),
+        };
+        let prefix = bip32::DerivationPath::from(prefix);
+        let parent = match self.xpubs.get(&(*xpub, prefix.clone())) {
+            Some(parent) => *parent,
+            None => {
+                let parent = xpub.derive_pub(secp, &prefix)?;
+                self.xpubs.insert((*xpub, prefix), parent);
+                parent
+            }
+        };
+        parent.ckd_pub(secp, *last)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn cached_derivation() {
+        let secp = Secp256k1::verification_only();
+        let xpub = bip32::Xpub::from_str("xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8").unwrap();
+        let mut cache = DerivationCache::new();
+        for i in 0..10 {
+            let path = bip32::DerivationPath::from_str(&format!("m/0/1/{}", i)).unwrap();
+            assert_eq!(
+                cache.derive_pub(&secp, &xpub, &path).unwrap(),
+                xpub.derive_pub(&secp, &path).unwrap()
+            );
+        }
+        assert_eq!(cache.len(), 1);
+
+        let path = bip32::DerivationPath::from_str("m/2").unwrap();
+        assert_eq!(
+            cache.derive_pub(&secp, &xpub, &path).unwrap(),
+            xpub.derive_pub(&secp, &path).unwrap()
+        );
+        assert_eq!(cache.len(), 2);
+        let master = bip32::DerivationPath::master();
+        assert_eq!(cache.derive_pub(&secp, &xpub, &master).unwrap(), xpub);
+        assert!(cache
+            .derive_pub(&secp, &xpub, &bip32::DerivationPath::from_str("m/0h").unwrap())
+            .is_err());
+    }
+}
@@ -0,0 +1,235 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Duplicate Key Detection
+//!
+//! Sane miniscripts may not repeat a key, but nothing stops the same key from
+//! appearing in several taproot leaves, or as both the internal key and a leaf
+//! key, and the same xpub can appear with derivation paths which produce the
+//! same key. Any of these can mean that a policy is weaker than intended, e.g.
+//! a 2-of-3 where one signer holds two of the keys.
+//!
+
+use core::fmt;
+
+use super::{Descriptor, DescriptorPublicKey, ShInner, WshInner};
+use crate::miniscript::decode::Terminal;
+use crate::prelude::*;
+use crate::{Miniscript, MiniscriptKey, ScriptContext};
+
+/// Where the two occurrences of a [`DuplicateKey`] are.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum DuplicateKeyKind {
+    /// Both occurrences are in the same `multi`, `multi_a` or `sortedmulti` fragment.
+    SameFragment,
+    /// Both occurrences are in the same script, in different fragments.
+    SameScript,
+    /// The occurrences are in different taproot leaves, given by their index
+    /// in [`super::Tr::iter_scripts`].
+    AcrossLeaves {
+        /// The leaf of the first occurrence.
+        first_leaf: usize,
+        /// The leaf of the second occurrence.
+        second_leaf: usize,
+    },
+    /// The first occurrence is the taproot internal key, the second is in a leaf.
+    InternalAndLeaf {
+        /// The leaf of the second occurrence.
+        leaf: usize,
+    },
+}
+
+/// A key which appears more than once in a descriptor.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct DuplicateKey<Pk: MiniscriptKey> {
+    /// The first occurrence of the key.
+    pub first: Pk,
+    /// The later occurrence, which is equal to or overlaps with the first.
+    pub second: Pk,
+    /// Where the two occurrences are.
+    pub kind: DuplicateKeyKind,
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for DuplicateKey<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} and {} ", self.first, self.second)?;
+        match self.kind {
+            DuplicateKeyKind::SameFragment => f.write_str("are repeated in the same fragment"),
+            DuplicateKeyKind::SameScript => f.write_str("are repeated in the same script"),
+            DuplicateKeyKind::AcrossLeaves { first_leaf, second_leaf } => {
+                write!(f, "are repeated in taproot leaves {} and {}", first_leaf, second_leaf)
+            }
+            DuplicateKeyKind::InternalAndLeaf { leaf } => {
+                write!(f, "are repeated as the internal key and in taproot leaf {}", leaf)
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Location {
+    Internal,
+    Script { leaf: usize, fragment: usize },
+}
+
+fn push_ms_keys<'a, Pk: MiniscriptKey, Ctx: ScriptContext>(
+    ms: &'a Miniscript<Pk, Ctx>,
+    leaf: usize,
+    keys: &mut Vec<(&'a Pk, Location)>,
+) {
+    for (fragment, node) in ms.iter().enumerate() {
+        let location = Location::Script { leaf, fragment };
+        match node.node {
+            Terminal::PkK(ref pk) | Terminal::PkH(ref pk) => keys.push((pk, location)),
+            Terminal::Multi(ref thresh) => keys.extend(thresh.iter().map(|pk| (pk, location))),
+            Terminal::MultiA(ref thresh) => keys.extend(thresh.iter().map(|pk| (pk, location))),
+            _ => {}
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey> Descriptor<Pk> {
+    /// Returns every pair of equal keys in the descriptor.
+    ///
+    /// For descriptors over [`DescriptorPublicKey`]s, prefer
+    /// [`Descriptor::overlapping_keys`] which also detects distinct xpub
+    /// expressions which can derive the same key.
+    pub fn duplicate_keys(&self) -> Vec<DuplicateKey<Pk>> { self.duplicate_keys_by(|a, b| a == b) }
+
+    fn duplicate_keys_by<F>(&self, mut same: F) -> Vec<DuplicateKey<Pk>>
+    where
+        F: FnMut(&Pk, &Pk) -> bool,
+    {
+        let mut keys = vec![];
+        let fragment = Location::Script { leaf: 0, fragment: 0 };
+        match *self {
+            Descriptor::Bare(ref bare) => push_ms_keys(bare.as_inner(), 0, &mut keys),
+            Descriptor::Pkh(ref pkh) => keys.push((pkh.as_inner(), fragment)),
+            Descriptor::Wpkh(ref wpkh) => keys.push((wpkh.as_inner(), fragment)),
+            Descriptor::Sh(ref sh) => match *sh.as_inner() {
+                ShInner::Wsh(ref wsh) => match *wsh.as_inner() {
+                    WshInner::SortedMulti(ref smv) => {
+                        keys.extend(smv.pks().iter().map(|pk| (pk, fragment)))
+                    }
+                    WshInner::Ms(ref ms) => push_ms_keys(ms, 0, &mut keys),
+                },
+                ShInner::Wpkh(ref wpkh) => keys.push((wpkh.as_inner(), fragment)),
+                ShInner::SortedMulti(ref smv) => {
+                    keys.extend(smv.pks().iter().map(|pk| (pk, fragment)))
+                }
+                ShInner::Ms(ref ms) => push_ms_keys(ms, 0, &mut keys),
+            },
+            Descriptor::Wsh(ref wsh) => match *wsh.as_inner() {
+                WshInner::SortedMulti(ref smv) => {
+                    keys.extend(smv.pks().iter().map(|pk| (pk, fragment)))
+                }
+                WshInner::Ms(ref ms) => push_ms_keys(ms, 0, &mut keys),
+            },
+            Descriptor::Tr(ref tr) => {
+                keys.push((tr.internal_key(), Location::Internal));
+                for (leaf, (_depth, ms)) in tr.iter_scripts().enumerate() {
+                    push_ms_keys(ms, leaf, &mut keys);
+                }
+            }
+        }
+
+        let mut ret = vec![];
+        for (i, &(first, first_loc)) in keys.iter().enumerate() {
+            for &(second, second_loc) in &keys[i + 1..] {
+                if !same(first, second) {
+                    continue;
+                }
+                let kind = match (first_loc, second_loc) {
+                    (Location::Internal, Location::Script { leaf, .. })
+                    | (Location::Script { leaf, .. }, Location::Internal) => {
+                        DuplicateKeyKind::InternalAndLeaf { leaf }
+                    }
+                    (
+                        Location::Script { leaf: first_leaf, fragment: first_fragment },
+                        Location::Script { leaf: second_leaf, fragment: second_fragment },
+                    ) => {
+                        if first_leaf != second_leaf {
+                            DuplicateKeyKind::AcrossLeaves { first_leaf, second_leaf }
+                        } else if first_fragment == second_fragment {
+                            DuplicateKeyKind::SameFragment
+                        } else {
+                            DuplicateKeyKind::SameScript
+                        }
+                    }
+                    (Location::Internal, Location::Internal) => {
+                        unreachable!("a descriptor has at most one internal key")
+                    }
+                };
+                ret.push(DuplicateKey { first: first.clone(), second: second.clone(), kind });
+            }
+        }
+        ret
+    }
+}
+
+impl Descriptor<DescriptorPublicKey> {
+    /// Returns every pair of keys in the descriptor which can produce the same
+    /// public key, as determined by [`DescriptorPublicKey::overlaps`].
+    pub fn overlapping_keys(&self) -> Vec<DuplicateKey<DescriptorPublicKey>> {
+        self.duplicate_keys_by(DescriptorPublicKey::overlaps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+
+    const XPUB: &str = "xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL";
+
+    fn kinds<Pk: MiniscriptKey>(dups: Vec<DuplicateKey<Pk>>) -> Vec<DuplicateKeyKind> {
+        dups.into_iter().map(|dup| dup.kind).collect()
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        let desc = Descriptor::<String>::from_str("wsh(multi(2,A,B,C))").unwrap();
+        assert!(desc.duplicate_keys().is_empty());
+
+        let desc = Descriptor::<String>::from_str("wsh(sortedmulti(2,A,B,A))").unwrap();
+        assert_eq!(kinds(desc.duplicate_keys()), vec![DuplicateKeyKind::SameFragment]);
+
+        let desc =
+            Descriptor::<String>::from_str("tr(A,{pk(B),{pk(A),and_v(v:pk(C),pk(B))}})").unwrap();
+        let dups = desc.duplicate_keys();
+        assert_eq!(
+            kinds(dups.clone()),
+            vec![
+                DuplicateKeyKind::InternalAndLeaf { leaf: 1 },
+                DuplicateKeyKind::AcrossLeaves { first_leaf: 0, second_leaf: 2 },
+            ]
+        );
+        assert_eq!(dups[1].to_string(), "B and B are repeated in taproot leaves 0 and 2");
+    }
+
+    #[test]
+    fn overlapping_keys() {
+        let key = |s: &str| DescriptorPublicKey::from_str(&s.replace("X", XPUB)).unwrap();
+        assert!(key("X/0/*").overlaps(&key("X/<0;1>/*")));
+        assert!(!key("X/0/*").overlaps(&key("X/1/*")));
+        assert!(key("X/*").overlaps(&key("X/5")));
+        assert!(key("X/5").overlaps(&key("[d34db33f/1]X/*")));
+        assert!(!key("X/*").overlaps(&key("X/5/6")));
+        assert!(!key("X/*").overlaps(&key("X/5h")));
+        assert!(!key("X/5").overlaps(&key("X/6")));
+
+        let full = key("0250863ad64a87ae8a2fe83c1af1a8403cb53f53e486d8511dad8a04887e5b2352");
+        let x_only = key("50863ad64a87ae8a2fe83c1af1a8403cb53f53e486d8511dad8a04887e5b2352");
+        assert!(full.overlaps(&x_only));
+        assert!(!full.overlaps(&key("X")));
+
+        let desc = Descriptor::<DescriptorPublicKey>::from_str(
+            &"wsh(multi(2,X/0/*,X/1/*,X/<1;2>/*))".replace("X", XPUB),
+        )
+        .unwrap();
+        assert!(desc.duplicate_keys().is_empty());
+        let dups = desc.overlapping_keys();
+        assert_eq!(kinds(dups.clone()), vec![DuplicateKeyKind::SameFragment]);
+        assert_eq!(dups[0].first, key("X/1/*"));
+    }
+}
@@ -11,9 +11,11 @@ use bitcoin::hashes::{hash160, ripemd160, sha256, Hash, HashEngine};
 use bitcoin::key::XOnlyPublicKey;
 use bitcoin::secp256k1::{Secp256k1, Signing, Verification};
 
+use super::{slip132, DerivationCache, DescriptorType};
 use crate::prelude::*;
 #[cfg(feature = "serde")]
 use crate::serde::{Deserialize, Deserializer, Serialize, Serializer};
+use crate::util::with_verify_context;
 use crate::{hash256, MiniscriptKey, ToPublicKey};
 
 /// The descriptor pubkey, either a single pubkey or an xpub.
@@ -189,6 +191,50 @@ pub enum Wildcard {
     Hardened,
 }
 
+/// The role of a derivation path of a multipath key, by convention
+///
+/// Wallets use the first path of a multipath key (`<0;1>`) for receive
+/// addresses and the second one for change addresses.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum PathRole {
+    /// First path, used for receive addresses
+    Receive,
+    /// Second path, used for change addresses
+    Change,
+    /// Any other path, with its position
+    Other(usize),
+}
+
+impl PathRole {
+    /// The role of the path at `position` in a multipath key.
+    pub fn from_position(position: usize) -> PathRole {
+        match position {
+            0 => PathRole::Receive,
+            1 => PathRole::Change,
+            n => PathRole::Other(n),
+        }
+    }
+
+    /// The position of the path with this role in a multipath key.
+    pub fn position(self) -> usize {
+        match self {
+            PathRole::Receive => 0,
+            PathRole::Change => 1,
+            PathRole::Other(n) => n,
+        }
+    }
+}
+
+impl fmt::Display for PathRole {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PathRole::Receive => f.write_str("receive"),
+            PathRole::Change => f.write_str("change"),
+            PathRole::Other(n) => write!(f, "path {}", n),
+        }
+    }
+}
+
 impl SinglePriv {
     /// Returns the public key of this key.
     fn to_public<C: Signing>(&self, secp: &Secp256k1<C>) -> SinglePub {
@@ -506,6 +552,32 @@ impl fmt::Display for DescriptorPublicKey {
 }
 
 impl DescriptorSecretKey {
+    /// Parses a key like [`DescriptorSecretKey::from_str`], also returning the
+    /// script type hinted at by SLIP-132 extended keys (`yprv`, `zprv`, ...).
+    ///
+    /// The extended key itself is always converted to a plain `xprv` or `tprv`.
+    pub fn from_str_with_slip132_hint(
+        s: &str,
+    ) -> Result<(Self, Option<DescriptorType>), DescriptorKeyParseError> {
+        let key = DescriptorSecretKey::from_str(s)?;
+        Ok((key, slip132_script_type(s, true)))
+    }
+
+    /// Serializes the key with its extended key in the SLIP-132 form for
+    /// `script_type`.
+    ///
+    /// Returns `None` for single keys and for script types which SLIP-132 has
+    /// no version bytes for.
+    pub fn to_slip132_string(&self, script_type: DescriptorType) -> Option<String> {
+        let xpriv = match *self {
+            DescriptorSecretKey::Single(_) => return None,
+            DescriptorSecretKey::XPrv(ref xpriv) => xpriv.xkey,
+            DescriptorSecretKey::MultiXPrv(ref xpriv) => xpriv.xkey,
+        };
+        let slip132 = slip132::encode_xpriv(&xpriv, script_type)?;
+        Some(self.to_string().replacen(&xpriv.to_string(), &slip132, 1))
+    }
+
     /// Returns the public version of this key.
     ///
     /// If the key is an "XPrv", the hardened derivation steps will be applied
@@ -677,14 +749,17 @@ pub enum ConversionError {
     HardenedChild,
     /// Attempted to convert a key with multiple derivation paths to a bitcoin public key
     MultiKey,
+    /// Attempted to select a derivation path beyond those of a multipath key
+    NoSuchPath(usize),
 }
 
 impl fmt::Display for ConversionError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_str(match *self {
-            ConversionError::HardenedChild => "hardened child step in bip32 path",
-            ConversionError::MultiKey => "multiple existing keys",
-        })
+        match *self {
+            ConversionError::HardenedChild => f.write_str("hardened child step in bip32 path"),
+            ConversionError::MultiKey => f.write_str("multiple existing keys"),
+            ConversionError::NoSuchPath(n) => write!(f, "no derivation path at position {}", n),
+        }
     }
 }
 
@@ -694,12 +769,38 @@ impl error::Error for ConversionError {
         use self::ConversionError::*;
 
         match self {
-            HardenedChild | MultiKey => None,
+            HardenedChild | MultiKey | NoSuchPath(_) => None,
         }
     }
 }
 
 impl DescriptorPublicKey {
+    /// Parses a key like [`DescriptorPublicKey::from_str`], also returning the
+    /// script type hinted at by SLIP-132 extended keys (`ypub`, `zpub`, ...).
+    ///
+    /// The extended key itself is always converted to a plain `xpub` or `tpub`.
+    pub fn from_str_with_slip132_hint(
+        s: &str,
+    ) -> Result<(Self, Option<DescriptorType>), DescriptorKeyParseError> {
+        let key = DescriptorPublicKey::from_str(s)?;
+        Ok((key, slip132_script_type(s, false)))
+    }
+
+    /// Serializes the key with its extended key in the SLIP-132 form for
+    /// `script_type`.
+    ///
+    /// Returns `None` for single keys and for script types which SLIP-132 has
+    /// no version bytes for.
+    pub fn to_slip132_string(&self, script_type: DescriptorType) -> Option<String> {
+        let xpub = match *self {
+            DescriptorPublicKey::Single(_) => return None,
+            DescriptorPublicKey::XPub(ref xpub) => xpub.xkey,
+            DescriptorPublicKey::MultiXPub(ref xpub) => xpub.xkey,
+        };
+        let slip132 = slip132::encode_xpub(&xpub, script_type)?;
+        Some(self.to_string().replacen(&xpub.to_string(), &slip132, 1))
+    }
+
     /// The fingerprint of the master key associated with this key, `0x00000000` if none.
     pub fn master_fingerprint(&self) -> bip32::Fingerprint {
         match *self {
@@ -889,6 +990,138 @@ impl DescriptorPublicKey {
             }
         }
     }
+
+    /// The derivation paths of a multipath key, with their conventional role.
+    ///
+    /// Returns an empty vector for any other key.
+    pub fn paths_with_roles(&self) -> Vec<(PathRole, &bip32::DerivationPath)> {
+        match *self {
+            DescriptorPublicKey::Single(..) | DescriptorPublicKey::XPub(..) => vec![],
+            DescriptorPublicKey::MultiXPub(ref xpub) => xpub
+                .derivation_paths
+                .paths()
+                .iter()
+                .enumerate()
+                .map(|(position, path)| (PathRole::from_position(position), path))
+                .collect(),
+        }
+    }
+
+    /// The position of the derivation path with the given role, if this is a
+    /// multipath key which has such a path.
+    pub fn path_position(&self, role: PathRole) -> Option<usize> {
+        match *self {
+            DescriptorPublicKey::Single(..) | DescriptorPublicKey::XPub(..) => None,
+            DescriptorPublicKey::MultiXPub(ref xpub) => {
+                Some(role.position()).filter(|&n| n < xpub.derivation_paths.paths().len())
+            }
+        }
+    }
+
+    /// Selects the derivation path at `position` of a multipath key and
+    /// replaces any wildcard with `index`, like
+    /// [`DescriptorPublicKey::at_derivation_index`].
+    ///
+    /// Keys with a single derivation path, or none, only have a path at
+    /// position 0.
+    ///
+    /// # Errors
+    ///
+    /// - If `index` is hardened.
+    /// - If there is no path at `position`.
+    pub fn at_path_and_index(
+        &self,
+        position: usize,
+        index: u32,
+    ) -> Result<DefiniteDescriptorKey, ConversionError> {
+        match *self {
+            DescriptorPublicKey::Single(..) | DescriptorPublicKey::XPub(..) if position == 0 => {
+                self.clone().at_derivation_index(index)
+            }
+            DescriptorPublicKey::Single(..) | DescriptorPublicKey::XPub(..) => {
+                Err(ConversionError::NoSuchPath(position))
+            }
+            DescriptorPublicKey::MultiXPub(ref xpub) => {
+                let derivation_path = xpub
+                    .derivation_paths
+                    .paths()
+                    .get(position)
+                    .ok_or(ConversionError::NoSuchPath(position))?;
+                DescriptorPublicKey::XPub(DescriptorXKey {
+                    origin: xpub.origin.clone(),
+                    xkey: xpub.xkey,
+                    derivation_path: derivation_path.clone(),
+                    wildcard: xpub.wildcard,
+                })
+                .at_derivation_index(index)
+            }
+        }
+    }
+
+    /// Whether this key and `other` can produce the same public key.
+    ///
+    /// This is the case for equal single keys, ignoring origin information, and for
+    /// extended keys with the same xpub whose derivations intersect, e.g. `xpub/0/*`
+    /// and `xpub/<0;1>/*`, or `xpub/*` and `xpub/5`. Single keys are not compared
+    /// against keys derived from extended keys.
+    pub fn overlaps(&self, other: &DescriptorPublicKey) -> bool {
+        type Derivations<'a> = (&'a bip32::Xpub, &'a [bip32::DerivationPath], Wildcard);
+
+        fn derivations(key: &DescriptorPublicKey) -> Option<Derivations<'_>> {
+            match *key {
+                DescriptorPublicKey::Single(..) => None,
+                DescriptorPublicKey::XPub(ref xpub) => {
+                    Some((&xpub.xkey, core::slice::from_ref(&xpub.derivation_path), xpub.wildcard))
+                }
+                DescriptorPublicKey::MultiXPub(ref xpub) => {
+                    Some((&xpub.xkey, xpub.derivation_paths.paths(), xpub.wildcard))
+                }
+            }
+        }
+
+        // Whether the path `prefix` followed by a wildcard can derive `path`.
+        fn wildcard_derives(
+            prefix: &bip32::DerivationPath,
+            wildcard: Wildcard,
+            path: &bip32::DerivationPath,
+        ) -> bool {
+            match path.as_ref().split_last() {
+                Some((last, rest)) => {
+                    rest == prefix.as_ref()
+                        && last.is_hardened() == (wildcard == Wildcard::Hardened)
+                }
+                None => false,
+            }
+        }
+
+        match (self, other) {
+            (DescriptorPublicKey::Single(a), DescriptorPublicKey::Single(b)) => {
+                match (&a.key, &b.key) {
+                    (SinglePubKey::FullKey(a), SinglePubKey::FullKey(b)) => a.inner == b.inner,
+                    (SinglePubKey::FullKey(a), SinglePubKey::XOnly(b))
+                    | (SinglePubKey::XOnly(b), SinglePubKey::FullKey(a)) => {
+                        a.inner.x_only_public_key().0 == *b
+                    }
+                    (SinglePubKey::XOnly(a), SinglePubKey::XOnly(b)) => a == b,
+                }
+            }
+            _ => match (derivations(self), derivations(other)) {
+                (Some((xkey_a, paths_a, wc_a)), Some((xkey_b, paths_b, wc_b)))
+                    if xkey_a == xkey_b =>
+                {
+                    paths_a.iter().any(|a| {
+                        paths_b.iter().any(|b| match (wc_a, wc_b) {
+                            (Wildcard::None, Wildcard::None) => a == b,
+                            (_, Wildcard::None) => wildcard_derives(a, wc_a, b),
+                            (Wildcard::None, _) => wildcard_derives(b, wc_b, a),
+                            _ => a == b && wc_a == wc_b,
+                        })
+                    })
+                }
+                _ => false,
+            },
+        }
+    }
 }
 
 impl FromStr for DescriptorSecretKey {
@@ -986,12 +1219,31 @@ fn parse_key_origin(s: &str) -> Result<(&str, Option<bip32::KeySource>), Descrip
     }
 }
 
+// SLIP-132 keys are accepted and converted to plain BIP 32 keys
 fn parse_bip32_xpub(xkey_str: &str) -> Result<bip32::Xpub, DescriptorKeyParseError> {
-    bip32::Xpub::from_str(xkey_str).map_err(DescriptorKeyParseError::Bip32Xpub)
+    bip32::Xpub::from_str(xkey_str).or_else(|err| match slip132::decode_xpub(xkey_str) {
+        Some((xpub, _)) => Ok(xpub),
+        None => Err(DescriptorKeyParseError::Bip32Xpub(err)),
+    })
 }
 
 fn parse_bip32_xpriv(xkey_str: &str) -> Result<bip32::Xpriv, DescriptorKeyParseError> {
-    bip32::Xpriv::from_str(xkey_str).map_err(DescriptorKeyParseError::Bip32Xpriv)
+    bip32::Xpriv::from_str(xkey_str).or_else(|err| match slip132::decode_xpriv(xkey_str) {
+        Some((xpriv, _)) => Ok(xpriv),
+        None => Err(DescriptorKeyParseError::Bip32Xpriv(err)),
+    })
+}
+
+// The script type hinted at by the version bytes of the extended key in
+// `s`, if it is a SLIP-132 one
+fn slip132_script_type(s: &str, private: bool) -> Option<DescriptorType> {
+    let (key_part, _) = parse_key_origin(s).ok()?;
+    let xkey_str = key_part.split('/').next()?;
+    if private {
+        slip132::decode_xpriv(xkey_str).map(|(_, script_type)| script_type)
+    } else {
+        slip132::decode_xpub(xkey_str).map(|(_, script_type)| script_type)
+    }
 }
 
 fn parse_xkey_deriv<Key>(
@@ -1233,6 +1485,25 @@ impl DefiniteDescriptorKey {
     pub fn derive_public_key<C: Verification>(
         &self,
         secp: &Secp256k1<C>,
+    ) -> Result<bitcoin::PublicKey, ConversionError> {
+        self.derive_public_key_with(secp, None)
+    }
+
+    /// Computes the public key corresponding to this descriptor key, like
+    /// [`DefiniteDescriptorKey::derive_public_key`], reusing the derivations
+    /// in `cache` and adding the new ones to it.
+    pub fn derive_public_key_cached<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        cache: &mut DerivationCache,
+    ) -> Result<bitcoin::PublicKey, ConversionError> {
+        self.derive_public_key_with(secp, Some(cache))
+    }
+
+    fn derive_public_key_with<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        cache: Option<&mut DerivationCache>,
     ) -> Result<bitcoin::PublicKey, ConversionError> {
         match self.0 {
             DescriptorPublicKey::Single(ref pk) => match pk.key {
@@ -1243,13 +1514,19 @@ impl DefiniteDescriptorKey {
                 Wildcard::Unhardened | Wildcard::Hardened => {
                     unreachable!("we've excluded this error case")
                 }
-                Wildcard::None => match xpk.xkey.derive_pub(secp, &xpk.derivation_path.as_ref()) {
-                    Ok(xpub) => Ok(bitcoin::PublicKey::new(xpub.public_key)),
-                    Err(bip32::Error::CannotDeriveFromHardenedKey) => {
-                        Err(ConversionError::HardenedChild)
+                Wildcard::None => {
+                    let derived = match cache {
+                        Some(cache) => cache.derive_pub(secp, &xpk.xkey, &xpk.derivation_path),
+                        None => xpk.xkey.derive_pub(secp, &xpk.derivation_path.as_ref()),
+                    };
+                    match derived {
+                        Ok(xpub) => Ok(bitcoin::PublicKey::new(xpub.public_key)),
+                        Err(bip32::Error::CannotDeriveFromHardenedKey) => {
+                            Err(ConversionError::HardenedChild)
+                        }
+                        Err(e) => unreachable!("cryptographically unreachable: {}", e),
                     }
-                    Err(e) => unreachable!("cryptographically unreachable: {}", e),
-                },
+                }
             },
             DescriptorPublicKey::MultiXPub(_) => {
                 unreachable!("A definite key cannot contain a multipath key.")
@@ -1319,8 +1596,7 @@ impl MiniscriptKey for DefiniteDescriptorKey {
 
 impl ToPublicKey for DefiniteDescriptorKey {
     fn to_public_key(&self) -> bitcoin::PublicKey {
-        let secp = Secp256k1::verification_only();
-        self.derive_public_key(&secp).unwrap()
+        with_verify_context(|secp| self.derive_public_key(secp)).unwrap()
     }
 
     fn to_sha256(hash: &sha256::Hash) -> sha256::Hash { *hash }
@@ -1370,7 +1646,8 @@ mod test {
     use serde_test::{assert_tokens, Token};
 
     use super::{
-        DescriptorMultiXKey, DescriptorPublicKey, DescriptorSecretKey, MiniscriptKey, Wildcard,
+        ConversionError, DescriptorMultiXKey, DescriptorPublicKey, DescriptorSecretKey,
+        DescriptorType, MiniscriptKey, PathRole, Wildcard,
     };
     use crate::prelude::*;
 
@@ -1713,6 +1990,40 @@ mod test {
         DescriptorPublicKey::from_str("tpubDBrgjcxBxnXyL575sHdkpKohWu5qHKoQ7TJXKNrYznh5fVEGBv89hA8ENW7A8MFVpFUSvgLqc4Nj1WZcpePX6rrxviVtPowvMuGF5rdT2Vi/2/4/<0;1;>").unwrap_err();
     }
 
+    #[test]
+    fn multipath_roles() {
+        let xpub = "tpubDBrgjcxBxnXyL575sHdkpKohWu5qHKoQ7TJXKNrYznh5fVEGBv89hA8ENW7A8MFVpFUSvgLqc4Nj1WZcpePX6rrxviVtPowvMuGF5rdT2Vi";
+        let key =
+            DescriptorPublicKey::from_str(&format!("[abcdef00/0'/1']{}/<0;1;7>/*", xpub)).unwrap();
+        let roles = key.paths_with_roles();
+        assert_eq!(
+            roles
+                .iter()
+                .map(|(role, path)| (*role, path.to_string()))
+                .collect::<Vec<_>>(),
+            vec![
+                (PathRole::Receive, "0".to_owned()),
+                (PathRole::Change, "1".to_owned()),
+                (PathRole::Other(2), "7".to_owned()),
+            ]
+        );
+        assert_eq!(PathRole::from_position(1), PathRole::Change);
+        assert_eq!(key.path_position(PathRole::Change), Some(1));
+        assert_eq!(key.path_position(PathRole::Other(3)), None);
+
+        let change = key
+            .at_path_and_index(key.path_position(PathRole::Change).unwrap(), 42)
+            .unwrap();
+        assert_eq!(change.to_string(), format!("[abcdef00/0'/1']{}/1/42", xpub));
+        assert_eq!(key.at_path_and_index(3, 0), Err(ConversionError::NoSuchPath(3)));
+
+        let single = DescriptorPublicKey::from_str(&format!("{}/0/*", xpub)).unwrap();
+        assert!(single.paths_with_roles().is_empty());
+        assert_eq!(single.path_position(PathRole::Receive), None);
+        assert_eq!(single.at_path_and_index(0, 5).unwrap().to_string(), format!("{}/0/5", xpub));
+        assert_eq!(single.at_path_and_index(1, 5), Err(ConversionError::NoSuchPath(1)));
+    }
+
     #[test]
     fn test_multixprv_to_public() {
         let secp = secp256k1::Secp256k1::signing_only();
@@ -1748,6 +2059,30 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_slip132_keys() {
+        let zpub = "[73c5da0a/84'/0'/0']zpub6rFR7y4Q2AijBEqTUquhVz398htDFrtymD9xYYfG1m4wAcvPhXNfE3EfH1r1ADqtfSdVCToUG868RvUUkgDKf31mGDtKsAYz2oz2AGutZYs/<0;1>/*";
+        let (key, hint) = DescriptorPublicKey::from_str_with_slip132_hint(zpub).unwrap();
+        assert_eq!(hint, Some(DescriptorType::Wpkh));
+        assert_eq!(key, DescriptorPublicKey::from_str(zpub).unwrap());
+        assert_eq!(key.to_string(), "[73c5da0a/84'/0'/0']xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V/<0;1>/*");
+        assert_eq!(key.to_slip132_string(DescriptorType::Wpkh).unwrap(), zpub);
+        assert!(key.to_slip132_string(DescriptorType::TrKeyOnly).is_none());
+
+        // Plain keys have no hint
+        let (_, hint) = DescriptorPublicKey::from_str_with_slip132_hint(&key.to_string()).unwrap();
+        assert_eq!(hint, None);
+
+        let zprv = "zprvAdG4iTXWBoARxkkzNpNh8r6Qag3irQB8PzEMkAFeTRXxHpbF9z4QgEvBRmfvqWvGp42t42nvgGpNgYSJA9iefm1yYNZKEm7z6qUWCroSQnE/0/*";
+        let (key, hint) = DescriptorSecretKey::from_str_with_slip132_hint(zprv).unwrap();
+        assert_eq!(hint, Some(DescriptorType::Wpkh));
+        assert!(key.to_string().starts_with("xprv"));
+        assert_eq!(key.to_slip132_string(DescriptorType::Wpkh).unwrap(), zprv);
+
+        // SLIP-132 keys can be used in descriptors
+        crate::Descriptor::<DescriptorPublicKey>::from_str(&format!("wpkh({})", zpub)).unwrap();
+    }
+
     #[test]
     #[cfg(feature = "serde")]
     fn test_descriptor_public_key_serde() {
@@ -0,0 +1,426 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Key Positions
+//!
+//! Locates keys in the structure of a descriptor, to tell users which
+//! occurrence of a key a problem is about, and translates keys reporting
+//! every key which failed to translate rather than only the first one.
+
+#[cfg(all(not(feature = "std"), not(test)))]
+use alloc::vec;
+use core::fmt;
+#[cfg(any(feature = "std", test))]
+use std::vec;
+
+use bitcoin::taproot::{LeafVersion, TapLeafHash};
+
+use super::{Descriptor, ShInner, WshInner};
+use crate::miniscript::decode::Terminal;
+use crate::prelude::*;
+use crate::{
+    Error, Miniscript, MiniscriptKey, ScriptContext, ToPublicKey, TranslateErr, Translator,
+};
+
+/// Where a key is in a descriptor.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum KeyPosition {
+    /// The taproot internal key.
+    InternalKey,
+    /// A key in a script, or the key of a `pkh` or `wpkh` descriptor.
+    Script {
+        /// The taproot leaf, given by its index in [`super::Tr::iter_scripts`],
+        /// or `None` outside of taproot.
+        leaf: Option<usize>,
+        /// The path of child indices from the root of the Miniscript to the
+        /// fragment of the key, as yielded by [`Miniscript::iter_with_paths`].
+        /// Empty for keys which are not in a Miniscript.
+        fragment: Vec<usize>,
+        /// The index of the key in its fragment, only non-zero in multisigs.
+        index: usize,
+    },
+}
+
+impl fmt::Display for KeyPosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            KeyPosition::InternalKey => f.write_str("internal key"),
+            KeyPosition::Script { leaf, ref fragment, index } => {
+                if let Some(leaf) = leaf {
+                    write!(f, "leaf {}, ", leaf)?;
+                }
+                f.write_str("fragment ")?;
+                if fragment.is_empty() {
+                    f.write_str("root")?;
+                }
+                for (i, child) in fragment.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(".")?;
+                    }
+                    write!(f, "{}", child)?;
+                }
+                write!(f, ", key {}", index)
+            }
+        }
+    }
+}
+
+impl KeyPosition {
+    /// Returns the taproot leaf of the key, given by its index in
+    /// [`super::Tr::iter_scripts`], or `None` for keys which are not in a
+    /// taproot leaf.
+    pub fn leaf(&self) -> Option<usize> {
+        match *self {
+            KeyPosition::InternalKey => None,
+            KeyPosition::Script { leaf, .. } => leaf,
+        }
+    }
+}
+
+/// Iterator over the keys of a descriptor and their positions, see
+/// [`Descriptor::keys_with_positions`]
+#[derive(Clone, Debug)]
+pub struct KeysWithPositions<'a, Pk: MiniscriptKey> {
+    keys: vec::IntoIter<(&'a Pk, KeyPosition)>,
+}
+
+impl<'a, Pk: MiniscriptKey + 'a> Iterator for KeysWithPositions<'a, Pk> {
+    type Item = (&'a Pk, KeyPosition);
+
+    fn next(&mut self) -> Option<Self::Item> { self.keys.next() }
+
+    fn size_hint(&self) -> (usize, Option<usize>) { self.keys.size_hint() }
+}
+
+impl<'a, Pk: MiniscriptKey + 'a> ExactSizeIterator for KeysWithPositions<'a, Pk> {}
+
+// Pushes the keys of a Miniscript with their positions
+fn push_ms_keys<'a, Pk: MiniscriptKey, Ctx: ScriptContext>(
+    ms: &'a Miniscript<Pk, Ctx>,
+    leaf: Option<usize>,
+    keys: &mut Vec<(&'a Pk, KeyPosition)>,
+) {
+    for (fragment, node) in ms.iter_with_paths() {
+        let pks: Vec<&Pk> = match *node {
+            Terminal::PkK(ref pk) | Terminal::PkH(ref pk) => vec![pk],
+            Terminal::Multi(ref thresh) => thresh.iter().collect(),
+            Terminal::MultiA(ref thresh) => thresh.iter().collect(),
+            _ => continue,
+        };
+        for (index, pk) in pks.into_iter().enumerate() {
+            keys.push((pk, KeyPosition::Script { leaf, fragment: fragment.clone(), index }));
+        }
+    }
+}
+
+// Pushes keys which are not in a Miniscript with their positions
+fn push_keys<'a, Pk: MiniscriptKey>(
+    pks: impl IntoIterator<Item = &'a Pk>,
+    keys: &mut Vec<(&'a Pk, KeyPosition)>,
+) {
+    for (index, pk) in pks.into_iter().enumerate() {
+        keys.push((pk, KeyPosition::Script { leaf: None, fragment: vec![], index }));
+    }
+}
+
+/// A key which failed to translate, see [`Descriptor::translate_pk_collecting`]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct KeyTranslationError<Pk, E> {
+    /// The key.
+    pub key: Pk,
+    /// Where the key is in the descriptor.
+    pub position: KeyPosition,
+    /// The error of the translator.
+    pub error: E,
+}
+
+impl<Pk: fmt::Display, E: fmt::Display> fmt::Display for KeyTranslationError<Pk, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "key {} ({}): {}", self.key, self.position, self.error)
+    }
+}
+
+impl<Pk: ToPublicKey> Descriptor<Pk> {
+    /// Returns the hash of the taproot leaf of a key position, or `None` if
+    /// the position is not in a leaf of this descriptor.
+    pub fn leaf_hash(&self, position: &KeyPosition) -> Option<TapLeafHash> {
+        match (self, position.leaf()) {
+            (Descriptor::Tr(tr), Some(leaf)) => tr
+                .iter_scripts()
+                .nth(leaf)
+                .map(|(_depth, ms)| TapLeafHash::from_script(&ms.encode(), LeafVersion::TapScript)),
+            _ => None,
+        }
+    }
+}
+
+/// Error of [`Descriptor::translate_pk_collecting`]
+#[derive(Debug)]
+pub enum CollectedTranslateErr<Pk, E> {
+    /// The translator failed on these keys, in the order in which they
+    /// appear in the descriptor string.
+    Keys(Vec<KeyTranslationError<Pk, E>>),
+    /// The translator failed on a hash, after all keys were translated.
+    Hash(E),
+    /// The translated descriptor is invalid, see [`TranslateErr::OuterError`].
+    OuterError(Error),
+}
+
+impl<Pk: fmt::Display, E: fmt::Display> fmt::Display for CollectedTranslateErr<Pk, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CollectedTranslateErr::Keys(ref errors) => {
+                write!(f, "{} keys failed to translate", errors.len())?;
+                for (i, e) in errors.iter().enumerate() {
+                    f.write_str(if i == 0 { ": " } else { "; " })?;
+                    fmt::Display::fmt(e, f)?;
+                }
+                Ok(())
+            }
+            CollectedTranslateErr::Hash(ref e) => write!(f, "hash failed to translate: {}", e),
+            CollectedTranslateErr::OuterError(ref e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<Pk, E> std::error::Error for CollectedTranslateErr<Pk, E>
+where
+    Pk: fmt::Debug + fmt::Display,
+    E: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            CollectedTranslateErr::Keys(_) => None,
+            CollectedTranslateErr::Hash(ref e) => Some(e),
+            CollectedTranslateErr::OuterError(ref e) => Some(e),
+        }
+    }
+}
+
+// Translator reusing keys translated beforehand and translating hashes with
+// the wrapped translator
+struct Translated<'t, P: MiniscriptKey, T: Translator<P>> {
+    keys: BTreeMap<P, T::TargetPk>,
+    t: &'t mut T,
+}
+
+impl<P: MiniscriptKey, T: Translator<P>> Translator<P> for Translated<'_, P, T> {
+    type TargetPk = T::TargetPk;
+    type Error = T::Error;
+
+    fn pk(&mut self, pk: &P) -> Result<T::TargetPk, T::Error> {
+        Ok(self.keys.get(pk).expect("all keys translated").clone())
+    }
+
+    fn sha256(
+        &mut self,
+        sha256: &P::Sha256,
+    ) -> Result<<T::TargetPk as MiniscriptKey>::Sha256, T::Error> {
+        self.t.sha256(sha256)
+    }
+
+    fn hash256(
+        &mut self,
+        hash256: &P::Hash256,
+    ) -> Result<<T::TargetPk as MiniscriptKey>::Hash256, T::Error> {
+        self.t.hash256(hash256)
+    }
+
+    fn ripemd160(
+        &mut self,
+        ripemd160: &P::Ripemd160,
+    ) -> Result<<T::TargetPk as MiniscriptKey>::Ripemd160, T::Error> {
+        self.t.ripemd160(ripemd160)
+    }
+
+    fn hash160(
+        &mut self,
+        hash160: &P::Hash160,
+    ) -> Result<<T::TargetPk as MiniscriptKey>::Hash160, T::Error> {
+        self.t.hash160(hash160)
+    }
+}
+
+impl<Pk: MiniscriptKey> Descriptor<Pk> {
+    /// Returns an iterator over the keys of the descriptor and their
+    /// positions, in the order in which they appear in the descriptor
+    /// string.
+    ///
+    /// Unlike [`crate::ForEachKey::for_each_key`], this tells apart several
+    /// occurrences of the same key, e.g. to request signatures for each
+    /// taproot leaf a key is in.
+    pub fn keys_with_positions(&self) -> KeysWithPositions<'_, Pk> {
+        let mut keys = vec![];
+        match *self {
+            Descriptor::Bare(ref bare) => push_ms_keys(bare.as_inner(), None, &mut keys),
+            Descriptor::Pkh(ref pkh) => push_keys(Some(pkh.as_inner()), &mut keys),
+            Descriptor::Wpkh(ref wpkh) => push_keys(Some(wpkh.as_inner()), &mut keys),
+            Descriptor::Sh(ref sh) => match *sh.as_inner() {
+                ShInner::Wsh(ref wsh) => match *wsh.as_inner() {
+                    WshInner::SortedMulti(ref smv) => push_keys(smv.pks(), &mut keys),
+                    WshInner::Ms(ref ms) => push_ms_keys(ms, None, &mut keys),
+                },
+                ShInner::Wpkh(ref wpkh) => push_keys(Some(wpkh.as_inner()), &mut keys),
+                ShInner::SortedMulti(ref smv) => push_keys(smv.pks(), &mut keys),
+                ShInner::Ms(ref ms) => push_ms_keys(ms, None, &mut keys),
+            },
+            Descriptor::Wsh(ref wsh) => match *wsh.as_inner() {
+                WshInner::SortedMulti(ref smv) => push_keys(smv.pks(), &mut keys),
+                WshInner::Ms(ref ms) => push_ms_keys(ms, None, &mut keys),
+            },
+            Descriptor::Tr(ref tr) => {
+                keys.push((tr.internal_key(), KeyPosition::InternalKey));
+                for (leaf, (_depth, ms)) in tr.iter_scripts().enumerate() {
+                    push_ms_keys(ms, Some(leaf), &mut keys);
+                }
+            }
+        }
+        KeysWithPositions { keys: keys.into_iter() }
+    }
+
+    /// Runs a fallible function on every key of the descriptor and its
+    /// position, stopping at and returning the first error.
+    ///
+    /// The keys are visited in the order in which they appear in the
+    /// descriptor string, which for taproot descriptors is not the order of
+    /// [`crate::ForEachKey::for_each_key`].
+    pub fn try_for_each_key_with_position<'a, E, F>(&'a self, mut f: F) -> Result<(), E>
+    where
+        F: FnMut(&'a Pk, KeyPosition) -> Result<(), E>,
+    {
+        self.keys_with_positions()
+            .try_for_each(|(pk, position)| f(pk, position))
+    }
+
+    /// Translates the keys of the descriptor like [`Descriptor::translate_pk`],
+    /// but reports every key the translator fails on, with its position,
+    /// rather than stopping at the first one.
+    ///
+    /// The translator is called once for each occurrence of each key, then
+    /// once for each hash if all keys were translated.
+    pub fn translate_pk_collecting<T>(
+        &self,
+        t: &mut T,
+    ) -> Result<Descriptor<T::TargetPk>, CollectedTranslateErr<Pk, T::Error>>
+    where
+        T: Translator<Pk>,
+    {
+        let mut keys = BTreeMap::new();
+        let mut errors = vec![];
+        for (pk, position) in self.keys_with_positions() {
+            match t.pk(pk) {
+                Ok(translated) => {
+                    keys.insert(pk.clone(), translated);
+                }
+                Err(error) => errors.push(KeyTranslationError { key: pk.clone(), position, error }),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(CollectedTranslateErr::Keys(errors));
+        }
+        self.translate_pk(&mut Translated { keys, t })
+            .map_err(|e| match e {
+                TranslateErr::TranslatorErr(e) => CollectedTranslateErr::Hash(e),
+                TranslateErr::OuterError(e) => CollectedTranslateErr::OuterError(e),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn translate_pk_collecting() {
+        let desc =
+            Descriptor::<String>::from_str("tr(A,{pk(B),and_v(v:multi_a(1,C,D),pk(E))})").unwrap();
+        let mut translator = crate::FnTranslator::new(|pk: &String| match pk.as_str() {
+            "B" | "D" => Err(format!("no key for {}", pk)),
+            _ => Ok(pk.to_lowercase()),
+        });
+        let errors = match desc.translate_pk_collecting(&mut translator) {
+            Err(CollectedTranslateErr::Keys(errors)) => errors,
+            _ => panic!("expected key errors"),
+        };
+        assert_eq!(
+            errors,
+            vec![
+                KeyTranslationError {
+                    key: "B".to_owned(),
+                    position: KeyPosition::Script { leaf: Some(0), fragment: vec![0], index: 0 },
+                    error: "no key for B".to_owned(),
+                },
+                KeyTranslationError {
+                    key: "D".to_owned(),
+                    position: KeyPosition::Script { leaf: Some(1), fragment: vec![0, 0], index: 1 },
+                    error: "no key for D".to_owned(),
+                },
+            ]
+        );
+        assert_eq!(errors[1].to_string(), "key D (leaf 1, fragment 0.0, key 1): no key for D");
+
+        let desc = Descriptor::<String>::from_str("wsh(sortedmulti(1,A,C))").unwrap();
+        let translated = desc.translate_pk_collecting(&mut translator).unwrap();
+        assert_eq!(format!("{:#}", translated), "wsh(sortedmulti(1,a,c))");
+    }
+
+    #[test]
+    fn try_for_each_key() {
+        use crate::ForEachKey;
+
+        let desc = Descriptor::<String>::from_str("tr(A,{pk(B),multi_a(1,C,D)})").unwrap();
+        let mut seen = vec![];
+        let res = desc.try_for_each_key(|pk| {
+            seen.push(pk.clone());
+            if pk == "C" {
+                Err(pk.clone())
+            } else {
+                Ok(())
+            }
+        });
+        assert_eq!(res, Err("C".to_owned()));
+        assert_eq!(seen, vec!["B", "C"]);
+
+        let res = desc.try_for_each_key_with_position(|pk, position| match position {
+            KeyPosition::Script { leaf: Some(1), index: 1, .. } => Err(pk.clone()),
+            _ => Ok(()),
+        });
+        assert_eq!(res, Err("D".to_owned()));
+        assert_eq!(desc.try_for_each_key_with_position(|_, _| Ok::<_, ()>(())), Ok(()));
+    }
+
+    #[test]
+    fn keys_with_positions() {
+        let keys: Vec<bitcoin::PublicKey> =
+            crate::miniscript::iter::test::gen_bitcoin_pubkeys(3, true);
+        let desc = Descriptor::<bitcoin::PublicKey>::from_str(&format!(
+            "tr({},{{pk({}),and_v(v:pk({}),pk({}))}})",
+            keys[0], keys[1], keys[2], keys[1]
+        ))
+        .unwrap();
+        let positions: Vec<_> = desc.keys_with_positions().collect();
+        assert_eq!(positions.len(), 4);
+        assert_eq!(positions[0], (&keys[0], KeyPosition::InternalKey));
+        assert_eq!(
+            positions[3],
+            (&keys[1], KeyPosition::Script { leaf: Some(1), fragment: vec![1, 0], index: 0 })
+        );
+
+        let leaf_hashes: Vec<_> = match desc {
+            Descriptor::Tr(ref tr) => tr
+                .iter_scripts()
+                .map(|(_, ms)| TapLeafHash::from_script(&ms.encode(), LeafVersion::TapScript))
+                .collect(),
+            _ => unreachable!(),
+        };
+        let key_leaves: Vec<_> = positions
+            .iter()
+            .filter(|(pk, _)| **pk == keys[1])
+            .map(|(_, position)| desc.leaf_hash(position))
+            .collect();
+        assert_eq!(key_leaves, vec![Some(leaf_hashes[0]), Some(leaf_hashes[1])]);
+        assert_eq!(desc.leaf_hash(&positions[0].1), None);
+    }
+}
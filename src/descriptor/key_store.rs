@@ -0,0 +1,285 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Key aliases
+//!
+//! Keeps human-readable descriptors and policies, which refer to keys by name
+//! (`wsh(multi(2,alice,bob))`), separate from the key material they stand for.
+
+use core::fmt;
+use core::str::FromStr;
+#[cfg(feature = "std")]
+use std::error;
+
+use bitcoin::bip32;
+use bitcoin::hashes::{hash160, ripemd160, sha256};
+use bitcoin::secp256k1::{Secp256k1, Signing};
+
+use super::{
+    Descriptor, DescriptorKeyParseError, DescriptorPublicKey, DescriptorSecretKey, KeyMap,
+};
+use crate::prelude::*;
+use crate::{hash256, Translator};
+
+/// Keys known by name
+///
+/// Resolves the names used as keys in alias descriptors to actual keys, and
+/// renders descriptors back with the names of the keys it knows. Keys may
+/// also be referred to by the hex of their master fingerprint, as long as
+/// no other key has the same one.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeyStore {
+    keys: BTreeMap<String, DescriptorPublicKey>,
+    secrets: KeyMap,
+}
+
+impl KeyStore {
+    /// Creates an empty key store.
+    pub fn new() -> Self { KeyStore::default() }
+
+    /// Names a public key, returning the key previously known by that name.
+    pub fn insert<S: Into<String>>(
+        &mut self,
+        alias: S,
+        key: DescriptorPublicKey,
+    ) -> Option<DescriptorPublicKey> {
+        self.keys.insert(alias.into(), key)
+    }
+
+    /// Names a secret key, returning the public key previously known by that
+    /// name.
+    ///
+    /// The public key is derived from the secret key, and used in its place
+    /// in descriptors.
+    pub fn insert_secret<S: Into<String>, C: Signing>(
+        &mut self,
+        secp: &Secp256k1<C>,
+        alias: S,
+        key: DescriptorSecretKey,
+    ) -> Result<Option<DescriptorPublicKey>, DescriptorKeyParseError> {
+        let public = key.to_public(secp)?;
+        self.secrets.insert(public.clone(), key);
+        Ok(self.keys.insert(alias.into(), public))
+    }
+
+    /// The public key known by `name`, which is an alias or a master
+    /// fingerprint.
+    pub fn get(&self, name: &str) -> Option<&DescriptorPublicKey> {
+        if let Some(key) = self.keys.get(name) {
+            return Some(key);
+        }
+        let fingerprint = bip32::Fingerprint::from_str(name).ok()?;
+        let mut keys = self
+            .keys
+            .values()
+            .filter(|key| key.master_fingerprint() == fingerprint);
+        match (keys.next(), keys.next()) {
+            (Some(key), None) => Some(key),
+            _ => None,
+        }
+    }
+
+    /// The secret key known by `name`, which is an alias or a master
+    /// fingerprint.
+    pub fn get_secret(&self, name: &str) -> Option<&DescriptorSecretKey> {
+        self.get(name).and_then(|key| self.secrets.get(key))
+    }
+
+    /// The alias of `key`, if it has one.
+    pub fn alias_of(&self, key: &DescriptorPublicKey) -> Option<&str> {
+        self.keys
+            .iter()
+            .find(|(_, k)| *k == key)
+            .map(|(alias, _)| alias.as_str())
+    }
+
+    /// The secret keys of the store, by public key.
+    pub fn key_map(&self) -> &KeyMap { &self.secrets }
+
+    /// Iterates over the aliases and their public keys.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &DescriptorPublicKey)> {
+        self.keys.iter().map(|(alias, key)| (alias.as_str(), key))
+    }
+
+    /// A translator from names to the keys they stand for, see
+    /// [`KeyStore::instantiate`].
+    pub fn resolver(&self) -> AliasResolver<'_> { AliasResolver(self) }
+
+    /// A translator from keys to their aliases, see [`KeyStore::render`].
+    pub fn renderer(&self) -> AliasRenderer<'_> { AliasRenderer(self) }
+
+    /// Replaces the names in an alias descriptor by the keys they stand for.
+    ///
+    /// Keys may also be given as they are.
+    pub fn instantiate(
+        &self,
+        descriptor: &Descriptor<String>,
+    ) -> Result<Descriptor<DescriptorPublicKey>, KeyStoreError> {
+        descriptor
+            .translate_pk(&mut self.resolver())
+            .map_err(|e| e.expect_translator_err("No Outer context errors"))
+    }
+
+    /// Replaces the keys of a descriptor by their aliases.
+    ///
+    /// Keys without an alias are kept as they are.
+    pub fn render(&self, descriptor: &Descriptor<DescriptorPublicKey>) -> Descriptor<String> {
+        descriptor
+            .translate_pk(&mut self.renderer())
+            .expect("Translation to string cannot fail")
+    }
+}
+
+/// Translator from names to keys of a [`KeyStore`].
+///
+/// Names which are not known to the store are parsed as keys, and hashes are
+/// parsed from their hex representation.
+pub struct AliasResolver<'a>(&'a KeyStore);
+
+impl Translator<String> for AliasResolver<'_> {
+    type TargetPk = DescriptorPublicKey;
+    type Error = KeyStoreError;
+
+    fn pk(&mut self, pk: &String) -> Result<DescriptorPublicKey, KeyStoreError> {
+        match self.0.get(pk) {
+            Some(key) => Ok(key.clone()),
+            None => DescriptorPublicKey::from_str(pk)
+                .map_err(|_| KeyStoreError::UnknownAlias(pk.clone())),
+        }
+    }
+
+    fn sha256(&mut self, sha256: &String) -> Result<sha256::Hash, KeyStoreError> {
+        sha256
+            .parse()
+            .map_err(|_| KeyStoreError::InvalidHash(sha256.clone()))
+    }
+
+    fn hash256(&mut self, hash256: &String) -> Result<hash256::Hash, KeyStoreError> {
+        hash256
+            .parse()
+            .map_err(|_| KeyStoreError::InvalidHash(hash256.clone()))
+    }
+
+    fn ripemd160(&mut self, ripemd160: &String) -> Result<ripemd160::Hash, KeyStoreError> {
+        ripemd160
+            .parse()
+            .map_err(|_| KeyStoreError::InvalidHash(ripemd160.clone()))
+    }
+
+    fn hash160(&mut self, hash160: &String) -> Result<hash160::Hash, KeyStoreError> {
+        hash160
+            .parse()
+            .map_err(|_| KeyStoreError::InvalidHash(hash160.clone()))
+    }
+}
+
+/// Translator from keys to their aliases in a [`KeyStore`].
+///
+/// Keys without an alias are rendered as they are.
+pub struct AliasRenderer<'a>(&'a KeyStore);
+
+impl Translator<DescriptorPublicKey> for AliasRenderer<'_> {
+    type TargetPk = String;
+    type Error = core::convert::Infallible;
+
+    fn pk(&mut self, pk: &DescriptorPublicKey) -> Result<String, Self::Error> {
+        Ok(match self.0.alias_of(pk) {
+            Some(alias) => alias.to_owned(),
+            None => pk.to_string(),
+        })
+    }
+
+    fn sha256(&mut self, sha256: &sha256::Hash) -> Result<String, Self::Error> {
+        Ok(sha256.to_string())
+    }
+
+    fn hash256(&mut self, hash256: &hash256::Hash) -> Result<String, Self::Error> {
+        Ok(hash256.to_string())
+    }
+
+    fn ripemd160(&mut self, ripemd160: &ripemd160::Hash) -> Result<String, Self::Error> {
+        Ok(ripemd160.to_string())
+    }
+
+    fn hash160(&mut self, hash160: &hash160::Hash) -> Result<String, Self::Error> {
+        Ok(hash160.to_string())
+    }
+}
+
+/// Errors resolving aliases with a [`KeyStore`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeyStoreError {
+    /// No key is known by this name
+    UnknownAlias(String),
+    /// A hash could not be parsed
+    InvalidHash(String),
+}
+
+impl fmt::Display for KeyStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            KeyStoreError::UnknownAlias(ref alias) => write!(f, "unknown key alias {}", alias),
+            KeyStoreError::InvalidHash(ref hash) => write!(f, "invalid hash {}", hash),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for KeyStoreError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aliases() {
+        let secp = Secp256k1::signing_only();
+        let alice = DescriptorPublicKey::from_str("[d34db33f/48'/0'/0'/2']xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL/<0;1>/*").unwrap();
+        let bob = DescriptorSecretKey::from_str("[c0ffee00/48'/0'/0'/2']xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi/<0;1>/*").unwrap();
+        let carol = "03a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd";
+
+        let mut store = KeyStore::new();
+        assert_eq!(store.insert("alice", alice.clone()), None);
+        assert_eq!(store.insert_secret(&secp, "bob", bob.clone()).unwrap(), None);
+        let bob_public = bob.to_public(&secp).unwrap();
+        assert_eq!(store.get("bob"), Some(&bob_public));
+        assert_eq!(store.get_secret("bob"), Some(&bob));
+        assert_eq!(store.get_secret("alice"), None);
+        // Master fingerprints also name keys
+        assert_eq!(store.get("d34db33f"), Some(&alice));
+        assert_eq!(store.get("deadbeef"), None);
+        assert_eq!(store.alias_of(&alice), Some("alice"));
+        assert_eq!(store.key_map().len(), 1);
+
+        let alias_desc = Descriptor::<String>::from_str(&format!(
+            "wsh(or_d(multi(2,alice,c0ffee00,{}),and_v(v:pk(bob),older(1000))))",
+            carol
+        ))
+        .unwrap();
+        let desc = store.instantiate(&alias_desc).unwrap();
+        assert_eq!(
+            desc,
+            Descriptor::from_str(&format!(
+                "wsh(or_d(multi(2,{},{},{}),and_v(v:pk({}),older(1000))))",
+                alice, bob_public, carol, bob_public
+            ))
+            .unwrap()
+        );
+        assert_eq!(
+            store.render(&desc).to_string(),
+            Descriptor::<String>::from_str(&format!(
+                "wsh(or_d(multi(2,alice,bob,{}),and_v(v:pk(bob),older(1000))))",
+                carol
+            ))
+            .unwrap()
+            .to_string()
+        );
+
+        let unknown = Descriptor::<String>::from_str("wpkh(dave)").unwrap();
+        assert_eq!(
+            store.instantiate(&unknown).unwrap_err(),
+            KeyStoreError::UnknownAlias("dave".to_owned())
+        );
+    }
+}
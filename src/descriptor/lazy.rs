@@ -0,0 +1,122 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Lazy descriptor parsing
+//!
+//! Parsing a descriptor parses and validates all of its keys, which is most
+//! of the cost of parsing. [`LazyDescriptor`] only checks the checksum and
+//! the structure of a descriptor string, borrowing its keys from the string,
+//! so that huge lists of descriptors can be scanned and only the few which
+//! are needed be fully parsed.
+
+use core::str::FromStr;
+
+use super::checksum::verify_checksum;
+use super::Descriptor;
+use crate::prelude::*;
+use crate::{expression, Error, FromStrKey};
+
+/// A descriptor string whose keys are not parsed yet
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LazyDescriptor<'a> {
+    desc: &'a str,
+    keys: Vec<&'a str>,
+}
+
+impl<'a> LazyDescriptor<'a> {
+    /// Checks the checksum, if any, and the structure of a descriptor string,
+    /// without parsing its keys.
+    ///
+    /// Errors in the keys, or in the fragments of the descriptor, are only
+    /// reported by [`LazyDescriptor::instantiate`].
+    pub fn parse(s: &'a str) -> Result<Self, Error> {
+        let tree = expression::Tree::from_str(s)?;
+        let desc = verify_checksum(s).expect("checksum verified when parsing the tree");
+
+        let mut keys = vec![];
+        for node in tree.root().pre_order_iter() {
+            let parent = match node.parent() {
+                Some(parent) => parent,
+                None => continue,
+            };
+            if node.n_children() > 0 {
+                continue;
+            }
+            // Keys are the arguments of key fragments, whichever their wrappers
+            let fragment = parent.name().rsplit(':').next().unwrap_or("");
+            let is_key = match fragment {
+                "pk" | "pkh" | "pk_k" | "pk_h" | "wpkh" => true,
+                "multi" | "sortedmulti" | "multi_a" | "sortedmulti_a" => !node.is_first_child(),
+                "tr" => node.is_first_child(),
+                _ => false,
+            };
+            if is_key {
+                // Names are slices of the string without checksum, which is a
+                // prefix of `s`
+                let pos = node.name_pos();
+                keys.push(&desc[pos..pos + node.name().len()]);
+            }
+        }
+        Ok(LazyDescriptor { desc, keys })
+    }
+
+    /// The descriptor string, without checksum.
+    pub fn as_str(&self) -> &'a str { self.desc }
+
+    /// The keys of the descriptor, as they are written in the descriptor
+    /// string, in order of appearance.
+    pub fn keys(&self) -> &[&'a str] { &self.keys }
+
+    /// Whether `key` appears in the descriptor, written as in the descriptor
+    /// string.
+    pub fn has_key(&self, key: &str) -> bool { self.keys.contains(&key) }
+
+    /// Fully parses the descriptor.
+    pub fn instantiate<Pk: FromStrKey>(&self) -> Result<Descriptor<Pk>, Error> {
+        Descriptor::from_str(self.desc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DescriptorPublicKey;
+
+    #[test]
+    fn lazy_descriptor() {
+        let xpub = "[d34db33f/48'/0'/0'/2']xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL/<0;1>/*";
+        let key = "03a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd";
+        let xonly = &key[2..];
+        let desc =
+            format!("wsh(or_d(multi(2,{},{}),and_v(v:pkh({}),older(1000))))", xpub, key, key);
+        let desc = Descriptor::<DescriptorPublicKey>::from_str(&desc)
+            .unwrap()
+            .to_string();
+
+        let lazy = LazyDescriptor::parse(&desc).unwrap();
+        assert_eq!(lazy.keys(), &[xpub, key, key]);
+        assert!(lazy.has_key(key));
+        assert!(!lazy.has_key(xonly));
+        assert_eq!(lazy.as_str(), desc.split('#').next().unwrap());
+        assert_eq!(
+            lazy.instantiate::<DescriptorPublicKey>()
+                .unwrap()
+                .to_string(),
+            desc
+        );
+
+        let tr = format!("tr({},{{pk({}),multi_a(1,{},{})}})", xonly, xonly, xpub, xonly);
+        let lazy = LazyDescriptor::parse(&tr).unwrap();
+        assert_eq!(lazy.keys(), &[xonly, xonly, xpub, xonly]);
+        assert!(lazy.instantiate::<DescriptorPublicKey>().is_ok());
+
+        // Keys are only checked when instantiating
+        let lazy = LazyDescriptor::parse("wpkh(alice)").unwrap();
+        assert_eq!(lazy.keys(), &["alice"]);
+        assert!(lazy.instantiate::<DescriptorPublicKey>().is_err());
+        assert!(lazy.instantiate::<String>().is_ok());
+
+        // The structure and checksum are checked when parsing
+        assert!(LazyDescriptor::parse("wpkh(alice").is_err());
+        assert!(LazyDescriptor::parse("wpkh(alice)#aaaaaaaa").is_err());
+    }
+}
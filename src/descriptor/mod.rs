@@ -11,6 +11,7 @@
 //! these with BIP32 paths, pay-to-contract instructions, etc.
 //!
 
+use core::convert::TryFrom;
 use core::fmt;
 use core::ops::Range;
 use core::str::{self, FromStr};
@@ -23,35 +24,67 @@ use sync::Arc;
 
 use crate::expression::FromTree as _;
 use crate::miniscript::decode::Terminal;
+use crate::miniscript::extension::{Extension, NoExt};
 use crate::miniscript::{satisfy, Legacy, Miniscript, Segwitv0};
-use crate::plan::{AssetProvider, Plan};
+use crate::plan::{
+    AssetProvider, Assets, ChainTime, MissingAssets, PathAssets, Plan, TaprootPlans, UnlockTime,
+};
 use crate::prelude::*;
 use crate::{
-    expression, hash256, BareCtx, Error, ForEachKey, FromStrKey, MiniscriptKey, ParseError,
-    Satisfier, ToPublicKey, TranslateErr, Translator,
+    expression, hash256, BareCtx, Error, FnTranslator, ForEachKey, FromStrKey, MiniscriptKey,
+    ParseError, Satisfier, ToPublicKey, TranslateErr, Translator,
 };
 
 mod bare;
+mod binary;
+mod builder;
+mod derivation_cache;
+mod duplicate_keys;
+mod key_position;
+mod lazy;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod redacted;
+mod satisfaction;
 mod segwitv0;
 mod sh;
+mod slip132;
 mod sortedmulti;
+mod spend_paths;
 mod tr;
+mod translators;
 
 // Descriptor Exports
 pub use self::bare::{Bare, Pkh};
+pub use self::binary::{BinaryDecodeError, BINARY_ENCODING_VERSION};
+pub use self::builder::{DescriptorBuilder, ShBuilder, TrBuilder, WshBuilder};
+pub use self::derivation_cache::DerivationCache;
+pub use self::duplicate_keys::{DuplicateKey, DuplicateKeyKind};
+pub use self::key_position::{
+    CollectedTranslateErr, KeyPosition, KeyTranslationError, KeysWithPositions,
+};
+pub use self::lazy::LazyDescriptor;
+#[cfg(feature = "rayon")]
+pub use self::parallel::AddressDerivationError;
+pub use self::redacted::{Redacted, REDACTED_MARKER};
+pub use self::satisfaction::{Satisfaction, SatisfactionWeight};
 pub use self::segwitv0::{Wpkh, Wsh, WshInner};
 pub use self::sh::{Sh, ShInner};
 pub use self::sortedmulti::SortedMultiVec;
+pub use self::spend_paths::{NonStandardWitness, SpendPath, SpendPathKind};
 pub use self::tr::{TapTree, Tr};
+pub use self::translators::{KeyHasher, OriginRemapper, OriginStripper, XOnlyConverter};
 
 pub mod checksum;
 mod key;
+mod key_store;
 
 pub use self::key::{
     ConversionError, DefiniteDescriptorKey, DerivPaths, DescriptorKeyParseError,
     DescriptorMultiXKey, DescriptorPublicKey, DescriptorSecretKey, DescriptorXKey, InnerXKey,
-    MalformedKeyDataKind, SinglePriv, SinglePub, SinglePubKey, Wildcard,
+    MalformedKeyDataKind, PathRole, SinglePriv, SinglePub, SinglePubKey, Wildcard,
 };
+pub use self::key_store::{AliasRenderer, AliasResolver, KeyStore, KeyStoreError};
 
 /// Alias type for a map of public key to secret key
 ///
@@ -63,24 +96,24 @@ pub type KeyMap = BTreeMap<DescriptorPublicKey, DescriptorSecretKey>;
 
 /// Script descriptor
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub enum Descriptor<Pk: MiniscriptKey> {
+pub enum Descriptor<Pk: MiniscriptKey, Ext: Extension = NoExt> {
     /// A raw scriptpubkey (including pay-to-pubkey) under Legacy context
-    Bare(Bare<Pk>),
+    Bare(Bare<Pk, Ext>),
     /// Pay-to-PubKey-Hash
     Pkh(Pkh<Pk>),
     /// Pay-to-Witness-PubKey-Hash
     Wpkh(Wpkh<Pk>),
     /// Pay-to-ScriptHash(includes nested wsh/wpkh/sorted multi)
-    Sh(Sh<Pk>),
+    Sh(Sh<Pk, Ext>),
     /// Pay-to-Witness-ScriptHash with Segwitv0 context
-    Wsh(Wsh<Pk>),
+    Wsh(Wsh<Pk, Ext>),
     /// Pay-to-Taproot
-    Tr(Tr<Pk>),
+    Tr(Tr<Pk, Ext>),
 }
 
-impl<Pk: MiniscriptKey> From<Bare<Pk>> for Descriptor<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> From<Bare<Pk, Ext>> for Descriptor<Pk, Ext> {
     #[inline]
-    fn from(inner: Bare<Pk>) -> Self { Descriptor::Bare(inner) }
+    fn from(inner: Bare<Pk, Ext>) -> Self { Descriptor::Bare(inner) }
 }
 
 impl<Pk: MiniscriptKey> From<Pkh<Pk>> for Descriptor<Pk> {
@@ -93,23 +126,96 @@ impl<Pk: MiniscriptKey> From<Wpkh<Pk>> for Descriptor<Pk> {
     fn from(inner: Wpkh<Pk>) -> Self { Descriptor::Wpkh(inner) }
 }
 
-impl<Pk: MiniscriptKey> From<Sh<Pk>> for Descriptor<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> From<Sh<Pk, Ext>> for Descriptor<Pk, Ext> {
     #[inline]
-    fn from(inner: Sh<Pk>) -> Self { Descriptor::Sh(inner) }
+    fn from(inner: Sh<Pk, Ext>) -> Self { Descriptor::Sh(inner) }
 }
 
-impl<Pk: MiniscriptKey> From<Wsh<Pk>> for Descriptor<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> From<Wsh<Pk, Ext>> for Descriptor<Pk, Ext> {
     #[inline]
-    fn from(inner: Wsh<Pk>) -> Self { Descriptor::Wsh(inner) }
+    fn from(inner: Wsh<Pk, Ext>) -> Self { Descriptor::Wsh(inner) }
 }
 
-impl<Pk: MiniscriptKey> From<Tr<Pk>> for Descriptor<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> From<Tr<Pk, Ext>> for Descriptor<Pk, Ext> {
     #[inline]
-    fn from(inner: Tr<Pk>) -> Self { Descriptor::Tr(inner) }
+    fn from(inner: Tr<Pk, Ext>) -> Self { Descriptor::Tr(inner) }
+}
+
+impl<Pk: MiniscriptKey, Ext: Extension> TryFrom<Descriptor<Pk, Ext>> for Bare<Pk, Ext> {
+    type Error = Descriptor<Pk, Ext>;
+
+    /// Returns the descriptor back if it is not a `Bare` descriptor.
+    fn try_from(descriptor: Descriptor<Pk, Ext>) -> Result<Self, Self::Error> {
+        match descriptor {
+            Descriptor::Bare(inner) => Ok(inner),
+            descriptor => Err(descriptor),
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey, Ext: Extension> TryFrom<Descriptor<Pk, Ext>> for Pkh<Pk> {
+    type Error = Descriptor<Pk, Ext>;
+
+    /// Returns the descriptor back if it is not a `Pkh` descriptor.
+    fn try_from(descriptor: Descriptor<Pk, Ext>) -> Result<Self, Self::Error> {
+        match descriptor {
+            Descriptor::Pkh(inner) => Ok(inner),
+            descriptor => Err(descriptor),
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey, Ext: Extension> TryFrom<Descriptor<Pk, Ext>> for Wpkh<Pk> {
+    type Error = Descriptor<Pk, Ext>;
+
+    /// Returns the descriptor back if it is not a `Wpkh` descriptor.
+    fn try_from(descriptor: Descriptor<Pk, Ext>) -> Result<Self, Self::Error> {
+        match descriptor {
+            Descriptor::Wpkh(inner) => Ok(inner),
+            descriptor => Err(descriptor),
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey, Ext: Extension> TryFrom<Descriptor<Pk, Ext>> for Sh<Pk, Ext> {
+    type Error = Descriptor<Pk, Ext>;
+
+    /// Returns the descriptor back if it is not a `Sh` descriptor.
+    fn try_from(descriptor: Descriptor<Pk, Ext>) -> Result<Self, Self::Error> {
+        match descriptor {
+            Descriptor::Sh(inner) => Ok(inner),
+            descriptor => Err(descriptor),
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey, Ext: Extension> TryFrom<Descriptor<Pk, Ext>> for Wsh<Pk, Ext> {
+    type Error = Descriptor<Pk, Ext>;
+
+    /// Returns the descriptor back if it is not a `Wsh` descriptor.
+    fn try_from(descriptor: Descriptor<Pk, Ext>) -> Result<Self, Self::Error> {
+        match descriptor {
+            Descriptor::Wsh(inner) => Ok(inner),
+            descriptor => Err(descriptor),
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey, Ext: Extension> TryFrom<Descriptor<Pk, Ext>> for Tr<Pk, Ext> {
+    type Error = Descriptor<Pk, Ext>;
+
+    /// Returns the descriptor back if it is not a `Tr` descriptor.
+    fn try_from(descriptor: Descriptor<Pk, Ext>) -> Result<Self, Self::Error> {
+        match descriptor {
+            Descriptor::Tr(inner) => Ok(inner),
+            descriptor => Err(descriptor),
+        }
+    }
 }
 
 /// Descriptor Type of the descriptor
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[non_exhaustive]
 pub enum DescriptorType {
     /// Bare descriptor(Contains the native P2pk)
     Bare,
@@ -131,8 +237,10 @@ pub enum DescriptorType {
     WshSortedMulti,
     /// Sh Wsh Sorted Multi
     ShWshSortedMulti,
-    /// Tr Descriptor
-    Tr,
+    /// Tr Descriptor without script tree, only spendable by key
+    TrKeyOnly,
+    /// Tr Descriptor with a script tree
+    TrScript,
 }
 
 impl DescriptorType {
@@ -142,13 +250,47 @@ impl DescriptorType {
     pub fn segwit_version(&self) -> Option<WitnessVersion> {
         use self::DescriptorType::*;
         match self {
-            Tr => Some(WitnessVersion::V1),
+            TrKeyOnly | TrScript => Some(WitnessVersion::V1),
             Wpkh | ShWpkh | Wsh | ShWsh | ShWshSortedMulti | WshSortedMulti => {
                 Some(WitnessVersion::V0)
             }
             Bare | Sh | Pkh | ShSortedMulti => None,
         }
     }
+
+    /// Whether the descriptor type is taproot.
+    pub fn is_taproot(&self) -> bool {
+        matches!(self, DescriptorType::TrKeyOnly | DescriptorType::TrScript)
+    }
+
+    /// Whether the scriptPubKey of the descriptor type is a witness program.
+    ///
+    /// This is `false` for p2sh-wrapped segwit, whose spends nonetheless
+    /// require a witness, see [`DescriptorType::requires_witness`].
+    pub fn is_segwit(&self) -> bool {
+        use self::DescriptorType::*;
+        match self {
+            Wpkh | Wsh | WshSortedMulti | TrKeyOnly | TrScript => true,
+            Bare | Sh | Pkh | ShWsh | ShWpkh | ShSortedMulti | ShWshSortedMulti => false,
+        }
+    }
+
+    /// Whether spends of the descriptor type have a witness, that is whether
+    /// it is native or p2sh-wrapped segwit.
+    pub fn requires_witness(&self) -> bool { self.segwit_version().is_some() }
+
+    /// Whether the descriptor type has a script, a Miniscript or a
+    /// sortedmulti, rather than only a single key.
+    ///
+    /// For taproot, this is whether there is a script tree.
+    pub fn has_script_path(&self) -> bool {
+        use self::DescriptorType::*;
+        match self {
+            Bare | Sh | Wsh | ShWsh | ShSortedMulti | WshSortedMulti | ShWshSortedMulti
+            | TrScript => true,
+            Pkh | Wpkh | ShWpkh | TrKeyOnly => false,
+        }
+    }
 }
 
 impl<Pk: MiniscriptKey> Descriptor<Pk> {
@@ -175,69 +317,71 @@ impl<Pk: MiniscriptKey> Descriptor<Pk> {
     /// Errors when uncompressed keys are supplied
     pub fn new_sh_wpkh(pk: Pk) -> Result<Self, Error> { Ok(Descriptor::Sh(Sh::new_wpkh(pk)?)) }
 
+    // Wrap with sh
+
+    /// Create a new sh wrapper for the given wpkh descriptor
+    pub fn new_sh_with_wpkh(wpkh: Wpkh<Pk>) -> Self { Descriptor::Sh(Sh::new_with_wpkh(wpkh)) }
+
+    // sorted multi
+
+    /// Create a new sh sortedmulti descriptor with threshold `k`
+    /// and Vec of `pks`.
+    /// Errors when miniscript exceeds resource limits under p2sh context
+    pub fn new_sh_sortedmulti(k: usize, pks: Vec<Pk>) -> Result<Self, Error> {
+        Ok(Descriptor::Sh(Sh::new_sortedmulti(k, pks)?))
+    }
+
+    /// Create a new sh wrapped wsh sortedmulti descriptor from threshold
+    /// `k` and Vec of `pks`
+    /// Errors when miniscript exceeds resource limits under segwit context
+    pub fn new_sh_wsh_sortedmulti(k: usize, pks: Vec<Pk>) -> Result<Self, Error> {
+        Ok(Descriptor::Sh(Sh::new_wsh_sortedmulti(k, pks)?))
+    }
+
+    /// Create a new wsh sorted multi descriptor
+    /// Errors when miniscript exceeds resource limits under p2sh context
+    pub fn new_wsh_sortedmulti(k: usize, pks: Vec<Pk>) -> Result<Self, Error> {
+        Ok(Descriptor::Wsh(Wsh::new_sortedmulti(k, pks)?))
+    }
+}
+
+impl<Pk: MiniscriptKey, Ext: Extension> Descriptor<Pk, Ext> {
     // Miniscripts
 
     /// Create a new sh for a given redeem script
     /// Errors when miniscript exceeds resource limits under p2sh context
     /// or does not type check at the top level
-    pub fn new_sh(ms: Miniscript<Pk, Legacy>) -> Result<Self, Error> {
+    pub fn new_sh(ms: Miniscript<Pk, Legacy, Ext>) -> Result<Self, Error> {
         Ok(Descriptor::Sh(Sh::new(ms)?))
     }
 
     /// Create a new wsh descriptor from witness script
     /// Errors when miniscript exceeds resource limits under p2sh context
     /// or does not type check at the top level
-    pub fn new_wsh(ms: Miniscript<Pk, Segwitv0>) -> Result<Self, Error> {
+    pub fn new_wsh(ms: Miniscript<Pk, Segwitv0, Ext>) -> Result<Self, Error> {
         Ok(Descriptor::Wsh(Wsh::new(ms)?))
     }
 
     /// Create a new sh wrapped wsh descriptor with witness script
     /// Errors when miniscript exceeds resource limits under wsh context
     /// or does not type check at the top level
-    pub fn new_sh_wsh(ms: Miniscript<Pk, Segwitv0>) -> Result<Self, Error> {
+    pub fn new_sh_wsh(ms: Miniscript<Pk, Segwitv0, Ext>) -> Result<Self, Error> {
         Ok(Descriptor::Sh(Sh::new_wsh(ms)?))
     }
 
     /// Create a new bare descriptor from witness script
     /// Errors when miniscript exceeds resource limits under bare context
     /// or does not type check at the top level
-    pub fn new_bare(ms: Miniscript<Pk, BareCtx>) -> Result<Self, Error> {
+    pub fn new_bare(ms: Miniscript<Pk, BareCtx, Ext>) -> Result<Self, Error> {
         Ok(Descriptor::Bare(Bare::new(ms)?))
     }
 
-    // Wrap with sh
-
-    /// Create a new sh wrapper for the given wpkh descriptor
-    pub fn new_sh_with_wpkh(wpkh: Wpkh<Pk>) -> Self { Descriptor::Sh(Sh::new_with_wpkh(wpkh)) }
-
     /// Create a new sh wrapper for the given wsh descriptor
-    pub fn new_sh_with_wsh(wsh: Wsh<Pk>) -> Self { Descriptor::Sh(Sh::new_with_wsh(wsh)) }
-
-    // sorted multi
-
-    /// Create a new sh sortedmulti descriptor with threshold `k`
-    /// and Vec of `pks`.
-    /// Errors when miniscript exceeds resource limits under p2sh context
-    pub fn new_sh_sortedmulti(k: usize, pks: Vec<Pk>) -> Result<Self, Error> {
-        Ok(Descriptor::Sh(Sh::new_sortedmulti(k, pks)?))
-    }
-
-    /// Create a new sh wrapped wsh sortedmulti descriptor from threshold
-    /// `k` and Vec of `pks`
-    /// Errors when miniscript exceeds resource limits under segwit context
-    pub fn new_sh_wsh_sortedmulti(k: usize, pks: Vec<Pk>) -> Result<Self, Error> {
-        Ok(Descriptor::Sh(Sh::new_wsh_sortedmulti(k, pks)?))
-    }
-
-    /// Create a new wsh sorted multi descriptor
-    /// Errors when miniscript exceeds resource limits under p2sh context
-    pub fn new_wsh_sortedmulti(k: usize, pks: Vec<Pk>) -> Result<Self, Error> {
-        Ok(Descriptor::Wsh(Wsh::new_sortedmulti(k, pks)?))
-    }
+    pub fn new_sh_with_wsh(wsh: Wsh<Pk, Ext>) -> Self { Descriptor::Sh(Sh::new_with_wsh(wsh)) }
 
     /// Create new tr descriptor
     /// Errors when miniscript exceeds resource limits under Tap context
-    pub fn new_tr(key: Pk, script: Option<tr::TapTree<Pk>>) -> Result<Self, Error> {
+    pub fn new_tr(key: Pk, script: Option<tr::TapTree<Pk, Ext>>) -> Result<Self, Error> {
         Ok(Descriptor::Tr(Tr::new(key, script)?))
     }
 
@@ -254,7 +398,7 @@ impl<Pk: MiniscriptKey> Descriptor<Pk> {
     ///
     /// To obtain the individual leaves of the tree, call [`TapTree::iter`] on the
     /// returned value.
-    pub fn tap_tree(&self) -> Option<&TapTree<Pk>> {
+    pub fn tap_tree(&self) -> Option<&TapTree<Pk, Ext>> {
         if let Descriptor::Tr(ref tr) = self {
             tr.tap_tree().as_ref()
         } else {
@@ -266,7 +410,7 @@ impl<Pk: MiniscriptKey> Descriptor<Pk> {
     ///
     /// If the descriptor is not a Taproot descriptor, **or** if the descriptor is a
     /// Taproot descriptor containing only a keyspend, returns an empty iterator.
-    pub fn tap_tree_iter(&self) -> tr::TapTreeIter<Pk> {
+    pub fn tap_tree_iter(&self) -> tr::TapTreeIter<Pk, Ext> {
         if let Descriptor::Tr(ref tr) = self {
             if let Some(ref tree) = tr.tap_tree() {
                 return tree.iter();
@@ -275,6 +419,82 @@ impl<Pk: MiniscriptKey> Descriptor<Pk> {
         tr::TapTreeIter::empty()
     }
 
+    /// Accessor for the inner `Bare` descriptor, if this is one.
+    pub fn as_bare(&self) -> Option<&Bare<Pk, Ext>> {
+        match *self {
+            Descriptor::Bare(ref inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    /// Accessor for the inner `Pkh` descriptor, if this is one.
+    pub fn as_pkh(&self) -> Option<&Pkh<Pk>> {
+        match *self {
+            Descriptor::Pkh(ref inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    /// Accessor for the inner `Wpkh` descriptor, if this is one.
+    pub fn as_wpkh(&self) -> Option<&Wpkh<Pk>> {
+        match *self {
+            Descriptor::Wpkh(ref inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    /// Accessor for the inner `Sh` descriptor, if this is one.
+    pub fn as_sh(&self) -> Option<&Sh<Pk, Ext>> {
+        match *self {
+            Descriptor::Sh(ref inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    /// Accessor for the inner `Wsh` descriptor, if this is one.
+    pub fn as_wsh(&self) -> Option<&Wsh<Pk, Ext>> {
+        match *self {
+            Descriptor::Wsh(ref inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    /// Accessor for the inner `Tr` descriptor, if this is one.
+    pub fn as_tr(&self) -> Option<&Tr<Pk, Ext>> {
+        match *self {
+            Descriptor::Tr(ref inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    /// Accessor for the sortedmulti of a `sh(sortedmulti(..))` descriptor.
+    pub fn as_sh_sorted_multi(&self) -> Option<&SortedMultiVec<Pk, Legacy>> {
+        match *self {
+            Descriptor::Sh(ref sh) => match *sh.as_inner() {
+                ShInner::SortedMulti(ref smv) => Some(smv),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Accessor for the sortedmulti of a `wsh(sortedmulti(..))` or
+    /// `sh(wsh(sortedmulti(..)))` descriptor.
+    pub fn as_wsh_sorted_multi(&self) -> Option<&SortedMultiVec<Pk, Segwitv0>> {
+        let wsh = match *self {
+            Descriptor::Wsh(ref wsh) => wsh,
+            Descriptor::Sh(ref sh) => match *sh.as_inner() {
+                ShInner::Wsh(ref wsh) => wsh,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        match *wsh.as_inner() {
+            WshInner::SortedMulti(ref smv) => Some(smv),
+            WshInner::Ms(_) => None,
+        }
+    }
+
     /// Get the [DescriptorType] of [Descriptor]
     pub fn desc_type(&self) -> DescriptorType {
         match *self {
@@ -294,7 +514,10 @@ impl<Pk: MiniscriptKey> Descriptor<Pk> {
                 WshInner::SortedMulti(ref _smv) => DescriptorType::WshSortedMulti,
                 WshInner::Ms(ref _ms) => DescriptorType::Wsh,
             },
-            Descriptor::Tr(ref _tr) => DescriptorType::Tr,
+            Descriptor::Tr(ref tr) => match tr.tap_tree() {
+                None => DescriptorType::TrKeyOnly,
+                Some(_) => DescriptorType::TrScript,
+            },
         }
     }
 
@@ -398,7 +621,7 @@ impl<Pk: MiniscriptKey> Descriptor<Pk> {
     pub fn translate_pk<T>(
         &self,
         t: &mut T,
-    ) -> Result<Descriptor<T::TargetPk>, TranslateErr<T::Error>>
+    ) -> Result<Descriptor<T::TargetPk, Ext>, TranslateErr<T::Error>>
     where
         T: Translator<Pk>,
     {
@@ -412,9 +635,47 @@ impl<Pk: MiniscriptKey> Descriptor<Pk> {
         };
         Ok(desc)
     }
+
+    /// Converts a descriptor using one kind of keys to another kind of key,
+    /// translating each key with the closure `f`.
+    ///
+    /// Hashes are kept as they are, so both kinds of keys must use the same
+    /// hash types. For anything else, use [`Descriptor::translate_pk`].
+    pub fn translate_pk_with<Q, E, F>(&self, f: F) -> Result<Descriptor<Q, Ext>, TranslateErr<E>>
+    where
+        Q: MiniscriptKey<
+            Sha256 = Pk::Sha256,
+            Hash256 = Pk::Hash256,
+            Ripemd160 = Pk::Ripemd160,
+            Hash160 = Pk::Hash160,
+        >,
+        F: FnMut(&Pk) -> Result<Q, E>,
+    {
+        self.translate_pk(&mut FnTranslator::new(f))
+    }
+
+    /// Substitutes all raw public key hashes (`expr_raw_pkh` fragments) in the
+    /// descriptor with the public keys as provided by map.
+    ///
+    /// The returned descriptor contains no raw public key hashes. If any hash
+    /// is missing from the map, the set of all such hashes is returned instead.
+    pub fn resolve_raw_pkh(
+        &self,
+        pk_map: &BTreeMap<hash160::Hash, Pk>,
+    ) -> Result<Descriptor<Pk, Ext>, BTreeSet<hash160::Hash>> {
+        let desc = match *self {
+            Descriptor::Bare(ref bare) => Descriptor::Bare(bare.resolve_raw_pkh(pk_map)?),
+            Descriptor::Pkh(ref pk) => Descriptor::Pkh(pk.clone()),
+            Descriptor::Wpkh(ref pk) => Descriptor::Wpkh(pk.clone()),
+            Descriptor::Sh(ref sh) => Descriptor::Sh(sh.resolve_raw_pkh(pk_map)?),
+            Descriptor::Wsh(ref wsh) => Descriptor::Wsh(wsh.resolve_raw_pkh(pk_map)?),
+            Descriptor::Tr(ref tr) => Descriptor::Tr(tr.resolve_raw_pkh(pk_map)?),
+        };
+        Ok(desc)
+    }
 }
 
-impl<Pk: MiniscriptKey + ToPublicKey> Descriptor<Pk> {
+impl<Pk: MiniscriptKey + ToPublicKey, Ext: Extension> Descriptor<Pk, Ext> {
     /// Computes the Bitcoin address of the descriptor, if one exists
     ///
     /// Some descriptors like pk() don't have an address.
@@ -444,6 +705,22 @@ impl<Pk: MiniscriptKey + ToPublicKey> Descriptor<Pk> {
         }
     }
 
+    /// Computes the scriptpubkey of the descriptor into `script`, reusing its
+    /// allocation rather than allocating a new script.
+    ///
+    /// This is meant for hot loops, such as indexing many outputs, where a
+    /// single buffer can be used for all the scripts.
+    pub fn script_pubkey_into(&self, script: &mut ScriptBuf) {
+        match *self {
+            Descriptor::Bare(ref bare) => bare.script_pubkey_into(script),
+            Descriptor::Pkh(ref pkh) => pkh.script_pubkey_into(script),
+            Descriptor::Wpkh(ref wpkh) => wpkh.script_pubkey_into(script),
+            Descriptor::Wsh(ref wsh) => wsh.script_pubkey_into(script),
+            Descriptor::Sh(ref sh) => sh.script_pubkey_into(script),
+            Descriptor::Tr(ref tr) => tr.script_pubkey_into(script),
+        }
+    }
+
     /// Computes the scriptSig that will be in place for an unsigned input
     /// spending an output with this descriptor. For pre-segwit descriptors,
     /// which use the scriptSig for signatures, this returns the empty script.
@@ -500,6 +777,11 @@ impl<Pk: MiniscriptKey + ToPublicKey> Descriptor<Pk> {
     /// Returns satisfying non-malleable witness and scriptSig to spend an
     /// output controlled by the given descriptor if it possible to
     /// construct one using the satisfier S.
+    ///
+    /// The witness is deterministic; see [`SATISFACTION_ORDERING_VERSION`] for how
+    /// satisfactions of equal weight are chosen between.
+    ///
+    /// [`SATISFACTION_ORDERING_VERSION`]: crate::miniscript::satisfy::SATISFACTION_ORDERING_VERSION
     pub fn get_satisfaction<S>(&self, satisfier: S) -> Result<(Vec<Vec<u8>>, ScriptBuf), Error>
     where
         S: Satisfier<Pk>,
@@ -563,16 +845,7 @@ impl Descriptor<DefiniteDescriptorKey> {
             Descriptor::Tr(ref tr) => tr.plan_satisfaction(provider),
         };
 
-        if let satisfy::Witness::Stack(stack) = satisfaction.stack {
-            Ok(Plan {
-                descriptor: self,
-                template: stack,
-                absolute_timelock: satisfaction.absolute_timelock.map(Into::into),
-                relative_timelock: satisfaction.relative_timelock.map(Into::into),
-            })
-        } else {
-            Err(self)
-        }
+        self.plan_from_satisfaction(satisfaction)
     }
 
     /// Returns a plan if the provided assets are sufficient to produce a malleable satisfaction
@@ -592,21 +865,174 @@ impl Descriptor<DefiniteDescriptorKey> {
             Descriptor::Tr(ref tr) => tr.plan_satisfaction_mall(provider),
         };
 
+        self.plan_from_satisfaction(satisfaction)
+    }
+
+    /// Returns a plan for the given satisfaction of this descriptor, if it has a witness
+    #[allow(clippy::result_large_err)] // our "error type" is the original descriptor
+    fn plan_from_satisfaction(
+        self,
+        satisfaction: satisfy::Satisfaction<satisfy::Placeholder<DefiniteDescriptorKey>>,
+    ) -> Result<Plan, Self> {
         if let satisfy::Witness::Stack(stack) = satisfaction.stack {
             Ok(Plan {
                 descriptor: self,
                 template: stack,
                 absolute_timelock: satisfaction.absolute_timelock.map(Into::into),
-                // unwrap to be removed in a later commit
                 relative_timelock: satisfaction.relative_timelock.map(Into::into),
             })
         } else {
             Err(self)
         }
     }
+
+    /// Returns a plan if the provided assets are sufficient to produce a non-malleable satisfaction
+    ///
+    /// Unlike [`Descriptor::plan`], if the assets aren't sufficient this returns the assets
+    /// missing for each candidate spending path. See [`MissingAssets`] for details.
+    pub fn try_plan<P>(self, provider: &P) -> Result<Plan, MissingAssets<DefiniteDescriptorKey>>
+    where
+        P: AssetProvider<DefiniteDescriptorKey>,
+    {
+        self.plan(provider)
+            .map_err(|desc| desc.missing_assets(provider))
+    }
+
+    /// Returns a plan for every spending path which the provided assets can satisfy
+    ///
+    /// Unlike [`Descriptor::plan`], which only returns the cheapest plan, this lets the caller
+    /// choose a spending path on other criteria, such as privacy or which signers are quickest
+    /// to reach. Each plan gives its weight with [`Plan::satisfaction_weight`] and what it needs
+    /// with [`Plan::requirements`].
+    ///
+    /// A spending path is identified by the minimal set of assets it needs, as reported by
+    /// [`Descriptor::missing_assets`], so the paths considered are limited to the first
+    /// [`MAX_MISSING_ASSET_PATHS`]. If several paths need the same assets (e.g. taproot leaves
+    /// with the same keys), only the cheapest is returned. Paths which can only be satisfied
+    /// malleably are skipped. The plans are sorted by satisfaction weight.
+    ///
+    /// [`MAX_MISSING_ASSET_PATHS`]: crate::plan::MAX_MISSING_ASSET_PATHS
+    pub fn plan_all<P>(&self, provider: &P) -> Vec<Plan>
+    where
+        P: AssetProvider<DefiniteDescriptorKey>,
+    {
+        let mut plans: Vec<Plan> = self
+            .missing_assets(&Assets::new())
+            .paths()
+            .iter()
+            .filter_map(|path| self.clone().plan(&PathAssets { provider, path }).ok())
+            .collect();
+        plans.sort_by_key(Plan::satisfaction_weight);
+        plans
+    }
+
+    /// Returns the cheapest plan which can be used in the block after the chain tip `at`
+    ///
+    /// This allows planning a spend ahead of time, e.g. to schedule a recovery sweep: the
+    /// timelocks of the plan are checked against `at` rather than those of `assets`, which are
+    /// ignored. `confirmation` is the position of the output being spent, needed for relative
+    /// timelocks: the height of the block confirming it, and, as in BIP 68, the median time
+    /// past of the block before that one. If it is `None`, no relative timelock is satisfied.
+    pub fn plan_at(
+        &self,
+        assets: &Assets,
+        at: ChainTime,
+        confirmation: Option<ChainTime>,
+    ) -> Option<Plan> {
+        let absolute: Vec<_> = at.absolute_timelocks().into_iter().map(Some).collect();
+        let relative: Vec<_> = match confirmation {
+            Some(confirmation) => at
+                .relative_timelocks(confirmation)
+                .into_iter()
+                .map(Some)
+                .collect(),
+            None => vec![None],
+        };
+        // A spending path can't mix units, so it's enough to try each pair of them
+        absolute
+            .iter()
+            .flat_map(|abs| relative.iter().map(move |rel| (*abs, *rel)))
+            .filter_map(|(absolute_timelock, relative_timelock)| {
+                let assets = Assets { absolute_timelock, relative_timelock, ..assets.clone() };
+                self.clone().plan(&assets).ok()
+            })
+            .min_by_key(Plan::satisfaction_weight)
+    }
+
+    /// Returns the earliest chain tips at which this descriptor can be spent with `assets`
+    ///
+    /// Each spending path whose only missing assets are timelocks gives an [`UnlockTime`]. As
+    /// heights and times can't be compared, several may be returned, each of which is earlier
+    /// than the others in height or in time. The descriptor becomes spendable after the first
+    /// one to be reached; if no timelocks are needed, a single [`UnlockTime`] with neither a
+    /// height nor a time is returned. An empty list means it can never be spent with `assets`.
+    ///
+    /// The timelocks of `assets` are ignored, and `confirmation` is as in
+    /// [`Descriptor::plan_at`]. Paths with relative timelocks are only considered if it is
+    /// given. Like [`Descriptor::missing_assets`], this doesn't check that a path can be
+    /// satisfied non-malleably, or that its timelocks can be combined.
+    pub fn unlock_times(
+        &self,
+        assets: &Assets,
+        confirmation: Option<ChainTime>,
+    ) -> Vec<UnlockTime> {
+        let assets = Assets { absolute_timelock: None, relative_timelock: None, ..assets.clone() };
+        let mut ret: Vec<UnlockTime> = vec![];
+        for path in self.missing_assets(&assets).paths() {
+            let unlock = match UnlockTime::of_timelocks(path, confirmation) {
+                Some(unlock) => unlock,
+                None => continue,
+            };
+            if ret.iter().any(|earlier| earlier.no_later_than(&unlock)) {
+                continue;
+            }
+            ret.retain(|later| !unlock.no_later_than(later));
+            ret.push(unlock);
+        }
+        ret
+    }
+
+    /// Returns the cheapest key spend and script spend plans of a taproot descriptor
+    ///
+    /// This allows comparing the cost of the two, e.g. with [`TaprootPlans::script_spend_premium`].
+    /// Returns `None` if this is not a taproot descriptor.
+    pub fn plan_taproot_spends<P>(&self, provider: &P) -> Option<TaprootPlans>
+    where
+        P: AssetProvider<DefiniteDescriptorKey>,
+    {
+        let tr = match *self {
+            Descriptor::Tr(ref tr) => tr,
+            _ => return None,
+        };
+        let key_spend = tr
+            .plan_key_spend_satisfaction(provider)
+            .and_then(|satisfaction| self.clone().plan_from_satisfaction(satisfaction).ok());
+        let script_spend = self
+            .clone()
+            .plan_from_satisfaction(tr.plan_script_spend_satisfaction(provider))
+            .ok();
+        Some(TaprootPlans { key_spend, script_spend })
+    }
+
+    /// Returns the assets missing from `provider` for each candidate spending path
+    ///
+    /// This can be used to explain why [`Descriptor::plan`] failed.
+    pub fn missing_assets<P>(&self, provider: &P) -> MissingAssets<DefiniteDescriptorKey>
+    where
+        P: AssetProvider<DefiniteDescriptorKey>,
+    {
+        match *self {
+            Descriptor::Bare(ref bare) => bare.missing_assets(provider),
+            Descriptor::Pkh(ref pkh) => pkh.missing_assets(provider),
+            Descriptor::Wpkh(ref wpkh) => wpkh.missing_assets(provider),
+            Descriptor::Wsh(ref wsh) => wsh.missing_assets(provider),
+            Descriptor::Sh(ref sh) => sh.missing_assets(provider),
+            Descriptor::Tr(ref tr) => tr.missing_assets(provider),
+        }
+    }
 }
 
-impl<Pk: MiniscriptKey> ForEachKey<Pk> for Descriptor<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> ForEachKey<Pk> for Descriptor<Pk, Ext> {
     fn for_each_key<'a, F: FnMut(&'a Pk) -> bool>(&'a self, pred: F) -> bool {
         match *self {
             Descriptor::Bare(ref bare) => bare.for_each_key(pred),
@@ -697,6 +1123,28 @@ impl Descriptor<DescriptorPublicKey> {
         self.at_derivation_index(index)?.derived_descriptor(secp)
     }
 
+    /// Convert all the public keys in the descriptor to [`bitcoin::PublicKey`]
+    /// by deriving them at `index`, like [`Descriptor::derived_descriptor`].
+    ///
+    /// The extended keys are derived at all but the last step of their path
+    /// only once, and kept in `cache`. Sharing the same cache when deriving
+    /// many indices, or many descriptors with the same extended keys, saves
+    /// most of the derivation work.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error for multi-path descriptors
+    /// or if hardened derivation is attempted,
+    pub fn derived_descriptor_cached<C: secp256k1::Verification>(
+        &self,
+        secp: &secp256k1::Secp256k1<C>,
+        index: u32,
+        cache: &mut DerivationCache,
+    ) -> Result<Descriptor<bitcoin::PublicKey>, ConversionError> {
+        self.at_derivation_index(index)?
+            .derived_descriptor_cached(secp, cache)
+    }
+
     /// Parse a descriptor that may contain secret keys
     ///
     /// Internally turns every secret key found into the corresponding public key and then returns a
@@ -837,8 +1285,9 @@ impl Descriptor<DescriptorPublicKey> {
     ) -> Result<Option<(u32, Descriptor<bitcoin::PublicKey>)>, ConversionError> {
         let range = if self.has_wildcard() { range } else { 0..1 };
 
+        let mut cache = DerivationCache::new();
         for i in range {
-            let concrete = self.derived_descriptor(secp, i)?;
+            let concrete = self.derived_descriptor_cached(secp, i, &mut cache)?;
             if &concrete.script_pubkey() == script_pubkey {
                 return Ok(Some((i, concrete)));
             }
@@ -912,6 +1361,51 @@ impl Descriptor<DescriptorPublicKey> {
 
         Ok(descriptors)
     }
+
+    /// Get the descriptors of [`Descriptor::into_single_descriptors`], with the
+    /// conventional role of the derivation path each of them uses.
+    ///
+    /// A descriptor without multipath key is its own [`PathRole::Receive`]
+    /// descriptor.
+    pub fn into_single_descriptors_with_roles(
+        self,
+    ) -> Result<Vec<(PathRole, Descriptor<DescriptorPublicKey>)>, Error> {
+        Ok(self
+            .into_single_descriptors()?
+            .into_iter()
+            .enumerate()
+            .map(|(position, desc)| (PathRole::from_position(position), desc))
+            .collect())
+    }
+
+    /// Selects the derivation path with the given role in the multipath keys
+    /// of the descriptor and replaces all wildcards with `index`, like
+    /// [`Descriptor::at_derivation_index`] does for the descriptors of
+    /// [`Descriptor::into_single_descriptors_with_roles`].
+    ///
+    /// # Errors
+    ///
+    /// - If index ≥ 2^31
+    /// - If the descriptor has no derivation path with this role
+    pub fn at_multipath_index(
+        &self,
+        role: PathRole,
+        index: u32,
+    ) -> Result<Descriptor<DefiniteDescriptorKey>, ConversionError> {
+        let position = role.position();
+        if !self.is_multipath() && position != 0 {
+            return Err(ConversionError::NoSuchPath(position));
+        }
+        let mut translator = FnTranslator::new(|pk: &DescriptorPublicKey| {
+            if pk.is_multipath() {
+                pk.at_path_and_index(position, index)
+            } else {
+                pk.clone().at_derivation_index(index)
+            }
+        });
+        self.translate_pk(&mut translator)
+            .map_err(|e| e.expect_translator_err("No Context errors while translating"))
+    }
 }
 
 impl Descriptor<DefiniteDescriptorKey> {
@@ -963,11 +1457,79 @@ impl Descriptor<DefiniteDescriptorKey> {
             Err(e) => Err(e.expect_translator_err("No Context errors when deriving keys")),
         }
     }
+
+    /// Convert all the public keys in the descriptor to [`bitcoin::PublicKey`]
+    /// like [`Descriptor::derived_descriptor`], reusing the derivations in
+    /// `cache` and adding the new ones to it.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if hardened derivation is attempted.
+    pub fn derived_descriptor_cached<C: secp256k1::Verification>(
+        &self,
+        secp: &secp256k1::Secp256k1<C>,
+        cache: &mut DerivationCache,
+    ) -> Result<Descriptor<bitcoin::PublicKey>, ConversionError> {
+        struct Derivator<'a, C: secp256k1::Verification>(
+            &'a secp256k1::Secp256k1<C>,
+            &'a mut DerivationCache,
+        );
+
+        impl<C: secp256k1::Verification> Translator<DefiniteDescriptorKey> for Derivator<'_, C> {
+            type TargetPk = bitcoin::PublicKey;
+            type Error = ConversionError;
+
+            fn pk(
+                &mut self,
+                pk: &DefiniteDescriptorKey,
+            ) -> Result<bitcoin::PublicKey, ConversionError> {
+                pk.derive_public_key_cached(self.0, self.1)
+            }
+
+            translate_hash_clone!(DefiniteDescriptorKey, bitcoin::PublicKey, ConversionError);
+        }
+
+        let derived = self.translate_pk(&mut Derivator(secp, cache));
+        match derived {
+            Ok(derived) => Ok(derived),
+            Err(e) => Err(e.expect_translator_err("No Context errors when deriving keys")),
+        }
+    }
 }
 
-impl<Pk: FromStrKey> crate::expression::FromTree for Descriptor<Pk> {
+impl<Pk: FromStrKey, Ext: Extension> Descriptor<Pk, Ext> {
+    /// Parses a descriptor which is known to be valid, such as one the
+    /// application serialized into its own database.
+    ///
+    /// This skips the checks that [`Descriptor::from_str`] does on top of
+    /// parsing: the checksum, if present, is removed without being verified,
+    /// and the leaves of Taproot descriptors are not sanity checked. The
+    /// descriptor is still fully parsed and type checked, so malformed input
+    /// is an error, but corrupted input may be accepted. Descriptors from
+    /// untrusted sources should be parsed with [`Descriptor::from_str`].
+    pub fn from_str_trusted(s: &str) -> Result<Descriptor<Pk, Ext>, Error> {
+        let s = match s.rfind('#') {
+            Some(pos) => &s[..pos],
+            None => s,
+        };
+        let top = expression::Tree::from_str(s)?;
+        Self::from_tree(top.root())
+    }
+
+    /// Parses a descriptor laid out for humans, over several lines and with
+    /// comments, such as one maintained in a policy file.
+    ///
+    /// Whitespace is ignored, and `#` starts a comment running to the end of
+    /// the line, unless it is directly followed by the eight characters of a
+    /// checksum. The descriptor is then parsed as by [`Descriptor::from_str`].
+    pub fn from_str_pretty(s: &str) -> Result<Descriptor<Pk, Ext>, Error> {
+        Descriptor::from_str(&expression::remove_whitespace_and_comments(s))
+    }
+}
+
+impl<Pk: FromStrKey, Ext: Extension> crate::expression::FromTree for Descriptor<Pk, Ext> {
     /// Parse an expression tree into a descriptor.
-    fn from_tree(top: expression::TreeIterItem) -> Result<Descriptor<Pk>, Error> {
+    fn from_tree(top: expression::TreeIterItem) -> Result<Descriptor<Pk, Ext>, Error> {
         Ok(match (top.name(), top.n_children()) {
             ("pkh", 1) => Descriptor::Pkh(Pkh::from_tree(top)?),
             ("wpkh", 1) => Descriptor::Wpkh(Wpkh::from_tree(top)?),
@@ -979,9 +1541,9 @@ impl<Pk: FromStrKey> crate::expression::FromTree for Descriptor<Pk> {
     }
 }
 
-impl<Pk: FromStrKey> FromStr for Descriptor<Pk> {
+impl<Pk: FromStrKey, Ext: Extension> FromStr for Descriptor<Pk, Ext> {
     type Err = Error;
-    fn from_str(s: &str) -> Result<Descriptor<Pk>, Error> {
+    fn from_str(s: &str) -> Result<Descriptor<Pk, Ext>, Error> {
         let top = expression::Tree::from_str(s)?;
         let ret = Self::from_tree(top.root())?;
         if let Descriptor::Tr(ref inner) = ret {
@@ -996,7 +1558,7 @@ impl<Pk: FromStrKey> FromStr for Descriptor<Pk> {
     }
 }
 
-impl<Pk: MiniscriptKey> fmt::Debug for Descriptor<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> fmt::Debug for Descriptor<Pk, Ext> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Descriptor::Bare(ref sub) => fmt::Debug::fmt(sub, f),
@@ -1009,7 +1571,7 @@ impl<Pk: MiniscriptKey> fmt::Debug for Descriptor<Pk> {
     }
 }
 
-impl<Pk: MiniscriptKey> fmt::Display for Descriptor<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> fmt::Display for Descriptor<Pk, Ext> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Descriptor::Bare(ref sub) => fmt::Display::fmt(sub, f),
@@ -1300,6 +1862,35 @@ mod tests {
         );
     }
 
+    #[test]
+    fn script_pubkey_into() {
+        let key = "020000000000000000000000000000000000000000000000000000000000000002";
+        let mut script = ScriptBuf::new();
+        for desc in [
+            format!("multi(1,{})", key),
+            format!("pkh({})", key),
+            format!("wpkh({})", key),
+            format!("wsh(multi(1,{}))", key),
+            format!("wsh(sortedmulti(1,{}))", key),
+            format!("sh(wpkh({}))", key),
+            format!("sh(wsh(pk({})))", key),
+            format!("sh(sortedmulti(1,{}))", key),
+            format!("sh(pk({}))", key),
+            format!("tr({},pk({}))", key, key),
+        ] {
+            let desc = StdDescriptor::from_str(&desc).unwrap();
+            desc.script_pubkey_into(&mut script);
+            assert_eq!(script, desc.script_pubkey());
+        }
+
+        let ms =
+            Miniscript::<bitcoin::PublicKey, Segwitv0>::from_str(&format!("pk({})", key)).unwrap();
+        let mut buf = vec![0x51];
+        ms.encode_to(&mut buf);
+        assert_eq!(buf[0], 0x51);
+        assert_eq!(buf[1..], *ms.encode().as_bytes());
+    }
+
     #[test]
     fn satisfy() {
         let secp = secp256k1::Secp256k1::new();
@@ -1342,7 +1933,7 @@ mod tests {
             sequence: Sequence::from_height(100),
             witness: Witness::default(),
         };
-        let bare = Descriptor::new_bare(ms).unwrap();
+        let bare = StdDescriptor::new_bare(ms).unwrap();
 
         bare.satisfy(&mut txin, &satisfier).expect("satisfaction");
         assert_eq!(
@@ -1416,7 +2007,7 @@ mod tests {
         );
 
         let ms = ms_str!("c:pk_k({})", pk);
-        let sh = Descriptor::new_sh(ms.clone()).unwrap();
+        let sh = StdDescriptor::new_sh(ms.clone()).unwrap();
         sh.satisfy(&mut txin, &satisfier).expect("satisfaction");
         assert_eq!(
             txin,
@@ -1434,7 +2025,7 @@ mod tests {
 
         let ms = ms_str!("c:pk_k({})", pk);
 
-        let wsh = Descriptor::new_wsh(ms.clone()).unwrap();
+        let wsh = StdDescriptor::new_wsh(ms.clone()).unwrap();
         wsh.satisfy(&mut txin, &satisfier).expect("satisfaction");
         assert_eq!(
             txin,
@@ -1447,7 +2038,7 @@ mod tests {
         );
         assert_eq!(wsh.unsigned_script_sig(), bitcoin::ScriptBuf::new());
 
-        let shwsh = Descriptor::new_sh_wsh(ms.clone()).unwrap();
+        let shwsh = StdDescriptor::new_sh_wsh(ms.clone()).unwrap();
         shwsh.satisfy(&mut txin, &satisfier).expect("satisfaction");
         assert_eq!(
             txin,
@@ -1534,6 +2125,55 @@ mod tests {
         )
     }
 
+    #[test]
+    fn variant_accessors() {
+        let desc = Descriptor::<String>::from_str("tr(A,pk(B))").unwrap();
+        assert_eq!(desc.as_tr().unwrap().internal_key(), "A");
+        assert!(desc.as_wsh().is_none());
+        let desc = Wsh::try_from(desc).unwrap_err();
+        let tr = Tr::try_from(desc.clone()).unwrap();
+        assert_eq!(Descriptor::from(tr), desc);
+
+        let desc = Descriptor::<String>::from_str("sh(wsh(sortedmulti(1,A,B)))").unwrap();
+        assert!(desc.as_sh().is_some());
+        assert!(desc.as_sh_sorted_multi().is_none());
+        assert_eq!(desc.as_wsh_sorted_multi().unwrap().k(), 1);
+        let desc = Descriptor::<String>::from_str("sh(sortedmulti(2,A,B))").unwrap();
+        assert_eq!(desc.as_sh_sorted_multi().unwrap().k(), 2);
+        assert!(desc.as_wsh_sorted_multi().is_none());
+    }
+
+    #[test]
+    fn desc_type_predicates() {
+        let check = |desc: &str, ty: DescriptorType, predicates: [bool; 4]| {
+            let desc_type = Descriptor::<String>::from_str(desc).unwrap().desc_type();
+            assert_eq!(desc_type, ty);
+            assert_eq!(
+                [
+                    desc_type.is_taproot(),
+                    desc_type.is_segwit(),
+                    desc_type.requires_witness(),
+                    desc_type.has_script_path(),
+                ],
+                predicates,
+                "{}",
+                desc
+            );
+        };
+        check("pkh(A)", DescriptorType::Pkh, [false, false, false, false]);
+        check("sh(wpkh(A))", DescriptorType::ShWpkh, [false, false, true, false]);
+        check("wpkh(A)", DescriptorType::Wpkh, [false, true, true, false]);
+        check("sh(pk(A))", DescriptorType::Sh, [false, false, false, true]);
+        check(
+            "sh(wsh(sortedmulti(1,A,B)))",
+            DescriptorType::ShWshSortedMulti,
+            [false, false, true, true],
+        );
+        check("wsh(pk(A))", DescriptorType::Wsh, [false, true, true, true]);
+        check("tr(A)", DescriptorType::TrKeyOnly, [true, true, true, false]);
+        check("tr(A,pk(B))", DescriptorType::TrScript, [true, true, true, true]);
+    }
+
     #[test]
     fn tr_script_pubkey() {
         let key = Descriptor::<bitcoin::PublicKey>::from_str(
@@ -1928,6 +2568,58 @@ pk(03f28773c2d975288bc7d1d205c3748651b075fbc6610e58cddeeddf8f19405aa8))";
         assert_eq!(descriptor.to_string(), "sh(wsh(pk(xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL)))#6c6hwr22");
     }
 
+    #[test]
+    fn from_str_trusted() {
+        let descriptor_str = "sh(wsh(pk(xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL)))#6c6hwr22";
+        let descriptor = Descriptor::<DescriptorPublicKey>::from_str(descriptor_str).unwrap();
+        assert_eq!(
+            Descriptor::<DescriptorPublicKey>::from_str_trusted(descriptor_str).unwrap(),
+            descriptor
+        );
+        // The checksum is not verified
+        let bad_checksum = descriptor_str.replace("#6c6hwr22", "#6c6hwr23");
+        Descriptor::<DescriptorPublicKey>::from_str(&bad_checksum).unwrap_err();
+        assert_eq!(
+            Descriptor::<DescriptorPublicKey>::from_str_trusted(&bad_checksum).unwrap(),
+            descriptor
+        );
+
+        // Nor are Taproot leaves sanity checked
+        let key = "08c0fcf8895f4361b4fc77afe2ad53b0bd27dcebfd863421b2b246dc283d4103";
+        let insane = format!("tr({},and_b(pk({}),a:pk({})))", key, key, key);
+        Descriptor::<DescriptorPublicKey>::from_str(&insane).unwrap_err();
+        Descriptor::<DescriptorPublicKey>::from_str_trusted(&insane).unwrap();
+
+        // But descriptors are still parsed
+        Descriptor::<DescriptorPublicKey>::from_str_trusted("sh(wsh(pk(03)))").unwrap_err();
+        Descriptor::<DescriptorPublicKey>::from_str_trusted("wsh(and_b(pk(A),pk(B)))").unwrap_err();
+    }
+
+    #[test]
+    fn from_str_pretty() {
+        let pretty = "
+            # Spendable by both of us, or by me alone after a day
+            wsh(
+              or_d(
+                multi(2, alice, bob),   # the happy path
+                and_v(v:pk(alice), older(144))
+              )
+            )";
+        let descriptor = Descriptor::<String>::from_str(
+            "wsh(or_d(multi(2,alice,bob),and_v(v:pk(alice),older(144))))",
+        )
+        .unwrap();
+        assert_eq!(Descriptor::<String>::from_str_pretty(pretty).unwrap(), descriptor);
+        Descriptor::<String>::from_str(pretty).unwrap_err();
+
+        // Checksums are kept and verified
+        let with_checksum =
+            format!("{}#{} # checked", pretty, descriptor.to_string().split('#').nth(1).unwrap());
+        assert_eq!(Descriptor::<String>::from_str_pretty(&with_checksum).unwrap(), descriptor);
+        let bad_checksum = format!("{}#aaaaaaaa", pretty);
+        Descriptor::<String>::from_str_pretty(&bad_checksum).unwrap_err();
+    }
+
     #[test]
     fn test_xonly_keys() {
         let comp_key = "0308c0fcf8895f4361b4fc77afe2ad53b0bd27dcebfd863421b2b246dc283d4103";
@@ -1967,6 +2659,69 @@ pk(03f28773c2d975288bc7d1d205c3748651b075fbc6610e58cddeeddf8f19405aa8))";
         );
     }
 
+    #[test]
+    fn cached_derivation() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let xpub = "xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL";
+        let descriptors = [
+            format!("wpkh({}/0/*)", xpub),
+            format!("wsh(multi(1,{}/0/*,{}/1/*))", xpub, xpub),
+            format!("tr({}/0/*,pk({}/2))", xpub, xpub),
+        ];
+        let mut cache = DerivationCache::new();
+        for desc in &descriptors {
+            let desc = Descriptor::<DescriptorPublicKey>::from_str(desc).unwrap();
+            for i in 0..5 {
+                assert_eq!(
+                    desc.derived_descriptor_cached(&secp, i, &mut cache),
+                    desc.derived_descriptor(&secp, i)
+                );
+            }
+        }
+        // The xpub is derived once at each of m/0, m/1 and m
+        assert_eq!(cache.len(), 3);
+
+        let hardened =
+            Descriptor::<DescriptorPublicKey>::from_str(&format!("wpkh({}/0h/*)", xpub)).unwrap();
+        assert_eq!(
+            hardened.derived_descriptor_cached(&secp, 0, &mut cache),
+            Err(ConversionError::HardenedChild)
+        );
+    }
+
+    #[test]
+    fn translate_pk_with() {
+        let desc = Descriptor::<String>::from_str(
+            "tr(a,{pk(b),and_v(v:pk(c),sha256(1111111111111111111111111111111111111111111111111111111111111111))})",
+        )
+        .unwrap();
+        let expected = Descriptor::<String>::from_str(
+            "tr(A,{pk(B),and_v(v:pk(C),sha256(1111111111111111111111111111111111111111111111111111111111111111))})",
+        )
+        .unwrap();
+        let upper = |pk: &String| -> Result<String, ()> { Ok(pk.to_uppercase()) };
+        assert_eq!(desc.translate_pk_with(upper).unwrap(), expected);
+
+        let (tree, expected_tree) = match (&desc, &expected) {
+            (Descriptor::Tr(tr), Descriptor::Tr(expected)) => {
+                (tr.tap_tree().as_ref().unwrap(), expected.tap_tree().as_ref().unwrap())
+            }
+            _ => unreachable!(),
+        };
+        assert_eq!(&tree.translate_pk_with(upper).unwrap(), expected_tree);
+        let (_, ms) = tree.iter().next().unwrap();
+        assert_eq!(ms.translate_pk_with(upper).unwrap().to_string(), "pk(B)");
+
+        // Errors of the closure are translator errors
+        let err = desc
+            .translate_pk_with(|pk: &String| match pk.as_str() {
+                "c" => Err(pk.clone()),
+                _ => Ok(pk.clone()),
+            })
+            .unwrap_err();
+        assert_eq!(err.expect_translator_err("closure error"), "c");
+    }
+
     #[test]
     fn display_alternate() {
         let bare = StdDescriptor::from_str(
@@ -2069,7 +2824,42 @@ pk(03f28773c2d975288bc7d1d205c3748651b075fbc6610e58cddeeddf8f19405aa8))";
         // We can detect regular single-path descriptors.
         let notmulti_desc = Descriptor::from_str("wsh(andor(pk(tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/0'/*),older(10000),pk(tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/8/4567/*)))").unwrap();
         assert!(!notmulti_desc.is_multipath());
-        assert_eq!(notmulti_desc.clone().into_single_descriptors().unwrap(), vec![notmulti_desc]);
+        assert_eq!(
+            notmulti_desc.clone().into_single_descriptors().unwrap(),
+            vec![notmulti_desc.clone()]
+        );
+
+        // The single descriptors can be told apart by role rather than by position.
+        let desc = Descriptor::from_str("wsh(andor(pk(tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/0'/<0;1>/*),older(10000),pk(tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/8/4567/*)))").unwrap();
+        let with_roles = desc.clone().into_single_descriptors_with_roles().unwrap();
+        assert_eq!(
+            with_roles.iter().map(|(role, _)| *role).collect::<Vec<_>>(),
+            vec![PathRole::Receive, PathRole::Change]
+        );
+        assert_eq!(
+            desc.at_multipath_index(PathRole::Change, 42).unwrap(),
+            with_roles[1].1.at_derivation_index(42).unwrap()
+        );
+        assert_eq!(
+            desc.at_multipath_index(PathRole::Other(2), 42),
+            Err(ConversionError::NoSuchPath(2))
+        );
+        assert_eq!(
+            notmulti_desc
+                .clone()
+                .into_single_descriptors_with_roles()
+                .unwrap(),
+            vec![(PathRole::Receive, notmulti_desc.clone())]
+        );
+        assert_eq!(
+            notmulti_desc
+                .at_multipath_index(PathRole::Receive, 0)
+                .unwrap(),
+            notmulti_desc.at_derivation_index(0).unwrap()
+        );
+        assert!(notmulti_desc
+            .at_multipath_index(PathRole::Change, 0)
+            .is_err());
 
         // We refuse to parse multipath descriptors with a mismatch in the number of derivation paths between keys.
         Descriptor::<DescriptorPublicKey>::from_str("wsh(andor(pk(tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/0'/<0;1>/*),older(10000),pk(tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/8/<0;1;2;3;4>/*)))").unwrap_err();
@@ -2149,4 +2939,33 @@ pk(03f28773c2d975288bc7d1d205c3748651b075fbc6610e58cddeeddf8f19405aa8))";
         Desc::from_str(&format!("tr({},pk({}))", x_only_key, uncomp_key)).unwrap_err();
         Desc::from_str(&format!("tr({},pk({}))", x_only_key, x_only_key)).unwrap();
     }
+
+    #[test]
+    fn resolve_raw_pkh() {
+        use crate::{ExtParams, Segwitv0};
+
+        let pk = PublicKey::from_str(
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+        )
+        .unwrap();
+        let hash = pk.pubkey_hash().to_raw_hash();
+        let ms = Miniscript::<PublicKey, Segwitv0>::from_str_ext(
+            &format!("and_v(v:pk({}),c:expr_raw_pkh({}))", pk, hash),
+            &ExtParams::allow_all(),
+        )
+        .unwrap();
+        let desc = Descriptor::new_sh_wsh(ms).unwrap();
+
+        let unresolved = desc.resolve_raw_pkh(&BTreeMap::new()).unwrap_err();
+        assert_eq!(unresolved.into_iter().collect::<Vec<_>>(), vec![hash]);
+
+        let mut map = BTreeMap::new();
+        map.insert(hash, pk);
+        let resolved = desc.resolve_raw_pkh(&map).unwrap();
+        assert_eq!(
+            resolved.to_string(),
+            format!("sh(wsh(and_v(v:pk({}),pkh({}))))#kmnh8n5k", pk, pk)
+        );
+        assert_eq!(resolved.script_pubkey(), desc.script_pubkey());
+    }
 }
@@ -0,0 +1,320 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Parallel bulk operations
+//!
+//! With the `rayon` feature, deriving the scripts and addresses of a ranged
+//! descriptor over many indices, and planning spends of large Taproot trees,
+//! are spread over the rayon thread pool. Results always come in the same
+//! order, and are the same, as with the sequential methods.
+
+use core::fmt;
+use core::ops::Range;
+
+use bitcoin::secp256k1::{Secp256k1, Verification};
+use bitcoin::{Address, Network, ScriptBuf};
+use rayon::prelude::*;
+
+use super::tr::{min_witness_satisfaction, tap_leaf_spend};
+use super::{
+    ConversionError, DefiniteDescriptorKey, DerivationCache, Descriptor, DescriptorPublicKey, Tr,
+};
+use crate::miniscript::satisfy::{Placeholder, Satisfaction};
+use crate::plan::{AssetProvider, Plan};
+use crate::prelude::*;
+use crate::Error;
+
+/// An error deriving the addresses of a descriptor, see
+/// [`Descriptor::par_addresses`]
+#[derive(Debug)]
+pub enum AddressDerivationError {
+    /// The descriptor could not be derived.
+    Conversion(ConversionError),
+    /// The derived descriptor has no address.
+    Address(Error),
+}
+
+impl fmt::Display for AddressDerivationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AddressDerivationError::Conversion(ref e) => e.fmt(f),
+            AddressDerivationError::Address(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for AddressDerivationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            AddressDerivationError::Conversion(ref e) => Some(e),
+            AddressDerivationError::Address(ref e) => Some(e),
+        }
+    }
+}
+
+impl Descriptor<DescriptorPublicKey> {
+    // Derives the descriptor at each index of `range` in parallel, mapping each
+    // derived descriptor with `f`
+    fn par_derive<C, T, F>(
+        &self,
+        secp: &Secp256k1<C>,
+        range: Range<u32>,
+        f: F,
+    ) -> Result<Vec<T>, ConversionError>
+    where
+        C: Verification,
+        T: Send,
+        F: Fn(Descriptor<bitcoin::PublicKey>) -> T + Sync,
+    {
+        range
+            .into_par_iter()
+            .map_init(DerivationCache::new, |cache, index| {
+                self.derived_descriptor_cached(secp, index, cache).map(&f)
+            })
+            .collect()
+    }
+
+    /// Derives the script pubkeys of the descriptor at each index of `range`,
+    /// in parallel.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error for multi-path descriptors
+    /// or if hardened derivation is attempted.
+    pub fn par_script_pubkeys<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        range: Range<u32>,
+    ) -> Result<Vec<ScriptBuf>, ConversionError> {
+        self.par_derive(secp, range, |desc| desc.script_pubkey())
+    }
+
+    /// Derives the addresses of the descriptor at each index of `range`, in
+    /// parallel.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error for multi-path and bare descriptors
+    /// or if hardened derivation is attempted.
+    pub fn par_addresses<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        range: Range<u32>,
+        network: Network,
+    ) -> Result<Vec<Address>, AddressDerivationError> {
+        self.par_derive(secp, range, |desc| desc.address(network))
+            .map_err(AddressDerivationError::Conversion)?
+            .into_iter()
+            .collect::<Result<_, _>>()
+            .map_err(AddressDerivationError::Address)
+    }
+
+    /// Derives the script pubkeys of the descriptor at each index of `range`,
+    /// in parallel, and maps each of them to the index it was derived at.
+    ///
+    /// Script pubkeys derived at several indices, as for descriptors without
+    /// wildcard, map to the lowest of them.
+    pub fn par_script_pubkey_index<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        range: Range<u32>,
+    ) -> Result<BTreeMap<ScriptBuf, u32>, ConversionError> {
+        let start = range.start;
+        let spks = self.par_script_pubkeys(secp, range)?;
+        let mut index = BTreeMap::new();
+        for (i, spk) in (start..).zip(spks) {
+            index.entry(spk).or_insert(i);
+        }
+        Ok(index)
+    }
+}
+
+impl Descriptor<DefiniteDescriptorKey> {
+    /// Returns a plan if the provided assets are sufficient to produce a
+    /// non-malleable satisfaction, like [`Descriptor::plan`], planning the
+    /// leaves of a Taproot tree in parallel.
+    ///
+    /// If the assets aren't sufficient for generating a Plan, the descriptor is returned
+    #[allow(clippy::result_large_err)] // our "error type" is the original descriptor
+    pub fn par_plan<P>(self, provider: &P) -> Result<Plan, Self>
+    where
+        P: AssetProvider<DefiniteDescriptorKey> + Sync,
+    {
+        match self {
+            Descriptor::Tr(ref tr) => {
+                let satisfaction = tr.par_plan_satisfaction(provider);
+                self.plan_from_satisfaction(satisfaction)
+            }
+            _ => self.plan(provider),
+        }
+    }
+
+    /// Returns a plan if the provided assets are sufficient to produce a
+    /// malleable satisfaction, like [`Descriptor::plan_mall`], planning the
+    /// leaves of a Taproot tree in parallel.
+    ///
+    /// If the assets aren't sufficient for generating a Plan, the descriptor is returned
+    #[allow(clippy::result_large_err)] // our "error type" is the original descriptor
+    pub fn par_plan_mall<P>(self, provider: &P) -> Result<Plan, Self>
+    where
+        P: AssetProvider<DefiniteDescriptorKey> + Sync,
+    {
+        match self {
+            Descriptor::Tr(ref tr) => {
+                let satisfaction = tr.par_plan_satisfaction_mall(provider);
+                self.plan_from_satisfaction(satisfaction)
+            }
+            _ => self.plan_mall(provider),
+        }
+    }
+}
+
+impl Tr<DefiniteDescriptorKey> {
+    /// Returns a plan if the provided assets are sufficient to produce a
+    /// non-malleable satisfaction, like [`Tr::plan_satisfaction`], planning
+    /// the leaves in parallel.
+    pub fn par_plan_satisfaction<P>(
+        &self,
+        provider: &P,
+    ) -> Satisfaction<Placeholder<DefiniteDescriptorKey>>
+    where
+        P: AssetProvider<DefiniteDescriptorKey> + Sync,
+    {
+        self.par_best_spend(provider, false /* allow_mall */)
+    }
+
+    /// Returns a plan if the provided assets are sufficient to produce a
+    /// malleable satisfaction, like [`Tr::plan_satisfaction_mall`], planning
+    /// the leaves in parallel.
+    pub fn par_plan_satisfaction_mall<P>(
+        &self,
+        provider: &P,
+    ) -> Satisfaction<Placeholder<DefiniteDescriptorKey>>
+    where
+        P: AssetProvider<DefiniteDescriptorKey> + Sync,
+    {
+        self.par_best_spend(provider, true /* allow_mall */)
+    }
+
+    // Tries the key spend path, then the leaves in parallel
+    fn par_best_spend<P>(
+        &self,
+        provider: &P,
+        allow_mall: bool,
+    ) -> Satisfaction<Placeholder<DefiniteDescriptorKey>>
+    where
+        P: AssetProvider<DefiniteDescriptorKey> + Sync,
+    {
+        if let Some(satisfaction) = self.plan_key_spend_satisfaction(provider) {
+            return satisfaction;
+        }
+        let spend_info = self.spend_info();
+        let leaves = self.iter_scripts().map(|(_, ms)| ms).collect::<Vec<_>>();
+        let satisfactions = leaves
+            .par_iter()
+            .filter_map(|ms| tap_leaf_spend(&spend_info, ms, provider, allow_mall))
+            .collect::<Vec<_>>();
+        min_witness_satisfaction(satisfactions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+    use crate::plan::Assets;
+
+    #[test]
+    fn parallel_derivation() {
+        let secp = Secp256k1::verification_only();
+        let desc = Descriptor::<DescriptorPublicKey>::from_str("tr([73c5da0a/86'/0'/0']xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ/0/*)").unwrap();
+
+        let spks = desc.par_script_pubkeys(&secp, 5..105).unwrap();
+        assert_eq!(spks.len(), 100);
+        for (i, spk) in (5..).zip(&spks) {
+            assert_eq!(*spk, desc.derived_descriptor(&secp, i).unwrap().script_pubkey());
+        }
+
+        let addresses = desc.par_addresses(&secp, 5..15, Network::Bitcoin).unwrap();
+        for (address, spk) in addresses.iter().zip(&spks) {
+            assert_eq!(address.script_pubkey(), *spk);
+        }
+
+        let index = desc.par_script_pubkey_index(&secp, 5..105).unwrap();
+        assert_eq!(index.len(), 100);
+        assert_eq!(index[&spks[42]], 47);
+
+        let single = Descriptor::<DescriptorPublicKey>::from_str(
+            "wpkh(02e96fe52ef0e22d2f131dd425ce1893073a3c6ad20e8cac36726393dfb4856a4c)",
+        )
+        .unwrap();
+        let index = single.par_script_pubkey_index(&secp, 3..10).unwrap();
+        let spk = single.derived_descriptor(&secp, 0).unwrap().script_pubkey();
+        assert_eq!(index.into_iter().collect::<Vec<_>>(), vec![(spk, 3)]);
+
+        let hardened = Descriptor::<DescriptorPublicKey>::from_str("wpkh(xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ/0h/*)").unwrap();
+        assert_eq!(hardened.par_script_pubkeys(&secp, 0..10), Err(ConversionError::HardenedChild));
+        assert!(matches!(
+            hardened.par_addresses(&secp, 0..10, Network::Bitcoin),
+            Err(AddressDerivationError::Conversion(ConversionError::HardenedChild))
+        ));
+        let bare = Descriptor::<DescriptorPublicKey>::from_str(
+            "pk(02e96fe52ef0e22d2f131dd425ce1893073a3c6ad20e8cac36726393dfb4856a4c)",
+        )
+        .unwrap();
+        assert!(matches!(
+            bare.par_addresses(&secp, 0..10, Network::Bitcoin),
+            Err(AddressDerivationError::Address(Error::BareDescriptorAddr))
+        ));
+    }
+
+    #[test]
+    fn parallel_planning() {
+        let keys = (1..=16u8)
+            .map(|i| {
+                let sk = bitcoin::secp256k1::SecretKey::from_slice(&[i; 32]).unwrap();
+                let secp = Secp256k1::signing_only();
+                bitcoin::PublicKey::new(sk.public_key(&secp)).to_string()
+            })
+            .collect::<Vec<_>>();
+        // A tree of 15 leaves, the first ones being the most expensive to satisfy
+        let leaves = (1..16)
+            .map(|i| format!("multi_a({},{})", 16 - i, keys[i..].join(",")))
+            .collect::<Vec<_>>();
+        let tree = leaves[1..]
+            .iter()
+            .fold(leaves[0].clone(), |tree, leaf| format!("{{{},{}}}", tree, leaf));
+        let desc =
+            Descriptor::<DefiniteDescriptorKey>::from_str(&format!("tr({},{})", keys[0], tree))
+                .unwrap();
+
+        let assets = keys[1..].iter().fold(Assets::new(), |assets, key| {
+            assets.add(DescriptorPublicKey::from_str(key).unwrap())
+        });
+        let plan = desc.clone().plan(&assets).unwrap();
+        let par_plan = desc.clone().par_plan(&assets).unwrap();
+        assert_eq!(par_plan.witness_template(), plan.witness_template());
+        assert_eq!(par_plan.satisfaction_weight(), plan.satisfaction_weight());
+        assert_eq!(
+            desc.clone()
+                .par_plan_mall(&assets)
+                .unwrap()
+                .witness_template(),
+            desc.clone().plan_mall(&assets).unwrap().witness_template()
+        );
+
+        // Key spends are preferred
+        let assets = assets.add(DescriptorPublicKey::from_str(&keys[0]).unwrap());
+        assert_eq!(
+            desc.clone().par_plan(&assets).unwrap().witness_template(),
+            desc.plan(&assets).unwrap().witness_template()
+        );
+        assert!(Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "tr({},{})",
+            keys[0], tree
+        ))
+        .unwrap()
+        .par_plan(&Assets::new())
+        .is_err());
+    }
+}
@@ -0,0 +1,161 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Redacted descriptors
+//!
+//! Displays descriptors for logs and support tickets, with less information
+//! about their keys than the descriptors themselves. Redacted descriptors
+//! never carry their checksum: it is replaced by the `#REDACTED` marker,
+//! which is not a valid checksum, so that they cannot be imported as if they
+//! were the original descriptor.
+
+use core::convert::Infallible;
+use core::fmt;
+
+use bitcoin::hashes::{hash160, ripemd160, sha256};
+
+use super::{Descriptor, DescriptorPublicKey, KeyStore, OriginStripper};
+use crate::prelude::*;
+use crate::{hash256, FnTranslator, MiniscriptKey, Translator};
+
+/// The marker replacing the checksum of redacted descriptors
+///
+/// Checksums are lowercase, so this is never a valid one.
+pub const REDACTED_MARKER: &str = "REDACTED";
+
+// How the keys of a redacted descriptor are displayed
+#[derive(Clone, Copy, Debug)]
+enum KeyRedaction<'a> {
+    Keep,
+    StripOrigins,
+    Fingerprints,
+    Aliases(&'a KeyStore),
+}
+
+impl KeyRedaction<'_> {
+    fn render(&self, pk: &DescriptorPublicKey) -> String {
+        match *self {
+            KeyRedaction::Keep => pk.to_string(),
+            KeyRedaction::StripOrigins => OriginStripper.pk(pk).map(|pk| pk.to_string()).unwrap(),
+            KeyRedaction::Fingerprints => format!("[{}]", pk.master_fingerprint()),
+            KeyRedaction::Aliases(store) => match store.alias_of(pk) {
+                Some(alias) => alias.to_owned(),
+                None => KeyRedaction::Fingerprints.render(pk),
+            },
+        }
+    }
+}
+
+// The keys of redacted descriptors, which are only displayed
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct RedactedKey(String);
+
+impl fmt::Display for RedactedKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { f.write_str(&self.0) }
+}
+
+impl MiniscriptKey for RedactedKey {
+    type Sha256 = sha256::Hash;
+    type Hash256 = hash256::Hash;
+    type Ripemd160 = ripemd160::Hash;
+    type Hash160 = hash160::Hash;
+}
+
+/// A descriptor displayed with redacted keys and without checksum, see
+/// [`Descriptor::redacted`]
+#[derive(Clone, Copy, Debug)]
+pub struct Redacted<'a> {
+    descriptor: &'a Descriptor<DescriptorPublicKey>,
+    keys: KeyRedaction<'a>,
+}
+
+impl<'a> Redacted<'a> {
+    /// Displays the keys without their origins.
+    pub fn strip_origins(self) -> Self { Redacted { keys: KeyRedaction::StripOrigins, ..self } }
+
+    /// Displays the keys as the fingerprints of their master keys, in
+    /// brackets.
+    pub fn fingerprints(self) -> Self { Redacted { keys: KeyRedaction::Fingerprints, ..self } }
+
+    /// Displays the keys as their aliases in `store`, or as fingerprints for
+    /// keys without alias.
+    pub fn aliases(self, store: &'a KeyStore) -> Self {
+        Redacted { keys: KeyRedaction::Aliases(store), ..self }
+    }
+}
+
+impl fmt::Display for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut translator = FnTranslator::new(|pk: &DescriptorPublicKey| {
+            Ok::<_, Infallible>(RedactedKey(self.keys.render(pk)))
+        });
+        let descriptor = self
+            .descriptor
+            .translate_pk(&mut translator)
+            .expect("redacted keys are valid in all contexts");
+        write!(f, "{:#}#{}", descriptor, REDACTED_MARKER)
+    }
+}
+
+impl Descriptor<DescriptorPublicKey> {
+    /// Displays the descriptor for logs and support tickets.
+    ///
+    /// By default the keys are displayed as they are, and only the checksum
+    /// is replaced by `#REDACTED`, so that the output is never a valid
+    /// descriptor.
+    pub fn redacted(&self) -> Redacted<'_> {
+        Redacted { descriptor: self, keys: KeyRedaction::Keep }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn redacted() {
+        let xpub = "xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL";
+        let key = "03a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd";
+        let desc = Descriptor::<DescriptorPublicKey>::from_str(&format!(
+            "wsh(and_v(v:multi(2,[d34db33f/48'/0'/0'/2']{}/0/*,{}),sha256({})))",
+            xpub,
+            key,
+            "a".repeat(64)
+        ))
+        .unwrap();
+        let key_fingerprint = DescriptorPublicKey::from_str(key)
+            .unwrap()
+            .master_fingerprint()
+            .to_string();
+
+        let redacted = desc.redacted().to_string();
+        assert_eq!(redacted, format!("{:#}#REDACTED", desc));
+        assert!(Descriptor::<DescriptorPublicKey>::from_str(&redacted).is_err());
+
+        assert_eq!(
+            desc.redacted().strip_origins().to_string(),
+            format!(
+                "wsh(and_v(v:multi(2,{}/0/*,{}),sha256({})))#REDACTED",
+                xpub,
+                key,
+                "a".repeat(64)
+            )
+        );
+        assert_eq!(
+            desc.redacted().fingerprints().to_string(),
+            format!(
+                "wsh(and_v(v:multi(2,[d34db33f],[{}]),sha256({})))#REDACTED",
+                key_fingerprint,
+                "a".repeat(64)
+            )
+        );
+
+        let mut store = KeyStore::new();
+        store.insert("alice", DescriptorPublicKey::from_str(key).unwrap());
+        assert_eq!(
+            desc.redacted().aliases(&store).to_string(),
+            format!("wsh(and_v(v:multi(2,[d34db33f],alice),sha256({})))#REDACTED", "a".repeat(64))
+        );
+    }
+}
@@ -0,0 +1,205 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Descriptor Satisfactions
+//!
+//! The witness and scriptSig spending a descriptor, together with what they
+//! spend through and what they cost.
+
+use bitcoin::{ScriptBuf, TxIn, Weight};
+
+use super::{Descriptor, ShInner, SpendPathKind};
+use crate::prelude::*;
+use crate::{Error, MiniscriptKey, Satisfier, ToPublicKey};
+
+/// A satisfaction of a descriptor, see [`Descriptor::satisfaction`]
+///
+/// Not to be confused with the Miniscript-level
+/// [`crate::miniscript::satisfy::Satisfaction`], which is a template of a
+/// witness for a single script.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Satisfaction {
+    /// The witness stack, empty for non-segwit descriptors.
+    pub witness: Vec<Vec<u8>>,
+    /// The scriptSig, empty for native segwit descriptors.
+    pub script_sig: ScriptBuf,
+    /// Which part of the descriptor the satisfaction spends through.
+    pub spend_path: SpendPathKind,
+    /// The exact weight this witness and scriptSig add to an unsatisfied
+    /// input, measured like [`Descriptor::max_weight_to_satisfy`] but not an
+    /// upper bound.
+    pub weight: Weight,
+}
+
+impl From<Satisfaction> for (Vec<Vec<u8>>, ScriptBuf) {
+    fn from(satisfaction: Satisfaction) -> Self { (satisfaction.witness, satisfaction.script_sig) }
+}
+
+/// The maximum weight to satisfy a descriptor, split between its scriptSig
+/// and its witness, see [`Descriptor::satisfaction_weight_breakdown`]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SatisfactionWeight {
+    /// The weight of the scriptSig, including its length prefix.
+    pub script_sig: Weight,
+    /// The weight of the witness, including its number of items.
+    pub witness: Weight,
+}
+
+impl SatisfactionWeight {
+    /// The total weight, that of [`Descriptor::max_weight_to_satisfy`].
+    pub fn total(&self) -> Weight { self.script_sig + self.witness }
+}
+
+impl<Pk: MiniscriptKey> Descriptor<Pk> {
+    /// Computes an upper bound on the weight to satisfy the descriptor, like
+    /// [`Descriptor::max_weight_to_satisfy`], split between the scriptSig and
+    /// the witness.
+    ///
+    /// # Errors
+    /// When the descriptor is impossible to safisfy (ex: sh(OP_FALSE)).
+    pub fn satisfaction_weight_breakdown(&self) -> Result<SatisfactionWeight, Error> {
+        let total = self.max_weight_to_satisfy()?;
+        let witness = match *self {
+            Descriptor::Bare(_) | Descriptor::Pkh(_) => Weight::ZERO,
+            Descriptor::Wpkh(_) | Descriptor::Wsh(_) | Descriptor::Tr(_) => total,
+            Descriptor::Sh(ref sh) => match *sh.as_inner() {
+                ShInner::Wsh(ref wsh) => wsh.max_weight_to_satisfy()?,
+                ShInner::Wpkh(ref wpkh) => wpkh.max_weight_to_satisfy(),
+                ShInner::SortedMulti(_) | ShInner::Ms(_) => Weight::ZERO,
+            },
+        };
+        Ok(SatisfactionWeight { script_sig: total - witness, witness })
+    }
+}
+
+impl<Pk: ToPublicKey> Descriptor<Pk> {
+    /// Returns the non-malleable satisfaction of the descriptor using the
+    /// satisfier, like [`Descriptor::get_satisfaction`], with the spending
+    /// path it uses and its weight.
+    pub fn satisfaction<S: Satisfier<Pk>>(&self, satisfier: S) -> Result<Satisfaction, Error> {
+        let (witness, script_sig) = self.get_satisfaction(satisfier)?;
+        Ok(self.describe_satisfaction(witness, script_sig))
+    }
+
+    /// Returns the possibly malleable satisfaction of the descriptor using
+    /// the satisfier, like [`Descriptor::get_satisfaction_mall`], with the
+    /// spending path it uses and its weight.
+    pub fn satisfaction_mall<S: Satisfier<Pk>>(&self, satisfier: S) -> Result<Satisfaction, Error> {
+        let (witness, script_sig) = self.get_satisfaction_mall(satisfier)?;
+        Ok(self.describe_satisfaction(witness, script_sig))
+    }
+
+    // Finds the spending path and the weight of a satisfaction
+    fn describe_satisfaction(&self, witness: Vec<Vec<u8>>, script_sig: ScriptBuf) -> Satisfaction {
+        let spend_path = match *self {
+            // Script spends end with the script and the control block, key
+            // spends only have a signature
+            Descriptor::Tr(ref tr) if witness.len() >= 2 => {
+                let control_block = &witness[witness.len() - 1];
+                let script = &witness[witness.len() - 2];
+                let depth = (control_block.len().saturating_sub(33) / 32) as u8;
+                tr.iter_scripts()
+                    .enumerate()
+                    .find(|(_, (d, ms))| *d == depth && ms.encode().as_bytes() == &script[..])
+                    .map(|(leaf, _)| SpendPathKind::TapLeaf { leaf, depth })
+                    .expect("satisfactions spend leaves of the descriptor")
+            }
+            Descriptor::Tr(_) => SpendPathKind::TapKeySpend,
+            _ => SpendPathKind::Script,
+        };
+        let txin = TxIn {
+            script_sig: script_sig.clone(),
+            witness: bitcoin::Witness::from_slice(&witness),
+            ..TxIn::default()
+        };
+        let weight = if self.desc_type().segwit_version().is_some() {
+            txin.segwit_weight() - TxIn::default().segwit_weight()
+        } else {
+            txin.legacy_weight() - TxIn::default().legacy_weight()
+        };
+        Satisfaction { witness, script_sig, spend_path, weight }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use bitcoin::secp256k1::{Secp256k1, SecretKey, XOnlyPublicKey};
+    use bitcoin::{secp256k1, taproot, TapLeafHash};
+
+    use super::*;
+
+    #[test]
+    fn satisfaction() {
+        let secp = Secp256k1::new();
+        let keys: Vec<XOnlyPublicKey> = (1..=2u8)
+            .map(|i| {
+                let sk = SecretKey::from_slice(&[i; 32]).unwrap();
+                sk.x_only_public_key(&secp).0
+            })
+            .collect();
+        let desc =
+            Descriptor::<XOnlyPublicKey>::from_str(&format!("tr({},pk({}))", keys[0], keys[1]))
+                .unwrap();
+        let sig = taproot::Signature {
+            signature: secp256k1::schnorr::Signature::from_slice(&[1; 64]).unwrap(),
+            sighash_type: bitcoin::TapSighashType::Default,
+        };
+        let leaf_hash = match desc {
+            Descriptor::Tr(ref tr) => TapLeafHash::from_script(
+                &tr.iter_scripts().next().unwrap().1.encode(),
+                taproot::LeafVersion::TapScript,
+            ),
+            _ => unreachable!(),
+        };
+        let mut satisfier = BTreeMap::new();
+        satisfier.insert((keys[1], leaf_hash), sig);
+
+        let satisfaction = desc.satisfaction(&satisfier).unwrap();
+        assert_eq!(satisfaction.spend_path, SpendPathKind::TapLeaf { leaf: 0, depth: 0 });
+        assert!(satisfaction.script_sig.is_empty());
+        // Signature (1 + 64), script (1 + 34) and control block (1 + 33)
+        assert_eq!(satisfaction.weight, Weight::from_wu(134));
+        assert_eq!(
+            <(Vec<Vec<u8>>, ScriptBuf)>::from(satisfaction),
+            desc.get_satisfaction(&satisfier).unwrap()
+        );
+
+        let sk = SecretKey::from_slice(&[1; 32]).unwrap();
+        let pk = bitcoin::PublicKey::new(sk.public_key(&secp));
+        let desc = Descriptor::new_pkh(pk).unwrap();
+        assert!(desc.satisfaction(()).is_err());
+        let sig = secp.sign_ecdsa(&secp256k1::Message::from_digest([1; 32]), &sk);
+        let mut satisfier = BTreeMap::new();
+        satisfier.insert(pk, bitcoin::ecdsa::Signature::sighash_all(sig));
+        let satisfaction = desc.satisfaction(&satisfier).unwrap();
+        assert_eq!(satisfaction.spend_path, SpendPathKind::Script);
+        assert!(satisfaction.witness.is_empty());
+        assert_eq!(
+            satisfaction.weight.to_wu(),
+            4 * satisfaction.script_sig.len() as u64,
+            "the scriptSig length fits in the byte of an empty one"
+        );
+    }
+
+    #[test]
+    fn satisfaction_weight_breakdown() {
+        let pk = "02e96fe52ef0e22d2f131dd425ce1893073a3c6ad20e8cac36726393dfb4856a4c";
+        let check = |desc: &str, script_sig: u64, witness: u64| {
+            let desc = Descriptor::<bitcoin::PublicKey>::from_str(desc).unwrap();
+            let weight = desc.satisfaction_weight_breakdown().unwrap();
+            assert_eq!(weight.script_sig, Weight::from_wu(script_sig), "{}", desc);
+            assert_eq!(weight.witness, Weight::from_wu(witness), "{}", desc);
+            assert_eq!(weight.total(), desc.max_weight_to_satisfy().unwrap());
+        };
+        // Signature (1 + 72) and key (1 + 33), the lengths of the scriptSig
+        // and of the witness stack fit in the byte of empty ones
+        check(&format!("pkh({})", pk), 4 * (73 + 34), 0);
+        check(&format!("wpkh({})", pk), 0, 73 + 34);
+        // Plus the push of the witness program (1 + 22)
+        check(&format!("sh(wpkh({}))", pk), 4 * 23, 73 + 34);
+
+        let desc = Descriptor::<bitcoin::PublicKey>::from_str("sh(0)").unwrap();
+        assert!(desc.satisfaction_weight_breakdown().is_err());
+    }
+}
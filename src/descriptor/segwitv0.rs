@@ -8,49 +8,53 @@
 use core::convert::TryFrom;
 use core::fmt;
 
-use bitcoin::{Address, Network, ScriptBuf, Weight};
+use bitcoin::hashes::hash160;
+use bitcoin::{opcodes, script, Address, Network, ScriptBuf, Weight};
 
 use super::SortedMultiVec;
 use crate::descriptor::{write_descriptor, DefiniteDescriptorKey};
 use crate::expression::{self, FromTree};
 use crate::miniscript::context::{ScriptContext, ScriptContextError};
+use crate::miniscript::extension::{Extension, NoExt};
 use crate::miniscript::satisfy::{Placeholder, Satisfaction, Witness};
-use crate::plan::AssetProvider;
+use crate::plan::{AssetProvider, MissingAsset, MissingAssets};
 use crate::policy::{semantic, Liftable};
 use crate::prelude::*;
-use crate::util::varint_len;
+use crate::util::{take_script_bytes, varint_len, witness_script_to_p2wsh};
 use crate::{
-    Error, ForEachKey, FromStrKey, Miniscript, MiniscriptKey, Satisfier, Segwitv0, ToPublicKey,
-    TranslateErr, Translator,
+    Error, ForEachKey, FromStrKey, Miniscript, MiniscriptKey, Satisfier, SatisfyError, Segwitv0,
+    ToPublicKey, TranslateErr, Translator,
 };
 /// A Segwitv0 wsh descriptor
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
-pub struct Wsh<Pk: MiniscriptKey> {
+pub struct Wsh<Pk: MiniscriptKey, Ext: Extension = NoExt> {
     /// underlying miniscript
-    inner: WshInner<Pk>,
+    inner: WshInner<Pk, Ext>,
 }
 
 impl<Pk: MiniscriptKey> Wsh<Pk> {
+    /// Create a new sortedmulti wsh descriptor
+    pub fn new_sortedmulti(k: usize, pks: Vec<Pk>) -> Result<Self, Error> {
+        // The context checks will be carried out inside new function for
+        // sortedMultiVec
+        Ok(Self { inner: WshInner::SortedMulti(SortedMultiVec::new(k, pks)?) })
+    }
+}
+
+impl<Pk: MiniscriptKey, Ext: Extension> Wsh<Pk, Ext> {
     /// Get the Inner
-    pub fn into_inner(self) -> WshInner<Pk> { self.inner }
+    pub fn into_inner(self) -> WshInner<Pk, Ext> { self.inner }
 
     /// Get a reference to inner
-    pub fn as_inner(&self) -> &WshInner<Pk> { &self.inner }
+    pub fn as_inner(&self) -> &WshInner<Pk, Ext> { &self.inner }
 
     /// Create a new wsh descriptor
-    pub fn new(ms: Miniscript<Pk, Segwitv0>) -> Result<Self, Error> {
+    pub fn new(ms: Miniscript<Pk, Segwitv0, Ext>) -> Result<Self, Error> {
         // do the top-level checks
         Segwitv0::top_level_checks(&ms)?;
         Ok(Self { inner: WshInner::Ms(ms) })
     }
 
-    /// Create a new sortedmulti wsh descriptor
-    pub fn new_sortedmulti(k: usize, pks: Vec<Pk>) -> Result<Self, Error> {
-        // The context checks will be carried out inside new function for
-        // sortedMultiVec
-        Ok(Self { inner: WshInner::SortedMulti(SortedMultiVec::new(k, pks)?) })
-    }
-
     /// Get the descriptor without the checksum
     #[deprecated(since = "8.0.0", note = "use format!(\"{:#}\") instead")]
     pub fn to_string_no_checksum(&self) -> String { format!("{:#}", self) }
@@ -129,7 +133,10 @@ impl<Pk: MiniscriptKey> Wsh<Pk> {
     }
 
     /// Converts the keys in a script from one type to another.
-    pub fn translate_pk<T>(&self, t: &mut T) -> Result<Wsh<T::TargetPk>, TranslateErr<T::Error>>
+    pub fn translate_pk<T>(
+        &self,
+        t: &mut T,
+    ) -> Result<Wsh<T::TargetPk, Ext>, TranslateErr<T::Error>>
     where
         T: Translator<Pk>,
     {
@@ -139,12 +146,41 @@ impl<Pk: MiniscriptKey> Wsh<Pk> {
         };
         Ok(Wsh { inner })
     }
+
+    /// Substitutes all raw public key hashes in the script with the public keys
+    /// as provided by map, failing with the set of unresolved hashes if any are missing.
+    pub fn resolve_raw_pkh(
+        &self,
+        pk_map: &BTreeMap<hash160::Hash, Pk>,
+    ) -> Result<Wsh<Pk, Ext>, BTreeSet<hash160::Hash>> {
+        let inner = match self.inner {
+            WshInner::SortedMulti(ref smv) => WshInner::SortedMulti(smv.clone()),
+            WshInner::Ms(ref ms) => WshInner::Ms(ms.resolve_raw_pkh(pk_map)?),
+        };
+        Ok(Wsh { inner })
+    }
 }
 
-impl<Pk: MiniscriptKey + ToPublicKey> Wsh<Pk> {
+impl<Pk: MiniscriptKey + ToPublicKey, Ext: Extension> Wsh<Pk, Ext> {
     /// Obtains the corresponding script pubkey for this descriptor.
     pub fn script_pubkey(&self) -> ScriptBuf { self.inner_script().to_p2wsh() }
 
+    /// Computes the script pubkey like [`Wsh::script_pubkey`], writing it
+    /// into `script` to reuse its allocation.
+    pub fn script_pubkey_into(&self, script: &mut ScriptBuf) {
+        let mut bytes = take_script_bytes(script);
+        self.encode_inner_to(&mut bytes);
+        *script = witness_script_to_p2wsh(bytes);
+    }
+
+    // Appends the witness script to `buf`
+    pub(super) fn encode_inner_to(&self, buf: &mut Vec<u8>) {
+        match self.inner {
+            WshInner::SortedMulti(ref smv) => smv.encode_to(buf),
+            WshInner::Ms(ref ms) => ms.encode_to(buf),
+        }
+    }
+
     /// Obtains the corresponding script pubkey for this descriptor.
     pub fn address(&self, network: Network) -> Address {
         match self.inner {
@@ -226,15 +262,26 @@ impl Wsh<DefiniteDescriptorKey> {
             WshInner::Ms(ms) => ms.build_template_mall(provider),
         }
     }
+
+    /// Returns the assets missing from `provider` for each candidate spending path
+    pub fn missing_assets<P>(&self, provider: &P) -> MissingAssets<DefiniteDescriptorKey>
+    where
+        P: AssetProvider<DefiniteDescriptorKey>,
+    {
+        match &self.inner {
+            WshInner::SortedMulti(sm) => sm.missing_assets(provider),
+            WshInner::Ms(ms) => ms.missing_assets(provider),
+        }
+    }
 }
 
 /// Wsh Inner
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
-pub enum WshInner<Pk: MiniscriptKey> {
+pub enum WshInner<Pk: MiniscriptKey, Ext: Extension = NoExt> {
     /// Sorted Multi
     SortedMulti(SortedMultiVec<Pk, Segwitv0>),
     /// Wsh Miniscript
-    Ms(Miniscript<Pk, Segwitv0>),
+    Ms(Miniscript<Pk, Segwitv0, Ext>),
 }
 
 impl<Pk: MiniscriptKey> Liftable<Pk> for Wsh<Pk> {
@@ -246,7 +293,7 @@ impl<Pk: MiniscriptKey> Liftable<Pk> for Wsh<Pk> {
     }
 }
 
-impl<Pk: FromStrKey> crate::expression::FromTree for Wsh<Pk> {
+impl<Pk: FromStrKey, Ext: Extension> crate::expression::FromTree for Wsh<Pk, Ext> {
     fn from_tree(top: expression::TreeIterItem) -> Result<Self, Error> {
         let top = top
             .verify_toplevel("wsh", 1..=1)
@@ -262,7 +309,7 @@ impl<Pk: FromStrKey> crate::expression::FromTree for Wsh<Pk> {
     }
 }
 
-impl<Pk: MiniscriptKey> fmt::Debug for Wsh<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> fmt::Debug for Wsh<Pk, Ext> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.inner {
             WshInner::SortedMulti(ref smv) => write!(f, "wsh({:?})", smv),
@@ -271,7 +318,7 @@ impl<Pk: MiniscriptKey> fmt::Debug for Wsh<Pk> {
     }
 }
 
-impl<Pk: MiniscriptKey> fmt::Display for Wsh<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> fmt::Display for Wsh<Pk, Ext> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.inner {
             WshInner::SortedMulti(ref smv) => write_descriptor!(f, "wsh({})", smv),
@@ -280,15 +327,15 @@ impl<Pk: MiniscriptKey> fmt::Display for Wsh<Pk> {
     }
 }
 
-impl<Pk: FromStrKey> core::str::FromStr for Wsh<Pk> {
+impl<Pk: FromStrKey, Ext: Extension> core::str::FromStr for Wsh<Pk, Ext> {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let top = expression::Tree::from_str(s)?;
-        Wsh::<Pk>::from_tree(top.root())
+        Wsh::<Pk, Ext>::from_tree(top.root())
     }
 }
 
-impl<Pk: MiniscriptKey> ForEachKey<Pk> for Wsh<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> ForEachKey<Pk> for Wsh<Pk, Ext> {
     fn for_each_key<'a, F: FnMut(&'a Pk) -> bool>(&'a self, pred: F) -> bool {
         match self.inner {
             WshInner::SortedMulti(ref smv) => smv.for_each_key(pred),
@@ -382,6 +429,18 @@ impl<Pk: MiniscriptKey + ToPublicKey> Wpkh<Pk> {
         addr.script_pubkey()
     }
 
+    /// Computes the script pubkey like [`Wpkh::script_pubkey`], writing it
+    /// into `script` to reuse its allocation.
+    pub fn script_pubkey_into(&self, script: &mut ScriptBuf) {
+        let pk = self.pk.to_public_key();
+        let compressed = bitcoin::key::CompressedPublicKey::try_from(pk)
+            .expect("wpkh descriptors have compressed keys");
+        *script = script::Builder::from(take_script_bytes(script))
+            .push_opcode(opcodes::all::OP_PUSHBYTES_0)
+            .push_slice(compressed.wpubkey_hash())
+            .into_script();
+    }
+
     /// Obtains the corresponding script pubkey for this descriptor.
     pub fn address(&self, network: Network) -> Address {
         let pk = self.pk.to_public_key();
@@ -417,7 +476,7 @@ impl<Pk: MiniscriptKey + ToPublicKey> Wpkh<Pk> {
             let witness = vec![sig_vec, self.pk.to_public_key().to_bytes()];
             Ok((witness, script_sig))
         } else {
-            Err(Error::MissingSig(self.pk.to_public_key()))
+            Err(Error::Satisfy(SatisfyError::MissingSig(self.pk.to_public_key())))
         }
     }
 
@@ -464,6 +523,15 @@ impl Wpkh<DefiniteDescriptorKey> {
     {
         self.plan_satisfaction(provider)
     }
+
+    /// Returns the assets missing from `provider` for each candidate spending path
+    pub fn missing_assets<P>(&self, provider: &P) -> MissingAssets<DefiniteDescriptorKey>
+    where
+        P: AssetProvider<DefiniteDescriptorKey>,
+    {
+        let available = provider.provider_lookup_ecdsa_sig(&self.pk);
+        MissingAssets::requires(MissingAsset::Signature(self.pk.clone()), available)
+    }
 }
 
 impl<Pk: MiniscriptKey> fmt::Debug for Wpkh<Pk> {
@@ -10,6 +10,7 @@
 use core::convert::TryFrom;
 use core::fmt;
 
+use bitcoin::hashes::hash160;
 use bitcoin::script::PushBytes;
 use bitcoin::{script, Address, Network, ScriptBuf, Weight};
 
@@ -17,34 +18,38 @@ use super::{SortedMultiVec, Wpkh, Wsh};
 use crate::descriptor::{write_descriptor, DefiniteDescriptorKey};
 use crate::expression::{self, FromTree};
 use crate::miniscript::context::ScriptContext;
+use crate::miniscript::extension::{Extension, NoExt};
 use crate::miniscript::satisfy::{Placeholder, Satisfaction};
-use crate::plan::AssetProvider;
+use crate::plan::{AssetProvider, MissingAssets};
 use crate::policy::{semantic, Liftable};
 use crate::prelude::*;
-use crate::util::{varint_len, witness_to_scriptsig};
+use crate::util::{
+    redeem_script_to_p2sh, take_script_bytes, varint_len, witness_script_to_p2wsh,
+    witness_to_scriptsig,
+};
 use crate::{
     push_opcode_size, Error, ForEachKey, FromStrKey, Legacy, Miniscript, MiniscriptKey, Satisfier,
-    Segwitv0, ToPublicKey, TranslateErr, Translator,
+    SatisfyError, Segwitv0, ToPublicKey, TranslateErr, Translator,
 };
 
 /// A Legacy p2sh Descriptor
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
-pub struct Sh<Pk: MiniscriptKey> {
+pub struct Sh<Pk: MiniscriptKey, Ext: Extension = NoExt> {
     /// underlying miniscript
-    inner: ShInner<Pk>,
+    inner: ShInner<Pk, Ext>,
 }
 
 /// Sh Inner
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
-pub enum ShInner<Pk: MiniscriptKey> {
+pub enum ShInner<Pk: MiniscriptKey, Ext: Extension = NoExt> {
     /// Nested Wsh
-    Wsh(Wsh<Pk>),
+    Wsh(Wsh<Pk, Ext>),
     /// Nested Wpkh
     Wpkh(Wpkh<Pk>),
     /// Inner Sorted Multi
     SortedMulti(SortedMultiVec<Pk, Legacy>),
     /// p2sh miniscript
-    Ms(Miniscript<Pk, Legacy>),
+    Ms(Miniscript<Pk, Legacy, Ext>),
 }
 
 impl<Pk: MiniscriptKey> Liftable<Pk> for Sh<Pk> {
@@ -58,7 +63,7 @@ impl<Pk: MiniscriptKey> Liftable<Pk> for Sh<Pk> {
     }
 }
 
-impl<Pk: MiniscriptKey> fmt::Debug for Sh<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> fmt::Debug for Sh<Pk, Ext> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.inner {
             ShInner::Wsh(ref wsh_inner) => write!(f, "sh({:?})", wsh_inner),
@@ -69,7 +74,7 @@ impl<Pk: MiniscriptKey> fmt::Debug for Sh<Pk> {
     }
 }
 
-impl<Pk: MiniscriptKey> fmt::Display for Sh<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> fmt::Display for Sh<Pk, Ext> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.inner {
             ShInner::Wsh(ref wsh) => write_descriptor!(f, "sh({:#})", wsh),
@@ -80,7 +85,7 @@ impl<Pk: MiniscriptKey> fmt::Display for Sh<Pk> {
     }
 }
 
-impl<Pk: FromStrKey> crate::expression::FromTree for Sh<Pk> {
+impl<Pk: FromStrKey, Ext: Extension> crate::expression::FromTree for Sh<Pk, Ext> {
     fn from_tree(top: expression::TreeIterItem) -> Result<Self, Error> {
         let top = top
             .verify_toplevel("sh", 1..=1)
@@ -101,7 +106,7 @@ impl<Pk: FromStrKey> crate::expression::FromTree for Sh<Pk> {
     }
 }
 
-impl<Pk: FromStrKey> core::str::FromStr for Sh<Pk> {
+impl<Pk: FromStrKey, Ext: Extension> core::str::FromStr for Sh<Pk, Ext> {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let top = expression::Tree::from_str(s)?;
@@ -110,34 +115,52 @@ impl<Pk: FromStrKey> core::str::FromStr for Sh<Pk> {
 }
 
 impl<Pk: MiniscriptKey> Sh<Pk> {
+    /// Create a new p2sh sortedmulti descriptor with threshold `k`
+    /// and Vec of `pks`.
+    pub fn new_sortedmulti(k: usize, pks: Vec<Pk>) -> Result<Self, Error> {
+        // The context checks will be carried out inside new function for
+        // sortedMultiVec
+        Ok(Self { inner: ShInner::SortedMulti(SortedMultiVec::new(k, pks)?) })
+    }
+
+    /// Create a new p2sh wrapped wsh sortedmulti descriptor from threshold
+    /// `k` and Vec of `pks`
+    pub fn new_wsh_sortedmulti(k: usize, pks: Vec<Pk>) -> Result<Self, Error> {
+        // The context checks will be carried out inside new function for
+        // sortedMultiVec
+        Ok(Self { inner: ShInner::Wsh(Wsh::new_sortedmulti(k, pks)?) })
+    }
+
+    /// Create a new p2sh wrapped wpkh from `Pk`
+    pub fn new_wpkh(pk: Pk) -> Result<Self, Error> {
+        Ok(Self { inner: ShInner::Wpkh(Wpkh::new(pk)?) })
+    }
+
+    /// Create a new p2sh wrapper for the given wpkh descriptor
+    pub fn new_with_wpkh(wpkh: Wpkh<Pk>) -> Self { Self { inner: ShInner::Wpkh(wpkh) } }
+}
+
+impl<Pk: MiniscriptKey, Ext: Extension> Sh<Pk, Ext> {
     /// Get the Inner
-    pub fn into_inner(self) -> ShInner<Pk> { self.inner }
+    pub fn into_inner(self) -> ShInner<Pk, Ext> { self.inner }
 
     /// Get a reference to inner
-    pub fn as_inner(&self) -> &ShInner<Pk> { &self.inner }
+    pub fn as_inner(&self) -> &ShInner<Pk, Ext> { &self.inner }
 
     /// Create a new p2sh descriptor with the raw miniscript
-    pub fn new(ms: Miniscript<Pk, Legacy>) -> Result<Self, Error> {
+    pub fn new(ms: Miniscript<Pk, Legacy, Ext>) -> Result<Self, Error> {
         // do the top-level checks
         Legacy::top_level_checks(&ms)?;
         Ok(Self { inner: ShInner::Ms(ms) })
     }
 
-    /// Create a new p2sh sortedmulti descriptor with threshold `k`
-    /// and Vec of `pks`.
-    pub fn new_sortedmulti(k: usize, pks: Vec<Pk>) -> Result<Self, Error> {
-        // The context checks will be carried out inside new function for
-        // sortedMultiVec
-        Ok(Self { inner: ShInner::SortedMulti(SortedMultiVec::new(k, pks)?) })
-    }
-
     /// Create a new p2sh wrapped wsh descriptor with the raw miniscript
-    pub fn new_wsh(ms: Miniscript<Pk, Segwitv0>) -> Result<Self, Error> {
+    pub fn new_wsh(ms: Miniscript<Pk, Segwitv0, Ext>) -> Result<Self, Error> {
         Ok(Self { inner: ShInner::Wsh(Wsh::new(ms)?) })
     }
 
     /// Create a new p2sh wrapper for the given wsh descriptor
-    pub fn new_with_wsh(wsh: Wsh<Pk>) -> Self { Self { inner: ShInner::Wsh(wsh) } }
+    pub fn new_with_wsh(wsh: Wsh<Pk, Ext>) -> Self { Self { inner: ShInner::Wsh(wsh) } }
 
     /// Checks whether the descriptor is safe.
     pub fn sanity_check(&self) -> Result<(), Error> {
@@ -150,22 +173,6 @@ impl<Pk: MiniscriptKey> Sh<Pk> {
         Ok(())
     }
 
-    /// Create a new p2sh wrapped wsh sortedmulti descriptor from threshold
-    /// `k` and Vec of `pks`
-    pub fn new_wsh_sortedmulti(k: usize, pks: Vec<Pk>) -> Result<Self, Error> {
-        // The context checks will be carried out inside new function for
-        // sortedMultiVec
-        Ok(Self { inner: ShInner::Wsh(Wsh::new_sortedmulti(k, pks)?) })
-    }
-
-    /// Create a new p2sh wrapped wpkh from `Pk`
-    pub fn new_wpkh(pk: Pk) -> Result<Self, Error> {
-        Ok(Self { inner: ShInner::Wpkh(Wpkh::new(pk)?) })
-    }
-
-    /// Create a new p2sh wrapper for the given wpkh descriptor
-    pub fn new_with_wpkh(wpkh: Wpkh<Pk>) -> Self { Self { inner: ShInner::Wpkh(wpkh) } }
-
     /// Computes an upper bound on the difference between a non-satisfied
     /// `TxIn`'s `segwit_weight` and a satisfied `TxIn`'s `segwit_weight`
     ///
@@ -215,7 +222,7 @@ impl<Pk: MiniscriptKey> Sh<Pk> {
         let wu = Weight::from_vb((scriptsig_varint_diff + scriptsig_size) as u64);
         match wu {
             Some(w) => Ok(w + witness_size),
-            None => Err(Error::CouldNotSatisfy),
+            None => Err(Error::Satisfy(SatisfyError::CouldNotSatisfy)),
         }
     }
 
@@ -255,7 +262,7 @@ impl<Pk: MiniscriptKey> Sh<Pk> {
     }
 
     /// Converts the keys in a script from one type to another.
-    pub fn translate_pk<T>(&self, t: &mut T) -> Result<Sh<T::TargetPk>, TranslateErr<T::Error>>
+    pub fn translate_pk<T>(&self, t: &mut T) -> Result<Sh<T::TargetPk, Ext>, TranslateErr<T::Error>>
     where
         T: Translator<Pk>,
     {
@@ -267,9 +274,24 @@ impl<Pk: MiniscriptKey> Sh<Pk> {
         };
         Ok(Sh { inner })
     }
+
+    /// Substitutes all raw public key hashes in the script with the public keys
+    /// as provided by map, failing with the set of unresolved hashes if any are missing.
+    pub fn resolve_raw_pkh(
+        &self,
+        pk_map: &BTreeMap<hash160::Hash, Pk>,
+    ) -> Result<Sh<Pk, Ext>, BTreeSet<hash160::Hash>> {
+        let inner = match self.inner {
+            ShInner::Wsh(ref wsh) => ShInner::Wsh(wsh.resolve_raw_pkh(pk_map)?),
+            ShInner::Wpkh(ref wpkh) => ShInner::Wpkh(wpkh.clone()),
+            ShInner::SortedMulti(ref smv) => ShInner::SortedMulti(smv.clone()),
+            ShInner::Ms(ref ms) => ShInner::Ms(ms.resolve_raw_pkh(pk_map)?),
+        };
+        Ok(Sh { inner })
+    }
 }
 
-impl<Pk: MiniscriptKey + ToPublicKey> Sh<Pk> {
+impl<Pk: MiniscriptKey + ToPublicKey, Ext: Extension> Sh<Pk, Ext> {
     /// Obtains the corresponding script pubkey for this descriptor.
     pub fn script_pubkey(&self) -> ScriptBuf {
         match self.inner {
@@ -280,6 +302,26 @@ impl<Pk: MiniscriptKey + ToPublicKey> Sh<Pk> {
         }
     }
 
+    /// Computes the script pubkey like [`Sh::script_pubkey`], writing it
+    /// into `script` to reuse its allocation.
+    pub fn script_pubkey_into(&self, script: &mut ScriptBuf) {
+        let mut bytes = take_script_bytes(script);
+        match self.inner {
+            ShInner::Wsh(ref wsh) => {
+                wsh.encode_inner_to(&mut bytes);
+                bytes = witness_script_to_p2wsh(bytes).into_bytes();
+            }
+            ShInner::Wpkh(ref wpkh) => {
+                let mut spk = ScriptBuf::from(bytes);
+                wpkh.script_pubkey_into(&mut spk);
+                bytes = spk.into_bytes();
+            }
+            ShInner::SortedMulti(ref smv) => smv.encode_to(&mut bytes),
+            ShInner::Ms(ref ms) => ms.encode_to(&mut bytes),
+        }
+        *script = redeem_script_to_p2sh(bytes);
+    }
+
     /// Obtains the corresponding address for this descriptor.
     pub fn address(&self, network: Network) -> Address {
         let addr = self.address_fallible(network);
@@ -440,9 +482,22 @@ impl Sh<DefiniteDescriptorKey> {
             _ => self.plan_satisfaction(provider),
         }
     }
+
+    /// Returns the assets missing from `provider` for each candidate spending path
+    pub fn missing_assets<P>(&self, provider: &P) -> MissingAssets<DefiniteDescriptorKey>
+    where
+        P: AssetProvider<DefiniteDescriptorKey>,
+    {
+        match &self.inner {
+            ShInner::Wsh(ref wsh) => wsh.missing_assets(provider),
+            ShInner::Wpkh(ref wpkh) => wpkh.missing_assets(provider),
+            ShInner::SortedMulti(ref smv) => smv.missing_assets(provider),
+            ShInner::Ms(ref ms) => ms.missing_assets(provider),
+        }
+    }
 }
 
-impl<Pk: MiniscriptKey> ForEachKey<Pk> for Sh<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> ForEachKey<Pk> for Sh<Pk, Ext> {
     fn for_each_key<'a, F: FnMut(&'a Pk) -> bool>(&'a self, pred: F) -> bool {
         match self.inner {
             ShInner::Wsh(ref wsh) => wsh.for_each_key(pred),
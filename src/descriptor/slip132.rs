@@ -0,0 +1,182 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! SLIP-132 extended keys
+//!
+//! Many wallets serialize extended keys with version bytes other than the
+//! BIP 32 ones (`ypub`, `zpub`, ...) to tell which kind of script they are
+//! used for. See <https://github.com/satoshilabs/slips/blob/master/slip-0132.md>.
+//! These are translated to and from plain BIP 32 keys, keeping the script
+//! type as a hint.
+
+use bitcoin::{base58, bip32, NetworkKind};
+
+use super::DescriptorType;
+use crate::prelude::*;
+
+/// Version bytes of a SLIP-132 extended key
+struct Version {
+    public: [u8; 4],
+    private: [u8; 4],
+    network: NetworkKind,
+    script_type: DescriptorType,
+}
+
+/// Versions other than the plain BIP 32 `xpub`/`tpub` ones, which are not
+/// tied to a script type in practice
+const VERSIONS: [Version; 8] = [
+    // ypub, yprv
+    Version {
+        public: [0x04, 0x9d, 0x7c, 0xb2],
+        private: [0x04, 0x9d, 0x78, 0x78],
+        network: NetworkKind::Main,
+        script_type: DescriptorType::ShWpkh,
+    },
+    // zpub, zprv
+    Version {
+        public: [0x04, 0xb2, 0x47, 0x46],
+        private: [0x04, 0xb2, 0x43, 0x0c],
+        network: NetworkKind::Main,
+        script_type: DescriptorType::Wpkh,
+    },
+    // Ypub, Yprv
+    Version {
+        public: [0x02, 0x95, 0xb4, 0x3f],
+        private: [0x02, 0x95, 0xb0, 0x05],
+        network: NetworkKind::Main,
+        script_type: DescriptorType::ShWsh,
+    },
+    // Zpub, Zprv
+    Version {
+        public: [0x02, 0xaa, 0x7e, 0xd3],
+        private: [0x02, 0xaa, 0x7a, 0x99],
+        network: NetworkKind::Main,
+        script_type: DescriptorType::Wsh,
+    },
+    // upub, uprv
+    Version {
+        public: [0x04, 0x4a, 0x52, 0x62],
+        private: [0x04, 0x4a, 0x4e, 0x28],
+        network: NetworkKind::Test,
+        script_type: DescriptorType::ShWpkh,
+    },
+    // vpub, vprv
+    Version {
+        public: [0x04, 0x5f, 0x1c, 0xf6],
+        private: [0x04, 0x5f, 0x18, 0xbc],
+        network: NetworkKind::Test,
+        script_type: DescriptorType::Wpkh,
+    },
+    // Upub, Uprv
+    Version {
+        public: [0x02, 0x42, 0x89, 0xef],
+        private: [0x02, 0x42, 0x85, 0xb5],
+        network: NetworkKind::Test,
+        script_type: DescriptorType::ShWsh,
+    },
+    // Vpub, Vprv
+    Version {
+        public: [0x02, 0x57, 0x54, 0x83],
+        private: [0x02, 0x57, 0x50, 0x48],
+        network: NetworkKind::Test,
+        script_type: DescriptorType::Wsh,
+    },
+];
+
+// Replaces the SLIP-132 version bytes of a serialized extended key by the
+// BIP 32 ones, returning the script type they stand for
+fn to_bip32(s: &str, private: bool) -> Option<(Vec<u8>, DescriptorType)> {
+    let mut data = base58::decode_check(s).ok()?;
+    if data.len() != 78 {
+        return None;
+    }
+    let version = VERSIONS.iter().find(|version| {
+        let bytes = if private {
+            version.private
+        } else {
+            version.public
+        };
+        data[..4] == bytes
+    })?;
+    let bip32_version = match (version.network, private) {
+        (NetworkKind::Main, false) => [0x04, 0x88, 0xb2, 0x1e],
+        (NetworkKind::Main, true) => [0x04, 0x88, 0xad, 0xe4],
+        (NetworkKind::Test, false) => [0x04, 0x35, 0x87, 0xcf],
+        (NetworkKind::Test, true) => [0x04, 0x35, 0x83, 0x94],
+    };
+    data[..4].copy_from_slice(&bip32_version);
+    Some((data, version.script_type))
+}
+
+// Serializes an extended key with the SLIP-132 version bytes of `script_type`
+fn from_bip32(
+    mut data: [u8; 78],
+    network: NetworkKind,
+    script_type: DescriptorType,
+    private: bool,
+) -> Option<String> {
+    let version = VERSIONS
+        .iter()
+        .find(|version| version.network == network && version.script_type == script_type)?;
+    data[..4].copy_from_slice(if private {
+        &version.private
+    } else {
+        &version.public
+    });
+    Some(base58::encode_check(&data))
+}
+
+/// Parses a SLIP-132 extended public key, along with its script type
+pub(super) fn decode_xpub(s: &str) -> Option<(bip32::Xpub, DescriptorType)> {
+    let (data, script_type) = to_bip32(s, false)?;
+    bip32::Xpub::decode(&data)
+        .ok()
+        .map(|xpub| (xpub, script_type))
+}
+
+/// Parses a SLIP-132 extended private key, along with its script type
+pub(super) fn decode_xpriv(s: &str) -> Option<(bip32::Xpriv, DescriptorType)> {
+    let (data, script_type) = to_bip32(s, true)?;
+    bip32::Xpriv::decode(&data)
+        .ok()
+        .map(|xpriv| (xpriv, script_type))
+}
+
+/// Serializes an extended public key in SLIP-132 form, if `script_type` has one
+pub(super) fn encode_xpub(xpub: &bip32::Xpub, script_type: DescriptorType) -> Option<String> {
+    from_bip32(xpub.encode(), xpub.network, script_type, false)
+}
+
+/// Serializes an extended private key in SLIP-132 form, if `script_type` has one
+pub(super) fn encode_xpriv(xpriv: &bip32::Xpriv, script_type: DescriptorType) -> Option<String> {
+    from_bip32(xpriv.encode(), xpriv.network, script_type, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slip132_round_trip() {
+        // Account keys of the BIP 84 test vectors
+        let zpub = "zpub6rFR7y4Q2AijBEqTUquhVz398htDFrtymD9xYYfG1m4wAcvPhXNfE3EfH1r1ADqtfSdVCToUG868RvUUkgDKf31mGDtKsAYz2oz2AGutZYs";
+        let zprv = "zprvAdG4iTXWBoARxkkzNpNh8r6Qag3irQB8PzEMkAFeTRXxHpbF9z4QgEvBRmfvqWvGp42t42nvgGpNgYSJA9iefm1yYNZKEm7z6qUWCroSQnE";
+
+        let (xpub, script_type) = decode_xpub(zpub).unwrap();
+        assert_eq!(script_type, DescriptorType::Wpkh);
+        assert!(xpub.to_string().starts_with("xpub"));
+        assert_eq!(encode_xpub(&xpub, DescriptorType::Wpkh).unwrap(), zpub);
+        let ypub = encode_xpub(&xpub, DescriptorType::ShWpkh).unwrap();
+        assert!(ypub.starts_with("ypub"));
+        assert_eq!(decode_xpub(&ypub).unwrap(), (xpub, DescriptorType::ShWpkh));
+        assert!(encode_xpub(&xpub, DescriptorType::TrKeyOnly).is_none());
+        // Plain BIP 32 keys, and private keys, are not SLIP-132 public keys
+        assert!(decode_xpub(&xpub.to_string()).is_none());
+        assert!(decode_xpub(zprv).is_none());
+
+        let (xpriv, script_type) = decode_xpriv(zprv).unwrap();
+        assert_eq!(script_type, DescriptorType::Wpkh);
+        assert_eq!(encode_xpriv(&xpriv, DescriptorType::Wpkh).unwrap(), zprv);
+        let secp = bitcoin::secp256k1::Secp256k1::signing_only();
+        assert_eq!(bip32::Xpub::from_priv(&secp, &xpriv), xpub);
+    }
+}
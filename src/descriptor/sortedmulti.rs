@@ -5,17 +5,18 @@
 //! Implementation of sorted multi primitive for descriptors
 //!
 
-use core::fmt;
 use core::marker::PhantomData;
+use core::{fmt, mem};
 
 use bitcoin::script;
 
 use crate::blanket_traits::FromStrKey;
+use crate::descriptor::{ConversionError, DescriptorPublicKey};
 use crate::miniscript::context::ScriptContext;
 use crate::miniscript::decode::Terminal;
 use crate::miniscript::limits::MAX_PUBKEYS_PER_MULTISIG;
 use crate::miniscript::satisfy::{Placeholder, Satisfaction};
-use crate::plan::AssetProvider;
+use crate::plan::{AssetProvider, MissingAssets};
 use crate::prelude::*;
 use crate::sync::Arc;
 use crate::{
@@ -139,6 +140,16 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> SortedMultiVec<Pk, Ctx> {
         Terminal::Multi(thresh)
     }
 
+    /// The position of each key of [`SortedMultiVec::pks`] among the sorted
+    /// keys of the script, which is the order its signature must be in for
+    /// `CHECKMULTISIG`.
+    pub fn sorted_positions(&self) -> Vec<usize>
+    where
+        Pk: ToPublicKey,
+    {
+        sorted_positions(self.pks())
+    }
+
     /// Encode as a Bitcoin script
     pub fn encode(&self) -> script::ScriptBuf
     where
@@ -149,6 +160,16 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> SortedMultiVec<Pk, Ctx> {
             .into_script()
     }
 
+    /// Encode as a Bitcoin script, appending it to `buf`, like
+    /// [`Miniscript::encode_to`]
+    pub fn encode_to(&self, buf: &mut Vec<u8>)
+    where
+        Pk: ToPublicKey,
+    {
+        let builder = script::Builder::from(mem::take(buf));
+        *buf = self.sorted_node().encode(builder).into_bytes();
+    }
+
     /// Attempt to produce a satisfying witness for the
     /// witness script represented by the parse tree
     pub fn satisfy<S>(&self, satisfier: S) -> Result<Vec<Vec<u8>>, Error>
@@ -170,6 +191,16 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> SortedMultiVec<Pk, Ctx> {
         ms.build_template(provider)
     }
 
+    /// Returns the assets missing from `provider` for each candidate spending path
+    pub fn missing_assets<P>(&self, provider: &P) -> MissingAssets<Pk>
+    where
+        Pk: ToPublicKey,
+        P: AssetProvider<Pk>,
+    {
+        let ms = Miniscript::from_ast(self.sorted_node()).expect("Multi node typecheck");
+        ms.missing_assets(provider)
+    }
+
     /// Size, in bytes of the script-pubkey. If this Miniscript is used outside
     /// of segwit (e.g. in a bare or P2SH descriptor), this quantity should be
     /// multiplied by 4 to compute the weight.
@@ -205,6 +236,39 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> SortedMultiVec<Pk, Ctx> {
     pub fn max_satisfaction_size(&self) -> usize { 1 + 73 * self.k() }
 }
 
+impl<Ctx: ScriptContext> SortedMultiVec<DescriptorPublicKey, Ctx> {
+    /// The position of each key among the sorted keys of the script once
+    /// derived at `index`, like [`SortedMultiVec::sorted_positions`].
+    ///
+    /// # Errors
+    /// - If index ≥ 2^31
+    /// - If a key has multiple derivation paths
+    pub fn sorted_positions_at(&self, index: u32) -> Result<Vec<usize>, ConversionError> {
+        let pks = self
+            .pks()
+            .iter()
+            .map(|pk| pk.clone().at_derivation_index(index))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(sorted_positions(&pks))
+    }
+}
+
+// The position of each key among the keys sorted according to BIP 67. Equal
+// keys keep their order, as in `SortedMultiVec::sorted_node`.
+fn sorted_positions<Pk: ToPublicKey>(pks: &[Pk]) -> Vec<usize> {
+    let serialized: Vec<_> = pks
+        .iter()
+        .map(|pk| pk.to_public_key().inner.serialize())
+        .collect();
+    let mut order: Vec<usize> = (0..pks.len()).collect();
+    order.sort_by(|&a, &b| serialized[a].cmp(&serialized[b]));
+    let mut positions = vec![0; pks.len()];
+    for (position, &key) in order.iter().enumerate() {
+        positions[key] = position;
+    }
+    positions
+}
+
 impl<Pk: MiniscriptKey, Ctx: ScriptContext> policy::Liftable<Pk> for SortedMultiVec<Pk, Ctx> {
     fn lift(&self) -> Result<policy::semantic::Policy<Pk>, Error> {
         Ok(policy::semantic::Policy::Thresh(
@@ -232,7 +296,7 @@ mod tests {
     use bitcoin::secp256k1::PublicKey;
 
     use super::*;
-    use crate::miniscript::context::Legacy;
+    use crate::miniscript::context::{Legacy, Segwitv0};
 
     #[test]
     fn too_many_pubkeys() {
@@ -257,4 +321,34 @@ mod tests {
             other => panic!("unexpected error: {:?}", other),
         }
     }
+
+    #[test]
+    fn sorted_positions() {
+        let xpub = "xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL";
+        let pks: Vec<DescriptorPublicKey> = (0..3)
+            .map(|i| DescriptorPublicKey::from_str(&format!("{}/{}/*", xpub, i)).unwrap())
+            .collect();
+        let smv = SortedMultiVec::<_, Segwitv0>::new(2, pks).unwrap();
+
+        for index in 0..4 {
+            let positions = smv.sorted_positions_at(index).unwrap();
+            let derived = smv
+                .translate_pk(&mut crate::FnTranslator::new(|pk: &DescriptorPublicKey| {
+                    pk.clone().at_derivation_index(index)
+                }))
+                .unwrap();
+            assert_eq!(derived.sorted_positions(), positions);
+            let sorted = match derived.sorted_node() {
+                Terminal::Multi(thresh) => thresh.data().to_vec(),
+                _ => unreachable!(),
+            };
+            for (pk, position) in derived.pks().iter().zip(positions) {
+                assert_eq!(&sorted[position], pk);
+            }
+        }
+
+        let wildcard = DescriptorPublicKey::from_str(&format!("{}/*", xpub)).unwrap();
+        let smv = SortedMultiVec::<_, Segwitv0>::new(1, vec![wildcard]).unwrap();
+        assert!(smv.sorted_positions_at(1 << 31).is_err());
+    }
 }
@@ -0,0 +1,575 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Spending Path Enumeration
+//!
+//! Lists every way in which a descriptor can be spent, with the assets each
+//! way needs and what it costs, e.g. to show the conditions of a wallet to
+//! its users or to audit a recovery setup.
+//!
+
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error;
+
+use bitcoin::hashes::{hash160, ripemd160, sha256};
+use bitcoin::{absolute, relative, TapLeafHash};
+
+use super::tr::tap_leaf_spend;
+use super::{DefiniteDescriptorKey, Descriptor, DescriptorType};
+use crate::miniscript::limits::{
+    MAX_STANDARD_P2WSH_STACK_ITEMS, MAX_STANDARD_P2WSH_STACK_ITEM_SIZE,
+};
+use crate::miniscript::satisfy::{Placeholder, Satisfaction, Witness};
+use crate::plan::{AssetProvider, MissingAsset, Plan};
+use crate::prelude::*;
+use crate::util::ItemSize;
+use crate::{hash256, AbsLockTime, MiniscriptKey, RelLockTime};
+
+/// Size of the largest Schnorr signature, which has a non-default sighash type.
+const MAX_SCHNORR_SIG_SIZE: usize = 65;
+
+/// Which part of a descriptor a [`SpendPath`] spends through.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum SpendPathKind {
+    /// The script of a non-taproot descriptor.
+    Script,
+    /// The taproot key spend.
+    TapKeySpend,
+    /// A taproot script leaf.
+    TapLeaf {
+        /// The index of the leaf in [`super::Tr::iter_scripts`].
+        leaf: usize,
+        /// The depth of the leaf in the tap tree.
+        depth: u8,
+    },
+}
+
+/// A way of spending a descriptor, with everything it requires.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SpendPath<Pk: MiniscriptKey> {
+    /// Which part of the descriptor is spent through.
+    pub kind: SpendPathKind,
+    /// Keys which must sign.
+    pub keys: BTreeSet<Pk>,
+    /// Hashes of keys which must be revealed and sign.
+    pub key_hashes: BTreeSet<hash160::Hash>,
+    /// SHA256 hashes whose preimages must be revealed.
+    pub sha256_preimages: BTreeSet<Pk::Sha256>,
+    /// HASH256 hashes whose preimages must be revealed.
+    pub hash256_preimages: BTreeSet<Pk::Hash256>,
+    /// RIPEMD160 hashes whose preimages must be revealed.
+    pub ripemd160_preimages: BTreeSet<Pk::Ripemd160>,
+    /// HASH160 hashes whose preimages must be revealed.
+    pub hash160_preimages: BTreeSet<Pk::Hash160>,
+    /// The greatest absolute timelock which must have expired.
+    pub absolute_timelock: Option<AbsLockTime>,
+    /// The greatest relative timelock which must have expired.
+    pub relative_timelock: Option<RelLockTime>,
+    /// The maximum weight of the satisfaction, as given by [`Plan::satisfaction_weight`].
+    ///
+    /// This is `None` if no non-malleable satisfaction can be planned for the path, e.g.
+    /// because it needs the signature of a key hash, whose key is not known.
+    pub max_weight: Option<usize>,
+}
+
+impl<Pk: MiniscriptKey> SpendPath<Pk> {
+    fn new(
+        kind: SpendPathKind,
+        assets: BTreeSet<MissingAsset<Pk>>,
+        max_weight: Option<usize>,
+    ) -> Self {
+        let mut path = SpendPath {
+            kind,
+            keys: BTreeSet::new(),
+            key_hashes: BTreeSet::new(),
+            sha256_preimages: BTreeSet::new(),
+            hash256_preimages: BTreeSet::new(),
+            ripemd160_preimages: BTreeSet::new(),
+            hash160_preimages: BTreeSet::new(),
+            absolute_timelock: None,
+            relative_timelock: None,
+            max_weight,
+        };
+        for asset in assets {
+            match asset {
+                MissingAsset::Signature(pk) => {
+                    path.keys.insert(pk);
+                }
+                MissingAsset::KeyHashSignature(hash) => {
+                    path.key_hashes.insert(hash);
+                }
+                MissingAsset::Sha256Preimage(hash) => {
+                    path.sha256_preimages.insert(hash);
+                }
+                MissingAsset::Hash256Preimage(hash) => {
+                    path.hash256_preimages.insert(hash);
+                }
+                MissingAsset::Ripemd160Preimage(hash) => {
+                    path.ripemd160_preimages.insert(hash);
+                }
+                MissingAsset::Hash160Preimage(hash) => {
+                    path.hash160_preimages.insert(hash);
+                }
+                MissingAsset::AbsoluteTimelock(t) => {
+                    path.absolute_timelock = path.absolute_timelock.max(Some(t));
+                }
+                MissingAsset::RelativeTimelock(t) => {
+                    path.relative_timelock = path.relative_timelock.max(Some(t));
+                }
+            }
+        }
+        path
+    }
+}
+
+/// A standardness limit on P2WSH witnesses broken by a spending path, see
+/// [`Descriptor::non_standard_segwitv0_paths`]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum NonStandardWitness {
+    /// The witness has more than `MAX_STANDARD_P2WSH_STACK_ITEMS` (100)
+    /// elements, not counting the witness script.
+    TooManyItems {
+        /// The number of elements.
+        actual: usize,
+        /// The maximum number of elements.
+        limit: usize,
+    },
+    /// An element of the witness, other than the witness script, is larger
+    /// than `MAX_STANDARD_P2WSH_STACK_ITEM_SIZE` (80) bytes.
+    ItemTooLarge {
+        /// The position of the element in the witness.
+        index: usize,
+        /// The size of the element, in bytes.
+        size: usize,
+        /// The maximum size of an element, in bytes.
+        limit: usize,
+    },
+}
+
+impl fmt::Display for NonStandardWitness {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NonStandardWitness::TooManyItems { actual, limit } => {
+                write!(f, "witness has {} elements, more than the standard {}", actual, limit)
+            }
+            NonStandardWitness::ItemTooLarge { index, size, limit } => write!(
+                f,
+                "witness element {} is {} bytes, more than the standard {}",
+                index, size, limit
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for NonStandardWitness {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            NonStandardWitness::TooManyItems { .. } | NonStandardWitness::ItemTooLarge { .. } => {
+                None
+            }
+        }
+    }
+}
+
+impl Plan {
+    /// The spending path chosen by this plan
+    ///
+    /// This tells which part of the descriptor is spent, e.g. which taproot leaf, and which
+    /// branch of its `or`s and thresholds: the keys, hash preimages and timelocks of the path
+    /// are exactly those used by the plan. `max_weight` is [`Plan::satisfaction_weight`].
+    ///
+    /// Returns `None` if the plan spends a taproot leaf which isn't in its descriptor, which
+    /// can only happen if it was deserialized.
+    pub fn spend_path(&self) -> Option<SpendPath<DefiniteDescriptorKey>> {
+        let mut assets = BTreeSet::new();
+        for placeholder in self.witness_template() {
+            let asset = match placeholder {
+                Placeholder::EcdsaSigPk(pk) | Placeholder::SchnorrSigPk(pk, ..) => {
+                    MissingAsset::Signature(pk.clone())
+                }
+                Placeholder::EcdsaSigPkHash(hash) | Placeholder::SchnorrSigPkHash(hash, ..) => {
+                    MissingAsset::KeyHashSignature(*hash)
+                }
+                Placeholder::Sha256Preimage(hash) | Placeholder::PendingSha256Preimage(hash) => {
+                    MissingAsset::Sha256Preimage(*hash)
+                }
+                Placeholder::Hash256Preimage(hash) | Placeholder::PendingHash256Preimage(hash) => {
+                    MissingAsset::Hash256Preimage(*hash)
+                }
+                Placeholder::Ripemd160Preimage(hash)
+                | Placeholder::PendingRipemd160Preimage(hash) => {
+                    MissingAsset::Ripemd160Preimage(*hash)
+                }
+                Placeholder::Hash160Preimage(hash) | Placeholder::PendingHash160Preimage(hash) => {
+                    MissingAsset::Hash160Preimage(*hash)
+                }
+                _ => continue,
+            };
+            assets.insert(asset);
+        }
+        if let Some(t) = self.absolute_timelock {
+            let t = AbsLockTime::from_consensus(t.to_consensus_u32());
+            assets.extend(t.map(MissingAsset::AbsoluteTimelock));
+        }
+        if let Some(t) = self.relative_timelock {
+            let t = RelLockTime::from_consensus(t.to_consensus_u32());
+            assets.extend(t.map(MissingAsset::RelativeTimelock));
+        }
+
+        let kind = match (&self.descriptor, self.tap_leaf()) {
+            (Descriptor::Tr(tr), Some(leaf)) => tr
+                .iter_scripts()
+                .enumerate()
+                .find(|(_, (depth, ms))| *depth == leaf.depth && ms.encode() == leaf.script)
+                .map(|(leaf, (depth, _))| SpendPathKind::TapLeaf { leaf, depth })?,
+            (Descriptor::Tr(_), None) => SpendPathKind::TapKeySpend,
+            _ => SpendPathKind::Script,
+        };
+        Some(SpendPath::new(kind, assets, Some(self.satisfaction_weight())))
+    }
+}
+
+/// Provides exactly the assets of a single spending path.
+struct PathAssets<'a>(&'a BTreeSet<MissingAsset<DefiniteDescriptorKey>>);
+
+impl PathAssets<'_> {
+    fn has_sig(&self, pk: &DefiniteDescriptorKey) -> bool {
+        self.0.contains(&MissingAsset::Signature(pk.clone()))
+    }
+}
+
+impl AssetProvider<DefiniteDescriptorKey> for PathAssets<'_> {
+    fn provider_lookup_ecdsa_sig(&self, pk: &DefiniteDescriptorKey) -> bool { self.has_sig(pk) }
+
+    fn provider_lookup_tap_key_spend_sig(&self, pk: &DefiniteDescriptorKey) -> Option<usize> {
+        self.has_sig(pk).then_some(MAX_SCHNORR_SIG_SIZE)
+    }
+
+    fn provider_lookup_tap_leaf_script_sig(
+        &self,
+        pk: &DefiniteDescriptorKey,
+        _: &TapLeafHash,
+    ) -> Option<usize> {
+        self.has_sig(pk).then_some(MAX_SCHNORR_SIG_SIZE)
+    }
+
+    fn provider_lookup_sha256(&self, hash: &sha256::Hash) -> bool {
+        self.0.contains(&MissingAsset::Sha256Preimage(*hash))
+    }
+
+    fn provider_lookup_hash256(&self, hash: &hash256::Hash) -> bool {
+        self.0.contains(&MissingAsset::Hash256Preimage(*hash))
+    }
+
+    fn provider_lookup_ripemd160(&self, hash: &ripemd160::Hash) -> bool {
+        self.0.contains(&MissingAsset::Ripemd160Preimage(*hash))
+    }
+
+    fn provider_lookup_hash160(&self, hash: &hash160::Hash) -> bool {
+        self.0.contains(&MissingAsset::Hash160Preimage(*hash))
+    }
+
+    fn check_older(&self, s: relative::LockTime) -> bool {
+        self.0.iter().any(|asset| match *asset {
+            MissingAsset::RelativeTimelock(t) => s.is_implied_by(t.into()),
+            _ => false,
+        })
+    }
+
+    fn check_after(&self, l: absolute::LockTime) -> bool {
+        self.0.iter().any(|asset| match *asset {
+            MissingAsset::AbsoluteTimelock(t) => l.is_implied_by(t.into()),
+            _ => false,
+        })
+    }
+}
+
+impl Descriptor<DefiniteDescriptorKey> {
+    /// Returns every distinct way of spending the descriptor, along with the keys, hash
+    /// preimages and timelocks each one requires and the maximum weight of its satisfaction.
+    ///
+    /// For taproot descriptors, the key spend comes first, followed by the paths through each
+    /// leaf in the order of [`super::Tr::iter_scripts`]. Within a script, paths are ordered
+    /// by the number of assets they require, and are subject to the same limit as
+    /// [`crate::plan::MissingAssets`].
+    pub fn spend_paths(&self) -> Vec<SpendPath<DefiniteDescriptorKey>> {
+        let no_assets = BTreeSet::new();
+        let mut ret = vec![];
+        match *self {
+            Descriptor::Tr(ref tr) => {
+                let assets = BTreeSet::from([MissingAsset::Signature(tr.internal_key().clone())]);
+                let max_weight = self.max_path_weight(&assets);
+                ret.push(SpendPath::new(SpendPathKind::TapKeySpend, assets, max_weight));
+
+                let spend_info = tr.spend_info();
+                for (leaf, (depth, ms)) in tr.iter_scripts().enumerate() {
+                    let kind = SpendPathKind::TapLeaf { leaf, depth };
+                    for assets in ms.missing_assets(&PathAssets(&no_assets)).into_paths() {
+                        // Plan the leaf on its own, as another one may be cheaper with the
+                        // same assets
+                        let max_weight =
+                            tap_leaf_spend(&spend_info, ms, &PathAssets(&assets), false)
+                                .and_then(|satisfaction| self.template_weight(satisfaction));
+                        ret.push(SpendPath::new(kind, assets, max_weight));
+                    }
+                }
+            }
+            _ => {
+                for assets in self.missing_assets(&PathAssets(&no_assets)).into_paths() {
+                    let max_weight = self.max_path_weight(&assets);
+                    ret.push(SpendPath::new(SpendPathKind::Script, assets, max_weight));
+                }
+            }
+        }
+        ret
+    }
+
+    /// Simulates the witness of each spending path of a `wsh` or `sh(wsh)`
+    /// descriptor, and returns the paths whose witness would break the
+    /// standardness limits on P2WSH witnesses, with the limits they break.
+    ///
+    /// The paths are those of [`Descriptor::spend_paths`], with the witness
+    /// of their cheapest satisfaction. Paths which can't be planned, e.g.
+    /// because they need the signature of a key hash, can't be simulated and
+    /// are never returned. Other descriptors have no such limits, so the
+    /// result is empty for them.
+    pub fn non_standard_segwitv0_paths(
+        &self,
+    ) -> Vec<(SpendPath<DefiniteDescriptorKey>, Vec<NonStandardWitness>)> {
+        match self.desc_type() {
+            DescriptorType::Wsh
+            | DescriptorType::ShWsh
+            | DescriptorType::WshSortedMulti
+            | DescriptorType::ShWshSortedMulti => {}
+            _ => return vec![],
+        }
+
+        let no_assets = BTreeSet::new();
+        let mut ret = vec![];
+        for assets in self.missing_assets(&PathAssets(&no_assets)).into_paths() {
+            let plan = match self.clone().plan(&PathAssets(&assets)) {
+                Ok(plan) => plan,
+                Err(_) => continue,
+            };
+            // The template leaves out the witness script
+            let template = plan.witness_template();
+            let mut errors = vec![];
+            if template.len() > MAX_STANDARD_P2WSH_STACK_ITEMS {
+                errors.push(NonStandardWitness::TooManyItems {
+                    actual: template.len(),
+                    limit: MAX_STANDARD_P2WSH_STACK_ITEMS,
+                });
+            }
+            for (index, item) in template.iter().enumerate() {
+                // Item sizes include their one-byte length prefix
+                let size = item.size() - 1;
+                if size > MAX_STANDARD_P2WSH_STACK_ITEM_SIZE {
+                    errors.push(NonStandardWitness::ItemTooLarge {
+                        index,
+                        size,
+                        limit: MAX_STANDARD_P2WSH_STACK_ITEM_SIZE,
+                    });
+                }
+            }
+            if !errors.is_empty() {
+                let max_weight = Some(plan.satisfaction_weight());
+                ret.push((SpendPath::new(SpendPathKind::Script, assets, max_weight), errors));
+            }
+        }
+        ret
+    }
+
+    /// The weight of the cheapest satisfaction using exactly the given assets.
+    fn max_path_weight(
+        &self,
+        assets: &BTreeSet<MissingAsset<DefiniteDescriptorKey>>,
+    ) -> Option<usize> {
+        let plan = self.clone().plan(&PathAssets(assets)).ok()?;
+        Some(plan.satisfaction_weight())
+    }
+
+    /// The weight of a satisfaction template of this descriptor.
+    fn template_weight(
+        &self,
+        satisfaction: Satisfaction<Placeholder<DefiniteDescriptorKey>>,
+    ) -> Option<usize> {
+        let template = match satisfaction.stack {
+            Witness::Stack(template) => template,
+            Witness::Unavailable | Witness::Impossible => return None,
+        };
+        let plan = Plan {
+            template,
+            absolute_timelock: satisfaction.absolute_timelock.map(Into::into),
+            relative_timelock: satisfaction.relative_timelock.map(Into::into),
+            descriptor: self.clone(),
+        };
+        Some(plan.satisfaction_weight())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+    use crate::plan::Assets;
+    use crate::DescriptorPublicKey;
+
+    const KEYS: [&str; 3] = [
+        "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+        "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+        "03500a2b48b0f66c8183cc0d6645ab21cc19c7fad8a33ff04d41c3ece54b0bc1c5",
+    ];
+
+    fn parse(s: &str) -> Descriptor<DefiniteDescriptorKey> {
+        let s = s
+            .replace("A", KEYS[0])
+            .replace("B", KEYS[1])
+            .replace("C", KEYS[2]);
+        Descriptor::from_str(&s).unwrap()
+    }
+
+    fn keys(names: &str) -> BTreeSet<DefiniteDescriptorKey> {
+        names
+            .chars()
+            .map(|c| DefiniteDescriptorKey::from_str(KEYS[(c as u8 - b'A') as usize]).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn segwit_spend_paths() {
+        let desc = parse("wsh(or_d(pk(A),and_v(v:pk(B),older(144))))");
+        let paths = desc.spend_paths();
+        assert_eq!(paths.len(), 2);
+        assert!(paths.iter().all(|path| path.kind == SpendPathKind::Script));
+        assert_eq!(paths[0].keys, keys("A"));
+        assert_eq!(paths[0].relative_timelock, None);
+        assert_eq!(paths[1].keys, keys("B"));
+        assert_eq!(paths[1].relative_timelock, Some(RelLockTime::from_height(144)));
+
+        // The weights agree with plans made from the same keys
+        let plan_weight = |key: usize| {
+            let assets = Assets::new()
+                .add(DescriptorPublicKey::from_str(KEYS[key]).unwrap())
+                .older(relative::LockTime::from_height(144));
+            desc.clone().plan(&assets).unwrap().satisfaction_weight()
+        };
+        assert_eq!(paths[0].max_weight, Some(plan_weight(0)));
+        assert_eq!(paths[1].max_weight, Some(plan_weight(1)));
+        assert!(paths[0].max_weight < paths[1].max_weight);
+
+        let paths = parse("sh(wsh(multi(2,A,B,C)))").spend_paths();
+        let key_sets = paths
+            .iter()
+            .map(|path| path.keys.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(key_sets, vec![keys("AB"), keys("AC"), keys("BC")]);
+        assert!(paths
+            .iter()
+            .all(|path| path.max_weight == paths[0].max_weight));
+    }
+
+    #[test]
+    fn taproot_spend_paths() {
+        let hash = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        let desc = parse(&format!("tr(A,{{pk(B),and_v(v:pk(C),sha256({}))}})", hash));
+        let paths = desc.spend_paths();
+        let kinds = paths.iter().map(|path| path.kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                SpendPathKind::TapKeySpend,
+                SpendPathKind::TapLeaf { leaf: 0, depth: 1 },
+                SpendPathKind::TapLeaf { leaf: 1, depth: 1 },
+            ]
+        );
+        assert_eq!(paths[0].keys, keys("A"));
+        assert_eq!(paths[1].keys, keys("B"));
+        assert_eq!(paths[2].keys, keys("C"));
+        assert_eq!(paths[2].sha256_preimages.len(), 1);
+
+        // Key spend: a 65-byte signature, its length and the witness element count
+        assert_eq!(paths[0].max_weight, Some(1 + 1 + 65 + 4));
+        assert!(paths[0].max_weight < paths[1].max_weight);
+        assert!(paths[1].max_weight < paths[2].max_weight);
+    }
+
+    #[test]
+    fn plan_spend_path() {
+        let assets =
+            |key: usize| Assets::new().add(DescriptorPublicKey::from_str(KEYS[key]).unwrap());
+
+        let desc = parse("wsh(or_d(pk(A),and_v(v:pk(B),older(144))))");
+        let plan = desc
+            .clone()
+            .plan(&assets(1).older(relative::LockTime::from_height(144)))
+            .unwrap();
+        let path = plan.spend_path().unwrap();
+        let expected = &desc.spend_paths()[1];
+        assert_eq!(path.kind, expected.kind);
+        assert_eq!(path.keys, expected.keys);
+        assert_eq!(path.relative_timelock, expected.relative_timelock);
+        assert_eq!(path.max_weight, Some(plan.satisfaction_weight()));
+        assert_eq!(plan.tap_leaf(), None);
+
+        let hash = sha256::Hash::from_str(
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+        )
+        .unwrap();
+        let desc = parse(&format!("tr(A,{{pk(B),and_v(v:pk(C),sha256({}))}})", hash));
+        let plan = desc.clone().plan(&assets(2).add(hash)).unwrap();
+        let path = plan.spend_path().unwrap();
+        assert_eq!(path.kind, SpendPathKind::TapLeaf { leaf: 1, depth: 1 });
+        assert_eq!(path.keys, keys("C"));
+        assert_eq!(path.sha256_preimages, BTreeSet::from([hash]));
+
+        let leaf = plan.tap_leaf().unwrap();
+        let (_, ms) = desc.tap_tree_iter().nth(1).unwrap();
+        assert_eq!(leaf.script, ms.encode());
+        assert_eq!(leaf.depth, 1);
+        assert_eq!(leaf.leaf_hash, TapLeafHash::from_script(&leaf.script, leaf.leaf_version));
+        let sig = &plan.requirements().signatures[0];
+        assert_eq!(sig.leaf_hash, Some(leaf.leaf_hash));
+
+        let plan = desc.plan(&assets(0)).unwrap();
+        assert_eq!(plan.spend_path().unwrap().kind, SpendPathKind::TapKeySpend);
+        assert_eq!(plan.tap_leaf(), None);
+    }
+
+    #[test]
+    fn non_standard_segwitv0_paths() {
+        assert!(parse("wsh(multi(2,A,B,C))")
+            .non_standard_segwitv0_paths()
+            .is_empty());
+
+        // Satisfying one 20-of-20 multisig and dissatisfying four others takes
+        // 5 * 21 witness elements
+        let keys = crate::miniscript::iter::test::gen_bitcoin_pubkeys(100, true);
+        let multis: Vec<String> = keys
+            .chunks(20)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let keys: Vec<String> = chunk.iter().map(|pk| pk.to_string()).collect();
+                let wrapper = if i == 0 { "" } else { "a:" };
+                format!("{}multi(20,{})", wrapper, keys.join(","))
+            })
+            .collect();
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(thresh(1,{}))",
+            multis.join(",")
+        ))
+        .unwrap();
+        let paths = desc.non_standard_segwitv0_paths();
+        assert_eq!(paths.len(), 5);
+        for (path, errors) in &paths {
+            assert_eq!(path.keys.len(), 20);
+            assert_eq!(errors, &[NonStandardWitness::TooManyItems { actual: 105, limit: 100 }]);
+        }
+
+        // Taproot has no such limits
+        assert!(parse("tr(A,pk(B))")
+            .non_standard_segwitv0_paths()
+            .is_empty());
+    }
+}
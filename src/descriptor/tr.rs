@@ -2,11 +2,12 @@
 
 use core::{cmp, fmt, hash};
 
+use bitcoin::hashes::hash160;
 #[cfg(not(test))] // https://github.com/rust-lang/rust/issues/121684
 use bitcoin::secp256k1;
 use bitcoin::taproot::{
-    LeafVersion, TaprootBuilder, TaprootSpendInfo, TAPROOT_CONTROL_BASE_SIZE,
-    TAPROOT_CONTROL_MAX_NODE_COUNT, TAPROOT_CONTROL_NODE_SIZE,
+    LeafVersion, TapLeafHash, TapNodeHash, TaprootBuilder, TaprootMerkleBranch, TaprootSpendInfo,
+    TAPROOT_CONTROL_BASE_SIZE, TAPROOT_CONTROL_MAX_NODE_COUNT, TAPROOT_CONTROL_NODE_SIZE,
 };
 use bitcoin::{opcodes, Address, Network, ScriptBuf, Weight};
 use sync::Arc;
@@ -14,29 +15,31 @@ use sync::Arc;
 use super::checksum;
 use crate::descriptor::DefiniteDescriptorKey;
 use crate::expression::{self, FromTree};
+use crate::iter::{Tree, TreeLike};
+use crate::miniscript::extension::{Extension, NoExt};
 use crate::miniscript::satisfy::{Placeholder, Satisfaction, SchnorrSigType, Witness};
 use crate::miniscript::Miniscript;
-use crate::plan::AssetProvider;
+use crate::plan::{AssetProvider, MissingAsset, MissingAssets};
 use crate::policy::semantic::Policy;
 use crate::policy::Liftable;
 use crate::prelude::*;
-use crate::util::{varint_len, witness_size};
+use crate::util::{take_script_bytes, varint_len, with_verify_context, witness_size};
 use crate::{
-    Error, ForEachKey, FromStrKey, MiniscriptKey, ParseError, Satisfier, ScriptContext, Tap,
-    Threshold, ToPublicKey, TranslateErr, Translator,
+    AnalysisError, Error, FnTranslator, ForEachKey, FromStrKey, MiniscriptKey, ParseError,
+    Satisfier, SatisfyError, ScriptContext, Tap, Threshold, ToPublicKey, TranslateErr, Translator,
 };
 
 /// A Taproot Tree representation.
 // Hidden leaves are not yet supported in descriptor spec. Conceptually, it should
 // be simple to integrate those here, but it is best to wait on core for the exact syntax.
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
-pub enum TapTree<Pk: MiniscriptKey> {
+pub enum TapTree<Pk: MiniscriptKey, Ext: Extension = NoExt> {
     /// A taproot tree structure
     Tree {
         /// Left tree branch.
-        left: Arc<TapTree<Pk>>,
+        left: Arc<TapTree<Pk, Ext>>,
         /// Right tree branch.
-        right: Arc<TapTree<Pk>>,
+        right: Arc<TapTree<Pk, Ext>>,
         /// Tree height, defined as `1 + max(left_height, right_height)`.
         height: usize,
     },
@@ -44,15 +47,15 @@ pub enum TapTree<Pk: MiniscriptKey> {
     // A new leaf version would require a new Context, therefore there is no point
     // in adding a LeafVersion with Leaf type here. All Miniscripts right now
     // are of Leafversion::default
-    Leaf(Arc<Miniscript<Pk, Tap>>),
+    Leaf(Arc<Miniscript<Pk, Tap, Ext>>),
 }
 
 /// A taproot descriptor
-pub struct Tr<Pk: MiniscriptKey> {
+pub struct Tr<Pk: MiniscriptKey, Ext: Extension = NoExt> {
     /// A taproot internal key
     internal_key: Pk,
     /// Optional Taproot Tree with spending conditions
-    tree: Option<TapTree<Pk>>,
+    tree: Option<TapTree<Pk, Ext>>,
     /// Optional spending information associated with the descriptor
     /// This will be [`None`] when the descriptor is not derived.
     /// This information will be cached automatically when it is required
@@ -63,7 +66,7 @@ pub struct Tr<Pk: MiniscriptKey> {
     spend_info: Mutex<Option<Arc<TaprootSpendInfo>>>,
 }
 
-impl<Pk: MiniscriptKey> Clone for Tr<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> Clone for Tr<Pk, Ext> {
     fn clone(&self) -> Self {
         // When cloning, construct a new Mutex so that distinct clones don't
         // cause blocking between each other. We clone only the internal `Arc`,
@@ -82,19 +85,19 @@ impl<Pk: MiniscriptKey> Clone for Tr<Pk> {
     }
 }
 
-impl<Pk: MiniscriptKey> PartialEq for Tr<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> PartialEq for Tr<Pk, Ext> {
     fn eq(&self, other: &Self) -> bool {
         self.internal_key == other.internal_key && self.tree == other.tree
     }
 }
 
-impl<Pk: MiniscriptKey> Eq for Tr<Pk> {}
+impl<Pk: MiniscriptKey, Ext: Extension> Eq for Tr<Pk, Ext> {}
 
-impl<Pk: MiniscriptKey> PartialOrd for Tr<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> PartialOrd for Tr<Pk, Ext> {
     fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> { Some(self.cmp(other)) }
 }
 
-impl<Pk: MiniscriptKey> Ord for Tr<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> Ord for Tr<Pk, Ext> {
     fn cmp(&self, other: &Self) -> cmp::Ordering {
         match self.internal_key.cmp(&other.internal_key) {
             cmp::Ordering::Equal => {}
@@ -104,16 +107,16 @@ impl<Pk: MiniscriptKey> Ord for Tr<Pk> {
     }
 }
 
-impl<Pk: MiniscriptKey> hash::Hash for Tr<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> hash::Hash for Tr<Pk, Ext> {
     fn hash<H: hash::Hasher>(&self, state: &mut H) {
         self.internal_key.hash(state);
         self.tree.hash(state);
     }
 }
 
-impl<Pk: MiniscriptKey> TapTree<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> TapTree<Pk, Ext> {
     /// Creates a `TapTree` by combining `left` and `right` tree nodes.
-    pub fn combine(left: TapTree<Pk>, right: TapTree<Pk>) -> Self {
+    pub fn combine(left: TapTree<Pk, Ext>, right: TapTree<Pk, Ext>) -> Self {
         let height = 1 + cmp::max(left.height(), right.height());
         TapTree::Tree { left: Arc::new(left), right: Arc::new(right), height }
     }
@@ -128,10 +131,31 @@ impl<Pk: MiniscriptKey> TapTree<Pk> {
 
     /// Iterates over all miniscripts in DFS walk order compatible with the
     /// PSBT requirements (BIP 371).
-    pub fn iter(&self) -> TapTreeIter<Pk> { TapTreeIter { stack: vec![(0, self)] } }
+    pub fn iter(&self) -> TapTreeIter<Pk, Ext> { TapTreeIter { stack: vec![(0, self)] } }
+
+    /// Converts the keys of the tree to another kind of key, translating each
+    /// key with the closure `f`.
+    ///
+    /// Hashes are kept as they are, so both kinds of keys must use the same
+    /// hash types.
+    pub fn translate_pk_with<Q, E, F>(&self, f: F) -> Result<TapTree<Q, Ext>, TranslateErr<E>>
+    where
+        Q: MiniscriptKey<
+            Sha256 = Pk::Sha256,
+            Hash256 = Pk::Hash256,
+            Ripemd160 = Pk::Ripemd160,
+            Hash160 = Pk::Hash160,
+        >,
+        F: FnMut(&Pk) -> Result<Q, E>,
+    {
+        self.translate_helper(&mut FnTranslator::new(f))
+    }
 
     // Helper function to translate keys
-    fn translate_helper<T>(&self, t: &mut T) -> Result<TapTree<T::TargetPk>, TranslateErr<T::Error>>
+    fn translate_helper<T>(
+        &self,
+        t: &mut T,
+    ) -> Result<TapTree<T::TargetPk, Ext>, TranslateErr<T::Error>>
     where
         T: Translator<Pk>,
     {
@@ -145,9 +169,39 @@ impl<Pk: MiniscriptKey> TapTree<Pk> {
         };
         Ok(frag)
     }
+
+    /// Substitutes all raw public key hashes in the tree leaves with the public keys
+    /// as provided by map, failing with the set of unresolved hashes if any are missing.
+    ///
+    /// Unresolved hashes are collected across all leaves of the tree.
+    pub fn resolve_raw_pkh(
+        &self,
+        pk_map: &BTreeMap<hash160::Hash, Pk>,
+    ) -> Result<TapTree<Pk, Ext>, BTreeSet<hash160::Hash>> {
+        let unresolved = self
+            .iter()
+            .flat_map(|(_, ms)| ms.unresolved_raw_pkh(pk_map))
+            .collect::<BTreeSet<_>>();
+        if !unresolved.is_empty() {
+            return Err(unresolved);
+        }
+        Ok(self.substitute_raw_pkh_helper(pk_map))
+    }
+
+    // Helper function to substitute raw public key hashes in all leaves
+    fn substitute_raw_pkh_helper(&self, pk_map: &BTreeMap<hash160::Hash, Pk>) -> TapTree<Pk, Ext> {
+        match *self {
+            TapTree::Tree { ref left, ref right, ref height } => TapTree::Tree {
+                left: Arc::new(left.substitute_raw_pkh_helper(pk_map)),
+                right: Arc::new(right.substitute_raw_pkh_helper(pk_map)),
+                height: *height,
+            },
+            TapTree::Leaf(ref ms) => TapTree::Leaf(Arc::new(ms.substitute_raw_pkh(pk_map))),
+        }
+    }
 }
 
-impl<Pk: MiniscriptKey> fmt::Display for TapTree<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> fmt::Display for TapTree<Pk, Ext> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             TapTree::Tree { ref left, ref right, height: _ } => {
@@ -158,7 +212,7 @@ impl<Pk: MiniscriptKey> fmt::Display for TapTree<Pk> {
     }
 }
 
-impl<Pk: MiniscriptKey> fmt::Debug for TapTree<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> fmt::Debug for TapTree<Pk, Ext> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             TapTree::Tree { ref left, ref right, height: _ } => {
@@ -169,9 +223,9 @@ impl<Pk: MiniscriptKey> fmt::Debug for TapTree<Pk> {
     }
 }
 
-impl<Pk: MiniscriptKey> Tr<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> Tr<Pk, Ext> {
     /// Create a new [`Tr`] descriptor from internal key and [`TapTree`]
-    pub fn new(internal_key: Pk, tree: Option<TapTree<Pk>>) -> Result<Self, Error> {
+    pub fn new(internal_key: Pk, tree: Option<TapTree<Pk, Ext>>) -> Result<Self, Error> {
         Tap::check_pk(&internal_key)?;
         let nodes = tree.as_ref().map(|t| t.height()).unwrap_or(0);
 
@@ -186,15 +240,15 @@ impl<Pk: MiniscriptKey> Tr<Pk> {
     pub fn internal_key(&self) -> &Pk { &self.internal_key }
 
     /// Obtain the [`TapTree`] of the [`Tr`] descriptor
-    pub fn tap_tree(&self) -> &Option<TapTree<Pk>> { &self.tree }
+    pub fn tap_tree(&self) -> &Option<TapTree<Pk, Ext>> { &self.tree }
 
     /// Obtain the [`TapTree`] of the [`Tr`] descriptor
     #[deprecated(since = "11.0.0", note = "use tap_tree instead")]
-    pub fn taptree(&self) -> &Option<TapTree<Pk>> { self.tap_tree() }
+    pub fn taptree(&self) -> &Option<TapTree<Pk, Ext>> { self.tap_tree() }
 
     /// Iterate over all scripts in merkle tree. If there is no script path, the iterator
     /// yields [`None`]
-    pub fn iter_scripts(&self) -> TapTreeIter<Pk> {
+    pub fn iter_scripts(&self) -> TapTreeIter<Pk, Ext> {
         match self.tree {
             Some(ref t) => t.iter(),
             None => TapTreeIter { stack: vec![] },
@@ -207,6 +261,18 @@ impl<Pk: MiniscriptKey> Tr<Pk> {
     ///
     /// [`TaprootSpendInfo`] is only required for spending via the script paths.
     pub fn spend_info(&self) -> Arc<TaprootSpendInfo>
+    where
+        Pk: ToPublicKey,
+    {
+        with_verify_context(|secp| self.spend_info_with_secp(secp))
+    }
+
+    /// Computes the [`TaprootSpendInfo`] like [`Tr::spend_info`], using the
+    /// provided secp256k1 context rather than creating one.
+    pub fn spend_info_with_secp<C: secp256k1::Verification>(
+        &self,
+        secp: &secp256k1::Secp256k1<C>,
+    ) -> Arc<TaprootSpendInfo>
     where
         Pk: ToPublicKey,
     {
@@ -218,12 +284,9 @@ impl<Pk: MiniscriptKey> Tr<Pk> {
         }
         drop(read_lock);
 
-        // Get a new secp context
-        // This would be cheap operation after static context support from upstream
-        let secp = secp256k1::Secp256k1::verification_only();
         // Key spend path with no merkle root
         let data = if self.tree.is_none() {
-            TaprootSpendInfo::new_key_spend(&secp, self.internal_key.to_x_only_pubkey(), None)
+            TaprootSpendInfo::new_key_spend(secp, self.internal_key.to_x_only_pubkey(), None)
         } else {
             let mut builder = TaprootBuilder::new();
             for (depth, ms) in self.iter_scripts() {
@@ -233,7 +296,7 @@ impl<Pk: MiniscriptKey> Tr<Pk> {
                     .expect("Computing spend data on a valid Tree should always succeed");
             }
             // Assert builder cannot error here because we have a well formed descriptor
-            match builder.finalize(&secp, self.internal_key.to_x_only_pubkey()) {
+            match builder.finalize(secp, self.internal_key.to_x_only_pubkey()) {
                 Ok(data) => data,
                 Err(_) => unreachable!("We know the builder can be finalized"),
             }
@@ -244,10 +307,33 @@ impl<Pk: MiniscriptKey> Tr<Pk> {
     }
 
     /// Checks whether the descriptor is safe.
+    ///
+    /// On top of the checks of [`Miniscript::sanity_check`] for each leaf,
+    /// this rejects leaves which can never be satisfied or exceed the
+    /// tapscript resource limits, and leaves with the same script, whose
+    /// control blocks cannot be told apart by their script.
     pub fn sanity_check(&self) -> Result<(), Error> {
-        for (_depth, ms) in self.iter_scripts() {
+        let leaves: Vec<_> = self.iter_scripts().map(|(_depth, ms)| ms).collect();
+        for (leaf, ms) in leaves.iter().enumerate() {
+            if ms.max_satisfaction_size().is_err() {
+                return Err(Error::AnalysisError(AnalysisError::UnsatisfiableTapLeaf(leaf)));
+            }
+            if !ms.within_resource_limits() {
+                return Err(Error::AnalysisError(AnalysisError::TapLeafExceedsResourceLimits(
+                    leaf,
+                )));
+            }
             ms.sanity_check()?;
         }
+        for (first, ms) in leaves.iter().enumerate() {
+            if let Some(second) = leaves[first + 1..].iter().position(|other| other == ms) {
+                let second = first + 1 + second;
+                return Err(Error::AnalysisError(AnalysisError::DuplicateTapLeaf {
+                    first,
+                    second,
+                }));
+            }
+        }
         Ok(())
     }
 
@@ -298,7 +384,7 @@ impl<Pk: MiniscriptKey> Tr<Pk> {
                 )
             })
             .max()
-            .ok_or(Error::ImpossibleSatisfaction)?;
+            .ok_or(Error::Satisfy(SatisfyError::ImpossibleSatisfaction))?;
 
         Ok(Weight::from_wu(wu as u64))
     }
@@ -347,14 +433,14 @@ impl<Pk: MiniscriptKey> Tr<Pk> {
                 )
             })
             .max()
-            .ok_or(Error::ImpossibleSatisfaction)
+            .ok_or(Error::Satisfy(SatisfyError::ImpossibleSatisfaction))
     }
 
     /// Converts keys from one type of public key to another.
     pub fn translate_pk<T>(
         &self,
         translate: &mut T,
-    ) -> Result<Tr<T::TargetPk>, TranslateErr<T::Error>>
+    ) -> Result<Tr<T::TargetPk, Ext>, TranslateErr<T::Error>>
     where
         T: Translator<Pk>,
     {
@@ -366,9 +452,22 @@ impl<Pk: MiniscriptKey> Tr<Pk> {
             Tr::new(translate.pk(&self.internal_key)?, tree).map_err(TranslateErr::OuterError)?;
         Ok(translate_desc)
     }
+
+    /// Substitutes all raw public key hashes in the tree leaves with the public keys
+    /// as provided by map, failing with the set of unresolved hashes if any are missing.
+    pub fn resolve_raw_pkh(
+        &self,
+        pk_map: &BTreeMap<hash160::Hash, Pk>,
+    ) -> Result<Tr<Pk, Ext>, BTreeSet<hash160::Hash>> {
+        let tree = match self.tree {
+            Some(ref tree) => Some(tree.resolve_raw_pkh(pk_map)?),
+            None => None,
+        };
+        Ok(Tr { internal_key: self.internal_key.clone(), tree, spend_info: Mutex::new(None) })
+    }
 }
 
-impl<Pk: MiniscriptKey + ToPublicKey> Tr<Pk> {
+impl<Pk: MiniscriptKey + ToPublicKey, Ext: Extension> Tr<Pk, Ext> {
     /// Obtains the corresponding script pubkey for this descriptor.
     pub fn script_pubkey(&self) -> ScriptBuf {
         let output_key = self.spend_info().output_key();
@@ -379,12 +478,36 @@ impl<Pk: MiniscriptKey + ToPublicKey> Tr<Pk> {
             .into_script()
     }
 
+    /// Computes the script pubkey like [`Tr::script_pubkey`], writing it
+    /// into `script` to reuse its allocation.
+    pub fn script_pubkey_into(&self, script: &mut ScriptBuf) {
+        let output_key = self.spend_info().output_key();
+        *script = bitcoin::blockdata::script::Builder::from(take_script_bytes(script))
+            .push_opcode(opcodes::all::OP_PUSHNUM_1)
+            .push_slice(output_key.serialize())
+            .into_script();
+    }
+
     /// Obtains the corresponding address for this descriptor.
     pub fn address(&self, network: Network) -> Address {
         let spend_info = self.spend_info();
         Address::p2tr_tweaked(spend_info.output_key(), network)
     }
 
+    /// Returns the hash of a leaf, given by its index in [`Tr::iter_scripts`],
+    /// and the hashes of its siblings from the leaf up to the merkle root, as
+    /// in its control block.
+    ///
+    /// Unlike [`Tr::spend_info`], this does not tweak the internal key, so it
+    /// needs no secp context. Returns `None` if there is no such leaf.
+    pub fn merkle_proof(&self, leaf: usize) -> Option<(TapLeafHash, TaprootMerkleBranch)> {
+        let mut proof = vec![];
+        let (_root, leaf_hash) =
+            merkle_proof_node(self.tree.as_ref()?, &mut Some(leaf), &mut proof);
+        let branch = TaprootMerkleBranch::try_from(proof).expect("tap trees are at most 128 deep");
+        leaf_hash.map(|leaf_hash| (leaf_hash, branch))
+    }
+
     /// Returns satisfying non-malleable witness and scriptSig with minimum
     /// weight to spend an output controlled by the given descriptor if it is
     /// possible to construct one using the `satisfier`.
@@ -398,7 +521,7 @@ impl<Pk: MiniscriptKey + ToPublicKey> Tr<Pk> {
         if let Witness::Stack(stack) = satisfaction.stack {
             Ok((stack, ScriptBuf::new()))
         } else {
-            Err(Error::CouldNotSatisfy)
+            Err(Error::Satisfy(SatisfyError::CouldNotSatisfy))
         }
     }
 
@@ -418,7 +541,7 @@ impl<Pk: MiniscriptKey + ToPublicKey> Tr<Pk> {
         if let Witness::Stack(stack) = satisfaction.stack {
             Ok((stack, ScriptBuf::new()))
         } else {
-            Err(Error::CouldNotSatisfy)
+            Err(Error::Satisfy(SatisfyError::CouldNotSatisfy))
         }
     }
 }
@@ -445,6 +568,45 @@ impl Tr<DefiniteDescriptorKey> {
     {
         best_tap_spend(self, provider, true /* allow_mall */)
     }
+
+    /// Returns the key spend satisfaction, if the provider can sign for the internal key
+    pub(crate) fn plan_key_spend_satisfaction<P>(
+        &self,
+        provider: &P,
+    ) -> Option<Satisfaction<Placeholder<DefiniteDescriptorKey>>>
+    where
+        P: AssetProvider<DefiniteDescriptorKey>,
+    {
+        tap_key_spend(self, provider)
+    }
+
+    /// Returns the cheapest non-malleable script spend satisfaction, ignoring the key spend path
+    pub(crate) fn plan_script_spend_satisfaction<P>(
+        &self,
+        provider: &P,
+    ) -> Satisfaction<Placeholder<DefiniteDescriptorKey>>
+    where
+        P: AssetProvider<DefiniteDescriptorKey>,
+    {
+        best_tap_script_spend(self, provider, false /* allow_mall */)
+    }
+
+    /// Returns the assets missing from `provider` for each candidate spending path
+    ///
+    /// The key spend path is listed alongside the paths of each script leaf.
+    pub fn missing_assets<P>(&self, provider: &P) -> MissingAssets<DefiniteDescriptorKey>
+    where
+        P: AssetProvider<DefiniteDescriptorKey>,
+    {
+        let key_spend = MissingAssets::requires(
+            MissingAsset::Signature(self.internal_key.clone()),
+            provider
+                .provider_lookup_tap_key_spend_sig(&self.internal_key)
+                .is_some(),
+        );
+        self.iter_scripts()
+            .fold(key_spend, |acc, (_depth, ms)| acc.or(ms.missing_assets(provider)))
+    }
 }
 
 /// Iterator for Taproot structures
@@ -460,20 +622,21 @@ impl Tr<DefiniteDescriptorKey> {
 /// would yield (2, A), (2, B), (2,C), (3, D), (3, E).
 ///
 #[derive(Debug, Clone)]
-pub struct TapTreeIter<'a, Pk: MiniscriptKey> {
-    stack: Vec<(u8, &'a TapTree<Pk>)>,
+pub struct TapTreeIter<'a, Pk: MiniscriptKey, Ext: Extension = NoExt> {
+    stack: Vec<(u8, &'a TapTree<Pk, Ext>)>,
 }
 
-impl<Pk: MiniscriptKey> TapTreeIter<'_, Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> TapTreeIter<'_, Pk, Ext> {
     /// Helper function to return an empty iterator from Descriptor::tap_tree_iter.
     pub(super) fn empty() -> Self { Self { stack: vec![] } }
 }
 
-impl<'a, Pk> Iterator for TapTreeIter<'a, Pk>
+impl<'a, Pk, Ext> Iterator for TapTreeIter<'a, Pk, Ext>
 where
     Pk: MiniscriptKey + 'a,
+    Ext: Extension + 'a,
 {
-    type Item = (u8, &'a Miniscript<Pk, Tap>);
+    type Item = (u8, &'a Miniscript<Pk, Tap, Ext>);
 
     fn next(&mut self) -> Option<Self::Item> {
         while let Some((depth, last)) = self.stack.pop() {
@@ -489,7 +652,22 @@ where
     }
 }
 
-impl<Pk: FromStrKey> core::str::FromStr for Tr<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> TreeLike for &TapTree<Pk, Ext> {
+    // Taproot trees are binary
+    type NaryChildren = core::convert::Infallible;
+
+    fn nary_len(tc: &Self::NaryChildren) -> usize { match *tc {} }
+    fn nary_index(tc: Self::NaryChildren, _: usize) -> Self { match tc {} }
+
+    fn as_node(&self) -> Tree<Self, Self::NaryChildren> {
+        match *self {
+            TapTree::Tree { ref left, ref right, height: _ } => Tree::Binary(left, right),
+            TapTree::Leaf(_) => Tree::Nullary,
+        }
+    }
+}
+
+impl<Pk: FromStrKey, Ext: Extension> core::str::FromStr for Tr<Pk, Ext> {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -498,22 +676,26 @@ impl<Pk: FromStrKey> core::str::FromStr for Tr<Pk> {
     }
 }
 
-impl<Pk: FromStrKey> crate::expression::FromTree for Tr<Pk> {
+impl<Pk: FromStrKey, Ext: Extension> crate::expression::FromTree for TapTree<Pk, Ext> {
     fn from_tree(root: expression::TreeIterItem) -> Result<Self, Error> {
         use crate::expression::{Parens, ParseTreeError};
 
-        struct TreeStack<'s, Pk: MiniscriptKey> {
-            inner: Vec<(expression::TreeIterItem<'s>, TapTree<Pk>)>,
+        struct TreeStack<'s, Pk: MiniscriptKey, Ext: Extension> {
+            inner: Vec<(Option<expression::TreeIterItem<'s>>, TapTree<Pk, Ext>)>,
         }
 
-        impl<'s, Pk: MiniscriptKey> TreeStack<'s, Pk> {
+        impl<'s, Pk: MiniscriptKey, Ext: Extension> TreeStack<'s, Pk, Ext> {
             fn new() -> Self { Self { inner: Vec::with_capacity(128) } }
 
-            fn push(&mut self, parent: expression::TreeIterItem<'s>, tree: TapTree<Pk>) {
+            fn push(
+                &mut self,
+                parent: Option<expression::TreeIterItem<'s>>,
+                tree: TapTree<Pk, Ext>,
+            ) {
                 let mut next_push = (parent, tree);
                 while let Some(top) = self.inner.pop() {
-                    if next_push.0.index() == top.0.index() {
-                        next_push.0 = top.0.parent().unwrap();
+                    if next_push.0.map(|p| p.index()) == top.0.map(|p| p.index()) {
+                        next_push.0 = top.0.and_then(|p| p.parent());
                         next_push.1 = TapTree::combine(top.1, next_push.1);
                     } else {
                         self.inner.push(top);
@@ -523,30 +705,14 @@ impl<Pk: FromStrKey> crate::expression::FromTree for Tr<Pk> {
                 self.inner.push(next_push);
             }
 
-            fn pop_final(&mut self) -> Option<TapTree<Pk>> {
+            fn pop_final(&mut self) -> TapTree<Pk, Ext> {
                 assert_eq!(self.inner.len(), 1);
-                self.inner.pop().map(|x| x.1)
+                self.inner.pop().unwrap().1
             }
         }
 
-        root.verify_toplevel("tr", 1..=2)
-            .map_err(From::from)
-            .map_err(Error::Parse)?;
-
-        let mut root_children = root.children();
-        let internal_key: Pk = root_children
-            .next()
-            .unwrap() // `verify_toplevel` above checked that first child existed
-            .verify_terminal("internal key")
-            .map_err(Error::Parse)?;
-
-        let tap_tree = match root_children.next() {
-            None => return Tr::new(internal_key, None),
-            Some(tree) => tree,
-        };
-
         let mut tree_stack = TreeStack::new();
-        let mut tap_tree_iter = tap_tree.pre_order_iter();
+        let mut tap_tree_iter = root.pre_order_iter();
         // while let construction needed because we modify the iterator inside the loop
         // (by calling skip_descendants to skip over the contents of the tapscripts).
         while let Some(node) = tap_tree_iter.next() {
@@ -567,15 +733,46 @@ impl<Pk: FromStrKey> crate::expression::FromTree for Tr<Pk> {
                     return Err(Error::NonTopLevel(format!("{:?}", script)));
                 };
 
-                tree_stack.push(node.parent().unwrap(), TapTree::Leaf(Arc::new(script)));
+                tree_stack.push(node.parent(), TapTree::Leaf(Arc::new(script)));
                 tap_tree_iter.skip_descendants();
             }
         }
-        Tr::new(internal_key, tree_stack.pop_final())
+        Ok(tree_stack.pop_final())
+    }
+}
+
+impl<Pk: FromStrKey, Ext: Extension> core::str::FromStr for TapTree<Pk, Ext> {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let expr_tree = expression::Tree::from_str(s)?;
+        Self::from_tree(expr_tree.root())
+    }
+}
+
+serde_string_impl_pk!(TapTree, "a taproot tree");
+
+impl<Pk: FromStrKey, Ext: Extension> crate::expression::FromTree for Tr<Pk, Ext> {
+    fn from_tree(root: expression::TreeIterItem) -> Result<Self, Error> {
+        root.verify_toplevel("tr", 1..=2)
+            .map_err(From::from)
+            .map_err(Error::Parse)?;
+
+        let mut root_children = root.children();
+        let internal_key: Pk = root_children
+            .next()
+            .unwrap() // `verify_toplevel` above checked that first child existed
+            .verify_terminal("internal key")
+            .map_err(Error::Parse)?;
+
+        match root_children.next() {
+            None => Tr::new(internal_key, None),
+            Some(tree) => Tr::new(internal_key, Some(TapTree::from_tree(tree)?)),
+        }
     }
 }
 
-impl<Pk: MiniscriptKey> fmt::Debug for Tr<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> fmt::Debug for Tr<Pk, Ext> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.tree {
             Some(ref s) => write!(f, "tr({:?},{:?})", self.internal_key, s),
@@ -584,7 +781,7 @@ impl<Pk: MiniscriptKey> fmt::Debug for Tr<Pk> {
     }
 }
 
-impl<Pk: MiniscriptKey> fmt::Display for Tr<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> fmt::Display for Tr<Pk, Ext> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use fmt::Write;
         let mut wrapped_f = checksum::Formatter::new(f);
@@ -625,7 +822,7 @@ impl<Pk: MiniscriptKey> Liftable<Pk> for Tr<Pk> {
     }
 }
 
-impl<Pk: MiniscriptKey> ForEachKey<Pk> for Tr<Pk> {
+impl<Pk: MiniscriptKey, Ext: Extension> ForEachKey<Pk> for Tr<Pk, Ext> {
     fn for_each_key<'a, F: FnMut(&'a Pk) -> bool>(&'a self, mut pred: F) -> bool {
         let script_keys_res = self
             .iter_scripts()
@@ -634,6 +831,34 @@ impl<Pk: MiniscriptKey> ForEachKey<Pk> for Tr<Pk> {
     }
 }
 
+// Computes the hash of a tap tree node. If the leaf with index `*leaf` in
+// the node is found, returns its hash and pushes the hashes of the siblings
+// on its path to `proof`, from the leaf up
+fn merkle_proof_node<Pk: ToPublicKey, Ext: Extension>(
+    tree: &TapTree<Pk, Ext>,
+    leaf: &mut Option<usize>,
+    proof: &mut Vec<TapNodeHash>,
+) -> (TapNodeHash, Option<TapLeafHash>) {
+    match *tree {
+        TapTree::Leaf(ref ms) => {
+            let hash = TapLeafHash::from_script(&ms.encode(), LeafVersion::TapScript);
+            let found = *leaf == Some(0);
+            *leaf = leaf.and_then(|n| n.checked_sub(1));
+            (TapNodeHash::from(hash), if found { Some(hash) } else { None })
+        }
+        TapTree::Tree { ref left, ref right, .. } => {
+            let (left, left_found) = merkle_proof_node(left, leaf, proof);
+            let (right, right_found) = merkle_proof_node(right, leaf, proof);
+            if left_found.is_some() {
+                proof.push(right);
+            } else if right_found.is_some() {
+                proof.push(left);
+            }
+            (TapNodeHash::from_node_hashes(left, right), left_found.or(right_found))
+        }
+    }
+}
+
 // Helper function to compute the len of control block at a given depth
 fn control_block_len(depth: u8) -> usize {
     TAPROOT_CONTROL_BASE_SIZE + (depth as usize) * TAPROOT_CONTROL_NODE_SIZE
@@ -641,74 +866,128 @@ fn control_block_len(depth: u8) -> usize {
 
 // Helper function to get a script spend satisfaction
 // try script spend
-fn best_tap_spend<Pk, P>(
-    desc: &Tr<Pk>,
+fn best_tap_spend<Pk, Ext, P>(
+    desc: &Tr<Pk, Ext>,
     provider: &P,
     allow_mall: bool,
 ) -> Satisfaction<Placeholder<Pk>>
 where
     Pk: ToPublicKey,
+    Ext: Extension,
     P: AssetProvider<Pk>,
 {
-    let spend_info = desc.spend_info();
     // First try the key spend path
-    if let Some(size) = provider.provider_lookup_tap_key_spend_sig(&desc.internal_key) {
-        Satisfaction {
-            stack: Witness::Stack(vec![Placeholder::SchnorrSigPk(
-                desc.internal_key.clone(),
-                SchnorrSigType::KeySpend { merkle_root: spend_info.merkle_root() },
-                size,
-            )]),
-            has_sig: true,
-            absolute_timelock: None,
-            relative_timelock: None,
-        }
-    } else {
-        // Since we have the complete descriptor we can ignore the satisfier. We don't use the control block
-        // map (lookup_control_block) from the satisfier here.
-        let mut min_satisfaction = Satisfaction {
-            stack: Witness::Unavailable,
-            has_sig: false,
-            relative_timelock: None,
-            absolute_timelock: None,
-        };
-        let mut min_wit_len = None;
-        for (_depth, ms) in desc.iter_scripts() {
-            let mut satisfaction = if allow_mall {
-                match ms.build_template(provider) {
-                    s @ Satisfaction { stack: Witness::Stack(_), .. } => s,
-                    _ => continue, // No witness for this script in tr descriptor, look for next one
-                }
-            } else {
-                match ms.build_template_mall(provider) {
-                    s @ Satisfaction { stack: Witness::Stack(_), .. } => s,
-                    _ => continue, // No witness for this script in tr descriptor, look for next one
-                }
-            };
-            let wit = match satisfaction {
-                Satisfaction { stack: Witness::Stack(ref mut wit), .. } => wit,
-                _ => unreachable!(),
-            };
+    tap_key_spend(desc, provider)
+        .unwrap_or_else(|| best_tap_script_spend(desc, provider, allow_mall))
+}
 
-            let leaf_script = (ms.encode(), LeafVersion::TapScript);
-            let control_block = spend_info
-                .control_block(&leaf_script)
-                .expect("Control block must exist in script map for every known leaf");
+/// Builds the template for the key spend path, or `None` if the provider can't sign for the
+/// internal key.
+fn tap_key_spend<Pk, Ext, P>(
+    desc: &Tr<Pk, Ext>,
+    provider: &P,
+) -> Option<Satisfaction<Placeholder<Pk>>>
+where
+    Pk: ToPublicKey,
+    Ext: Extension,
+    P: AssetProvider<Pk>,
+{
+    let size = provider.provider_lookup_tap_key_spend_sig(&desc.internal_key)?;
+    Some(Satisfaction {
+        stack: Witness::Stack(vec![Placeholder::SchnorrSigPk(
+            desc.internal_key.clone(),
+            SchnorrSigType::KeySpend { merkle_root: desc.spend_info().merkle_root() },
+            size,
+        )]),
+        has_sig: true,
+        absolute_timelock: None,
+        relative_timelock: None,
+    })
+}
 
-            wit.push(Placeholder::TapScript(leaf_script.0));
-            wit.push(Placeholder::TapControlBlock(control_block));
+/// Builds the template for the script leaf with the smallest witness.
+fn best_tap_script_spend<Pk, Ext, P>(
+    desc: &Tr<Pk, Ext>,
+    provider: &P,
+    allow_mall: bool,
+) -> Satisfaction<Placeholder<Pk>>
+where
+    Pk: ToPublicKey,
+    Ext: Extension,
+    P: AssetProvider<Pk>,
+{
+    let spend_info = desc.spend_info();
+    // Since we have the complete descriptor we can ignore the satisfier. We don't use the control block
+    // map (lookup_control_block) from the satisfier here.
+    min_witness_satisfaction(
+        desc.iter_scripts()
+            .filter_map(|(_depth, ms)| tap_leaf_spend(&spend_info, ms, provider, allow_mall)),
+    )
+}
 
-            let wit_size = witness_size(wit);
-            if min_wit_len.is_some() && Some(wit_size) > min_wit_len {
-                continue;
-            } else {
-                min_satisfaction = satisfaction;
-                min_wit_len = Some(wit_size);
-            }
-        }
+/// Picks the satisfaction with the smallest witness among the satisfactions of the leaves of a
+/// tree, in their DFS order. Ties go to the last leaf.
+pub(crate) fn min_witness_satisfaction<Pk, I>(satisfactions: I) -> Satisfaction<Placeholder<Pk>>
+where
+    Pk: ToPublicKey,
+    I: IntoIterator<Item = Satisfaction<Placeholder<Pk>>>,
+{
+    let mut min_satisfaction = Satisfaction {
+        stack: Witness::Unavailable,
+        has_sig: false,
+        relative_timelock: None,
+        absolute_timelock: None,
+    };
+    let mut min_wit_len = None;
+    for satisfaction in satisfactions {
+        let wit = match satisfaction {
+            Satisfaction { stack: Witness::Stack(ref wit), .. } => wit,
+            _ => unreachable!(),
+        };
 
-        min_satisfaction
+        let wit_size = witness_size(wit);
+        if min_wit_len.is_some() && Some(wit_size) > min_wit_len {
+            continue;
+        } else {
+            min_satisfaction = satisfaction;
+            min_wit_len = Some(wit_size);
+        }
     }
+
+    min_satisfaction
+}
+
+/// Builds the template for spending the leaf `ms` of a taproot output, including the
+/// leaf script and control block, or `None` if the leaf cannot be satisfied.
+pub(crate) fn tap_leaf_spend<Pk, Ext, P>(
+    spend_info: &TaprootSpendInfo,
+    ms: &Miniscript<Pk, Tap, Ext>,
+    provider: &P,
+    allow_mall: bool,
+) -> Option<Satisfaction<Placeholder<Pk>>>
+where
+    Pk: ToPublicKey,
+    Ext: Extension,
+    P: AssetProvider<Pk>,
+{
+    let mut satisfaction = if allow_mall {
+        ms.build_template(provider)
+    } else {
+        ms.build_template_mall(provider)
+    };
+    let wit = match satisfaction {
+        Satisfaction { stack: Witness::Stack(ref mut wit), .. } => wit,
+        _ => return None,
+    };
+
+    let leaf_script = (ms.encode(), LeafVersion::TapScript);
+    let control_block = spend_info
+        .control_block(&leaf_script)
+        .expect("Control block must exist in script map for every known leaf");
+
+    wit.push(Placeholder::TapScript(leaf_script.0));
+    wit.push(Placeholder::TapControlBlock(control_block));
+    Some(satisfaction)
 }
 
 #[cfg(test)]
@@ -730,7 +1009,7 @@ mod tests {
               )
             }
          })";
-        desc.replace(&[' ', '\n'][..], "")
+        expression::remove_whitespace_and_comments(desc)
     }
 
     #[test]
@@ -747,4 +1026,83 @@ mod tests {
         let tr = Tr::<String>::from_str(&desc).unwrap();
         assert_eq!(tr.tap_tree().as_ref().unwrap().height(), 2);
     }
+
+    #[test]
+    fn spend_info_with_secp() {
+        let key = "03a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd";
+        let desc = format!("tr({},{{pk({}),older(144)}})", key, key);
+        let secp = secp256k1::Secp256k1::verification_only();
+        let tr = Tr::<bitcoin::PublicKey>::from_str(&desc).unwrap();
+        let spend_info = tr.spend_info_with_secp(&secp);
+        assert_eq!(
+            *spend_info,
+            *Tr::<bitcoin::PublicKey>::from_str(&desc)
+                .unwrap()
+                .spend_info()
+        );
+        // The spend info is cached, whichever context computed it
+        assert!(Arc::ptr_eq(&spend_info, &tr.spend_info()));
+    }
+
+    #[test]
+    fn sanity_check() {
+        let check = |desc: &str| Tr::<String>::from_str(desc).unwrap().sanity_check();
+        check("tr(A,{pk(B),pk(C)})").unwrap();
+
+        assert!(matches!(
+            check("tr(A,{pk(B),{pk(C),pk(B)}})"),
+            Err(Error::AnalysisError(AnalysisError::DuplicateTapLeaf { first: 0, second: 2 }))
+        ));
+        assert!(matches!(
+            check("tr(A,{pk(B),and_v(v:pk(C),0)})"),
+            Err(Error::AnalysisError(AnalysisError::UnsatisfiableTapLeaf(1)))
+        ));
+        let keys: Vec<_> = (0..999).map(|i| format!("K{}", i)).collect();
+        assert!(matches!(
+            check(&format!("tr(A,{{pk(B),multi_a(1,{})}})", keys.join(","))),
+            Err(Error::AnalysisError(AnalysisError::TapLeafExceedsResourceLimits(1)))
+        ));
+    }
+
+    #[test]
+    fn merkle_proof() {
+        let key = "03a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd";
+        let desc = format!(
+            "tr({},{{pk({}),{{older(144),{{after(100),sha256({})}}}}}})",
+            key,
+            key,
+            "a".repeat(64)
+        );
+        let tr = Tr::<bitcoin::PublicKey>::from_str(&desc).unwrap();
+        let spend_info = tr.spend_info();
+        for (leaf, (_depth, ms)) in tr.iter_scripts().enumerate() {
+            let (leaf_hash, branch) = tr.merkle_proof(leaf).unwrap();
+            assert_eq!(leaf_hash, TapLeafHash::from_script(&ms.encode(), LeafVersion::TapScript));
+            let control_block = spend_info
+                .control_block(&(ms.encode(), LeafVersion::TapScript))
+                .unwrap();
+            assert_eq!(branch, control_block.merkle_branch);
+        }
+        assert_eq!(tr.merkle_proof(4), None);
+        let tr = Tr::<bitcoin::PublicKey>::from_str(&format!("tr({})", key)).unwrap();
+        assert_eq!(tr.merkle_proof(0), None);
+    }
+
+    #[test]
+    fn tap_tree_like() {
+        let tr = Tr::<String>::from_str("tr(A,{pk(B),{pk(C),pk(D)}})").unwrap();
+        let tree = tr.tap_tree().as_ref().unwrap();
+        assert_eq!(tree.pre_order_iter().count(), 5);
+
+        let leaves: Vec<_> = tree
+            .post_order_iter()
+            .filter_map(|item| match *item.node {
+                TapTree::Leaf(ref ms) => Some(ms.to_string()),
+                TapTree::Tree { .. } => None,
+            })
+            .collect();
+        assert_eq!(leaves, ["pk(B)", "pk(C)", "pk(D)"]);
+        let root = tree.post_order_iter().last().unwrap();
+        assert_eq!(root.child_indices, [0, 3]);
+    }
 }
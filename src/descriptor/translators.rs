@@ -0,0 +1,219 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Common key translators
+//!
+//! Ready-made [`Translator`]s for transformations of the keys of descriptors
+//! and policies that are often needed, to be used with `translate_pk`.
+
+use core::convert::Infallible;
+
+use bitcoin::bip32;
+use bitcoin::hashes::{hash160, Hash};
+use bitcoin::secp256k1::XOnlyPublicKey;
+
+use super::{DescriptorPublicKey, SinglePubKey};
+use crate::prelude::*;
+use crate::{MiniscriptKey, Translator};
+
+// The origin of a descriptor key
+fn origin_mut(
+    key: &mut DescriptorPublicKey,
+) -> &mut Option<(bip32::Fingerprint, bip32::DerivationPath)> {
+    match *key {
+        DescriptorPublicKey::Single(ref mut single) => &mut single.origin,
+        DescriptorPublicKey::XPub(ref mut xpub) => &mut xpub.origin,
+        DescriptorPublicKey::MultiXPub(ref mut xpub) => &mut xpub.origin,
+    }
+}
+
+/// Translator removing the origin information of descriptor keys
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OriginStripper;
+
+impl Translator<DescriptorPublicKey> for OriginStripper {
+    type TargetPk = DescriptorPublicKey;
+    type Error = Infallible;
+
+    fn pk(&mut self, pk: &DescriptorPublicKey) -> Result<DescriptorPublicKey, Infallible> {
+        let mut pk = pk.clone();
+        *origin_mut(&mut pk) = None;
+        Ok(pk)
+    }
+
+    translate_hash_clone!(DescriptorPublicKey, DescriptorPublicKey, Infallible);
+}
+
+/// Translator replacing the master fingerprints in the origins of descriptor
+/// keys
+///
+/// Keys without an origin, or with a fingerprint which is not in the map, are
+/// kept as they are.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OriginRemapper {
+    fingerprints: BTreeMap<bip32::Fingerprint, bip32::Fingerprint>,
+}
+
+impl OriginRemapper {
+    /// Creates a translator replacing each fingerprint of the map by the
+    /// fingerprint it maps to.
+    pub fn new(fingerprints: BTreeMap<bip32::Fingerprint, bip32::Fingerprint>) -> Self {
+        OriginRemapper { fingerprints }
+    }
+}
+
+impl Translator<DescriptorPublicKey> for OriginRemapper {
+    type TargetPk = DescriptorPublicKey;
+    type Error = Infallible;
+
+    fn pk(&mut self, pk: &DescriptorPublicKey) -> Result<DescriptorPublicKey, Infallible> {
+        let mut pk = pk.clone();
+        if let Some((ref mut fingerprint, _)) = *origin_mut(&mut pk) {
+            if let Some(remapped) = self.fingerprints.get(fingerprint) {
+                *fingerprint = *remapped;
+            }
+        }
+        Ok(pk)
+    }
+
+    translate_hash_clone!(DescriptorPublicKey, DescriptorPublicKey, Infallible);
+}
+
+/// Translator converting full public keys to x-only keys
+///
+/// X-only keys are only legal in Taproot, so translating anything else fails
+/// the context checks of `translate_pk`. Extended keys of descriptors are kept
+/// as they are.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct XOnlyConverter;
+
+impl Translator<bitcoin::PublicKey> for XOnlyConverter {
+    type TargetPk = XOnlyPublicKey;
+    type Error = Infallible;
+
+    fn pk(&mut self, pk: &bitcoin::PublicKey) -> Result<XOnlyPublicKey, Infallible> {
+        Ok(XOnlyPublicKey::from(pk.inner))
+    }
+
+    translate_hash_clone!(bitcoin::PublicKey, XOnlyPublicKey, Infallible);
+}
+
+impl Translator<DescriptorPublicKey> for XOnlyConverter {
+    type TargetPk = DescriptorPublicKey;
+    type Error = Infallible;
+
+    fn pk(&mut self, pk: &DescriptorPublicKey) -> Result<DescriptorPublicKey, Infallible> {
+        let mut pk = pk.clone();
+        if let DescriptorPublicKey::Single(ref mut single) = pk {
+            if let SinglePubKey::FullKey(key) = single.key {
+                single.key = SinglePubKey::XOnly(XOnlyPublicKey::from(key.inner));
+            }
+        }
+        Ok(pk)
+    }
+
+    translate_hash_clone!(DescriptorPublicKey, DescriptorPublicKey, Infallible);
+}
+
+/// Translator replacing keys by the hex of the HASH160 of their string form
+///
+/// This keeps the structure of a descriptor, including which keys are used
+/// several times, while not revealing the keys themselves. Hashes are kept as
+/// they are.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KeyHasher;
+
+impl<Pk: MiniscriptKey> Translator<Pk> for KeyHasher {
+    type TargetPk = String;
+    type Error = Infallible;
+
+    fn pk(&mut self, pk: &Pk) -> Result<String, Infallible> {
+        Ok(hash160::Hash::hash(pk.to_string().as_bytes()).to_string())
+    }
+
+    fn sha256(&mut self, sha256: &Pk::Sha256) -> Result<String, Infallible> {
+        Ok(sha256.to_string())
+    }
+
+    fn hash256(&mut self, hash256: &Pk::Hash256) -> Result<String, Infallible> {
+        Ok(hash256.to_string())
+    }
+
+    fn ripemd160(&mut self, ripemd160: &Pk::Ripemd160) -> Result<String, Infallible> {
+        Ok(ripemd160.to_string())
+    }
+
+    fn hash160(&mut self, hash160: &Pk::Hash160) -> Result<String, Infallible> {
+        Ok(hash160.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+    use crate::Descriptor;
+
+    #[test]
+    fn translators() {
+        let xpub = "xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL";
+        let key = "03a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd";
+        let xonly_key = &key[2..];
+        let desc = Descriptor::<DescriptorPublicKey>::from_str(&format!(
+            "tr([d34db33f/86'/0'/0']{}/0/*,pk([c0ffee00/0']{}))",
+            xpub, key
+        ))
+        .unwrap();
+
+        let stripped = desc.translate_pk(&mut OriginStripper).unwrap();
+        assert_eq!(
+            stripped,
+            Descriptor::from_str(&format!("tr({}/0/*,pk({}))", xpub, key)).unwrap()
+        );
+
+        let mut remapper = OriginRemapper::new(
+            vec![(
+                bip32::Fingerprint::from_str("d34db33f").unwrap(),
+                bip32::Fingerprint::from_str("deadbeef").unwrap(),
+            )]
+            .into_iter()
+            .collect(),
+        );
+        assert_eq!(
+            desc.translate_pk(&mut remapper).unwrap(),
+            Descriptor::from_str(&format!(
+                "tr([deadbeef/86'/0'/0']{}/0/*,pk([c0ffee00/0']{}))",
+                xpub, key
+            ))
+            .unwrap()
+        );
+
+        let xonly = desc.translate_pk(&mut XOnlyConverter).unwrap();
+        assert_eq!(
+            xonly,
+            Descriptor::from_str(&format!(
+                "tr([d34db33f/86'/0'/0']{}/0/*,pk([c0ffee00/0']{}))",
+                xpub, xonly_key
+            ))
+            .unwrap()
+        );
+        // X-only keys are not allowed outside of Taproot
+        let wsh =
+            Descriptor::<DescriptorPublicKey>::from_str(&format!("wsh(pk({}))", key)).unwrap();
+        assert!(wsh.translate_pk(&mut XOnlyConverter).is_err());
+        let concrete = Descriptor::<bitcoin::PublicKey>::from_str(&format!("tr({})", key)).unwrap();
+        assert_eq!(
+            concrete.translate_pk(&mut XOnlyConverter).unwrap(),
+            Descriptor::<XOnlyPublicKey>::from_str(&format!("tr({})", xonly_key)).unwrap()
+        );
+
+        let hashed = wsh.translate_pk(&mut KeyHasher).unwrap();
+        let key_hash = hash160::Hash::hash(key.as_bytes()).to_string();
+        assert_eq!(
+            hashed.to_string(),
+            Descriptor::<String>::from_str(&format!("wsh(pk({}))", key_hash))
+                .unwrap()
+                .to_string()
+        );
+    }
+}
@@ -12,9 +12,55 @@ use crate::primitives::absolute_locktime::AbsLockTimeError;
 use crate::primitives::relative_locktime::RelLockTimeError;
 use crate::Box;
 
+/// The class of failure of an [`Error`](crate::Error)
+///
+/// Lets callers tell failures apart without matching on every variant of
+/// the error, or comparing error strings. Some classes have their own error
+/// type, reachable through an accessor of [`Error`](crate::Error): parsing
+/// has [`ParseError`], type checking [`types::Error`](crate::miniscript::types::Error)
+/// and satisfaction [`SatisfyError`]. Planning and PSBT operations do not
+/// return [`Error`](crate::Error) but errors of their own modules, such as
+/// [`MissingAssets`](crate::plan::MissingAssets) and [`psbt::Error`](crate::psbt::Error).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// A string or script could not be parsed
+    Parse,
+    /// A Miniscript does not type check
+    Type,
+    /// A Miniscript is not valid, or not sane, in its script context
+    Context,
+    /// A Miniscript could not be satisfied
+    Satisfy,
+    /// A policy could not be compiled, lifted or is invalid
+    Policy,
+    /// An address could not be computed
+    Address,
+    /// The operation is not supported by the kind of descriptor
+    Unsupported,
+    /// A cryptographic operation failed
+    Crypto,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            ErrorKind::Parse => "parse error",
+            ErrorKind::Type => "type error",
+            ErrorKind::Context => "script context error",
+            ErrorKind::Satisfy => "satisfaction error",
+            ErrorKind::Policy => "policy error",
+            ErrorKind::Address => "address error",
+            ErrorKind::Unsupported => "unsupported operation",
+            ErrorKind::Crypto => "cryptographic error",
+        })
+    }
+}
+
 /// An error parsing a Miniscript object (policy, descriptor or miniscript)
 /// from a string.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum ParseError {
     /// Invalid absolute locktime
     AbsoluteLockTime(AbsLockTimeError),
@@ -38,6 +84,39 @@ impl ParseError {
     }
 }
 
+/// An error satisfying a Miniscript object (miniscript or descriptor).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SatisfyError {
+    /// Could not satisfy a script (fragment) because of a missing signature
+    MissingSig(bitcoin::PublicKey),
+    /// General failure to satisfy
+    CouldNotSatisfy,
+    /// Miniscript is equivalent to false. No possible satisfaction
+    ImpossibleSatisfaction,
+}
+
+impl fmt::Display for SatisfyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SatisfyError::MissingSig(ref pk) => write!(f, "missing signature for key {:?}", pk),
+            SatisfyError::CouldNotSatisfy => f.write_str("could not satisfy"),
+            SatisfyError::ImpossibleSatisfaction => f.write_str("Impossible to satisfy Miniscript"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for SatisfyError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            SatisfyError::MissingSig(_)
+            | SatisfyError::CouldNotSatisfy
+            | SatisfyError::ImpossibleSatisfaction => None,
+        }
+    }
+}
+
 impl From<crate::ParseNumError> for ParseError {
     fn from(e: crate::ParseNumError) -> Self { Self::Num(e) }
 }
@@ -235,6 +235,8 @@ pub enum ParseThresholdError {
     ParseK(ParseNumError),
     /// Threshold parameters were invalid.
     Threshold(ThresholdError),
+    /// A weighted threshold expanded to more than the given number of leaves.
+    WeightedTooLarge(usize),
 }
 
 impl fmt::Display for ParseThresholdError {
@@ -252,6 +254,9 @@ impl fmt::Display for ParseThresholdError {
             ),
             ParseK(ref x) => write!(f, "failed to parse threshold value: {}", x),
             Threshold(ref e) => e.fmt(f),
+            WeightedTooLarge(max) => {
+                write!(f, "weighted threshold expands to more than {} leaves", max)
+            }
         }
     }
 }
@@ -262,7 +267,7 @@ impl std::error::Error for ParseThresholdError {
         use ParseThresholdError::*;
 
         match *self {
-            NoChildren | KNotTerminal | IllegalOr | IllegalAnd => None,
+            NoChildren | KNotTerminal | IllegalOr | IllegalAnd | WeightedTooLarge(..) => None,
             ParseK(ref e) => Some(e),
             Threshold(ref e) => Some(e),
         }
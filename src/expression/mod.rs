@@ -32,7 +32,7 @@ use core::str::FromStr;
 
 pub use self::error::{ParseNumError, ParseThresholdError, ParseTreeError};
 use crate::blanket_traits::StaticDebugAndDisplay;
-use crate::descriptor::checksum::verify_checksum;
+use crate::descriptor::checksum::{is_checksum_like, verify_checksum};
 use crate::prelude::*;
 use crate::{AbsLockTime, Error, ParseError, RelLockTime, Threshold, MAX_RECURSION_DEPTH};
 
@@ -678,6 +678,48 @@ impl<'a> Tree<'a> {
     }
 }
 
+/// Removes the whitespace and comments of a multi-line expression.
+///
+/// Comments start with a `#` and run to the end of the line. The last token
+/// of the expression is a checksum rather than a comment, and is kept so that
+/// checksums are never silently dropped, if it is a `#` directly following the
+/// expression, with no whitespace in between, and directly followed by eight
+/// characters of the checksum charset and then only by whitespace or a comment.
+pub fn remove_whitespace_and_comments(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len());
+    let mut checksum = None;
+    for line in s.lines() {
+        let (content, comment) = match line.find('#') {
+            Some(pos) => (&line[..pos], Some(&line[pos + 1..])),
+            None => (line, None),
+        };
+        let attached = content
+            .chars()
+            .next_back()
+            .map_or(false, |ch| !ch.is_whitespace());
+        let len = ret.len();
+        ret.extend(content.chars().filter(|ch| !ch.is_whitespace()));
+        if ret.len() > len {
+            checksum = None;
+        }
+        if let (true, Some(comment)) = (attached, comment) {
+            let end = comment
+                .find(|ch: char| ch.is_whitespace() || ch == '#')
+                .unwrap_or(comment.len());
+            let (word, rest) = comment.split_at(end);
+            let rest = rest.trim_start();
+            if is_checksum_like(word) && (rest.is_empty() || rest.starts_with('#')) {
+                checksum = Some(word);
+            }
+        }
+    }
+    if let Some(checksum) = checksum {
+        ret.push('#');
+        ret.push_str(checksum);
+    }
+    ret
+}
+
 /// Parse a string as a u32, for timelocks or thresholds
 pub fn parse_num(s: &str) -> Result<u32, ParseNumError> {
     if s == "0" {
@@ -913,4 +955,30 @@ mod tests {
                 .into_tree()
         );
     }
+
+    #[test]
+    fn whitespace_and_comments() {
+        assert_eq!(remove_whitespace_and_comments("pk( A )"), "pk(A)");
+        assert_eq!(
+            remove_whitespace_and_comments("or_i(\n  pk(A), # key\n  pk(B)\n)"),
+            "or_i(pk(A),pk(B))"
+        );
+        assert_eq!(remove_whitespace_and_comments("# only a comment"), "");
+        // Checksums are kept when they end the expression
+        assert_eq!(remove_whitespace_and_comments("pk(A)#acdefghj"), "pk(A)#acdefghj");
+        assert_eq!(remove_whitespace_and_comments("pk(A)#acdefghj # key"), "pk(A)#acdefghj");
+        assert_eq!(remove_whitespace_and_comments("pk(\n  A\n)#acdefghj\n"), "pk(A)#acdefghj");
+        // Anything else after a `#` is a comment, including checksum-like
+        // words separated from the expression
+        assert_eq!(remove_whitespace_and_comments("pk(A) #treasury"), "pk(A)");
+        assert_eq!(remove_whitespace_and_comments("pk(A)\n#acdefghj\n"), "pk(A)");
+        assert_eq!(remove_whitespace_and_comments("pk(A) #acdefghj pk(B)"), "pk(A)");
+        assert_eq!(
+            remove_whitespace_and_comments("and_v(v:pk(A), #treasury\npk(B))"),
+            "and_v(v:pk(A),pk(B))"
+        );
+        assert_eq!(remove_whitespace_and_comments("pk(A) #fallback"), "pk(A)");
+        assert_eq!(remove_whitespace_and_comments("pk(A) #ACDEFGHJ"), "pk(A)");
+        assert_eq!(remove_whitespace_and_comments("pk(A) #acdefghji"), "pk(A)");
+    }
 }
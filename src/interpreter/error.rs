@@ -12,6 +12,7 @@ use bitcoin::secp256k1;
 use bitcoin::{absolute, relative, taproot};
 
 use super::BitcoinKey;
+use crate::miniscript::limits::{MAX_OPS_PER_SCRIPT, MAX_SCRIPT_ELEMENT_SIZE, MAX_STACK_SIZE};
 use crate::prelude::*;
 
 /// Detailed Error type for Interpreter
@@ -56,6 +57,15 @@ pub enum Error {
     InvalidSchnorrSignature(bitcoin::key::XOnlyPublicKey),
     /// Last byte of this signature isn't a standard sighash type
     NonStandardSighash(Vec<u8>),
+    /// A script which is not Miniscript executes more non-push opcodes than
+    /// consensus allows
+    MaxOpCountExceeded,
+    /// A script which is not Miniscript pushes, or is given, an element of
+    /// this size, which exceeds the consensus limit
+    MaxPushSizeExceeded(usize),
+    /// A script which is not Miniscript grows its stack and altstack beyond
+    /// the consensus limit
+    MaxStackSizeExceeded,
     /// Miniscript error
     Miniscript(crate::Error),
     /// MultiSig requires 1 extra zero element apart from the `k` signatures
@@ -79,6 +89,9 @@ pub enum Error {
     /// The Public Key hash check for the given pubkey. This occurs in `PkH`
     /// node when the given key does not match to Hash in script.
     PkHashVerifyFail(hash160::Hash),
+    /// The number of outputs spent, second, differs from the number of
+    /// transaction inputs, first
+    PrevoutCountMismatch(usize, usize),
     /// Parse Error while parsing a `stack::Element::Push` as a Pubkey. Both
     /// 33 byte and 65 bytes are supported.
     PubkeyParseError,
@@ -90,14 +103,20 @@ pub enum Error {
     RelativeLockTimeDisabled(relative::LockTime),
     /// Forward-secp related errors
     Secp(secp256k1::Error),
+    /// Error decoding a script which is not Miniscript, or one of its numbers
+    Script(bitcoin::blockdata::script::Error),
     /// Miniscript requires the entire top level script to be satisfied.
     ScriptSatisfactionError,
+    /// A script which is not Miniscript failed when executing this opcode
+    ScriptFailed(bitcoin::Opcode),
     /// Schnorr Signature error
     SchnorrSig(bitcoin::taproot::SigFromSliceError),
     /// Errors in signature hash calculations
     SighashError(bitcoin::sighash::InvalidSighashTypeError),
-    /// Taproot Annex Unsupported
+    /// Taproot Annex Unsupported in script spends
     TapAnnexUnsupported,
+    /// A script which is not Miniscript has unbalanced IF/ELSE/ENDIF opcodes
+    UnbalancedConditional,
     /// An uncompressed public key was encountered in a context where it is
     /// disallowed (e.g. in a Segwit script or p2wpkh output)
     UncompressedPubkey,
@@ -110,6 +129,8 @@ pub enum Error {
     /// was expecting a stack boolean `stack::Element::Satisfied` or
     /// `stack::Element::Dissatisfied`
     UnexpectedStackElementPush,
+    /// A script which is not Miniscript uses an opcode the interpreter cannot execute
+    UnsupportedOpcode(bitcoin::Opcode),
     /// Verify expects stack top element exactly to be `stack::Element::Satisfied`.
     /// This error is raised even if the stack top is `stack::Element::Push`.
     VerifyFailed,
@@ -152,6 +173,17 @@ impl fmt::Display for Error {
             }
             Error::NonEmptyWitness => f.write_str("legacy spend had nonempty witness"),
             Error::NonEmptyScriptSig => f.write_str("segwit spend had nonempty scriptsig"),
+            Error::MaxOpCountExceeded => {
+                write!(f, "script executes more than {} opcodes", MAX_OPS_PER_SCRIPT)
+            }
+            Error::MaxPushSizeExceeded(size) => write!(
+                f,
+                "script element of {} bytes exceeds the maximum of {}",
+                size, MAX_SCRIPT_ELEMENT_SIZE
+            ),
+            Error::MaxStackSizeExceeded => {
+                write!(f, "script stack exceeds the maximum of {} elements", MAX_STACK_SIZE)
+            }
             Error::Miniscript(ref e) => write!(f, "parse error: {}", e),
             Error::MissingExtraZeroMultiSig => f.write_str("CMS missing extra zero"),
             Error::MultiSigEvaluationError => {
@@ -159,6 +191,9 @@ impl fmt::Display for Error {
             }
             Error::PkEvaluationError(ref key) => write!(f, "Incorrect Signature for pk {}", key),
             Error::PkHashVerifyFail(ref hash) => write!(f, "Pubkey Hash check failed {}", hash),
+            Error::PrevoutCountMismatch(inputs, prevouts) => {
+                write!(f, "transaction has {} inputs but {} prevouts were given", inputs, prevouts)
+            }
             Error::PubkeyParseError => f.write_str("could not parse pubkey"),
             Error::XOnlyPublicKeyParseError => f.write_str("could not parse x-only pubkey"),
             Error::RelativeLockTimeNotMet(n) => {
@@ -167,11 +202,14 @@ impl fmt::Display for Error {
             Error::RelativeLockTimeDisabled(n) => {
                 write!(f, "required relative locktime CSV of {} blocks, but tx sequence number has disable-flag set", n)
             }
+            Error::Script(ref e) => write!(f, "script error: {}", e),
             Error::ScriptSatisfactionError => f.write_str("Top level script must be satisfied"),
+            Error::ScriptFailed(op) => write!(f, "script failed at {}", op),
             Error::Secp(ref e) => fmt::Display::fmt(e, f),
             Error::SchnorrSig(ref s) => write!(f, "Schnorr sig error: {}", s),
             Error::SighashError(ref e) => fmt::Display::fmt(e, f),
-            Error::TapAnnexUnsupported => f.write_str("Encountered annex element"),
+            Error::TapAnnexUnsupported => f.write_str("Encountered annex element in script spend"),
+            Error::UnbalancedConditional => f.write_str("unbalanced conditional in script"),
             Error::UncompressedPubkey => {
                 f.write_str("uncompressed pubkey in non-legacy descriptor")
             }
@@ -180,6 +218,7 @@ impl fmt::Display for Error {
             }
             Error::UnexpectedStackElementPush => write!(f, "Got {}, expected Stack Boolean", 1),
             Error::UnexpectedStackEnd => f.write_str("unexpected end of stack"),
+            Error::UnsupportedOpcode(op) => write!(f, "unsupported opcode {} in script", op),
             Error::VerifyFailed => {
                 f.write_str("Expected Satisfied Boolean at stack top for VERIFY")
             }
@@ -189,7 +228,7 @@ impl fmt::Display for Error {
 
 #[cfg(feature = "std")]
 impl error::Error for Error {
-    fn cause(&self) -> Option<&dyn error::Error> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         use self::Error::*;
 
         match self {
@@ -208,11 +247,15 @@ impl error::Error for Error {
             | InvalidEcdsaSignature(_)
             | InvalidSchnorrSignature(_)
             | InvalidSchnorrSighashType(_)
+            | MaxOpCountExceeded
+            | MaxPushSizeExceeded(_)
+            | MaxStackSizeExceeded
             | NonStandardSighash(_)
             | MissingExtraZeroMultiSig
             | MultiSigEvaluationError
             | NonEmptyWitness
             | NonEmptyScriptSig
+            | PrevoutCountMismatch(_, _)
             | PubkeyParseError
             | XOnlyPublicKeyParseError
             | PkEvaluationError(_)
@@ -220,15 +263,19 @@ impl error::Error for Error {
             | RelativeLockTimeNotMet(_)
             | RelativeLockTimeDisabled(_)
             | ScriptSatisfactionError
+            | ScriptFailed(_)
             | TapAnnexUnsupported
+            | UnbalancedConditional
             | UncompressedPubkey
             | UnexpectedStackBoolean
             | UnexpectedStackEnd
             | UnexpectedStackElementPush
+            | UnsupportedOpcode(_)
             | VerifyFailed => None,
             ControlBlockParse(e) => Some(e),
             EcdsaSig(e) => Some(e),
             Miniscript(e) => Some(e),
+            Script(e) => Some(e),
             Secp(e) => Some(e),
             SchnorrSig(e) => Some(e),
             SighashError(e) => Some(e),
@@ -236,6 +283,49 @@ impl error::Error for Error {
     }
 }
 
+/// A failed evaluation, along with the state of the interpreter when it failed
+#[derive(Debug)]
+pub struct Trace {
+    /// The error evaluation failed with
+    pub error: Error,
+    /// The Miniscript fragment being evaluated when evaluation failed, or the
+    /// last opcode executed for scripts which are not Miniscript
+    pub fragment: Option<String>,
+    /// The witness elements consumed by evaluation, in the order they were consumed
+    pub consumed: Vec<Vec<u8>>,
+    /// The stack when evaluation failed, bottom first
+    pub stack: Vec<Vec<u8>>,
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn write_elems(f: &mut fmt::Formatter, elems: &[Vec<u8>]) -> fmt::Result {
+            for elem in elems {
+                if elem.is_empty() {
+                    f.write_str("\n    <empty>")?;
+                } else {
+                    write!(f, "\n    {:x}", elem.as_hex())?;
+                }
+            }
+            Ok(())
+        }
+
+        fmt::Display::fmt(&self.error, f)?;
+        if let Some(ref fragment) = self.fragment {
+            write!(f, "\n  while evaluating {}", fragment)?;
+        }
+        f.write_str("\n  consumed witness elements, first consumed first:")?;
+        write_elems(f, &self.consumed)?;
+        f.write_str("\n  stack at failure, top last:")?;
+        write_elems(f, &self.stack)
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for Trace {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> { Some(&self.error) }
+}
+
 #[doc(hidden)]
 impl From<secp256k1::Error> for Error {
     fn from(e: secp256k1::Error) -> Error { Error::Secp(e) }
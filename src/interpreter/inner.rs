@@ -51,6 +51,31 @@ fn script_from_stack_elem<Ctx: ScriptContext>(
     }
 }
 
+// Parse the script as Miniscript like `script_from_stack_elem`, falling back to
+// the raw script when it is not Miniscript and the `raw-script` feature is
+// enabled, so that historical or exotic scripts can still be executed opcode
+// by opcode. Also returns the script itself, re-encoded in the Miniscript case.
+fn inner_from_stack_elem<Ctx: ScriptContext>(
+    elem: &stack::Element<'_>,
+    script_type: ScriptType,
+) -> Result<(Inner, bitcoin::ScriptBuf), Error>
+where
+    Miniscript<Ctx::Key, Ctx>: ToNoChecks,
+{
+    match script_from_stack_elem::<Ctx>(elem) {
+        Ok(ms) => {
+            let script = ms.encode();
+            Ok((Inner::Script(ms.to_no_checks_ms(), script_type), script))
+        }
+        #[cfg(feature = "raw-script")]
+        Err(Error::Miniscript(_)) => {
+            let script = bitcoin::ScriptBuf::from_bytes(elem.as_push()?.to_vec());
+            Ok((Inner::Raw(script.clone(), script_type), script))
+        }
+        Err(e) => Err(e),
+    }
+}
+
 /// Helper type to indicate the origin of the bare pubkey that the interpereter uses
 #[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
 pub enum PubkeyType {
@@ -81,6 +106,10 @@ pub(super) enum Inner {
     PublicKey(super::BitcoinKey, PubkeyType),
     /// The script being evaluated is an actual script
     Script(Miniscript<super::BitcoinKey, NoChecks>, ScriptType),
+    /// The script being evaluated is not Miniscript, and is executed opcode
+    /// by opcode instead
+    #[cfg_attr(not(feature = "raw-script"), allow(dead_code))]
+    Raw(bitcoin::ScriptBuf, ScriptType),
 }
 
 // The `Script` returned by this method is always generated/cloned ... when
@@ -173,12 +202,11 @@ pub(super) fn from_txdata<'txin>(
         } else {
             match wit_stack.pop() {
                 Some(elem) => {
-                    let miniscript = script_from_stack_elem::<Segwitv0>(&elem)?;
-                    let script = miniscript.encode();
-                    let miniscript = miniscript.to_no_checks_ms();
+                    let (inner, script) =
+                        inner_from_stack_elem::<Segwitv0>(&elem, ScriptType::Wsh)?;
                     let scripthash = sha256::Hash::hash(script.as_bytes());
                     if *spk == bitcoin::ScriptBuf::new_p2wsh(&scripthash.into()) {
-                        Ok((Inner::Script(miniscript, ScriptType::Wsh), wit_stack, Some(script)))
+                        Ok((inner, wit_stack, Some(script)))
                     } else {
                         Err(Error::IncorrectWScriptHash)
                     }
@@ -201,9 +229,12 @@ pub(super) fn from_txdata<'txin>(
             let has_annex = has_annex && (wit_stack.len() >= 2);
             if has_annex {
                 // Annex is non-standard, bitcoin consensus rules ignore it.
-                // Our sighash structure and signature verification
-                // does not support annex, return error
-                return Err(Error::TapAnnexUnsupported);
+                // Key spends only need it for their sighash, which the
+                // interpreter takes care of, but script spends are not supported.
+                if wit_stack.len() > 2 {
+                    return Err(Error::TapAnnexUnsupported);
+                }
+                wit_stack.pop();
             }
             match wit_stack.len() {
                 0 => Err(Error::UnexpectedStackEnd),
@@ -219,14 +250,13 @@ pub(super) fn from_txdata<'txin>(
                     let tap_script = wit_stack.pop().ok_or(Error::UnexpectedStackEnd)?;
                     let ctrl_blk =
                         ControlBlock::decode(ctrl_blk).map_err(Error::ControlBlockParse)?;
-                    let tap_script = script_from_stack_elem::<Tap>(&tap_script)?;
-                    let ms = tap_script.to_no_checks_ms();
+                    let (inner, tap_script) =
+                        inner_from_stack_elem::<Tap>(&tap_script, ScriptType::Tr)?;
                     // Creating new contexts is cheap
                     let secp = bitcoin::secp256k1::Secp256k1::verification_only();
-                    let tap_script = tap_script.encode();
                     if ctrl_blk.verify_taproot_commitment(&secp, output_key, &tap_script) {
                         Ok((
-                            Inner::Script(ms, ScriptType::Tr),
+                            inner,
                             wit_stack,
                             // Tapscript is returned as a "scriptcode". This is a hack, but avoids adding yet
                             // another enum just for taproot, and this function is not a publicly exposed API,
@@ -284,19 +314,16 @@ pub(super) fn from_txdata<'txin>(
                                     Err(Error::NonEmptyScriptSig)
                                 } else {
                                     // parse wsh with Segwitv0 context
-                                    let miniscript = script_from_stack_elem::<Segwitv0>(&elem)?;
-                                    let script = miniscript.encode();
-                                    let miniscript = miniscript.to_no_checks_ms();
+                                    let (inner, script) = inner_from_stack_elem::<Segwitv0>(
+                                        &elem,
+                                        ScriptType::ShWsh,
+                                    )?;
                                     let scripthash = sha256::Hash::hash(script.as_bytes());
                                     if slice
                                         == bitcoin::ScriptBuf::new_p2wsh(&scripthash.into())
                                             .as_bytes()
                                     {
-                                        Ok((
-                                            Inner::Script(miniscript, ScriptType::ShWsh),
-                                            wit_stack,
-                                            Some(script),
-                                        ))
+                                        Ok((inner, wit_stack, Some(script)))
                                     } else {
                                         Err(Error::IncorrectWScriptHash)
                                     }
@@ -307,13 +334,11 @@ pub(super) fn from_txdata<'txin>(
                     }
                 }
                 // normal p2sh parsed in Legacy context
-                let miniscript = script_from_stack_elem::<Legacy>(&elem)?;
-                let script = miniscript.encode();
-                let miniscript = miniscript.to_no_checks_ms();
+                let (inner, script) = inner_from_stack_elem::<Legacy>(&elem, ScriptType::Sh)?;
                 if wit_stack.is_empty() {
                     let scripthash = hash160::Hash::hash(script.as_bytes());
                     if *spk == bitcoin::ScriptBuf::new_p2sh(&scripthash.into()) {
-                        Ok((Inner::Script(miniscript, ScriptType::Sh), ssig_stack, Some(script)))
+                        Ok((inner, ssig_stack, Some(script)))
                     } else {
                         Err(Error::IncorrectScriptHash)
                     }
@@ -326,13 +351,18 @@ pub(super) fn from_txdata<'txin>(
     // ** bare script **
     } else {
         if wit_stack.is_empty() {
-            // Bare script parsed in BareCtx
-            let miniscript = Miniscript::<bitcoin::PublicKey, BareCtx>::parse_with_ext(
+            // Bare script parsed in BareCtx, or executed as is if it is not Miniscript
+            let inner = match Miniscript::<bitcoin::PublicKey, BareCtx>::parse_with_ext(
                 spk,
                 &ExtParams::allow_all(),
-            )?;
-            let miniscript = miniscript.to_no_checks_ms();
-            Ok((Inner::Script(miniscript, ScriptType::Bare), ssig_stack, Some(spk.to_owned())))
+            ) {
+                Ok(miniscript) => Inner::Script(miniscript.to_no_checks_ms(), ScriptType::Bare),
+                #[cfg(feature = "raw-script")]
+                Err(_) => Inner::Raw(spk.to_owned(), ScriptType::Bare),
+                #[cfg(not(feature = "raw-script"))]
+                Err(e) => return Err(e.into()),
+            };
+            Ok((inner, ssig_stack, Some(spk.to_owned())))
         } else {
             Err(Error::NonEmptyWitness)
         }
@@ -524,12 +554,20 @@ mod tests {
         let err = from_txdata(&spk, &bitcoin::ScriptBuf::new(), &empty_wit).unwrap_err();
         assert_eq!(err.to_string(), "could not parse pubkey");
 
-        // Scriptpubkey has invalid script
+        // Scriptpubkey is not Miniscript, and is kept as a raw script
         let mut spk = comp.pk_spk.to_bytes();
         spk[0] = 100;
         let spk = bitcoin::ScriptBuf::from(spk);
-        let err = from_txdata(&spk, &bitcoin::ScriptBuf::new(), &empty_wit).unwrap_err();
-        assert_eq!(&err.to_string()[0..12], "parse error:");
+        #[cfg(feature = "raw-script")]
+        {
+            let (inner, _, _) = from_txdata(&spk, &bitcoin::ScriptBuf::new(), &empty_wit).unwrap();
+            assert_eq!(inner, Inner::Raw(spk, ScriptType::Bare));
+        }
+        #[cfg(not(feature = "raw-script"))]
+        {
+            let err = from_txdata(&spk, &bitcoin::ScriptBuf::new(), &empty_wit).unwrap_err();
+            assert_eq!(&err.to_string()[0..12], "parse error:");
+        }
 
         // Witness is nonempty
         let wit = Witness::from_slice(&[vec![]]);
@@ -700,8 +738,19 @@ mod tests {
         assert_eq!(stack, Stack::from(vec![]));
         assert_eq!(script_code, Some(spk.clone()));
 
-        let err = from_txdata(&blank_script, &blank_script, &empty_wit).unwrap_err();
-        assert_eq!(&err.to_string()[0..12], "parse error:");
+        // scripts which are not Miniscript fall back to raw execution
+        #[cfg(feature = "raw-script")]
+        {
+            let (inner, _, script_code) =
+                from_txdata(&blank_script, &blank_script, &empty_wit).expect("parse txdata");
+            assert_eq!(inner, Inner::Raw(blank_script.clone(), ScriptType::Bare));
+            assert_eq!(script_code, Some(blank_script.clone()));
+        }
+        #[cfg(not(feature = "raw-script"))]
+        {
+            let err = from_txdata(&blank_script, &blank_script, &empty_wit).unwrap_err();
+            assert_eq!(&err.to_string()[0..12], "parse error:");
+        }
 
         // nonempty witness
         let wit = Witness::from_slice(&[vec![]]);
@@ -763,6 +812,9 @@ mod tests {
         // with incorrect witness
         let wit = Witness::from_slice(&[spk.to_bytes()]);
         let err = from_txdata(&spk, &blank_script, &wit).unwrap_err();
+        #[cfg(feature = "raw-script")]
+        assert_eq!(&err.to_string(), "witness script did not match scriptpubkey");
+        #[cfg(not(feature = "raw-script"))]
         assert_eq!(&err.to_string()[0..12], "parse error:");
 
         // with correct witness
@@ -808,6 +860,9 @@ mod tests {
         // with incorrect witness
         let wit = Witness::from_slice(&[spk.to_bytes()]);
         let err = from_txdata(&spk, &script_sig, &wit).unwrap_err();
+        #[cfg(feature = "raw-script")]
+        assert_eq!(&err.to_string(), "witness script did not match scriptpubkey");
+        #[cfg(not(feature = "raw-script"))]
         assert_eq!(&err.to_string()[0..12], "parse error:");
 
         // with incorrect scriptsig
@@ -8,23 +8,32 @@
 //! assuming that the spent coin was descriptor controlled.
 //!
 
-use core::fmt;
 use core::str::FromStr;
+use core::{cmp, fmt};
 
 use bitcoin::hashes::{hash160, ripemd160, sha256, Hash};
+use bitcoin::hex::DisplayHex;
 use bitcoin::{absolute, relative, secp256k1, sighash, taproot, Sequence, TxOut, Witness};
 
 use crate::miniscript::context::{NoChecks, SigType};
 use crate::miniscript::ScriptContext;
+use crate::policy::semantic;
 use crate::prelude::*;
-use crate::{hash256, Descriptor, Miniscript, Terminal, ToPublicKey};
+use crate::sync::Arc;
+use crate::{
+    hash256, AbsLockTime, Descriptor, Miniscript, RelLockTime, Terminal, Threshold, ToPublicKey,
+};
 
 mod error;
 mod inner;
+mod policy;
+#[cfg(feature = "raw-script")]
+mod raw;
 mod stack;
 
-pub use self::error::Error;
 use self::error::PkEvalErrInner;
+pub use self::error::{Error, Trace};
+pub use self::policy::{PolicyFlags, PolicyViolation};
 use self::stack::Stack;
 use crate::MiniscriptKey;
 
@@ -32,11 +41,16 @@ use crate::MiniscriptKey;
 pub struct Interpreter<'txin> {
     inner: inner::Inner,
     stack: Stack<'txin>,
+    script_sig: &'txin bitcoin::Script,
     /// For non-Taproot spends, the scriptCode; for Taproot script-spends, this
     /// is the leaf script; for key-spends it is `None`.
     script_code: Option<bitcoin::ScriptBuf>,
     sequence: Sequence,
     lock_time: absolute::LockTime,
+    /// The annex of Taproot key spends which have one
+    annex: Option<&'txin [u8]>,
+    /// The control block of Taproot script spends
+    control_block: Option<taproot::ControlBlock>,
 }
 
 // A type representing functions for checking signatures that accept both
@@ -139,6 +153,9 @@ impl<'txin> Interpreter<'txin> {
     /// that ECSDA signatures are valid, this can be set to the constant true
     /// function; otherwise, it should be a closure containing a sighash and
     /// secp context, which can actually verify a given signature.
+    ///
+    /// Scripts which are not Miniscript are rejected, unless the `raw-script`
+    /// feature is enabled, in which case they are executed opcode by opcode.
     pub fn from_txdata(
         spk: &bitcoin::ScriptBuf,
         script_sig: &'txin bitcoin::Script,
@@ -147,9 +164,43 @@ impl<'txin> Interpreter<'txin> {
         lock_time: absolute::LockTime, // CLTV, absolute lock time.
     ) -> Result<Self, Error> {
         let (inner, stack, script_code) = inner::from_txdata(spk, script_sig, witness)?;
-        Ok(Interpreter { inner, stack, script_code, sequence, lock_time })
+        // BIP 341: a key spend witness with two elements ends with the annex
+        let annex = match inner {
+            inner::Inner::PublicKey(_, inner::PubkeyType::Tr) if witness.len() == 2 => {
+                witness.last()
+            }
+            _ => None,
+        };
+        // Script spends with an annex are rejected above, so the control
+        // block is always the last witness element
+        let control_block = match inner {
+            inner::Inner::Script(_, inner::ScriptType::Tr)
+            | inner::Inner::Raw(_, inner::ScriptType::Tr) => witness
+                .last()
+                .and_then(|ctrl_blk| taproot::ControlBlock::decode(ctrl_blk).ok()),
+            _ => None,
+        };
+        Ok(Interpreter {
+            inner,
+            stack,
+            script_sig,
+            script_code,
+            sequence,
+            lock_time,
+            annex,
+            control_block,
+        })
     }
 
+    /// The annex of the spend, for Taproot key spends which have one
+    pub fn annex(&self) -> Option<&'txin [u8]> { self.annex }
+
+    /// The control block of the spend, for Taproot script spends
+    ///
+    /// It reveals the internal key of the output and the hashes of the
+    /// branches of the Taproot tree which were not used by the spend.
+    pub fn control_block(&self) -> Option<&taproot::ControlBlock> { self.control_block.as_ref() }
+
     /// Same as [`Interpreter::iter`], but allows for a custom verification function.
     /// See [Self::iter_assume_sigs] for a simpler API without information about Prevouts
     /// but skips the signature verification
@@ -164,16 +215,25 @@ impl<'txin> Interpreter<'txin> {
             } else {
                 None
             },
+            current: None,
             state: if let inner::Inner::Script(ref script, _) = self.inner {
                 vec![NodeEvaluationState { node: script, n_evaluated: 0, n_satisfied: 0 }]
             } else {
                 vec![]
             },
+            #[cfg(feature = "raw-script")]
+            raw: if let inner::Inner::Raw(ref script, script_type) = self.inner {
+                Some(raw::Exec::new(script, &self.stack, script_type))
+            } else {
+                None
+            },
             // Cloning the references to elements of stack should be fine as it allows
             // call interpreter.iter() without mutating interpreter
             stack: self.stack.clone(),
+            initial_stack: self.stack.clone(),
             sequence: self.sequence,
             lock_time: self.lock_time,
+            annex: self.annex,
             has_errored: false,
             sig_type: self.sig_type(),
         }
@@ -248,9 +308,15 @@ impl<'txin> Interpreter<'txin> {
             }
             KeySigPair::Schnorr(xpk, schnorr_sig) => {
                 let sighash_msg = if self.is_taproot_v1_key_spend() {
-                    cache.taproot_key_spend_signature_hash(
+                    let annex = match self.annex.map(sighash::Annex::new).transpose() {
+                        Ok(annex) => annex,
+                        Err(_) => return false,
+                    };
+                    cache.taproot_signature_hash(
                         input_idx,
                         prevouts,
+                        annex,
+                        None,
                         schnorr_sig.sighash_type,
                     )
                 } else if self.is_taproot_v1_script_spend() {
@@ -315,6 +381,93 @@ impl<'txin> Interpreter<'txin> {
         self.iter_custom(Box::new(|_| true))
     }
 
+    /// Hash preimages revealed by the spend, keyed by their hash
+    ///
+    /// Signatures are not checked, as they do not matter to learn preimages.
+    /// This lets watchtowers and Lightning implementations recover preimages
+    /// from spends they see on chain.
+    pub fn revealed_preimages(&self) -> Result<BTreeMap<HashLockType, [u8; 32]>, Error> {
+        let mut preimages = BTreeMap::new();
+        for constraint in self.iter_assume_sigs() {
+            if let SatisfiedConstraint::HashLock { hash, preimage } = constraint? {
+                preimages.insert(hash, preimage);
+            }
+        }
+        Ok(preimages)
+    }
+
+    /// Checks the spend against the standardness rules of `flags`
+    ///
+    /// Nodes only relay transactions whose inputs follow these rules on top
+    /// of consensus ones. Violations are reported whether or not the spend is
+    /// valid, so that a spend which would not be relayed can be detected
+    /// before broadcast. Signatures are not verified, and scripts are only
+    /// checked as far as their execution goes.
+    ///
+    /// The rules checked while executing the script, such as `MINIMALIF`,
+    /// need the `raw-script` feature; without it only the script sig, the
+    /// stack element sizes and key spend signatures are checked.
+    pub fn policy_violations(&self, flags: PolicyFlags) -> Vec<PolicyViolation> {
+        let mut violations = vec![];
+        if flags.minimal_data {
+            for (pos, ins) in self.script_sig.instruction_indices().flatten() {
+                if let bitcoin::script::Instruction::PushBytes(data) = ins {
+                    let opcode = bitcoin::Opcode::from(self.script_sig.as_bytes()[pos]);
+                    let data = data.as_bytes();
+                    if !policy::is_minimal_push(opcode, data) {
+                        violations.push(PolicyViolation::NonMinimalPush(opcode, data.to_vec()));
+                    }
+                }
+            }
+        }
+
+        match self.inner {
+            inner::Inner::PublicKey(..) => {
+                // The signature is the only element left on the stack
+                if flags.low_s && self.sig_type() == SigType::Ecdsa {
+                    if let Some(stack::Element::Push(sig)) = self.stack.last() {
+                        violations.extend(policy::high_s_sig(sig).map(PolicyViolation::HighS));
+                    }
+                }
+            }
+            inner::Inner::Script(_, script_type) | inner::Inner::Raw(_, script_type) => {
+                let limit = match script_type {
+                    inner::ScriptType::Wsh | inner::ScriptType::ShWsh => {
+                        Some(crate::miniscript::limits::MAX_STANDARD_P2WSH_STACK_ITEM_SIZE)
+                    }
+                    inner::ScriptType::Tr => {
+                        Some(crate::miniscript::limits::MAX_STANDARD_TAPSCRIPT_STACK_ITEM_SIZE)
+                    }
+                    inner::ScriptType::Bare | inner::ScriptType::Sh => None,
+                };
+                if let (true, Some(limit)) = (flags.element_size, limit) {
+                    for elem in self.stack.to_byte_vecs() {
+                        if elem.len() > limit {
+                            violations.push(PolicyViolation::OversizedElement(elem.len(), limit));
+                        }
+                    }
+                }
+
+                #[cfg(feature = "raw-script")]
+                {
+                    let script = self
+                        .script_code
+                        .as_ref()
+                        .expect("Scripts are always run with a script code");
+                    let mut exec =
+                        raw::Exec::new(script, &self.stack, script_type).check_policy(flags);
+                    let mut verify_sig: Box<dyn FnMut(&KeySigPair) -> bool> = Box::new(|_| true);
+                    while let Some(Ok(_)) =
+                        exec.step(&mut verify_sig, self.sequence, self.lock_time)
+                    {
+                    }
+                    violations.extend_from_slice(exec.violations());
+                }
+            }
+        }
+        violations
+    }
+
     /// Outputs a "descriptor" string which reproduces the spent coins
     ///
     /// This may not represent the original descriptor used to produce the transaction,
@@ -346,13 +499,38 @@ impl<'txin> Interpreter<'txin> {
             inner::Inner::Script(ref ms, inner::ScriptType::Wsh) => format!("wsh({})", ms),
             inner::Inner::Script(ref ms, inner::ScriptType::ShWsh) => format!("sh(wsh({}))", ms),
             inner::Inner::Script(ref ms, inner::ScriptType::Tr) => {
-                // Hidden paths are still under discussion, once the spec is finalized, we can support
-                // rawnode and raw leaf.
-                format!("tr(hidden_paths_not_yet_supported,{})", ms)
+                self.inferred_tr_descriptor_string(ms.to_string())
+            }
+            inner::Inner::Raw(ref script, inner::ScriptType::Tr) => self
+                .inferred_tr_descriptor_string(format!(
+                    "raw_script_not_supported({:x})",
+                    script.as_bytes().as_hex()
+                )),
+            inner::Inner::Raw(ref script, _) => {
+                // Scripts which are not Miniscript have no descriptor
+                format!("raw_script_not_supported({:x})", script.as_bytes().as_hex())
             }
         }
     }
 
+    // Rebuilds the part of the Taproot tree revealed by the control block
+    // around the spent leaf. Hidden paths are still under discussion, once the
+    // spec is finalized they can be parsed; for now the branches which were not
+    // spent are written as `hidden(<node hash>)` and only single leaf trees
+    // result in a descriptor which can be parsed.
+    fn inferred_tr_descriptor_string(&self, leaf: String) -> String {
+        let ctrl_blk = self
+            .control_block
+            .as_ref()
+            .expect("Taproot script spends always have a control block");
+        let tree = ctrl_blk
+            .merkle_branch
+            .iter()
+            .fold(leaf, |tree, hash| format!("{{{},hidden({})}}", tree, hash));
+        let internal_key = BitcoinKey::from(ctrl_blk.internal_key);
+        format!("tr({},{})", internal_key, tree)
+    }
+
     /// Whether this is a pre-segwit spend
     pub fn is_legacy(&self) -> bool {
         match self.inner {
@@ -361,11 +539,16 @@ impl<'txin> Interpreter<'txin> {
             inner::Inner::PublicKey(_, inner::PubkeyType::Wpkh) => false,
             inner::Inner::PublicKey(_, inner::PubkeyType::ShWpkh) => false, // lol "sorta"
             inner::Inner::PublicKey(_, inner::PubkeyType::Tr) => false,     // lol "sorta"
-            inner::Inner::Script(_, inner::ScriptType::Bare) => true,
-            inner::Inner::Script(_, inner::ScriptType::Sh) => true,
-            inner::Inner::Script(_, inner::ScriptType::Wsh) => false,
-            inner::Inner::Script(_, inner::ScriptType::ShWsh) => false, // lol "sorta"
-            inner::Inner::Script(_, inner::ScriptType::Tr) => false,
+            inner::Inner::Script(_, inner::ScriptType::Bare)
+            | inner::Inner::Raw(_, inner::ScriptType::Bare) => true,
+            inner::Inner::Script(_, inner::ScriptType::Sh)
+            | inner::Inner::Raw(_, inner::ScriptType::Sh) => true,
+            inner::Inner::Script(_, inner::ScriptType::Wsh)
+            | inner::Inner::Raw(_, inner::ScriptType::Wsh) => false,
+            inner::Inner::Script(_, inner::ScriptType::ShWsh)
+            | inner::Inner::Raw(_, inner::ScriptType::ShWsh) => false, // lol "sorta"
+            inner::Inner::Script(_, inner::ScriptType::Tr)
+            | inner::Inner::Raw(_, inner::ScriptType::Tr) => false,
         }
     }
 
@@ -377,11 +560,16 @@ impl<'txin> Interpreter<'txin> {
             inner::Inner::PublicKey(_, inner::PubkeyType::Wpkh) => true,
             inner::Inner::PublicKey(_, inner::PubkeyType::ShWpkh) => true, // lol "sorta"
             inner::Inner::PublicKey(_, inner::PubkeyType::Tr) => false,
-            inner::Inner::Script(_, inner::ScriptType::Bare) => false,
-            inner::Inner::Script(_, inner::ScriptType::Sh) => false,
-            inner::Inner::Script(_, inner::ScriptType::Wsh) => true,
-            inner::Inner::Script(_, inner::ScriptType::ShWsh) => true, // lol "sorta"
-            inner::Inner::Script(_, inner::ScriptType::Tr) => false,
+            inner::Inner::Script(_, inner::ScriptType::Bare)
+            | inner::Inner::Raw(_, inner::ScriptType::Bare) => false,
+            inner::Inner::Script(_, inner::ScriptType::Sh)
+            | inner::Inner::Raw(_, inner::ScriptType::Sh) => false,
+            inner::Inner::Script(_, inner::ScriptType::Wsh)
+            | inner::Inner::Raw(_, inner::ScriptType::Wsh) => true,
+            inner::Inner::Script(_, inner::ScriptType::ShWsh)
+            | inner::Inner::Raw(_, inner::ScriptType::ShWsh) => true, // lol "sorta"
+            inner::Inner::Script(_, inner::ScriptType::Tr)
+            | inner::Inner::Raw(_, inner::ScriptType::Tr) => false,
         }
     }
 
@@ -393,11 +581,16 @@ impl<'txin> Interpreter<'txin> {
             inner::Inner::PublicKey(_, inner::PubkeyType::Wpkh) => false,
             inner::Inner::PublicKey(_, inner::PubkeyType::ShWpkh) => false,
             inner::Inner::PublicKey(_, inner::PubkeyType::Tr) => true,
-            inner::Inner::Script(_, inner::ScriptType::Bare) => false,
-            inner::Inner::Script(_, inner::ScriptType::Sh) => false,
-            inner::Inner::Script(_, inner::ScriptType::Wsh) => false,
-            inner::Inner::Script(_, inner::ScriptType::ShWsh) => false,
-            inner::Inner::Script(_, inner::ScriptType::Tr) => false,
+            inner::Inner::Script(_, inner::ScriptType::Bare)
+            | inner::Inner::Raw(_, inner::ScriptType::Bare) => false,
+            inner::Inner::Script(_, inner::ScriptType::Sh)
+            | inner::Inner::Raw(_, inner::ScriptType::Sh) => false,
+            inner::Inner::Script(_, inner::ScriptType::Wsh)
+            | inner::Inner::Raw(_, inner::ScriptType::Wsh) => false,
+            inner::Inner::Script(_, inner::ScriptType::ShWsh)
+            | inner::Inner::Raw(_, inner::ScriptType::ShWsh) => false,
+            inner::Inner::Script(_, inner::ScriptType::Tr)
+            | inner::Inner::Raw(_, inner::ScriptType::Tr) => false,
         }
     }
 
@@ -409,11 +602,16 @@ impl<'txin> Interpreter<'txin> {
             inner::Inner::PublicKey(_, inner::PubkeyType::Wpkh) => false,
             inner::Inner::PublicKey(_, inner::PubkeyType::ShWpkh) => false,
             inner::Inner::PublicKey(_, inner::PubkeyType::Tr) => false,
-            inner::Inner::Script(_, inner::ScriptType::Bare) => false,
-            inner::Inner::Script(_, inner::ScriptType::Sh) => false,
-            inner::Inner::Script(_, inner::ScriptType::Wsh) => false,
-            inner::Inner::Script(_, inner::ScriptType::ShWsh) => false,
-            inner::Inner::Script(_, inner::ScriptType::Tr) => true,
+            inner::Inner::Script(_, inner::ScriptType::Bare)
+            | inner::Inner::Raw(_, inner::ScriptType::Bare) => false,
+            inner::Inner::Script(_, inner::ScriptType::Sh)
+            | inner::Inner::Raw(_, inner::ScriptType::Sh) => false,
+            inner::Inner::Script(_, inner::ScriptType::Wsh)
+            | inner::Inner::Raw(_, inner::ScriptType::Wsh) => false,
+            inner::Inner::Script(_, inner::ScriptType::ShWsh)
+            | inner::Inner::Raw(_, inner::ScriptType::ShWsh) => false,
+            inner::Inner::Script(_, inner::ScriptType::Tr)
+            | inner::Inner::Raw(_, inner::ScriptType::Tr) => true,
         }
     }
 
@@ -421,7 +619,8 @@ impl<'txin> Interpreter<'txin> {
     pub fn sig_type(&self) -> SigType {
         match self.inner {
             inner::Inner::PublicKey(_, inner::PubkeyType::Tr) => SigType::Schnorr,
-            inner::Inner::Script(_, inner::ScriptType::Tr) => SigType::Schnorr,
+            inner::Inner::Script(_, inner::ScriptType::Tr)
+            | inner::Inner::Raw(_, inner::ScriptType::Tr) => SigType::Schnorr,
             inner::Inner::PublicKey(_, inner::PubkeyType::Pk)
             | inner::Inner::PublicKey(_, inner::PubkeyType::Pkh)
             | inner::Inner::PublicKey(_, inner::PubkeyType::Wpkh)
@@ -429,7 +628,11 @@ impl<'txin> Interpreter<'txin> {
             | inner::Inner::Script(_, inner::ScriptType::Bare)
             | inner::Inner::Script(_, inner::ScriptType::Sh)
             | inner::Inner::Script(_, inner::ScriptType::Wsh)
-            | inner::Inner::Script(_, inner::ScriptType::ShWsh) => SigType::Ecdsa,
+            | inner::Inner::Script(_, inner::ScriptType::ShWsh)
+            | inner::Inner::Raw(_, inner::ScriptType::Bare)
+            | inner::Inner::Raw(_, inner::ScriptType::Sh)
+            | inner::Inner::Raw(_, inner::ScriptType::Wsh)
+            | inner::Inner::Raw(_, inner::ScriptType::ShWsh) => SigType::Ecdsa,
         }
     }
 
@@ -439,13 +642,52 @@ impl<'txin> Interpreter<'txin> {
     /// since it cannot distinguish between sorted and unsorted multisigs (and anyway
     /// it can only see the final keys, keyorigin info is lost in serializing to Bitcoin).
     /// x-only keys are translated to [`bitcoin::PublicKey`] with 0x02 prefix.
+    ///
+    /// Taproot script spends are inferred as a `tr` descriptor with the
+    /// internal key from the control block. Since descriptors cannot yet
+    /// express hidden branches, this fails unless the tree has a single leaf;
+    /// see [`Interpreter::inferred_descriptor_string`] for such spends.
     pub fn inferred_descriptor(&self) -> Result<Descriptor<bitcoin::PublicKey>, crate::Error> {
         Descriptor::from_str(&self.inferred_descriptor_string())
     }
 }
 
+/// Verifies all inputs of a transaction
+///
+/// `prevouts` are the outputs spent by each input of `tx`, in order. Builds an
+/// [`Interpreter`] for every input and evaluates it, checking signatures
+/// against the sighashes of `tx`. Returns, in input order, the constraints
+/// satisfied by each input or the error its verification failed with.
+pub fn verify_transaction<C: secp256k1::Verification, T: Borrow<TxOut>>(
+    tx: &bitcoin::Transaction,
+    prevouts: &[T],
+    secp: &secp256k1::Secp256k1<C>,
+) -> Result<Vec<Result<Vec<SatisfiedConstraint>, Error>>, Error> {
+    if prevouts.len() != tx.input.len() {
+        return Err(Error::PrevoutCountMismatch(tx.input.len(), prevouts.len()));
+    }
+    let all_prevouts = sighash::Prevouts::All(prevouts);
+    let results = tx
+        .input
+        .iter()
+        .zip(prevouts)
+        .enumerate()
+        .map(|(index, (txin, prevout))| {
+            let interpreter = Interpreter::from_txdata(
+                &prevout.borrow().script_pubkey,
+                &txin.script_sig,
+                &txin.witness,
+                txin.sequence,
+                tx.lock_time,
+            )?;
+            interpreter.iter(secp, tx, index, &all_prevouts).collect()
+        })
+        .collect();
+    Ok(results)
+}
+
 /// Type of HashLock used for SatisfiedConstraint structure
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub enum HashLockType {
     ///SHA 256 hashlock
     Sha256(sha256::Hash),
@@ -457,10 +699,9 @@ pub enum HashLockType {
     Ripemd160(ripemd160::Hash),
 }
 
-/// A satisfied Miniscript condition (Signature, Hashlock, Timelock)
-/// 'intp represents the lifetime of descriptor and `stack represents
-/// the lifetime of witness
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+/// A satisfied Miniscript condition (Signature, Hashlock, Timelock), or
+/// data committed to by the spend (Annex)
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
 pub enum SatisfiedConstraint {
     ///Public key and corresponding signature
     PublicKey {
@@ -491,6 +732,11 @@ pub enum SatisfiedConstraint {
         /// The value of Absolute timelock
         n: absolute::LockTime,
     },
+    ///Annex of a Taproot key spend, committed to by its signature.
+    Annex {
+        /// The annex, including its 0x50 prefix
+        annex: Vec<u8>,
+    },
 }
 
 ///This is used by the interpreter to know which evaluation state a AstemElem is.
@@ -522,10 +768,16 @@ struct NodeEvaluationState<'intp> {
 pub struct Iter<'intp, 'txin: 'intp> {
     verify_sig: Box<dyn FnMut(&KeySigPair) -> bool + 'intp>,
     public_key: Option<&'intp BitcoinKey>,
+    // The fragment evaluated last, reported in traces
+    current: Option<&'intp Miniscript<BitcoinKey, NoChecks>>,
     state: Vec<NodeEvaluationState<'intp>>,
+    #[cfg(feature = "raw-script")]
+    raw: Option<raw::Exec<'intp>>,
     stack: Stack<'txin>,
+    initial_stack: Stack<'txin>,
     sequence: Sequence,
     lock_time: absolute::LockTime,
+    annex: Option<&'txin [u8]>,
     has_errored: bool,
     sig_type: SigType,
 }
@@ -555,6 +807,98 @@ impl<'intp, 'txin: 'intp> Iter<'intp, 'txin>
 where
     NoChecks: ScriptContext,
 {
+    /// Evaluates the rest of the spend, collecting the satisfied constraints
+    ///
+    /// On failure, returns a [`Trace`] telling which fragment failed, which
+    /// witness elements were consumed and what the stack looked like, which
+    /// is handy to debug hand-crafted witnesses.
+    pub fn collect_traced(mut self) -> Result<Vec<SatisfiedConstraint>, Trace> {
+        let mut constraints = vec![];
+        loop {
+            match self.next() {
+                Some(Ok(constraint)) => constraints.push(constraint),
+                Some(Err(error)) => return Err(self.trace(error)),
+                None => return Ok(constraints),
+            }
+        }
+    }
+
+    /// Evaluates the rest of the spend, describing the constraints it
+    /// satisfied as a semantic policy
+    ///
+    /// The policy is the conjunction of the signatures, hashlocks and
+    /// timelocks the spend relied upon, i.e. exactly the condition it met,
+    /// or [`semantic::Policy::Trivial`] if there were none. x-only keys are
+    /// translated to [`bitcoin::PublicKey`] with 0x02 prefix.
+    pub fn satisfied_policy(self) -> Result<semantic::Policy<bitcoin::PublicKey>, Error> {
+        let mut policies = vec![];
+        for constraint in self {
+            let policy = match constraint? {
+                SatisfiedConstraint::PublicKey { key_sig }
+                | SatisfiedConstraint::PublicKeyHash { key_sig, .. } => match key_sig {
+                    KeySigPair::Ecdsa(pk, _) => semantic::Policy::Key(pk),
+                    KeySigPair::Schnorr(pk, _) => semantic::Policy::Key(pk.to_public_key()),
+                },
+                SatisfiedConstraint::HashLock { hash, .. } => match hash {
+                    HashLockType::Sha256(hash) => semantic::Policy::Sha256(hash),
+                    HashLockType::Hash256(hash) => semantic::Policy::Hash256(hash),
+                    HashLockType::Hash160(hash) => semantic::Policy::Hash160(hash),
+                    HashLockType::Ripemd160(hash) => semantic::Policy::Ripemd160(hash),
+                },
+                // Timelocks of zero, which raw scripts may check, are always met
+                SatisfiedConstraint::RelativeTimelock { n } => {
+                    match RelLockTime::from_consensus(n.to_consensus_u32()) {
+                        Ok(n) => semantic::Policy::Older(n),
+                        Err(_) => continue,
+                    }
+                }
+                SatisfiedConstraint::AbsoluteTimelock { n } => {
+                    match AbsLockTime::from_consensus(n.to_consensus_u32()) {
+                        Ok(n) => semantic::Policy::After(n),
+                        Err(_) => continue,
+                    }
+                }
+                SatisfiedConstraint::Annex { .. } => continue,
+            };
+            policies.push(policy);
+        }
+        Ok(match policies.len() {
+            0 => semantic::Policy::Trivial,
+            1 => policies.pop().expect("one policy"),
+            _ => semantic::Policy::Thresh(Threshold::and_n(
+                policies.into_iter().map(Arc::new).collect(),
+            )),
+        })
+    }
+
+    // Describes the state of evaluation when it failed with `error`
+    fn trace(&self, error: Error) -> Trace {
+        #[cfg(feature = "raw-script")]
+        let raw = self.raw.as_ref().map(|exec| {
+            (exec.last_op().map(|op| op.to_string()), exec.low_water(), exec.stack().to_vec())
+        });
+        #[cfg(not(feature = "raw-script"))]
+        let raw = None;
+        let (fragment, low_water, stack) = match raw {
+            Some(state) => state,
+            None => {
+                let fragment = match (self.current, self.public_key) {
+                    (Some(node), _) => Some(node.to_string()),
+                    (None, Some(pk)) => Some(format!("pk({})", pk)),
+                    (None, None) => None,
+                };
+                (fragment, self.stack.low_water(), self.stack.to_byte_vecs())
+            }
+        };
+        let initial = self.initial_stack.to_byte_vecs();
+        let consumed = initial[cmp::min(low_water, initial.len())..]
+            .iter()
+            .rev()
+            .cloned()
+            .collect();
+        Trace { error, fragment, consumed, stack }
+    }
+
     /// Helper function to push a NodeEvaluationState on state stack
     fn push_evaluation_state(
         &mut self,
@@ -568,7 +912,15 @@ where
 
     /// Helper function to step the iterator
     fn iter_next(&mut self) -> Option<Result<SatisfiedConstraint, Error>> {
+        if let Some(annex) = self.annex.take() {
+            return Some(Ok(SatisfiedConstraint::Annex { annex: annex.to_vec() }));
+        }
+        #[cfg(feature = "raw-script")]
+        if let Some(ref mut exec) = self.raw {
+            return exec.step(&mut self.verify_sig, self.sequence, self.lock_time);
+        }
         while let Some(node_state) = self.state.pop() {
+            self.current = Some(node_state.node);
             //non-empty stack
             match node_state.node.node {
                 Terminal::True => {
@@ -1135,11 +1487,16 @@ mod tests {
         ) -> Iter<'elem, 'txin> {
             Iter {
                 verify_sig: verify_fn,
+                initial_stack: stack.clone(),
                 stack,
                 public_key: None,
+                current: None,
                 state: vec![NodeEvaluationState { node: ms, n_evaluated: 0, n_satisfied: 0 }],
+                #[cfg(feature = "raw-script")]
+                raw: None,
                 sequence: Sequence::from_height(1002),
                 lock_time: absolute::LockTime::from_height(1002).unwrap(),
+                annex: None,
                 has_errored: false,
                 sig_type: SigType::Ecdsa,
             }
@@ -1554,6 +1911,707 @@ mod tests {
         assert!(multi_a_error.is_err());
     }
 
+    #[test]
+    #[cfg(feature = "raw-script")]
+    fn raw_script_constraints() {
+        use bitcoin::blockdata::opcodes::all::*;
+
+        let (pks, der_sigs, ecdsa_sigs, sighash, secp, _, _, _) = setup_keys_sigs(2);
+        let vfyfn = |pksig: &KeySigPair| match pksig.as_ecdsa() {
+            Some((pk, ecdsa_sig)) => secp
+                .verify_ecdsa(&sighash, &ecdsa_sig.signature, &pk.inner)
+                .is_ok(),
+            None => false,
+        };
+
+        // A hashlock without a size check, and keys selected by IF/ELSE, are not Miniscript
+        let preimage = [0xab; 32];
+        let hash = sha256::Hash::hash(&preimage);
+        let script = bitcoin::script::Builder::new()
+            .push_opcode(OP_SHA256)
+            .push_slice(hash.to_byte_array())
+            .push_opcode(OP_EQUALVERIFY)
+            .push_opcode(OP_IF)
+            .push_key(&pks[0])
+            .push_opcode(OP_ELSE)
+            .push_int(1000)
+            .push_opcode(OP_CLTV)
+            .push_opcode(OP_DROP)
+            .push_key(&pks[1])
+            .push_opcode(OP_ENDIF)
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let spk = bitcoin::ScriptBuf::new_p2wsh(&script.wscript_hash());
+        let script_sig = bitcoin::ScriptBuf::new();
+        let lock_time = absolute::LockTime::from_height(1002).unwrap();
+
+        let witness = Witness::from_slice(&[
+            der_sigs[0].clone(),
+            vec![1],
+            preimage.to_vec(),
+            script.to_bytes(),
+        ]);
+        let interpreter =
+            Interpreter::from_txdata(&spk, &script_sig, &witness, Sequence::MAX, lock_time)
+                .unwrap();
+        assert!(interpreter.is_segwit_v0());
+        assert!(interpreter.inferred_descriptor().is_err());
+        let constraints: Result<Vec<_>, _> = interpreter.iter_custom(Box::new(vfyfn)).collect();
+        assert_eq!(
+            constraints.unwrap(),
+            vec![
+                SatisfiedConstraint::HashLock { hash: HashLockType::Sha256(hash), preimage },
+                SatisfiedConstraint::PublicKey {
+                    key_sig: KeySigPair::Ecdsa(pks[0], ecdsa_sigs[0])
+                },
+            ]
+        );
+
+        let witness = Witness::from_slice(&[
+            der_sigs[1].clone(),
+            vec![],
+            preimage.to_vec(),
+            script.to_bytes(),
+        ]);
+        let interpreter =
+            Interpreter::from_txdata(&spk, &script_sig, &witness, Sequence::MAX, lock_time)
+                .unwrap();
+        let constraints: Result<Vec<_>, _> = interpreter.iter_custom(Box::new(vfyfn)).collect();
+        assert_eq!(
+            constraints.unwrap(),
+            vec![
+                SatisfiedConstraint::HashLock { hash: HashLockType::Sha256(hash), preimage },
+                SatisfiedConstraint::AbsoluteTimelock {
+                    n: absolute::LockTime::from_height(1000).unwrap()
+                },
+                SatisfiedConstraint::PublicKey {
+                    key_sig: KeySigPair::Ecdsa(pks[1], ecdsa_sigs[1])
+                },
+            ]
+        );
+
+        // Signature by the wrong key
+        let witness = Witness::from_slice(&[
+            der_sigs[1].clone(),
+            vec![1],
+            preimage.to_vec(),
+            script.to_bytes(),
+        ]);
+        let interpreter =
+            Interpreter::from_txdata(&spk, &script_sig, &witness, Sequence::MAX, lock_time)
+                .unwrap();
+        let constraints: Result<Vec<_>, _> = interpreter.iter_custom(Box::new(vfyfn)).collect();
+        assert!(matches!(constraints, Err(Error::InvalidEcdsaSignature(pk)) if pk == pks[0]));
+
+        // Wrong preimage
+        let witness = Witness::from_slice(&[
+            der_sigs[0].clone(),
+            vec![1],
+            vec![0xcd; 32],
+            script.to_bytes(),
+        ]);
+        let interpreter =
+            Interpreter::from_txdata(&spk, &script_sig, &witness, Sequence::MAX, lock_time)
+                .unwrap();
+        let constraints: Result<Vec<_>, _> = interpreter.iter_custom(Box::new(vfyfn)).collect();
+        assert!(matches!(constraints, Err(Error::ScriptFailed(OP_EQUALVERIFY))));
+    }
+
+    #[test]
+    fn traces() {
+        let (pks, der_sigs, _, sighash, secp, _, _, _) = setup_keys_sigs(2);
+        let vfyfn = |pksig: &KeySigPair| match pksig.as_ecdsa() {
+            Some((pk, ecdsa_sig)) => secp
+                .verify_ecdsa(&sighash, &ecdsa_sig.signature, &pk.inner)
+                .is_ok(),
+            None => false,
+        };
+        let script_sig = bitcoin::ScriptBuf::new();
+        let lock_time = absolute::LockTime::ZERO;
+
+        // The signature for the first key is actually by the second one
+        let ms = Miniscript::<bitcoin::PublicKey, crate::Segwitv0>::from_str(&format!(
+            "and_v(v:pk({}),pk({}))",
+            pks[0], pks[1]
+        ))
+        .unwrap();
+        let script = ms.encode();
+        let spk = bitcoin::ScriptBuf::new_p2wsh(&script.wscript_hash());
+        let witness =
+            Witness::from_slice(&[der_sigs[1].clone(), der_sigs[1].clone(), script.to_bytes()]);
+        let interpreter =
+            Interpreter::from_txdata(&spk, &script_sig, &witness, Sequence::MAX, lock_time)
+                .unwrap();
+        let trace = interpreter
+            .iter_custom(Box::new(vfyfn))
+            .collect_traced()
+            .unwrap_err();
+        assert!(matches!(trace.error, Error::InvalidEcdsaSignature(pk) if pk == pks[0]));
+        assert_eq!(trace.fragment, Some(format!("pk_k({})", pks[0])));
+        assert_eq!(trace.consumed, vec![der_sigs[1].clone()]);
+        assert_eq!(trace.stack, vec![der_sigs[1].clone()]);
+        assert_eq!(
+            trace.to_string(),
+            format!(
+                "bad ecdsa signature with pk {}\n  while evaluating pk_k({})\n  \
+                 consumed witness elements, first consumed first:\n    {:x}\n  \
+                 stack at failure, top last:\n    {:x}",
+                pks[0],
+                pks[0],
+                der_sigs[1].as_hex(),
+                der_sigs[1].as_hex(),
+            )
+        );
+
+        // Successful evaluations yield all constraints
+        let witness =
+            Witness::from_slice(&[der_sigs[1].clone(), der_sigs[0].clone(), script.to_bytes()]);
+        let interpreter =
+            Interpreter::from_txdata(&spk, &script_sig, &witness, Sequence::MAX, lock_time)
+                .unwrap();
+        let constraints = interpreter
+            .iter_custom(Box::new(vfyfn))
+            .collect_traced()
+            .unwrap();
+        assert_eq!(constraints.len(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "raw-script")]
+    fn raw_tapscript_success_op() {
+        use bitcoin::blockdata::opcodes::all::*;
+
+        let secp = Secp256k1::verification_only();
+        let (pks, ..) = setup_keys_sigs(1);
+        let internal_key = pks[0].inner.x_only_public_key().0;
+        let script_sig = bitcoin::ScriptBuf::new();
+        let spend = |script: &bitcoin::Script| {
+            let spend_info = taproot::TaprootBuilder::new()
+                .add_leaf(0, script.to_owned())
+                .unwrap()
+                .finalize(&secp, internal_key)
+                .unwrap();
+            let ctrl_blk = spend_info
+                .control_block(&(script.to_owned(), taproot::LeafVersion::TapScript))
+                .unwrap();
+            let spk = bitcoin::ScriptBuf::new_p2tr_tweaked(spend_info.output_key());
+            let witness = Witness::from_slice(&[script.to_bytes(), ctrl_blk.serialize()]);
+            let interpreter = Interpreter::from_txdata(
+                &spk,
+                &script_sig,
+                &witness,
+                Sequence::MAX,
+                absolute::LockTime::ZERO,
+            )
+            .unwrap();
+            interpreter
+                .iter_assume_sigs()
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        // OP_RESERVED is OP_SUCCESS80 in tapscript, and is found before
+        // execution, so the failing OP_VERIFY in front of it does not matter
+        let script = bitcoin::script::Builder::new()
+            .push_opcode(OP_PUSHBYTES_0)
+            .push_opcode(OP_VERIFY)
+            .push_opcode(OP_RESERVED)
+            .into_script();
+        assert_eq!(spend(&script).unwrap(), vec![]);
+
+        // Without it, the script fails
+        let script = bitcoin::script::Builder::new()
+            .push_opcode(OP_PUSHBYTES_0)
+            .push_opcode(OP_VERIFY)
+            .push_opcode(OP_PUSHNUM_1)
+            .into_script();
+        assert!(spend(&script).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "raw-script")]
+    fn raw_script_traces() {
+        use bitcoin::blockdata::opcodes::all::*;
+
+        let script_sig = bitcoin::ScriptBuf::new();
+        let lock_time = absolute::LockTime::ZERO;
+
+        // Scripts which are not Miniscript report the opcode which failed
+        let script = bitcoin::script::Builder::new()
+            .push_opcode(OP_SHA256)
+            .push_slice([0; 32])
+            .push_opcode(OP_EQUALVERIFY)
+            .push_opcode(OP_DROP)
+            .push_opcode(OP_PUSHNUM_1)
+            .into_script();
+        let spk = bitcoin::ScriptBuf::new_p2wsh(&script.wscript_hash());
+        let witness = Witness::from_slice(&[vec![], vec![0xcd; 32], script.to_bytes()]);
+        let interpreter =
+            Interpreter::from_txdata(&spk, &script_sig, &witness, Sequence::MAX, lock_time)
+                .unwrap();
+        let trace = interpreter.iter_assume_sigs().collect_traced().unwrap_err();
+        assert!(matches!(trace.error, Error::ScriptFailed(OP_EQUALVERIFY)));
+        assert_eq!(trace.fragment, Some("OP_EQUALVERIFY".to_owned()));
+        assert_eq!(trace.consumed, vec![vec![0xcd; 32]]);
+        assert_eq!(trace.stack, vec![vec![]]);
+    }
+
+    #[test]
+    #[cfg(feature = "raw-script")]
+    fn raw_script_limits() {
+        use bitcoin::blockdata::opcodes::all::*;
+        use bitcoin::script::PushBytesBuf;
+
+        let script_sig = bitcoin::ScriptBuf::new();
+        let spend = |stack: Vec<Vec<u8>>, script: &bitcoin::Script| {
+            let spk = bitcoin::ScriptBuf::new_p2wsh(&script.wscript_hash());
+            let mut witness = stack;
+            witness.push(script.to_bytes());
+            let witness = Witness::from_slice(&witness);
+            let interpreter = Interpreter::from_txdata(
+                &spk,
+                &script_sig,
+                &witness,
+                Sequence::MAX,
+                absolute::LockTime::ZERO,
+            )
+            .unwrap();
+            interpreter
+                .iter_assume_sigs()
+                .collect::<Result<Vec<_>, _>>()
+        };
+        // Opcodes in branches which are not executed count too
+        let unexecuted = |nops: usize| {
+            let mut builder = bitcoin::script::Builder::new()
+                .push_opcode(OP_PUSHBYTES_0)
+                .push_opcode(OP_IF);
+            for _ in 0..nops {
+                builder = builder.push_opcode(OP_NOP);
+            }
+            builder
+                .push_opcode(OP_ENDIF)
+                .push_opcode(OP_PUSHNUM_1)
+                .into_script()
+        };
+
+        assert_eq!(spend(vec![], &unexecuted(199)).unwrap(), vec![]);
+        assert!(matches!(spend(vec![], &unexecuted(200)), Err(Error::MaxOpCountExceeded)));
+
+        let script = bitcoin::script::Builder::new()
+            .push_opcode(OP_PUSHBYTES_0)
+            .push_opcode(OP_IF)
+            .push_slice(PushBytesBuf::try_from(vec![0; 521]).unwrap())
+            .push_opcode(OP_ENDIF)
+            .push_opcode(OP_PUSHNUM_1)
+            .into_script();
+        assert!(matches!(spend(vec![], &script), Err(Error::MaxPushSizeExceeded(521))));
+        let script = bitcoin::script::Builder::new()
+            .push_opcode(OP_DROP)
+            .push_opcode(OP_PUSHNUM_1)
+            .into_script();
+        assert_eq!(spend(vec![vec![0; 520]], &script).unwrap(), vec![]);
+        assert!(matches!(
+            spend(vec![vec![0; 521]], &script),
+            Err(Error::MaxPushSizeExceeded(521))
+        ));
+
+        // The stack and altstack are limited together
+        let script = bitcoin::script::Builder::new()
+            .push_opcode(OP_TOALTSTACK)
+            .push_opcode(OP_DUP)
+            .push_opcode(OP_DUP)
+            .into_script();
+        assert!(matches!(spend(vec![vec![]; 999], &script), Err(Error::MaxStackSizeExceeded)));
+        assert!(matches!(spend(vec![vec![]; 1001], &script), Err(Error::MaxStackSizeExceeded)));
+    }
+
+    #[test]
+    fn verify_whole_transaction() {
+        use bitcoin::key::{Keypair, TapTweak};
+        use bitcoin::{transaction, Amount, OutPoint, TxIn};
+
+        let secp = Secp256k1::new();
+        let sk = secp256k1::SecretKey::from_slice(&[1; 32]).unwrap();
+        let pk = bitcoin::PublicKey::new(sk.public_key(&secp));
+        let keypair = Keypair::from_seckey_slice(&secp, &[2; 32]).unwrap();
+        let (internal_key, _) = keypair.x_only_public_key();
+        let tweaked = Keypair::from(keypair.tap_tweak(&secp, None));
+        let prevouts = vec![
+            TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: bitcoin::ScriptBuf::new_p2wpkh(&pk.wpubkey_hash().unwrap()),
+            },
+            TxOut {
+                value: Amount::from_sat(20_000),
+                script_pubkey: bitcoin::ScriptBuf::new_p2tr(&secp, internal_key, None),
+            },
+        ];
+        let mut tx = bitcoin::Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![
+                TxIn { previous_output: OutPoint::null(), ..Default::default() },
+                TxIn { previous_output: OutPoint::null(), ..Default::default() },
+            ],
+            output: vec![TxOut {
+                value: Amount::from_sat(25_000),
+                script_pubkey: bitcoin::ScriptBuf::new(),
+            }],
+        };
+
+        let mut cache = sighash::SighashCache::new(&tx);
+        let hash = cache
+            .p2wpkh_signature_hash(
+                0,
+                &prevouts[0].script_pubkey,
+                prevouts[0].value,
+                sighash::EcdsaSighashType::All,
+            )
+            .unwrap();
+        let ecdsa_sig = bitcoin::ecdsa::Signature::sighash_all(
+            secp.sign_ecdsa(&secp256k1::Message::from_digest(hash.to_byte_array()), &sk),
+        );
+        let hash = cache
+            .taproot_key_spend_signature_hash(
+                1,
+                &sighash::Prevouts::All(&prevouts),
+                sighash::TapSighashType::Default,
+            )
+            .unwrap();
+        let schnorr_sig = taproot::Signature {
+            signature: secp.sign_schnorr_no_aux_rand(
+                &secp256k1::Message::from_digest(hash.to_byte_array()),
+                &tweaked,
+            ),
+            sighash_type: sighash::TapSighashType::Default,
+        };
+        tx.input[0].witness = Witness::p2wpkh(&ecdsa_sig, &pk.inner);
+        tx.input[1].witness = Witness::from_slice(&[schnorr_sig.to_vec()]);
+
+        let results = verify_transaction(&tx, &prevouts, &secp).unwrap();
+        let (output_key, _) = tweaked.x_only_public_key();
+        assert_eq!(
+            results.into_iter().collect::<Result<Vec<_>, _>>().unwrap(),
+            vec![
+                vec![SatisfiedConstraint::PublicKey { key_sig: KeySigPair::Ecdsa(pk, ecdsa_sig) }],
+                vec![SatisfiedConstraint::PublicKey {
+                    key_sig: KeySigPair::Schnorr(output_key, schnorr_sig)
+                }],
+            ]
+        );
+
+        // Signatures do not hold for amounts other than the ones signed
+        let mut prevouts = prevouts;
+        prevouts[0].value = Amount::from_sat(11_000);
+        let results = verify_transaction(&tx, &prevouts, &secp).unwrap();
+        assert!(matches!(results[0], Err(Error::PkEvaluationError(_))));
+        assert!(matches!(results[1], Err(Error::PkEvaluationError(_))));
+
+        assert!(matches!(
+            verify_transaction(&tx, &prevouts[..1], &secp),
+            Err(Error::PrevoutCountMismatch(2, 1))
+        ));
+    }
+
+    #[test]
+    fn taproot_key_spend_with_annex() {
+        use bitcoin::key::{Keypair, TapTweak};
+        use bitcoin::{transaction, Amount, OutPoint, TxIn};
+
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_seckey_slice(&secp, &[1; 32]).unwrap();
+        let (internal_key, _) = keypair.x_only_public_key();
+        let tweaked = Keypair::from(keypair.tap_tweak(&secp, None));
+        let prevout = TxOut {
+            value: Amount::from_sat(10_000),
+            script_pubkey: bitcoin::ScriptBuf::new_p2tr(&secp, internal_key, None),
+        };
+        let tx = bitcoin::Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn { previous_output: OutPoint::null(), ..Default::default() }],
+            output: vec![],
+        };
+        let prevouts = [prevout.clone()];
+        let prevouts = sighash::Prevouts::All(&prevouts);
+
+        let annex = vec![0x50, 0xab, 0xcd];
+        let sighash = sighash::SighashCache::new(&tx)
+            .taproot_signature_hash(
+                0,
+                &prevouts,
+                Some(sighash::Annex::new(&annex).unwrap()),
+                None,
+                sighash::TapSighashType::Default,
+            )
+            .unwrap();
+        let msg = secp256k1::Message::from_digest(sighash.to_byte_array());
+        let sig = taproot::Signature {
+            signature: secp.sign_schnorr_no_aux_rand(&msg, &tweaked),
+            sighash_type: sighash::TapSighashType::Default,
+        };
+
+        let script_sig = bitcoin::ScriptBuf::new();
+        let witness = Witness::from_slice(&[sig.to_vec(), annex.clone()]);
+        let interpreter = Interpreter::from_txdata(
+            &prevout.script_pubkey,
+            &script_sig,
+            &witness,
+            Sequence::MAX,
+            absolute::LockTime::ZERO,
+        )
+        .unwrap();
+        assert!(interpreter.is_taproot_v1_key_spend());
+        assert_eq!(interpreter.annex(), Some(&annex[..]));
+        let constraints: Result<Vec<_>, _> = interpreter.iter(&secp, &tx, 0, &prevouts).collect();
+        let (output_key, _) = tweaked.x_only_public_key();
+        assert_eq!(
+            constraints.unwrap(),
+            vec![
+                SatisfiedConstraint::Annex { annex: annex.clone() },
+                SatisfiedConstraint::PublicKey { key_sig: KeySigPair::Schnorr(output_key, sig) },
+            ]
+        );
+
+        // The signature commits to the annex
+        let witness = Witness::from_slice(&[sig.to_vec(), vec![0x50, 0xab]]);
+        let interpreter = Interpreter::from_txdata(
+            &prevout.script_pubkey,
+            &script_sig,
+            &witness,
+            Sequence::MAX,
+            absolute::LockTime::ZERO,
+        )
+        .unwrap();
+        let constraints: Result<Vec<_>, _> = interpreter.iter(&secp, &tx, 0, &prevouts).collect();
+        assert!(constraints.is_err());
+    }
+
+    #[test]
+    fn revealed_preimages() {
+        let (pks, der_sigs, ..) = setup_keys_sigs(1);
+        let preimage1 = [0x11; 32];
+        let preimage2 = [0x22; 32];
+        let sha256_hash = sha256::Hash::hash(&preimage1);
+        let hash160_hash = hash160::Hash::hash(&preimage2);
+        let ms = Miniscript::<bitcoin::PublicKey, crate::Segwitv0>::from_str(&format!(
+            "and_v(v:sha256({}),and_v(v:hash160({}),pk({})))",
+            sha256_hash, hash160_hash, pks[0]
+        ))
+        .unwrap();
+        let script = ms.encode();
+        let spk = bitcoin::ScriptBuf::new_p2wsh(&script.wscript_hash());
+        let script_sig = bitcoin::ScriptBuf::new();
+        let witness = Witness::from_slice(&[
+            der_sigs[0].clone(),
+            preimage2.to_vec(),
+            preimage1.to_vec(),
+            script.to_bytes(),
+        ]);
+        let interpreter = Interpreter::from_txdata(
+            &spk,
+            &script_sig,
+            &witness,
+            Sequence::MAX,
+            absolute::LockTime::ZERO,
+        )
+        .unwrap();
+        let preimages = interpreter.revealed_preimages().unwrap();
+        assert_eq!(preimages.len(), 2);
+        assert_eq!(preimages[&HashLockType::Sha256(sha256_hash)], preimage1);
+        assert_eq!(preimages[&HashLockType::Hash160(hash160_hash)], preimage2);
+
+        // Wrong preimages are not revealed
+        let witness = Witness::from_slice(&[
+            der_sigs[0].clone(),
+            preimage1.to_vec(),
+            preimage1.to_vec(),
+            script.to_bytes(),
+        ]);
+        let interpreter = Interpreter::from_txdata(
+            &spk,
+            &script_sig,
+            &witness,
+            Sequence::MAX,
+            absolute::LockTime::ZERO,
+        )
+        .unwrap();
+        assert!(interpreter.revealed_preimages().is_err());
+    }
+
+    #[test]
+    fn satisfied_policy() {
+        let (pks, der_sigs, ..) = setup_keys_sigs(2);
+        let ms = Miniscript::<bitcoin::PublicKey, crate::Segwitv0>::from_str(&format!(
+            "or_d(pk({}),and_v(v:pk({}),after(1000)))",
+            pks[0], pks[1]
+        ))
+        .unwrap();
+        let script = ms.encode();
+        let spk = bitcoin::ScriptBuf::new_p2wsh(&script.wscript_hash());
+        let script_sig = bitcoin::ScriptBuf::new();
+        let lock_time = absolute::LockTime::from_height(1002).unwrap();
+        let spend = |witness: &[Vec<u8>]| {
+            let witness = Witness::from_slice(witness);
+            let interpreter =
+                Interpreter::from_txdata(&spk, &script_sig, &witness, Sequence::MAX, lock_time)
+                    .unwrap();
+            interpreter.iter_assume_sigs().satisfied_policy()
+        };
+
+        let policy = spend(&[der_sigs[0].clone(), script.to_bytes()]).unwrap();
+        assert_eq!(policy, semantic::Policy::Key(pks[0]));
+        let policy = spend(&[der_sigs[1].clone(), vec![], script.to_bytes()]).unwrap();
+        assert_eq!(
+            policy,
+            semantic::Policy::from_str(&format!("and(pk({}),after(1000))", pks[1])).unwrap()
+        );
+        assert!(spend(&[vec![], vec![], script.to_bytes()]).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "raw-script")]
+    fn script_policy_flags() {
+        use bitcoin::blockdata::opcodes::all::*;
+
+        let script_sig = bitcoin::ScriptBuf::new();
+
+        let script = bitcoin::script::Builder::new()
+            .push_opcode(OP_IF)
+            .push_opcode(OP_PUSHDATA1)
+            .push_slice([3])
+            .push_opcode(OP_DROP)
+            .push_slice([5, 0])
+            .push_opcode(OP_1ADD)
+            .push_opcode(OP_DROP)
+            .push_opcode(OP_ENDIF)
+            .push_opcode(OP_DROP)
+            .push_opcode(OP_PUSHNUM_1)
+            .into_script();
+        let spk = bitcoin::ScriptBuf::new_p2wsh(&script.wscript_hash());
+        let witness = Witness::from_slice(&[vec![0; 81], vec![2], script.to_bytes()]);
+        let interpreter = Interpreter::from_txdata(
+            &spk,
+            &script_sig,
+            &witness,
+            Sequence::MAX,
+            absolute::LockTime::ZERO,
+        )
+        .unwrap();
+        // The spend is valid, but not standard
+        assert!(interpreter.iter_assume_sigs().all(|res| res.is_ok()));
+        assert_eq!(interpreter.policy_violations(PolicyFlags::new()), vec![]);
+        assert_eq!(
+            interpreter.policy_violations(PolicyFlags::standard()),
+            vec![
+                PolicyViolation::OversizedElement(81, 80),
+                PolicyViolation::NonMinimalIf(vec![2]),
+                PolicyViolation::NonMinimalPush(OP_PUSHDATA1, vec![3]),
+                PolicyViolation::NonMinimalNumber(vec![5, 0]),
+            ]
+        );
+        assert_eq!(
+            interpreter.policy_violations(PolicyFlags::new().minimal_if()),
+            vec![PolicyViolation::NonMinimalIf(vec![2])]
+        );
+    }
+
+    #[test]
+    fn policy_flags() {
+        let (pks, der_sigs, ..) = setup_keys_sigs(1);
+        let script_sig = bitcoin::ScriptBuf::new();
+
+        // Flip the S value of the signature to its high counterpart
+        let mut sig = bitcoin::ecdsa::Signature::from_slice(&der_sigs[0]).unwrap();
+        let mut compact = sig.signature.serialize_compact();
+        let order = secp256k1::constants::CURVE_ORDER;
+        let mut borrow = 0;
+        for i in (32..64).rev() {
+            let diff = i16::from(order[i - 32]) - i16::from(compact[i]) - borrow;
+            compact[i] = diff.rem_euclid(256) as u8;
+            borrow = i16::from(diff < 0);
+        }
+        sig.signature = secp256k1::ecdsa::Signature::from_compact(&compact).unwrap();
+        let spk = bitcoin::ScriptBuf::new_p2wpkh(&pks[0].wpubkey_hash().unwrap());
+        let witness = Witness::from_slice(&[sig.to_vec(), pks[0].to_bytes()]);
+        let interpreter = Interpreter::from_txdata(
+            &spk,
+            &script_sig,
+            &witness,
+            Sequence::MAX,
+            absolute::LockTime::ZERO,
+        )
+        .unwrap();
+        assert_eq!(
+            interpreter.policy_violations(PolicyFlags::standard()),
+            vec![PolicyViolation::HighS(sig)]
+        );
+        let witness = Witness::from_slice(&[der_sigs[0].clone(), pks[0].to_bytes()]);
+        let interpreter = Interpreter::from_txdata(
+            &spk,
+            &script_sig,
+            &witness,
+            Sequence::MAX,
+            absolute::LockTime::ZERO,
+        )
+        .unwrap();
+        assert_eq!(interpreter.policy_violations(PolicyFlags::standard()), vec![]);
+    }
+
+    #[test]
+    fn inferred_tr_descriptors() {
+        let (pks, ..) = setup_keys_sigs(3);
+        let xonly = |i: usize| pks[i].inner.x_only_public_key().0;
+        // x-only keys are inferred with an even prefix
+        let even = |i: usize| xonly(i).to_public_key();
+        let script_sig = bitcoin::ScriptBuf::new();
+        let spend = |desc: &str| {
+            let desc = Descriptor::<bitcoin::key::XOnlyPublicKey>::from_str(desc).unwrap();
+            let tr = match desc {
+                Descriptor::Tr(ref tr) => tr.clone(),
+                _ => unreachable!(),
+            };
+            let (_, leaf) = tr.iter_scripts().next().unwrap();
+            let script = leaf.encode();
+            let ctrl_blk = tr
+                .spend_info()
+                .control_block(&(script.clone(), taproot::LeafVersion::TapScript))
+                .unwrap();
+            let witness = Witness::from_slice(&[vec![], script.to_bytes(), ctrl_blk.serialize()]);
+            let interpreter = Interpreter::from_txdata(
+                &desc.script_pubkey(),
+                &script_sig,
+                &witness,
+                Sequence::MAX,
+                absolute::LockTime::ZERO,
+            )
+            .unwrap();
+            assert_eq!(interpreter.control_block(), Some(&ctrl_blk));
+            (interpreter.inferred_descriptor_string(), interpreter.inferred_descriptor())
+        };
+
+        // A single leaf tree is entirely revealed by the spend
+        let (desc_str, desc) = spend(&format!("tr({},pk({}))", xonly(0), xonly(1)));
+        assert_eq!(desc_str, format!("tr({},pk({}))", even(0), even(1)));
+        assert_eq!(desc.unwrap(), Descriptor::from_str(&desc_str).unwrap());
+
+        // Otherwise the unspent branches are hidden
+        let tree = format!("{{pk({}),{{pk({}),pk({})}}}}", xonly(1), xonly(2), xonly(0));
+        let (desc_str, desc) = spend(&format!("tr({},{})", xonly(0), tree));
+        let branch = Descriptor::<bitcoin::key::XOnlyPublicKey>::from_str(&format!(
+            "tr({},{{pk({}),pk({})}})",
+            xonly(0),
+            xonly(2),
+            xonly(0)
+        ))
+        .unwrap();
+        let branch = match branch {
+            Descriptor::Tr(ref tr) => tr.spend_info().merkle_root().unwrap(),
+            _ => unreachable!(),
+        };
+        assert_eq!(desc_str, format!("tr({},{{pk({}),hidden({})}})", even(0), even(1), branch));
+        assert!(desc.is_err());
+    }
+
     // By design there is no support for parse a miniscript with BitcoinKey
     // because it does not implement FromStr
     fn no_checks_ms(ms: &str) -> Miniscript<BitcoinKey, NoChecks> {
@@ -0,0 +1,172 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Standardness policy
+//!
+//! On top of consensus rules, nodes only relay transactions whose inputs
+//! follow a number of standardness rules. Bitcoin Core enforces them with
+//! script verification flags; this module emulates some of them so that a
+//! spend which would not be relayed can be detected before broadcast.
+
+use core::fmt;
+
+use bitcoin::blockdata::opcodes::all::{OP_PUSHDATA1, OP_PUSHDATA2};
+use bitcoin::hex::DisplayHex;
+use bitcoin::{ecdsa, Opcode};
+
+use crate::prelude::*;
+
+/// Standardness rules to check a spend against
+///
+/// Each flag corresponds to the Bitcoin Core script verification flag of the
+/// same name.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default, Hash)]
+pub struct PolicyFlags {
+    /// `MINIMALDATA`: pushes and numeric operands use their shortest encoding
+    pub minimal_data: bool,
+    /// `MINIMALIF`: arguments of `OP_IF` and `OP_NOTIF` in witness scripts
+    /// are either empty or `0x01`
+    pub minimal_if: bool,
+    /// `LOW_S`: ECDSA signatures have an S value in the lower half of the
+    /// curve order
+    pub low_s: bool,
+    /// Initial witness stack elements of script spends are at most 80 bytes
+    pub element_size: bool,
+}
+
+impl PolicyFlags {
+    /// Create a new PolicyFlags that checks none of the rules
+    pub fn new() -> PolicyFlags { PolicyFlags::default() }
+
+    /// Create a new PolicyFlags that checks all of the rules, as standardness
+    /// does
+    pub fn standard() -> PolicyFlags {
+        PolicyFlags { minimal_data: true, minimal_if: true, low_s: true, element_size: true }
+    }
+
+    /// Builder that checks `MINIMALDATA`.
+    pub fn minimal_data(mut self) -> PolicyFlags {
+        self.minimal_data = true;
+        self
+    }
+
+    /// Builder that checks `MINIMALIF`.
+    pub fn minimal_if(mut self) -> PolicyFlags {
+        self.minimal_if = true;
+        self
+    }
+
+    /// Builder that checks `LOW_S`.
+    pub fn low_s(mut self) -> PolicyFlags {
+        self.low_s = true;
+        self
+    }
+
+    /// Builder that checks the size of witness stack elements.
+    pub fn element_size(mut self) -> PolicyFlags {
+        self.element_size = true;
+        self
+    }
+}
+
+/// A standardness rule broken by a spend
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PolicyViolation {
+    /// Data was pushed with a longer opcode than needed (`MINIMALDATA`)
+    NonMinimalPush(Opcode, Vec<u8>),
+    /// A numeric operand was not minimally encoded (`MINIMALDATA`)
+    NonMinimalNumber(Vec<u8>),
+    /// The argument of an `OP_IF`/`OP_NOTIF` was neither empty nor `0x01`
+    /// (`MINIMALIF`)
+    NonMinimalIf(Vec<u8>),
+    /// An ECDSA signature has a high S value (`LOW_S`)
+    HighS(ecdsa::Signature),
+    /// A witness stack element is larger than allowed, with its size and the
+    /// limit
+    OversizedElement(usize, usize),
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PolicyViolation::NonMinimalPush(op, ref data) => {
+                write!(f, "{} pushing {:x} is not minimal", op, data.as_hex())
+            }
+            PolicyViolation::NonMinimalNumber(ref n) => {
+                write!(f, "number {:x} is not minimally encoded", n.as_hex())
+            }
+            PolicyViolation::NonMinimalIf(ref arg) => {
+                write!(f, "argument {:x} of conditional is not minimal", arg.as_hex())
+            }
+            PolicyViolation::HighS(ref sig) => write!(f, "signature {} has a high S value", sig),
+            PolicyViolation::OversizedElement(size, limit) => {
+                write!(f, "stack element of {} bytes exceeds the limit of {} bytes", size, limit)
+            }
+        }
+    }
+}
+
+// Whether `data`, pushed with `opcode`, could not have been pushed with a
+// shorter opcode
+pub(super) fn is_minimal_push(opcode: Opcode, data: &[u8]) -> bool {
+    let opcode = opcode.to_u8();
+    match data.len() {
+        0 => opcode == 0,
+        1 if (1..=16).contains(&data[0]) => false,
+        1 if data[0] == 0x81 => false,
+        len if len <= 75 => usize::from(opcode) == len,
+        len if len <= 255 => opcode == OP_PUSHDATA1.to_u8(),
+        len if len <= 65535 => opcode == OP_PUSHDATA2.to_u8(),
+        _ => true,
+    }
+}
+
+// Whether `n` is a script number without any superfluous trailing byte
+#[cfg(any(feature = "raw-script", test))]
+pub(super) fn is_minimal_num(n: &[u8]) -> bool {
+    match n.split_last() {
+        // The last byte may only be zero or the sign if the previous byte
+        // would otherwise be interpreted as a sign
+        Some((last, rest)) if last & 0x7f == 0 => {
+            rest.last().map(|prev| prev & 0x80 != 0).unwrap_or(false)
+        }
+        _ => true,
+    }
+}
+
+// Parses an ECDSA signature with a high S value, if `sig` is one
+pub(super) fn high_s_sig(sig: &[u8]) -> Option<ecdsa::Signature> {
+    let sig = ecdsa::Signature::from_slice(sig).ok()?;
+    let mut normalized = sig.signature;
+    normalized.normalize_s();
+    if normalized == sig.signature {
+        None
+    } else {
+        Some(sig)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minimal_encodings() {
+        assert!(is_minimal_push(Opcode::from(0), &[]));
+        assert!(!is_minimal_push(OP_PUSHDATA1, &[]));
+        assert!(!is_minimal_push(Opcode::from(1), &[5]));
+        assert!(!is_minimal_push(Opcode::from(1), &[0x81]));
+        assert!(is_minimal_push(Opcode::from(1), &[0x11]));
+        assert!(is_minimal_push(Opcode::from(32), &[0; 32]));
+        assert!(!is_minimal_push(OP_PUSHDATA1, &[0; 32]));
+        assert!(is_minimal_push(OP_PUSHDATA1, &[0; 76]));
+        assert!(!is_minimal_push(OP_PUSHDATA2, &[0; 76]));
+
+        assert!(is_minimal_num(&[]));
+        assert!(is_minimal_num(&[0x01]));
+        assert!(is_minimal_num(&[0xff, 0x00]));
+        assert!(is_minimal_num(&[0xff, 0x80]));
+        assert!(!is_minimal_num(&[0x00]));
+        assert!(!is_minimal_num(&[0x80]));
+        assert!(!is_minimal_num(&[0x01, 0x00]));
+    }
+}
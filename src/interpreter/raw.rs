@@ -0,0 +1,719 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Raw script execution
+//!
+//! Scripts which are not Miniscript cannot be walked fragment by fragment, so
+//! the interpreter falls back to executing them opcode by opcode. Signatures,
+//! hashlocks and timelocks checked along the way are reported as the same
+//! [`SatisfiedConstraint`]s the Miniscript interpreter yields.
+//!
+//! This is not a consensus implementation. It covers the opcodes enabled in
+//! legacy, segwit v0 and tapscript scripts except `OP_CODESEPARATOR`, and
+//! enforces the consensus limits on the number of opcodes, the size of pushes
+//! and the size of the stack, but not the tapscript signature budget. It does
+//! not enforce policy rules such as minimal encodings, it can instead report
+//! where they are broken, see [`PolicyFlags`].
+
+use core::cmp;
+use core::convert::TryFrom;
+
+use bitcoin::blockdata::opcodes::{self, Class, ClassifyContext, Ordinary};
+use bitcoin::blockdata::script::{self, Instruction, InstructionIndices};
+use bitcoin::hashes::{hash160, ripemd160, sha1, sha256, Hash};
+use bitcoin::{absolute, Opcode, Sequence};
+
+use super::inner::ScriptType;
+use super::policy::{self, PolicyFlags, PolicyViolation};
+use super::stack::Stack;
+use super::{verify_sersig, BitcoinKey, Error, HashLockType, KeySigPair, SatisfiedConstraint};
+use crate::hash256;
+use crate::miniscript::limits::{MAX_OPS_PER_SCRIPT, MAX_SCRIPT_ELEMENT_SIZE, MAX_STACK_SIZE};
+use crate::prelude::*;
+
+/// Maximum size of a numeric operand, in bytes
+const MAX_NUM_SIZE: usize = 4;
+/// Maximum size of a `CHECKLOCKTIMEVERIFY`/`CHECKSEQUENCEVERIFY` operand, in bytes
+const MAX_LOCKTIME_NUM_SIZE: usize = 5;
+/// Maximum number of keys in a `CHECKMULTISIG`
+const MAX_MULTISIG_KEYS: i64 = 20;
+
+/// Execution state of a script which is not Miniscript
+pub(super) struct Exec<'intp> {
+    script: &'intp bitcoin::Script,
+    instructions: InstructionIndices<'intp>,
+    ctx: ClassifyContext,
+    // Legacy scripts may leave extra elements on the stack
+    clean_stack: bool,
+    stack: Vec<Vec<u8>>,
+    // Smallest length the stack ever had, see `Stack`
+    low_water: usize,
+    alt_stack: Vec<Vec<u8>>,
+    // Whether each enclosing IF/NOTIF branch is being executed
+    conditions: Vec<bool>,
+    // Constraints satisfied by the last opcode which were not yielded yet
+    pending: VecDeque<SatisfiedConstraint>,
+    // Output of the last hash opcode applied to a 32-byte element, with
+    // that element, so that a following EQUAL can report a hashlock
+    last_hash: Option<(Vec<u8>, HashLockType, [u8; 32])>,
+    // Last opcode executed, reported in traces
+    last_op: Option<Opcode>,
+    // Non-push opcodes counted against `MAX_OPS_PER_SCRIPT`, outside tapscript
+    op_count: usize,
+    // Whether the initial stack was checked against the limits
+    started: bool,
+    done: bool,
+    // Standardness rules whose violations are recorded
+    policy: PolicyFlags,
+    violations: Vec<PolicyViolation>,
+}
+
+impl<'intp> Exec<'intp> {
+    /// Starts the execution of `script` over the initial `stack`
+    pub(super) fn new(
+        script: &'intp bitcoin::Script,
+        stack: &Stack<'_>,
+        script_type: ScriptType,
+    ) -> Self {
+        let (ctx, clean_stack) = match script_type {
+            ScriptType::Bare | ScriptType::Sh => (ClassifyContext::Legacy, false),
+            ScriptType::Wsh | ScriptType::ShWsh => (ClassifyContext::Legacy, true),
+            ScriptType::Tr => (ClassifyContext::TapScript, true),
+        };
+        // BIP 342: a tapscript with an OP_SUCCESSx anywhere in it succeeds
+        // unconditionally, without being executed, unless it fails to decode
+        // before that opcode
+        let has_success_op = ctx == ClassifyContext::TapScript
+            && script
+                .instructions()
+                .map_while(Result::ok)
+                .any(|ins| match ins {
+                    Instruction::Op(op) => matches!(op.classify(ctx), Class::SuccessOp),
+                    Instruction::PushBytes(_) => false,
+                });
+        Exec {
+            script,
+            instructions: script.instruction_indices(),
+            ctx,
+            clean_stack,
+            stack: stack.to_byte_vecs(),
+            low_water: stack.low_water(),
+            alt_stack: vec![],
+            conditions: vec![],
+            pending: VecDeque::new(),
+            last_hash: None,
+            last_op: None,
+            op_count: 0,
+            started: false,
+            done: has_success_op,
+            policy: PolicyFlags::new(),
+            violations: vec![],
+        }
+    }
+
+    /// Records the violations of the standardness rules of `flags` while
+    /// executing
+    pub(super) fn check_policy(mut self, flags: PolicyFlags) -> Self {
+        self.policy = flags;
+        self
+    }
+
+    /// The standardness violations recorded so far
+    pub(super) fn violations(&self) -> &[PolicyViolation] { &self.violations }
+
+    /// The stack, bottom first
+    pub(super) fn stack(&self) -> &[Vec<u8>] { &self.stack }
+
+    /// Smallest number of elements the stack had since execution started
+    pub(super) fn low_water(&self) -> usize { self.low_water }
+
+    /// The last opcode executed, if any
+    pub(super) fn last_op(&self) -> Option<Opcode> { self.last_op }
+
+    /// Executes the script up to the next satisfied constraint, checking the
+    /// final stack once all of the script was executed
+    pub(super) fn step<'a>(
+        &mut self,
+        verify_sig: &mut Box<dyn FnMut(&KeySigPair) -> bool + 'a>,
+        sequence: Sequence,
+        lock_time: absolute::LockTime,
+    ) -> Option<Result<SatisfiedConstraint, Error>> {
+        if !self.started && !self.done {
+            self.started = true;
+            if let Err(e) = self.check_initial_stack() {
+                return Some(Err(e));
+            }
+        }
+        loop {
+            if let Some(constraint) = self.pending.pop_front() {
+                return Some(Ok(constraint));
+            }
+            if self.done {
+                return None;
+            }
+            let res = match self.instructions.next() {
+                Some(Ok((pos, ins))) => self
+                    .execute(pos, ins, verify_sig, sequence, lock_time)
+                    .and_then(|()| self.check_stack_size()),
+                Some(Err(e)) => Err(Error::Script(e)),
+                None => {
+                    self.done = true;
+                    self.check_final_stack()
+                }
+            };
+            if let Err(e) = res {
+                return Some(Err(e));
+            }
+        }
+    }
+
+    fn check_initial_stack(&self) -> Result<(), Error> {
+        if let Some(elem) = self
+            .stack
+            .iter()
+            .find(|elem| elem.len() > MAX_SCRIPT_ELEMENT_SIZE)
+        {
+            return Err(Error::MaxPushSizeExceeded(elem.len()));
+        }
+        self.check_stack_size()
+    }
+
+    fn check_stack_size(&self) -> Result<(), Error> {
+        if self.stack.len() + self.alt_stack.len() > MAX_STACK_SIZE {
+            Err(Error::MaxStackSizeExceeded)
+        } else {
+            Ok(())
+        }
+    }
+
+    // Counts `n` opcodes against the limit, which tapscript does not have
+    fn count_ops(&mut self, n: usize) -> Result<(), Error> {
+        if self.ctx == ClassifyContext::TapScript {
+            return Ok(());
+        }
+        self.op_count += n;
+        if self.op_count > MAX_OPS_PER_SCRIPT {
+            Err(Error::MaxOpCountExceeded)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_final_stack(&self) -> Result<(), Error> {
+        if !self.conditions.is_empty() {
+            return Err(Error::UnbalancedConditional);
+        }
+        match self.stack.last() {
+            Some(top)
+                if script::read_scriptbool(top) && (!self.clean_stack || self.stack.len() == 1) =>
+            {
+                Ok(())
+            }
+            _ => Err(Error::ScriptSatisfactionError),
+        }
+    }
+
+    fn execute<'a>(
+        &mut self,
+        pos: usize,
+        ins: Instruction<'_>,
+        verify_sig: &mut Box<dyn FnMut(&KeySigPair) -> bool + 'a>,
+        sequence: Sequence,
+        lock_time: absolute::LockTime,
+    ) -> Result<(), Error> {
+        use bitcoin::blockdata::opcodes::all::{OP_CLTV, OP_CSV, OP_PUSHNUM_16};
+
+        // The limits apply to branches which are not executed too
+        let executing = self.conditions.iter().all(|cond| *cond);
+        let op = match ins {
+            Instruction::PushBytes(bytes) => {
+                if bytes.len() > MAX_SCRIPT_ELEMENT_SIZE {
+                    return Err(Error::MaxPushSizeExceeded(bytes.len()));
+                }
+                if executing {
+                    let opcode = Opcode::from(self.script.as_bytes()[pos]);
+                    let bytes = bytes.as_bytes().to_vec();
+                    if self.policy.minimal_data && !policy::is_minimal_push(opcode, &bytes) {
+                        self.violations
+                            .push(PolicyViolation::NonMinimalPush(opcode, bytes.clone()));
+                    }
+                    self.stack.push(bytes);
+                }
+                return Ok(());
+            }
+            Instruction::Op(op) => op,
+        };
+        self.last_op = Some(op);
+        if op.to_u8() > OP_PUSHNUM_16.to_u8() {
+            self.count_ops(1)?;
+        }
+        let ord = match op.classify(self.ctx) {
+            Class::PushNum(n) => {
+                if executing {
+                    self.push_num(n.into());
+                }
+                return Ok(());
+            }
+            Class::PushBytes(_) => unreachable!("pushes are decoded as Instruction::PushBytes"),
+            Class::SuccessOp => unreachable!("tapscripts with OP_SUCCESSx are not executed"),
+            Class::IllegalOp => return Err(Error::ScriptFailed(op)),
+            Class::ReturnOp if executing => return Err(Error::ScriptFailed(op)),
+            Class::NoOp if executing && op == OP_CLTV => return self.check_lock_time(lock_time),
+            Class::NoOp if executing && op == OP_CSV => return self.check_sequence(sequence),
+            Class::ReturnOp | Class::NoOp => return Ok(()),
+            Class::Ordinary(ord) => ord,
+        };
+
+        match ord {
+            Ordinary::OP_IF | Ordinary::OP_NOTIF => {
+                let cond = if executing {
+                    let top = self.pop()?;
+                    // MINIMALIF only applies to witness scripts
+                    if self.policy.minimal_if && self.clean_stack && !top.is_empty() && top != [1] {
+                        self.violations
+                            .push(PolicyViolation::NonMinimalIf(top.clone()));
+                    }
+                    script::read_scriptbool(&top) == (ord == Ordinary::OP_IF)
+                } else {
+                    false
+                };
+                self.conditions.push(cond);
+                return Ok(());
+            }
+            Ordinary::OP_ELSE => {
+                match self.conditions.last_mut() {
+                    Some(cond) => *cond = !*cond,
+                    None => return Err(Error::UnbalancedConditional),
+                }
+                return Ok(());
+            }
+            Ordinary::OP_ENDIF => {
+                self.conditions.pop().ok_or(Error::UnbalancedConditional)?;
+                return Ok(());
+            }
+            _ if !executing => return Ok(()),
+            _ => {}
+        }
+
+        match ord {
+            Ordinary::OP_VERIFY => {
+                let top = self.pop()?;
+                self.verify(op, script::read_scriptbool(&top))?;
+            }
+            Ordinary::OP_TOALTSTACK => {
+                let top = self.pop()?;
+                self.alt_stack.push(top);
+            }
+            Ordinary::OP_FROMALTSTACK => {
+                let top = self.alt_stack.pop().ok_or(Error::UnexpectedStackEnd)?;
+                self.stack.push(top);
+            }
+            Ordinary::OP_2DROP => {
+                self.pop()?;
+                self.pop()?;
+            }
+            Ordinary::OP_2DUP => self.copy(&[1, 1])?,
+            Ordinary::OP_3DUP => self.copy(&[2, 2, 2])?,
+            Ordinary::OP_2OVER => self.copy(&[3, 3])?,
+            Ordinary::OP_2ROT => self.roll(&[5, 5])?,
+            Ordinary::OP_2SWAP => self.roll(&[3, 3])?,
+            Ordinary::OP_DROP => {
+                self.pop()?;
+            }
+            Ordinary::OP_DUP => self.copy(&[0])?,
+            Ordinary::OP_NIP => {
+                self.remove(1)?;
+            }
+            Ordinary::OP_OVER => self.copy(&[1])?,
+            Ordinary::OP_PICK | Ordinary::OP_ROLL => {
+                let n = self.pop_num()?;
+                let n = usize::try_from(n).map_err(|_| Error::ScriptFailed(op))?;
+                if ord == Ordinary::OP_PICK {
+                    self.copy(&[n])?;
+                } else {
+                    self.roll(&[n])?;
+                }
+            }
+            Ordinary::OP_ROT => self.roll(&[2])?,
+            Ordinary::OP_SWAP => self.roll(&[1])?,
+            Ordinary::OP_TUCK => {
+                let top = self.peek(0)?.clone();
+                if self.stack.len() < 2 {
+                    return Err(Error::UnexpectedStackEnd);
+                }
+                self.stack.insert(self.stack.len() - 2, top);
+            }
+            Ordinary::OP_IFDUP => {
+                if script::read_scriptbool(self.peek(0)?) {
+                    self.copy(&[0])?;
+                }
+            }
+            Ordinary::OP_DEPTH => self.push_num(self.stack.len() as i64),
+            Ordinary::OP_SIZE => {
+                let size = self.peek(0)?.len();
+                self.push_num(size as i64);
+            }
+            Ordinary::OP_EQUAL | Ordinary::OP_EQUALVERIFY => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                let equal = a == b;
+                if let Some((hash, lock, preimage)) = self.last_hash.take() {
+                    if equal && hash == a {
+                        self.pending
+                            .push_back(SatisfiedConstraint::HashLock { hash: lock, preimage });
+                    }
+                }
+                if ord == Ordinary::OP_EQUAL {
+                    self.push_bool(equal);
+                } else {
+                    self.verify(op, equal)?;
+                }
+            }
+            Ordinary::OP_1ADD
+            | Ordinary::OP_1SUB
+            | Ordinary::OP_NEGATE
+            | Ordinary::OP_ABS
+            | Ordinary::OP_NOT
+            | Ordinary::OP_0NOTEQUAL => {
+                let n = self.pop_num()?;
+                let res = match ord {
+                    Ordinary::OP_1ADD => n + 1,
+                    Ordinary::OP_1SUB => n - 1,
+                    Ordinary::OP_NEGATE => -n,
+                    Ordinary::OP_ABS => n.abs(),
+                    Ordinary::OP_NOT => (n == 0).into(),
+                    _ => (n != 0).into(),
+                };
+                self.push_num(res);
+            }
+            Ordinary::OP_ADD
+            | Ordinary::OP_SUB
+            | Ordinary::OP_BOOLAND
+            | Ordinary::OP_BOOLOR
+            | Ordinary::OP_NUMEQUAL
+            | Ordinary::OP_NUMEQUALVERIFY
+            | Ordinary::OP_NUMNOTEQUAL
+            | Ordinary::OP_LESSTHAN
+            | Ordinary::OP_GREATERTHAN
+            | Ordinary::OP_LESSTHANOREQUAL
+            | Ordinary::OP_GREATERTHANOREQUAL
+            | Ordinary::OP_MIN
+            | Ordinary::OP_MAX => {
+                let b = self.pop_num()?;
+                let a = self.pop_num()?;
+                let res = match ord {
+                    Ordinary::OP_ADD => a + b,
+                    Ordinary::OP_SUB => a - b,
+                    Ordinary::OP_BOOLAND => (a != 0 && b != 0).into(),
+                    Ordinary::OP_BOOLOR => (a != 0 || b != 0).into(),
+                    Ordinary::OP_NUMEQUAL | Ordinary::OP_NUMEQUALVERIFY => (a == b).into(),
+                    Ordinary::OP_NUMNOTEQUAL => (a != b).into(),
+                    Ordinary::OP_LESSTHAN => (a < b).into(),
+                    Ordinary::OP_GREATERTHAN => (a > b).into(),
+                    Ordinary::OP_LESSTHANOREQUAL => (a <= b).into(),
+                    Ordinary::OP_GREATERTHANOREQUAL => (a >= b).into(),
+                    Ordinary::OP_MIN => a.min(b),
+                    _ => a.max(b),
+                };
+                if ord == Ordinary::OP_NUMEQUALVERIFY {
+                    self.verify(op, res != 0)?;
+                } else {
+                    self.push_num(res);
+                }
+            }
+            Ordinary::OP_WITHIN => {
+                let max = self.pop_num()?;
+                let min = self.pop_num()?;
+                let n = self.pop_num()?;
+                self.push_bool(min <= n && n < max);
+            }
+            Ordinary::OP_RIPEMD160 => {
+                let data = self.pop()?;
+                let hash = ripemd160::Hash::hash(&data);
+                self.push_hash(&hash[..], Some(HashLockType::Ripemd160(hash)), &data);
+            }
+            Ordinary::OP_SHA1 => {
+                let data = self.pop()?;
+                self.push_hash(&sha1::Hash::hash(&data)[..], None, &data);
+            }
+            Ordinary::OP_SHA256 => {
+                let data = self.pop()?;
+                let hash = sha256::Hash::hash(&data);
+                self.push_hash(&hash[..], Some(HashLockType::Sha256(hash)), &data);
+            }
+            Ordinary::OP_HASH160 => {
+                let data = self.pop()?;
+                let hash = hash160::Hash::hash(&data);
+                self.push_hash(&hash[..], Some(HashLockType::Hash160(hash)), &data);
+            }
+            Ordinary::OP_HASH256 => {
+                let data = self.pop()?;
+                let hash = hash256::Hash::hash(&data);
+                self.push_hash(&hash[..], Some(HashLockType::Hash256(hash)), &data);
+            }
+            Ordinary::OP_CHECKSIG | Ordinary::OP_CHECKSIGVERIFY => {
+                let pk = self.pop()?;
+                let sig = self.pop()?;
+                let res = self.check_sig(verify_sig, &pk, &sig)?;
+                if ord == Ordinary::OP_CHECKSIG {
+                    self.push_bool(res);
+                } else {
+                    self.verify(op, res)?;
+                }
+            }
+            Ordinary::OP_CHECKSIGADD => {
+                let pk = self.pop()?;
+                let n = self.pop_num()?;
+                let sig = self.pop()?;
+                let res = self.check_sig(verify_sig, &pk, &sig)?;
+                self.push_num(n + i64::from(res));
+            }
+            Ordinary::OP_CHECKMULTISIG | Ordinary::OP_CHECKMULTISIGVERIFY => {
+                let res = self.check_multisig(op, verify_sig)?;
+                if ord == Ordinary::OP_CHECKMULTISIG {
+                    self.push_bool(res);
+                } else {
+                    self.verify(op, res)?;
+                }
+            }
+            // OP_CODESEPARATOR would change the scriptCode used for sighashes,
+            // the remaining opcodes are pushes which are decoded as such
+            _ => return Err(Error::UnsupportedOpcode(op)),
+        }
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<Vec<u8>, Error> {
+        let elem = self.stack.pop().ok_or(Error::UnexpectedStackEnd)?;
+        self.low_water = cmp::min(self.low_water, self.stack.len());
+        Ok(elem)
+    }
+
+    fn pop_num(&mut self) -> Result<i64, Error> {
+        let elem = self.pop()?;
+        self.check_minimal_num(&elem);
+        read_num(&elem, MAX_NUM_SIZE)
+    }
+
+    fn check_minimal_num(&mut self, n: &[u8]) {
+        if self.policy.minimal_data && !policy::is_minimal_num(n) {
+            self.violations
+                .push(PolicyViolation::NonMinimalNumber(n.to_vec()));
+        }
+    }
+
+    fn check_low_s(&mut self, sig: &[u8]) {
+        if self.policy.low_s && self.ctx == ClassifyContext::Legacy {
+            if let Some(sig) = policy::high_s_sig(sig) {
+                self.violations.push(PolicyViolation::HighS(sig));
+            }
+        }
+    }
+
+    // Element at `depth` from the top of the stack
+    fn peek(&self, depth: usize) -> Result<&Vec<u8>, Error> {
+        self.stack
+            .len()
+            .checked_sub(depth + 1)
+            .map(|i| &self.stack[i])
+            .ok_or(Error::UnexpectedStackEnd)
+    }
+
+    fn remove(&mut self, depth: usize) -> Result<Vec<u8>, Error> {
+        match self.stack.len().checked_sub(depth + 1) {
+            Some(i) => {
+                self.low_water = cmp::min(self.low_water, i);
+                Ok(self.stack.remove(i))
+            }
+            None => Err(Error::UnexpectedStackEnd),
+        }
+    }
+
+    // Pushes copies of the elements at each of `depths` in turn
+    fn copy(&mut self, depths: &[usize]) -> Result<(), Error> {
+        for depth in depths {
+            let elem = self.peek(*depth)?.clone();
+            self.stack.push(elem);
+        }
+        Ok(())
+    }
+
+    // Moves the elements at each of `depths` in turn to the top of the stack
+    fn roll(&mut self, depths: &[usize]) -> Result<(), Error> {
+        for depth in depths {
+            let elem = self.remove(*depth)?;
+            self.stack.push(elem);
+        }
+        Ok(())
+    }
+
+    fn push_bool(&mut self, b: bool) { self.stack.push(if b { vec![1] } else { vec![] }); }
+
+    fn push_num(&mut self, n: i64) {
+        let mut buf = [0; 8];
+        let len = script::write_scriptint(&mut buf, n);
+        self.stack.push(buf[..len].to_vec());
+    }
+
+    fn push_hash(&mut self, hash: &[u8], lock: Option<HashLockType>, data: &[u8]) {
+        self.last_hash = match (lock, <[u8; 32]>::try_from(data)) {
+            (Some(lock), Ok(preimage)) => Some((hash.to_vec(), lock, preimage)),
+            _ => None,
+        };
+        self.stack.push(hash.to_vec());
+    }
+
+    fn verify(&self, op: Opcode, res: bool) -> Result<(), Error> {
+        if res {
+            Ok(())
+        } else {
+            Err(Error::ScriptFailed(op))
+        }
+    }
+
+    // Checks a signature the way CHECKSIG does. Empty signatures fail the
+    // check, while non-empty invalid ones fail the whole script.
+    fn check_sig<'a>(
+        &mut self,
+        verify_sig: &mut Box<dyn FnMut(&KeySigPair) -> bool + 'a>,
+        pk: &[u8],
+        sig: &[u8],
+    ) -> Result<bool, Error> {
+        if sig.is_empty() {
+            return Ok(false);
+        }
+        self.check_low_s(sig);
+        let pk: BitcoinKey = match self.ctx {
+            ClassifyContext::TapScript => bitcoin::key::XOnlyPublicKey::from_slice(pk)
+                .map_err(|_| Error::XOnlyPublicKeyParseError)?
+                .into(),
+            ClassifyContext::Legacy => bitcoin::PublicKey::from_slice(pk)
+                .map_err(|_| Error::PubkeyParseError)?
+                .into(),
+        };
+        let key_sig = verify_sersig(verify_sig, &pk, sig)?;
+        self.pending
+            .push_back(SatisfiedConstraint::PublicKey { key_sig });
+        Ok(true)
+    }
+
+    // Checks signatures the way CHECKMULTISIG does: each signature must match
+    // one of the keys, in the same order as the keys.
+    fn check_multisig<'a>(
+        &mut self,
+        op: Opcode,
+        verify_sig: &mut Box<dyn FnMut(&KeySigPair) -> bool + 'a>,
+    ) -> Result<bool, Error> {
+        let n = self.pop_num()?;
+        if !(0..=MAX_MULTISIG_KEYS).contains(&n) {
+            return Err(Error::ScriptFailed(op));
+        }
+        // Each key counts as an opcode
+        self.count_ops(n as usize)?;
+        let keys = (0..n).map(|_| self.pop()).collect::<Result<Vec<_>, _>>()?;
+        let m = self.pop_num()?;
+        if !(0..=n).contains(&m) {
+            return Err(Error::ScriptFailed(op));
+        }
+        let sigs = (0..m).map(|_| self.pop()).collect::<Result<Vec<_>, _>>()?;
+        // The extra element consumed because of the off-by-one bug
+        self.pop()?;
+
+        let mut keys = keys.iter();
+        for sig in &sigs {
+            if sig.is_empty() {
+                return Ok(false);
+            }
+            self.check_low_s(sig);
+            let key_sig = keys.by_ref().find_map(|pk| {
+                let pk = bitcoin::PublicKey::from_slice(pk).ok()?;
+                verify_sersig(verify_sig, &BitcoinKey::Fullkey(pk), sig).ok()
+            });
+            match key_sig {
+                Some(key_sig) => self
+                    .pending
+                    .push_back(SatisfiedConstraint::PublicKey { key_sig }),
+                None => return Ok(false),
+            }
+        }
+        Ok(true)
+    }
+
+    fn check_lock_time(&mut self, lock_time: absolute::LockTime) -> Result<(), Error> {
+        use absolute::LockTime::*;
+
+        let n = self.peek(0)?.clone();
+        self.check_minimal_num(&n);
+        let n = read_num(&n, MAX_LOCKTIME_NUM_SIZE)?;
+        let n = u32::try_from(n).map_err(|_| Error::ScriptFailed(opcodes::all::OP_CLTV))?;
+        let n = absolute::LockTime::from_consensus(n);
+        let is_satisfied = match (n, lock_time) {
+            (Blocks(n), Blocks(lock_time)) => n <= lock_time,
+            (Seconds(n), Seconds(lock_time)) => n <= lock_time,
+            _ => return Err(Error::AbsoluteLockTimeComparisonInvalid(n, lock_time)),
+        };
+        if is_satisfied {
+            self.pending
+                .push_back(SatisfiedConstraint::AbsoluteTimelock { n });
+            Ok(())
+        } else {
+            Err(Error::AbsoluteLockTimeNotMet(n))
+        }
+    }
+
+    fn check_sequence(&mut self, sequence: Sequence) -> Result<(), Error> {
+        let n = self.peek(0)?.clone();
+        self.check_minimal_num(&n);
+        let n = read_num(&n, MAX_LOCKTIME_NUM_SIZE)?;
+        let n = u32::try_from(n).map_err(|_| Error::ScriptFailed(opcodes::all::OP_CSV))?;
+        // BIP 112: if the operand has the disable flag set, CSV is a NOP
+        let n = match Sequence::from_consensus(n).to_relative_lock_time() {
+            Some(n) => n,
+            None => return Ok(()),
+        };
+        match sequence.to_relative_lock_time() {
+            Some(tx_lock_time) if n.is_implied_by(tx_lock_time) => {
+                self.pending
+                    .push_back(SatisfiedConstraint::RelativeTimelock { n });
+                Ok(())
+            }
+            Some(_) => Err(Error::RelativeLockTimeNotMet(n)),
+            None => Err(Error::RelativeLockTimeDisabled(n)),
+        }
+    }
+}
+
+// Reads a little-endian sign-magnitude script number of at most `max_len`
+// bytes, accepting non-minimal encodings
+fn read_num(v: &[u8], max_len: usize) -> Result<i64, Error> {
+    if v.len() > max_len {
+        return Err(Error::Script(script::Error::NumericOverflow));
+    }
+    let n = v
+        .iter()
+        .rev()
+        .fold(0i64, |acc, byte| (acc << 8) | i64::from(*byte));
+    match v.len().checked_sub(1) {
+        Some(last) if v[last] & 0x80 != 0 => Ok(-(n & !(0x80 << (8 * last)))),
+        _ => Ok(n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn script_numbers() {
+        assert_eq!(read_num(&[], MAX_NUM_SIZE).unwrap(), 0);
+        assert_eq!(read_num(&[0x81], MAX_NUM_SIZE).unwrap(), -1);
+        assert_eq!(read_num(&[0xff, 0x00], MAX_NUM_SIZE).unwrap(), 255);
+        assert_eq!(read_num(&[0xff, 0x80], MAX_NUM_SIZE).unwrap(), -255);
+        // Non-minimal encodings are accepted
+        assert_eq!(read_num(&[0x01, 0x00, 0x00], MAX_NUM_SIZE).unwrap(), 1);
+        assert!(read_num(&[0; 5], MAX_NUM_SIZE).is_err());
+        assert_eq!(
+            read_num(&[0xff, 0xff, 0xff, 0xff, 0x00], MAX_LOCKTIME_NUM_SIZE).unwrap(),
+            0xffff_ffff
+        );
+    }
+}
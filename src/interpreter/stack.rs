@@ -3,6 +3,8 @@
 
 //! Interpreter stack
 
+use core::cmp;
+
 use bitcoin::blockdata::{opcodes, script};
 use bitcoin::hashes::{hash160, ripemd160, sha256, Hash};
 use bitcoin::{absolute, relative, Sequence};
@@ -72,32 +74,66 @@ impl<'txin> Element<'txin> {
 
 /// Stack Data structure representing the stack input to Miniscript. This Stack
 /// is created from the combination of ScriptSig and Witness stack.
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Default, Hash)]
-pub struct Stack<'txin>(Vec<Element<'txin>>);
+#[derive(Clone, Debug, Default)]
+pub struct Stack<'txin> {
+    elems: Vec<Element<'txin>>,
+    // Smallest length the stack ever had. Elements below it were never popped,
+    // so that comparing with the initial stack tells which were consumed.
+    low_water: usize,
+}
 
 impl<'txin> From<Vec<Element<'txin>>> for Stack<'txin> {
-    fn from(v: Vec<Element<'txin>>) -> Self { Stack(v) }
+    fn from(v: Vec<Element<'txin>>) -> Self { Stack { low_water: v.len(), elems: v } }
 }
 
+impl<'txin> PartialEq for Stack<'txin> {
+    fn eq(&self, other: &Self) -> bool { self.elems == other.elems }
+}
+
+impl<'txin> Eq for Stack<'txin> {}
+
 impl<'txin> Stack<'txin> {
     /// Whether the stack is empty
-    pub fn is_empty(&self) -> bool { self.0.is_empty() }
+    pub fn is_empty(&self) -> bool { self.elems.is_empty() }
 
     /// Number of elements on the stack
-    pub fn len(&mut self) -> usize { self.0.len() }
+    pub fn len(&mut self) -> usize { self.elems.len() }
 
     /// Removes the top stack element, if the stack is nonempty
-    pub fn pop(&mut self) -> Option<Element<'txin>> { self.0.pop() }
+    pub fn pop(&mut self) -> Option<Element<'txin>> {
+        let elem = self.elems.pop();
+        self.low_water = cmp::min(self.low_water, self.elems.len());
+        elem
+    }
 
     /// Pushes an element onto the top of the stack
-    pub fn push(&mut self, elem: Element<'txin>) { self.0.push(elem); }
+    pub fn push(&mut self, elem: Element<'txin>) { self.elems.push(elem); }
 
     /// Returns a new stack representing the top `k` elements of the stack,
     /// removing these elements from the original
-    pub fn split_off(&mut self, k: usize) -> Vec<Element<'txin>> { self.0.split_off(k) }
+    pub fn split_off(&mut self, k: usize) -> Vec<Element<'txin>> {
+        self.low_water = cmp::min(self.low_water, k);
+        self.elems.split_off(k)
+    }
 
     /// Returns a reference to the top stack element, if the stack is nonempty
-    pub fn last(&self) -> Option<&Element<'txin>> { self.0.last() }
+    pub fn last(&self) -> Option<&Element<'txin>> { self.elems.last() }
+
+    /// Smallest number of elements the stack had since it was created
+    pub(super) fn low_water(&self) -> usize { self.low_water }
+
+    /// Returns the elements of the stack as byte vectors, bottom first, undoing
+    /// the translation of `vec![]` and `vec![1]` into booleans
+    pub(super) fn to_byte_vecs(&self) -> Vec<Vec<u8>> {
+        self.elems
+            .iter()
+            .map(|elem| match *elem {
+                Element::Satisfied => vec![1],
+                Element::Dissatisfied => vec![],
+                Element::Push(sl) => sl.to_vec(),
+            })
+            .collect()
+    }
 
     /// Helper function to evaluate a Pk Node which takes the
     /// top of the stack as input signature and validates it.
@@ -6,19 +6,49 @@
 //! as trees, iterating over them in various orders. The iterators in this
 //! module can be used to avoid explicitly recursive algorithms.
 //!
+//! [`TreeLike`] is implemented for references to [`Miniscript`], [`Terminal`],
+//! [`TapTree`](crate::descriptor::TapTree) and both kinds of policies, and may
+//! be implemented for other trees. The trait, its iterators and the order in
+//! which they yield nodes are part of the stable API of the crate: changes to
+//! them are breaking changes.
+//!
+//! # Examples
+//!
+//! Computing the depth of a Miniscript without recursion, by visiting the
+//! children of each node before the node itself:
+//!
+//! ```
+//! use core::str::FromStr;
+//!
+//! use miniscript::iter::TreeLike;
+//! use miniscript::{Miniscript, Segwitv0};
+//!
+//! let ms = Miniscript::<String, Segwitv0>::from_str("or_d(pk(A),and_v(v:pk(B),older(9)))")
+//!     .unwrap();
+//! let mut depths = vec![];
+//! for item in ms.post_order_iter() {
+//!     let depth = item.child_indices.iter().map(|&i| depths[i] + 1).max().unwrap_or(0);
+//!     depths.push(depth);
+//! }
+//! // or_d, and_v, v:, c:pk_k and pk_k
+//! assert_eq!(depths.last(), Some(&4));
+//! ```
 
 mod tree;
 
 pub use tree::{
-    PostOrderIter, PostOrderIterItem, PreOrderIter, PreOrderIterItem, Tree, TreeLike,
-    VerbosePreOrderIter,
+    PostOrderIter, PostOrderIterItem, PreOrderIter, PreOrderIterItem, RtlPostOrderIter, Tree,
+    TreeLike, VerbosePreOrderIter,
 };
 
+use crate::miniscript::extension::Extension;
 use crate::sync::Arc;
 use crate::{Miniscript, MiniscriptKey, ScriptContext, Terminal};
 
-impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> TreeLike for &'a Miniscript<Pk, Ctx> {
-    type NaryChildren = &'a [Arc<Miniscript<Pk, Ctx>>];
+impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> TreeLike
+    for &'a Miniscript<Pk, Ctx, Ext>
+{
+    type NaryChildren = &'a [Arc<Miniscript<Pk, Ctx, Ext>>];
 
     fn nary_len(tc: &Self::NaryChildren) -> usize { tc.len() }
     fn nary_index(tc: Self::NaryChildren, idx: usize) -> Self { Arc::as_ref(&tc[idx]) }
@@ -27,7 +57,9 @@ impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> TreeLike for &'a Miniscript<Pk,
         use Terminal::*;
         match self.node {
             PkK(..) | PkH(..) | RawPkH(..) | After(..) | Older(..) | Sha256(..) | Hash256(..)
-            | Ripemd160(..) | Hash160(..) | True | False | Multi(..) | MultiA(..) => Tree::Nullary,
+            | Ripemd160(..) | Hash160(..) | True | False | Multi(..) | MultiA(..) | Ext(..) => {
+                Tree::Nullary
+            }
             Alt(ref sub)
             | Swap(ref sub)
             | Check(ref sub)
@@ -47,8 +79,10 @@ impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> TreeLike for &'a Miniscript<Pk,
     }
 }
 
-impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> TreeLike for &'a Arc<Miniscript<Pk, Ctx>> {
-    type NaryChildren = &'a [Arc<Miniscript<Pk, Ctx>>];
+impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> TreeLike
+    for &'a Arc<Miniscript<Pk, Ctx, Ext>>
+{
+    type NaryChildren = &'a [Arc<Miniscript<Pk, Ctx, Ext>>];
 
     fn nary_len(tc: &Self::NaryChildren) -> usize { tc.len() }
     fn nary_index(tc: Self::NaryChildren, idx: usize) -> Self { &tc[idx] }
@@ -57,7 +91,9 @@ impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> TreeLike for &'a Arc<Miniscript<
         use Terminal::*;
         match self.node {
             PkK(..) | PkH(..) | RawPkH(..) | After(..) | Older(..) | Sha256(..) | Hash256(..)
-            | Ripemd160(..) | Hash160(..) | True | False | Multi(..) | MultiA(..) => Tree::Nullary,
+            | Ripemd160(..) | Hash160(..) | True | False | Multi(..) | MultiA(..) | Ext(..) => {
+                Tree::Nullary
+            }
             Alt(ref sub)
             | Swap(ref sub)
             | Check(ref sub)
@@ -77,8 +113,10 @@ impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> TreeLike for &'a Arc<Miniscript<
     }
 }
 
-impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> TreeLike for &'a Terminal<Pk, Ctx> {
-    type NaryChildren = &'a [Arc<Miniscript<Pk, Ctx>>];
+impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> TreeLike
+    for &'a Terminal<Pk, Ctx, Ext>
+{
+    type NaryChildren = &'a [Arc<Miniscript<Pk, Ctx, Ext>>];
 
     fn nary_len(tc: &Self::NaryChildren) -> usize { tc.len() }
     fn nary_index(tc: Self::NaryChildren, idx: usize) -> Self { tc[idx].as_inner() }
@@ -87,7 +125,9 @@ impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> TreeLike for &'a Terminal<Pk, Ct
         use Terminal::*;
         match self {
             PkK(..) | PkH(..) | RawPkH(..) | After(..) | Older(..) | Sha256(..) | Hash256(..)
-            | Ripemd160(..) | Hash160(..) | True | False | Multi(..) | MultiA(..) => Tree::Nullary,
+            | Ripemd160(..) | Hash160(..) | True | False | Multi(..) | MultiA(..) | Ext(..) => {
+                Tree::Nullary
+            }
             Alt(ref sub)
             | Swap(ref sub)
             | Check(ref sub)
@@ -19,9 +19,9 @@ pub enum Tree<T, NT> {
     Unary(T),
     /// Combinator with two children.
     Binary(T, T),
-    /// Combinator with two children.
+    /// Combinator with three children.
     Ternary(T, T, T),
-    /// Combinator with more than two children.
+    /// Combinator with more than three children.
     Nary(NT),
 }
 
@@ -32,7 +32,7 @@ pub enum Tree<T, NT> {
 /// assume copying is cheap.
 ///
 /// To implement this trait, you only need to implement the [`TreeLike::as_node`],
-/// [`TreeLike::nary_len`] and `[TreeLike::nary_index'] methods, which should
+/// [`TreeLike::nary_len`] and [`TreeLike::nary_index`] methods, which should
 /// be very mechanical. Everything else is provided.
 pub trait TreeLike: Clone + Sized {
     /// An abstraction over the children of n-ary nodes. Typically when
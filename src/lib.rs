@@ -123,11 +123,16 @@ pub mod plan;
 pub mod policy;
 mod primitives;
 pub mod psbt;
+#[cfg(feature = "serde")]
+pub mod serde_structured;
+#[cfg(feature = "proptest")]
+pub mod strategy;
 
 #[cfg(test)]
 mod test_utils;
 mod util;
 
+use core::marker::PhantomData;
 use core::{fmt, hash, str};
 
 use bitcoin::hashes::{hash160, ripemd160, sha256, Hash};
@@ -136,18 +141,19 @@ use bitcoin::{script, Opcode};
 
 pub use crate::blanket_traits::FromStrKey;
 pub use crate::descriptor::{DefiniteDescriptorKey, Descriptor, DescriptorPublicKey};
-pub use crate::error::ParseError;
+pub use crate::error::{ErrorKind, ParseError, SatisfyError};
 pub use crate::expression::{ParseNumError, ParseThresholdError, ParseTreeError};
 pub use crate::interpreter::Interpreter;
 pub use crate::miniscript::analyzable::{AnalysisError, ExtParams};
 pub use crate::miniscript::context::{BareCtx, Legacy, ScriptContext, Segwitv0, SigType, Tap};
 pub use crate::miniscript::decode::Terminal;
+pub use crate::miniscript::extension::{Extension, NoExt};
 pub use crate::miniscript::satisfy::{Preimage32, Satisfier};
 pub use crate::miniscript::{hash256, Miniscript};
 use crate::prelude::*;
 pub use crate::primitives::absolute_locktime::{AbsLockTime, AbsLockTimeError};
 pub use crate::primitives::relative_locktime::{RelLockTime, RelLockTimeError};
-pub use crate::primitives::threshold::{Threshold, ThresholdError};
+pub use crate::primitives::threshold::{Threshold, ThresholdError, WeightedThreshold};
 
 /// Public key trait which can be converted to Hash type
 pub trait MiniscriptKey: Clone + Eq + Ord + fmt::Debug + fmt::Display + hash::Hash {
@@ -329,6 +335,44 @@ pub trait Translator<P: MiniscriptKey> {
     ) -> Result<<Self::TargetPk as MiniscriptKey>::Hash160, Self::Error>;
 }
 
+// Translator translating keys with a closure and keeping hashes as they are,
+// used by the `translate_pk_with` methods
+pub(crate) struct FnTranslator<F, Q, E> {
+    f: F,
+    _marker: PhantomData<fn() -> (Q, E)>,
+}
+
+impl<F, Q, E> FnTranslator<F, Q, E> {
+    pub(crate) fn new(f: F) -> Self { FnTranslator { f, _marker: PhantomData } }
+}
+
+impl<P, Q, E, F> Translator<P> for FnTranslator<F, Q, E>
+where
+    P: MiniscriptKey,
+    Q: MiniscriptKey<
+        Sha256 = P::Sha256,
+        Hash256 = P::Hash256,
+        Ripemd160 = P::Ripemd160,
+        Hash160 = P::Hash160,
+    >,
+    F: FnMut(&P) -> Result<Q, E>,
+{
+    type TargetPk = Q;
+    type Error = E;
+
+    fn pk(&mut self, pk: &P) -> Result<Q, E> { (self.f)(pk) }
+
+    fn sha256(&mut self, sha256: &P::Sha256) -> Result<Q::Sha256, E> { Ok(sha256.clone()) }
+
+    fn hash256(&mut self, hash256: &P::Hash256) -> Result<Q::Hash256, E> { Ok(hash256.clone()) }
+
+    fn ripemd160(&mut self, ripemd160: &P::Ripemd160) -> Result<Q::Ripemd160, E> {
+        Ok(ripemd160.clone())
+    }
+
+    fn hash160(&mut self, hash160: &P::Hash160) -> Result<Q::Hash160, E> { Ok(hash160.clone()) }
+}
+
 /// An enum for representing translation errors
 pub enum TranslateErr<E> {
     /// Error inside in the underlying key translation
@@ -404,11 +448,30 @@ pub trait ForEachKey<Pk: MiniscriptKey> {
     {
         !self.for_each_key(|key| !pred(key))
     }
+
+    /// Run a fallible function on every key in the descriptor, stopping at
+    /// and returning the first error
+    fn try_for_each_key<'a, E, F>(&'a self, mut f: F) -> Result<(), E>
+    where
+        Pk: 'a,
+        F: FnMut(&'a Pk) -> Result<(), E>,
+    {
+        let mut ret = Ok(());
+        self.for_each_key(|key| match f(key) {
+            Ok(()) => true,
+            Err(e) => {
+                ret = Err(e);
+                false
+            }
+        });
+        ret
+    }
 }
 
 /// Miniscript
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     /// Opcode appeared which is not part of the script subset
     InvalidOpcode(Opcode),
@@ -433,12 +496,10 @@ pub enum Error {
     NonTopLevel(String),
     /// Parsed a miniscript but there were more script opcodes after it
     Trailing(String),
-    /// Could not satisfy a script (fragment) because of a missing signature
-    MissingSig(bitcoin::PublicKey),
-    /// General failure to satisfy
-    CouldNotSatisfy,
+    /// Could not satisfy a script
+    Satisfy(SatisfyError),
     /// Typechecking failed
-    TypeCheck(String),
+    TypeCheck(miniscript::types::Error),
     /// Forward-secp related errors
     Secp(bitcoin::secp256k1::Error),
     #[cfg(feature = "compiler")]
@@ -457,8 +518,6 @@ pub enum Error {
     NonStandardBareScript,
     /// Analysis Error
     AnalysisError(miniscript::analyzable::AnalysisError),
-    /// Miniscript is equivalent to false. No possible satisfaction
-    ImpossibleSatisfaction,
     /// Bare descriptors don't have any addresses
     BareDescriptorAddr,
     /// PubKey invalid under current context
@@ -485,6 +544,65 @@ impl From<ParseThresholdError> for Error {
     fn from(e: ParseThresholdError) -> Self { Self::ParseThreshold(e) }
 }
 
+impl Error {
+    /// The class of failure of this error.
+    pub fn kind(&self) -> ErrorKind {
+        match *self {
+            Error::InvalidOpcode(_)
+            | Error::NonMinimalVerify(_)
+            | Error::InvalidPush(_)
+            | Error::Script(_)
+            | Error::UnexpectedStart
+            | Error::Unexpected(_)
+            | Error::UnknownWrapper(_)
+            | Error::Trailing(_)
+            | Error::MaxRecursiveDepthExceeded
+            | Error::MultipathDescLenMismatch
+            | Error::AbsoluteLockTime(_)
+            | Error::RelativeLockTime(_)
+            | Error::Threshold(_)
+            | Error::ParseThreshold(_)
+            | Error::Parse(_) => ErrorKind::Parse,
+            Error::NonTopLevel(_) | Error::TypeCheck(_) => ErrorKind::Type,
+            Error::PubKeyCtxError(..)
+            | Error::ContextError(_)
+            | Error::NonStandardBareScript
+            | Error::AnalysisError(_) => ErrorKind::Context,
+            Error::Satisfy(_) => ErrorKind::Satisfy,
+            #[cfg(feature = "compiler")]
+            Error::CompilerError(_) => ErrorKind::Policy,
+            Error::ConcretePolicy(_) | Error::LiftError(_) => ErrorKind::Policy,
+            Error::AddrError(_) | Error::AddrP2shError(_) => ErrorKind::Address,
+            Error::BareDescriptorAddr | Error::TrNoScriptCode => ErrorKind::Unsupported,
+            Error::Secp(_) => ErrorKind::Crypto,
+        }
+    }
+
+    /// The type checking error, if this is one.
+    pub fn type_error(&self) -> Option<&miniscript::types::Error> {
+        match *self {
+            Error::TypeCheck(ref e) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// The satisfaction error, if this is one.
+    pub fn satisfy_error(&self) -> Option<&SatisfyError> {
+        match *self {
+            Error::Satisfy(ref e) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// The parsing error, if this is one of the errors of the string parser.
+    pub fn parse_error(&self) -> Option<&ParseError> {
+        match *self {
+            Error::Parse(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
 // https://github.com/sipa/miniscript/pull/5 for discussion on this number
 const MAX_RECURSION_DEPTH: u32 = 402;
 
@@ -504,8 +622,7 @@ impl fmt::Display for Error {
             Error::UnknownWrapper(ch) => write!(f, "unknown wrapper «{}:»", ch),
             Error::NonTopLevel(ref s) => write!(f, "non-T miniscript: {}", s),
             Error::Trailing(ref s) => write!(f, "trailing tokens: {}", s),
-            Error::MissingSig(ref pk) => write!(f, "missing signature for key {:?}", pk),
-            Error::CouldNotSatisfy => f.write_str("could not satisfy"),
+            Error::Satisfy(ref e) => e.fmt(f),
             Error::TypeCheck(ref e) => write!(f, "typecheck: {}", e),
             Error::Secp(ref e) => fmt::Display::fmt(e, f),
             Error::ContextError(ref e) => fmt::Display::fmt(e, f),
@@ -525,7 +642,6 @@ impl fmt::Display for Error {
                 "
             ),
             Error::AnalysisError(ref e) => e.fmt(f),
-            Error::ImpossibleSatisfaction => write!(f, "Impossible to satisfy Miniscript"),
             Error::BareDescriptorAddr => write!(f, "Bare descriptors don't have address"),
             Error::PubKeyCtxError(ref pk, ref ctx) => {
                 write!(f, "Pubkey error: {} under {} scriptcontext", pk, ctx)
@@ -543,7 +659,7 @@ impl fmt::Display for Error {
 
 #[cfg(feature = "std")]
 impl std::error::Error for Error {
-    fn cause(&self) -> Option<&dyn std::error::Error> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         use self::Error::*;
 
         match self {
@@ -555,16 +671,14 @@ impl std::error::Error for Error {
             | UnknownWrapper(_)
             | NonTopLevel(_)
             | Trailing(_)
-            | MissingSig(_)
-            | CouldNotSatisfy
-            | TypeCheck(_)
             | MaxRecursiveDepthExceeded
             | NonStandardBareScript
-            | ImpossibleSatisfaction
             | BareDescriptorAddr
             | TrNoScriptCode
             | MultipathDescLenMismatch => None,
             Script(e) => Some(e),
+            TypeCheck(e) => Some(e),
+            Satisfy(e) => Some(e),
             AddrError(e) => Some(e),
             AddrP2shError(e) => Some(e),
             Secp(e) => Some(e),
@@ -586,7 +700,12 @@ impl std::error::Error for Error {
 
 #[doc(hidden)]
 impl From<miniscript::types::Error> for Error {
-    fn from(e: miniscript::types::Error) -> Error { Error::TypeCheck(e.to_string()) }
+    fn from(e: miniscript::types::Error) -> Error { Error::TypeCheck(e) }
+}
+
+#[doc(hidden)]
+impl From<SatisfyError> for Error {
+    fn from(e: SatisfyError) -> Error { Error::Satisfy(e) }
 }
 
 #[doc(hidden)]
@@ -709,6 +828,55 @@ mod tests {
         let got = pk.to_pubkeyhash(SigType::Schnorr);
         assert_eq!(got, want)
     }
+
+    #[test]
+    fn error_kinds() {
+        use crate::miniscript::types;
+
+        let err = Descriptor::<String>::from_str("wsh(pk(A)").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Parse);
+        assert!(err.parse_error().is_some());
+
+        let err = Miniscript::<String, Segwitv0>::from_str("and_v(pk(A),pk(B))").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Type);
+        let type_error = err.type_error().unwrap();
+        assert_eq!(type_error.fragment_string, "and_v(pk(A),pk(B))");
+        assert!(matches!(type_error.error, types::ErrorKind::ChildBase2(..)));
+        #[cfg(feature = "std")]
+        {
+            use std::error::Error as _;
+            assert_eq!(err.source().unwrap().to_string(), type_error.to_string());
+        }
+
+        let err = Descriptor::<bitcoin::PublicKey>::from_str(
+            "pk(020000000000000000000000000000000000000000000000000000000000000002)",
+        )
+        .unwrap()
+        .address(bitcoin::Network::Bitcoin)
+        .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+
+        let pk = bitcoin::PublicKey::from_str(
+            "020000000000000000000000000000000000000000000000000000000000000002",
+        )
+        .unwrap();
+        let sigs = BTreeMap::<bitcoin::PublicKey, bitcoin::ecdsa::Signature>::new();
+        let err = Descriptor::new_wpkh(pk)
+            .unwrap()
+            .get_satisfaction(&sigs)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Satisfy);
+        assert_eq!(err.satisfy_error(), Some(&SatisfyError::MissingSig(pk)));
+
+        // A compressed key in a tapscript
+        let script = bitcoin::script::Builder::new()
+            .push_slice([2; 33])
+            .push_opcode(bitcoin::opcodes::all::OP_CHECKSIG)
+            .into_script();
+        let err = Miniscript::<bitcoin::key::XOnlyPublicKey, Tap>::parse(&script).unwrap_err();
+        assert!(matches!(err, Error::PubKeyCtxError(..)));
+        assert_eq!(err.kind(), ErrorKind::Context);
+    }
 }
 
 #[allow(unused_imports)] // this is an internal prelude module; not all imports are used with every feature combination
@@ -9,6 +9,7 @@ use core::fmt;
 #[cfg(feature = "std")]
 use std::error;
 
+use crate::miniscript::extension::Extension;
 use crate::prelude::*;
 use crate::{Miniscript, MiniscriptKey, ScriptContext, Terminal};
 
@@ -24,6 +25,8 @@ use crate::{Miniscript, MiniscriptKey, ScriptContext, Terminal};
 ///    guarantees are not satisfied.
 /// 4. It has repeated public keys
 /// 5. raw pkh fragments without the pk. This could be obtained when parsing miniscript from script
+/// 6. It has hybrid public keys, which are consensus-valid in Bare and Legacy scripts but
+///    rejected by standardness
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default, Hash)]
 pub struct ExtParams {
     /// Allow parsing of non-safe miniscripts
@@ -39,6 +42,10 @@ pub struct ExtParams {
     /// Allow parsing of miniscripts with raw pkh fragments without the pk.
     /// This could be obtained when parsing miniscript from script
     pub raw_pkh: bool,
+    /// Allow parsing of Bare and Legacy scripts with hybrid public keys.
+    /// These are read as the equivalent uncompressed keys, so the parsed
+    /// miniscript is only good for analysis and cannot satisfy the script
+    pub hybrid_keys: bool,
 }
 
 impl ExtParams {
@@ -51,6 +58,7 @@ impl ExtParams {
             malleability: false,
             repeated_pk: false,
             raw_pkh: false,
+            hybrid_keys: false,
         }
     }
 
@@ -68,6 +76,7 @@ impl ExtParams {
             malleability: true,
             repeated_pk: true,
             raw_pkh: false,
+            hybrid_keys: false,
         }
     }
 
@@ -80,6 +89,7 @@ impl ExtParams {
             malleability: true,
             repeated_pk: true,
             raw_pkh: true,
+            hybrid_keys: true,
         }
     }
 
@@ -118,6 +128,12 @@ impl ExtParams {
         self.raw_pkh = true;
         self
     }
+
+    /// Builder that allows scripts with hybrid public keys.
+    pub fn hybrid_keys(mut self) -> ExtParams {
+        self.hybrid_keys = true;
+        self
+    }
 }
 
 /// Possible reasons Miniscript guarantees can fail
@@ -143,6 +159,21 @@ pub enum AnalysisError {
     Malleable,
     /// Contains partial descriptor raw pkh
     ContainsRawPkh,
+    /// Script contains a hybrid public key, given in hex
+    HybridKey(String),
+    /// Two taproot leaves, given by their index in
+    /// [`crate::descriptor::Tr::iter_scripts`], have the same script
+    DuplicateTapLeaf {
+        /// The first leaf with the script.
+        first: usize,
+        /// The second leaf with the script.
+        second: usize,
+    },
+    /// The taproot leaf with the given index can never be satisfied
+    UnsatisfiableTapLeaf(usize),
+    /// The taproot leaf with the given index exceeds the tapscript resource
+    /// limits
+    TapLeafExceedsResourceLimits(usize),
 }
 
 impl fmt::Display for AnalysisError {
@@ -162,13 +193,23 @@ impl fmt::Display for AnalysisError {
             }
             AnalysisError::Malleable => f.write_str("Miniscript is malleable"),
             AnalysisError::ContainsRawPkh => f.write_str("Miniscript contains raw pkh"),
+            AnalysisError::HybridKey(ref key) => write!(f, "Script contains hybrid key {}", key),
+            AnalysisError::DuplicateTapLeaf { first, second } => {
+                write!(f, "Taproot leaves {} and {} have the same script", first, second)
+            }
+            AnalysisError::UnsatisfiableTapLeaf(leaf) => {
+                write!(f, "Taproot leaf {} can never be satisfied", leaf)
+            }
+            AnalysisError::TapLeafExceedsResourceLimits(leaf) => {
+                write!(f, "Taproot leaf {} exceeds the tapscript resource limits", leaf)
+            }
         }
     }
 }
 
 #[cfg(feature = "std")]
 impl error::Error for AnalysisError {
-    fn cause(&self) -> Option<&dyn error::Error> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         use self::AnalysisError::*;
 
         match self {
@@ -177,12 +218,16 @@ impl error::Error for AnalysisError {
             | BranchExceedResouceLimits
             | HeightTimelockCombination
             | Malleable
-            | ContainsRawPkh => None,
+            | ContainsRawPkh
+            | HybridKey(_)
+            | DuplicateTapLeaf { .. }
+            | UnsatisfiableTapLeaf(_)
+            | TapLeafExceedsResourceLimits(_) => None,
         }
     }
 }
 
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Miniscript<Pk, Ctx, Ext> {
     /// Whether all spend paths of miniscript require a signature
     pub fn requires_sig(&self) -> bool { self.ty.mall.safe }
 
@@ -9,6 +9,7 @@ use bitcoin::hashes::{hash160, ripemd160, sha256};
 use bitcoin::Weight;
 
 use super::decode::ParseableKey;
+use super::extension::Extension;
 use crate::miniscript::limits::{
     MAX_OPS_PER_SCRIPT, MAX_SCRIPTSIG_SIZE, MAX_SCRIPT_ELEMENT_SIZE, MAX_SCRIPT_SIZE,
     MAX_STACK_SIZE, MAX_STANDARD_P2WSH_SCRIPT_SIZE, MAX_STANDARD_P2WSH_STACK_ITEMS,
@@ -70,7 +71,7 @@ pub enum ScriptContextError {
 
 #[cfg(feature = "std")]
 impl error::Error for ScriptContextError {
-    fn cause(&self) -> Option<&dyn error::Error> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         use self::ScriptContextError::*;
 
         match self {
@@ -191,8 +192,8 @@ where
     /// This does NOT recursively check if the children of the fragment are
     /// valid or not. Since the compilation proceeds in a leaf to root fashion,
     /// a recursive check is unnecessary.
-    fn check_terminal_non_malleable<Pk: MiniscriptKey>(
-        _frag: &Terminal<Pk, Self>,
+    fn check_terminal_non_malleable<Pk: MiniscriptKey, Ext: Extension>(
+        _frag: &Terminal<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError>;
 
     /// Check whether the given satisfaction is valid under the ScriptContext
@@ -212,7 +213,9 @@ where
     fn check_pk<Pk: MiniscriptKey>(pk: &Pk) -> Result<(), ScriptContextError>;
 
     /// Depending on script context, the size of a satifaction witness may slightly differ.
-    fn max_satisfaction_size<Pk: MiniscriptKey>(ms: &Miniscript<Pk, Self>) -> Option<usize>;
+    fn max_satisfaction_size<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Option<usize>;
     /// Depending on script Context, some of the Terminals might not
     /// be valid under the current consensus rules.
     /// Or some of the script resource limits may have been exceeded.
@@ -223,8 +226,8 @@ where
     /// In LegacyP2SH context, scripts above 520 bytes are invalid.
     /// Post Tapscript upgrade, this would have to consider other nodes.
     /// This does *NOT* recursively check the miniscript fragments.
-    fn check_global_consensus_validity<Pk: MiniscriptKey>(
-        _ms: &Miniscript<Pk, Self>,
+    fn check_global_consensus_validity<Pk: MiniscriptKey, Ext: Extension>(
+        _ms: &Miniscript<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         Ok(())
     }
@@ -237,8 +240,8 @@ where
     /// scripts over 3600 bytes are invalid.
     /// Post Tapscript upgrade, this would have to consider other nodes.
     /// This does *NOT* recursively check the miniscript fragments.
-    fn check_global_policy_validity<Pk: MiniscriptKey>(
-        _ms: &Miniscript<Pk, Self>,
+    fn check_global_policy_validity<Pk: MiniscriptKey, Ext: Extension>(
+        _ms: &Miniscript<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         Ok(())
     }
@@ -247,8 +250,8 @@ where
     /// It is possible that some paths of miniscript may exceed resource limits
     /// and our current satisfier and lifting analysis would not work correctly.
     /// For example, satisfaction path(Legacy/Segwitv0) may require more than 201 opcodes.
-    fn check_local_consensus_validity<Pk: MiniscriptKey>(
-        _ms: &Miniscript<Pk, Self>,
+    fn check_local_consensus_validity<Pk: MiniscriptKey, Ext: Extension>(
+        _ms: &Miniscript<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         Ok(())
     }
@@ -258,16 +261,16 @@ where
     /// and our current satisfier and lifting analysis would not work correctly.
     /// For example, satisfaction path in Legacy context scriptSig more
     /// than 1650 bytes
-    fn check_local_policy_validity<Pk: MiniscriptKey>(
-        _ms: &Miniscript<Pk, Self>,
+    fn check_local_policy_validity<Pk: MiniscriptKey, Ext: Extension>(
+        _ms: &Miniscript<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         Ok(())
     }
 
     /// Check the consensus + policy(if not disabled) rules that are not based
     /// satisfaction
-    fn check_global_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_global_validity<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         Self::check_global_consensus_validity(ms)?;
         Self::check_global_policy_validity(ms)?;
@@ -276,8 +279,8 @@ where
 
     /// Check the consensus + policy(if not disabled) rules including the
     /// ones for satisfaction
-    fn check_local_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_local_validity<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         Self::check_global_consensus_validity(ms)?;
         Self::check_global_policy_validity(ms)?;
@@ -287,7 +290,9 @@ where
     }
 
     /// Check whether the top-level is type B
-    fn top_level_type_check<Pk: MiniscriptKey>(ms: &Miniscript<Pk, Self>) -> Result<(), Error> {
+    fn top_level_type_check<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Result<(), Error> {
         if ms.ty.corr.base != types::Base::B {
             return Err(Error::NonTopLevel(format!("{:?}", ms)));
         }
@@ -325,7 +330,9 @@ where
     }
 
     /// Other top level checks that are context specific
-    fn other_top_level_checks<Pk: MiniscriptKey>(_ms: &Miniscript<Pk, Self>) -> Result<(), Error> {
+    fn other_top_level_checks<Pk: MiniscriptKey, Ext: Extension>(
+        _ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Result<(), Error> {
         Ok(())
     }
 
@@ -338,7 +345,9 @@ where
     // that are only applicable at the top-level
     // We can also combine the top-level check for Base::B here
     // even though it does not depend on context, but helps in cleaner code
-    fn top_level_checks<Pk: MiniscriptKey>(ms: &Miniscript<Pk, Self>) -> Result<(), Error> {
+    fn top_level_checks<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Result<(), Error> {
         Self::top_level_type_check(ms)?;
         Self::other_top_level_checks(ms)
     }
@@ -376,8 +385,8 @@ pub enum Legacy {}
 
 impl ScriptContext for Legacy {
     type Key = bitcoin::PublicKey;
-    fn check_terminal_non_malleable<Pk: MiniscriptKey>(
-        frag: &Terminal<Pk, Self>,
+    fn check_terminal_non_malleable<Pk: MiniscriptKey, Ext: Extension>(
+        frag: &Terminal<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         match *frag {
             Terminal::PkH(ref _pkh) => Err(ScriptContextError::MalleablePkH),
@@ -410,8 +419,8 @@ impl ScriptContext for Legacy {
         Ok(())
     }
 
-    fn check_global_consensus_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_global_consensus_validity<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         // 1. Check the node first, throw an error on the language itself
         let node_checked = match ms.node {
@@ -441,8 +450,8 @@ impl ScriptContext for Legacy {
         }
     }
 
-    fn check_local_consensus_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_local_consensus_validity<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         match ms.ext.ops.op_count() {
             None => Err(ScriptContextError::ImpossibleSatisfaction),
@@ -456,8 +465,8 @@ impl ScriptContext for Legacy {
         }
     }
 
-    fn check_local_policy_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_local_policy_validity<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         // Legacy scripts permit upto 1000 stack elements, 520 bytes consensus limits
         // on P2SH size, it is not possible to reach the 1000 elements limit and hence
@@ -474,7 +483,9 @@ impl ScriptContext for Legacy {
         }
     }
 
-    fn max_satisfaction_size<Pk: MiniscriptKey>(ms: &Miniscript<Pk, Self>) -> Option<usize> {
+    fn max_satisfaction_size<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Option<usize> {
         // The scriptSig cost is the second element of the tuple
         ms.ext.max_sat_size.map(|x| x.1)
     }
@@ -498,8 +509,8 @@ pub enum Segwitv0 {}
 
 impl ScriptContext for Segwitv0 {
     type Key = bitcoin::PublicKey;
-    fn check_terminal_non_malleable<Pk: MiniscriptKey>(
-        _frag: &Terminal<Pk, Self>,
+    fn check_terminal_non_malleable<Pk: MiniscriptKey, Ext: Extension>(
+        _frag: &Terminal<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         Ok(())
     }
@@ -525,8 +536,8 @@ impl ScriptContext for Segwitv0 {
         Ok(())
     }
 
-    fn check_global_consensus_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_global_consensus_validity<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         // 1. Check the node first, throw an error on the language itself
         let node_checked = match ms.node {
@@ -556,8 +567,8 @@ impl ScriptContext for Segwitv0 {
         }
     }
 
-    fn check_local_consensus_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_local_consensus_validity<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         match ms.ext.ops.op_count() {
             None => Err(ScriptContextError::ImpossibleSatisfaction),
@@ -571,8 +582,8 @@ impl ScriptContext for Segwitv0 {
         }
     }
 
-    fn check_global_policy_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_global_policy_validity<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         if ms.ext.pk_cost > MAX_STANDARD_P2WSH_SCRIPT_SIZE {
             return Err(ScriptContextError::MaxWitnessScriptSizeExceeded {
@@ -583,8 +594,8 @@ impl ScriptContext for Segwitv0 {
         Ok(())
     }
 
-    fn check_local_policy_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_local_policy_validity<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         // We don't need to know if this is actually a p2wsh as the standard satisfaction for
         // other Segwitv0 defined programs all require (much) less than 100 elements.
@@ -602,7 +613,9 @@ impl ScriptContext for Segwitv0 {
         }
     }
 
-    fn max_satisfaction_size<Pk: MiniscriptKey>(ms: &Miniscript<Pk, Self>) -> Option<usize> {
+    fn max_satisfaction_size<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Option<usize> {
         // The witness stack cost is the first element of the tuple
         ms.ext.max_sat_size.map(|x| x.0)
     }
@@ -620,8 +633,8 @@ pub enum Tap {}
 
 impl ScriptContext for Tap {
     type Key = bitcoin::secp256k1::XOnlyPublicKey;
-    fn check_terminal_non_malleable<Pk: MiniscriptKey>(
-        _frag: &Terminal<Pk, Self>,
+    fn check_terminal_non_malleable<Pk: MiniscriptKey, Ext: Extension>(
+        _frag: &Terminal<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         // No fragment is malleable in tapscript context.
         // Certain fragments like Multi are invalid, but are not malleable
@@ -648,8 +661,8 @@ impl ScriptContext for Tap {
         Ok(())
     }
 
-    fn check_global_consensus_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_global_consensus_validity<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         // 1. Check the node first, throw an error on the language itself
         let node_checked = match ms.node {
@@ -684,8 +697,8 @@ impl ScriptContext for Tap {
         }
     }
 
-    fn check_local_consensus_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_local_consensus_validity<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         // Taproot introduces the concept of sigops budget.
         // All valid miniscripts satisfy the sigops constraint
@@ -708,20 +721,22 @@ impl ScriptContext for Tap {
         Ok(())
     }
 
-    fn check_global_policy_validity<Pk: MiniscriptKey>(
-        _ms: &Miniscript<Pk, Self>,
+    fn check_global_policy_validity<Pk: MiniscriptKey, Ext: Extension>(
+        _ms: &Miniscript<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         // No script rules, rules are subject to entire tx rules
         Ok(())
     }
 
-    fn check_local_policy_validity<Pk: MiniscriptKey>(
-        _ms: &Miniscript<Pk, Self>,
+    fn check_local_policy_validity<Pk: MiniscriptKey, Ext: Extension>(
+        _ms: &Miniscript<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         Ok(())
     }
 
-    fn max_satisfaction_size<Pk: MiniscriptKey>(ms: &Miniscript<Pk, Self>) -> Option<usize> {
+    fn max_satisfaction_size<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Option<usize> {
         // The witness stack cost is the first element of the tuple
         ms.ext.max_sat_size.map(|x| x.0)
     }
@@ -742,8 +757,8 @@ pub enum BareCtx {}
 
 impl ScriptContext for BareCtx {
     type Key = bitcoin::PublicKey;
-    fn check_terminal_non_malleable<Pk: MiniscriptKey>(
-        _frag: &Terminal<Pk, Self>,
+    fn check_terminal_non_malleable<Pk: MiniscriptKey, Ext: Extension>(
+        _frag: &Terminal<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         // Bare fragments can't contain miniscript because of standardness rules
         // This function is only used in compiler which already checks the standardness
@@ -761,8 +776,8 @@ impl ScriptContext for BareCtx {
         }
     }
 
-    fn check_global_consensus_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_global_consensus_validity<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         // 1. Check the node first, throw an error on the language itself
         let node_checked = match ms.node {
@@ -792,8 +807,8 @@ impl ScriptContext for BareCtx {
         }
     }
 
-    fn check_local_consensus_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_local_consensus_validity<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         match ms.ext.ops.op_count() {
             None => Err(ScriptContextError::ImpossibleSatisfaction),
@@ -807,7 +822,9 @@ impl ScriptContext for BareCtx {
         }
     }
 
-    fn other_top_level_checks<Pk: MiniscriptKey>(ms: &Miniscript<Pk, Self>) -> Result<(), Error> {
+    fn other_top_level_checks<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Result<(), Error> {
         match &ms.node {
             Terminal::Check(ref ms) => match &ms.node {
                 Terminal::RawPkH(_pkh) => Ok(()),
@@ -819,7 +836,9 @@ impl ScriptContext for BareCtx {
         }
     }
 
-    fn max_satisfaction_size<Pk: MiniscriptKey>(ms: &Miniscript<Pk, Self>) -> Option<usize> {
+    fn max_satisfaction_size<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Option<usize> {
         // The witness stack cost is the first element of the tuple
         ms.ext.max_sat_size.map(|x| x.1)
     }
@@ -847,8 +866,8 @@ pub enum NoChecks {}
 impl ScriptContext for NoChecks {
     // todo: When adding support for interpreter, we need a enum with all supported keys here
     type Key = bitcoin::PublicKey;
-    fn check_terminal_non_malleable<Pk: MiniscriptKey>(
-        _frag: &Terminal<Pk, Self>,
+    fn check_terminal_non_malleable<Pk: MiniscriptKey, Ext: Extension>(
+        _frag: &Terminal<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         Ok(())
     }
@@ -856,31 +875,33 @@ impl ScriptContext for NoChecks {
     // No checks in NoChecks
     fn check_pk<Pk: MiniscriptKey>(_pk: &Pk) -> Result<(), ScriptContextError> { Ok(()) }
 
-    fn check_global_policy_validity<Pk: MiniscriptKey>(
-        _ms: &Miniscript<Pk, Self>,
+    fn check_global_policy_validity<Pk: MiniscriptKey, Ext: Extension>(
+        _ms: &Miniscript<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         Ok(())
     }
 
-    fn check_global_consensus_validity<Pk: MiniscriptKey>(
-        _ms: &Miniscript<Pk, Self>,
+    fn check_global_consensus_validity<Pk: MiniscriptKey, Ext: Extension>(
+        _ms: &Miniscript<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         Ok(())
     }
 
-    fn check_local_policy_validity<Pk: MiniscriptKey>(
-        _ms: &Miniscript<Pk, Self>,
+    fn check_local_policy_validity<Pk: MiniscriptKey, Ext: Extension>(
+        _ms: &Miniscript<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         Ok(())
     }
 
-    fn check_local_consensus_validity<Pk: MiniscriptKey>(
-        _ms: &Miniscript<Pk, Self>,
+    fn check_local_consensus_validity<Pk: MiniscriptKey, Ext: Extension>(
+        _ms: &Miniscript<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         Ok(())
     }
 
-    fn max_satisfaction_size<Pk: MiniscriptKey>(_ms: &Miniscript<Pk, Self>) -> Option<usize> {
+    fn max_satisfaction_size<Pk: MiniscriptKey, Ext: Extension>(
+        _ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Option<usize> {
         panic!("Tried to compute a satisfaction size bound on a no-checks ecdsa miniscript")
     }
 
@@ -900,16 +921,16 @@ impl ScriptContext for NoChecks {
         Ok(())
     }
 
-    fn check_global_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_global_validity<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         Self::check_global_consensus_validity(ms)?;
         Self::check_global_policy_validity(ms)?;
         Ok(())
     }
 
-    fn check_local_validity<Pk: MiniscriptKey>(
-        ms: &Miniscript<Pk, Self>,
+    fn check_local_validity<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
     ) -> Result<(), ScriptContextError> {
         Self::check_global_consensus_validity(ms)?;
         Self::check_global_policy_validity(ms)?;
@@ -918,18 +939,24 @@ impl ScriptContext for NoChecks {
         Ok(())
     }
 
-    fn top_level_type_check<Pk: MiniscriptKey>(ms: &Miniscript<Pk, Self>) -> Result<(), Error> {
+    fn top_level_type_check<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Result<(), Error> {
         if ms.ty.corr.base != types::Base::B {
             return Err(Error::NonTopLevel(format!("{:?}", ms)));
         }
         Ok(())
     }
 
-    fn other_top_level_checks<Pk: MiniscriptKey>(_ms: &Miniscript<Pk, Self>) -> Result<(), Error> {
+    fn other_top_level_checks<Pk: MiniscriptKey, Ext: Extension>(
+        _ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Result<(), Error> {
         Ok(())
     }
 
-    fn top_level_checks<Pk: MiniscriptKey>(ms: &Miniscript<Pk, Self>) -> Result<(), Error> {
+    fn top_level_checks<Pk: MiniscriptKey, Ext: Extension>(
+        ms: &Miniscript<Pk, Self, Ext>,
+    ) -> Result<(), Error> {
         Self::top_level_type_check(ms)?;
         Self::other_top_level_checks(ms)
     }
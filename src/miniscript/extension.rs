@@ -0,0 +1,309 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Miniscript Extensions
+//!
+//! The interface through which downstream crates can add custom fragments,
+//! such as introspection opcodes or covenants, to Miniscript. [`Terminal`],
+//! [`Miniscript`] and the script descriptors are generic over an extension,
+//! whose fragments are type-checked, sized, parsed, encoded and satisfied like
+//! the fragments of Miniscript. [`NoExt`], which has no fragments, is the
+//! extension of plain Miniscript and the default of these generics.
+//!
+//! [`Terminal`]: crate::Terminal
+//! [`Miniscript`]: crate::Miniscript
+
+use core::{fmt, hash};
+
+use bitcoin::script;
+
+use super::context::ScriptContextError;
+use super::lex::TokenIter;
+use super::satisfy::{Placeholder, Satisfaction};
+use super::types::{ExtData, Type};
+use super::ScriptContext;
+use crate::plan::AssetProvider;
+use crate::prelude::*;
+use crate::{expression, Error, MiniscriptKey, ParseError, ParseTreeError, ToPublicKey};
+
+/// A set of custom fragments extending Miniscript.
+///
+/// Like those of Miniscript, the fragments of an extension are terminals:
+/// they have no Miniscript children, and their arguments, if any, are written
+/// as the leaves of the expression tree, as in `covenant(1,2)`. They have no
+/// keys either, so translating the keys of a Miniscript leaves them as they
+/// are.
+pub trait Extension: Clone + Eq + Ord + fmt::Debug + fmt::Display + hash::Hash {
+    /// The correctness and malleability type of the fragment.
+    fn type_check(&self) -> Type;
+
+    /// The extra properties of the fragment: its script size, its
+    /// satisfaction sizes and its timelocks.
+    fn extra_prop(&self) -> ExtData;
+
+    /// Checks that the fragment may be used in the script context `Ctx`.
+    ///
+    /// By default fragments may be used in all contexts.
+    fn check_context<Ctx: ScriptContext>(&self) -> Result<(), ScriptContextError> { Ok(()) }
+
+    /// Parses the fragment named `name`, without its wrappers, from a node
+    /// of an expression tree, such as a descriptor string.
+    ///
+    /// Returns an [`ParseTreeError::UnknownName`] error if `name` is not a
+    /// fragment of the extension.
+    fn from_tree(name: &str, node: expression::TreeIterItem) -> Result<Self, Error>;
+
+    /// Parses the fragment from the end of a script, consuming its tokens.
+    ///
+    /// Scripts are decoded backwards, so the tokens are popped from the last
+    /// one. Returns `None`, leaving the tokens untouched, if the script does
+    /// not end with a fragment of the extension.
+    fn from_token_iter(tokens: &mut TokenIter) -> Option<Self>;
+
+    /// Encodes the fragment to Script.
+    fn encode(&self, builder: script::Builder) -> script::Builder;
+
+    /// The witness template satisfying the fragment, given the assets
+    /// available to `provider`.
+    fn satisfy<Pk, P>(&self, provider: &P) -> Satisfaction<Placeholder<Pk>>
+    where
+        Pk: MiniscriptKey + ToPublicKey,
+        P: AssetProvider<Pk>;
+
+    /// The witness template dissatisfying the fragment, given the assets
+    /// available to `provider`.
+    fn dissatisfy<Pk, P>(&self, provider: &P) -> Satisfaction<Placeholder<Pk>>
+    where
+        Pk: MiniscriptKey + ToPublicKey,
+        P: AssetProvider<Pk>;
+}
+
+/// The extension without fragments, that of plain Miniscript.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum NoExt {}
+
+impl fmt::Display for NoExt {
+    fn fmt(&self, _: &mut fmt::Formatter) -> fmt::Result { match *self {} }
+}
+
+impl Extension for NoExt {
+    fn type_check(&self) -> Type { match *self {} }
+
+    fn extra_prop(&self) -> ExtData { match *self {} }
+
+    fn from_tree(name: &str, _: expression::TreeIterItem) -> Result<Self, Error> {
+        Err(Error::Parse(ParseError::Tree(ParseTreeError::UnknownName {
+            name: name.to_owned(),
+        })))
+    }
+
+    fn from_token_iter(_: &mut TokenIter) -> Option<Self> { None }
+
+    fn encode(&self, _: script::Builder) -> script::Builder { match *self {} }
+
+    fn satisfy<Pk, P>(&self, _: &P) -> Satisfaction<Placeholder<Pk>>
+    where
+        Pk: MiniscriptKey + ToPublicKey,
+        P: AssetProvider<Pk>,
+    {
+        match *self {}
+    }
+
+    fn dissatisfy<Pk, P>(&self, _: &P) -> Satisfaction<Placeholder<Pk>>
+    where
+        Pk: MiniscriptKey + ToPublicKey,
+        P: AssetProvider<Pk>,
+    {
+        match *self {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use bitcoin::hashes::{sha256, Hash};
+    use bitcoin::opcodes::all::OP_NOP4;
+    use bitcoin::{secp256k1, PublicKey};
+
+    use super::*;
+    use crate::miniscript::lex::Token;
+    use crate::miniscript::satisfy::Witness;
+    use crate::miniscript::types::extra_props::{OpLimits, TimelockInfo};
+    use crate::{Descriptor, Legacy, Miniscript, Segwitv0};
+
+    /// `ctv(H)`, which requires the spending transaction to match the
+    /// template hash `H`, checked by `<H> OP_CHECKTEMPLATEVERIFY` (`OP_NOP4`).
+    #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+    struct Ctv(sha256::Hash);
+
+    impl fmt::Display for Ctv {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "ctv({})", self.0) }
+    }
+
+    impl Extension for Ctv {
+        fn type_check(&self) -> Type { Type::time() }
+
+        fn extra_prop(&self) -> ExtData {
+            ExtData {
+                pk_cost: 33 + 1,
+                has_free_verify: false,
+                ops: OpLimits::new(1, Some(0), None),
+                stack_elem_count_sat: Some(0),
+                stack_elem_count_dissat: None,
+                max_sat_size: Some((0, 0)),
+                max_dissat_size: None,
+                timelock_info: TimelockInfo::new(),
+                exec_stack_elem_count_sat: Some(1), // <H>
+                exec_stack_elem_count_dissat: None,
+                tree_height: 0,
+            }
+        }
+
+        // The template commits to the scriptSig, which under p2sh holds the
+        // script, and so the template itself
+        fn check_context<Ctx: ScriptContext>(&self) -> Result<(), ScriptContextError> {
+            if Ctx::name_str() == Legacy::name_str() {
+                Err(ScriptContextError::ImpossibleSatisfaction)
+            } else {
+                Ok(())
+            }
+        }
+
+        fn from_tree(name: &str, node: expression::TreeIterItem) -> Result<Self, Error> {
+            match name {
+                "ctv" => node
+                    .verify_terminal_parent("ctv", "template hash")
+                    .map(Ctv)
+                    .map_err(Error::Parse),
+                _ => NoExt::from_tree(name, node).map(|ext| match ext {}),
+            }
+        }
+
+        fn from_token_iter(tokens: &mut TokenIter) -> Option<Self> {
+            if tokens.peek() != Some(&Token::Opcode(OP_NOP4)) {
+                return None;
+            }
+            let op = tokens.next().unwrap();
+            match tokens.next() {
+                Some(Token::Bytes32(hash)) => Some(Ctv(sha256::Hash::from_slice(hash).unwrap())),
+                other => {
+                    if let Some(tok) = other {
+                        tokens.un_next(tok);
+                    }
+                    tokens.un_next(op);
+                    None
+                }
+            }
+        }
+
+        fn encode(&self, builder: script::Builder) -> script::Builder {
+            builder
+                .push_slice(self.0.to_byte_array())
+                .push_opcode(OP_NOP4)
+        }
+
+        fn satisfy<Pk, P>(&self, _: &P) -> Satisfaction<Placeholder<Pk>>
+        where
+            Pk: MiniscriptKey + ToPublicKey,
+            P: AssetProvider<Pk>,
+        {
+            Satisfaction {
+                stack: Witness::Stack(vec![]),
+                has_sig: false,
+                absolute_timelock: None,
+                relative_timelock: None,
+            }
+        }
+
+        fn dissatisfy<Pk, P>(&self, _: &P) -> Satisfaction<Placeholder<Pk>>
+        where
+            Pk: MiniscriptKey + ToPublicKey,
+            P: AssetProvider<Pk>,
+        {
+            Satisfaction {
+                stack: Witness::Impossible,
+                has_sig: false,
+                absolute_timelock: None,
+                relative_timelock: None,
+            }
+        }
+    }
+
+    fn key_and_sig() -> (PublicKey, bitcoin::ecdsa::Signature) {
+        let secp = secp256k1::Secp256k1::new();
+        let sk = secp256k1::SecretKey::from_slice(&[1; 32]).unwrap();
+        let msg = secp256k1::Message::from_digest([2; 32]);
+        let pk = PublicKey::new(secp256k1::PublicKey::from_secret_key(&secp, &sk));
+        let sig = bitcoin::ecdsa::Signature {
+            signature: secp.sign_ecdsa(&msg, &sk),
+            sighash_type: bitcoin::sighash::EcdsaSighashType::All,
+        };
+        (pk, sig)
+    }
+
+    fn ms_string(pk: &PublicKey) -> String {
+        format!("and_v(v:pk({}),ctv({}))", pk, sha256::Hash::hash(b"template"))
+    }
+
+    #[test]
+    fn parse_and_encode() {
+        let (pk, _) = key_and_sig();
+        let s = ms_string(&pk);
+        let ms = Miniscript::<PublicKey, Segwitv0, Ctv>::from_str(&s).unwrap();
+        assert_eq!(ms.to_string(), s);
+        assert_eq!(ms.script_size(), 34 + 34 + 1);
+        assert!(ms.sanity_check().is_ok());
+
+        let script = ms.encode();
+        assert_eq!(script.len(), ms.script_size());
+        assert_eq!(Miniscript::<PublicKey, Segwitv0, Ctv>::parse(&script).unwrap(), ms);
+
+        // Plain Miniscript knows neither the fragment nor its opcode
+        assert!(matches!(
+            Miniscript::<PublicKey, Segwitv0>::from_str(&s),
+            Err(Error::Parse(ParseError::Tree(ParseTreeError::UnknownName { .. })))
+        ));
+        assert!(matches!(
+            Miniscript::<PublicKey, Segwitv0>::parse(&script),
+            Err(Error::InvalidOpcode(OP_NOP4))
+        ));
+    }
+
+    #[test]
+    fn context() {
+        let (pk, _) = key_and_sig();
+        let s = ms_string(&pk);
+        assert!(Miniscript::<PublicKey, Segwitv0, Ctv>::from_str(&s).is_ok());
+        assert!(matches!(
+            Miniscript::<PublicKey, Legacy, Ctv>::from_str(&s),
+            Err(Error::ContextError(ScriptContextError::ImpossibleSatisfaction))
+        ));
+    }
+
+    #[test]
+    fn descriptors() {
+        let (pk, sig) = key_and_sig();
+        let mut sigs = BTreeMap::new();
+        sigs.insert(pk, sig);
+
+        let wsh =
+            Descriptor::<PublicKey, Ctv>::from_str(&format!("wsh({})", ms_string(&pk))).unwrap();
+        assert_eq!(wsh, Descriptor::<PublicKey, Ctv>::from_str(&wsh.to_string()).unwrap());
+        let witness_script = wsh.explicit_script().unwrap();
+        assert_eq!(wsh.script_pubkey(), witness_script.to_p2wsh());
+        let (witness, script_sig) = wsh.get_satisfaction(&sigs).unwrap();
+        assert_eq!(witness, vec![sig.to_vec(), witness_script.into_bytes()]);
+        assert!(script_sig.is_empty());
+
+        let xonly = pk.inner.x_only_public_key().0;
+        let tr = Descriptor::<bitcoin::key::XOnlyPublicKey, Ctv>::from_str(&format!(
+            "tr({},and_v(v:pk({}),ctv({})))",
+            xonly,
+            xonly,
+            sha256::Hash::hash(b"template")
+        ))
+        .unwrap();
+        assert_eq!(tr.tap_tree_iter().count(), 1);
+        assert!(tr.max_weight_to_satisfy().is_ok());
+    }
+}
@@ -10,24 +10,41 @@ use core::ops::Deref;
 use sync::Arc;
 
 use super::decode::Terminal;
+use super::extension::{Extension, NoExt};
 use super::{Miniscript, MiniscriptKey, ScriptContext};
 use crate::prelude::*;
 
 /// Iterator-related extensions for [Miniscript]
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Miniscript<Pk, Ctx, Ext> {
     /// Creates a new [Iter] iterator that will iterate over all [Miniscript] items within
     /// AST by traversing its branches. For the specific algorithm please see
     /// [Iter::next] function.
-    pub fn iter(&self) -> Iter<Pk, Ctx> { Iter::new(self) }
+    pub fn iter(&self) -> Iter<Pk, Ctx, Ext> { Iter::new(self) }
 
     /// Creates a new [PkIter] iterator that will iterate over all plain public keys (and not
     /// key hash values) present in [Miniscript] items within AST by traversing all its branches.
     /// For the specific algorithm please see [PkIter::next] function.
-    pub fn iter_pk(&self) -> PkIter<Pk, Ctx> { PkIter::new(self) }
+    pub fn iter_pk(&self) -> PkIter<Pk, Ctx, Ext> { PkIter::new(self) }
+
+    /// Creates a new [PathIter] iterator that will iterate over all [Terminal] fragments within
+    /// AST, together with the path of child indices leading from `self` to each fragment.
+    ///
+    /// The fragments are yielded in the same order as [Miniscript::iter]; the root is yielded
+    /// with an empty path.
+    pub fn iter_with_paths(&self) -> PathIter<'_, Pk, Ctx, Ext> { PathIter::new(self) }
+
+    /// Returns the node reached by following the given path of child indices from `self`,
+    /// if any. An empty path returns `self`.
+    ///
+    /// Paths are as yielded by [Miniscript::iter_with_paths].
+    pub fn get_at_path(&self, path: &[usize]) -> Option<&Miniscript<Pk, Ctx, Ext>> {
+        path.iter()
+            .try_fold(self, |node, &idx| node.get_nth_child(idx))
+    }
 
     /// Enumerates all child nodes of the current AST node (`self`) and returns a `Vec` referencing
     /// them.
-    pub fn branches(&self) -> Vec<&Miniscript<Pk, Ctx>> {
+    pub fn branches(&self) -> Vec<&Miniscript<Pk, Ctx, Ext>> {
         match self.node {
             Terminal::PkK(_) | Terminal::PkH(_) | Terminal::RawPkH(_) | Terminal::Multi(_) => {
                 vec![]
@@ -57,7 +74,7 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
     }
 
     /// Returns child node with given index, if any
-    pub fn get_nth_child(&self, n: usize) -> Option<&Miniscript<Pk, Ctx>> {
+    pub fn get_nth_child(&self, n: usize) -> Option<&Miniscript<Pk, Ctx, Ext>> {
         match (n, &self.node) {
             (0, Terminal::Alt(node))
             | (0, Terminal::Swap(node))
@@ -104,22 +121,24 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
 
 /// Iterator for traversing all [Miniscript] miniscript AST references starting from some specific
 /// node which constructs the iterator via [Miniscript::iter] method.
-pub struct Iter<'a, Pk: MiniscriptKey, Ctx: ScriptContext> {
-    next: Option<&'a Miniscript<Pk, Ctx>>,
+pub struct Iter<'a, Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension = NoExt> {
+    next: Option<&'a Miniscript<Pk, Ctx, Ext>>,
     // Here we store vec of path elements, where each element is a tuple, consisting of:
     // 1. Miniscript node on the path
     // 2. Index of the current branch
-    path: Vec<(&'a Miniscript<Pk, Ctx>, usize)>,
+    path: Vec<(&'a Miniscript<Pk, Ctx, Ext>, usize)>,
 }
 
-impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> Iter<'a, Pk, Ctx> {
-    fn new(miniscript: &'a Miniscript<Pk, Ctx>) -> Self {
+impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Iter<'a, Pk, Ctx, Ext> {
+    fn new(miniscript: &'a Miniscript<Pk, Ctx, Ext>) -> Self {
         Iter { next: Some(miniscript), path: vec![] }
     }
 }
 
-impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> Iterator for Iter<'a, Pk, Ctx> {
-    type Item = &'a Miniscript<Pk, Ctx>;
+impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Iterator
+    for Iter<'a, Pk, Ctx, Ext>
+{
+    type Item = &'a Miniscript<Pk, Ctx, Ext>;
 
     /// First, the function returns `self`, then the first child of the self (if any),
     /// then proceeds to the child of the child — down to a leaf of the tree in its first branch.
@@ -163,20 +182,20 @@ impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> Iterator for Iter<'a, Pk, Ctx> {
 
 /// Iterator for traversing all [MiniscriptKey]'s in AST starting from some specific node which
 /// constructs the iterator via [Miniscript::iter_pk] method.
-pub struct PkIter<'a, Pk: MiniscriptKey, Ctx: ScriptContext> {
-    node_iter: Iter<'a, Pk, Ctx>,
-    curr_node: Option<&'a Miniscript<Pk, Ctx>>,
+pub struct PkIter<'a, Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension = NoExt> {
+    node_iter: Iter<'a, Pk, Ctx, Ext>,
+    curr_node: Option<&'a Miniscript<Pk, Ctx, Ext>>,
     key_index: usize,
 }
 
-impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> PkIter<'a, Pk, Ctx> {
-    fn new(miniscript: &'a Miniscript<Pk, Ctx>) -> Self {
+impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> PkIter<'a, Pk, Ctx, Ext> {
+    fn new(miniscript: &'a Miniscript<Pk, Ctx, Ext>) -> Self {
         let mut iter = Iter::new(miniscript);
         PkIter { curr_node: iter.next(), node_iter: iter, key_index: 0 }
     }
 }
 
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> Iterator for PkIter<'_, Pk, Ctx> {
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Iterator for PkIter<'_, Pk, Ctx, Ext> {
     type Item = Pk;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -199,6 +218,41 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Iterator for PkIter<'_, Pk, Ctx> {
     }
 }
 
+/// Iterator for traversing all [Terminal] fragments in AST together with their paths, starting
+/// from some specific node which constructs the iterator via [Miniscript::iter_with_paths] method.
+///
+/// A path is the sequence of child indices (as accepted by [Miniscript::get_nth_child]) which
+/// leads from the root node to the yielded fragment.
+pub struct PathIter<'a, Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension = NoExt> {
+    // Stack of nodes still to be yielded, along with their paths. Children are pushed in
+    // reverse order so that they are popped left-to-right.
+    #[allow(clippy::type_complexity)]
+    stack: Vec<(Vec<usize>, &'a Miniscript<Pk, Ctx, Ext>)>,
+}
+
+impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> PathIter<'a, Pk, Ctx, Ext> {
+    fn new(miniscript: &'a Miniscript<Pk, Ctx, Ext>) -> Self {
+        PathIter { stack: vec![(vec![], miniscript)] }
+    }
+}
+
+impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Iterator
+    for PathIter<'a, Pk, Ctx, Ext>
+{
+    type Item = (Vec<usize>, &'a Terminal<Pk, Ctx, Ext>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, node) = self.stack.pop()?;
+        for (idx, child) in node.branches().into_iter().enumerate().rev() {
+            let mut child_path = Vec::with_capacity(path.len() + 1);
+            child_path.extend_from_slice(&path);
+            child_path.push(idx);
+            self.stack.push((child_path, child));
+        }
+        Some((path, &node.node))
+    }
+}
+
 /// Module is public since it export testcase generation which may be used in
 /// dependent libraries for their own tasts based on Miniscript AST
 #[cfg(test)]
@@ -345,4 +399,38 @@ pub mod test {
             assert_eq!(ms.iter_pk().collect::<Vec<bitcoin::PublicKey>>(), k);
         })
     }
+
+    #[test]
+    fn paths() {
+        gen_testcases().into_iter().for_each(|(ms, _, _, _)| {
+            let with_paths = ms.iter_with_paths().collect::<Vec<_>>();
+            let plain = ms.iter().collect::<Vec<_>>();
+            assert_eq!(with_paths.len(), plain.len());
+            for ((path, term), node) in with_paths.iter().zip(plain) {
+                assert_eq!(*term, &node.node);
+                assert_eq!(ms.get_at_path(path).map(|n| &n.node), Some(*term));
+            }
+        });
+
+        let k = gen_bitcoin_pubkeys(3, true);
+        let ms: Miniscript<bitcoin::PublicKey, Segwitv0> =
+            ms_str!("or_d(c:pk_k({}),andor(c:pk_k({}),older(10),c:pk_k({})))", k[0], k[1], k[2]);
+        let paths = ms.iter_with_paths().map(|(p, _)| p).collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                vec![],
+                vec![0],
+                vec![0, 0],
+                vec![1],
+                vec![1, 0],
+                vec![1, 0, 0],
+                vec![1, 1],
+                vec![1, 2],
+                vec![1, 2, 0],
+            ]
+        );
+        assert_eq!(ms.get_at_path(&[1, 2, 0]).unwrap().node, crate::Terminal::PkK(k[2]));
+        assert!(ms.get_at_path(&[1, 3]).is_none());
+    }
 }
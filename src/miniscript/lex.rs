@@ -5,7 +5,7 @@
 //! Translates a script into a reversed sequence of tokens
 //!
 
-use core::fmt;
+use core::{fmt, hash, mem};
 
 use bitcoin::blockdata::{opcodes, script};
 
@@ -13,7 +13,7 @@ use super::Error;
 use crate::prelude::*;
 
 /// Atom of a tokenized version of a script
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[allow(missing_docs)]
 pub enum Token<'s> {
     BoolAnd,
@@ -48,6 +48,24 @@ pub enum Token<'s> {
     Bytes32(&'s [u8]),
     Bytes33(&'s [u8]),
     Bytes65(&'s [u8]),
+    /// An opcode Miniscript does not use, left for the fragments of an
+    /// [`Extension`](super::extension::Extension) to decode.
+    Opcode(opcodes::Opcode),
+}
+
+// Opcodes are not `Hash`, so this cannot be derived
+impl hash::Hash for Token<'_> {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+        match *self {
+            Token::Num(n) => n.hash(state),
+            Token::Hash20(b) | Token::Bytes32(b) | Token::Bytes33(b) | Token::Bytes65(b) => {
+                b.hash(state)
+            }
+            Token::Opcode(op) => op.to_u8().hash(state),
+            _ => {}
+        }
+    }
 }
 
 impl fmt::Display for Token<'_> {
@@ -275,7 +293,7 @@ pub fn lex(script: &'_ script::Script) -> Result<Vec<Token<'_>>, Error> {
             script::Instruction::Op(opcodes::all::OP_PUSHNUM_16) => {
                 ret.push(Token::Num(16));
             }
-            script::Instruction::Op(op) => return Err(Error::InvalidOpcode(op)),
+            script::Instruction::Op(op) => ret.push(Token::Opcode(op)),
         };
     }
     Ok(ret)
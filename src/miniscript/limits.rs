@@ -10,6 +10,12 @@ pub const MAX_OPS_PER_SCRIPT: usize = 201;
 /// Maximum p2wsh initial stack items
 // https://github.com/bitcoin/bitcoin/blob/875e1ccc9fe01e026e564dfd39a64d9a4b332a89/src/policy/policy.h#L40
 pub const MAX_STANDARD_P2WSH_STACK_ITEMS: usize = 100;
+/// Maximum size of p2wsh initial stack items, besides the witness script
+// https://github.com/bitcoin/bitcoin/blob/875e1ccc9fe01e026e564dfd39a64d9a4b332a89/src/policy/policy.h#L42
+pub const MAX_STANDARD_P2WSH_STACK_ITEM_SIZE: usize = 80;
+/// Maximum size of tapscript initial stack items, besides the script and control block
+// https://github.com/bitcoin/bitcoin/blob/99b06b7f1d4194fb8036b90e5308101645f968e7/src/policy/policy.h#L46
+pub const MAX_STANDARD_TAPSCRIPT_STACK_ITEM_SIZE: usize = 80;
 /// Maximum script size allowed by consensus rules
 // https://github.com/bitcoin/bitcoin/blob/42b66a6b814bca130a9ccf0a3f747cf33d628232/src/script/script.h#L32
 pub const MAX_SCRIPT_SIZE: usize = 10_000;
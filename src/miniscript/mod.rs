@@ -12,23 +12,25 @@
 //! components of the AST.
 //!
 
-use core::{hash, str};
+use core::{hash, mem, str};
 
 use bitcoin::hashes::hash160;
+use bitcoin::hex::DisplayHex;
 use bitcoin::script;
 use bitcoin::taproot::{LeafVersion, TapLeafHash};
 
-use self::analyzable::ExtParams;
+use self::analyzable::{AnalysisError, ExtParams};
 pub use self::context::{BareCtx, Legacy, Segwitv0, Tap};
 use crate::iter::TreeLike;
 use crate::prelude::*;
-use crate::{script_num_size, TranslateErr};
+use crate::{script_num_size, FnTranslator, TranslateErr};
 
 pub mod analyzable;
 pub mod astelem;
 pub(crate) mod context;
 pub mod decode;
 mod display;
+pub mod extension;
 pub mod iter;
 pub mod lex;
 pub mod limits;
@@ -39,12 +41,14 @@ use core::cmp;
 
 use sync::Arc;
 
-use self::lex::{lex, TokenIter};
+use self::extension::Extension;
+use self::lex::{lex, Token, TokenIter};
 use crate::expression::{FromTree, TreeIterItem};
 pub use crate::miniscript::context::ScriptContext;
 use crate::miniscript::decode::Terminal;
 use crate::{
-    expression, plan, Error, ForEachKey, FromStrKey, MiniscriptKey, ToPublicKey, Translator,
+    expression, plan, Error, ForEachKey, FromStrKey, MiniscriptKey, SatisfyError, SigType,
+    ToPublicKey, Translator,
 };
 #[cfg(test)]
 mod ms_tests;
@@ -52,6 +56,7 @@ mod ms_tests;
 mod private {
     use core::marker::PhantomData;
 
+    use super::extension::{Extension, NoExt};
     use super::types::{ExtData, Type};
     use crate::iter::TreeLike as _;
     pub use crate::miniscript::context::ScriptContext;
@@ -60,9 +65,9 @@ mod private {
     use crate::{AbsLockTime, Error, MiniscriptKey, RelLockTime, Terminal, MAX_RECURSION_DEPTH};
 
     /// The top-level miniscript abstract syntax tree (AST).
-    pub struct Miniscript<Pk: MiniscriptKey, Ctx: ScriptContext> {
+    pub struct Miniscript<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension = NoExt> {
         /// A node in the AST.
-        pub node: Terminal<Pk, Ctx>,
+        pub node: Terminal<Pk, Ctx, Ext>,
         /// The correctness and malleability type information for the AST node.
         pub ty: types::Type,
         /// Additional information helpful for extra analysis.
@@ -71,7 +76,7 @@ mod private {
         phantom: PhantomData<Ctx>,
     }
 
-    impl<Pk: MiniscriptKey, Ctx: ScriptContext> Clone for Miniscript<Pk, Ctx> {
+    impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Clone for Miniscript<Pk, Ctx, Ext> {
         /// We implement clone as a "deep clone" which reconstructs the entire tree.
         ///
         /// If users just want to clone Arcs they can use Arc::clone themselves.
@@ -126,6 +131,7 @@ mod private {
                     }
                     Terminal::Multi(ref thresh) => Terminal::Multi(thresh.clone()),
                     Terminal::MultiA(ref thresh) => Terminal::MultiA(thresh.clone()),
+                    Terminal::Ext(ref e) => Terminal::Ext(e.clone()),
                 };
 
                 stack.push(Arc::new(Miniscript {
@@ -141,7 +147,7 @@ mod private {
         }
     }
 
-    impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
+    impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Miniscript<Pk, Ctx, Ext> {
         /// The `1` combinator.
         pub const TRUE: Self = Miniscript {
             node: Terminal::True,
@@ -273,7 +279,7 @@ mod private {
         /// Add type information(Type and Extdata) to Miniscript based on
         /// `AstElem` fragment. Dependent on display and clone because of Error
         /// Display code of type_check.
-        pub fn from_ast(t: Terminal<Pk, Ctx>) -> Result<Miniscript<Pk, Ctx>, Error> {
+        pub fn from_ast(t: Terminal<Pk, Ctx, Ext>) -> Result<Miniscript<Pk, Ctx, Ext>, Error> {
             let res = Miniscript {
                 ty: Type::type_check(&t)?,
                 ext: ExtData::type_check(&t),
@@ -287,6 +293,9 @@ mod private {
             if (res.ext.tree_height as u32) > MAX_RECURSION_DEPTH {
                 return Err(Error::MaxRecursiveDepthExceeded);
             }
+            if let Terminal::Ext(ref e) = res.node {
+                e.check_context::<Ctx>()?;
+            }
             Ctx::check_global_validity(&res)?;
             Ok(res)
         }
@@ -297,10 +306,10 @@ mod private {
         ///
         /// You should almost always use `Miniscript::from_ast` instead of this function.
         pub fn from_components_unchecked(
-            node: Terminal<Pk, Ctx>,
+            node: Terminal<Pk, Ctx, Ext>,
             ty: types::Type,
             ext: types::extra_props::ExtData,
-        ) -> Miniscript<Pk, Ctx> {
+        ) -> Miniscript<Pk, Ctx, Ext> {
             Miniscript { node, ty, ext, phantom: PhantomData }
         }
     }
@@ -308,12 +317,12 @@ mod private {
 
 pub use private::Miniscript;
 
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Miniscript<Pk, Ctx, Ext> {
     /// Extracts the `AstElem` representing the root of the miniscript
-    pub fn into_inner(self) -> Terminal<Pk, Ctx> { self.node }
+    pub fn into_inner(self) -> Terminal<Pk, Ctx, Ext> { self.node }
 
     /// Get a reference to the inner `AstElem` representing the root of miniscript
-    pub fn as_inner(&self) -> &Terminal<Pk, Ctx> { &self.node }
+    pub fn as_inner(&self) -> &Terminal<Pk, Ctx, Ext> { &self.node }
 
     /// Encode as a Bitcoin script
     pub fn encode(&self) -> script::ScriptBuf
@@ -323,6 +332,18 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
         self.node.encode(script::Builder::new()).into_script()
     }
 
+    /// Encode as a Bitcoin script, appending it to `buf`
+    ///
+    /// Unlike [`Miniscript::encode`], this does not allocate a new script, so
+    /// that the same buffer can be reused to encode many scripts.
+    pub fn encode_to(&self, buf: &mut Vec<u8>)
+    where
+        Pk: ToPublicKey,
+    {
+        let builder = script::Builder::from(mem::take(buf));
+        *buf = self.node.encode(builder).into_bytes();
+    }
+
     /// Size, in bytes of the script-pubkey. If this Miniscript is used outside
     /// of segwit (e.g. in a bare or P2SH descriptor), this quantity should be
     /// multiplied by 4 to compute the weight.
@@ -367,6 +388,7 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
                         + thresh.iter().map(|pk| Ctx::pk_len(pk)).sum::<usize>() // n keys
                         + thresh.n() // n times CHECKSIGADD
                 }
+                Terminal::Ext(ref e) => e.extra_prop().pk_cost,
             }
         }
         len
@@ -383,7 +405,7 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
         self.ext
             .stack_elem_count_sat
             .map(|x| x + 1)
-            .ok_or(Error::ImpossibleSatisfaction)
+            .ok_or(Error::Satisfy(SatisfyError::ImpossibleSatisfaction))
     }
 
     /// Maximum size, in bytes, of a satisfying witness. For Segwit outputs
@@ -399,7 +421,7 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
     /// length prefix (segwit) or push opcode (pre-segwit) and sighash
     /// postfix.
     pub fn max_satisfaction_size(&self) -> Result<usize, Error> {
-        Ctx::max_satisfaction_size(self).ok_or(Error::ImpossibleSatisfaction)
+        Ctx::max_satisfaction_size(self).ok_or(Error::Satisfy(SatisfyError::ImpossibleSatisfaction))
     }
 
     /// Helper function to produce Taproot leaf hashes
@@ -454,7 +476,7 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
                 Ok(stack)
             }
             satisfy::Witness::Unavailable | satisfy::Witness::Impossible => {
-                Err(Error::CouldNotSatisfy)
+                Err(Error::Satisfy(SatisfyError::CouldNotSatisfy))
             }
         }
     }
@@ -490,16 +512,161 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
             &self.leaf_hash_internal(),
         )
     }
+
+    /// Returns the assets missing from `provider` for each candidate spending path
+    ///
+    /// This can be used to explain why [`Miniscript::build_template`] or
+    /// [`Miniscript::satisfy`] failed.
+    pub fn missing_assets<P: plan::AssetProvider<Pk>>(
+        &self,
+        provider: &P,
+    ) -> plan::MissingAssets<Pk>
+    where
+        Pk: ToPublicKey,
+    {
+        use plan::{MissingAsset, MissingAssets};
+
+        let leaf_hash = self.leaf_hash_internal();
+        let sig = |pk: &Pk| {
+            let available = match Ctx::sig_type() {
+                SigType::Ecdsa => provider.provider_lookup_ecdsa_sig(pk),
+                SigType::Schnorr => provider
+                    .provider_lookup_tap_leaf_script_sig(pk, &leaf_hash)
+                    .is_some(),
+            };
+            MissingAssets::requires(MissingAsset::Signature(pk.clone()), available)
+        };
+
+        let mut stack = vec![];
+        for item in self.rtl_post_order_iter() {
+            let missing = match item.node.node {
+                Terminal::PkK(ref pk) | Terminal::PkH(ref pk) => sig(pk),
+                Terminal::RawPkH(ref hash) => {
+                    let available = match Ctx::sig_type() {
+                        SigType::Ecdsa => {
+                            provider.provider_lookup_raw_pkh_ecdsa_sig(hash).is_some()
+                        }
+                        SigType::Schnorr => provider
+                            .provider_lookup_raw_pkh_tap_leaf_script_sig(&(*hash, leaf_hash))
+                            .is_some(),
+                    };
+                    MissingAssets::requires(MissingAsset::KeyHashSignature(*hash), available)
+                }
+                Terminal::After(t) => MissingAssets::requires(
+                    MissingAsset::AbsoluteTimelock(t),
+                    provider.check_after(t.into()),
+                ),
+                Terminal::Older(t) => MissingAssets::requires(
+                    MissingAsset::RelativeTimelock(t),
+                    provider.check_older(t.into()),
+                ),
+                Terminal::Sha256(ref h) => MissingAssets::requires(
+                    MissingAsset::Sha256Preimage(h.clone()),
+                    provider.provider_lookup_sha256(h)
+                        || provider.provider_lookup_pending_sha256(h),
+                ),
+                Terminal::Hash256(ref h) => MissingAssets::requires(
+                    MissingAsset::Hash256Preimage(h.clone()),
+                    provider.provider_lookup_hash256(h)
+                        || provider.provider_lookup_pending_hash256(h),
+                ),
+                Terminal::Ripemd160(ref h) => MissingAssets::requires(
+                    MissingAsset::Ripemd160Preimage(h.clone()),
+                    provider.provider_lookup_ripemd160(h)
+                        || provider.provider_lookup_pending_ripemd160(h),
+                ),
+                Terminal::Hash160(ref h) => MissingAssets::requires(
+                    MissingAsset::Hash160Preimage(h.clone()),
+                    provider.provider_lookup_hash160(h)
+                        || provider.provider_lookup_pending_hash160(h),
+                ),
+                Terminal::True => MissingAssets::available(),
+                Terminal::False => MissingAssets::impossible(),
+                Terminal::Alt(..)
+                | Terminal::Swap(..)
+                | Terminal::Check(..)
+                | Terminal::DupIf(..)
+                | Terminal::Verify(..)
+                | Terminal::NonZero(..)
+                | Terminal::ZeroNotEqual(..) => stack.pop().unwrap(),
+                Terminal::AndV(..) | Terminal::AndB(..) => {
+                    let left: MissingAssets<Pk> = stack.pop().unwrap();
+                    left.and(stack.pop().unwrap())
+                }
+                Terminal::AndOr(..) => {
+                    let (a, b, c) =
+                        (stack.pop().unwrap(), stack.pop().unwrap(), stack.pop().unwrap());
+                    a.and(b).or(c)
+                }
+                Terminal::OrB(..) | Terminal::OrD(..) | Terminal::OrC(..) | Terminal::OrI(..) => {
+                    let left: MissingAssets<Pk> = stack.pop().unwrap();
+                    left.or(stack.pop().unwrap())
+                }
+                Terminal::Thresh(ref thresh) => {
+                    let subs = (0..thresh.n())
+                        .map(|_| stack.pop().unwrap())
+                        .collect::<Vec<_>>();
+                    MissingAssets::thresh(thresh.k(), subs)
+                }
+                Terminal::Multi(ref thresh) => {
+                    MissingAssets::thresh(thresh.k(), thresh.iter().map(sig))
+                }
+                Terminal::MultiA(ref thresh) => {
+                    MissingAssets::thresh(thresh.k(), thresh.iter().map(sig))
+                }
+                // What the fragments of an extension need is up to the extension
+                Terminal::Ext(ref e) => match e.satisfy(provider).stack {
+                    satisfy::Witness::Stack(_) => MissingAssets::available(),
+                    _ => MissingAssets::impossible(),
+                },
+            };
+            stack.push(missing);
+        }
+        stack.pop().unwrap()
+    }
 }
 
-impl Miniscript<<Tap as ScriptContext>::Key, Tap> {
+impl<Ext: Extension> Miniscript<<Tap as ScriptContext>::Key, Tap, Ext> {
     /// Returns the leaf hash used within a Taproot signature for this script.
     ///
     /// Note that this method is only implemented for Taproot Miniscripts.
     pub fn leaf_hash(&self) -> TapLeafHash { self.leaf_hash_internal() }
 }
 
-impl<Ctx: ScriptContext> Miniscript<Ctx::Key, Ctx> {
+// Replaces the hybrid keys pushed by `script` with the equivalent uncompressed
+// keys, returning `None` if there are none. Fails with the first hybrid key if
+// they are not allowed.
+fn normalize_hybrid_keys(
+    script: &script::Script,
+    allowed: bool,
+) -> Result<Option<script::ScriptBuf>, Error> {
+    let mut normalized: Option<Vec<u8>> = None;
+    for ins in script.instruction_indices() {
+        if let Ok((pos, script::Instruction::PushBytes(push))) = ins {
+            let bytes = push.as_bytes();
+            if bytes.len() != 65 || (bytes[0] != 0x06 && bytes[0] != 0x07) {
+                continue;
+            }
+            if !allowed {
+                let key = bytes.to_lower_hex_string();
+                return Err(Error::AnalysisError(AnalysisError::HybridKey(key)));
+            }
+            // Keys whose prefix does not match the parity of y fail to parse later
+            if bitcoin::secp256k1::PublicKey::from_slice(bytes).is_ok() {
+                let offset = match script.as_bytes()[pos] {
+                    0x4c => 2, // OP_PUSHDATA1
+                    0x4d => 3, // OP_PUSHDATA2
+                    0x4e => 5, // OP_PUSHDATA4
+                    _ => 1,
+                };
+                normalized.get_or_insert_with(|| script.to_bytes())[pos + offset] = 0x04;
+            }
+        }
+    }
+    Ok(normalized.map(script::ScriptBuf::from))
+}
+
+impl<Ctx: ScriptContext, Ext: Extension> Miniscript<Ctx::Key, Ctx, Ext> {
     /// Attempt to parse an insane(scripts don't clear sanity checks)
     /// script into a Miniscript representation.
     /// Use this to parse scripts with repeated pubkeys, timelock mixing, malleable
@@ -507,7 +674,7 @@ impl<Ctx: ScriptContext> Miniscript<Ctx::Key, Ctx> {
     /// Some of the analysis guarantees of miniscript are lost when dealing with
     /// insane scripts. In general, in a multi-party setting users should only
     /// accept sane scripts.
-    pub fn parse_insane(script: &script::Script) -> Result<Miniscript<Ctx::Key, Ctx>, Error> {
+    pub fn parse_insane(script: &script::Script) -> Result<Miniscript<Ctx::Key, Ctx, Ext>, Error> {
         Miniscript::parse_with_ext(script, &ExtParams::insane())
     }
 
@@ -516,12 +683,19 @@ impl<Ctx: ScriptContext> Miniscript<Ctx::Key, Ctx> {
     /// Currently, this function supports the following features:
     ///     - Parsing all insane scripts
     ///     - Parsing miniscripts with raw pubkey hashes
+    ///     - Parsing Bare and Legacy scripts with hybrid public keys
     ///
     /// Allowed extra features can be specified by the ext [`ExtParams`] argument.
     pub fn parse_with_ext(
         script: &script::Script,
         ext: &ExtParams,
-    ) -> Result<Miniscript<Ctx::Key, Ctx>, Error> {
+    ) -> Result<Miniscript<Ctx::Key, Ctx, Ext>, Error> {
+        let normalized = if Ctx::sig_type() == SigType::Ecdsa {
+            normalize_hybrid_keys(script, ext.hybrid_keys)?
+        } else {
+            None
+        };
+        let script = normalized.as_deref().unwrap_or(script);
         let tokens = lex(script)?;
         let mut iter = TokenIter::new(tokens);
 
@@ -532,7 +706,10 @@ impl<Ctx: ScriptContext> Miniscript<Ctx::Key, Ctx> {
             return Err(Error::NonTopLevel(format!("{:?}", top)));
         };
         if let Some(leading) = iter.next() {
-            Err(Error::Trailing(leading.to_string()))
+            match leading {
+                Token::Opcode(op) => Err(Error::InvalidOpcode(op)),
+                _ => Err(Error::Trailing(leading.to_string())),
+            }
         } else {
             top.ext_check(ext)?;
             Ok(top)
@@ -572,7 +749,7 @@ impl<Ctx: ScriptContext> Miniscript<Ctx::Key, Ctx> {
     ///     .expect("Compressed keys are allowed in Segwit context");
     ///
     /// ```
-    pub fn parse(script: &script::Script) -> Result<Miniscript<Ctx::Key, Ctx>, Error> {
+    pub fn parse(script: &script::Script) -> Result<Miniscript<Ctx::Key, Ctx, Ext>, Error> {
         let ms = Self::parse_with_ext(script, &ExtParams::sane())?;
         Ok(ms)
     }
@@ -581,8 +758,10 @@ impl<Ctx: ScriptContext> Miniscript<Ctx::Key, Ctx> {
 /// `PartialOrd` of `Miniscript` must depend only on node and not the type information.
 ///
 /// The type information and extra properties are implied by the AST.
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> PartialOrd for Miniscript<Pk, Ctx> {
-    fn partial_cmp(&self, other: &Miniscript<Pk, Ctx>) -> Option<cmp::Ordering> {
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> PartialOrd
+    for Miniscript<Pk, Ctx, Ext>
+{
+    fn partial_cmp(&self, other: &Miniscript<Pk, Ctx, Ext>) -> Option<cmp::Ordering> {
         Some(self.node.cmp(&other.node))
     }
 }
@@ -590,30 +769,34 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> PartialOrd for Miniscript<Pk, Ctx> {
 /// `Ord` of `Miniscript` must depend only on node and not the type information.
 ///
 /// The type information and extra properties are implied by the AST.
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> Ord for Miniscript<Pk, Ctx> {
-    fn cmp(&self, other: &Miniscript<Pk, Ctx>) -> cmp::Ordering { self.node.cmp(&other.node) }
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Ord for Miniscript<Pk, Ctx, Ext> {
+    fn cmp(&self, other: &Miniscript<Pk, Ctx, Ext>) -> cmp::Ordering { self.node.cmp(&other.node) }
 }
 
 /// `PartialEq` of `Miniscript` must depend only on node and not the type information.
 ///
 /// The type information and extra properties are implied by the AST.
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> PartialEq for Miniscript<Pk, Ctx> {
-    fn eq(&self, other: &Miniscript<Pk, Ctx>) -> bool { self.node.eq(&other.node) }
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> PartialEq for Miniscript<Pk, Ctx, Ext> {
+    fn eq(&self, other: &Miniscript<Pk, Ctx, Ext>) -> bool { self.node.eq(&other.node) }
 }
 
 /// `Eq` of `Miniscript` must depend only on node and not the type information.
 ///
 /// The type information and extra properties are implied by the AST.
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> Eq for Miniscript<Pk, Ctx> {}
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Eq for Miniscript<Pk, Ctx, Ext> {}
 
 /// `Hash` of `Miniscript` must depend only on node and not the type information.
 ///
 /// The type information and extra properties are implied by the AST.
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> hash::Hash for Miniscript<Pk, Ctx> {
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> hash::Hash
+    for Miniscript<Pk, Ctx, Ext>
+{
     fn hash<H: hash::Hasher>(&self, state: &mut H) { self.node.hash(state); }
 }
 
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> ForEachKey<Pk> for Miniscript<Pk, Ctx> {
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> ForEachKey<Pk>
+    for Miniscript<Pk, Ctx, Ext>
+{
     fn for_each_key<'a, F: FnMut(&'a Pk) -> bool>(&'a self, mut pred: F) -> bool {
         for ms in self.pre_order_iter() {
             match ms.node {
@@ -646,23 +829,44 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> ForEachKey<Pk> for Miniscript<Pk, Ct
     }
 }
 
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
+impl<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension> Miniscript<Pk, Ctx, Ext> {
     /// Translates a struct from one generic to another where the translation
     /// for Pk is provided by [`Translator`]
     pub fn translate_pk<T>(
         &self,
         t: &mut T,
-    ) -> Result<Miniscript<T::TargetPk, Ctx>, TranslateErr<T::Error>>
+    ) -> Result<Miniscript<T::TargetPk, Ctx, Ext>, TranslateErr<T::Error>>
     where
         T: Translator<Pk>,
     {
         self.translate_pk_ctx(t)
     }
 
+    /// Translates a struct from one generic to another where the translation
+    /// for Pk is provided by the closure `f`
+    ///
+    /// Hashes are kept as they are, so both kinds of keys must use the same
+    /// hash types.
+    pub fn translate_pk_with<Q, E, F>(
+        &self,
+        f: F,
+    ) -> Result<Miniscript<Q, Ctx, Ext>, TranslateErr<E>>
+    where
+        Q: MiniscriptKey<
+            Sha256 = Pk::Sha256,
+            Hash256 = Pk::Hash256,
+            Ripemd160 = Pk::Ripemd160,
+            Hash160 = Pk::Hash160,
+        >,
+        F: FnMut(&Pk) -> Result<Q, E>,
+    {
+        self.translate_pk(&mut FnTranslator::new(f))
+    }
+
     pub(super) fn translate_pk_ctx<CtxQ, T>(
         &self,
         t: &mut T,
-    ) -> Result<Miniscript<T::TargetPk, CtxQ>, TranslateErr<T::Error>>
+    ) -> Result<Miniscript<T::TargetPk, CtxQ, Ext>, TranslateErr<T::Error>>
     where
         CtxQ: ScriptContext,
         T: Translator<Pk>,
@@ -718,6 +922,7 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
                 Terminal::MultiA(ref thresh) => {
                     Terminal::MultiA(thresh.translate_ref(|k| t.pk(k))?)
                 }
+                Terminal::Ext(ref e) => Terminal::Ext(e.clone()),
             };
             let new_ms = Miniscript::from_ast(new_term).map_err(TranslateErr::OuterError)?;
             translated.push(Arc::new(new_ms));
@@ -727,7 +932,10 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
     }
 
     /// Substitutes raw public keys hashes with the public keys as provided by map.
-    pub fn substitute_raw_pkh(&self, pk_map: &BTreeMap<hash160::Hash, Pk>) -> Miniscript<Pk, Ctx> {
+    pub fn substitute_raw_pkh(
+        &self,
+        pk_map: &BTreeMap<hash160::Hash, Pk>,
+    ) -> Miniscript<Pk, Ctx, Ext> {
         let mut stack = vec![];
         for item in self.rtl_post_order_iter() {
             let new_term = match item.node.node {
@@ -769,6 +977,7 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
                 }
                 Terminal::Multi(ref thresh) => Terminal::Multi(thresh.clone()),
                 Terminal::MultiA(ref thresh) => Terminal::MultiA(thresh.clone()),
+                Terminal::Ext(ref e) => Terminal::Ext(e.clone()),
             };
 
             stack.push(Arc::new(Miniscript::from_components_unchecked(
@@ -781,9 +990,40 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
         assert_eq!(stack.len(), 1);
         Arc::try_unwrap(stack.pop().unwrap()).unwrap()
     }
+
+    /// Substitutes all raw public key hashes with the public keys as provided by map.
+    ///
+    /// Unlike [`Miniscript::substitute_raw_pkh`], this fails unless every `expr_raw_pkh`
+    /// fragment can be resolved, so the returned miniscript contains no raw public key
+    /// hashes. On failure, the set of hashes which are missing from the map is returned.
+    pub fn resolve_raw_pkh(
+        &self,
+        pk_map: &BTreeMap<hash160::Hash, Pk>,
+    ) -> Result<Miniscript<Pk, Ctx, Ext>, BTreeSet<hash160::Hash>> {
+        let unresolved = self.unresolved_raw_pkh(pk_map);
+        if unresolved.is_empty() {
+            Ok(self.substitute_raw_pkh(pk_map))
+        } else {
+            Err(unresolved)
+        }
+    }
+
+    /// Returns the set of raw public key hashes in the miniscript which are missing
+    /// from the map.
+    pub(crate) fn unresolved_raw_pkh(
+        &self,
+        pk_map: &BTreeMap<hash160::Hash, Pk>,
+    ) -> BTreeSet<hash160::Hash> {
+        self.iter()
+            .filter_map(|ms| match ms.node {
+                Terminal::RawPkH(ref hash) if !pk_map.contains_key(hash) => Some(*hash),
+                _ => None,
+            })
+            .collect()
+    }
 }
 
-impl<Pk: FromStrKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
+impl<Pk: FromStrKey, Ctx: ScriptContext, Ext: Extension> Miniscript<Pk, Ctx, Ext> {
     /// Attempt to parse an insane(scripts don't clear sanity checks)
     /// from string into a Miniscript representation.
     /// Use this to parse scripts with repeated pubkeys, timelock mixing, malleable
@@ -791,7 +1031,7 @@ impl<Pk: FromStrKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
     /// Some of the analysis guarantees of miniscript are lost when dealing with
     /// insane scripts. In general, in a multi-party setting users should only
     /// accept sane scripts.
-    pub fn from_str_insane(s: &str) -> Result<Miniscript<Pk, Ctx>, Error> {
+    pub fn from_str_insane(s: &str) -> Result<Miniscript<Pk, Ctx, Ext>, Error> {
         Miniscript::from_str_ext(s, &ExtParams::insane())
     }
 
@@ -800,10 +1040,10 @@ impl<Pk: FromStrKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
     /// scripts, raw pubkey hashes without sig or scripts that can exceed resource limits.
     ///
     /// Use [`ExtParams`] builder to specify the types of non-sane rules to allow while parsing.
-    pub fn from_str_ext(s: &str, ext: &ExtParams) -> Result<Miniscript<Pk, Ctx>, Error> {
+    pub fn from_str_ext(s: &str, ext: &ExtParams) -> Result<Miniscript<Pk, Ctx, Ext>, Error> {
         // This checks for invalid ASCII chars
         let top = expression::Tree::from_str(s)?;
-        let ms: Miniscript<Pk, Ctx> = expression::FromTree::from_tree(top.root())?;
+        let ms: Miniscript<Pk, Ctx, Ext> = expression::FromTree::from_tree(top.root())?;
         ms.ext_check(ext)?;
 
         if ms.ty.corr.base != types::Base::B {
@@ -814,26 +1054,59 @@ impl<Pk: FromStrKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
     }
 }
 
-impl<Pk: FromStrKey, Ctx: ScriptContext> FromTree for Arc<Miniscript<Pk, Ctx>> {
+impl<Pk: FromStrKey, Ctx: ScriptContext, Ext: Extension> FromTree
+    for Arc<Miniscript<Pk, Ctx, Ext>>
+{
     fn from_tree(root: TreeIterItem) -> Result<Self, Error> {
         Miniscript::from_tree(root).map(Arc::new)
     }
 }
 
-impl<Pk: FromStrKey, Ctx: ScriptContext> FromTree for Miniscript<Pk, Ctx> {
+impl<Pk: FromStrKey, Ctx: ScriptContext, Ext: Extension> FromTree for Miniscript<Pk, Ctx, Ext> {
     fn from_tree(root: TreeIterItem) -> Result<Self, Error> {
         #[allow(clippy::type_complexity)]
-        fn binary<Pk: MiniscriptKey, Ctx: ScriptContext>(
+        fn binary<Pk: MiniscriptKey, Ctx: ScriptContext, Ext: Extension>(
             node: expression::TreeIterItem,
-            stack: &mut Vec<Arc<Miniscript<Pk, Ctx>>>,
+            stack: &mut Vec<Arc<Miniscript<Pk, Ctx, Ext>>>,
             name: &'static str,
-            termfn: fn(Arc<Miniscript<Pk, Ctx>>, Arc<Miniscript<Pk, Ctx>>) -> Terminal<Pk, Ctx>,
-        ) -> Result<Miniscript<Pk, Ctx>, Error> {
+            termfn: fn(
+                Arc<Miniscript<Pk, Ctx, Ext>>,
+                Arc<Miniscript<Pk, Ctx, Ext>>,
+            ) -> Terminal<Pk, Ctx, Ext>,
+        ) -> Result<Miniscript<Pk, Ctx, Ext>, Error> {
             node.verify_n_children(name, 2..=2)
                 .map_err(From::from)
                 .map_err(Error::Parse)?;
             Miniscript::from_ast(termfn(stack.pop().unwrap(), stack.pop().unwrap()))
         }
+        // The names of the fragments of Miniscript, as opposed to those of an extension
+        const FRAGMENT_NAMES: &[&str] = &[
+            "expr_raw_pkh",
+            "pk",
+            "pkh",
+            "pk_k",
+            "pk_h",
+            "after",
+            "older",
+            "sha256",
+            "hash256",
+            "ripemd160",
+            "hash160",
+            "1",
+            "0",
+            "and_v",
+            "and_b",
+            "and_n",
+            "andor",
+            "or_b",
+            "or_d",
+            "or_c",
+            "or_i",
+            "thresh",
+            "multi",
+            "multi_a",
+        ];
+
         root.verify_no_curly_braces()
             .map_err(From::from)
             .map_err(Error::Parse)?;
@@ -845,8 +1118,9 @@ impl<Pk: FromStrKey, Ctx: ScriptContext> FromTree for Miniscript<Pk, Ctx> {
             // in Miniscript that have a single child that these might be confused with.
             // (Well, there are, but they're all serialized as wrappers.)
             //
-            // We also skip all the children of multi/multi_a and the first child of thresh
-            // (which will be the k value, not a real child).
+            // We also skip all the children of multi/multi_a and of the fragments of the
+            // extension, and the first child of thresh (which will be the k value, not a
+            // real child).
             //
             // We do not do this check on the root node, because its parent might be wsh or
             // sh or something, and actually these ARE single-child combinators, but we don't
@@ -865,6 +1139,10 @@ impl<Pk: FromStrKey, Ctx: ScriptContext> FromTree for Miniscript<Pk, Ctx> {
                 if parent_name == "multi" || parent_name == "multi_a" {
                     continue;
                 }
+                // The arguments of the fragments of an extension
+                if !FRAGMENT_NAMES.contains(&parent_name) {
+                    continue;
+                }
                 if parent_name == "thresh" && node.is_first_child() {
                     continue;
                 }
@@ -964,11 +1242,9 @@ impl<Pk: FromStrKey, Ctx: ScriptContext> FromTree for Miniscript<Pk, Ctx> {
                     .verify_threshold(|sub| sub.verify_terminal("public_key").map_err(Error::Parse))
                     .map(Terminal::MultiA)
                     .and_then(Miniscript::from_ast),
-                x => {
-                    Err(Error::Parse(crate::ParseError::Tree(crate::ParseTreeError::UnknownName {
-                        name: x.to_owned(),
-                    })))
-                }
+                x => Ext::from_tree(x, node)
+                    .map(Terminal::Ext)
+                    .and_then(Miniscript::from_ast),
             }?;
 
             let mut new = Arc::new(new);
@@ -1014,12 +1290,12 @@ impl<Pk: FromStrKey, Ctx: ScriptContext> FromTree for Miniscript<Pk, Ctx> {
     }
 }
 
-impl<Pk: FromStrKey, Ctx: ScriptContext> str::FromStr for Miniscript<Pk, Ctx> {
+impl<Pk: FromStrKey, Ctx: ScriptContext, Ext: Extension> str::FromStr for Miniscript<Pk, Ctx, Ext> {
     type Err = Error;
     /// Parse a Miniscript from string and perform sanity checks
     /// See [Miniscript::from_str_insane] to parse scripts from string that
     /// do not clear the [Miniscript::sanity_check] checks.
-    fn from_str(s: &str) -> Result<Miniscript<Pk, Ctx>, Error> {
+    fn from_str(s: &str) -> Result<Miniscript<Pk, Ctx, Ext>, Error> {
         let ms = Self::from_str_ext(s, &ExtParams::sane())?;
         Ok(ms)
     }
@@ -1635,6 +1911,204 @@ mod tests {
         assert_eq!(wit, vec![schnorr_sig.as_ref().to_vec(), vec![], vec![]]);
     }
 
+    #[test]
+    fn satisfier_combinators() {
+        use bitcoin::absolute;
+
+        use super::satisfy::{FirstMatch, Union};
+
+        let keys = pubkeys(2);
+        let ms = Segwitv0Script::from_str_insane(&format!(
+            "or_d(pk({}),and_v(v:pk({}),after(1000)))",
+            keys[0], keys[1]
+        ))
+        .unwrap();
+
+        let sig = secp256k1::ecdsa::Signature::from_str("3045022100a7acc3719e9559a59d60d7b2837f9842df30e7edcd754e63227e6168cec72c5d022066c2feba4671c3d99ea75d9976b4da6c86968dbf3bab47b1061e7a1966b1778c").unwrap();
+        let sig = bitcoin::ecdsa::Signature {
+            signature: sig,
+            sighash_type: bitcoin::sighash::EcdsaSighashType::All,
+        };
+        let sigs = BTreeMap::from([(keys[1], sig)]);
+        let after = absolute::LockTime::from_height(1000).unwrap();
+
+        // Neither satisfier alone satisfies the script.
+        assert!(ms.satisfy(&sigs).is_err());
+        assert!(ms.satisfy(after).is_err());
+
+        let satisfiers: Vec<&dyn Satisfier<bitcoin::PublicKey>> = vec![&sigs, &after];
+        let first = FirstMatch::new(satisfiers.clone());
+        let wit = ms.satisfy(&first).unwrap();
+        assert_eq!(wit, vec![sig.to_vec(), vec![]]);
+
+        let union = Union::new(satisfiers);
+        assert_eq!(ms.satisfy(&union).unwrap(), wit);
+
+        // The highest priority satisfier wins.
+        let other_sig = bitcoin::ecdsa::Signature {
+            signature: sig.signature,
+            sighash_type: bitcoin::sighash::EcdsaSighashType::None,
+        };
+        let other = BTreeMap::from([(keys[1], other_sig)]);
+        let prioritized = FirstMatch::from_prioritized(vec![(0, &sigs), (1, &other)]);
+        assert_eq!(prioritized.satisfiers(), &[&other, &sigs]);
+        assert_eq!(
+            Satisfier::<bitcoin::PublicKey>::lookup_ecdsa_sig(&prioritized, &keys[1]),
+            Some(other_sig),
+        );
+    }
+
+    #[test]
+    fn sighash_satisfier() {
+        use bitcoin::sighash::{EcdsaSighashType, TapSighashType};
+
+        use super::satisfy::WithSighash;
+        use crate::plan::AssetProvider;
+
+        let keys = pubkeys(1);
+        let ms = Segwitv0Script::from_str_insane(&format!("pk({})", keys[0])).unwrap();
+        let sig = secp256k1::ecdsa::Signature::from_str("3045022100a7acc3719e9559a59d60d7b2837f9842df30e7edcd754e63227e6168cec72c5d022066c2feba4671c3d99ea75d9976b4da6c86968dbf3bab47b1061e7a1966b1778c").unwrap();
+        let all = bitcoin::ecdsa::Signature { signature: sig, sighash_type: EcdsaSighashType::All };
+        let single = bitcoin::ecdsa::Signature {
+            signature: sig,
+            sighash_type: EcdsaSighashType::SinglePlusAnyoneCanPay,
+        };
+        let all_sigs = BTreeMap::from([(keys[0], all)]);
+        let single_sigs = BTreeMap::from([(keys[0], single)]);
+        let sigs = (&all_sigs, &single_sigs);
+
+        // Only the signature with the requested sighash type is used.
+        assert_eq!(ms.satisfy(sigs).unwrap(), vec![all.to_vec()]);
+        assert_eq!(ms.satisfy(WithSighash::new(&sigs)).unwrap(), vec![all.to_vec()]);
+        let satisfier = WithSighash::new(&sigs).ecdsa(EcdsaSighashType::SinglePlusAnyoneCanPay);
+        assert_eq!(ms.satisfy(&satisfier).unwrap(), vec![single.to_vec()]);
+        assert!(ms.satisfy(WithSighash::new(&single_sigs)).is_err());
+
+        // A satisfier which can sign with any sighash type.
+        struct Signer(secp256k1::schnorr::Signature);
+
+        impl<Pk: ToPublicKey> Satisfier<Pk> for Signer {
+            fn lookup_tap_leaf_script_sig_with_sighash(
+                &self,
+                _: &Pk,
+                _: &TapLeafHash,
+                sighash_type: TapSighashType,
+            ) -> Option<bitcoin::taproot::Signature> {
+                Some(bitcoin::taproot::Signature { signature: self.0, sighash_type })
+            }
+        }
+
+        let schnorr_sig = secp256k1::schnorr::Signature::from_slice(&[1; 64]).unwrap();
+        let signer = Signer(schnorr_sig);
+        let key = XOnlyPublicKey::from(keys[0].inner);
+        let leaf_hash = TapLeafHash::all_zeros();
+        assert_eq!(
+            Satisfier::<XOnlyPublicKey>::lookup_tap_leaf_script_sig(&signer, &key, &leaf_hash),
+            None,
+        );
+        // Planning accounts for the sighash byte of non-default sighash types.
+        assert_eq!(
+            WithSighash::new(&signer).provider_lookup_tap_leaf_script_sig(&key, &leaf_hash),
+            Some(64),
+        );
+        let satisfier = WithSighash::new(&signer).tap(TapSighashType::SinglePlusAnyoneCanPay);
+        assert_eq!(satisfier.provider_lookup_tap_leaf_script_sig(&key, &leaf_hash), Some(65));
+    }
+
+    #[test]
+    fn preimage_satisfier() {
+        use bitcoin::hashes::ripemd160;
+
+        use super::hash256;
+        use super::satisfy::{PreimageLengthError, Preimages};
+
+        let preimage = [0x11; 32];
+        let ms = Segwitv0Script::from_str_insane(&format!(
+            "and_v(v:sha256({}),and_v(v:hash256({}),and_v(v:ripemd160({}),hash160({}))))",
+            sha256::Hash::hash(&preimage),
+            hash256::Hash::hash(&preimage),
+            ripemd160::Hash::hash(&preimage),
+            hash160::Hash::hash(&preimage),
+        ))
+        .unwrap();
+        let expected = vec![preimage.to_vec(); 4];
+
+        let preimages = Preimages::from_iter([preimage]);
+        assert_eq!(ms.satisfy(&preimages).unwrap(), expected);
+
+        let mut preimages = Preimages::new();
+        assert!(preimages.is_empty());
+        assert_eq!(preimages.insert(&[0x11; 20]), Err(PreimageLengthError { len: 20 }));
+        assert!(preimages.is_empty());
+        preimages.insert_sha256(&preimage).unwrap();
+        preimages.insert_hash256(&preimage).unwrap();
+        preimages.insert_ripemd160(&preimage).unwrap();
+        assert!(ms.satisfy(&preimages).is_err());
+        assert_eq!(preimages.insert_hash160(&preimage), Ok(hash160::Hash::hash(&preimage)));
+        assert_eq!(ms.satisfy(&preimages).unwrap(), expected);
+
+        // Plain maps of preimages can also be combined.
+        let sha256s = BTreeMap::from([(sha256::Hash::hash(&preimage), preimage)]);
+        let hash256s = BTreeMap::from([(hash256::Hash::hash(&preimage), preimage)]);
+        let ripemd160s = BTreeMap::from([(ripemd160::Hash::hash(&preimage), preimage)]);
+        let hash160s = BTreeMap::from([(hash160::Hash::hash(&preimage), preimage)]);
+        let satisfier = (sha256s, hash256s, ripemd160s, hash160s);
+        assert_eq!(ms.satisfy(&satisfier).unwrap(), expected);
+    }
+
+    #[test]
+    fn thresh_satisfaction_timelock_kinds() {
+        use bitcoin::absolute;
+
+        use crate::AbsLockTime;
+
+        struct SimpleSatisfier(bitcoin::ecdsa::Signature, Vec<bitcoin::PublicKey>);
+
+        // a satisfier with signatures for the given keys, which accepts any locktime
+        impl Satisfier<bitcoin::PublicKey> for SimpleSatisfier {
+            fn lookup_ecdsa_sig(
+                &self,
+                pk: &bitcoin::PublicKey,
+            ) -> Option<bitcoin::ecdsa::Signature> {
+                Some(self.0).filter(|_| self.1.contains(pk))
+            }
+
+            fn check_after(&self, _: absolute::LockTime) -> bool { true }
+        }
+
+        let sig = secp256k1::ecdsa::Signature::from_str("3045022100a7acc3719e9559a59d60d7b2837f9842df30e7edcd754e63227e6168cec72c5d022066c2feba4671c3d99ea75d9976b4da6c86968dbf3bab47b1061e7a1966b1778c").unwrap();
+        let sig = bitcoin::ecdsa::Signature::sighash_all(sig);
+
+        // The two timelocked branches are cheapest, but cannot be used together
+        // since one uses a height and the other a timestamp.
+        let keys = pubkeys(4);
+        let ms = Segwitv0Script::from_str_insane(&format!(
+            "thresh(2,and_b(pk({}),a:pk({})),a:andor(pk({}),n:after(100),0),a:andor(pk({}),n:after(500000001),0))",
+            keys[0], keys[1], keys[2], keys[3],
+        ))
+        .unwrap();
+
+        let s = SimpleSatisfier(sig, keys.clone());
+        let template = ms.build_template(&s);
+        assert!(matches!(template.stack, super::satisfy::Witness::Stack(_)));
+        assert_eq!(template.absolute_timelock, Some(AbsLockTime::from_consensus(100).unwrap()));
+        assert!(ms.satisfy(&s).is_ok());
+
+        // Without the first branch there is no valid combination.
+        let s = SimpleSatisfier(sig, keys[2..].to_vec());
+        assert!(ms.satisfy(&s).is_err());
+
+        // A timelock without signature which cannot be used is left over, so
+        // the witness would be malleable.
+        let ms = Segwitv0Script::from_str_insane(&format!(
+            "thresh(2,pk({}),sln:after(100),sln:after(500000001))",
+            keys[0],
+        ))
+        .unwrap();
+        let template = ms.build_template(&SimpleSatisfier(sig, keys.clone()));
+        assert_eq!(template.stack, super::satisfy::Witness::Unavailable);
+    }
+
     #[test]
     fn decode_bug_cpp_review() {
         let ms = Miniscript::<String, Segwitv0>::from_str_insane(
@@ -1674,6 +2148,10 @@ mod tests {
         map.insert(hash160, pk);
         let ms_no_raw = ms.substitute_raw_pkh(&map);
         assert_eq!(ms_no_raw.to_string(), format!("pkh({})", pk),);
+        assert_eq!(ms.resolve_raw_pkh(&map).unwrap(), ms_no_raw);
+
+        let unresolved = ms.resolve_raw_pkh(&BTreeMap::new()).unwrap_err();
+        assert_eq!(unresolved.into_iter().collect::<Vec<_>>(), vec![hash160]);
     }
 
     #[test]
@@ -1862,6 +2340,50 @@ mod tests {
         Tapscript::parse_insane(&script.into_script()).unwrap_err();
     }
 
+    #[test]
+    fn hybrid_keys() {
+        use bitcoin::hex::DisplayHex;
+
+        use crate::AnalysisError;
+
+        let key = bitcoin::PublicKey::from_str(
+            "03a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd",
+        )
+        .unwrap();
+        let mut hybrid = key.inner.serialize_uncompressed();
+        hybrid[0] = 0x06 | (hybrid[64] & 1);
+        let script = bitcoin::script::Builder::new()
+            .push_slice(hybrid)
+            .push_opcode(bitcoin::opcodes::all::OP_CHECKSIG)
+            .into_script();
+
+        // Rejected by default, naming the key
+        match Miniscript::<bitcoin::PublicKey, Legacy>::parse(&script) {
+            Err(Error::AnalysisError(AnalysisError::HybridKey(k))) => {
+                assert_eq!(k, hybrid.to_lower_hex_string())
+            }
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // Read as the uncompressed key when allowed
+        let ext = ExtParams::sane().hybrid_keys();
+        let uncompressed = bitcoin::PublicKey::new_uncompressed(key.inner);
+        let ms = Miniscript::<bitcoin::PublicKey, BareCtx>::parse_with_ext(&script, &ext).unwrap();
+        assert_eq!(ms.to_string(), format!("pk({})", uncompressed));
+        assert_ne!(ms.encode(), script);
+        Miniscript::<bitcoin::PublicKey, Legacy>::parse_with_ext(&script, &ext).unwrap();
+        // Uncompressed keys are still not allowed in segwit
+        Miniscript::<bitcoin::PublicKey, Segwitv0>::parse_with_ext(&script, &ext).unwrap_err();
+
+        // The prefix must match the parity of the key
+        hybrid[0] ^= 1;
+        let script = bitcoin::script::Builder::new()
+            .push_slice(hybrid)
+            .push_opcode(bitcoin::opcodes::all::OP_CHECKSIG)
+            .into_script();
+        Miniscript::<bitcoin::PublicKey, Legacy>::parse_with_ext(&script, &ext).unwrap_err();
+    }
+
     #[test]
     fn test_context_global_consensus() {
         // Test from string tests
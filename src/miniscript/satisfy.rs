@@ -5,26 +5,50 @@
 //! Traits and implementations to support producing witnesses for Miniscript
 //! scriptpubkeys.
 //!
+//! ## Witness ordering
+//!
+//! Satisfaction is deterministic: the same script and the same satisfier data
+//! always produce the same witness. When several satisfactions have the same
+//! weight, the choice between them is made by the rules below. These rules
+//! are identified by [`SATISFACTION_ORDERING_VERSION`], and will not change
+//! without it being bumped, so that protocols which commit to witnesses or
+//! witness templates in advance can detect incompatible upgrades.
+//!
+//! * `or_b(X,Z)` satisfies `X` and dissatisfies `Z`.
+//! * `or_c(X,Z)`, `or_d(X,Z)` and `or_i(X,Z)` satisfy `Z`.
+//! * `andor(X,Y,Z)` dissatisfies `X` and satisfies `Z`.
+//! * `thresh` satisfies its earliest children.
+//! * `multi` uses signatures for its earliest keys, and `multi_a` for its last keys.
+//! * `tr` descriptors use the key spend path if possible. Otherwise they use the
+//!   last leaf, in depth-first order, among those with the smallest witness.
+//!
 
 use core::{cmp, fmt, mem};
 
-use bitcoin::hashes::hash160;
+use bitcoin::hashes::{hash160, ripemd160, sha256, Hash};
 use bitcoin::key::XOnlyPublicKey;
 use bitcoin::taproot::{ControlBlock, LeafVersion, TapLeafHash, TapNodeHash};
-use bitcoin::{absolute, relative, ScriptBuf, Sequence};
+use bitcoin::{absolute, relative, EcdsaSighashType, ScriptBuf, Sequence, TapSighashType};
 use sync::Arc;
 
 use super::context::SigType;
+use super::extension::Extension;
 use crate::plan::AssetProvider;
 use crate::prelude::*;
 use crate::util::witness_size;
 use crate::{
-    AbsLockTime, Miniscript, MiniscriptKey, RelLockTime, ScriptContext, Terminal, Threshold,
-    ToPublicKey,
+    hash256, AbsLockTime, Miniscript, MiniscriptKey, RelLockTime, ScriptContext, Terminal,
+    Threshold, ToPublicKey,
 };
 
 /// Type alias for 32 byte Preimage.
 pub type Preimage32 = [u8; 32];
+
+/// Version of the rules used to choose between satisfactions of equal weight.
+///
+/// See the [module documentation](self) for the rules themselves.
+pub const SATISFACTION_ORDERING_VERSION: u32 = 1;
+
 /// Trait describing a lookup table for signatures, hash preimages, etc.
 ///
 /// Every method has a default implementation that simply returns `None`
@@ -46,6 +70,48 @@ pub trait Satisfier<Pk: MiniscriptKey + ToPublicKey> {
         None
     }
 
+    /// Given a public key, look up an ECDSA signature with that key and the
+    /// given sighash type
+    ///
+    /// By default this is the signature of [`Satisfier::lookup_ecdsa_sig`],
+    /// if it has the sighash type. Satisfiers holding several signatures per
+    /// key should override it.
+    fn lookup_ecdsa_sig_with_sighash(
+        &self,
+        pk: &Pk,
+        sighash_type: EcdsaSighashType,
+    ) -> Option<bitcoin::ecdsa::Signature> {
+        self.lookup_ecdsa_sig(pk)
+            .filter(|sig| sig.sighash_type == sighash_type)
+    }
+
+    /// Lookup the tap key spend sig with the given sighash type
+    ///
+    /// By default this is the signature of
+    /// [`Satisfier::lookup_tap_key_spend_sig`], if it has the sighash type.
+    fn lookup_tap_key_spend_sig_with_sighash(
+        &self,
+        sighash_type: TapSighashType,
+    ) -> Option<bitcoin::taproot::Signature> {
+        self.lookup_tap_key_spend_sig()
+            .filter(|sig| sig.sighash_type == sighash_type)
+    }
+
+    /// Given a public key and a associated leaf hash, look up a schnorr
+    /// signature with that key and the given sighash type
+    ///
+    /// By default this is the signature of
+    /// [`Satisfier::lookup_tap_leaf_script_sig`], if it has the sighash type.
+    fn lookup_tap_leaf_script_sig_with_sighash(
+        &self,
+        pk: &Pk,
+        leaf_hash: &TapLeafHash,
+        sighash_type: TapSighashType,
+    ) -> Option<bitcoin::taproot::Signature> {
+        self.lookup_tap_leaf_script_sig(pk, leaf_hash)
+            .filter(|sig| sig.sighash_type == sighash_type)
+    }
+
     /// Obtain a reference to the control block for a ver and script
     fn lookup_tap_control_block_map(
         &self,
@@ -262,7 +328,180 @@ impl_satisfier_for_map_hash_tapleafhash_to_key_taproot_sig! {
     impl Satisfier<Pk> for HashMap<(hash160::Hash, TapLeafHash), (Pk, bitcoin::taproot::Signature)>
 }
 
-impl<Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for &S {
+macro_rules! impl_satisfier_for_map_hash_to_preimage {
+    ($(#[$($attr:meta)*])* impl Satisfier<Pk> for $map:ident<Hash, Preimage32>) => {
+        impl_satisfier_for_map_hash_to_preimage!(
+            $(#[$($attr)*])* $map, sha256::Hash, Sha256, lookup_sha256, to_sha256
+        );
+        impl_satisfier_for_map_hash_to_preimage!(
+            $(#[$($attr)*])* $map, hash256::Hash, Hash256, lookup_hash256, to_hash256
+        );
+        impl_satisfier_for_map_hash_to_preimage!(
+            $(#[$($attr)*])* $map, ripemd160::Hash, Ripemd160, lookup_ripemd160, to_ripemd160
+        );
+        impl_satisfier_for_map_hash_to_preimage!(
+            $(#[$($attr)*])* $map, hash160::Hash, Hash160, lookup_hash160, to_hash160
+        );
+    };
+    ($(#[$($attr:meta)*])* $map:ident, $hash:ty, $assoc:ident, $lookup:ident, $to_hash:ident) => {
+        $(#[$($attr)*])*
+        impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for $map<$hash, Preimage32> {
+            fn $lookup(&self, h: &Pk::$assoc) -> Option<Preimage32> {
+                self.get(&Pk::$to_hash(h)).copied()
+            }
+        }
+    };
+}
+
+impl_satisfier_for_map_hash_to_preimage! {
+    impl Satisfier<Pk> for BTreeMap<Hash, Preimage32>
+}
+
+impl_satisfier_for_map_hash_to_preimage! {
+    #[cfg(feature = "std")]
+    impl Satisfier<Pk> for HashMap<Hash, Preimage32>
+}
+
+/// Error returned when a hash preimage is not exactly 32 bytes long.
+///
+/// Miniscript hash fragments only accept 32-byte preimages, so any other
+/// preimage can never be used in a satisfaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreimageLengthError {
+    /// The length of the rejected preimage.
+    pub len: usize,
+}
+
+impl fmt::Display for PreimageLengthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "hash preimage has length {} (must be 32)", self.len)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PreimageLengthError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> { None }
+}
+
+/// A satisfier for the hash fragments of a Miniscript.
+///
+/// Preimages are stored indexed by their hash, which is computed on insertion,
+/// so that lookups never need to hash. A preimage may be registered for a
+/// single hash type, or for all four at once using [`Preimages::insert`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Preimages {
+    sha256: BTreeMap<sha256::Hash, Preimage32>,
+    hash256: BTreeMap<hash256::Hash, Preimage32>,
+    ripemd160: BTreeMap<ripemd160::Hash, Preimage32>,
+    hash160: BTreeMap<hash160::Hash, Preimage32>,
+}
+
+impl Preimages {
+    /// Constructs a new, empty, set of preimages.
+    pub fn new() -> Self { Self::default() }
+
+    /// Whether the set contains no preimages.
+    pub fn is_empty(&self) -> bool {
+        self.sha256.is_empty()
+            && self.hash256.is_empty()
+            && self.ripemd160.is_empty()
+            && self.hash160.is_empty()
+    }
+
+    /// Adds a preimage for all four hash types.
+    pub fn insert(&mut self, preimage: &[u8]) -> Result<(), PreimageLengthError> {
+        let preimage = preimage32(preimage)?;
+        self.insert_preimage32(preimage);
+        Ok(())
+    }
+
+    /// Adds a preimage, which is already known to be 32 bytes, for all four hash types.
+    pub fn insert_preimage32(&mut self, preimage: Preimage32) {
+        self.sha256.insert(sha256::Hash::hash(&preimage), preimage);
+        self.hash256
+            .insert(hash256::Hash::hash(&preimage), preimage);
+        self.ripemd160
+            .insert(ripemd160::Hash::hash(&preimage), preimage);
+        self.hash160
+            .insert(hash160::Hash::hash(&preimage), preimage);
+    }
+
+    /// Adds a SHA256 preimage, returning its hash.
+    pub fn insert_sha256(&mut self, preimage: &[u8]) -> Result<sha256::Hash, PreimageLengthError> {
+        let preimage = preimage32(preimage)?;
+        let hash = sha256::Hash::hash(&preimage);
+        self.sha256.insert(hash, preimage);
+        Ok(hash)
+    }
+
+    /// Adds a HASH256 preimage, returning its hash.
+    pub fn insert_hash256(
+        &mut self,
+        preimage: &[u8],
+    ) -> Result<hash256::Hash, PreimageLengthError> {
+        let preimage = preimage32(preimage)?;
+        let hash = hash256::Hash::hash(&preimage);
+        self.hash256.insert(hash, preimage);
+        Ok(hash)
+    }
+
+    /// Adds a RIPEMD160 preimage, returning its hash.
+    pub fn insert_ripemd160(
+        &mut self,
+        preimage: &[u8],
+    ) -> Result<ripemd160::Hash, PreimageLengthError> {
+        let preimage = preimage32(preimage)?;
+        let hash = ripemd160::Hash::hash(&preimage);
+        self.ripemd160.insert(hash, preimage);
+        Ok(hash)
+    }
+
+    /// Adds a HASH160 preimage, returning its hash.
+    pub fn insert_hash160(
+        &mut self,
+        preimage: &[u8],
+    ) -> Result<hash160::Hash, PreimageLengthError> {
+        let preimage = preimage32(preimage)?;
+        let hash = hash160::Hash::hash(&preimage);
+        self.hash160.insert(hash, preimage);
+        Ok(hash)
+    }
+}
+
+impl FromIterator<Preimage32> for Preimages {
+    fn from_iter<I: IntoIterator<Item = Preimage32>>(iter: I) -> Self {
+        let mut ret = Preimages::new();
+        for preimage in iter {
+            ret.insert_preimage32(preimage);
+        }
+        ret
+    }
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for Preimages {
+    fn lookup_sha256(&self, h: &Pk::Sha256) -> Option<Preimage32> {
+        self.sha256.get(&Pk::to_sha256(h)).copied()
+    }
+
+    fn lookup_hash256(&self, h: &Pk::Hash256) -> Option<Preimage32> {
+        self.hash256.get(&Pk::to_hash256(h)).copied()
+    }
+
+    fn lookup_ripemd160(&self, h: &Pk::Ripemd160) -> Option<Preimage32> {
+        self.ripemd160.get(&Pk::to_ripemd160(h)).copied()
+    }
+
+    fn lookup_hash160(&self, h: &Pk::Hash160) -> Option<Preimage32> {
+        self.hash160.get(&Pk::to_hash160(h)).copied()
+    }
+}
+
+/// Converts a preimage to a [`Preimage32`], checking its length.
+fn preimage32(preimage: &[u8]) -> Result<Preimage32, PreimageLengthError> {
+    Preimage32::try_from(preimage).map_err(|_| PreimageLengthError { len: preimage.len() })
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk> + ?Sized> Satisfier<Pk> for &S {
     fn lookup_ecdsa_sig(&self, p: &Pk) -> Option<bitcoin::ecdsa::Signature> {
         (**self).lookup_ecdsa_sig(p)
     }
@@ -275,6 +514,30 @@ impl<Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for &S {
         (**self).lookup_tap_leaf_script_sig(p, h)
     }
 
+    fn lookup_ecdsa_sig_with_sighash(
+        &self,
+        p: &Pk,
+        t: EcdsaSighashType,
+    ) -> Option<bitcoin::ecdsa::Signature> {
+        (**self).lookup_ecdsa_sig_with_sighash(p, t)
+    }
+
+    fn lookup_tap_key_spend_sig_with_sighash(
+        &self,
+        t: TapSighashType,
+    ) -> Option<bitcoin::taproot::Signature> {
+        (**self).lookup_tap_key_spend_sig_with_sighash(t)
+    }
+
+    fn lookup_tap_leaf_script_sig_with_sighash(
+        &self,
+        p: &Pk,
+        h: &TapLeafHash,
+        t: TapSighashType,
+    ) -> Option<bitcoin::taproot::Signature> {
+        (**self).lookup_tap_leaf_script_sig_with_sighash(p, h, t)
+    }
+
     fn lookup_raw_pkh_pk(&self, pkh: &hash160::Hash) -> Option<bitcoin::PublicKey> {
         (**self).lookup_raw_pkh_pk(pkh)
     }
@@ -322,7 +585,7 @@ impl<Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for &S {
     fn check_after(&self, n: absolute::LockTime) -> bool { (**self).check_after(n) }
 }
 
-impl<Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for &mut S {
+impl<Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk> + ?Sized> Satisfier<Pk> for &mut S {
     fn lookup_ecdsa_sig(&self, p: &Pk) -> Option<bitcoin::ecdsa::Signature> {
         (**self).lookup_ecdsa_sig(p)
     }
@@ -335,6 +598,30 @@ impl<Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for &mut S
         (**self).lookup_tap_leaf_script_sig(p, h)
     }
 
+    fn lookup_ecdsa_sig_with_sighash(
+        &self,
+        p: &Pk,
+        t: EcdsaSighashType,
+    ) -> Option<bitcoin::ecdsa::Signature> {
+        (**self).lookup_ecdsa_sig_with_sighash(p, t)
+    }
+
+    fn lookup_tap_key_spend_sig_with_sighash(
+        &self,
+        t: TapSighashType,
+    ) -> Option<bitcoin::taproot::Signature> {
+        (**self).lookup_tap_key_spend_sig_with_sighash(t)
+    }
+
+    fn lookup_tap_leaf_script_sig_with_sighash(
+        &self,
+        p: &Pk,
+        h: &TapLeafHash,
+        t: TapSighashType,
+    ) -> Option<bitcoin::taproot::Signature> {
+        (**self).lookup_tap_leaf_script_sig_with_sighash(p, h, t)
+    }
+
     fn lookup_tap_key_spend_sig(&self) -> Option<bitcoin::taproot::Signature> {
         (**self).lookup_tap_key_spend_sig()
     }
@@ -420,6 +707,48 @@ macro_rules! impl_tuple_satisfier {
                 None
             }
 
+            fn lookup_ecdsa_sig_with_sighash(
+                &self,
+                key: &Pk,
+                t: EcdsaSighashType,
+            ) -> Option<bitcoin::ecdsa::Signature> {
+                let &($(ref $ty,)*) = self;
+                $(
+                    if let Some(result) = $ty.lookup_ecdsa_sig_with_sighash(key, t) {
+                        return Some(result);
+                    }
+                )*
+                None
+            }
+
+            fn lookup_tap_key_spend_sig_with_sighash(
+                &self,
+                t: TapSighashType,
+            ) -> Option<bitcoin::taproot::Signature> {
+                let &($(ref $ty,)*) = self;
+                $(
+                    if let Some(result) = $ty.lookup_tap_key_spend_sig_with_sighash(t) {
+                        return Some(result);
+                    }
+                )*
+                None
+            }
+
+            fn lookup_tap_leaf_script_sig_with_sighash(
+                &self,
+                key: &Pk,
+                h: &TapLeafHash,
+                t: TapSighashType,
+            ) -> Option<bitcoin::taproot::Signature> {
+                let &($(ref $ty,)*) = self;
+                $(
+                    if let Some(result) = $ty.lookup_tap_leaf_script_sig_with_sighash(key, h, t) {
+                        return Some(result);
+                    }
+                )*
+                None
+            }
+
             fn lookup_raw_pkh_ecdsa_sig(
                 &self,
                 key_hash: &hash160::Hash,
@@ -471,89 +800,472 @@ macro_rules! impl_tuple_satisfier {
                 None
             }
 
-            fn lookup_tap_control_block_map(
-                &self,
-            ) -> Option<&BTreeMap<ControlBlock, (bitcoin::ScriptBuf, LeafVersion)>> {
-                let &($(ref $ty,)*) = self;
-                $(
-                    if let Some(result) = $ty.lookup_tap_control_block_map() {
-                        return Some(result);
-                    }
-                )*
-                None
-            }
+            fn lookup_tap_control_block_map(
+                &self,
+            ) -> Option<&BTreeMap<ControlBlock, (bitcoin::ScriptBuf, LeafVersion)>> {
+                let &($(ref $ty,)*) = self;
+                $(
+                    if let Some(result) = $ty.lookup_tap_control_block_map() {
+                        return Some(result);
+                    }
+                )*
+                None
+            }
+
+            fn lookup_sha256(&self, h: &Pk::Sha256) -> Option<Preimage32> {
+                let &($(ref $ty,)*) = self;
+                $(
+                    if let Some(result) = $ty.lookup_sha256(h) {
+                        return Some(result);
+                    }
+                )*
+                None
+            }
+
+            fn lookup_hash256(&self, h: &Pk::Hash256) -> Option<Preimage32> {
+                let &($(ref $ty,)*) = self;
+                $(
+                    if let Some(result) = $ty.lookup_hash256(h) {
+                        return Some(result);
+                    }
+                )*
+                None
+            }
+
+            fn lookup_ripemd160(&self, h: &Pk::Ripemd160) -> Option<Preimage32> {
+                let &($(ref $ty,)*) = self;
+                $(
+                    if let Some(result) = $ty.lookup_ripemd160(h) {
+                        return Some(result);
+                    }
+                )*
+                None
+            }
+
+            fn lookup_hash160(&self, h: &Pk::Hash160) -> Option<Preimage32> {
+                let &($(ref $ty,)*) = self;
+                $(
+                    if let Some(result) = $ty.lookup_hash160(h) {
+                        return Some(result);
+                    }
+                )*
+                None
+            }
+
+            fn check_older(&self, n: relative::LockTime) -> bool {
+                let &($(ref $ty,)*) = self;
+                $(
+                    if $ty.check_older(n) {
+                        return true;
+                    }
+                )*
+                false
+            }
+
+            fn check_after(&self, n: absolute::LockTime) -> bool {
+                let &($(ref $ty,)*) = self;
+                $(
+                    if $ty.check_after(n) {
+                        return true;
+                    }
+                )*
+                false
+            }
+        }
+    }
+}
+
+impl_tuple_satisfier!(A);
+impl_tuple_satisfier!(A, B);
+impl_tuple_satisfier!(A, B, C);
+impl_tuple_satisfier!(A, B, C, D);
+impl_tuple_satisfier!(A, B, C, D, E);
+impl_tuple_satisfier!(A, B, C, D, E, F);
+impl_tuple_satisfier!(A, B, C, D, E, F, G);
+impl_tuple_satisfier!(A, B, C, D, E, F, G, H);
+
+/// A satisfier which consults a list of satisfiers in order.
+///
+/// Every lookup returns the result of the first satisfier which has one, and
+/// timelock checks succeed if any satisfier accepts them. This is the dynamic
+/// equivalent of using a tuple of satisfiers; to combine satisfiers of different
+/// types, use a list of `&dyn Satisfier<Pk>`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct FirstMatch<S> {
+    satisfiers: Vec<S>,
+}
+
+impl<S> FirstMatch<S> {
+    /// Constructs a new satisfier which consults the given satisfiers in order.
+    pub fn new(satisfiers: Vec<S>) -> Self { FirstMatch { satisfiers } }
+
+    /// Constructs a new satisfier from a list of satisfiers with priorities.
+    ///
+    /// Satisfiers are consulted in order of decreasing priority. Satisfiers with
+    /// equal priority are consulted in the order they were given.
+    pub fn from_prioritized<I: IntoIterator<Item = (i32, S)>>(satisfiers: I) -> Self {
+        let mut satisfiers = satisfiers.into_iter().collect::<Vec<_>>();
+        satisfiers.sort_by_key(|&(priority, _)| cmp::Reverse(priority));
+        FirstMatch { satisfiers: satisfiers.into_iter().map(|(_, s)| s).collect() }
+    }
+
+    /// Adds a satisfier which is consulted after all existing ones.
+    pub fn push(&mut self, satisfier: S) { self.satisfiers.push(satisfier) }
+
+    /// Accessor for the satisfiers, in the order they are consulted.
+    pub fn satisfiers(&self) -> &[S] { &self.satisfiers }
+
+    /// Returns the satisfiers, in the order they are consulted.
+    pub fn into_satisfiers(self) -> Vec<S> { self.satisfiers }
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for FirstMatch<S> {
+    fn lookup_ecdsa_sig(&self, p: &Pk) -> Option<bitcoin::ecdsa::Signature> {
+        self.satisfiers.iter().find_map(|s| s.lookup_ecdsa_sig(p))
+    }
+
+    fn lookup_tap_key_spend_sig(&self) -> Option<bitcoin::taproot::Signature> {
+        self.satisfiers
+            .iter()
+            .find_map(|s| s.lookup_tap_key_spend_sig())
+    }
+
+    fn lookup_tap_leaf_script_sig(
+        &self,
+        p: &Pk,
+        h: &TapLeafHash,
+    ) -> Option<bitcoin::taproot::Signature> {
+        self.satisfiers
+            .iter()
+            .find_map(|s| s.lookup_tap_leaf_script_sig(p, h))
+    }
+
+    fn lookup_ecdsa_sig_with_sighash(
+        &self,
+        p: &Pk,
+        t: EcdsaSighashType,
+    ) -> Option<bitcoin::ecdsa::Signature> {
+        self.satisfiers
+            .iter()
+            .find_map(|s| s.lookup_ecdsa_sig_with_sighash(p, t))
+    }
+
+    fn lookup_tap_key_spend_sig_with_sighash(
+        &self,
+        t: TapSighashType,
+    ) -> Option<bitcoin::taproot::Signature> {
+        self.satisfiers
+            .iter()
+            .find_map(|s| s.lookup_tap_key_spend_sig_with_sighash(t))
+    }
+
+    fn lookup_tap_leaf_script_sig_with_sighash(
+        &self,
+        p: &Pk,
+        h: &TapLeafHash,
+        t: TapSighashType,
+    ) -> Option<bitcoin::taproot::Signature> {
+        self.satisfiers
+            .iter()
+            .find_map(|s| s.lookup_tap_leaf_script_sig_with_sighash(p, h, t))
+    }
+
+    fn lookup_tap_control_block_map(
+        &self,
+    ) -> Option<&BTreeMap<ControlBlock, (bitcoin::ScriptBuf, LeafVersion)>> {
+        self.satisfiers
+            .iter()
+            .find_map(|s| s.lookup_tap_control_block_map())
+    }
+
+    fn lookup_raw_pkh_pk(&self, pkh: &hash160::Hash) -> Option<bitcoin::PublicKey> {
+        self.satisfiers
+            .iter()
+            .find_map(|s| s.lookup_raw_pkh_pk(pkh))
+    }
+
+    fn lookup_raw_pkh_x_only_pk(&self, pkh: &hash160::Hash) -> Option<XOnlyPublicKey> {
+        self.satisfiers
+            .iter()
+            .find_map(|s| s.lookup_raw_pkh_x_only_pk(pkh))
+    }
+
+    fn lookup_raw_pkh_ecdsa_sig(
+        &self,
+        pkh: &hash160::Hash,
+    ) -> Option<(bitcoin::PublicKey, bitcoin::ecdsa::Signature)> {
+        self.satisfiers
+            .iter()
+            .find_map(|s| s.lookup_raw_pkh_ecdsa_sig(pkh))
+    }
+
+    fn lookup_raw_pkh_tap_leaf_script_sig(
+        &self,
+        pkh: &(hash160::Hash, TapLeafHash),
+    ) -> Option<(XOnlyPublicKey, bitcoin::taproot::Signature)> {
+        self.satisfiers
+            .iter()
+            .find_map(|s| s.lookup_raw_pkh_tap_leaf_script_sig(pkh))
+    }
+
+    fn lookup_sha256(&self, h: &Pk::Sha256) -> Option<Preimage32> {
+        self.satisfiers.iter().find_map(|s| s.lookup_sha256(h))
+    }
+
+    fn lookup_hash256(&self, h: &Pk::Hash256) -> Option<Preimage32> {
+        self.satisfiers.iter().find_map(|s| s.lookup_hash256(h))
+    }
+
+    fn lookup_ripemd160(&self, h: &Pk::Ripemd160) -> Option<Preimage32> {
+        self.satisfiers.iter().find_map(|s| s.lookup_ripemd160(h))
+    }
+
+    fn lookup_hash160(&self, h: &Pk::Hash160) -> Option<Preimage32> {
+        self.satisfiers.iter().find_map(|s| s.lookup_hash160(h))
+    }
+
+    fn check_older(&self, t: relative::LockTime) -> bool {
+        self.satisfiers.iter().any(|s| s.check_older(t))
+    }
+
+    fn check_after(&self, t: absolute::LockTime) -> bool {
+        self.satisfiers.iter().any(|s| s.check_after(t))
+    }
+}
+
+/// A satisfier which combines the data of a list of satisfiers.
+///
+/// This behaves like [`FirstMatch`], except that the taproot control block maps
+/// of all the satisfiers are merged, rather than only the first one being used.
+/// Since [`Satisfier::lookup_tap_control_block_map`] returns a reference, the
+/// merged map is computed once, when the union is constructed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Union<S> {
+    inner: FirstMatch<S>,
+    control_blocks: Option<BTreeMap<ControlBlock, (bitcoin::ScriptBuf, LeafVersion)>>,
+}
+
+impl<S> Union<S> {
+    /// Constructs the union of the given satisfiers.
+    ///
+    /// Where satisfiers have conflicting data, the earliest satisfier in the list wins.
+    pub fn new<Pk>(satisfiers: Vec<S>) -> Self
+    where
+        Pk: MiniscriptKey + ToPublicKey,
+        S: Satisfier<Pk>,
+    {
+        let mut control_blocks: Option<BTreeMap<_, _>> = None;
+        for map in satisfiers
+            .iter()
+            .rev()
+            .filter_map(|s| s.lookup_tap_control_block_map())
+        {
+            control_blocks
+                .get_or_insert_with(BTreeMap::new)
+                .extend(map.iter().map(|(cb, v)| (cb.clone(), v.clone())));
+        }
+        Union { inner: FirstMatch::new(satisfiers), control_blocks }
+    }
+
+    /// Accessor for the satisfiers, in the order they are consulted.
+    pub fn satisfiers(&self) -> &[S] { self.inner.satisfiers() }
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for Union<S> {
+    fn lookup_ecdsa_sig(&self, p: &Pk) -> Option<bitcoin::ecdsa::Signature> {
+        self.inner.lookup_ecdsa_sig(p)
+    }
+
+    fn lookup_tap_key_spend_sig(&self) -> Option<bitcoin::taproot::Signature> {
+        self.inner.lookup_tap_key_spend_sig()
+    }
+
+    fn lookup_tap_leaf_script_sig(
+        &self,
+        p: &Pk,
+        h: &TapLeafHash,
+    ) -> Option<bitcoin::taproot::Signature> {
+        self.inner.lookup_tap_leaf_script_sig(p, h)
+    }
+
+    fn lookup_ecdsa_sig_with_sighash(
+        &self,
+        p: &Pk,
+        t: EcdsaSighashType,
+    ) -> Option<bitcoin::ecdsa::Signature> {
+        self.inner.lookup_ecdsa_sig_with_sighash(p, t)
+    }
+
+    fn lookup_tap_key_spend_sig_with_sighash(
+        &self,
+        t: TapSighashType,
+    ) -> Option<bitcoin::taproot::Signature> {
+        self.inner.lookup_tap_key_spend_sig_with_sighash(t)
+    }
+
+    fn lookup_tap_leaf_script_sig_with_sighash(
+        &self,
+        p: &Pk,
+        h: &TapLeafHash,
+        t: TapSighashType,
+    ) -> Option<bitcoin::taproot::Signature> {
+        self.inner.lookup_tap_leaf_script_sig_with_sighash(p, h, t)
+    }
+
+    fn lookup_tap_control_block_map(
+        &self,
+    ) -> Option<&BTreeMap<ControlBlock, (bitcoin::ScriptBuf, LeafVersion)>> {
+        self.control_blocks.as_ref()
+    }
+
+    fn lookup_raw_pkh_pk(&self, pkh: &hash160::Hash) -> Option<bitcoin::PublicKey> {
+        self.inner.lookup_raw_pkh_pk(pkh)
+    }
+
+    fn lookup_raw_pkh_x_only_pk(&self, pkh: &hash160::Hash) -> Option<XOnlyPublicKey> {
+        self.inner.lookup_raw_pkh_x_only_pk(pkh)
+    }
+
+    fn lookup_raw_pkh_ecdsa_sig(
+        &self,
+        pkh: &hash160::Hash,
+    ) -> Option<(bitcoin::PublicKey, bitcoin::ecdsa::Signature)> {
+        self.inner.lookup_raw_pkh_ecdsa_sig(pkh)
+    }
+
+    fn lookup_raw_pkh_tap_leaf_script_sig(
+        &self,
+        pkh: &(hash160::Hash, TapLeafHash),
+    ) -> Option<(XOnlyPublicKey, bitcoin::taproot::Signature)> {
+        self.inner.lookup_raw_pkh_tap_leaf_script_sig(pkh)
+    }
+
+    fn lookup_sha256(&self, h: &Pk::Sha256) -> Option<Preimage32> { self.inner.lookup_sha256(h) }
+
+    fn lookup_hash256(&self, h: &Pk::Hash256) -> Option<Preimage32> { self.inner.lookup_hash256(h) }
+
+    fn lookup_ripemd160(&self, h: &Pk::Ripemd160) -> Option<Preimage32> {
+        self.inner.lookup_ripemd160(h)
+    }
+
+    fn lookup_hash160(&self, h: &Pk::Hash160) -> Option<Preimage32> { self.inner.lookup_hash160(h) }
+
+    fn check_older(&self, t: relative::LockTime) -> bool {
+        Satisfier::<Pk>::check_older(&self.inner, t)
+    }
+
+    fn check_after(&self, t: absolute::LockTime) -> bool {
+        Satisfier::<Pk>::check_after(&self.inner, t)
+    }
+}
+
+/// A satisfier which only provides signatures with the given sighash types.
+///
+/// Signature lookups go through the `_with_sighash` methods of the wrapped
+/// satisfier, for instance to sign with `SIGHASH_SINGLE|ANYONECANPAY` in
+/// protocols which let others add inputs and outputs. Since planning with a
+/// satisfier uses the sizes of its signatures, plans made through this
+/// wrapper account for the sighash types: taproot signatures with a
+/// non-default sighash type are a byte longer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WithSighash<S> {
+    inner: S,
+    ecdsa: EcdsaSighashType,
+    tap: TapSighashType,
+}
+
+impl<S> WithSighash<S> {
+    /// Constructs a satisfier providing the signatures of `inner` with the
+    /// sighash types `SIGHASH_ALL` for ECDSA and `SIGHASH_DEFAULT` for
+    /// taproot.
+    pub fn new(inner: S) -> Self {
+        WithSighash { inner, ecdsa: EcdsaSighashType::All, tap: TapSighashType::Default }
+    }
+
+    /// Sets the sighash type of ECDSA signatures.
+    pub fn ecdsa(self, sighash_type: EcdsaSighashType) -> Self {
+        WithSighash { ecdsa: sighash_type, ..self }
+    }
+
+    /// Sets the sighash type of schnorr signatures.
+    pub fn tap(self, sighash_type: TapSighashType) -> Self {
+        WithSighash { tap: sighash_type, ..self }
+    }
+
+    /// Accessor for the wrapped satisfier.
+    pub fn inner(&self) -> &S { &self.inner }
+
+    /// Returns the wrapped satisfier.
+    pub fn into_inner(self) -> S { self.inner }
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for WithSighash<S> {
+    fn lookup_ecdsa_sig(&self, p: &Pk) -> Option<bitcoin::ecdsa::Signature> {
+        self.inner.lookup_ecdsa_sig_with_sighash(p, self.ecdsa)
+    }
+
+    fn lookup_tap_key_spend_sig(&self) -> Option<bitcoin::taproot::Signature> {
+        self.inner.lookup_tap_key_spend_sig_with_sighash(self.tap)
+    }
+
+    fn lookup_tap_leaf_script_sig(
+        &self,
+        p: &Pk,
+        h: &TapLeafHash,
+    ) -> Option<bitcoin::taproot::Signature> {
+        self.inner
+            .lookup_tap_leaf_script_sig_with_sighash(p, h, self.tap)
+    }
+
+    fn lookup_tap_control_block_map(
+        &self,
+    ) -> Option<&BTreeMap<ControlBlock, (bitcoin::ScriptBuf, LeafVersion)>> {
+        self.inner.lookup_tap_control_block_map()
+    }
+
+    fn lookup_raw_pkh_pk(&self, pkh: &hash160::Hash) -> Option<bitcoin::PublicKey> {
+        self.inner.lookup_raw_pkh_pk(pkh)
+    }
 
-            fn lookup_sha256(&self, h: &Pk::Sha256) -> Option<Preimage32> {
-                let &($(ref $ty,)*) = self;
-                $(
-                    if let Some(result) = $ty.lookup_sha256(h) {
-                        return Some(result);
-                    }
-                )*
-                None
-            }
+    fn lookup_raw_pkh_x_only_pk(&self, pkh: &hash160::Hash) -> Option<XOnlyPublicKey> {
+        self.inner.lookup_raw_pkh_x_only_pk(pkh)
+    }
 
-            fn lookup_hash256(&self, h: &Pk::Hash256) -> Option<Preimage32> {
-                let &($(ref $ty,)*) = self;
-                $(
-                    if let Some(result) = $ty.lookup_hash256(h) {
-                        return Some(result);
-                    }
-                )*
-                None
-            }
+    // Raw pkh lookups have no sighash-aware variant: keep the signature only
+    // if it has the right type, so that the key is still known for
+    // dissatisfactions
+    fn lookup_raw_pkh_ecdsa_sig(
+        &self,
+        pkh: &hash160::Hash,
+    ) -> Option<(bitcoin::PublicKey, bitcoin::ecdsa::Signature)> {
+        self.inner
+            .lookup_raw_pkh_ecdsa_sig(pkh)
+            .filter(|(_, sig)| sig.sighash_type == self.ecdsa)
+    }
 
-            fn lookup_ripemd160(&self, h: &Pk::Ripemd160) -> Option<Preimage32> {
-                let &($(ref $ty,)*) = self;
-                $(
-                    if let Some(result) = $ty.lookup_ripemd160(h) {
-                        return Some(result);
-                    }
-                )*
-                None
-            }
+    fn lookup_raw_pkh_tap_leaf_script_sig(
+        &self,
+        pkh: &(hash160::Hash, TapLeafHash),
+    ) -> Option<(XOnlyPublicKey, bitcoin::taproot::Signature)> {
+        self.inner
+            .lookup_raw_pkh_tap_leaf_script_sig(pkh)
+            .filter(|(_, sig)| sig.sighash_type == self.tap)
+    }
 
-            fn lookup_hash160(&self, h: &Pk::Hash160) -> Option<Preimage32> {
-                let &($(ref $ty,)*) = self;
-                $(
-                    if let Some(result) = $ty.lookup_hash160(h) {
-                        return Some(result);
-                    }
-                )*
-                None
-            }
+    fn lookup_sha256(&self, h: &Pk::Sha256) -> Option<Preimage32> { self.inner.lookup_sha256(h) }
 
-            fn check_older(&self, n: relative::LockTime) -> bool {
-                let &($(ref $ty,)*) = self;
-                $(
-                    if $ty.check_older(n) {
-                        return true;
-                    }
-                )*
-                false
-            }
+    fn lookup_hash256(&self, h: &Pk::Hash256) -> Option<Preimage32> { self.inner.lookup_hash256(h) }
 
-            fn check_after(&self, n: absolute::LockTime) -> bool {
-                let &($(ref $ty,)*) = self;
-                $(
-                    if $ty.check_after(n) {
-                        return true;
-                    }
-                )*
-                false
-            }
-        }
+    fn lookup_ripemd160(&self, h: &Pk::Ripemd160) -> Option<Preimage32> {
+        self.inner.lookup_ripemd160(h)
     }
-}
 
-impl_tuple_satisfier!(A);
-impl_tuple_satisfier!(A, B);
-impl_tuple_satisfier!(A, B, C);
-impl_tuple_satisfier!(A, B, C, D);
-impl_tuple_satisfier!(A, B, C, D, E);
-impl_tuple_satisfier!(A, B, C, D, E, F);
-impl_tuple_satisfier!(A, B, C, D, E, F, G);
-impl_tuple_satisfier!(A, B, C, D, E, F, G, H);
+    fn lookup_hash160(&self, h: &Pk::Hash160) -> Option<Preimage32> { self.inner.lookup_hash160(h) }
+
+    fn check_older(&self, t: relative::LockTime) -> bool { self.inner.check_older(t) }
+
+    fn check_after(&self, t: absolute::LockTime) -> bool { self.inner.check_after(t) }
+}
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 /// Type of schnorr signature to produce
@@ -595,6 +1307,14 @@ pub enum Placeholder<Pk: MiniscriptKey> {
     Ripemd160Preimage(Pk::Ripemd160),
     /// HASH160 preimage
     Hash160Preimage(Pk::Hash160),
+    /// SHA-256 preimage which isn't known yet, but can be obtained on demand
+    PendingSha256Preimage(Pk::Sha256),
+    /// HASH256 preimage which isn't known yet, but can be obtained on demand
+    PendingHash256Preimage(Pk::Hash256),
+    /// RIPEMD160 preimage which isn't known yet, but can be obtained on demand
+    PendingRipemd160Preimage(Pk::Ripemd160),
+    /// HASH160 preimage which isn't known yet, but can be obtained on demand
+    PendingHash160Preimage(Pk::Hash160),
     /// Hash dissatisfaction (32 bytes of 0x00)
     HashDissatisfaction,
     /// OP_1
@@ -629,6 +1349,12 @@ impl<Pk: MiniscriptKey> fmt::Display for Placeholder<Pk> {
             Hash256Preimage(hash) => write!(f, "Hash256Preimage(hash: {})", hash),
             Ripemd160Preimage(hash) => write!(f, "Ripemd160Preimage(hash: {})", hash),
             Hash160Preimage(hash) => write!(f, "Hash160Preimage(hash: {})", hash),
+            PendingSha256Preimage(hash) => write!(f, "PendingSha256Preimage(hash: {})", hash),
+            PendingHash256Preimage(hash) => write!(f, "PendingHash256Preimage(hash: {})", hash),
+            PendingRipemd160Preimage(hash) => {
+                write!(f, "PendingRipemd160Preimage(hash: {})", hash)
+            }
+            PendingHash160Preimage(hash) => write!(f, "PendingHash160Preimage(hash: {})", hash),
             HashDissatisfaction => write!(f, "HashDissatisfaction"),
             PushOne => write!(f, "PushOne"),
             PushZero => write!(f, "PushZero"),
@@ -663,10 +1389,18 @@ impl<Pk: MiniscriptKey + ToPublicKey> Placeholder<Pk> {
                     debug_assert!(1 + pk.len() == *size);
                     pk
                 }),
-            Placeholder::Hash256Preimage(h) => sat.lookup_hash256(h).map(|p| p.to_vec()),
-            Placeholder::Sha256Preimage(h) => sat.lookup_sha256(h).map(|p| p.to_vec()),
-            Placeholder::Hash160Preimage(h) => sat.lookup_hash160(h).map(|p| p.to_vec()),
-            Placeholder::Ripemd160Preimage(h) => sat.lookup_ripemd160(h).map(|p| p.to_vec()),
+            Placeholder::Hash256Preimage(h) | Placeholder::PendingHash256Preimage(h) => {
+                sat.lookup_hash256(h).map(|p| p.to_vec())
+            }
+            Placeholder::Sha256Preimage(h) | Placeholder::PendingSha256Preimage(h) => {
+                sat.lookup_sha256(h).map(|p| p.to_vec())
+            }
+            Placeholder::Hash160Preimage(h) | Placeholder::PendingHash160Preimage(h) => {
+                sat.lookup_hash160(h).map(|p| p.to_vec())
+            }
+            Placeholder::Ripemd160Preimage(h) | Placeholder::PendingRipemd160Preimage(h) => {
+                sat.lookup_ripemd160(h).map(|p| p.to_vec())
+            }
             Placeholder::EcdsaSigPk(pk) => sat.lookup_ecdsa_sig(pk).map(|s| s.to_vec()),
             Placeholder::EcdsaSigPkHash(pkh) => {
                 sat.lookup_raw_pkh_ecdsa_sig(pkh).map(|(_, s)| s.to_vec())
@@ -814,6 +1548,8 @@ impl<Pk: MiniscriptKey + ToPublicKey> Witness<Placeholder<Pk>> {
     fn ripemd160_preimage<S: AssetProvider<Pk>>(sat: &S, h: &Pk::Ripemd160) -> Self {
         if sat.provider_lookup_ripemd160(h) {
             Witness::Stack(vec![Placeholder::Ripemd160Preimage(h.clone())])
+        } else if sat.provider_lookup_pending_ripemd160(h) {
+            Witness::Stack(vec![Placeholder::PendingRipemd160Preimage(h.clone())])
         // Note hash preimages are unavailable instead of impossible
         } else {
             Witness::Unavailable
@@ -824,6 +1560,8 @@ impl<Pk: MiniscriptKey + ToPublicKey> Witness<Placeholder<Pk>> {
     fn hash160_preimage<S: AssetProvider<Pk>>(sat: &S, h: &Pk::Hash160) -> Self {
         if sat.provider_lookup_hash160(h) {
             Witness::Stack(vec![Placeholder::Hash160Preimage(h.clone())])
+        } else if sat.provider_lookup_pending_hash160(h) {
+            Witness::Stack(vec![Placeholder::PendingHash160Preimage(h.clone())])
         // Note hash preimages are unavailable instead of impossible
         } else {
             Witness::Unavailable
@@ -834,6 +1572,8 @@ impl<Pk: MiniscriptKey + ToPublicKey> Witness<Placeholder<Pk>> {
     fn sha256_preimage<S: AssetProvider<Pk>>(sat: &S, h: &Pk::Sha256) -> Self {
         if sat.provider_lookup_sha256(h) {
             Witness::Stack(vec![Placeholder::Sha256Preimage(h.clone())])
+        } else if sat.provider_lookup_pending_sha256(h) {
+            Witness::Stack(vec![Placeholder::PendingSha256Preimage(h.clone())])
         // Note hash preimages are unavailable instead of impossible
         } else {
             Witness::Unavailable
@@ -844,6 +1584,8 @@ impl<Pk: MiniscriptKey + ToPublicKey> Witness<Placeholder<Pk>> {
     fn hash256_preimage<S: AssetProvider<Pk>>(sat: &S, h: &Pk::Hash256) -> Self {
         if sat.provider_lookup_hash256(h) {
             Witness::Stack(vec![Placeholder::Hash256Preimage(h.clone())])
+        } else if sat.provider_lookup_pending_hash256(h) {
+            Witness::Stack(vec![Placeholder::PendingHash256Preimage(h.clone())])
         // Note hash preimages are unavailable instead of impossible
         } else {
             Witness::Unavailable
@@ -920,14 +1662,15 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
         }
     }
 
-    pub(crate) fn build_template<P, Ctx>(
-        term: &Terminal<Pk, Ctx>,
+    pub(crate) fn build_template<P, Ctx, Ext>(
+        term: &Terminal<Pk, Ctx, Ext>,
         provider: &P,
         root_has_sig: bool,
         leaf_hash: &TapLeafHash,
     ) -> Self
     where
         Ctx: ScriptContext,
+        Ext: Extension,
         P: AssetProvider<Pk>,
     {
         Self::satisfy_helper(
@@ -940,14 +1683,15 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
         )
     }
 
-    pub(crate) fn build_template_mall<P, Ctx>(
-        term: &Terminal<Pk, Ctx>,
+    pub(crate) fn build_template_mall<P, Ctx, Ext>(
+        term: &Terminal<Pk, Ctx, Ext>,
         provider: &P,
         root_has_sig: bool,
         leaf_hash: &TapLeafHash,
     ) -> Self
     where
         Ctx: ScriptContext,
+        Ext: Extension,
         P: AssetProvider<Pk>,
     {
         Self::satisfy_helper(
@@ -961,8 +1705,8 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
     }
 
     // produce a non-malleable satisafaction for thesh frag
-    fn thresh<Ctx, Sat, F>(
-        thresh: &Threshold<Arc<Miniscript<Pk, Ctx>>, 0>,
+    fn thresh<Ctx, Ext, Sat, F>(
+        thresh: &Threshold<Arc<Miniscript<Pk, Ctx, Ext>>, 0>,
         stfr: &Sat,
         root_has_sig: bool,
         leaf_hash: &TapLeafHash,
@@ -970,6 +1714,7 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
     ) -> Self
     where
         Ctx: ScriptContext,
+        Ext: Extension,
         Sat: AssetProvider<Pk>,
         F: FnMut(
             Satisfaction<Placeholder<Pk>>,
@@ -1004,83 +1749,166 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
             })
             .collect::<Vec<_>>();
 
-        // Sort everything by (sat cost - dissat cost), except that
-        // satisfactions without signatures beat satisfactions with
-        // signatures
-        let mut sat_indices = (0..thresh.n()).collect::<Vec<_>>();
-        sat_indices.sort_by_key(|&i| {
-            let stack_weight = match (&sats[i].stack, &ret_stack[i].stack) {
-                (&Witness::Unavailable, _) | (&Witness::Impossible, _) => i64::MAX,
-                // This can only be the case when we have PkH without the corresponding
-                // Pubkey.
-                (_, &Witness::Unavailable) | (_, &Witness::Impossible) => i64::MIN,
-                (Witness::Stack(s), Witness::Stack(d)) => {
-                    witness_size(s) as i64 - witness_size(d) as i64
-                }
-            };
-            let is_impossible = sats[i].stack == Witness::Impossible;
-            // First consider the candidates that are not impossible to satisfy
-            // by any party. Among those first consider the ones that have no sig
-            // because third party can malleate them if they are not chosen.
-            // Lastly, choose by weight.
-            (is_impossible, sats[i].has_sig, stack_weight)
-        });
-
-        for i in 0..thresh.k() {
-            mem::swap(&mut ret_stack[sat_indices[i]], &mut sats[sat_indices[i]]);
-        }
-
-        // We preferably take satisfactions that are not impossible
-        // If we cannot find `k` satisfactions that are not impossible
-        // then the threshold branch is impossible to satisfy
-        // For example, the fragment thresh(2, hash, 0, 0, 0)
-        // is has an impossible witness
-        if sats[sat_indices[thresh.k() - 1]].stack == Witness::Impossible {
-            Satisfaction {
-                stack: Witness::Impossible,
-                // If the witness is impossible, we don't care about the
-                // has_sig flag, nor about the timelocks
+        // Satisfactions without signatures must all be used, since otherwise a
+        // third party could malleate the witness by swapping one of them in.
+        // For example, the fragment thresh(2, hash, hash, 0, 0) is uniquely
+        // satisfiable because there is no satisfaction for the 0 fragment,
+        // but thresh(1, hash, hash) is not.
+        let n_forced = sats
+            .iter()
+            .filter(|sat| !sat.has_sig && sat.stack != Witness::Impossible)
+            .count();
+        if n_forced > thresh.k() {
+            return Satisfaction {
+                stack: Witness::Unavailable,
                 has_sig: false,
                 relative_timelock: None,
                 absolute_timelock: None,
-            }
+            };
         }
-        // We are now guaranteed that all elements in `k` satisfactions
-        // are not impossible(we sort by is_impossible bool).
-        // The above loop should have taken everything without a sig
-        // (since those were sorted higher than non-sigs). If there
-        // are remaining non-sig satisfactions this indicates a
-        // malleability vector
-        // For example, the fragment thresh(2, hash, hash, 0, 0)
-        // is uniquely satisfyiable because there is no satisfaction
-        // for the 0 fragment
-        else if !sats[sat_indices[thresh.k()]].has_sig
-            && sats[sat_indices[thresh.k()]].stack != Witness::Impossible
-        {
-            // All arguments should be `d`, so dissatisfactions have no
-            // signatures; and in this branch we assume too many weak
-            // arguments, so none of the satisfactions should have
-            // signatures either.
-            for sat in &ret_stack {
-                assert!(!sat.has_sig);
-            }
-            Satisfaction {
-                stack: Witness::Unavailable,
+
+        match Self::thresh_selection(&sats, &ret_stack, thresh.k()) {
+            Ok(selection) => {
+                for i in selection {
+                    mem::swap(&mut ret_stack[i], &mut sats[i]);
+                }
+                ret_stack
+                    .into_iter()
+                    .fold(Satisfaction::empty(), Satisfaction::concatenate_rev)
+            }
+            // If we cannot find `k` satisfactions that are not impossible, and
+            // which can be combined, then the threshold is impossible to satisfy.
+            // For example, the fragment thresh(2, hash, 0, 0, 0) has an impossible
+            // witness. If a satisfaction without signature is left over, the
+            // witness is unavailable, as it would be malleable.
+            Err(stack) => Satisfaction {
+                stack,
+                // If there is no witness, we don't care about the has_sig flag,
+                // nor about the timelocks
                 has_sig: false,
                 relative_timelock: None,
                 absolute_timelock: None,
+            },
+        }
+    }
+
+    /// Selects which `k` children of a non-malleable `thresh` to satisfy, returning their
+    /// indices.
+    ///
+    /// Since a transaction has a single locktime and sequence per input, satisfactions
+    /// and dissatisfactions using height-based and time-based locks of the same kind
+    /// cannot be combined. For each combination of lock kinds, the cheapest selection
+    /// whose satisfactions and dissatisfactions can all be used is found exactly, by
+    /// dynamic programming over the children and the number of them satisfied, and the
+    /// cheapest selection overall is kept. On ties, satisfying earlier children wins.
+    ///
+    /// If there is no selection, returns [`Witness::Unavailable`] if one would leave
+    /// over a satisfaction without signature, as the witness would be malleable, and
+    /// [`Witness::Impossible`] otherwise.
+    fn thresh_selection(
+        sats: &[Self],
+        dissats: &[Self],
+        k: usize,
+    ) -> Result<Vec<usize>, Witness<Placeholder<Pk>>> {
+        // The cost of a selection: the number of satisfactions without signature it
+        // leaves over, the number of unavailable stacks and the witness size. Any
+        // leftover satisfaction makes the selection malleable, and any unavailable
+        // stack makes it worse than every available one.
+        type Cost = (usize, usize, usize);
+
+        fn cost<Pk: MiniscriptKey>(stack: &Witness<Placeholder<Pk>>, leftover: bool) -> Cost {
+            let leftover = usize::from(leftover);
+            match stack {
+                Witness::Stack(s) => (leftover, 0, witness_size(s)),
+                Witness::Unavailable | Witness::Impossible => (leftover, 1, 0),
             }
+        }
+
+        fn add(a: Cost, b: Cost) -> Cost { (a.0 + b.0, a.1 + b.1, a.2 + b.2) }
+
+        let uses_lock = |f: fn(&Self) -> bool| sats.iter().chain(dissats).any(f);
+        let abs_kinds: &[bool] = if uses_lock(|s| s.absolute_timelock.is_some()) {
+            &[true, false]
+        } else {
+            &[true]
+        };
+        let rel_kinds: &[bool] = if uses_lock(|s| s.relative_timelock.is_some()) {
+            &[true, false]
         } else {
-            // Otherwise flatten everything out
-            ret_stack
-                .into_iter()
-                .fold(Satisfaction::empty(), Satisfaction::concatenate_rev)
+            &[true]
+        };
+
+        let n = sats.len();
+        let mut best: Option<(Cost, Vec<usize>)> = None;
+        for &abs_height in abs_kinds {
+            for &rel_height in rel_kinds {
+                let usable = |sat: &Self| {
+                    sat.stack != Witness::Impossible
+                        && sat
+                            .absolute_timelock
+                            .map_or(true, |t| t.is_block_height() == abs_height)
+                        && sat
+                            .relative_timelock
+                            .map_or(true, |t| t.is_height_locked() == rel_height)
+                };
+
+                // `costs[j]` is the cost of the cheapest selection of `j` of the
+                // children from `i` onwards, and `take[i][j]` whether it satisfies
+                // child `i`. Children are visited last to first so that, on ties,
+                // the earliest children are the ones satisfied.
+                let mut costs: Vec<Option<Cost>> = vec![None; k + 1];
+                costs[0] = Some((0, 0, 0));
+                let mut take = vec![vec![false; k + 1]; n];
+                for i in (0..n).rev() {
+                    let leftover = !sats[i].has_sig && sats[i].stack != Witness::Impossible;
+                    for j in (0..=k).rev() {
+                        let skip = costs[j]
+                            .filter(|_| usable(&dissats[i]))
+                            .map(|c| add(c, cost(&dissats[i].stack, leftover)));
+                        let sat = match j.checked_sub(1) {
+                            Some(prev) if usable(&sats[i]) => {
+                                costs[prev].map(|c| add(c, cost(&sats[i].stack, false)))
+                            }
+                            _ => None,
+                        };
+                        take[i][j] = match (sat, skip) {
+                            (Some(sat), Some(skip)) => sat <= skip,
+                            (sat, _) => sat.is_some(),
+                        };
+                        costs[j] = if take[i][j] { sat } else { skip };
+                    }
+                }
+
+                let total = match costs[k] {
+                    Some(total) => total,
+                    None => continue,
+                };
+                if best
+                    .as_ref()
+                    .map_or(true, |(best_total, _)| total < *best_total)
+                {
+                    let mut selected = Vec::with_capacity(k);
+                    let mut j = k;
+                    for (i, take) in take.iter().enumerate() {
+                        if take[j] {
+                            selected.push(i);
+                            j -= 1;
+                        }
+                    }
+                    best = Some((total, selected));
+                }
+            }
+        }
+        match best {
+            Some(((0, _, _), selected)) => Ok(selected),
+            Some(_) => Err(Witness::Unavailable),
+            None => Err(Witness::Impossible),
         }
     }
 
     // produce a possily malleable satisafaction for thesh frag
-    fn thresh_mall<Ctx, Sat, F>(
-        thresh: &Threshold<Arc<Miniscript<Pk, Ctx>>, 0>,
+    fn thresh_mall<Ctx, Ext, Sat, F>(
+        thresh: &Threshold<Arc<Miniscript<Pk, Ctx, Ext>>, 0>,
         stfr: &Sat,
         root_has_sig: bool,
         leaf_hash: &TapLeafHash,
@@ -1088,6 +1916,7 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
     ) -> Self
     where
         Ctx: ScriptContext,
+        Ext: Extension,
         Sat: AssetProvider<Pk>,
         F: FnMut(
             Satisfaction<Placeholder<Pk>>,
@@ -1226,8 +2055,8 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
     }
 
     // produce a non-malleable satisfaction
-    fn satisfy_helper<Ctx, Sat, F, G>(
-        term: &Terminal<Pk, Ctx>,
+    fn satisfy_helper<Ctx, Ext, Sat, F, G>(
+        term: &Terminal<Pk, Ctx, Ext>,
         stfr: &Sat,
         root_has_sig: bool,
         leaf_hash: &TapLeafHash,
@@ -1236,13 +2065,14 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
     ) -> Self
     where
         Ctx: ScriptContext,
+        Ext: Extension,
         Sat: AssetProvider<Pk>,
         F: FnMut(
             Satisfaction<Placeholder<Pk>>,
             Satisfaction<Placeholder<Pk>>,
         ) -> Satisfaction<Placeholder<Pk>>,
         G: FnMut(
-            &Threshold<Arc<Miniscript<Pk, Ctx>>, 0>,
+            &Threshold<Arc<Miniscript<Pk, Ctx, Ext>>, 0>,
             &Sat,
             bool,
             &TapLeafHash,
@@ -1565,12 +2395,13 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
                     }
                 }
             }
+            Terminal::Ext(ref e) => e.satisfy(stfr),
         }
     }
 
     // Helper function to produce a dissatisfaction
-    fn dissatisfy_helper<Ctx, Sat, F, G>(
-        term: &Terminal<Pk, Ctx>,
+    fn dissatisfy_helper<Ctx, Ext, Sat, F, G>(
+        term: &Terminal<Pk, Ctx, Ext>,
         stfr: &Sat,
         root_has_sig: bool,
         leaf_hash: &TapLeafHash,
@@ -1579,13 +2410,14 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
     ) -> Self
     where
         Ctx: ScriptContext,
+        Ext: Extension,
         Sat: AssetProvider<Pk>,
         F: FnMut(
             Satisfaction<Placeholder<Pk>>,
             Satisfaction<Placeholder<Pk>>,
         ) -> Satisfaction<Placeholder<Pk>>,
         G: FnMut(
-            &Threshold<Arc<Miniscript<Pk, Ctx>>, 0>,
+            &Threshold<Arc<Miniscript<Pk, Ctx, Ext>>, 0>,
             &Sat,
             bool,
             &TapLeafHash,
@@ -1748,6 +2580,7 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
                 relative_timelock: None,
                 absolute_timelock: None,
             },
+            Terminal::Ext(ref e) => e.dissatisfy(stfr),
         }
     }
 
@@ -1775,14 +2608,15 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
 
 impl Satisfaction<Vec<u8>> {
     /// Produce a satisfaction non-malleable satisfaction
-    pub(super) fn satisfy<Ctx, Pk, Sat>(
-        term: &Terminal<Pk, Ctx>,
+    pub(super) fn satisfy<Ctx, Ext, Pk, Sat>(
+        term: &Terminal<Pk, Ctx, Ext>,
         stfr: &Sat,
         root_has_sig: bool,
         leaf_hash: &TapLeafHash,
     ) -> Self
     where
         Ctx: ScriptContext,
+        Ext: Extension,
         Pk: MiniscriptKey + ToPublicKey,
         Sat: Satisfier<Pk>,
     {
@@ -1792,14 +2626,15 @@ impl Satisfaction<Vec<u8>> {
     }
 
     /// Produce a satisfaction(possibly malleable)
-    pub(super) fn satisfy_mall<Ctx, Pk, Sat>(
-        term: &Terminal<Pk, Ctx>,
+    pub(super) fn satisfy_mall<Ctx, Ext, Pk, Sat>(
+        term: &Terminal<Pk, Ctx, Ext>,
         stfr: &Sat,
         root_has_sig: bool,
         leaf_hash: &TapLeafHash,
     ) -> Self
     where
         Ctx: ScriptContext,
+        Ext: Extension,
         Pk: MiniscriptKey + ToPublicKey,
         Sat: Satisfier<Pk>,
     {
@@ -1808,3 +2643,431 @@ impl Satisfaction<Vec<u8>> {
             .expect("the same satisfier should manage to complete the template")
     }
 }
+
+#[cfg(feature = "serde")]
+mod serde_impls {
+    //! Serde support for witness templates.
+    //!
+    //! Enums are encoded as serde enums, with the variant names of the Rust types. Keys and
+    //! hashes are encoded as strings, and tuple variants as tuples.
+
+    use core::marker::PhantomData;
+
+    use bitcoin::hex::{DisplayHex, FromHex};
+    use serde::de::{self, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor};
+    use serde::ser::SerializeStruct;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::*;
+    use crate::util::{Identifier, StrForm};
+    use crate::FromStrKey;
+
+    const SCHNORR_SIG_TYPE_VARIANTS: &[&str] = &["KeySpend", "ScriptSpend"];
+
+    impl Serialize for SchnorrSigType {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            const NAME: &str = "SchnorrSigType";
+            match self {
+                SchnorrSigType::KeySpend { merkle_root } => serializer.serialize_newtype_variant(
+                    NAME,
+                    0,
+                    "KeySpend",
+                    &merkle_root.as_ref().map(StrForm),
+                ),
+                SchnorrSigType::ScriptSpend { leaf_hash } => serializer.serialize_newtype_variant(
+                    NAME,
+                    1,
+                    "ScriptSpend",
+                    &StrForm(leaf_hash),
+                ),
+            }
+        }
+    }
+
+    impl<'de> Deserialize<'de> for SchnorrSigType {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct SchnorrSigTypeVisitor;
+
+            impl<'de> Visitor<'de> for SchnorrSigTypeVisitor {
+                type Value = SchnorrSigType;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("a schnorr signature type")
+                }
+
+                fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+                    let (idx, variant) =
+                        data.variant_seed(Identifier(SCHNORR_SIG_TYPE_VARIANTS))?;
+                    Ok(match idx {
+                        0 => SchnorrSigType::KeySpend {
+                            merkle_root: variant
+                                .newtype_variant::<Option<StrForm<TapNodeHash>>>()?
+                                .map(|h| h.0),
+                        },
+                        _ => SchnorrSigType::ScriptSpend {
+                            leaf_hash: variant.newtype_variant::<StrForm<TapLeafHash>>()?.0,
+                        },
+                    })
+                }
+            }
+
+            deserializer.deserialize_enum(
+                "SchnorrSigType",
+                SCHNORR_SIG_TYPE_VARIANTS,
+                SchnorrSigTypeVisitor,
+            )
+        }
+    }
+
+    const PLACEHOLDER_VARIANTS: &[&str] = &[
+        "Pubkey",
+        "PubkeyHash",
+        "EcdsaSigPk",
+        "EcdsaSigPkHash",
+        "SchnorrSigPk",
+        "SchnorrSigPkHash",
+        "Sha256Preimage",
+        "Hash256Preimage",
+        "Ripemd160Preimage",
+        "Hash160Preimage",
+        "HashDissatisfaction",
+        "PushOne",
+        "PushZero",
+        "TapScript",
+        "TapControlBlock",
+        "PendingSha256Preimage",
+        "PendingHash256Preimage",
+        "PendingRipemd160Preimage",
+        "PendingHash160Preimage",
+    ];
+
+    impl<Pk: MiniscriptKey> Serialize for Placeholder<Pk> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            use Placeholder::*;
+
+            const NAME: &str = "Placeholder";
+            let v = PLACEHOLDER_VARIANTS;
+            match self {
+                Pubkey(pk, size) => {
+                    serializer.serialize_newtype_variant(NAME, 0, v[0], &(StrForm(pk), size))
+                }
+                PubkeyHash(hash, size) => {
+                    serializer.serialize_newtype_variant(NAME, 1, v[1], &(StrForm(hash), size))
+                }
+                EcdsaSigPk(pk) => serializer.serialize_newtype_variant(NAME, 2, v[2], &StrForm(pk)),
+                EcdsaSigPkHash(hash) => {
+                    serializer.serialize_newtype_variant(NAME, 3, v[3], &StrForm(hash))
+                }
+                SchnorrSigPk(pk, sig_type, size) => serializer.serialize_newtype_variant(
+                    NAME,
+                    4,
+                    v[4],
+                    &(StrForm(pk), sig_type, size),
+                ),
+                SchnorrSigPkHash(hash, leaf_hash, size) => serializer.serialize_newtype_variant(
+                    NAME,
+                    5,
+                    v[5],
+                    &(StrForm(hash), StrForm(leaf_hash), size),
+                ),
+                Sha256Preimage(hash) => {
+                    serializer.serialize_newtype_variant(NAME, 6, v[6], &StrForm(hash))
+                }
+                Hash256Preimage(hash) => {
+                    serializer.serialize_newtype_variant(NAME, 7, v[7], &StrForm(hash))
+                }
+                Ripemd160Preimage(hash) => {
+                    serializer.serialize_newtype_variant(NAME, 8, v[8], &StrForm(hash))
+                }
+                Hash160Preimage(hash) => {
+                    serializer.serialize_newtype_variant(NAME, 9, v[9], &StrForm(hash))
+                }
+                HashDissatisfaction => serializer.serialize_unit_variant(NAME, 10, v[10]),
+                PushOne => serializer.serialize_unit_variant(NAME, 11, v[11]),
+                PushZero => serializer.serialize_unit_variant(NAME, 12, v[12]),
+                TapScript(script) => serializer.serialize_newtype_variant(NAME, 13, v[13], script),
+                TapControlBlock(cb) => serializer.serialize_newtype_variant(
+                    NAME,
+                    14,
+                    v[14],
+                    &cb.serialize().to_lower_hex_string(),
+                ),
+                PendingSha256Preimage(hash) => {
+                    serializer.serialize_newtype_variant(NAME, 15, v[15], &StrForm(hash))
+                }
+                PendingHash256Preimage(hash) => {
+                    serializer.serialize_newtype_variant(NAME, 16, v[16], &StrForm(hash))
+                }
+                PendingRipemd160Preimage(hash) => {
+                    serializer.serialize_newtype_variant(NAME, 17, v[17], &StrForm(hash))
+                }
+                PendingHash160Preimage(hash) => {
+                    serializer.serialize_newtype_variant(NAME, 18, v[18], &StrForm(hash))
+                }
+            }
+        }
+    }
+
+    impl<'de, Pk: FromStrKey> Deserialize<'de> for Placeholder<Pk> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct PlaceholderVisitor<Pk>(PhantomData<Pk>);
+
+            impl<'de, Pk: FromStrKey> Visitor<'de> for PlaceholderVisitor<Pk> {
+                type Value = Placeholder<Pk>;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("a witness template placeholder")
+                }
+
+                fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+                    use Placeholder::*;
+
+                    let (idx, variant) = data.variant_seed(Identifier(PLACEHOLDER_VARIANTS))?;
+                    Ok(match idx {
+                        0 => {
+                            let (pk, size) = variant.newtype_variant::<(StrForm<Pk>, usize)>()?;
+                            Pubkey(pk.0, size)
+                        }
+                        1 => {
+                            let (hash, size) =
+                                variant.newtype_variant::<(StrForm<hash160::Hash>, usize)>()?;
+                            PubkeyHash(hash.0, size)
+                        }
+                        2 => EcdsaSigPk(variant.newtype_variant::<StrForm<Pk>>()?.0),
+                        3 => EcdsaSigPkHash(variant.newtype_variant::<StrForm<hash160::Hash>>()?.0),
+                        4 => {
+                            let (pk, sig_type, size) =
+                                variant
+                                    .newtype_variant::<(StrForm<Pk>, SchnorrSigType, usize)>()?;
+                            SchnorrSigPk(pk.0, sig_type, size)
+                        }
+                        5 => {
+                            let (hash, leaf_hash, size) = variant.newtype_variant::<(
+                                StrForm<hash160::Hash>,
+                                StrForm<TapLeafHash>,
+                                usize,
+                            )>(
+                            )?;
+                            SchnorrSigPkHash(hash.0, leaf_hash.0, size)
+                        }
+                        6 => Sha256Preimage(variant.newtype_variant::<StrForm<Pk::Sha256>>()?.0),
+                        7 => Hash256Preimage(variant.newtype_variant::<StrForm<Pk::Hash256>>()?.0),
+                        8 => Ripemd160Preimage(
+                            variant.newtype_variant::<StrForm<Pk::Ripemd160>>()?.0,
+                        ),
+                        9 => Hash160Preimage(variant.newtype_variant::<StrForm<Pk::Hash160>>()?.0),
+                        10 => variant.unit_variant().map(|_| HashDissatisfaction)?,
+                        11 => variant.unit_variant().map(|_| PushOne)?,
+                        12 => variant.unit_variant().map(|_| PushZero)?,
+                        13 => TapScript(variant.newtype_variant()?),
+                        15 => PendingSha256Preimage(
+                            variant.newtype_variant::<StrForm<Pk::Sha256>>()?.0,
+                        ),
+                        16 => PendingHash256Preimage(
+                            variant.newtype_variant::<StrForm<Pk::Hash256>>()?.0,
+                        ),
+                        17 => PendingRipemd160Preimage(
+                            variant.newtype_variant::<StrForm<Pk::Ripemd160>>()?.0,
+                        ),
+                        18 => PendingHash160Preimage(
+                            variant.newtype_variant::<StrForm<Pk::Hash160>>()?.0,
+                        ),
+                        _ => {
+                            let hex = variant.newtype_variant::<String>()?;
+                            let bytes = Vec::<u8>::from_hex(&hex).map_err(de::Error::custom)?;
+                            TapControlBlock(
+                                ControlBlock::decode(&bytes).map_err(de::Error::custom)?,
+                            )
+                        }
+                    })
+                }
+            }
+
+            deserializer.deserialize_enum(
+                "Placeholder",
+                PLACEHOLDER_VARIANTS,
+                PlaceholderVisitor(PhantomData),
+            )
+        }
+    }
+
+    const WITNESS_VARIANTS: &[&str] = &["Stack", "Unavailable", "Impossible"];
+
+    impl<T: Serialize> Serialize for Witness<T> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            const NAME: &str = "Witness";
+            match self {
+                Witness::Stack(stack) => {
+                    serializer.serialize_newtype_variant(NAME, 0, "Stack", stack)
+                }
+                Witness::Unavailable => serializer.serialize_unit_variant(NAME, 1, "Unavailable"),
+                Witness::Impossible => serializer.serialize_unit_variant(NAME, 2, "Impossible"),
+            }
+        }
+    }
+
+    impl<'de, T: Deserialize<'de>> Deserialize<'de> for Witness<T> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct WitnessVisitor<T>(PhantomData<T>);
+
+            impl<'de, T: Deserialize<'de>> Visitor<'de> for WitnessVisitor<T> {
+                type Value = Witness<T>;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("a witness")
+                }
+
+                fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+                    let (idx, variant) = data.variant_seed(Identifier(WITNESS_VARIANTS))?;
+                    match idx {
+                        0 => variant.newtype_variant().map(Witness::Stack),
+                        1 => variant.unit_variant().map(|_| Witness::Unavailable),
+                        _ => variant.unit_variant().map(|_| Witness::Impossible),
+                    }
+                }
+            }
+
+            deserializer.deserialize_enum("Witness", WITNESS_VARIANTS, WitnessVisitor(PhantomData))
+        }
+    }
+
+    const SATISFACTION_FIELDS: &[&str] =
+        &["stack", "has_sig", "absolute_timelock", "relative_timelock"];
+
+    // Timelocks are encoded as their consensus values.
+    impl<T: Serialize> Serialize for Satisfaction<T> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut s = serializer.serialize_struct("Satisfaction", 4)?;
+            s.serialize_field("stack", &self.stack)?;
+            s.serialize_field("has_sig", &self.has_sig)?;
+            s.serialize_field(
+                "absolute_timelock",
+                &self.absolute_timelock.map(AbsLockTime::to_consensus_u32),
+            )?;
+            s.serialize_field(
+                "relative_timelock",
+                &self.relative_timelock.map(RelLockTime::to_consensus_u32),
+            )?;
+            s.end()
+        }
+    }
+
+    impl<'de, T: Deserialize<'de>> Deserialize<'de> for Satisfaction<T> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct SatisfactionVisitor<T>(PhantomData<T>);
+
+            fn build<T, E: de::Error>(
+                stack: Option<Witness<T>>,
+                has_sig: Option<bool>,
+                absolute_timelock: Option<u32>,
+                relative_timelock: Option<u32>,
+            ) -> Result<Satisfaction<T>, E> {
+                Ok(Satisfaction {
+                    stack: stack.ok_or_else(|| E::missing_field("stack"))?,
+                    has_sig: has_sig.ok_or_else(|| E::missing_field("has_sig"))?,
+                    absolute_timelock: absolute_timelock
+                        .map(AbsLockTime::from_consensus)
+                        .transpose()
+                        .map_err(E::custom)?,
+                    relative_timelock: relative_timelock
+                        .map(RelLockTime::from_consensus)
+                        .transpose()
+                        .map_err(E::custom)?,
+                })
+            }
+
+            impl<'de, T: Deserialize<'de>> Visitor<'de> for SatisfactionVisitor<T> {
+                type Value = Satisfaction<T>;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("a satisfaction")
+                }
+
+                fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                    let stack = seq.next_element()?;
+                    let has_sig = seq.next_element()?;
+                    let absolute_timelock = seq.next_element()?.flatten();
+                    let relative_timelock = seq.next_element()?.flatten();
+                    build(stack, has_sig, absolute_timelock, relative_timelock)
+                }
+
+                fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                    let (mut stack, mut has_sig) = (None, None);
+                    let (mut absolute_timelock, mut relative_timelock) = (None, None);
+                    while let Some(idx) = map.next_key_seed(Identifier(SATISFACTION_FIELDS))? {
+                        match idx {
+                            0 => stack = Some(map.next_value()?),
+                            1 => has_sig = Some(map.next_value()?),
+                            2 => absolute_timelock = map.next_value()?,
+                            _ => relative_timelock = map.next_value()?,
+                        }
+                    }
+                    build(stack, has_sig, absolute_timelock, relative_timelock)
+                }
+            }
+
+            deserializer.deserialize_struct(
+                "Satisfaction",
+                SATISFACTION_FIELDS,
+                SatisfactionVisitor(PhantomData),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+    use crate::AbsLockTime;
+
+    fn sat(
+        stack: Vec<Placeholder<bitcoin::PublicKey>>,
+        absolute_timelock: Option<u32>,
+    ) -> Satisfaction<Placeholder<bitcoin::PublicKey>> {
+        Satisfaction {
+            has_sig: stack
+                .iter()
+                .any(|p| matches!(p, Placeholder::EcdsaSigPk(_))),
+            stack: Witness::Stack(stack),
+            relative_timelock: None,
+            absolute_timelock: absolute_timelock.map(|n| AbsLockTime::from_consensus(n).unwrap()),
+        }
+    }
+
+    #[test]
+    fn thresh_selection_exact() {
+        let pk = bitcoin::PublicKey::from_str(
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+        )
+        .unwrap();
+        let sig = || Placeholder::EcdsaSigPk(pk);
+
+        // The dissatisfactions of the first two children use a height and a
+        // timestamp, so one of them must be satisfied. Satisfying the last
+        // child alone is cheapest, but leaves no usable combination.
+        let sats = vec![
+            sat(vec![sig(), sig()], None),
+            sat(vec![sig(), sig()], None),
+            sat(vec![sig()], None),
+        ];
+        let dissats = vec![
+            sat(vec![Placeholder::PushZero], Some(100)),
+            sat(vec![Placeholder::PushZero], Some(500_000_001)),
+            sat(vec![Placeholder::PushZero], None),
+        ];
+        assert_eq!(Satisfaction::thresh_selection(&sats, &dissats, 1), Ok(vec![1]));
+        assert_eq!(Satisfaction::thresh_selection(&sats, &dissats, 2), Ok(vec![1, 2]));
+
+        // Without locks, the children cheapest to satisfy are selected, the
+        // earliest ones on ties
+        let dissats = vec![
+            sat(vec![Placeholder::PushZero], None),
+            sat(vec![Placeholder::PushZero], None),
+            sat(vec![Placeholder::PushZero], None),
+        ];
+        assert_eq!(Satisfaction::thresh_selection(&sats, &dissats, 1), Ok(vec![2]));
+        assert_eq!(Satisfaction::thresh_selection(&sats, &dissats, 2), Ok(vec![0, 2]));
+    }
+}
@@ -8,6 +8,7 @@ use core::iter::once;
 
 use super::ScriptContext;
 use crate::miniscript::context::SigType;
+use crate::miniscript::extension::Extension;
 use crate::prelude::*;
 use crate::{script_num_size, AbsLockTime, MiniscriptKey, RelLockTime, Terminal};
 
@@ -924,10 +925,11 @@ impl ExtData {
 
     /// Compute the type of a fragment assuming all the children of
     /// Miniscript have been computed already.
-    pub fn type_check<Pk, Ctx>(fragment: &Terminal<Pk, Ctx>) -> Self
+    pub fn type_check<Pk, Ctx, Ext>(fragment: &Terminal<Pk, Ctx, Ext>) -> Self
     where
         Ctx: ScriptContext,
         Pk: MiniscriptKey,
+        Ext: Extension,
     {
         let ret = match *fragment {
             Terminal::True => Self::TRUE,
@@ -942,6 +944,7 @@ impl ExtData {
             Terminal::Hash256(..) => Self::hash256(),
             Terminal::Ripemd160(..) => Self::ripemd160(),
             Terminal::Hash160(..) => Self::hash160(),
+            Terminal::Ext(ref e) => e.extra_prop(),
             Terminal::Alt(ref sub) => Self::cast_alt(sub.ext),
             Terminal::Swap(ref sub) => Self::cast_swap(sub.ext),
             Terminal::Check(ref sub) => Self::cast_check(sub.ext),
@@ -18,6 +18,7 @@ use std::error;
 pub use self::correctness::{Base, Correctness, Input};
 pub use self::extra_props::ExtData;
 pub use self::malleability::{Dissat, Malleability};
+use super::extension::Extension;
 use super::ScriptContext;
 use crate::{MiniscriptKey, Terminal};
 
@@ -145,7 +146,7 @@ impl fmt::Display for Error {
 
 #[cfg(feature = "std")]
 impl error::Error for Error {
-    fn cause(&self) -> Option<&dyn error::Error> { None }
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> { None }
 }
 
 /// Structure representing the type of a Miniscript fragment, including all
@@ -444,10 +445,11 @@ impl Type {
 impl Type {
     /// Compute the type of a fragment assuming all the children of
     /// Miniscript have been computed already.
-    pub fn type_check<Pk, Ctx>(fragment: &Terminal<Pk, Ctx>) -> Result<Self, Error>
+    pub fn type_check<Pk, Ctx, Ext>(fragment: &Terminal<Pk, Ctx, Ext>) -> Result<Self, Error>
     where
         Pk: MiniscriptKey,
         Ctx: ScriptContext,
+        Ext: Extension,
     {
         let wrap_err = |result: Result<Self, ErrorKind>| {
             result.map_err(|kind| Error { fragment_string: fragment.to_string(), error: kind })
@@ -466,6 +468,7 @@ impl Type {
             Terminal::Hash256(..) => Ok(Self::hash()),
             Terminal::Ripemd160(..) => Ok(Self::hash()),
             Terminal::Hash160(..) => Ok(Self::hash()),
+            Terminal::Ext(ref e) => Ok(e.type_check()),
             Terminal::Alt(ref sub) => wrap_err(Self::cast_alt(sub.ty)),
             Terminal::Swap(ref sub) => wrap_err(Self::cast_swap(sub.ty)),
             Terminal::Check(ref sub) => wrap_err(Self::cast_check(sub.ty)),
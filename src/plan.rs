@@ -17,19 +17,27 @@
 //! witness/script_sig for the input.
 
 use core::iter::FromIterator;
+use core::{cmp, fmt};
 
 use bitcoin::hashes::{hash160, ripemd160, sha256};
 use bitcoin::key::XOnlyPublicKey;
 use bitcoin::script::PushBytesBuf;
 use bitcoin::taproot::{ControlBlock, LeafVersion, TapLeafHash};
-use bitcoin::{absolute, bip32, psbt, relative, ScriptBuf, WitnessVersion};
+use bitcoin::transaction::Version;
+use bitcoin::{
+    absolute, bip32, psbt, relative, Amount, FeeRate, ScriptBuf, Sequence, Transaction, TxIn,
+    Weight, WitnessVersion,
+};
 
 use crate::descriptor::{self, Descriptor, DescriptorType, KeyMap};
 use crate::miniscript::hash256;
 use crate::miniscript::satisfy::{Placeholder, Satisfier, SchnorrSigType};
 use crate::prelude::*;
 use crate::util::witness_size;
-use crate::{DefiniteDescriptorKey, DescriptorPublicKey, Error, MiniscriptKey, ToPublicKey};
+use crate::{
+    AbsLockTime, DefiniteDescriptorKey, DescriptorPublicKey, Error, ForEachKey, MiniscriptKey,
+    RelLockTime, SatisfyError, ToPublicKey,
+};
 
 /// Trait describing a present/missing lookup table for constructing witness templates
 ///
@@ -105,6 +113,22 @@ pub trait AssetProvider<Pk: MiniscriptKey> {
     /// Given a HASH160 hash, look up its preimage, return whether we found it
     fn provider_lookup_hash160(&self, _: &Pk::Hash160) -> bool { false }
 
+    /// Given a SHA256 hash, return whether its preimage can be obtained on demand, though
+    /// it isn't known yet
+    fn provider_lookup_pending_sha256(&self, _: &Pk::Sha256) -> bool { false }
+
+    /// Given a HASH256 hash, return whether its preimage can be obtained on demand, though
+    /// it isn't known yet
+    fn provider_lookup_pending_hash256(&self, _: &Pk::Hash256) -> bool { false }
+
+    /// Given a RIPEMD160 hash, return whether its preimage can be obtained on demand, though
+    /// it isn't known yet
+    fn provider_lookup_pending_ripemd160(&self, _: &Pk::Ripemd160) -> bool { false }
+
+    /// Given a HASH160 hash, return whether its preimage can be obtained on demand, though
+    /// it isn't known yet
+    fn provider_lookup_pending_hash160(&self, _: &Pk::Hash160) -> bool { false }
+
     /// Assert whether a relative locktime is satisfied
     fn check_older(&self, _: relative::LockTime) -> bool { false }
 
@@ -142,6 +166,10 @@ impl AssetProvider<DefiniteDescriptorKey> for LoggerAssetProvider<'_> {
     impl_log_method!(provider_lookup_hash256, hash: &hash256::Hash, -> bool);
     impl_log_method!(provider_lookup_ripemd160, hash: &ripemd160::Hash, -> bool);
     impl_log_method!(provider_lookup_hash160, hash: &hash160::Hash, -> bool);
+    impl_log_method!(provider_lookup_pending_sha256, hash: &sha256::Hash, -> bool);
+    impl_log_method!(provider_lookup_pending_hash256, hash: &hash256::Hash, -> bool);
+    impl_log_method!(provider_lookup_pending_ripemd160, hash: &ripemd160::Hash, -> bool);
+    impl_log_method!(provider_lookup_pending_hash160, hash: &hash160::Hash, -> bool);
     impl_log_method!(check_older, s: relative::LockTime, -> bool);
     impl_log_method!(check_after, t: absolute::LockTime, -> bool);
 }
@@ -223,7 +251,11 @@ where
 /// and the timelocks needed for satisfying the plan.
 /// Calling `plan` on a Descriptor will return this structure,
 /// containing the cheapest spending path possible (considering the `Assets` given)
-#[derive(Debug, Clone)]
+///
+/// With the `serde` feature, a plan can be serialized, e.g. to be computed once by a
+/// coordinator and shipped to air-gapped signers. Deserialization does not check that the
+/// witness template is consistent with the descriptor.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Plan {
     /// This plan's witness template
     pub(crate) template: Vec<Placeholder<DefiniteDescriptorKey>>,
@@ -274,6 +306,222 @@ impl Plan {
         }
     }
 
+    /// The weight of the input spending this plan
+    ///
+    /// This adds the outpoint and `nSequence` to [`Plan::satisfaction_weight`], so like it,
+    /// leaves out the empty witness of a legacy input in a segwit transaction.
+    pub fn input_weight(&self) -> Weight {
+        // outpoint (32 + 4) + nSequence (4)
+        Weight::from_non_witness_data_size(32 + 4 + 4)
+            + Weight::from_wu_usize(self.satisfaction_weight())
+    }
+
+    /// The fee paid at `feerate` for the satisfaction weight of this plan
+    pub fn satisfaction_fee(&self, feerate: FeeRate) -> Amount {
+        Weight::from_wu_usize(self.satisfaction_weight()) * feerate
+    }
+
+    /// The weight added to a transaction by adding an input spending this plan
+    ///
+    /// `tx_has_witness` is whether the transaction already has a segwit input. If not, adding a
+    /// segwit input also adds the segwit marker and flag. If so, adding a legacy input also adds
+    /// its empty witness. The change in size of the input count is not included.
+    pub fn marginal_weight(&self, tx_has_witness: bool) -> Weight {
+        let extra = match (self.witness_version().is_some(), tx_has_witness) {
+            // marker (1) + flag (1)
+            (true, false) => Weight::from_wu(2),
+            // empty witness stack (1)
+            (false, true) => Weight::from_wu(1),
+            _ => Weight::ZERO,
+        };
+        self.input_weight() + extra
+    }
+
+    /// The fee paid at `feerate` for adding an input spending this plan to a transaction
+    ///
+    /// See [`Plan::marginal_weight`] for the meaning of `tx_has_witness`.
+    pub fn marginal_fee(&self, feerate: FeeRate, tx_has_witness: bool) -> Amount {
+        self.marginal_weight(tx_has_witness) * feerate
+    }
+
+    /// The `nLockTime` required by this plan, if any
+    ///
+    /// The spending transaction's locktime must be of the same unit as, and at
+    /// least, this value.
+    pub fn required_locktime(&self) -> Option<absolute::LockTime> { self.absolute_timelock }
+
+    /// The `nSequence` required by this plan, if any
+    ///
+    /// The spending input's sequence must encode a relative timelock of the same
+    /// unit as, and at least, this value. Note that this also requires the
+    /// transaction version to be at least 2.
+    pub fn required_sequence(&self) -> Option<Sequence> {
+        self.relative_timelock.map(|lt| lt.to_sequence())
+    }
+
+    /// The signatures, hash preimages and timelocks needed to complete this plan
+    ///
+    /// Signatures and preimages are listed in the order in which they appear in the
+    /// witness.
+    pub fn requirements(&self) -> PlanRequirements {
+        let mut reqs = PlanRequirements {
+            signatures: vec![],
+            sha256_preimages: vec![],
+            hash256_preimages: vec![],
+            ripemd160_preimages: vec![],
+            hash160_preimages: vec![],
+            absolute_timelock: self.absolute_timelock,
+            relative_timelock: self.relative_timelock,
+        };
+        for placeholder in &self.template {
+            let sig = match placeholder {
+                Placeholder::EcdsaSigPk(pk) => RequiredSignature {
+                    key: SignatureKey::Key(pk.clone()),
+                    leaf_hash: None,
+                    sighash: RequiredSighash::Ecdsa,
+                },
+                Placeholder::EcdsaSigPkHash(hash) => RequiredSignature {
+                    key: SignatureKey::KeyHash(*hash),
+                    leaf_hash: None,
+                    sighash: RequiredSighash::Ecdsa,
+                },
+                Placeholder::SchnorrSigPk(pk, sig_type, size) => RequiredSignature {
+                    key: SignatureKey::Key(pk.clone()),
+                    leaf_hash: match sig_type {
+                        SchnorrSigType::KeySpend { .. } => None,
+                        SchnorrSigType::ScriptSpend { leaf_hash } => Some(*leaf_hash),
+                    },
+                    sighash: RequiredSighash::schnorr(*size),
+                },
+                Placeholder::SchnorrSigPkHash(hash, leaf_hash, size) => RequiredSignature {
+                    key: SignatureKey::KeyHash(*hash),
+                    leaf_hash: Some(*leaf_hash),
+                    sighash: RequiredSighash::schnorr(*size),
+                },
+                Placeholder::Sha256Preimage(hash) | Placeholder::PendingSha256Preimage(hash) => {
+                    reqs.sha256_preimages.push(*hash);
+                    continue;
+                }
+                Placeholder::Hash256Preimage(hash) | Placeholder::PendingHash256Preimage(hash) => {
+                    reqs.hash256_preimages.push(*hash);
+                    continue;
+                }
+                Placeholder::Ripemd160Preimage(hash)
+                | Placeholder::PendingRipemd160Preimage(hash) => {
+                    reqs.ripemd160_preimages.push(*hash);
+                    continue;
+                }
+                Placeholder::Hash160Preimage(hash) | Placeholder::PendingHash160Preimage(hash) => {
+                    reqs.hash160_preimages.push(*hash);
+                    continue;
+                }
+                Placeholder::Pubkey(..)
+                | Placeholder::PubkeyHash(..)
+                | Placeholder::HashDissatisfaction
+                | Placeholder::PushOne
+                | Placeholder::PushZero
+                | Placeholder::TapScript(_)
+                | Placeholder::TapControlBlock(_) => continue,
+            };
+            reqs.signatures.push(sig);
+        }
+        reqs
+    }
+
+    /// The taproot leaf this plan spends, or `None` for a key spend or a non-taproot descriptor
+    ///
+    /// This gives the leaf hash to sign for, e.g. to pass to a hardware signer. See also
+    /// [`Plan::spend_path`], which gives the conditions of the chosen branch.
+    pub fn tap_leaf(&self) -> Option<PlanLeaf> {
+        let script = self
+            .template
+            .iter()
+            .find_map(|placeholder| match placeholder {
+                Placeholder::TapScript(script) => Some(script),
+                _ => None,
+            })?;
+        let control_block = self
+            .template
+            .iter()
+            .find_map(|placeholder| match placeholder {
+                Placeholder::TapControlBlock(control_block) => Some(control_block),
+                _ => None,
+            })?;
+        Some(PlanLeaf {
+            leaf_hash: TapLeafHash::from_script(script, control_block.leaf_version),
+            depth: control_block.merkle_branch.len() as u8,
+            script: script.clone(),
+            leaf_version: control_block.leaf_version,
+        })
+    }
+
+    /// Sets the sequence of an input so that it satisfies this plan
+    ///
+    /// If the input already has a relative timelock which implies the required one,
+    /// it is left unchanged. If this plan requires an absolute timelock but no
+    /// relative timelock, a final sequence is replaced by one which enables the
+    /// locktime.
+    ///
+    /// This does not check the transaction version; see [`Plan::apply_timelocks`].
+    pub fn apply_sequence(&self, txin: &mut TxIn) -> Result<(), TimelockConflict> {
+        if let Some(required) = self.relative_timelock {
+            match txin.sequence.to_relative_lock_time() {
+                Some(existing) if required.is_implied_by(existing) => {}
+                // A zero relative timelock places no constraints, so it may be replaced.
+                Some(existing)
+                    if !existing.is_same_unit(required) && existing.to_consensus_u32() != 0 =>
+                {
+                    return Err(TimelockConflict::Sequence { required, existing: txin.sequence });
+                }
+                _ => txin.sequence = required.to_sequence(),
+            }
+        } else if self.absolute_timelock.is_some() && txin.sequence == Sequence::MAX {
+            txin.sequence = Sequence::ENABLE_LOCKTIME_NO_RBF;
+        }
+        Ok(())
+    }
+
+    /// Updates a transaction so that its `index`th input satisfies this plan's timelocks
+    ///
+    /// This sets the input's sequence as [`Plan::apply_sequence`] does, raises the
+    /// transaction's locktime if it is lower than required, and raises the transaction
+    /// version to 2 if a relative timelock is required. Values which already satisfy
+    /// the plan are left unchanged.
+    ///
+    /// Returns an error, without modifying the transaction, if its existing locktime
+    /// or sequence uses a different unit than the one required.
+    pub fn apply_timelocks(
+        &self,
+        tx: &mut Transaction,
+        index: usize,
+    ) -> Result<(), TimelockConflict> {
+        let n_inputs = tx.input.len();
+        let mut txin = tx
+            .input
+            .get(index)
+            .cloned()
+            .ok_or(TimelockConflict::InputIndexOutOfBounds { index, n_inputs })?;
+        self.apply_sequence(&mut txin)?;
+
+        let mut lock_time = tx.lock_time;
+        if let Some(required) = self.absolute_timelock {
+            if lock_time == absolute::LockTime::ZERO
+                || (lock_time.is_same_unit(required) && !required.is_implied_by(lock_time))
+            {
+                lock_time = required;
+            } else if !lock_time.is_same_unit(required) {
+                return Err(TimelockConflict::LockTime { required, existing: lock_time });
+            }
+        }
+
+        tx.input[index] = txin;
+        tx.lock_time = lock_time;
+        if self.relative_timelock.is_some() && tx.version < Version::TWO {
+            tx.version = Version::TWO;
+        }
+        Ok(())
+    }
+
     /// Try creating the final script_sig and witness using a [`Satisfier`]
     pub fn satisfy<Sat: Satisfier<DefiniteDescriptorKey>>(
         &self,
@@ -286,7 +534,7 @@ impl Plan {
             .iter()
             .map(|placeholder| placeholder.satisfy_self(stfr))
             .collect::<Option<Vec<Vec<u8>>>>()
-            .ok_or(Error::CouldNotSatisfy)?;
+            .ok_or(Error::Satisfy(SatisfyError::CouldNotSatisfy))?;
 
         Ok(match self.descriptor.desc_type() {
             DescriptorType::Bare
@@ -306,7 +554,8 @@ impl Plan {
             DescriptorType::Wpkh
             | DescriptorType::Wsh
             | DescriptorType::WshSortedMulti
-            | DescriptorType::Tr => (stack, ScriptBuf::new()),
+            | DescriptorType::TrKeyOnly
+            | DescriptorType::TrScript => (stack, ScriptBuf::new()),
             DescriptorType::ShWsh | DescriptorType::ShWshSortedMulti | DescriptorType::ShWpkh => {
                 (stack, self.descriptor.unsigned_script_sig())
             }
@@ -431,6 +680,459 @@ impl Plan {
     }
 }
 
+/// The taproot leaf spent by a [`Plan`], as returned by [`Plan::tap_leaf`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PlanLeaf {
+    /// The hash of the leaf, which script path signatures commit to.
+    pub leaf_hash: TapLeafHash,
+    /// The depth of the leaf in the tap tree.
+    pub depth: u8,
+    /// The leaf script.
+    pub script: ScriptBuf,
+    /// The leaf version.
+    pub leaf_version: LeafVersion,
+}
+
+/// Everything needed to complete a [`Plan`], as returned by [`Plan::requirements`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlanRequirements {
+    /// The signatures to produce.
+    pub signatures: Vec<RequiredSignature>,
+    /// The SHA256 hashes whose preimages must be provided.
+    pub sha256_preimages: Vec<sha256::Hash>,
+    /// The HASH256 hashes whose preimages must be provided.
+    pub hash256_preimages: Vec<hash256::Hash>,
+    /// The RIPEMD160 hashes whose preimages must be provided.
+    pub ripemd160_preimages: Vec<ripemd160::Hash>,
+    /// The HASH160 hashes whose preimages must be provided.
+    pub hash160_preimages: Vec<hash160::Hash>,
+    /// The absolute timelock the spending transaction must satisfy, if any.
+    pub absolute_timelock: Option<absolute::LockTime>,
+    /// The relative timelock the spending input must satisfy, if any.
+    pub relative_timelock: Option<relative::LockTime>,
+}
+
+/// A signature needed to complete a [`Plan`]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RequiredSignature {
+    /// The key which must sign.
+    pub key: SignatureKey,
+    /// The leaf the signature is for, for taproot script spends.
+    pub leaf_hash: Option<TapLeafHash>,
+    /// The kind of sighash the signature must use.
+    pub sighash: RequiredSighash,
+}
+
+/// The key of a [`RequiredSignature`]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SignatureKey {
+    /// A key given in the descriptor.
+    Key(DefiniteDescriptorKey),
+    /// A key given by its hash in the descriptor, which the signer must also provide.
+    KeyHash(hash160::Hash),
+}
+
+/// The kind of sighash a [`RequiredSignature`] must use
+///
+/// This follows from the size of the signature assumed by the plan, see
+/// [`TaprootCanSign::sighash_default`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RequiredSighash {
+    /// An ECDSA signature, with any sighash type.
+    Ecdsa,
+    /// A Schnorr signature with `SIGHASH_DEFAULT`, i.e. without a sighash byte.
+    SchnorrDefault,
+    /// A Schnorr signature with an explicit sighash type.
+    SchnorrExplicit,
+}
+
+impl RequiredSighash {
+    fn schnorr(sig_size: usize) -> Self {
+        if sig_size == 64 {
+            RequiredSighash::SchnorrDefault
+        } else {
+            RequiredSighash::SchnorrExplicit
+        }
+    }
+}
+
+/// Error applying the timelocks of a [`Plan`] to a transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimelockConflict {
+    /// The transaction's locktime uses a different unit than the required one.
+    LockTime {
+        /// The locktime required by the plan.
+        required: absolute::LockTime,
+        /// The transaction's existing locktime.
+        existing: absolute::LockTime,
+    },
+    /// The input's sequence encodes a relative timelock with a different unit than the
+    /// required one.
+    Sequence {
+        /// The relative timelock required by the plan.
+        required: relative::LockTime,
+        /// The input's existing sequence.
+        existing: Sequence,
+    },
+    /// The transaction has no input with the given index.
+    InputIndexOutOfBounds {
+        /// The requested input index.
+        index: usize,
+        /// The number of inputs in the transaction.
+        n_inputs: usize,
+    },
+}
+
+impl fmt::Display for TimelockConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TimelockConflict::LockTime { required, existing } => write!(
+                f,
+                "plan requires locktime {} but transaction has incompatible locktime {}",
+                required, existing
+            ),
+            TimelockConflict::Sequence { required, existing } => write!(
+                f,
+                "plan requires relative timelock {} but input has incompatible sequence {}",
+                required, existing
+            ),
+            TimelockConflict::InputIndexOutOfBounds { index, n_inputs } => write!(
+                f,
+                "input index {} out of bounds for transaction with {} inputs",
+                index, n_inputs
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for TimelockConflict {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> { None }
+}
+
+/// Maximum number of spending paths reported by [`MissingAssets`].
+///
+/// Thresholds can have exponentially many spending paths, so only this many
+/// of the paths with the fewest missing assets are kept.
+pub const MAX_MISSING_ASSET_PATHS: usize = 64;
+
+/// An asset which is needed to satisfy a spending path, but which is not available.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MissingAsset<Pk: MiniscriptKey> {
+    /// A signature for the given key.
+    Signature(Pk),
+    /// A public key, and a signature for it, for the given key hash.
+    KeyHashSignature(hash160::Hash),
+    /// A SHA256 preimage.
+    Sha256Preimage(Pk::Sha256),
+    /// A HASH256 preimage.
+    Hash256Preimage(Pk::Hash256),
+    /// A RIPEMD160 preimage.
+    Ripemd160Preimage(Pk::Ripemd160),
+    /// A HASH160 preimage.
+    Hash160Preimage(Pk::Hash160),
+    /// An absolute timelock which has not been reached.
+    AbsoluteTimelock(AbsLockTime),
+    /// A relative timelock which has not been reached.
+    RelativeTimelock(RelLockTime),
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for MissingAsset<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MissingAsset::Signature(pk) => write!(f, "signature for {}", pk),
+            MissingAsset::KeyHashSignature(hash) => {
+                write!(f, "key and signature for key hash {}", hash)
+            }
+            MissingAsset::Sha256Preimage(hash) => write!(f, "sha256 preimage of {}", hash),
+            MissingAsset::Hash256Preimage(hash) => write!(f, "hash256 preimage of {}", hash),
+            MissingAsset::Ripemd160Preimage(hash) => write!(f, "ripemd160 preimage of {}", hash),
+            MissingAsset::Hash160Preimage(hash) => write!(f, "hash160 preimage of {}", hash),
+            MissingAsset::AbsoluteTimelock(t) => write!(f, "absolute timelock {}", t),
+            MissingAsset::RelativeTimelock(t) => write!(f, "relative timelock {}", t),
+        }
+    }
+}
+
+/// The assets missing to satisfy each candidate spending path of a descriptor or Miniscript
+///
+/// Each path is given by the minimal set of assets which, added to those already available,
+/// would allow it to be satisfied. Paths which need a superset of the assets of another path
+/// are omitted, as are paths beyond the first [`MAX_MISSING_ASSET_PATHS`], ordered by the
+/// number of missing assets.
+///
+/// A path with no missing assets means the provided assets are sufficient. If a plan still
+/// cannot be made in that case, it is because the only satisfactions are malleable or combine
+/// incompatible timelocks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MissingAssets<Pk: MiniscriptKey> {
+    paths: Vec<BTreeSet<MissingAsset<Pk>>>,
+}
+
+impl<Pk: MiniscriptKey> MissingAssets<Pk> {
+    /// The assets missing for each spending path, with the fewest missing assets first.
+    ///
+    /// If there are no paths, the script cannot be satisfied whatever assets are provided.
+    pub fn paths(&self) -> &[BTreeSet<MissingAsset<Pk>>] { &self.paths }
+
+    /// Converts into the assets missing for each spending path.
+    pub fn into_paths(self) -> Vec<BTreeSet<MissingAsset<Pk>>> { self.paths }
+
+    /// A fragment which can never be satisfied.
+    pub(crate) fn impossible() -> Self { MissingAssets { paths: vec![] } }
+
+    /// A fragment which can be satisfied without any further assets.
+    pub(crate) fn available() -> Self { MissingAssets { paths: vec![BTreeSet::new()] } }
+
+    /// A fragment which can be satisfied using a single asset.
+    pub(crate) fn requires(asset: MissingAsset<Pk>, available: bool) -> Self {
+        if available {
+            Self::available()
+        } else {
+            MissingAssets { paths: vec![BTreeSet::from([asset])] }
+        }
+    }
+
+    /// Combines two fragments which must both be satisfied.
+    pub(crate) fn and(self, other: Self) -> Self {
+        let mut paths = Vec::with_capacity(self.paths.len() * other.paths.len());
+        for left in &self.paths {
+            for right in &other.paths {
+                paths.push(left.union(right).cloned().collect());
+            }
+        }
+        Self::minimize(paths)
+    }
+
+    /// Combines two fragments either of which may be satisfied.
+    pub(crate) fn or(mut self, other: Self) -> Self {
+        self.paths.extend(other.paths);
+        Self::minimize(self.paths)
+    }
+
+    /// Combines fragments of which `k` must be satisfied.
+    pub(crate) fn thresh<I: IntoIterator<Item = Self>>(k: usize, subs: I) -> Self {
+        // `by_count[i]` holds the paths which satisfy exactly `i` of the fragments seen so far.
+        let mut by_count = vec![Self::impossible(); k + 1];
+        by_count[0] = Self::available();
+        for sub in subs {
+            for i in (0..k).rev() {
+                let extended = by_count[i].clone().and(sub.clone());
+                by_count[i + 1] = by_count[i + 1].clone().or(extended);
+            }
+        }
+        by_count.pop().expect("k + 1 > 0")
+    }
+
+    /// Removes redundant paths, and all but the first [`MAX_MISSING_ASSET_PATHS`].
+    fn minimize(mut paths: Vec<BTreeSet<MissingAsset<Pk>>>) -> Self {
+        paths.sort_by_key(BTreeSet::len);
+        let mut ret: Vec<BTreeSet<MissingAsset<Pk>>> = vec![];
+        for path in paths {
+            if ret.len() == MAX_MISSING_ASSET_PATHS {
+                break;
+            }
+            if !ret.iter().any(|kept| kept.is_subset(&path)) {
+                ret.push(path);
+            }
+        }
+        MissingAssets { paths: ret }
+    }
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for MissingAssets<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.paths.is_empty() {
+            return f.write_str("no spending path can be satisfied");
+        }
+        f.write_str("missing assets: ")?;
+        for (i, path) in self.paths.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" or ")?;
+            }
+            f.write_str("{")?;
+            for (j, asset) in path.iter().enumerate() {
+                if j > 0 {
+                    f.write_str(", ")?;
+                }
+                fmt::Display::fmt(asset, f)?;
+            }
+            f.write_str("}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<Pk: MiniscriptKey> std::error::Error for MissingAssets<Pk> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> { None }
+}
+
+/// The cheapest key spend and script spend plans of a taproot descriptor
+///
+/// Returned by [`Descriptor::plan_taproot_spends`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TaprootPlans {
+    /// The key spend plan, if the internal key can sign
+    pub key_spend: Option<Plan>,
+    /// The cheapest script spend plan, if any leaf can be satisfied
+    pub script_spend: Option<Plan>,
+}
+
+impl TaprootPlans {
+    /// The cheapest of the two plans, preferring the key spend
+    pub fn cheapest(&self) -> Option<&Plan> {
+        match (&self.key_spend, &self.script_spend) {
+            (Some(key), Some(script)) => {
+                if script.satisfaction_weight() < key.satisfaction_weight() {
+                    Some(script)
+                } else {
+                    Some(key)
+                }
+            }
+            (key, script) => key.as_ref().or(script.as_ref()),
+        }
+    }
+
+    /// How much more the script spend costs than the key spend at `feerate`
+    ///
+    /// Returns `None` unless both spends are possible. A script spend is never cheaper than a
+    /// key spend, whose witness is a single signature.
+    pub fn script_spend_premium(&self, feerate: FeeRate) -> Option<Amount> {
+        let key = self.key_spend.as_ref()?.satisfaction_fee(feerate);
+        let script = self.script_spend.as_ref()?.satisfaction_fee(feerate);
+        Some(script.checked_sub(key).unwrap_or(Amount::ZERO))
+    }
+}
+
+/// Restricts an [`AssetProvider`] to the assets of a single spending path
+///
+/// Used to plan each spending path separately in [`Descriptor::plan_all`].
+pub(crate) struct PathAssets<'a, P> {
+    pub(crate) provider: &'a P,
+    pub(crate) path: &'a BTreeSet<MissingAsset<DefiniteDescriptorKey>>,
+}
+
+impl<P> PathAssets<'_, P> {
+    fn has_sig(&self, pk: &DefiniteDescriptorKey) -> bool {
+        self.path.contains(&MissingAsset::Signature(pk.clone()))
+    }
+
+    fn has_pkh_sig(&self, hash: &hash160::Hash) -> bool {
+        self.path.contains(&MissingAsset::KeyHashSignature(*hash))
+    }
+}
+
+impl<P: AssetProvider<DefiniteDescriptorKey>> AssetProvider<DefiniteDescriptorKey>
+    for PathAssets<'_, P>
+{
+    fn provider_lookup_ecdsa_sig(&self, pk: &DefiniteDescriptorKey) -> bool {
+        self.has_sig(pk) && self.provider.provider_lookup_ecdsa_sig(pk)
+    }
+
+    fn provider_lookup_tap_key_spend_sig(&self, pk: &DefiniteDescriptorKey) -> Option<usize> {
+        self.provider
+            .provider_lookup_tap_key_spend_sig(pk)
+            .filter(|_| self.has_sig(pk))
+    }
+
+    fn provider_lookup_tap_leaf_script_sig(
+        &self,
+        pk: &DefiniteDescriptorKey,
+        leaf_hash: &TapLeafHash,
+    ) -> Option<usize> {
+        self.provider
+            .provider_lookup_tap_leaf_script_sig(pk, leaf_hash)
+            .filter(|_| self.has_sig(pk))
+    }
+
+    fn provider_lookup_tap_control_block_map(
+        &self,
+    ) -> Option<&BTreeMap<ControlBlock, (bitcoin::ScriptBuf, LeafVersion)>> {
+        self.provider.provider_lookup_tap_control_block_map()
+    }
+
+    fn provider_lookup_raw_pkh_pk(&self, hash: &hash160::Hash) -> Option<bitcoin::PublicKey> {
+        self.provider.provider_lookup_raw_pkh_pk(hash)
+    }
+
+    fn provider_lookup_raw_pkh_x_only_pk(&self, hash: &hash160::Hash) -> Option<XOnlyPublicKey> {
+        self.provider.provider_lookup_raw_pkh_x_only_pk(hash)
+    }
+
+    fn provider_lookup_raw_pkh_ecdsa_sig(
+        &self,
+        hash: &hash160::Hash,
+    ) -> Option<bitcoin::PublicKey> {
+        self.provider
+            .provider_lookup_raw_pkh_ecdsa_sig(hash)
+            .filter(|_| self.has_pkh_sig(hash))
+    }
+
+    fn provider_lookup_raw_pkh_tap_leaf_script_sig(
+        &self,
+        hash: &(hash160::Hash, TapLeafHash),
+    ) -> Option<(XOnlyPublicKey, usize)> {
+        self.provider
+            .provider_lookup_raw_pkh_tap_leaf_script_sig(hash)
+            .filter(|_| self.has_pkh_sig(&hash.0))
+    }
+
+    fn provider_lookup_sha256(&self, hash: &sha256::Hash) -> bool {
+        self.path.contains(&MissingAsset::Sha256Preimage(*hash))
+            && self.provider.provider_lookup_sha256(hash)
+    }
+
+    fn provider_lookup_hash256(&self, hash: &hash256::Hash) -> bool {
+        self.path.contains(&MissingAsset::Hash256Preimage(*hash))
+            && self.provider.provider_lookup_hash256(hash)
+    }
+
+    fn provider_lookup_ripemd160(&self, hash: &ripemd160::Hash) -> bool {
+        self.path.contains(&MissingAsset::Ripemd160Preimage(*hash))
+            && self.provider.provider_lookup_ripemd160(hash)
+    }
+
+    fn provider_lookup_hash160(&self, hash: &hash160::Hash) -> bool {
+        self.path.contains(&MissingAsset::Hash160Preimage(*hash))
+            && self.provider.provider_lookup_hash160(hash)
+    }
+
+    fn provider_lookup_pending_sha256(&self, hash: &sha256::Hash) -> bool {
+        self.path.contains(&MissingAsset::Sha256Preimage(*hash))
+            && self.provider.provider_lookup_pending_sha256(hash)
+    }
+
+    fn provider_lookup_pending_hash256(&self, hash: &hash256::Hash) -> bool {
+        self.path.contains(&MissingAsset::Hash256Preimage(*hash))
+            && self.provider.provider_lookup_pending_hash256(hash)
+    }
+
+    fn provider_lookup_pending_ripemd160(&self, hash: &ripemd160::Hash) -> bool {
+        self.path.contains(&MissingAsset::Ripemd160Preimage(*hash))
+            && self.provider.provider_lookup_pending_ripemd160(hash)
+    }
+
+    fn provider_lookup_pending_hash160(&self, hash: &hash160::Hash) -> bool {
+        self.path.contains(&MissingAsset::Hash160Preimage(*hash))
+            && self.provider.provider_lookup_pending_hash160(hash)
+    }
+
+    fn check_older(&self, s: relative::LockTime) -> bool {
+        self.path.iter().any(|asset| match asset {
+            MissingAsset::RelativeTimelock(t) => relative::LockTime::from(*t) == s,
+            _ => false,
+        }) && self.provider.check_older(s)
+    }
+
+    fn check_after(&self, l: absolute::LockTime) -> bool {
+        self.path.iter().any(|asset| match asset {
+            MissingAsset::AbsoluteTimelock(t) => absolute::LockTime::from(*t) == l,
+            _ => false,
+        }) && self.provider.check_after(l)
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 /// Signatures which a key can produce
 ///
@@ -505,7 +1207,7 @@ impl TaprootAvailableLeaves {
 }
 
 /// The Assets we can use to satisfy a particular spending path
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct Assets {
     /// Keys the user can sign for, and how.
     ///
@@ -515,6 +1217,8 @@ pub struct Assets {
     /// by exactly one child number. For example, if the derivation path `m/0/1` is provided, the
     /// user can sign with either `m/0/1` or `m/0/1/*`.
     pub keys: BTreeSet<(bip32::KeySource, CanSign)>,
+    /// Ranges of keys the user can sign for, and how.
+    pub key_ranges: BTreeSet<KeyRange>,
     /// Set of available sha256 preimages
     pub sha256_preimages: BTreeSet<sha256::Hash>,
     /// Set of available hash256 preimages
@@ -523,12 +1227,56 @@ pub struct Assets {
     pub ripemd160_preimages: BTreeSet<ripemd160::Hash>,
     /// Set of available hash160 preimages
     pub hash160_preimages: BTreeSet<hash160::Hash>,
+    /// Set of sha256 hashes whose preimages can be obtained on demand
+    pub pending_sha256_preimages: BTreeSet<sha256::Hash>,
+    /// Set of hash256 hashes whose preimages can be obtained on demand
+    pub pending_hash256_preimages: BTreeSet<hash256::Hash>,
+    /// Set of ripemd160 hashes whose preimages can be obtained on demand
+    pub pending_ripemd160_preimages: BTreeSet<ripemd160::Hash>,
+    /// Set of hash160 hashes whose preimages can be obtained on demand
+    pub pending_hash160_preimages: BTreeSet<hash160::Hash>,
     /// Maximum absolute timelock allowed
     pub absolute_timelock: Option<absolute::LockTime>,
     /// Maximum relative timelock allowed
     pub relative_timelock: Option<relative::LockTime>,
 }
 
+/// The keys derived from an extended key at a range of child numbers
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct KeyRange {
+    /// The fingerprint of the master key and the full derivation path of the parent key.
+    pub parent: bip32::KeySource,
+    /// The first child number included in the range.
+    pub start: u32,
+    /// The first child number after the range.
+    pub end: u32,
+    /// Whether the child numbers are hardened.
+    pub hardened: bool,
+    /// The signatures the keys can produce.
+    pub can_sign: CanSign,
+}
+
+impl KeyRange {
+    /// Whether `pk` is a child of the parent key at a child number in the range.
+    fn contains(&self, pk: &DefiniteDescriptorKey) -> bool {
+        if pk.master_fingerprint() != self.parent.0 {
+            return false;
+        }
+        pk.full_derivation_paths().iter().any(|path| {
+            let (last, parent_path) = match path.as_ref().split_last() {
+                Some(split) => split,
+                None => return false,
+            };
+            let index = match (*last, self.hardened) {
+                (bip32::ChildNumber::Normal { index }, false)
+                | (bip32::ChildNumber::Hardened { index }, true) => index,
+                _ => return false,
+            };
+            parent_path == self.parent.1.as_ref() && (self.start..self.end).contains(&index)
+        })
+    }
+}
+
 // Checks if the `pk` is a "direct child" of the `derivation_path` provided.
 // Direct child means that the key derivation path is either the same as the
 // `derivation_path`, or the same extened by exactly one child number.
@@ -553,25 +1301,39 @@ fn is_key_direct_child_of(
 }
 
 impl Assets {
+    /// The ways in which the assets can sign for `pk`.
+    fn signers_for<'a>(
+        &'a self,
+        pk: &'a DefiniteDescriptorKey,
+    ) -> impl Iterator<Item = &'a CanSign> + 'a {
+        let keys = self.keys.iter().filter_map(move |(keysource, can_sign)| {
+            if pk.master_fingerprint() == keysource.0 && is_key_direct_child_of(pk, &keysource.1) {
+                Some(can_sign)
+            } else {
+                None
+            }
+        });
+        let ranges = self
+            .key_ranges
+            .iter()
+            .filter(move |range| range.contains(pk))
+            .map(|range| &range.can_sign);
+        keys.chain(ranges)
+    }
+
+    /// Whether the assets can sign for `pk` in any way.
+    pub(crate) fn has_key(&self, pk: &DefiniteDescriptorKey) -> bool {
+        self.signers_for(pk).next().is_some()
+    }
+
     pub(crate) fn has_ecdsa_key(&self, pk: &DefiniteDescriptorKey) -> bool {
-        self.keys.iter().any(|(keysource, can_sign)| {
-            can_sign.ecdsa
-                && pk.master_fingerprint() == keysource.0
-                && is_key_direct_child_of(pk, &keysource.1)
-        })
+        self.signers_for(pk).any(|can_sign| can_sign.ecdsa)
     }
 
     pub(crate) fn has_taproot_internal_key(&self, pk: &DefiniteDescriptorKey) -> Option<usize> {
-        self.keys.iter().find_map(|(keysource, can_sign)| {
-            if !can_sign.taproot.key_spend
-                || pk.master_fingerprint() != keysource.0
-                || !is_key_direct_child_of(pk, &keysource.1)
-            {
-                None
-            } else {
-                Some(can_sign.taproot.sig_len())
-            }
-        })
+        self.signers_for(pk)
+            .find(|can_sign| can_sign.taproot.key_spend)
+            .map(|can_sign| can_sign.taproot.sig_len())
     }
 
     pub(crate) fn has_taproot_script_key(
@@ -579,16 +1341,9 @@ impl Assets {
         pk: &DefiniteDescriptorKey,
         tap_leaf_hash: &TapLeafHash,
     ) -> Option<usize> {
-        self.keys.iter().find_map(|(keysource, can_sign)| {
-            if !can_sign.taproot.script_spend.is_available(tap_leaf_hash)
-                || pk.master_fingerprint() != keysource.0
-                || !is_key_direct_child_of(pk, &keysource.1)
-            {
-                None
-            } else {
-                Some(can_sign.taproot.sig_len())
-            }
-        })
+        self.signers_for(pk)
+            .find(|can_sign| can_sign.taproot.script_spend.is_available(tap_leaf_hash))
+            .map(|can_sign| can_sign.taproot.sig_len())
     }
 }
 
@@ -625,6 +1380,22 @@ impl AssetProvider<DefiniteDescriptorKey> for Assets {
         self.hash160_preimages.contains(hash)
     }
 
+    fn provider_lookup_pending_sha256(&self, hash: &sha256::Hash) -> bool {
+        self.pending_sha256_preimages.contains(hash)
+    }
+
+    fn provider_lookup_pending_hash256(&self, hash: &hash256::Hash) -> bool {
+        self.pending_hash256_preimages.contains(hash)
+    }
+
+    fn provider_lookup_pending_ripemd160(&self, hash: &ripemd160::Hash) -> bool {
+        self.pending_ripemd160_preimages.contains(hash)
+    }
+
+    fn provider_lookup_pending_hash160(&self, hash: &hash160::Hash) -> bool {
+        self.pending_hash160_preimages.contains(hash)
+    }
+
     fn check_older(&self, s: relative::LockTime) -> bool {
         if let Some(timelock) = self.relative_timelock {
             s.is_implied_by(timelock)
@@ -711,6 +1482,82 @@ impl Assets {
         self
     }
 
+    /// Constructs assets which can sign for every key of `descriptor`
+    ///
+    /// Keys with a wildcard can only sign at a derivation index in `indices`, so that
+    /// planning against a ranged descriptor does not require deriving each of its keys.
+    pub fn from_descriptor_keys(
+        descriptor: &Descriptor<DescriptorPublicKey>,
+        indices: core::ops::Range<u32>,
+    ) -> Self {
+        let mut assets = Assets::new();
+        descriptor.for_each_key(|pk| {
+            let wildcard = match pk {
+                DescriptorPublicKey::Single(_) => descriptor::Wildcard::None,
+                DescriptorPublicKey::XPub(xpub) => xpub.wildcard,
+                DescriptorPublicKey::MultiXPub(xpub) => xpub.wildcard,
+            };
+            let hardened = match wildcard {
+                descriptor::Wildcard::None => {
+                    assets.append(pk.clone().into_assets());
+                    return true;
+                }
+                descriptor::Wildcard::Unhardened => false,
+                descriptor::Wildcard::Hardened => true,
+            };
+            for path in pk.full_derivation_paths() {
+                assets.key_ranges.insert(KeyRange {
+                    parent: (pk.master_fingerprint(), path),
+                    start: indices.start,
+                    end: indices.end,
+                    hardened,
+                    can_sign: CanSign::default(),
+                });
+            }
+            true
+        });
+        assets
+    }
+
+    /// Add any key derived from the `parent` key at an unhardened child number in `indices`
+    pub fn key_range(mut self, parent: bip32::KeySource, indices: core::ops::Range<u32>) -> Self {
+        self.key_ranges.insert(KeyRange {
+            parent,
+            start: indices.start,
+            end: indices.end,
+            hardened: false,
+            can_sign: CanSign::default(),
+        });
+        self
+    }
+
+    /// Add a sha256 hash whose preimage isn't known yet, but can be obtained on demand
+    ///
+    /// Plans use a [`Placeholder::PendingSha256Preimage`] for it, so that they can be made
+    /// before the preimage is revealed.
+    pub fn pending_sha256(mut self, hash: sha256::Hash) -> Self {
+        self.pending_sha256_preimages.insert(hash);
+        self
+    }
+
+    /// Add a hash256 hash whose preimage isn't known yet, but can be obtained on demand
+    pub fn pending_hash256(mut self, hash: hash256::Hash) -> Self {
+        self.pending_hash256_preimages.insert(hash);
+        self
+    }
+
+    /// Add a ripemd160 hash whose preimage isn't known yet, but can be obtained on demand
+    pub fn pending_ripemd160(mut self, hash: ripemd160::Hash) -> Self {
+        self.pending_ripemd160_preimages.insert(hash);
+        self
+    }
+
+    /// Add a hash160 hash whose preimage isn't known yet, but can be obtained on demand
+    pub fn pending_hash160(mut self, hash: hash160::Hash) -> Self {
+        self.pending_hash160_preimages.insert(hash);
+        self
+    }
+
     /// Set the maximum relative timelock allowed
     pub fn older(mut self, seq: relative::LockTime) -> Self {
         self.relative_timelock = Some(seq);
@@ -725,16 +1572,429 @@ impl Assets {
 
     fn append(&mut self, b: Self) {
         self.keys.extend(b.keys);
+        self.key_ranges.extend(b.key_ranges);
         self.sha256_preimages.extend(b.sha256_preimages);
         self.hash256_preimages.extend(b.hash256_preimages);
         self.ripemd160_preimages.extend(b.ripemd160_preimages);
         self.hash160_preimages.extend(b.hash160_preimages);
+        self.pending_sha256_preimages
+            .extend(b.pending_sha256_preimages);
+        self.pending_hash256_preimages
+            .extend(b.pending_hash256_preimages);
+        self.pending_ripemd160_preimages
+            .extend(b.pending_ripemd160_preimages);
+        self.pending_hash160_preimages
+            .extend(b.pending_hash160_preimages);
 
         self.relative_timelock = b.relative_timelock.or(self.relative_timelock);
         self.absolute_timelock = b.absolute_timelock.or(self.absolute_timelock);
     }
 }
 
+/// A position in the chain, given by the height and median time past (MTP) of a block
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ChainTime {
+    /// The block height
+    pub height: absolute::Height,
+    /// The median time past of the block
+    pub mtp: absolute::Time,
+}
+
+impl ChainTime {
+    /// Creates a new chain position
+    pub fn new(height: absolute::Height, mtp: absolute::Time) -> Self { ChainTime { height, mtp } }
+
+    /// The largest absolute timelocks, of each unit, which a transaction included in the block
+    /// after this one can satisfy
+    pub(crate) fn absolute_timelocks(&self) -> Vec<absolute::LockTime> {
+        // The locktime must be lower than the height of the next block, or the MTP of this one
+        let mut ret = vec![absolute::LockTime::from(self.height)];
+        if let Ok(time) = absolute::Time::from_consensus(self.mtp.to_consensus_u32() - 1) {
+            ret.push(time.into());
+        }
+        ret
+    }
+
+    /// The largest relative timelocks, of each unit, which an input spending an output
+    /// confirmed at `confirmation` can satisfy in the block after this one
+    ///
+    /// As in BIP 68, the `mtp` of `confirmation` is that of the block before the one
+    /// confirming the output.
+    pub(crate) fn relative_timelocks(&self, confirmation: ChainTime) -> Vec<relative::LockTime> {
+        let mut ret = vec![];
+        let height = self.height.to_consensus_u32() + 1;
+        if let Some(blocks) = height.checked_sub(confirmation.height.to_consensus_u32()) {
+            let blocks = u16::try_from(blocks).unwrap_or(u16::MAX);
+            ret.push(relative::LockTime::from_height(blocks));
+        }
+        let mtp = self.mtp.to_consensus_u32();
+        if let Some(seconds) = mtp.checked_sub(confirmation.mtp.to_consensus_u32()) {
+            let intervals = u16::try_from(seconds / 512).unwrap_or(u16::MAX);
+            ret.push(relative::LockTime::from_512_second_intervals(intervals));
+        }
+        ret
+    }
+}
+
+/// The earliest chain tip at which a spending path can be used
+///
+/// Returned by [`Descriptor::unlock_times`]. The path can be used in the block after a tip
+/// which reaches both `height` and `mtp`, where present. If neither is present, it can be used
+/// right away.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct UnlockTime {
+    /// The height the chain tip must reach
+    pub height: Option<absolute::Height>,
+    /// The median time past the chain tip must reach
+    pub mtp: Option<absolute::Time>,
+}
+
+impl UnlockTime {
+    /// Returns the earliest time at which the given timelocks are all satisfied, or `None` if
+    /// it can't be represented or relative timelocks are used with no `confirmation`.
+    pub(crate) fn of_timelocks<'a, I>(timelocks: I, confirmation: Option<ChainTime>) -> Option<Self>
+    where
+        I: IntoIterator<Item = &'a MissingAsset<DefiniteDescriptorKey>>,
+    {
+        let mut height = None;
+        let mut mtp = None;
+        for asset in timelocks {
+            match *asset {
+                MissingAsset::AbsoluteTimelock(lt) => match absolute::LockTime::from(lt) {
+                    absolute::LockTime::Blocks(h) => height = cmp::max(height, Some(h)),
+                    absolute::LockTime::Seconds(t) => {
+                        let t = t.to_consensus_u32().checked_add(1)?;
+                        let t = absolute::Time::from_consensus(t).ok()?;
+                        mtp = cmp::max(mtp, Some(t));
+                    }
+                },
+                MissingAsset::RelativeTimelock(lt) => {
+                    let confirmation = confirmation?;
+                    match relative::LockTime::from(lt) {
+                        relative::LockTime::Blocks(h) => {
+                            let h = confirmation.height.to_consensus_u32() + u32::from(h.value());
+                            let h = absolute::Height::from_consensus(h - 1).ok()?;
+                            height = cmp::max(height, Some(h));
+                        }
+                        relative::LockTime::Time(t) => {
+                            let t = confirmation
+                                .mtp
+                                .to_consensus_u32()
+                                .checked_add(u32::from(t.value()) * 512)?;
+                            let t = absolute::Time::from_consensus(t).ok()?;
+                            mtp = cmp::max(mtp, Some(t));
+                        }
+                    }
+                }
+                _ => return None,
+            }
+        }
+        Some(UnlockTime { height, mtp })
+    }
+
+    /// Whether this is no later than `other`
+    pub(crate) fn no_later_than(&self, other: &Self) -> bool {
+        fn le<T: Ord>(a: Option<T>, b: Option<T>) -> bool {
+            match (a, b) {
+                (Some(a), Some(b)) => a <= b,
+                (Some(_), None) => false,
+                (None, _) => true,
+            }
+        }
+        le(self.height, other.height) && le(self.mtp, other.mtp)
+    }
+}
+
+/// Maximum number of search steps taken by [`select_plans`] before it returns the best
+/// selection found so far.
+pub const MAX_SELECTION_STEPS: usize = 100_000;
+
+/// A coin which [`select_plans`] may spend
+#[derive(Clone, Debug)]
+pub struct SpendCandidate {
+    /// The descriptor of the output being spent
+    pub descriptor: Descriptor<DefiniteDescriptorKey>,
+    /// The assets available to spend it
+    pub assets: Assets,
+    /// The value of the output
+    pub value: Amount,
+}
+
+/// A set of inputs, and the plan to spend each of them, selected by [`select_plans`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlanSelection {
+    /// The index of each selected candidate, with the plan chosen to spend it
+    pub inputs: Vec<(usize, Plan)>,
+    /// The total weight added to the transaction by the selected inputs
+    pub weight: Weight,
+    /// The total value of the selected inputs
+    pub value: Amount,
+    /// The fee paid for the weight of the selected inputs
+    pub fee: Amount,
+    /// The `nLockTime` required by the selected plans, if any
+    pub locktime: Option<absolute::LockTime>,
+}
+
+/// Error selecting inputs with [`select_plans`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SelectionError {
+    /// The candidates which can be spent are not worth enough to pay for the target and
+    /// their own fees.
+    InsufficientFunds {
+        /// The target amount.
+        target: Amount,
+        /// The total value of the spendable candidates, net of the fees to spend them.
+        available: Amount,
+    },
+    /// No selection was found within [`MAX_SELECTION_STEPS`], e.g. because the plans of
+    /// candidates require absolute timelocks of different units.
+    NoSolution,
+}
+
+impl fmt::Display for SelectionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SelectionError::InsufficientFunds { target, available } => write!(
+                f,
+                "insufficient funds: target is {} but only {} is available after fees",
+                target, available
+            ),
+            SelectionError::NoSolution => f.write_str("no compatible selection of inputs found"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SelectionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> { None }
+}
+
+/// A way of spending a candidate, as considered by [`select_plans`].
+struct SpendOption {
+    plan: Plan,
+    weight: Weight,
+    effective_value: Amount,
+}
+
+/// Search state of [`select_plans`].
+struct SelectionSearch<'a> {
+    options: &'a [(usize, Vec<SpendOption>)],
+    // `remaining[i]` is the largest effective value obtainable from `options[i..]`
+    remaining: Vec<Amount>,
+    target: Amount,
+    steps: usize,
+    current: Vec<(usize, usize)>,
+    best: Option<(Weight, Vec<(usize, usize)>)>,
+}
+
+impl SelectionSearch<'_> {
+    fn search(
+        &mut self,
+        i: usize,
+        weight: Weight,
+        value: Amount,
+        locktime: Option<absolute::LockTime>,
+    ) {
+        if self.steps == MAX_SELECTION_STEPS {
+            return;
+        }
+        self.steps += 1;
+        if self
+            .best
+            .as_ref()
+            .map_or(false, |(best, _)| weight >= *best)
+        {
+            return;
+        }
+        if value >= self.target {
+            self.best = Some((weight, self.current.clone()));
+            return;
+        }
+        if i == self.options.len() || value + self.remaining[i] < self.target {
+            return;
+        }
+        for (j, option) in self.options[i].1.iter().enumerate() {
+            let locktime = match (locktime, option.plan.absolute_timelock) {
+                (Some(lt), Some(required)) if !lt.is_same_unit(required) => continue,
+                (lt, required) => lt.or(required),
+            };
+            self.current.push((i, j));
+            self.search(i + 1, weight + option.weight, value + option.effective_value, locktime);
+            self.current.pop();
+        }
+        self.search(i + 1, weight, value, locktime);
+    }
+}
+
+/// Selects which candidates to spend, and how, to fund `target` at `feerate`
+///
+/// Each candidate may be spent by any of the plans of [`Descriptor::plan_all`]. Out of the
+/// selections whose value, net of the fees for their own weight, covers `target`, the one with
+/// the lowest total weight is returned. Plans requiring absolute timelocks of different units
+/// are never combined. `target` should include the value of the outputs and the fee for the rest
+/// of the transaction, including the segwit marker and flag: inputs are weighed as in a segwit
+/// transaction (see [`Plan::marginal_weight`]).
+///
+/// The search is exhaustive for a small number of candidates, but stops after
+/// [`MAX_SELECTION_STEPS`], returning the best selection found until then.
+pub fn select_plans(
+    candidates: &[SpendCandidate],
+    target: Amount,
+    feerate: FeeRate,
+) -> Result<PlanSelection, SelectionError> {
+    let mut options: Vec<(usize, Vec<SpendOption>)> = candidates
+        .iter()
+        .enumerate()
+        .map(|(i, candidate)| {
+            let options: Vec<_> = candidate
+                .descriptor
+                .plan_all(&candidate.assets)
+                .into_iter()
+                .filter_map(|plan| {
+                    let weight = plan.marginal_weight(true);
+                    // Inputs worth less than their own fee never help
+                    let effective_value = candidate.value.checked_sub(weight * feerate)?;
+                    Some(SpendOption { plan, weight, effective_value })
+                })
+                .collect();
+            (i, options)
+        })
+        .filter(|(_, options)| !options.is_empty())
+        .collect();
+    // Trying the most valuable candidates first finds a selection sooner
+    let max_value = |options: &[SpendOption]| {
+        options
+            .iter()
+            .map(|option| option.effective_value)
+            .max()
+            .unwrap_or(Amount::ZERO)
+    };
+    options.sort_by_key(|(_, options)| core::cmp::Reverse(max_value(options)));
+
+    let mut remaining = vec![Amount::ZERO; options.len() + 1];
+    for i in (0..options.len()).rev() {
+        remaining[i] = remaining[i + 1] + max_value(&options[i].1);
+    }
+    if remaining[0] < target {
+        return Err(SelectionError::InsufficientFunds { target, available: remaining[0] });
+    }
+
+    let mut search = SelectionSearch {
+        options: &options,
+        remaining,
+        target,
+        steps: 0,
+        current: vec![],
+        best: None,
+    };
+    search.search(0, Weight::ZERO, Amount::ZERO, None);
+    let (weight, selected) = search.best.ok_or(SelectionError::NoSolution)?;
+
+    let mut inputs: Vec<(usize, Plan)> = selected
+        .into_iter()
+        .map(|(i, j)| (options[i].0, options[i].1[j].plan.clone()))
+        .collect();
+    inputs.sort_by_key(|(index, _)| *index);
+    let value = inputs
+        .iter()
+        .map(|(index, _)| candidates[*index].value)
+        .sum();
+    let locktime = inputs
+        .iter()
+        .filter_map(|(_, plan)| plan.absolute_timelock)
+        .max_by_key(|lt| lt.to_consensus_u32());
+    Ok(PlanSelection { inputs, weight, value, fee: weight * feerate, locktime })
+}
+
+#[cfg(feature = "serde")]
+const PLAN_FIELDS: &[&str] = &[
+    "descriptor",
+    "template",
+    "absolute_timelock",
+    "relative_timelock",
+];
+
+// Timelocks are encoded as their consensus values.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Plan {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut s = serializer.serialize_struct("Plan", 4)?;
+        s.serialize_field("descriptor", &self.descriptor)?;
+        s.serialize_field("template", &self.template)?;
+        s.serialize_field(
+            "absolute_timelock",
+            &self.absolute_timelock.map(|lt| lt.to_consensus_u32()),
+        )?;
+        s.serialize_field(
+            "relative_timelock",
+            &self.relative_timelock.map(|lt| lt.to_consensus_u32()),
+        )?;
+        s.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Plan {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::{self, MapAccess, SeqAccess, Visitor};
+
+        use crate::util::Identifier;
+
+        struct PlanVisitor;
+
+        fn build<E: de::Error>(
+            descriptor: Option<Descriptor<DefiniteDescriptorKey>>,
+            template: Option<Vec<Placeholder<DefiniteDescriptorKey>>>,
+            absolute_timelock: Option<u32>,
+            relative_timelock: Option<u32>,
+        ) -> Result<Plan, E> {
+            Ok(Plan {
+                descriptor: descriptor.ok_or_else(|| E::missing_field("descriptor"))?,
+                template: template.ok_or_else(|| E::missing_field("template"))?,
+                absolute_timelock: absolute_timelock.map(absolute::LockTime::from_consensus),
+                relative_timelock: relative_timelock
+                    .map(relative::LockTime::from_consensus)
+                    .transpose()
+                    .map_err(E::custom)?,
+            })
+        }
+
+        impl<'de> Visitor<'de> for PlanVisitor {
+            type Value = Plan;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a spending plan")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Plan, A::Error> {
+                let descriptor = seq.next_element()?;
+                let template = seq.next_element()?;
+                let absolute_timelock = seq.next_element()?.flatten();
+                let relative_timelock = seq.next_element()?.flatten();
+                build(descriptor, template, absolute_timelock, relative_timelock)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Plan, A::Error> {
+                let (mut descriptor, mut template) = (None, None);
+                let (mut absolute_timelock, mut relative_timelock) = (None, None);
+                while let Some(idx) = map.next_key_seed(Identifier(PLAN_FIELDS))? {
+                    match idx {
+                        0 => descriptor = Some(map.next_value()?),
+                        1 => template = Some(map.next_value()?),
+                        2 => absolute_timelock = map.next_value()?,
+                        _ => relative_timelock = map.next_value()?,
+                    }
+                }
+                build(descriptor, template, absolute_timelock, relative_timelock)
+            }
+        }
+
+        deserializer.deserialize_struct("Plan", PLAN_FIELDS, PlanVisitor)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::str::FromStr;
@@ -1066,6 +2326,305 @@ mod test {
         test_inner(&desc, keys, hashes, tests);
     }
 
+    #[test]
+    fn test_ordering() {
+        use crate::miniscript::satisfy::{Placeholder, SATISFACTION_ORDERING_VERSION};
+
+        // If any of these expectations change, `SATISFACTION_ORDERING_VERSION` must be bumped.
+        assert_eq!(SATISFACTION_ORDERING_VERSION, 1);
+
+        let keys = [
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+            "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+            "03500a2b48b0f66c8183cc0d6645ab21cc19c7fad8a33ff04d41c3ece54b0bc1c5",
+        ]
+        .map(|k| DefiniteDescriptorKey::from_str(k).unwrap());
+        let assets = |indices: &[usize]| {
+            Assets::new().add(
+                indices
+                    .iter()
+                    .map(|&i| keys[i].as_descriptor_public_key().clone())
+                    .collect::<Vec<_>>(),
+            )
+        };
+        let template_with = |desc: String, assets: Assets| {
+            Descriptor::<DefiniteDescriptorKey>::from_str(&desc)
+                .unwrap()
+                .plan(&assets)
+                .unwrap()
+                .witness_template()
+                .clone()
+        };
+        let template = |desc: String| template_with(desc, assets(&[0, 1, 2]));
+        let sig = |i: usize| Placeholder::EcdsaSigPk(keys[i].clone());
+
+        // or_b satisfies its left child
+        let desc = format!("wsh(or_b(pk({}),s:pk({})))", keys[0], keys[1]);
+        assert_eq!(template(desc)[..2], [Placeholder::PushZero, sig(0)]);
+
+        // thresh satisfies its earliest children
+        let desc = format!("wsh(thresh(2,pk({}),s:pk({}),s:pk({})))", keys[0], keys[1], keys[2]);
+        assert_eq!(template(desc)[..3], [Placeholder::PushZero, sig(1), sig(0)]);
+
+        // multi uses its earliest keys
+        let desc = format!("wsh(multi(2,{},{},{}))", keys[0], keys[1], keys[2]);
+        assert_eq!(template(desc)[..3], [Placeholder::PushZero, sig(0), sig(1)]);
+
+        // tr uses the last of the cheapest leaves, if the key spend is unavailable
+        let desc = format!("tr({},{{pk({}),pk({})}})", keys[0], keys[1], keys[2]);
+        let leaf = Miniscript::<DefiniteDescriptorKey, Tap>::from_str(&format!("pk({})", keys[2]))
+            .unwrap()
+            .encode();
+        assert!(template_with(desc, assets(&[1, 2])).contains(&Placeholder::TapScript(leaf)));
+    }
+
+    #[test]
+    fn test_apply_timelocks() {
+        use bitcoin::transaction::Version;
+        use bitcoin::{Sequence, Transaction, TxIn};
+
+        let key = DescriptorPublicKey::from_str(
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+        )
+        .unwrap();
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(and_v(v:pk({}),and_v(v:older(144),after(1000))))",
+            key
+        ))
+        .unwrap();
+        let older = relative::LockTime::from_height(144);
+        let after = absolute::LockTime::from_height(1000).unwrap();
+        let assets = Assets::new().add(key.clone()).older(older).after(after);
+        let plan = desc.plan(&assets).unwrap();
+        assert_eq!(plan.required_locktime(), Some(after));
+        assert_eq!(plan.required_sequence(), Some(Sequence::from_height(144)));
+
+        let unsigned_tx = Transaction {
+            version: Version::ONE,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn::default(), TxIn::default()],
+            output: vec![],
+        };
+
+        let mut tx = unsigned_tx.clone();
+        plan.apply_timelocks(&mut tx, 1).unwrap();
+        assert_eq!(tx.version, Version::TWO);
+        assert_eq!(tx.lock_time, after);
+        assert_eq!(tx.input[0].sequence, Sequence::MAX);
+        assert_eq!(tx.input[1].sequence, Sequence::from_height(144));
+
+        // Higher values of the same unit are kept.
+        let mut tx = unsigned_tx.clone();
+        tx.lock_time = absolute::LockTime::from_height(2000).unwrap();
+        tx.input[0].sequence = Sequence::from_height(200);
+        plan.apply_timelocks(&mut tx, 0).unwrap();
+        assert_eq!(tx.lock_time, absolute::LockTime::from_height(2000).unwrap());
+        assert_eq!(tx.input[0].sequence, Sequence::from_height(200));
+
+        // Values of a different unit conflict, and leave the transaction unchanged.
+        let mut tx = unsigned_tx.clone();
+        tx.lock_time = absolute::LockTime::from_time(500_001_000).unwrap();
+        assert_eq!(
+            plan.apply_timelocks(&mut tx, 0),
+            Err(TimelockConflict::LockTime { required: after, existing: tx.lock_time })
+        );
+        assert_eq!(tx.input[0].sequence, Sequence::MAX);
+
+        let mut tx = unsigned_tx.clone();
+        tx.input[0].sequence = Sequence::from_512_second_intervals(10);
+        assert_eq!(
+            plan.apply_timelocks(&mut tx, 0),
+            Err(TimelockConflict::Sequence { required: older, existing: tx.input[0].sequence })
+        );
+        assert_eq!(
+            plan.apply_timelocks(&mut tx, 2),
+            Err(TimelockConflict::InputIndexOutOfBounds { index: 2, n_inputs: 2 })
+        );
+
+        // An absolute timelock alone only needs the input to be non-final.
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(and_v(v:pk({}),after(1000)))",
+            key
+        ))
+        .unwrap();
+        let plan = desc.plan(&assets).unwrap();
+        assert_eq!(plan.required_sequence(), None);
+        let mut txin = TxIn::default();
+        plan.apply_sequence(&mut txin).unwrap();
+        assert_eq!(txin.sequence, Sequence::ENABLE_LOCKTIME_NO_RBF);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_plan_serde() {
+        use serde_test::{assert_tokens, Token};
+
+        let key = "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c";
+        let pk = DescriptorPublicKey::from_str(key).unwrap();
+        let desc = format!("wsh(and_v(v:pk({}),older(144)))", key);
+        let older = relative::LockTime::from_height(144);
+        let plan = Descriptor::<DefiniteDescriptorKey>::from_str(&desc)
+            .unwrap()
+            .plan(&Assets::new().add(pk.clone()).older(older))
+            .unwrap();
+        assert_tokens(
+            &plan,
+            &[
+                Token::Struct { name: "Plan", len: 4 },
+                Token::Str("descriptor"),
+                Token::Str("wsh(and_v(v:pk(02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c),older(144)))#mfp7pzh3"),
+                Token::Str("template"),
+                Token::Seq { len: Some(1) },
+                Token::NewtypeVariant { name: "Placeholder", variant: "EcdsaSigPk" },
+                Token::Str(key),
+                Token::SeqEnd,
+                Token::Str("absolute_timelock"),
+                Token::None,
+                Token::Str("relative_timelock"),
+                Token::Some,
+                Token::U32(144),
+                Token::StructEnd,
+            ],
+        );
+
+        let xonly = &key[2..];
+        let plan = Descriptor::<DefiniteDescriptorKey>::from_str(&format!("tr({})", xonly))
+            .unwrap()
+            .plan(&Assets::new().add(DescriptorPublicKey::from_str(xonly).unwrap()))
+            .unwrap();
+        assert_tokens(
+            &plan,
+            &[
+                Token::Struct { name: "Plan", len: 4 },
+                Token::Str("descriptor"),
+                Token::Str(
+                    "tr(c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c)#vxpwlr4z",
+                ),
+                Token::Str("template"),
+                Token::Seq { len: Some(1) },
+                Token::NewtypeVariant { name: "Placeholder", variant: "SchnorrSigPk" },
+                Token::Tuple { len: 3 },
+                Token::Str(xonly),
+                Token::NewtypeVariant { name: "SchnorrSigType", variant: "KeySpend" },
+                Token::None,
+                Token::U64(64),
+                Token::TupleEnd,
+                Token::SeqEnd,
+                Token::Str("absolute_timelock"),
+                Token::None,
+                Token::Str("relative_timelock"),
+                Token::None,
+                Token::StructEnd,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_plan_requirements() {
+        let keys = [
+            DescriptorPublicKey::from_str(
+                "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+            )
+            .unwrap(),
+            DescriptorPublicKey::from_str(
+                "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+            )
+            .unwrap(),
+        ];
+        let hash = hash160::Hash::from_slice(&[0; 20]).unwrap();
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(and_v(v:pk({}),and_v(v:hash160({}),older(144))))",
+            keys[0], hash
+        ))
+        .unwrap();
+        let older = relative::LockTime::from_height(144);
+        let assets = Assets::new().add(keys[0].clone()).add(hash).older(older);
+        let reqs = desc.plan(&assets).unwrap().requirements();
+        assert_eq!(
+            reqs.signatures,
+            vec![RequiredSignature {
+                key: SignatureKey::Key(
+                    DefiniteDescriptorKey::from_str(&keys[0].to_string()).unwrap()
+                ),
+                leaf_hash: None,
+                sighash: RequiredSighash::Ecdsa,
+            }]
+        );
+        assert_eq!(reqs.hash160_preimages, vec![hash]);
+        assert!(reqs.sha256_preimages.is_empty());
+        assert_eq!(reqs.absolute_timelock, None);
+        assert_eq!(reqs.relative_timelock, Some(older));
+
+        // A script spend with a non-default sighash type
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "tr({},pk({}))",
+            keys[0], keys[1]
+        ))
+        .unwrap();
+        let leaf_hash = match desc {
+            Descriptor::Tr(ref tr) => {
+                let (_, ms) = tr.iter_scripts().next().unwrap();
+                TapLeafHash::from_script(&ms.encode(), LeafVersion::TapScript)
+            }
+            _ => unreachable!(),
+        };
+        let can_sign = CanSign {
+            ecdsa: false,
+            taproot: TaprootCanSign { sighash_default: false, ..Default::default() },
+        };
+        let mut assets = Assets::new();
+        let key_source = (keys[1].master_fingerprint(), bip32::DerivationPath::master());
+        assets.keys.insert((key_source, can_sign));
+        let reqs = desc.plan(&assets).unwrap().requirements();
+        assert_eq!(
+            reqs.signatures,
+            vec![RequiredSignature {
+                key: SignatureKey::Key(
+                    DefiniteDescriptorKey::from_str(&keys[1].to_string()).unwrap()
+                ),
+                leaf_hash: Some(leaf_hash),
+                sighash: RequiredSighash::SchnorrExplicit,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_assets_key_ranges() {
+        let xpub = "[abcdef00/84'/0'/0']xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL";
+        let desc =
+            Descriptor::<DescriptorPublicKey>::from_str(&format!("wpkh({}/0/*)", xpub)).unwrap();
+        let assets = Assets::from_descriptor_keys(&desc, 10..20);
+        assert!(assets.keys.is_empty());
+        assert_eq!(assets.key_ranges.len(), 1);
+        for (index, expected) in [(9, false), (10, true), (19, true), (20, false)] {
+            let plan = desc.at_derivation_index(index).unwrap().plan(&assets);
+            assert_eq!(plan.is_ok(), expected, "index {}", index);
+        }
+
+        // A single key is added as is
+        let key = "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c";
+        let desc = Descriptor::<DescriptorPublicKey>::from_str(&format!(
+            "wsh(and_v(v:pk({}/1/*),pk({})))",
+            xpub, key
+        ))
+        .unwrap();
+        let assets = Assets::from_descriptor_keys(&desc, 0..5);
+        assert_eq!(assets.keys.len(), 1);
+        assert!(desc.at_derivation_index(4).unwrap().plan(&assets).is_ok());
+        assert!(desc.at_derivation_index(5).unwrap().plan(&assets).is_err());
+
+        // Ranges can also be added directly
+        let parent = (
+            bip32::Fingerprint::from_str("abcdef00").unwrap(),
+            bip32::DerivationPath::from_str("m/84'/0'/0'/1").unwrap(),
+        );
+        let assets = Assets::new()
+            .key_range(parent, 5..6)
+            .add(DescriptorPublicKey::from_str(key).unwrap());
+        assert!(desc.at_derivation_index(5).unwrap().plan(&assets).is_ok());
+        assert!(desc.at_derivation_index(4).unwrap().plan(&assets).is_err());
+    }
+
     #[test]
     fn test_hash() {
         let keys = vec![DescriptorPublicKey::from_str(
@@ -1090,6 +2649,33 @@ mod test {
         test_inner(&desc, keys, hashes, tests);
     }
 
+    #[test]
+    fn test_pending_preimages() {
+        let key = DescriptorPublicKey::from_str(
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+        )
+        .unwrap();
+        let hash = sha256::Hash::hash(&[1; 32]);
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(and_v(v:pk({}),sha256({})))",
+            key, hash
+        ))
+        .unwrap();
+
+        let known = Assets::new().add(key.clone()).add(hash);
+        let known = desc.clone().plan(&known).unwrap();
+        let pending = Assets::new().add(key.clone()).pending_sha256(hash);
+        assert!(desc.missing_assets(&pending).paths()[0].is_empty());
+        let pending = desc.clone().plan(&pending).unwrap();
+
+        assert_eq!(pending.template[0], Placeholder::PendingSha256Preimage(hash));
+        assert_eq!(known.template[0], Placeholder::Sha256Preimage(hash));
+        assert_eq!(pending.template[1..], known.template[1..]);
+        assert_eq!(pending.satisfaction_weight(), known.satisfaction_weight());
+        assert_eq!(pending.requirements(), known.requirements());
+        assert!(desc.plan(&Assets::new().add(key)).is_err());
+    }
+
     #[test]
     fn test_plan_update_psbt_tr() {
         // keys taken from: https://github.com/bitcoin/bips/blob/master/bip-0086.mediawiki#Specifications
@@ -1159,4 +2745,336 @@ mod test {
         assert!(psbt_input.redeem_script.is_none(), "Redeem script present");
         assert_eq!(psbt_input.bip32_derivation.len(), 2, "Unexpected number of bip32_derivation");
     }
+
+    #[test]
+    fn test_missing_assets() {
+        let keys: Vec<DescriptorPublicKey> = [
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+            "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+            "03500a2b48b0f66c8183cc0d6645ab21cc19c7fad8a33ff04d41c3ece54b0bc1c5",
+        ]
+        .iter()
+        .map(|s| DescriptorPublicKey::from_str(s).unwrap())
+        .collect();
+        let sig =
+            |i: usize| MissingAsset::Signature(keys[i].clone().at_derivation_index(0).unwrap());
+        let set = |assets: Vec<MissingAsset<DefiniteDescriptorKey>>| {
+            assets.into_iter().collect::<BTreeSet<_>>()
+        };
+
+        // Any two of the three keys
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(multi(2,{},{},{}))",
+            keys[0], keys[1], keys[2]
+        ))
+        .unwrap();
+        let missing = desc.missing_assets(&Assets::new());
+        assert_eq!(
+            missing.paths(),
+            &[
+                set(vec![sig(0), sig(1)]),
+                set(vec![sig(0), sig(2)]),
+                set(vec![sig(1), sig(2)])
+            ]
+        );
+        let missing = desc
+            .clone()
+            .try_plan(&Assets::new().add(keys[1].clone()))
+            .unwrap_err();
+        assert_eq!(missing.paths(), &[set(vec![sig(0)]), set(vec![sig(2)])]);
+        assert!(desc
+            .try_plan(&Assets::new().add(keys[..2].to_vec()))
+            .is_ok());
+
+        // A timelocked recovery path
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(or_d(pk({}),and_v(v:pk({}),older(144))))",
+            keys[0], keys[1]
+        ))
+        .unwrap();
+        let missing = desc
+            .try_plan(&Assets::new().add(keys[1].clone()))
+            .unwrap_err();
+        let older = RelLockTime::from_height(144);
+        assert_eq!(
+            missing.paths(),
+            &[
+                set(vec![sig(0)]),
+                set(vec![MissingAsset::RelativeTimelock(older)])
+            ]
+        );
+        assert_eq!(
+            missing.to_string(),
+            format!(
+                "missing assets: {{signature for {}}} or {{relative timelock {}}}",
+                keys[0], older
+            )
+        );
+
+        // Key spend and script paths
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "tr({},and_v(v:pk({}),pk({})))",
+            keys[0], keys[1], keys[2]
+        ))
+        .unwrap();
+        let missing = desc.missing_assets(&Assets::new().add(keys[2].clone()));
+        assert_eq!(missing.paths(), &[set(vec![sig(0)]), set(vec![sig(1)])]);
+        assert!(desc
+            .missing_assets(&Assets::new().add(keys[0].clone()))
+            .paths()[0]
+            .is_empty());
+
+        // No assets can satisfy a script which always fails
+        let desc =
+            Descriptor::<DefiniteDescriptorKey>::from_str("wsh(and_v(v:after(100),0))").unwrap();
+        let missing = desc.try_plan(&Assets::new()).unwrap_err();
+        assert!(missing.paths().is_empty());
+        assert_eq!(missing.to_string(), "no spending path can be satisfied");
+    }
+
+    #[test]
+    fn test_plan_all() {
+        let keys: Vec<DescriptorPublicKey> = [
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+            "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+            "03500a2b48b0f66c8183cc0d6645ab21cc19c7fad8a33ff04d41c3ece54b0bc1c5",
+        ]
+        .iter()
+        .map(|s| DescriptorPublicKey::from_str(s).unwrap())
+        .collect();
+        let older = relative::LockTime::from_height(144);
+
+        // Both the primary and the timelocked recovery path
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(or_d(pk({}),and_v(v:pk({}),older(144))))",
+            keys[0], keys[1]
+        ))
+        .unwrap();
+        let assets = Assets::new().add(keys.clone()).older(older);
+        let plans = desc.plan_all(&assets);
+        assert_eq!(plans.len(), 2);
+        assert_eq!(plans[0], desc.clone().plan(&assets).unwrap());
+        assert_eq!(plans[0].requirements().relative_timelock, None);
+        assert_eq!(plans[1].requirements().relative_timelock, Some(older));
+        assert!(plans[0].satisfaction_weight() < plans[1].satisfaction_weight());
+
+        // Without the timelock only the primary path remains
+        assert_eq!(desc.plan_all(&Assets::new().add(keys.clone())).len(), 1);
+        assert!(desc.plan_all(&Assets::new()).is_empty());
+
+        // Every pair of keys
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(multi(2,{},{},{}))",
+            keys[0], keys[1], keys[2]
+        ))
+        .unwrap();
+        let plans = desc.plan_all(&Assets::new().add(keys.clone()));
+        assert_eq!(plans.len(), 3);
+        assert!(plans
+            .iter()
+            .all(|plan| plan.requirements().signatures.len() == 2));
+
+        // The key spend and the script path
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "tr({},pk({}))",
+            keys[0], keys[1]
+        ))
+        .unwrap();
+        let plans = desc.plan_all(&Assets::new().add(keys.clone()));
+        assert_eq!(plans.len(), 2);
+        assert_eq!(plans[0].requirements().signatures[0].leaf_hash, None);
+        assert!(plans[1].requirements().signatures[0].leaf_hash.is_some());
+    }
+
+    #[test]
+    fn test_plan_fees() {
+        let keys: Vec<DescriptorPublicKey> = [
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+            "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+        ]
+        .iter()
+        .map(|s| DescriptorPublicKey::from_str(s).unwrap())
+        .collect();
+        let feerate = FeeRate::from_sat_per_vb(10).unwrap();
+
+        let desc =
+            Descriptor::<DefiniteDescriptorKey>::from_str(&format!("wpkh({})", keys[0])).unwrap();
+        let plan = desc.plan(&Assets::new().add(keys[0].clone())).unwrap();
+        // 1 (witness count) + 1 + 72 (signature) + 1 + 33 (key), plus scriptSig length
+        assert_eq!(plan.satisfaction_weight(), 108 + 4);
+        assert_eq!(plan.satisfaction_fee(feerate), Amount::from_sat(280));
+        assert_eq!(plan.input_weight(), Weight::from_wu(160 + 112));
+        assert_eq!(plan.marginal_weight(true), plan.input_weight());
+        assert_eq!(plan.marginal_weight(false), Weight::from_wu(160 + 112 + 2));
+        assert_eq!(plan.marginal_fee(feerate, true), Amount::from_sat(680));
+
+        let desc =
+            Descriptor::<DefiniteDescriptorKey>::from_str(&format!("pkh({})", keys[0])).unwrap();
+        let plan = desc.plan(&Assets::new().add(keys[0].clone())).unwrap();
+        assert_eq!(plan.marginal_weight(false), plan.input_weight());
+        assert_eq!(plan.marginal_weight(true), plan.input_weight() + Weight::from_wu(1));
+
+        // Key spend versus script spend
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "tr({},pk({}))",
+            keys[0], keys[1]
+        ))
+        .unwrap();
+        let plans = desc
+            .plan_taproot_spends(&Assets::new().add(keys.clone()))
+            .unwrap();
+        let key_spend = plans.key_spend.as_ref().unwrap();
+        let script_spend = plans.script_spend.as_ref().unwrap();
+        assert_eq!(plans.cheapest(), Some(key_spend));
+        assert_eq!(
+            plans.script_spend_premium(feerate),
+            Some(script_spend.satisfaction_fee(feerate) - key_spend.satisfaction_fee(feerate))
+        );
+        assert!(plans.script_spend_premium(feerate).unwrap() > Amount::ZERO);
+
+        let plans = desc
+            .plan_taproot_spends(&Assets::new().add(keys[1].clone()))
+            .unwrap();
+        assert_eq!(plans.key_spend, None);
+        assert_eq!(plans.cheapest(), plans.script_spend.as_ref());
+        assert_eq!(plans.script_spend_premium(feerate), None);
+
+        let desc =
+            Descriptor::<DefiniteDescriptorKey>::from_str(&format!("wpkh({})", keys[0])).unwrap();
+        assert_eq!(desc.plan_taproot_spends(&Assets::new()), None);
+    }
+
+    #[test]
+    fn test_plan_at() {
+        let keys: Vec<DescriptorPublicKey> = [
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+            "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+        ]
+        .iter()
+        .map(|s| DescriptorPublicKey::from_str(s).unwrap())
+        .collect();
+        let chain_time = |height: u32, mtp: u32| {
+            ChainTime::new(
+                absolute::Height::from_consensus(height).unwrap(),
+                absolute::Time::from_consensus(mtp).unwrap(),
+            )
+        };
+        let unlock = |height: Option<u32>, mtp: Option<u32>| UnlockTime {
+            height: height.map(|h| absolute::Height::from_consensus(h).unwrap()),
+            mtp: mtp.map(|t| absolute::Time::from_consensus(t).unwrap()),
+        };
+        let confirmation = chain_time(1000, 1_700_000_000);
+
+        // A recovery path using a relative timelock
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(or_d(pk({}),and_v(v:pk({}),older(144))))",
+            keys[0], keys[1]
+        ))
+        .unwrap();
+        let assets = Assets::new().add(keys[1].clone());
+        let at = |height| chain_time(height, 1_700_000_000);
+        assert_eq!(desc.plan_at(&assets, at(1142), Some(confirmation)), None);
+        let plan = desc.plan_at(&assets, at(1143), Some(confirmation)).unwrap();
+        assert_eq!(plan.relative_timelock, Some(relative::LockTime::from_height(144)));
+        assert_eq!(desc.plan_at(&assets, at(2000), None), None);
+        assert_eq!(desc.unlock_times(&assets, Some(confirmation)), vec![unlock(Some(1143), None)]);
+        assert!(desc.unlock_times(&assets, None).is_empty());
+        let assets = Assets::new().add(keys.clone());
+        assert_eq!(desc.unlock_times(&assets, None), vec![unlock(None, None)]);
+        assert!(desc
+            .plan_at(&assets, at(1000), None)
+            .unwrap()
+            .relative_timelock
+            .is_none());
+
+        // Absolute timelocks, with a later height being redundant
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(or_i(and_v(v:pk({0}),after(800000)),or_i(and_v(v:pk({0}),after(900000)),\
+             and_v(v:pk({0}),after(1700000000)))))",
+            keys[0]
+        ))
+        .unwrap();
+        let assets = Assets::new().add(keys[0].clone());
+        assert_eq!(
+            desc.unlock_times(&assets, None),
+            vec![
+                unlock(Some(800000), None),
+                unlock(None, Some(1_700_000_001))
+            ]
+        );
+        assert_eq!(desc.plan_at(&assets, chain_time(799_999, 1_700_000_000), None), None);
+        let plan = desc
+            .plan_at(&assets, chain_time(799_999, 1_700_000_001), None)
+            .unwrap();
+        assert_eq!(plan.absolute_timelock, Some(absolute::LockTime::from_consensus(1_700_000_000)));
+        let plan = desc
+            .plan_at(&assets, chain_time(800_000, 1_700_000_000), None)
+            .unwrap();
+        assert_eq!(plan.absolute_timelock, Some(absolute::LockTime::from_consensus(800_000)));
+    }
+
+    #[test]
+    fn test_select_plans() {
+        let keys: Vec<DescriptorPublicKey> = [
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+            "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+            "03500a2b48b0f66c8183cc0d6645ab21cc19c7fad8a33ff04d41c3ece54b0bc1c5",
+        ]
+        .iter()
+        .map(|s| DescriptorPublicKey::from_str(s).unwrap())
+        .collect();
+        let candidate = |desc: String, assets: Assets, value: u64| SpendCandidate {
+            descriptor: Descriptor::from_str(&desc).unwrap(),
+            assets,
+            value: Amount::from_sat(value),
+        };
+        let feerate = FeeRate::from_sat_per_vb(1).unwrap();
+        let both = || Assets::new().add(keys[0].clone()).add(keys[1].clone());
+        let candidates = vec![
+            candidate(format!("wpkh({})", keys[0]), both(), 10_000),
+            candidate(format!("wpkh({})", keys[1]), both(), 50_000),
+            candidate(format!("wsh(multi(2,{},{},{}))", keys[0], keys[1], keys[2]), both(), 60_000),
+            // Can't be spent
+            candidate(format!("wpkh({})", keys[2]), both(), 1_000_000),
+        ];
+
+        let selection = select_plans(&candidates, Amount::from_sat(45_000), feerate).unwrap();
+        assert_eq!(selection.inputs.len(), 1);
+        assert_eq!(selection.inputs[0].0, 1);
+        assert_eq!(selection.weight, selection.inputs[0].1.marginal_weight(true));
+        assert_eq!(selection.value, Amount::from_sat(50_000));
+        assert_eq!(selection.fee, selection.weight * feerate);
+
+        // A single multisig input is lighter than two single key inputs
+        let selection = select_plans(&candidates, Amount::from_sat(55_000), feerate).unwrap();
+        let indices: Vec<_> = selection.inputs.iter().map(|(i, _)| *i).collect();
+        assert_eq!(indices, vec![2]);
+
+        let selection = select_plans(&candidates, Amount::from_sat(100_000), feerate).unwrap();
+        let indices: Vec<_> = selection.inputs.iter().map(|(i, _)| *i).collect();
+        assert_eq!(indices, vec![1, 2]);
+
+        assert!(matches!(
+            select_plans(&candidates, Amount::from_sat(200_000), feerate),
+            Err(SelectionError::InsufficientFunds { .. })
+        ));
+
+        // Absolute timelocks of different units can't be combined
+        let height = absolute::LockTime::from_height(100).unwrap();
+        let time = absolute::LockTime::from_time(500_000_001).unwrap();
+        let timelocked = |lt: absolute::LockTime| {
+            candidate(
+                format!("wsh(and_v(v:pk({}),after({})))", keys[0], lt.to_consensus_u32()),
+                Assets::new().add(keys[0].clone()).after(lt),
+                30_000,
+            )
+        };
+        let candidates = vec![timelocked(height), timelocked(time)];
+        let selection = select_plans(&candidates, Amount::from_sat(20_000), feerate).unwrap();
+        assert!(selection.locktime.is_some());
+        assert_eq!(
+            select_plans(&candidates, Amount::from_sat(50_000), feerate),
+            Err(SelectionError::NoSolution)
+        );
+    }
 }
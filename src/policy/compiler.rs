@@ -18,8 +18,291 @@ use crate::policy::Concrete;
 use crate::prelude::*;
 use crate::{policy, Miniscript, MiniscriptKey, Terminal};
 
-type PolicyCache<Pk, Ctx> =
-    BTreeMap<(Concrete<Pk>, OrdF64, Option<OrdF64>), BTreeMap<CompilationKey, AstElemExt<Pk, Ctx>>>;
+type PolicyCacheKey<Pk> = (Concrete<Pk>, OrdF64, Option<OrdF64>);
+
+/// Cache of the best compilations of each sub-policy, under a given cost model.
+struct PolicyCache<Pk: MiniscriptKey, Ctx: ScriptContext> {
+    model: CostModel,
+    map: BTreeMap<PolicyCacheKey<Pk>, BTreeMap<CompilationKey, AstElemExt<Pk, Ctx>>>,
+    /// Number of lookups which found a compilation.
+    hits: usize,
+    /// Number of lookups which did not find a compilation.
+    misses: usize,
+}
+
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> PolicyCache<Pk, Ctx> {
+    fn new(model: CostModel) -> Self {
+        PolicyCache { model, map: BTreeMap::new(), hits: 0, misses: 0 }
+    }
+}
+
+/// A cache of compilations which persists across calls to the compiler.
+///
+/// Compiling many policies which share sub-policies, e.g. when creating wallets from a
+/// template, compiles each shared sub-policy only once. Compilations depend on the script
+/// context and the cost model, so a cache is specific to both. It is never evicted from;
+/// use [`CompilerCache::clear`] to release its memory.
+#[cfg(feature = "compiler-cache")]
+pub struct CompilerCache<Pk: MiniscriptKey, Ctx: ScriptContext> {
+    inner: PolicyCache<Pk, Ctx>,
+}
+
+/// Statistics of a [`CompilerCache`].
+#[cfg(feature = "compiler-cache")]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct CacheStats {
+    /// Number of sub-policy compilations found in the cache.
+    pub hits: usize,
+    /// Number of sub-policy compilations not found in the cache, and computed.
+    pub misses: usize,
+    /// Number of sub-policy compilations currently in the cache.
+    pub entries: usize,
+}
+
+#[cfg(feature = "compiler-cache")]
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> CompilerCache<Pk, Ctx> {
+    /// Creates an empty cache for compilations under the given cost model.
+    pub fn new(model: CostModel) -> Self { CompilerCache { inner: PolicyCache::new(model) } }
+
+    /// The cost model of the compilations in the cache.
+    pub fn model(&self) -> CostModel { self.inner.model }
+
+    /// Returns the statistics of the cache since it was created or last cleared.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.inner.hits,
+            misses: self.inner.misses,
+            entries: self.inner.map.len(),
+        }
+    }
+
+    /// Removes all compilations from the cache, and resets its statistics.
+    pub fn clear(&mut self) { self.inner = PolicyCache::new(self.inner.model) }
+}
+
+#[cfg(feature = "compiler-cache")]
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> Default for CompilerCache<Pk, Ctx> {
+    fn default() -> Self { Self::new(CostModel::default()) }
+}
+
+/// Weights used by the compiler to compare candidate compilations.
+///
+/// The cost of a compilation is the size of its script times `script_weight`, plus
+/// the expected sizes of its satisfaction and dissatisfaction witnesses, given the
+/// probabilities of the policy, times `sat_weight` and `dissat_weight` respectively.
+/// Weights may be given in any unit, e.g. as the feerates in sat/vB at which the
+/// script and the witness will be paid for. Only their ratios affect compilation.
+///
+/// The default model weighs all bytes equally, breaks ties with [`TieBreak::FirstFound`],
+/// and is the one used by [`Concrete::compile`].
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct CostModel {
+    /// Weight of each byte of script.
+    pub script_weight: f64,
+    /// Weight of each expected byte of satisfaction witness.
+    pub sat_weight: f64,
+    /// Weight of each expected byte of dissatisfaction witness.
+    pub dissat_weight: f64,
+    /// How to choose between compilations of equal cost.
+    pub tie_break: TieBreak,
+}
+
+/// How the compiler chooses between candidate compilations of equal cost.
+///
+/// Compilation is deterministic whatever the choice: compiling the same policy under the
+/// same cost model always gives the same miniscript, on any platform.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash, Default)]
+pub enum TieBreak {
+    /// Keep the candidate which the compiler found first.
+    #[default]
+    FirstFound,
+    /// Prefer the candidate with the smaller script.
+    SmallestScript,
+    /// Prefer the candidate which comes first in the ordering of [`Miniscript`]s.
+    ///
+    /// Unlike the other choices, this does not depend on the order in which the compiler
+    /// explores candidates, so it is the least likely to change between versions of this
+    /// library.
+    Canonical,
+}
+
+impl CostModel {
+    /// Creates a cost model which weighs script and witness bytes differently.
+    pub fn new(script_weight: f64, witness_weight: f64) -> Self {
+        CostModel {
+            script_weight,
+            sat_weight: witness_weight,
+            dissat_weight: witness_weight,
+            tie_break: TieBreak::default(),
+        }
+    }
+
+    /// Returns the cost model with the given tie-breaking rule.
+    pub fn with_tie_break(self, tie_break: TieBreak) -> Self { CostModel { tie_break, ..self } }
+
+    /// Compares two compilations by cost, then by the tie-breaking rule.
+    fn compare<Pk: MiniscriptKey, Ctx: ScriptContext>(
+        &self,
+        a: &AstElemExt<Pk, Ctx>,
+        b: &AstElemExt<Pk, Ctx>,
+        sat_prob: f64,
+        dissat_prob: Option<f64>,
+    ) -> cmp::Ordering {
+        let a_cost = OrdF64(a.cost_1d(self, sat_prob, dissat_prob));
+        let b_cost = OrdF64(b.cost_1d(self, sat_prob, dissat_prob));
+        a_cost.cmp(&b_cost).then_with(|| match self.tie_break {
+            TieBreak::FirstFound => cmp::Ordering::Equal,
+            TieBreak::SmallestScript => a.ms.script_size().cmp(&b.ms.script_size()),
+            TieBreak::Canonical => a.ms.cmp(&b.ms),
+        })
+    }
+
+    /// A cost model which only minimizes the size of the script, e.g. for bare outputs
+    /// whose script is paid for when the output is created.
+    pub fn script_size() -> Self { CostModel::new(1.0, 0.0) }
+
+    /// A cost model which only minimizes the expected size of the witness.
+    pub fn witness_size() -> Self { CostModel::new(0.0, 1.0) }
+
+    /// Checks that all weights are finite and non-negative.
+    fn is_valid(&self) -> bool {
+        [self.script_weight, self.sat_weight, self.dissat_weight]
+            .iter()
+            .all(|w| w.is_finite() && *w >= 0.0)
+    }
+}
+
+impl Default for CostModel {
+    fn default() -> Self { CostModel::new(1.0, 1.0) }
+}
+
+/// Restrictions on the miniscripts the compiler may produce, e.g. to accommodate the
+/// limitations of a signing device.
+///
+/// Restrictions on fragment classes are checked against the policy, since the compiler
+/// cannot avoid a fragment the policy requires. Size and key-count limits are applied to
+/// each candidate compilation, so a costlier compilation may be chosen to respect them.
+/// The default value imposes no restriction.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash, Default)]
+pub struct CompilerConstraints {
+    /// Forbid hash locks (`sha256`, `hash256`, `ripemd160` and `hash160`).
+    pub no_hash_locks: bool,
+    /// Forbid absolute timelocks (`after`).
+    pub no_absolute_timelocks: bool,
+    /// Forbid relative timelocks (`older`).
+    pub no_relative_timelocks: bool,
+    /// Maximum size of a script, in bytes. For taproot, this applies to each leaf.
+    pub max_script_size: Option<usize>,
+    /// Maximum number of keys in a script. For taproot, this applies to each leaf.
+    pub max_keys: Option<usize>,
+}
+
+/// A [`CompilerConstraints`] restriction which a compilation could not respect.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum ConstraintViolation {
+    /// The policy contains a hash lock.
+    HashLock,
+    /// The policy contains an absolute timelock.
+    AbsoluteTimelock,
+    /// The policy contains a relative timelock.
+    RelativeTimelock,
+    /// No compilation found was within the maximum script size.
+    ScriptSize {
+        /// Size of the script of the best compilation, in bytes.
+        size: usize,
+        /// Maximum allowed script size.
+        max: usize,
+    },
+    /// No compilation found was within the maximum number of keys.
+    KeyCount {
+        /// Number of keys in the script of the best compilation.
+        n: usize,
+        /// Maximum allowed number of keys.
+        max: usize,
+    },
+}
+
+impl fmt::Display for ConstraintViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConstraintViolation::HashLock => f.write_str("policy contains a forbidden hash lock"),
+            ConstraintViolation::AbsoluteTimelock => {
+                f.write_str("policy contains a forbidden absolute timelock")
+            }
+            ConstraintViolation::RelativeTimelock => {
+                f.write_str("policy contains a forbidden relative timelock")
+            }
+            ConstraintViolation::ScriptSize { size, max } => {
+                write!(f, "script size {} exceeds the maximum of {}", size, max)
+            }
+            ConstraintViolation::KeyCount { n, max } => {
+                write!(f, "script has {} keys, exceeding the maximum of {}", n, max)
+            }
+        }
+    }
+}
+
+impl CompilerConstraints {
+    /// Checks that the policy has no forbidden fragment.
+    fn check_policy<Pk: MiniscriptKey>(&self, policy: &Concrete<Pk>) -> Result<(), CompilerError> {
+        use crate::iter::TreeLike;
+
+        for node in policy.pre_order_iter() {
+            let violation = match *node {
+                Concrete::Sha256(..)
+                | Concrete::Hash256(..)
+                | Concrete::Ripemd160(..)
+                | Concrete::Hash160(..)
+                    if self.no_hash_locks =>
+                {
+                    ConstraintViolation::HashLock
+                }
+                Concrete::After(..) if self.no_absolute_timelocks => {
+                    ConstraintViolation::AbsoluteTimelock
+                }
+                Concrete::Older(..) if self.no_relative_timelocks => {
+                    ConstraintViolation::RelativeTimelock
+                }
+                _ => continue,
+            };
+            return Err(CompilerError::ConstraintViolated(violation));
+        }
+        Ok(())
+    }
+
+    /// Checks that a compilation respects the size and key-count limits.
+    fn check_miniscript<Pk: MiniscriptKey, Ctx: ScriptContext>(
+        &self,
+        ms: &Miniscript<Pk, Ctx>,
+    ) -> Result<(), ConstraintViolation> {
+        if let Some(max) = self.max_script_size {
+            let size = ms.script_size();
+            if size > max {
+                return Err(ConstraintViolation::ScriptSize { size, max });
+            }
+        }
+        if let Some(max) = self.max_keys {
+            let n = ms.iter_pk().count();
+            if n > max {
+                return Err(ConstraintViolation::KeyCount { n, max });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A compilation returned by [`ranked_compilations`], with its cost metrics.
+#[derive(Clone, PartialEq, Debug)]
+pub struct RankedCompilation<Pk: MiniscriptKey, Ctx: ScriptContext> {
+    /// The compiled miniscript.
+    pub ms: Miniscript<Pk, Ctx>,
+    /// The cost of the compilation under the cost model used, by which candidates are ranked.
+    pub cost: f64,
+    /// The size of the script, in bytes.
+    pub script_size: usize,
+    /// The expected size of the satisfaction witness, given the probabilities of the policy.
+    pub expected_sat_size: f64,
+}
 
 /// Ordered f64 for comparison.
 #[derive(Copy, Clone, PartialEq, Debug)]
@@ -62,6 +345,10 @@ pub enum CompilerError {
     },
     ///Policy related errors
     PolicyError(policy::concrete::PolicyError),
+    /// A cost model had a negative or non-finite weight.
+    InvalidCostModel,
+    /// No compilation respected the given [`CompilerConstraints`].
+    ConstraintViolated(ConstraintViolation),
 }
 
 impl fmt::Display for CompilerError {
@@ -83,13 +370,19 @@ impl fmt::Display for CompilerError {
                 write!(f, "Policy had too many Tapleaves (found {}, maximum {})", n, max)
             }
             CompilerError::PolicyError(ref e) => fmt::Display::fmt(e, f),
+            CompilerError::InvalidCostModel => {
+                f.write_str("Cost model weights must be finite and non-negative")
+            }
+            CompilerError::ConstraintViolated(ref v) => {
+                write!(f, "Compiler constraint violated: {}", v)
+            }
         }
     }
 }
 
 #[cfg(feature = "std")]
 impl error::Error for CompilerError {
-    fn cause(&self) -> Option<&dyn error::Error> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         use self::CompilerError::*;
 
         match self {
@@ -97,7 +390,9 @@ impl error::Error for CompilerError {
             | ImpossibleNonMalleableCompilation
             | LimitsExceeded
             | NoInternalKey
-            | TooManyTapleaves { .. } => None,
+            | TooManyTapleaves { .. }
+            | InvalidCostModel
+            | ConstraintViolated(..) => None,
             PolicyError(e) => Some(e),
         }
     }
@@ -490,6 +785,7 @@ impl CompilerExtData {
             Terminal::Thresh(ref thresh) => {
                 Self::threshold(thresh.k(), thresh.n(), |n| get_child(&thresh.data()[n].node, n))
             }
+            Terminal::Ext(ref e) => match *e {},
         }
     }
 }
@@ -507,11 +803,11 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> AstElemExt<Pk, Ctx> {
     /// Compute a 1-dimensional cost, given a probability of satisfaction
     /// and a probability of dissatisfaction; if `dissat_prob` is `None`
     /// then it is assumed that dissatisfaction never occurs
-    fn cost_1d(&self, sat_prob: f64, dissat_prob: Option<f64>) -> f64 {
-        self.ms.ext.pk_cost as f64
-            + self.comp_ext_data.sat_cost * sat_prob
+    fn cost_1d(&self, model: &CostModel, sat_prob: f64, dissat_prob: Option<f64>) -> f64 {
+        model.script_weight * self.ms.ext.pk_cost as f64
+            + model.sat_weight * self.comp_ext_data.sat_cost * sat_prob
             + match (dissat_prob, self.comp_ext_data.dissat_cost) {
-                (Some(prob), Some(cost)) => prob * cost,
+                (Some(prob), Some(cost)) => model.dissat_weight * prob * cost,
                 (Some(_), None) => f64::INFINITY,
                 (None, Some(_)) => 0.0,
                 (None, None) => 0.0,
@@ -671,6 +967,7 @@ fn all_casts<Pk: MiniscriptKey, Ctx: ScriptContext>() -> [Cast<Pk, Ctx>; 10] {
 fn insert_elem<Pk: MiniscriptKey, Ctx: ScriptContext>(
     map: &mut BTreeMap<CompilationKey, AstElemExt<Pk, Ctx>>,
     elem: AstElemExt<Pk, Ctx>,
+    model: &CostModel,
     sat_prob: f64,
     dissat_prob: Option<f64>,
 ) -> bool {
@@ -684,16 +981,14 @@ fn insert_elem<Pk: MiniscriptKey, Ctx: ScriptContext>(
         return false;
     }
 
-    let elem_cost = elem.cost_1d(sat_prob, dissat_prob);
-
     let elem_key = CompilationKey::from_type(elem.ms.ty, elem.ms.ext.has_free_verify, dissat_prob);
 
     // Check whether the new element is worse than any existing element. If there
     // is an element which is a subtype of the current element and has better
     // cost, don't consider this element.
     let is_worse = map.iter().any(|(existing_key, existing_elem)| {
-        let existing_elem_cost = existing_elem.cost_1d(sat_prob, dissat_prob);
-        existing_key.is_subtype(elem_key) && existing_elem_cost <= elem_cost
+        existing_key.is_subtype(elem_key)
+            && model.compare(existing_elem, &elem, sat_prob, dissat_prob) != cmp::Ordering::Greater
     });
     if !is_worse {
         // If the element is not worse any element in the map, remove elements
@@ -701,8 +996,9 @@ fn insert_elem<Pk: MiniscriptKey, Ctx: ScriptContext>(
         *map = mem::take(map)
             .into_iter()
             .filter(|(existing_key, existing_elem)| {
-                let existing_elem_cost = existing_elem.cost_1d(sat_prob, dissat_prob);
-                !(elem_key.is_subtype(*existing_key) && existing_elem_cost >= elem_cost)
+                !(elem_key.is_subtype(*existing_key)
+                    && model.compare(existing_elem, &elem, sat_prob, dissat_prob)
+                        != cmp::Ordering::Less)
             })
             .collect();
         map.insert(elem_key, elem);
@@ -721,11 +1017,12 @@ fn insert_elem<Pk: MiniscriptKey, Ctx: ScriptContext>(
 fn insert_elem_closure<Pk: MiniscriptKey, Ctx: ScriptContext>(
     map: &mut BTreeMap<CompilationKey, AstElemExt<Pk, Ctx>>,
     astelem_ext: AstElemExt<Pk, Ctx>,
+    model: &CostModel,
     sat_prob: f64,
     dissat_prob: Option<f64>,
 ) {
     let mut cast_stack: VecDeque<AstElemExt<Pk, Ctx>> = VecDeque::new();
-    if insert_elem(map, astelem_ext.clone(), sat_prob, dissat_prob) {
+    if insert_elem(map, astelem_ext.clone(), model, sat_prob, dissat_prob) {
         cast_stack.push_back(astelem_ext);
     }
 
@@ -735,7 +1032,7 @@ fn insert_elem_closure<Pk: MiniscriptKey, Ctx: ScriptContext>(
 
         for c in &casts {
             if let Ok(new_ext) = c.cast(&current) {
-                if insert_elem(map, new_ext.clone(), sat_prob, dissat_prob) {
+                if insert_elem(map, new_ext.clone(), model, sat_prob, dissat_prob) {
                     cast_stack.push_back(new_ext);
                 }
             }
@@ -760,7 +1057,8 @@ fn insert_best_wrapped<Pk: MiniscriptKey, Ctx: ScriptContext>(
     sat_prob: f64,
     dissat_prob: Option<f64>,
 ) -> Result<(), CompilerError> {
-    insert_elem_closure(map, data, sat_prob, dissat_prob);
+    let model = policy_cache.model;
+    insert_elem_closure(map, data, &model, sat_prob, dissat_prob);
 
     if dissat_prob.is_some() {
         let casts: [Cast<Pk, Ctx>; 10] = all_casts::<Pk, Ctx>();
@@ -768,7 +1066,7 @@ fn insert_best_wrapped<Pk: MiniscriptKey, Ctx: ScriptContext>(
         for c in &casts {
             for x in best_compilations(policy_cache, policy, sat_prob, None)?.values() {
                 if let Ok(new_ext) = c.cast(x) {
-                    insert_elem_closure(map, new_ext, sat_prob, dissat_prob);
+                    insert_elem_closure(map, new_ext, &model, sat_prob, dissat_prob);
                 }
             }
         }
@@ -791,9 +1089,14 @@ where
     //Check the cache for hits
     let ord_sat_prob = OrdF64(sat_prob);
     let ord_dissat_prob = dissat_prob.map(OrdF64);
-    if let Some(ret) = policy_cache.get(&(policy.clone(), ord_sat_prob, ord_dissat_prob)) {
+    if let Some(ret) = policy_cache
+        .map
+        .get(&(policy.clone(), ord_sat_prob, ord_dissat_prob))
+    {
+        policy_cache.hits += 1;
         return Ok(ret.clone());
     }
+    policy_cache.misses += 1;
 
     let mut ret = BTreeMap::new();
 
@@ -990,7 +1293,8 @@ where
                 let be = best(types::Base::B, policy_cache, ast.as_ref(), sp, dp)?;
                 let bw = best(types::Base::W, policy_cache, ast.as_ref(), sp, dp)?;
 
-                let diff = be.cost_1d(sp, dp) - bw.cost_1d(sp, dp);
+                let model = policy_cache.model;
+                let diff = be.cost_1d(&model, sp, dp) - bw.cost_1d(&model, sp, dp);
                 best_es.push((be.comp_ext_data, be));
                 best_ws.push((bw.comp_ext_data, bw));
 
@@ -1078,7 +1382,9 @@ where
         // before calling this compile function
         Err(CompilerError::LimitsExceeded)
     } else {
-        policy_cache.insert((policy.clone(), ord_sat_prob, ord_dissat_prob), ret.clone());
+        policy_cache
+            .map
+            .insert((policy.clone(), ord_sat_prob, ord_dissat_prob), ret.clone());
         Ok(ret)
     }
 }
@@ -1156,8 +1462,109 @@ fn compile_tern<Pk: MiniscriptKey, Ctx: ScriptContext>(
 pub fn best_compilation<Pk: MiniscriptKey, Ctx: ScriptContext>(
     policy: &Concrete<Pk>,
 ) -> Result<Miniscript<Pk, Ctx>, CompilerError> {
-    let mut policy_cache = PolicyCache::<Pk, Ctx>::new();
-    let x = &*best_t(&mut policy_cache, policy, 1.0, None)?.ms;
+    best_compilation_with_model(policy, &CostModel::default())
+}
+
+/// Obtain the best compilation of for p=1.0 and q=0 under the given cost model
+pub fn best_compilation_with_model<Pk: MiniscriptKey, Ctx: ScriptContext>(
+    policy: &Concrete<Pk>,
+    model: &CostModel,
+) -> Result<Miniscript<Pk, Ctx>, CompilerError> {
+    if !model.is_valid() {
+        return Err(CompilerError::InvalidCostModel);
+    }
+    best_compilation_in(&mut PolicyCache::new(*model), policy)
+}
+
+/// Obtain the best compilation of for p=1.0 and q=0, reusing and adding to the compilations
+/// in `cache`
+#[cfg(feature = "compiler-cache")]
+pub fn best_compilation_cached<Pk: MiniscriptKey, Ctx: ScriptContext>(
+    policy: &Concrete<Pk>,
+    cache: &mut CompilerCache<Pk, Ctx>,
+) -> Result<Miniscript<Pk, Ctx>, CompilerError> {
+    if !cache.inner.model.is_valid() {
+        return Err(CompilerError::InvalidCostModel);
+    }
+    best_compilation_in(&mut cache.inner, policy)
+}
+
+/// Obtain the best compilation of for p=1.0 and q=0 under the given cost model which
+/// respects `constraints`
+///
+/// If the best compilation does not respect the size and key-count limits, the other
+/// candidates of [`ranked_compilations`] are tried in order, followed by those under
+/// [`CostModel::script_size`].
+pub fn best_compilation_with_constraints<Pk: MiniscriptKey, Ctx: ScriptContext>(
+    policy: &Concrete<Pk>,
+    model: &CostModel,
+    constraints: &CompilerConstraints,
+) -> Result<Miniscript<Pk, Ctx>, CompilerError> {
+    constraints.check_policy(policy)?;
+    let best = best_compilation_with_model(policy, model)?;
+    let violation = match constraints.check_miniscript(&best) {
+        Ok(()) => return Ok(best),
+        Err(violation) => violation,
+    };
+    for model in [*model, CostModel::script_size()] {
+        let found = ranked_compilations(policy, &model, usize::MAX)?
+            .into_iter()
+            .map(|candidate| candidate.ms)
+            .find(|ms| constraints.check_miniscript(ms).is_ok());
+        if let Some(ms) = found {
+            return Ok(ms);
+        }
+    }
+    Err(CompilerError::ConstraintViolated(violation))
+}
+
+/// Obtain up to `n` distinct compilations for p=1.0 and q=0 under the given cost model,
+/// ranked from the best
+///
+/// The compiler only keeps the best compilation of each type of fragment, so there may
+/// be fewer than `n` candidates even for large policies.
+pub fn ranked_compilations<Pk: MiniscriptKey, Ctx: ScriptContext>(
+    policy: &Concrete<Pk>,
+    model: &CostModel,
+    n: usize,
+) -> Result<Vec<RankedCompilation<Pk, Ctx>>, CompilerError> {
+    if !model.is_valid() {
+        return Err(CompilerError::InvalidCostModel);
+    }
+    let mut policy_cache = PolicyCache::<Pk, Ctx>::new(*model);
+    let mut candidates = best_compilations(&mut policy_cache, policy, 1.0, None)?
+        .into_iter()
+        .filter(|(key, val)| {
+            key.ty.corr.base == types::Base::B
+                && key.dissat_prob.is_none()
+                && val.ms.ty.mall.safe
+                && val.ms.ty.mall.non_malleable
+        })
+        .map(|(_, val)| val)
+        .collect::<Vec<_>>();
+    if candidates.is_empty() {
+        return Err(CompilerError::LimitsExceeded);
+    }
+    candidates.sort_by(|a, b| model.compare(a, b, 1.0, None));
+    candidates.dedup_by(|a, b| a.ms == b.ms);
+
+    Ok(candidates
+        .into_iter()
+        .take(n)
+        .map(|ext| RankedCompilation {
+            cost: ext.cost_1d(model, 1.0, None),
+            script_size: ext.ms.script_size(),
+            expected_sat_size: ext.comp_ext_data.sat_cost,
+            ms: Arc::try_unwrap(ext.ms).unwrap_or_else(|ms| (*ms).clone()),
+        })
+        .collect())
+}
+
+fn best_compilation_in<Pk: MiniscriptKey, Ctx: ScriptContext>(
+    policy_cache: &mut PolicyCache<Pk, Ctx>,
+    policy: &Concrete<Pk>,
+) -> Result<Miniscript<Pk, Ctx>, CompilerError> {
+    let x = &*best_t(policy_cache, policy, 1.0, None)?.ms;
     if !x.ty.mall.safe {
         Err(CompilerError::TopLevelNonSafe)
     } else if !x.ty.mall.non_malleable {
@@ -1178,13 +1585,14 @@ where
     Pk: MiniscriptKey,
     Ctx: ScriptContext,
 {
+    let model = policy_cache.model;
     best_compilations(policy_cache, policy, sat_prob, dissat_prob)?
         .into_iter()
         .filter(|&(key, _)| {
             key.ty.corr.base == types::Base::B && key.dissat_prob == dissat_prob.map(OrdF64)
         })
         .map(|(_, val)| val)
-        .min_by_key(|ext| OrdF64(ext.cost_1d(sat_prob, dissat_prob)))
+        .min_by(|a, b| model.compare(a, b, sat_prob, dissat_prob))
         .ok_or(CompilerError::LimitsExceeded)
 }
 
@@ -1200,6 +1608,7 @@ where
     Pk: MiniscriptKey,
     Ctx: ScriptContext,
 {
+    let model = policy_cache.model;
     best_compilations(policy_cache, policy, sat_prob, dissat_prob)?
         .into_iter()
         .filter(|(key, val)| {
@@ -1209,7 +1618,7 @@ where
                 && key.dissat_prob == dissat_prob.map(OrdF64)
         })
         .map(|(_, val)| val)
-        .min_by_key(|ext| OrdF64(ext.cost_1d(sat_prob, dissat_prob)))
+        .min_by(|a, b| model.compare(a, b, sat_prob, dissat_prob))
         .ok_or(CompilerError::LimitsExceeded)
 }
 
@@ -1311,18 +1720,23 @@ mod tests {
     #[test]
     fn compile_q() {
         let policy = SPolicy::from_str("or(1@and(pk(A),pk(B)),127@pk(C))").expect("parsing");
-        let compilation: TapAstElemExt = best_t(&mut BTreeMap::new(), &policy, 1.0, None).unwrap();
+        let compilation: TapAstElemExt =
+            best_t(&mut PolicyCache::new(CostModel::default()), &policy, 1.0, None).unwrap();
 
-        assert_eq!(compilation.cost_1d(1.0, None), 87.0 + 67.0390625);
+        assert_eq!(compilation.cost_1d(&CostModel::default(), 1.0, None), 87.0 + 67.0390625);
         assert_eq!(policy.lift().unwrap().sorted(), compilation.ms.lift().unwrap().sorted());
 
         // compile into taproot context to avoid limit errors
         let policy = SPolicy::from_str(
                 "and(and(and(or(127@thresh(2,pk(A),pk(B),thresh(2,or(127@pk(A),1@pk(B)),after(100),or(and(pk(C),after(200)),and(pk(D),sha256(66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925))),pk(E))),1@pk(F)),sha256(66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925)),or(127@pk(G),1@after(300))),or(127@after(400),pk(H)))"
             ).expect("parsing");
-        let compilation: TapAstElemExt = best_t(&mut BTreeMap::new(), &policy, 1.0, None).unwrap();
+        let compilation: TapAstElemExt =
+            best_t(&mut PolicyCache::new(CostModel::default()), &policy, 1.0, None).unwrap();
 
-        assert_eq!(compilation.cost_1d(1.0, None), 433.0 + 275.7909749348958);
+        assert_eq!(
+            compilation.cost_1d(&CostModel::default(), 1.0, None),
+            433.0 + 275.7909749348958
+        );
         assert_eq!(policy.lift().unwrap().sorted(), compilation.ms.lift().unwrap().sorted());
     }
 
@@ -1645,4 +2059,153 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn compile_with_cost_model() {
+        let policy = SPolicy::from_str("or(pk(A),and(pk(B),after(100)))").unwrap();
+        let default: Miniscript<String, Segwitv0> = policy.compile().unwrap();
+        assert_eq!(policy.compile_with_model(&CostModel::default()), Ok(default.clone()));
+
+        let small_script: Miniscript<String, Segwitv0> = policy
+            .compile_with_model(&CostModel::script_size())
+            .unwrap();
+        assert_eq!(small_script.to_string(), "or_d(pkh(A),and_v(v:pkh(B),after(100)))");
+        assert!(small_script.script_size() < default.script_size());
+
+        let small_witness: Miniscript<String, Segwitv0> = policy
+            .compile_with_model(&CostModel::witness_size())
+            .unwrap();
+        assert!(
+            small_witness.max_satisfaction_size().unwrap()
+                < small_script.max_satisfaction_size().unwrap()
+        );
+        assert_eq!(policy.lift().unwrap().sorted(), small_witness.lift().unwrap().sorted());
+
+        let invalid = CostModel::new(-1.0, 1.0);
+        assert_eq!(
+            policy.compile_with_model::<Segwitv0>(&invalid),
+            Err(CompilerError::InvalidCostModel)
+        );
+    }
+    #[test]
+    fn compile_with_constraints() {
+        let policy = SPolicy::from_str("or(pk(A),and(pk(B),after(100)))").unwrap();
+        let default: Miniscript<String, Segwitv0> = policy.compile().unwrap();
+        let unconstrained = CompilerConstraints::default();
+        assert_eq!(policy.compile_with_constraints(&unconstrained), Ok(default.clone()));
+
+        let max_script_size = Some(default.script_size() - 1);
+        let constraints = CompilerConstraints { max_script_size, ..Default::default() };
+        let small: Miniscript<String, Segwitv0> =
+            policy.compile_with_constraints(&constraints).unwrap();
+        assert!(small.script_size() < default.script_size());
+        assert_eq!(policy.lift().unwrap().sorted(), small.lift().unwrap().sorted());
+
+        let constraints = CompilerConstraints { max_script_size: Some(10), ..Default::default() };
+        assert_eq!(
+            policy.compile_with_constraints::<Segwitv0>(&constraints),
+            Err(CompilerError::ConstraintViolated(ConstraintViolation::ScriptSize {
+                size: default.script_size(),
+                max: 10,
+            }))
+        );
+
+        let constraints = CompilerConstraints { no_absolute_timelocks: true, ..Default::default() };
+        assert_eq!(
+            policy.compile_with_constraints::<Segwitv0>(&constraints),
+            Err(CompilerError::ConstraintViolated(ConstraintViolation::AbsoluteTimelock))
+        );
+        let constraints = CompilerConstraints {
+            no_hash_locks: true,
+            no_relative_timelocks: true,
+            ..Default::default()
+        };
+        assert!(policy
+            .compile_with_constraints::<Segwitv0>(&constraints)
+            .is_ok());
+
+        let policy = SPolicy::from_str("thresh(2,pk(A),pk(B),pk(C))").unwrap();
+        let constraints = CompilerConstraints { max_keys: Some(2), ..Default::default() };
+        assert_eq!(
+            policy.compile_with_constraints::<Segwitv0>(&constraints),
+            Err(CompilerError::ConstraintViolated(ConstraintViolation::KeyCount {
+                n: 3,
+                max: 2
+            }))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "compiler-cache")]
+    fn compile_cached() {
+        let mut cache = CompilerCache::<String, Segwitv0>::default();
+        let shared = "and(pk(B),after(100))";
+        let first = SPolicy::from_str(&format!("or(pk(A),{})", shared)).unwrap();
+        let second = SPolicy::from_str(&format!("or(pk(C),{})", shared)).unwrap();
+
+        assert_eq!(first.compile_cached(&mut cache), first.compile());
+        let stats = cache.stats();
+        assert!(stats.misses > 0);
+        assert!(stats.entries > 0);
+
+        // The shared sub-policy is found in the cache
+        assert_eq!(second.compile_cached(&mut cache), second.compile());
+        assert!(cache.stats().hits > stats.hits);
+
+        // Nothing new is compiled a second time
+        let stats = cache.stats();
+        assert_eq!(first.compile_cached(&mut cache), first.compile());
+        assert_eq!(cache.stats().misses, stats.misses);
+        assert_eq!(cache.stats().entries, stats.entries);
+
+        cache.clear();
+        assert_eq!(cache.stats(), CacheStats::default());
+    }
+    #[test]
+    fn compile_deterministic() {
+        let policy = SPolicy::from_str("or(pk(A),and(pk(B),after(100)))").unwrap();
+        let compile = |tie_break| -> Miniscript<String, Segwitv0> {
+            let model = CostModel::default().with_tie_break(tie_break);
+            policy.clone().compile_with_model(&model).unwrap()
+        };
+
+        let first_found = compile(TieBreak::FirstFound);
+        assert_eq!(first_found, policy.compile().unwrap());
+        assert_eq!(first_found.to_string(), "or_d(pk(A),and_v(v:pk(B),after(100)))");
+        // Candidates of equal cost are chosen by the tie-breaking rule
+        let canonical = compile(TieBreak::Canonical);
+        assert_eq!(canonical.to_string(), "andor(pk(B),after(100),pk(A))");
+        assert!(canonical < first_found);
+
+        for tie_break in [
+            TieBreak::FirstFound,
+            TieBreak::SmallestScript,
+            TieBreak::Canonical,
+        ] {
+            assert_eq!(compile(tie_break), compile(tie_break));
+            let reparsed = SPolicy::from_str(&policy.to_string()).unwrap();
+            let model = CostModel::default().with_tie_break(tie_break);
+            assert_eq!(reparsed.compile_with_model(&model), Ok(compile(tie_break)));
+        }
+    }
+    #[test]
+    fn compile_ranked_candidates() {
+        let policy = SPolicy::from_str("or(pk(A),and(pk(B),after(100)))").unwrap();
+        let candidates = policy.compile_candidates::<Segwitv0>(10).unwrap();
+        assert!(candidates.len() > 1);
+        assert_eq!(candidates[0].ms, policy.compile::<Segwitv0>().unwrap());
+        for pair in candidates.windows(2) {
+            assert!(pair[0].cost <= pair[1].cost);
+            assert_ne!(pair[0].ms, pair[1].ms);
+        }
+        for candidate in &candidates {
+            assert_eq!(candidate.script_size, candidate.ms.script_size());
+            assert_eq!(candidate.cost, candidate.script_size as f64 + candidate.expected_sat_size);
+            assert!(candidate.ms.sanity_check().is_ok());
+            assert_eq!(policy.lift().unwrap().sorted(), candidate.ms.lift().unwrap().sorted());
+        }
+
+        assert_eq!(policy.compile_candidates::<Segwitv0>(1).unwrap().len(), 1);
+        assert!(policy.compile_candidates::<Segwitv0>(0).unwrap().is_empty());
+    }
 }
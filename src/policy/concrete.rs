@@ -12,28 +12,43 @@ use bitcoin::absolute;
 use {
     crate::descriptor::TapTree,
     crate::miniscript::ScriptContext,
-    crate::policy::compiler::{self, CompilerError, OrdF64},
+    crate::policy::compiler::{
+        self, CompilerConstraints, CompilerError, CostModel, OrdF64, RankedCompilation,
+    },
+    crate::util::varint_len,
     crate::Descriptor,
     crate::Miniscript,
     crate::Tap,
-    core::cmp::Reverse,
+    bitcoin::taproot::TAPROOT_CONTROL_NODE_SIZE,
+    bitcoin::Weight,
+    core::cmp::{self, Reverse},
 };
 
+use crate::blanket_traits::StaticDebugAndDisplay;
 use crate::expression::{self, FromTree};
 use crate::iter::{Tree, TreeLike};
 use crate::miniscript::types::extra_props::TimelockInfo;
+#[cfg(feature = "compiler-cache")]
+use crate::policy::compiler::CompilerCache;
 use crate::prelude::*;
 use crate::sync::Arc;
 #[cfg(all(doc, not(feature = "compiler")))]
 use crate::Descriptor;
 use crate::{
-    AbsLockTime, Error, ForEachKey, FromStrKey, MiniscriptKey, RelLockTime, Threshold, Translator,
+    AbsLockTime, Error, ForEachKey, FromStrKey, MiniscriptKey, ParseError, RelLockTime, Threshold,
+    Translator,
 };
+#[cfg(feature = "wthresh")]
+use crate::{ParseThresholdError, WeightedThreshold};
 
 /// Maximum TapLeafs allowed in a compiled TapTree
 #[cfg(feature = "compiler")]
 const MAX_COMPILATION_LEAVES: usize = 1024;
 
+/// Maximum number of leaves a weighted threshold may expand to
+#[cfg(feature = "wthresh")]
+const MAX_WEIGHTED_EXPANSION_LEAVES: usize = 1024;
+
 /// Concrete policy which corresponds directly to a miniscript structure,
 /// and whose disjunctions are annotated with satisfaction probabilities
 /// to assist the compiler.
@@ -80,6 +95,8 @@ pub enum PolicyError {
     HeightTimelockCombination,
     /// Duplicate Public Keys.
     DuplicatePubKeys,
+    /// The odds of an `Or` fragment are all zero, or their sum overflows.
+    InvalidOdds,
 }
 
 /// Descriptor context for [`Policy`] compilation into a [`Descriptor`].
@@ -97,6 +114,52 @@ pub enum DescriptorCtx<Pk> {
     Tr(Option<Pk>),
 }
 
+/// How [`Policy::compile_tr_with_internal_key`] chooses the internal key of the
+/// compiled [`Descriptor::Tr`].
+#[cfg(feature = "compiler")]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum TrInternalKey<Pk> {
+    /// Promotes the most probable key of the policy to the key path, falling back to the
+    /// given unspendable key if the policy has no key which can be promoted. This is what
+    /// [`Policy::compile_tr`] does.
+    Extract(Option<Pk>),
+    /// Like [`TrInternalKey::Extract`], but only the `eligible` keys may be promoted to the
+    /// key path; every other key stays in a leaf.
+    ExtractFrom {
+        /// The keys which may be promoted to the key path.
+        eligible: Vec<Pk>,
+        /// The internal key to use if none of the `eligible` keys can be promoted.
+        unspendable: Option<Pk>,
+    },
+    /// Uses the given key as the internal key. If the policy can be satisfied by this key
+    /// alone, the corresponding branches are removed from the leaves as if the key had been
+    /// extracted; otherwise the whole policy is compiled into leaves.
+    Explicit(Pk),
+    /// Never extracts a key: the given key, typically a NUMS point, is used as the internal
+    /// key and the whole policy is compiled into leaves.
+    Unspendable(Pk),
+}
+
+/// A descriptor produced by [`Policy::compile_best`], along with the weight of
+/// creating and spending an output locked to it.
+#[cfg(feature = "compiler")]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CompilationCandidate<Pk: MiniscriptKey> {
+    /// The compiled descriptor.
+    pub descriptor: Descriptor<Pk>,
+    /// Weight of a transaction output paying to the descriptor.
+    pub output_weight: Weight,
+    /// Weight of the largest satisfaction of the descriptor, as computed by
+    /// [`Descriptor::max_weight_to_satisfy`].
+    pub satisfaction_weight: Weight,
+}
+
+#[cfg(feature = "compiler")]
+impl<Pk: MiniscriptKey> CompilationCandidate<Pk> {
+    /// The total weight of creating and then spending an output locked to the descriptor.
+    pub fn total_weight(&self) -> Weight { self.output_weight + self.satisfaction_weight }
+}
+
 impl fmt::Display for PolicyError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -108,17 +171,24 @@ impl fmt::Display for PolicyError {
                 f.write_str("Cannot lift policies that have a heightlock and timelock combination")
             }
             PolicyError::DuplicatePubKeys => f.write_str("Policy contains duplicate keys"),
+            PolicyError::InvalidOdds => {
+                f.write_str("Or policy fragment odds must not all be zero or overflow")
+            }
         }
     }
 }
 
 #[cfg(feature = "std")]
 impl error::Error for PolicyError {
-    fn cause(&self) -> Option<&dyn error::Error> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         use self::PolicyError::*;
 
         match self {
-            NonBinaryArgAnd | NonBinaryArgOr | HeightTimelockCombination | DuplicatePubKeys => None,
+            NonBinaryArgAnd
+            | NonBinaryArgOr
+            | HeightTimelockCombination
+            | DuplicatePubKeys
+            | InvalidOdds => None,
         }
     }
 }
@@ -187,10 +257,24 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
     /// Extracts the internal_key from this policy tree.
     #[cfg(feature = "compiler")]
     fn extract_key(self, unspendable_key: Option<Pk>) -> Result<(Pk, Policy<Pk>), CompilerError> {
+        self.extract_key_from(|_| true, unspendable_key)
+    }
+
+    /// Extracts the most probable key for which `eligible` returns `true` as the internal_key
+    /// from this policy tree.
+    #[cfg(feature = "compiler")]
+    fn extract_key_from<F>(
+        self,
+        eligible: F,
+        unspendable_key: Option<Pk>,
+    ) -> Result<(Pk, Policy<Pk>), CompilerError>
+    where
+        F: Fn(&Pk) -> bool,
+    {
         let internal_key = self
             .tapleaf_probability_iter()
             .filter_map(|(prob, ref pol)| match pol {
-                Policy::Key(pk) => Some((OrdF64(prob), pk)),
+                Policy::Key(pk) if eligible(pk) => Some((OrdF64(prob), pk)),
                 _ => None,
             })
             .max_by_key(|(prob, _)| *prob)
@@ -203,6 +287,33 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
         }
     }
 
+    /// Chooses the internal key for a taproot compilation of this policy, along with the
+    /// policy which remains to be compiled into leaves.
+    #[cfg(feature = "compiler")]
+    fn choose_internal_key(
+        self,
+        internal_key: TrInternalKey<Pk>,
+    ) -> Result<(Pk, Policy<Pk>), CompilerError> {
+        match internal_key {
+            TrInternalKey::Extract(unspendable) => self.extract_key(unspendable),
+            TrInternalKey::ExtractFrom { eligible, unspendable } => {
+                self.extract_key_from(|pk| eligible.contains(pk), unspendable)
+            }
+            TrInternalKey::Explicit(key) => {
+                let is_leaf_key = self
+                    .tapleaf_probability_iter()
+                    .any(|(_, pol)| matches!(pol, Policy::Key(pk) if *pk == key));
+                if is_leaf_key {
+                    let policy = self.translate_unsatisfiable_pk(&key);
+                    Ok((key, policy))
+                } else {
+                    Ok((key, self))
+                }
+            }
+            TrInternalKey::Unspendable(key) => Ok((key, self)),
+        }
+    }
+
     /// Compiles the [`Policy`] into a [`Descriptor::Tr`].
     ///
     /// ### TapTree compilation
@@ -219,15 +330,53 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
     /// Refer to [this link](https://gist.github.com/SarcasticNastik/9e70b2b43375aab3e78c51e09c288c89)
     /// or [doc/Tr compiler.pdf] in the root of the repository to understand why such compilation
     /// is also *cost-efficient*.
+    ///
+    /// ### Threshold splitting
+    ///
+    /// A `thresh(k, ..n..)` of keys in the vector, with `1 < k < n`, is split into one leaf per
+    /// subset of `k` keys if that lowers the cost of satisfying it, i.e. if the size saved by
+    /// leaving out the `n - k` other keys from the script and witness exceeds the size of the
+    /// extra control block nodes. This is typically the case for large federations with a low
+    /// threshold. The total number of leaves is still limited to `MAX_COMPILATION_LEAVES`.
     // TODO: We might require other compile errors for Taproot.
     #[cfg(feature = "compiler")]
     pub fn compile_tr(&self, unspendable_key: Option<Pk>) -> Result<Descriptor<Pk>, CompilerError> {
+        self.compile_tr_with_internal_key(TrInternalKey::Extract(unspendable_key))
+    }
+
+    /// Compiles the [`Policy`] into a [`Descriptor::Tr`], choosing the internal key as
+    /// described by `internal_key`.
+    ///
+    /// The leaves are compiled as in [`Policy::compile_tr`], which is equivalent to passing
+    /// [`TrInternalKey::Extract`]. The other variants allow keeping keys out of the key path,
+    /// either entirely or all but a given set of them, or using a known internal key.
+    #[cfg(feature = "compiler")]
+    pub fn compile_tr_with_internal_key(
+        &self,
+        internal_key: TrInternalKey<Pk>,
+    ) -> Result<Descriptor<Pk>, CompilerError> {
+        self.compile_tr_with_constraints(internal_key, &CompilerConstraints::default())
+    }
+
+    /// Compiles the [`Policy`] into a [`Descriptor::Tr`] as in
+    /// [`Policy::compile_tr_with_internal_key`], with every leaf respecting `constraints`.
+    ///
+    /// # Errors
+    ///
+    /// [`CompilerError::ConstraintViolated`] if the policy contains a forbidden fragment, or
+    /// if no compilation of some leaf respects the size and key-count limits.
+    #[cfg(feature = "compiler")]
+    pub fn compile_tr_with_constraints(
+        &self,
+        internal_key: TrInternalKey<Pk>,
+        constraints: &CompilerConstraints,
+    ) -> Result<Descriptor<Pk>, CompilerError> {
         self.is_valid().map_err(CompilerError::PolicyError)?;
         match self.is_safe_nonmalleable() {
             (false, _) => Err(CompilerError::TopLevelNonSafe),
             (_, false) => Err(CompilerError::ImpossibleNonMalleableCompilation),
             _ => {
-                let (internal_key, policy) = self.clone().extract_key(unspendable_key)?;
+                let (internal_key, policy) = self.clone().choose_internal_key(internal_key)?;
                 policy.check_num_tapleaves()?;
                 let tree = Descriptor::new_tr(
                     internal_key,
@@ -235,16 +384,32 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
                         Policy::Trivial => None,
                         policy => {
                             let mut leaf_compilations: Vec<(OrdF64, Miniscript<Pk, Tap>)> = vec![];
+                            let mut n_leaves = policy.num_tap_leaves();
                             for (prob, pol) in policy.tapleaf_probability_iter() {
                                 // policy corresponding to the key (replaced by unsatisfiable) is skipped
                                 if *pol == Policy::Unsatisfiable {
                                     continue;
                                 }
-                                let compilation = compiler::best_compilation::<Pk, Tap>(pol)?;
+                                let compilation = compiler::best_compilation_with_constraints::<
+                                    Pk,
+                                    Tap,
+                                >(
+                                    pol, &CostModel::default(), constraints
+                                )?;
                                 compilation
                                     .sanity_check()
                                     .expect("compiler produces sane output");
-                                leaf_compilations.push((OrdF64(prob), compilation));
+                                let max_split = MAX_COMPILATION_LEAVES - n_leaves + 1;
+                                match pol.split_key_thresh(&compilation, max_split)? {
+                                    Some(split) => {
+                                        n_leaves += split.len() - 1;
+                                        let split_prob = prob / split.len() as f64;
+                                        leaf_compilations.extend(
+                                            split.into_iter().map(|ms| (OrdF64(split_prob), ms)),
+                                        );
+                                    }
+                                    None => leaf_compilations.push((OrdF64(prob), compilation)),
+                                }
                             }
                             if !leaf_compilations.is_empty() {
                                 let tap_tree = with_huffman_tree::<Pk>(leaf_compilations);
@@ -354,6 +519,58 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
         }
     }
 
+    /// Compiles the policy for every descriptor context in which it is legal, and ranks
+    /// the results by [`CompilationCandidate::total_weight`], cheapest first.
+    ///
+    /// Bare, `sh`, `wsh`, `sh(wsh)` and `tr` are tried in that order, which is also the order
+    /// of candidates with equal weights. Contexts the policy cannot be compiled for, e.g.
+    /// because the result would exceed their resource limits, are left out. `unspendable_key`
+    /// is used as in [`Policy::compile_tr`].
+    ///
+    /// # Errors
+    ///
+    /// If the policy cannot be compiled for any context, the error from the first one.
+    #[cfg(feature = "compiler")]
+    pub fn compile_best(
+        &self,
+        unspendable_key: Option<Pk>,
+    ) -> Result<Vec<CompilationCandidate<Pk>>, Error> {
+        let ctxs = vec![
+            DescriptorCtx::Bare,
+            DescriptorCtx::Sh,
+            DescriptorCtx::Wsh,
+            DescriptorCtx::ShWsh,
+            DescriptorCtx::Tr(unspendable_key),
+        ];
+        let mut candidates = Vec::with_capacity(ctxs.len());
+        let mut first_err = None;
+        for desc_ctx in ctxs {
+            let candidate = self
+                .compile_to_descriptor::<Tap>(desc_ctx)
+                .and_then(|descriptor| {
+                    let satisfaction_weight = descriptor.max_weight_to_satisfy()?;
+                    let spk_len = script_pubkey_len(&descriptor);
+                    let output_weight = Weight::from_non_witness_data_size(
+                        (8 + varint_len(spk_len) + spk_len) as u64,
+                    );
+                    Ok(CompilationCandidate { descriptor, output_weight, satisfaction_weight })
+                });
+            match candidate {
+                Ok(candidate) => candidates.push(candidate),
+                Err(e) => {
+                    first_err.get_or_insert(e);
+                }
+            }
+        }
+        match first_err {
+            Some(e) if candidates.is_empty() => Err(e),
+            _ => {
+                candidates.sort_by_key(CompilationCandidate::total_weight);
+                Ok(candidates)
+            }
+        }
+    }
+
     /// Compiles the descriptor into an optimized `Miniscript` representation.
     ///
     /// # NOTE:
@@ -370,6 +587,98 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
             _ => compiler::best_compilation(self),
         }
     }
+
+    /// Compiles the descriptor into an optimized `Miniscript` representation, using the
+    /// given [`CostModel`] to compare candidate compilations instead of the default one.
+    ///
+    /// This allows, e.g., favoring smaller scripts over smaller witnesses, or vice versa.
+    #[cfg(feature = "compiler")]
+    pub fn compile_with_model<Ctx: ScriptContext>(
+        &self,
+        model: &CostModel,
+    ) -> Result<Miniscript<Pk, Ctx>, CompilerError> {
+        self.is_valid()?;
+        match self.is_safe_nonmalleable() {
+            (false, _) => Err(CompilerError::TopLevelNonSafe),
+            (_, false) => Err(CompilerError::ImpossibleNonMalleableCompilation),
+            _ => compiler::best_compilation_with_model(self, model),
+        }
+    }
+
+    /// Compiles the descriptor into an optimized `Miniscript` representation which respects
+    /// `constraints`, e.g. the limitations of a signing device.
+    ///
+    /// The result is that of [`Policy::compile`] if it respects the constraints, and otherwise
+    /// the best compilation which does.
+    ///
+    /// # Errors
+    ///
+    /// [`CompilerError::ConstraintViolated`] if the policy contains a forbidden fragment, or
+    /// if no compilation respects the size and key-count limits.
+    #[cfg(feature = "compiler")]
+    pub fn compile_with_constraints<Ctx: ScriptContext>(
+        &self,
+        constraints: &CompilerConstraints,
+    ) -> Result<Miniscript<Pk, Ctx>, CompilerError> {
+        self.is_valid()?;
+        match self.is_safe_nonmalleable() {
+            (false, _) => Err(CompilerError::TopLevelNonSafe),
+            (_, false) => Err(CompilerError::ImpossibleNonMalleableCompilation),
+            _ => compiler::best_compilation_with_constraints(
+                self,
+                &CostModel::default(),
+                constraints,
+            ),
+        }
+    }
+
+    /// Compiles the descriptor into up to `n` distinct `Miniscript` representations, ranked
+    /// from the best under the default [`CostModel`], along with their cost metrics.
+    ///
+    /// The first candidate is the result of [`Policy::compile`]. The others let callers
+    /// apply their own criteria, e.g. a cap on the script size. See
+    /// [`compiler::ranked_compilations`] for details.
+    #[cfg(feature = "compiler")]
+    pub fn compile_candidates<Ctx: ScriptContext>(
+        &self,
+        n: usize,
+    ) -> Result<Vec<RankedCompilation<Pk, Ctx>>, CompilerError> {
+        self.is_valid()?;
+        match self.is_safe_nonmalleable() {
+            (false, _) => Err(CompilerError::TopLevelNonSafe),
+            (_, false) => Err(CompilerError::ImpossibleNonMalleableCompilation),
+            _ => compiler::ranked_compilations(self, &CostModel::default(), n),
+        }
+    }
+
+    /// Compiles the descriptor into an optimized `Miniscript` representation, reusing the
+    /// compilations of sub-policies in `cache` and adding the new ones to it.
+    ///
+    /// The result is the same as [`Policy::compile_with_model`] with the model of the cache.
+    #[cfg(feature = "compiler-cache")]
+    pub fn compile_cached<Ctx: ScriptContext>(
+        &self,
+        cache: &mut CompilerCache<Pk, Ctx>,
+    ) -> Result<Miniscript<Pk, Ctx>, CompilerError> {
+        self.is_valid()?;
+        match self.is_safe_nonmalleable() {
+            (false, _) => Err(CompilerError::TopLevelNonSafe),
+            (_, false) => Err(CompilerError::ImpossibleNonMalleableCompilation),
+            _ => compiler::best_compilation_cached(self, cache),
+        }
+    }
+}
+
+/// The length of the scriptPubKey of a descriptor.
+#[cfg(feature = "compiler")]
+fn script_pubkey_len<Pk: MiniscriptKey>(descriptor: &Descriptor<Pk>) -> usize {
+    match *descriptor {
+        Descriptor::Bare(ref bare) => bare.as_inner().script_size(),
+        Descriptor::Pkh(..) => 25,
+        Descriptor::Wpkh(..) => 22,
+        Descriptor::Sh(..) => 23,
+        Descriptor::Wsh(..) | Descriptor::Tr(..) => 34,
+    }
 }
 
 #[cfg(feature = "compiler")]
@@ -400,6 +709,57 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
         }
     }
 
+    /// Splits a `thresh(k, ..n..)` of keys into one taproot leaf per subset of `k` keys, if
+    /// that is cheaper than `compilation`, the compilation of the whole threshold.
+    ///
+    /// Returns `None` if the policy is not such a threshold, if splitting would not lower the
+    /// cost, or if it would need more than `max_leaves` leaves.
+    fn split_key_thresh(
+        &self,
+        compilation: &Miniscript<Pk, Tap>,
+        max_leaves: usize,
+    ) -> Result<Option<Vec<Miniscript<Pk, Tap>>>, CompilerError> {
+        let thresh = match *self {
+            Policy::Thresh(ref thresh) if !thresh.is_and() && !thresh.is_or() => thresh,
+            _ => return Ok(None),
+        };
+        if !thresh.iter().all(|sub| matches!(**sub, Policy::Key(_))) {
+            return Ok(None);
+        }
+        let subsets = match key_subsets(thresh.n(), thresh.k(), max_leaves) {
+            Some(subsets) => subsets,
+            None => return Ok(None),
+        };
+
+        let compile_subset = |subset: &[usize]| {
+            let subs = subset
+                .iter()
+                .map(|&i| Arc::clone(&thresh.data()[i]))
+                .collect();
+            let and = Threshold::new(subset.len(), subs).expect("k is at least 1");
+            compiler::best_compilation::<Pk, Tap>(&Policy::Thresh(and))
+        };
+        // Every subset leaf has the same cost, so it suffices to compile one of them
+        let leaf_cost = |ms: &Miniscript<Pk, Tap>| {
+            ms.script_size()
+                + ms.max_satisfaction_size()
+                    .expect("compiled leaf is satisfiable")
+        };
+        let subset_compilation = compile_subset(&subsets[0])?;
+        let extra_depth = subsets.len().next_power_of_two().trailing_zeros() as usize;
+        let split_cost = leaf_cost(&subset_compilation) + extra_depth * TAPROOT_CONTROL_NODE_SIZE;
+        if split_cost >= leaf_cost(compilation) {
+            return Ok(None);
+        }
+
+        let mut ret = Vec::with_capacity(subsets.len());
+        ret.push(subset_compilation);
+        for subset in &subsets[1..] {
+            ret.push(compile_subset(subset)?);
+        }
+        Ok(Some(ret))
+    }
+
     /// Generates a root-level disjunctive tree over the given policy tree.
     ///
     /// Uses a fixed-point algorithm to enumerate the disjunctions until exhaustive root-level
@@ -589,6 +949,52 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
             .collect()
     }
 
+    /// Gets every sub-policy, in pre-order, along with the probability that it is
+    /// satisfied when spending, as assumed by the compiler.
+    ///
+    /// Probabilities propagate multiplicatively from the root, which is always satisfied:
+    /// the branches of an `Or` are weighted by their odds, the sub-policies of an `And`
+    /// are satisfied whenever it is, and each sub-policy of a k-of-n `Thresh` is satisfied
+    /// with probability k/n. The odds must be valid, see [`Policy::check_odds`].
+    pub fn satisfaction_probabilities(&self) -> Vec<(f64, &Policy<Pk>)> {
+        let mut ret = vec![];
+        let mut stack = vec![(1.0, self)];
+        while let Some((prob, policy)) = stack.pop() {
+            ret.push((prob, policy));
+            match *policy {
+                Policy::Or(ref subs) => {
+                    let total = subs.iter().map(|(odds, _)| *odds as f64).sum::<f64>();
+                    for (odds, sub) in subs.iter().rev() {
+                        stack.push((prob * *odds as f64 / total, sub));
+                    }
+                }
+                Policy::And(ref subs) => stack.extend(subs.iter().rev().map(|sub| (prob, &**sub))),
+                Policy::Thresh(ref thresh) => {
+                    let k_over_n = thresh.k() as f64 / thresh.n() as f64;
+                    stack.extend(thresh.iter().rev().map(|sub| (prob * k_over_n, &**sub)));
+                }
+                _ => {}
+            }
+        }
+        ret
+    }
+
+    /// Checks that the odds of every `Or` fragment are consistent, i.e. that they are not
+    /// all zero and that their sum does not overflow.
+    pub fn check_odds(&self) -> Result<(), PolicyError> {
+        for policy in self.pre_order_iter() {
+            if let Policy::Or(ref subs) = *policy {
+                let total = subs
+                    .iter()
+                    .try_fold(0usize, |acc, (odds, _)| acc.checked_add(*odds));
+                if total.unwrap_or(0) == 0 {
+                    return Err(PolicyError::InvalidOdds);
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Gets the number of [TapLeaf](`TapTree::Leaf`)s considering exhaustive root-level [`Policy::Or`]
     /// and [`Policy::Thresh`] disjunctions for the `TapTree`.
     #[cfg(feature = "compiler")]
@@ -688,6 +1094,7 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
 
         self.check_timelocks()?;
         self.check_duplicate_keys()?;
+        self.check_odds()?;
 
         for policy in self.pre_order_iter() {
             match *policy {
@@ -757,6 +1164,32 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
     }
 }
 
+#[cfg(feature = "wthresh")]
+impl<Pk: MiniscriptKey> Policy<Pk> {
+    /// Constructs the policy of a weighted threshold, written
+    /// `wthresh(k,w@sub,...)`, which is satisfied when sub-policies of total
+    /// weight at least `k` are.
+    ///
+    /// There is no weighted fragment in Script, so the weighted threshold is
+    /// expanded into nested thresholds by [`WeightedThreshold::expand`], and
+    /// is displayed as such. The expansion is limited to
+    /// `MAX_WEIGHTED_EXPANSION_LEAVES` leaves.
+    pub fn weighted_threshold(
+        thresh: &WeightedThreshold<Arc<Policy<Pk>>>,
+    ) -> Result<Policy<Pk>, ParseThresholdError> {
+        let policy = thresh
+            .expand(MAX_WEIGHTED_EXPANSION_LEAVES, Arc::clone, |thresh| {
+                Arc::new(match thresh.n() {
+                    2 if thresh.is_and() => Policy::And(thresh.into_data()),
+                    2 => Policy::Or(thresh.into_iter().map(|sub| (1, sub)).collect()),
+                    _ => Policy::Thresh(thresh),
+                })
+            })
+            .ok_or(ParseThresholdError::WeightedTooLarge(MAX_WEIGHTED_EXPANSION_LEAVES))?;
+        Ok(Arc::try_unwrap(policy).unwrap_or_else(|policy| (*policy).clone()))
+    }
+}
+
 impl<Pk: MiniscriptKey> fmt::Debug for Policy<Pk> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -841,6 +1274,98 @@ impl<Pk: FromStrKey> str::FromStr for Policy<Pk> {
     }
 }
 
+impl<Pk: FromStrKey> Policy<Pk> {
+    /// Parses a policy in which keys may be written as aliases from `aliases`, rather than
+    /// in full, e.g. `and(pk(alice),pk(bob))`.
+    ///
+    /// Names which are not in `aliases` are parsed as keys. The aliases can be restored for
+    /// display with [`Policy::to_string_with_aliases`].
+    pub fn from_str_with_aliases(
+        s: &str,
+        aliases: &BTreeMap<String, Pk>,
+    ) -> Result<Policy<Pk>, Error> {
+        struct AliasLookUp<'a, Pk>(&'a BTreeMap<String, Pk>);
+
+        fn parse<T>(s: &str) -> Result<T, Error>
+        where
+            T: str::FromStr,
+            T::Err: StaticDebugAndDisplay,
+        {
+            T::from_str(s).map_err(|e| Error::Parse(ParseError::box_from_str(e)))
+        }
+
+        impl<Pk: FromStrKey> Translator<String> for AliasLookUp<'_, Pk> {
+            type TargetPk = Pk;
+            type Error = Error;
+
+            fn pk(&mut self, name: &String) -> Result<Pk, Error> {
+                match self.0.get(name) {
+                    Some(pk) => Ok(pk.clone()),
+                    None => parse(name),
+                }
+            }
+
+            fn sha256(&mut self, hash: &String) -> Result<Pk::Sha256, Error> { parse(hash) }
+
+            fn hash256(&mut self, hash: &String) -> Result<Pk::Hash256, Error> { parse(hash) }
+
+            fn ripemd160(&mut self, hash: &String) -> Result<Pk::Ripemd160, Error> { parse(hash) }
+
+            fn hash160(&mut self, hash: &String) -> Result<Pk::Hash160, Error> { parse(hash) }
+        }
+
+        let policy: Policy<String> = s.parse()?;
+        policy.translate_pk(&mut AliasLookUp(aliases))
+    }
+}
+
+impl<Pk: MiniscriptKey> Policy<Pk> {
+    /// Formats the policy with every key which appears in `aliases` replaced by its alias.
+    ///
+    /// If a key has several aliases, the first in the order of the map is used. This is the
+    /// inverse of [`Policy::from_str_with_aliases`].
+    pub fn to_string_with_aliases(&self, aliases: &BTreeMap<String, Pk>) -> String {
+        struct AliasReplace<'a, Pk>(BTreeMap<&'a Pk, &'a String>);
+
+        impl<Pk: MiniscriptKey> Translator<Pk> for AliasReplace<'_, Pk> {
+            type TargetPk = String;
+            type Error = core::convert::Infallible;
+
+            fn pk(&mut self, pk: &Pk) -> Result<String, Self::Error> {
+                match self.0.get(pk) {
+                    Some(alias) => Ok((*alias).clone()),
+                    None => Ok(pk.to_string()),
+                }
+            }
+
+            fn sha256(&mut self, hash: &Pk::Sha256) -> Result<String, Self::Error> {
+                Ok(hash.to_string())
+            }
+
+            fn hash256(&mut self, hash: &Pk::Hash256) -> Result<String, Self::Error> {
+                Ok(hash.to_string())
+            }
+
+            fn ripemd160(&mut self, hash: &Pk::Ripemd160) -> Result<String, Self::Error> {
+                Ok(hash.to_string())
+            }
+
+            fn hash160(&mut self, hash: &Pk::Hash160) -> Result<String, Self::Error> {
+                Ok(hash.to_string())
+            }
+        }
+
+        let mut by_key = BTreeMap::new();
+        for (alias, pk) in aliases {
+            by_key.entry(pk).or_insert(alias);
+        }
+        match self.translate_pk(&mut AliasReplace(by_key)) {
+            Ok(policy) => policy.to_string(),
+            Err(infallible) => match infallible {},
+        }
+    }
+}
+
 serde_string_impl_pk!(Policy, "a miniscript concrete policy");
 
 impl<Pk: FromStrKey> expression::FromTree for Policy<Pk> {
@@ -864,10 +1389,11 @@ impl<Pk: FromStrKey> expression::FromTree for Policy<Pk> {
                     .name_separated('@')
                     .map_err(From::from)
                     .map_err(Error::Parse)?;
-                if node.is_first_child() && parent_name == "thresh" {
+                if node.is_first_child() && matches!(parent_name, "thresh" | "wthresh") {
                     continue;
                 }
-                allow_prob = parent_name == "or";
+                // The children of weighted thresholds are prefixed by their weight
+                allow_prob = parent_name == "or" || parent_name == "wthresh";
             } else {
                 allow_prob = false;
             }
@@ -939,6 +1465,22 @@ impl<Pk: FromStrKey> expression::FromTree for Policy<Pk> {
                     "thresh" => node
                         .verify_threshold(|_| Ok(stack.pop().unwrap().1))
                         .map(Self::Thresh),
+                    #[cfg(feature = "wthresh")]
+                    "wthresh" => {
+                        let kchild = node.first_child().ok_or(ParseThresholdError::NoChildren)?;
+                        if kchild.n_children() > 0 {
+                            return Err(ParseThresholdError::KNotTerminal.into());
+                        }
+                        let k = expression::parse_num(kchild.name())
+                            .map_err(ParseThresholdError::ParseK)?;
+                        let subs = (1..node.n_children())
+                            .map(|_| stack.pop().unwrap())
+                            .collect();
+                        WeightedThreshold::new(k as usize, subs)
+                            .map_err(ParseThresholdError::Threshold)
+                            .and_then(|thresh| Self::weighted_threshold(&thresh))
+                            .map_err(From::from)
+                    }
                     x => Err(Error::Parse(crate::ParseError::Tree(
                         crate::ParseTreeError::UnknownName { name: x.to_owned() },
                     ))),
@@ -1005,6 +1547,37 @@ fn generate_combination<Pk: MiniscriptKey>(
     ret
 }
 
+/// Enumerates the subsets of `k` of the indices `0..n` in lexicographic order, or returns
+/// `None` if there are more than `max` of them.
+#[cfg(feature = "compiler")]
+fn key_subsets(n: usize, k: usize, max: usize) -> Option<Vec<Vec<usize>>> {
+    // Compute the binomial coefficient incrementally, stopping as soon as it exceeds `max`.
+    // Using the smaller of `k` and `n - k` keeps the partial products increasing.
+    let mut count = 1usize;
+    for i in 0..cmp::min(k, n - k) {
+        count = count.checked_mul(n - i)? / (i + 1);
+        if count > max {
+            return None;
+        }
+    }
+
+    let mut ret = Vec::with_capacity(count);
+    let mut subset: Vec<usize> = (0..k).collect();
+    loop {
+        ret.push(subset.clone());
+        // Find the rightmost index which can still be incremented
+        match (0..k).rev().find(|&i| subset[i] < n - k + i) {
+            Some(i) => {
+                subset[i] += 1;
+                for j in i + 1..k {
+                    subset[j] = subset[j - 1] + 1;
+                }
+            }
+            None => return Some(ret),
+        }
+    }
+}
+
 /// A type used within the iterator API to abstract over the two kinds
 /// of n-ary nodes in a [`Policy`].
 ///
@@ -1132,6 +1705,41 @@ mod compiler_tests {
         // pk(A) promoted to the internal key, leaving the script tree empty
         assert_eq!(desc.to_string(), "tr(A)#xyg3grex");
     }
+
+    #[test]
+    fn compile_best() {
+        let policy: Policy<String> = policy_str!("pk(A)");
+        let candidates = policy.compile_best(None).unwrap();
+        let descs = candidates
+            .iter()
+            .map(|c| c.descriptor.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            descs,
+            vec![
+                "tr(A)#xyg3grex",
+                "wsh(pk(A))#5wp9m2z3",
+                "sh(wsh(pk(A)))#98dq8r8s",
+                "pk(A)#cpa0ghz7",
+                "sh(pk(A))#k80zhe7s",
+            ]
+        );
+        assert!(candidates
+            .windows(2)
+            .all(|w| w[0].total_weight() <= w[1].total_weight()));
+        assert_eq!(candidates[0].output_weight.to_wu(), 4 * (8 + 1 + 34));
+
+        // Too many keys for a legacy `multi`, and too large for a legacy script
+        let keys = (0..25).map(|i| format!("pk(K{})", i)).collect::<Vec<_>>();
+        let policy: Policy<String> = policy_str!("thresh(25,{})", keys.join(","));
+        let candidates = policy.compile_best(Some("UNSPENDABLE".into())).unwrap();
+        assert!(candidates
+            .iter()
+            .all(|c| !matches!(c.descriptor, Descriptor::Bare(..))));
+
+        let policy: Policy<String> = policy_str!("or(pk(A),pk(A))");
+        assert!(policy.compile_best(None).is_err());
+    }
 }
 
 #[cfg(test)]
@@ -1227,4 +1835,213 @@ mod tests {
         // This implicitly tests the check_timelocks API (has height and time locks).
         let _ = Policy::<String>::from_str("and(after(10),after(500000000))").unwrap();
     }
+
+    #[test]
+    #[cfg(feature = "compiler")]
+    fn key_subsets_enumeration() {
+        assert_eq!(
+            key_subsets(4, 2, 10).unwrap(),
+            vec![
+                vec![0, 1],
+                vec![0, 2],
+                vec![0, 3],
+                vec![1, 2],
+                vec![1, 3],
+                vec![2, 3]
+            ]
+        );
+        assert_eq!(key_subsets(3, 3, 1).unwrap(), vec![vec![0, 1, 2]]);
+        assert_eq!(key_subsets(20, 2, 190).unwrap().len(), 190);
+        assert!(key_subsets(20, 2, 189).is_none());
+        assert_eq!(key_subsets(20, 18, 190).unwrap().len(), 190);
+        assert!(key_subsets(200, 100, 1024).is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "compiler")]
+    fn compile_tr_split_thresh() {
+        let keys: Vec<String> = (0..20).map(|i| format!("K{}", i)).collect();
+        let pks = keys
+            .iter()
+            .map(|k| format!("pk({})", k))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        // A large federation with a low threshold is split into one leaf per pair of keys
+        let policy = Policy::<String>::from_str(&format!("thresh(2,{})", pks)).unwrap();
+        let desc = policy.compile_tr(Some("UNSPEND".to_owned())).unwrap();
+        let tr = match desc {
+            Descriptor::Tr(ref tr) => tr,
+            _ => unreachable!(),
+        };
+        let leaves: Vec<_> = tr.iter_scripts().collect();
+        assert_eq!(leaves.len(), 190);
+        assert!(leaves
+            .iter()
+            .all(|(depth, ms)| *depth <= 8 && ms.iter_pk().count() == 2));
+        let split_cost = leaves[0].1.script_size() + leaves[0].1.max_satisfaction_size().unwrap();
+        let single = compiler::best_compilation::<String, Tap>(&policy).unwrap();
+        let single_cost = single.script_size() + single.max_satisfaction_size().unwrap();
+        assert!(split_cost + 8 * TAPROOT_CONTROL_NODE_SIZE < single_cost);
+
+        // A high threshold is cheaper as a single leaf
+        let policy = Policy::<String>::from_str(&format!("thresh(18,{})", pks)).unwrap();
+        let desc = policy.compile_tr(Some("UNSPEND".to_owned())).unwrap();
+        assert_eq!(desc.to_string().matches("multi_a(18,").count(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "compiler")]
+    fn compile_tr_internal_key_choice() {
+        let policy = Policy::<String>::from_str("or(9@pk(A),1@or(3@pk(B),1@pk(C)))").unwrap();
+        let compile = |internal_key| {
+            policy
+                .compile_tr_with_internal_key(internal_key)
+                .unwrap()
+                .to_string()
+        };
+        let default = policy.compile_tr(None).unwrap().to_string();
+        assert_eq!(compile(TrInternalKey::Extract(None)), default);
+        assert!(default.starts_with("tr(A,"));
+        assert!(!default.contains("pk(A)"));
+
+        let desc = compile(TrInternalKey::ExtractFrom {
+            eligible: vec!["B".to_owned(), "C".to_owned()],
+            unspendable: None,
+        });
+        assert!(desc.starts_with("tr(B,"));
+        assert!(desc.contains("pk(A)") && !desc.contains("pk(B)"));
+
+        let err = policy
+            .compile_tr_with_internal_key(TrInternalKey::ExtractFrom {
+                eligible: vec!["D".to_owned()],
+                unspendable: None,
+            })
+            .unwrap_err();
+        assert_eq!(err, CompilerError::NoInternalKey);
+
+        let desc = compile(TrInternalKey::Unspendable("NUMS".to_owned()));
+        assert!(desc.starts_with("tr(NUMS,"));
+        assert!(desc.contains("pk(A)") && desc.contains("pk(B)") && desc.contains("pk(C)"));
+
+        let desc = compile(TrInternalKey::Explicit("C".to_owned()));
+        assert!(desc.starts_with("tr(C,"));
+        assert!(desc.contains("pk(A)") && !desc.contains("pk(C)"));
+        let desc = compile(TrInternalKey::Explicit("D".to_owned()));
+        assert!(desc.starts_with("tr(D,"));
+        assert!(desc.contains("pk(A)") && desc.contains("pk(C)"));
+    }
+
+    #[test]
+    #[cfg(feature = "compiler")]
+    fn compile_tr_constraints() {
+        let policy = Policy::<String>::from_str(
+            "or(pk(A),or(and(pk(B),older(144)),and(pk(C),sha256(ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff))))",
+        )
+        .unwrap();
+        let internal_key = TrInternalKey::Extract(None);
+        let constraints = CompilerConstraints { max_keys: Some(1), ..Default::default() };
+        let desc = policy
+            .compile_tr_with_constraints(internal_key.clone(), &constraints)
+            .unwrap();
+        assert_eq!(desc, policy.compile_tr(None).unwrap());
+
+        let constraints = CompilerConstraints { no_hash_locks: true, ..Default::default() };
+        assert_eq!(
+            policy.compile_tr_with_constraints(internal_key, &constraints),
+            Err(CompilerError::ConstraintViolated(compiler::ConstraintViolation::HashLock))
+        );
+    }
+    #[test]
+    fn aliases() {
+        let alice = bitcoin::PublicKey::from_str(
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+        )
+        .unwrap();
+        let bob = bitcoin::PublicKey::from_str(
+            "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+        )
+        .unwrap();
+        let carol = "03500a2b48b0f66c8183cc0d6645ab21cc19c7fad8a33ff04d41c3ece54b0bc1c5";
+        let aliases: BTreeMap<String, bitcoin::PublicKey> =
+            [("alice".to_owned(), alice), ("bob".to_owned(), bob)].into();
+
+        let s = format!("or(99@pk(alice),1@and(pk(bob),or(1@pk({}),1@older(144))))", carol);
+        let policy = Policy::from_str_with_aliases(&s, &aliases).unwrap();
+        let expected = Policy::<bitcoin::PublicKey>::from_str(
+            &s.replace("alice", &alice.to_string())
+                .replace("bob", &bob.to_string()),
+        )
+        .unwrap();
+        assert_eq!(policy, expected);
+        assert_eq!(policy.to_string_with_aliases(&aliases), s);
+
+        assert!(Policy::from_str_with_aliases("pk(dave)", &aliases).is_err());
+        assert!(Policy::from_str_with_aliases("sha256(alice)", &aliases).is_err());
+    }
+
+    #[test]
+    fn satisfaction_probabilities() {
+        let policy =
+            Policy::<String>::from_str("or(3@pk(A),1@and(pk(B),thresh(1,pk(C),pk(D))))").unwrap();
+        let probs = policy
+            .satisfaction_probabilities()
+            .into_iter()
+            .map(|(prob, pol)| (prob, pol.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            probs,
+            vec![
+                (1.0, policy.to_string()),
+                (0.75, "pk(A)".to_string()),
+                (0.25, "and(pk(B),thresh(1,pk(C),pk(D)))".to_string()),
+                (0.25, "pk(B)".to_string()),
+                (0.25, "thresh(1,pk(C),pk(D))".to_string()),
+                (0.125, "pk(C)".to_string()),
+                (0.125, "pk(D)".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_odds() {
+        let policy = Policy::<String>::from_str("or(0@pk(A),1@pk(B))").unwrap();
+        assert_eq!(policy.check_odds(), Ok(()));
+        let policy = Policy::<String>::from_str("and(pk(C),or(0@pk(A),0@pk(B)))").unwrap();
+        assert_eq!(policy.check_odds(), Err(PolicyError::InvalidOdds));
+        assert_eq!(policy.is_valid(), Err(PolicyError::InvalidOdds));
+
+        let policy = Policy::Or(vec![
+            (usize::MAX, Arc::new(Policy::Key("A".to_string()))),
+            (1, Arc::new(Policy::Key("B".to_string()))),
+        ]);
+        assert_eq!(policy.check_odds(), Err(PolicyError::InvalidOdds));
+    }
+
+    #[test]
+    #[cfg(feature = "wthresh")]
+    fn weighted_threshold() {
+        // The chair and one member, or all three members
+        let policy = Policy::<String>::from_str("wthresh(3,2@pk(C),pk(A),pk(B),pk(D))").unwrap();
+        assert_eq!(
+            policy.to_string(),
+            "or(1@and(pk(C),thresh(1,pk(A),pk(B),pk(D))),1@thresh(3,pk(A),pk(B),pk(D)))"
+        );
+        assert!(Policy::<String>::from_str("wthresh(7,3@pk(C),pk(A),pk(B),pk(D))").is_err());
+        assert!(Policy::<String>::from_str("wthresh(pk(A),pk(B))").is_err());
+
+        // Uniform weights give a plain threshold
+        let policy = Policy::<String>::from_str("wthresh(4,2@pk(A),2@pk(B),2@pk(C))").unwrap();
+        assert_eq!(policy, Policy::<String>::from_str("thresh(2,pk(A),pk(B),pk(C))").unwrap());
+
+        // Distinct weights blow up the expansion, which is capped
+        let subs: Vec<String> = (1..=24).map(|i| format!("{}@pk(K{})", i, i)).collect();
+        let s = format!("wthresh(150,{})", subs.join(","));
+        assert!(matches!(
+            Policy::<String>::from_str(&s),
+            Err(Error::ParseThreshold(ParseThresholdError::WeightedTooLarge(
+                MAX_WEIGHTED_EXPANSION_LEAVES
+            )))
+        ));
+    }
 }
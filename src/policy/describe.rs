@@ -0,0 +1,447 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Policy Descriptions
+//!
+//! Renders semantic policies as plain-English summaries of who can spend and
+//! when, e.g. for display in wallet interfaces. The summaries are built from
+//! structured data which can be rendered differently, e.g. to localize them.
+//!
+
+use core::fmt;
+
+use bitcoin::{absolute, relative};
+
+use super::{Liftable, Semantic};
+use crate::prelude::*;
+use crate::{AbsLockTime, Descriptor, Error, MiniscriptKey, RelLockTime};
+
+/// A UTC date and time, as given by an absolute timelock.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct DateTime {
+    /// The year.
+    pub year: u32,
+    /// The month, from 1 to 12.
+    pub month: u8,
+    /// The day of the month, from 1 to 31.
+    pub day: u8,
+    /// The hour, from 0 to 23.
+    pub hour: u8,
+    /// The minute, from 0 to 59.
+    pub minute: u8,
+    /// The second, from 0 to 59.
+    pub second: u8,
+}
+
+impl DateTime {
+    /// Converts a UNIX timestamp to a date and time.
+    pub fn from_unix_timestamp(timestamp: u32) -> Self {
+        // Days to civil date conversion from http://howardhinnant.github.io/date_algorithms.html
+        let secs = timestamp % 86400;
+        let z = timestamp / 86400 + 719468;
+        let era = z / 146097;
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        DateTime {
+            year: yoe + era * 400 + u32::from(month <= 2),
+            month: month as u8,
+            day: day as u8,
+            hour: (secs / 3600) as u8,
+            minute: (secs / 60 % 60) as u8,
+            second: (secs % 60) as u8,
+        }
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)?;
+        if (self.hour, self.minute, self.second) != (0, 0, 0) {
+            write!(f, " {:02}:{:02}:{:02} UTC", self.hour, self.minute, self.second)?;
+        }
+        Ok(())
+    }
+}
+
+/// A timelock of a spending path, in human terms.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum TimeCondition {
+    /// Once the chain has reached the given block height.
+    AtHeight(u32),
+    /// Once the median time past of the chain has reached the given date.
+    AtTime(DateTime),
+    /// Once the coins have the given number of confirmations.
+    AfterBlocks(u16),
+    /// Once the given number of seconds have elapsed since the coins were confirmed.
+    AfterSeconds(u32),
+}
+
+impl From<AbsLockTime> for TimeCondition {
+    fn from(t: AbsLockTime) -> Self {
+        match absolute::LockTime::from(t) {
+            absolute::LockTime::Blocks(h) => TimeCondition::AtHeight(h.to_consensus_u32()),
+            absolute::LockTime::Seconds(t) => {
+                TimeCondition::AtTime(DateTime::from_unix_timestamp(t.to_consensus_u32()))
+            }
+        }
+    }
+}
+
+impl From<RelLockTime> for TimeCondition {
+    fn from(t: RelLockTime) -> Self {
+        match relative::LockTime::from(t) {
+            relative::LockTime::Blocks(h) => TimeCondition::AfterBlocks(h.value()),
+            relative::LockTime::Time(t) => TimeCondition::AfterSeconds(u32::from(t.value()) * 512),
+        }
+    }
+}
+
+impl fmt::Display for TimeCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TimeCondition::AtHeight(h) => write!(f, "after block {}", h),
+            TimeCondition::AtTime(t) => write!(f, "after {}", t),
+            TimeCondition::AfterBlocks(n) => write!(f, "once the coins are {} blocks old", n),
+            TimeCondition::AfterSeconds(n) => {
+                let (n, unit) = match n {
+                    n if n % 86400 == 0 => (n / 86400, "days"),
+                    n if n % 3600 == 0 => (n / 3600, "hours"),
+                    n if n % 60 == 0 => (n / 60, "minutes"),
+                    n => (n, "seconds"),
+                };
+                write!(f, "once the coins are {} {} old", n, unit)
+            }
+        }
+    }
+}
+
+/// A hash whose preimage must be revealed on a spending path.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum HashLock<Pk: MiniscriptKey> {
+    /// A SHA256 hash.
+    Sha256(Pk::Sha256),
+    /// A SHA256d hash.
+    Hash256(Pk::Hash256),
+    /// A RIPEMD160 hash.
+    Ripemd160(Pk::Ripemd160),
+    /// A HASH160 hash.
+    Hash160(Pk::Hash160),
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for HashLock<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HashLock::Sha256(ref h) => write!(f, "the SHA256 preimage of {}", h),
+            HashLock::Hash256(ref h) => write!(f, "the HASH256 preimage of {}", h),
+            HashLock::Ripemd160(ref h) => write!(f, "the RIPEMD160 preimage of {}", h),
+            HashLock::Hash160(ref h) => write!(f, "the HASH160 preimage of {}", h),
+        }
+    }
+}
+
+/// A set of keys which must sign on a spending path.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum Signers<Pk: MiniscriptKey> {
+    /// A single key.
+    One(Pk),
+    /// Any `k` of the keys.
+    Threshold {
+        /// The number of keys required.
+        k: usize,
+        /// The keys.
+        keys: Vec<Pk>,
+    },
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for Signers<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Signers::One(ref pk) => write!(f, "{}", pk),
+            Signers::Threshold { k, ref keys } => {
+                write!(f, "{} of {{", k)?;
+                write_list(f, keys, ", ")?;
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+/// The conditions of a single spending path, all of which must be met.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub struct PathDescription<Pk: MiniscriptKey> {
+    /// The keys which must sign.
+    pub signers: Vec<Signers<Pk>>,
+    /// The hashes whose preimages must be revealed.
+    pub hash_locks: Vec<HashLock<Pk>>,
+    /// The timelocks which must have expired.
+    pub timelocks: Vec<TimeCondition>,
+}
+
+impl<Pk: MiniscriptKey> PathDescription<Pk> {
+    fn empty() -> Self {
+        PathDescription { signers: vec![], hash_locks: vec![], timelocks: vec![] }
+    }
+
+    fn merge(&mut self, other: PathDescription<Pk>) {
+        self.signers.extend(other.signers);
+        self.hash_locks.extend(other.hash_locks);
+        self.timelocks.extend(other.timelocks);
+    }
+
+    /// Formats the path; a `standalone` path is a full alternative of the policy, and
+    /// is worded as such.
+    fn fmt_path(&self, f: &mut fmt::Formatter, standalone: bool) -> fmt::Result {
+        match self.signers[..] {
+            [] => f.write_str("anyone")?,
+            [Signers::One(ref pk)] if standalone => write!(f, "{} alone", pk)?,
+            ref signers => write_list(f, signers, " and ")?,
+        }
+        if !self.hash_locks.is_empty() {
+            f.write_str(" revealing ")?;
+            write_list(f, &self.hash_locks, " and ")?;
+        }
+        if !self.timelocks.is_empty() {
+            f.write_str(" ")?;
+            write_list(f, &self.timelocks, " and ")
+        } else if standalone {
+            f.write_str(" at any time")
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for PathDescription<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { self.fmt_path(f, true) }
+}
+
+/// A structured, human-readable description of a spending policy.
+///
+/// Its [`fmt::Display`] implementation renders it in plain English. Wallets which
+/// need other wordings or languages can render the structure themselves.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum PolicyDescription<Pk: MiniscriptKey> {
+    /// Spendable by anyone.
+    Anyone,
+    /// Not spendable.
+    Nobody,
+    /// A single spending path.
+    Path(PathDescription<Pk>),
+    /// Any of the descriptions.
+    Any(Vec<PolicyDescription<Pk>>),
+    /// All of the descriptions.
+    All(Vec<PolicyDescription<Pk>>),
+    /// Any `k` of the descriptions.
+    Threshold(usize, Vec<PolicyDescription<Pk>>),
+}
+
+impl<Pk: MiniscriptKey> PolicyDescription<Pk> {
+    fn fmt_nested(&self, f: &mut fmt::Formatter, standalone: bool) -> fmt::Result {
+        let (label, subs) = match *self {
+            PolicyDescription::Anyone => return f.write_str("anyone"),
+            PolicyDescription::Nobody => return f.write_str("nobody"),
+            PolicyDescription::Path(ref path) => return path.fmt_path(f, standalone),
+            PolicyDescription::Any(ref subs) => (String::from("any"), subs),
+            PolicyDescription::All(ref subs) => (String::from("all"), subs),
+            PolicyDescription::Threshold(k, ref subs) => (k.to_string(), subs),
+        };
+        write!(f, "{} of (", label)?;
+        for (i, sub) in subs.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            sub.fmt_nested(f, false)?;
+        }
+        f.write_str(")")
+    }
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for PolicyDescription<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Spendable by: ")?;
+        match *self {
+            PolicyDescription::Any(ref subs) => {
+                for (i, sub) in subs.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", OR ")?;
+                    }
+                    sub.fmt_nested(f, true)?;
+                }
+                Ok(())
+            }
+            _ => self.fmt_nested(f, true),
+        }
+    }
+}
+
+fn write_list<T: fmt::Display>(f: &mut fmt::Formatter, items: &[T], sep: &str) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            f.write_str(sep)?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+fn path<Pk: MiniscriptKey>(f: impl FnOnce(&mut PathDescription<Pk>)) -> PolicyDescription<Pk> {
+    let mut path = PathDescription::empty();
+    f(&mut path);
+    PolicyDescription::Path(path)
+}
+
+fn describe<Pk: MiniscriptKey>(policy: &Semantic<Pk>) -> PolicyDescription<Pk> {
+    let thresh = match *policy {
+        Semantic::Unsatisfiable => return PolicyDescription::Nobody,
+        Semantic::Trivial => return PolicyDescription::Anyone,
+        Semantic::Key(ref pk) => return path(|p| p.signers.push(Signers::One(pk.clone()))),
+        Semantic::After(t) => return path(|p| p.timelocks.push(t.into())),
+        Semantic::Older(t) => return path(|p| p.timelocks.push(t.into())),
+        Semantic::Sha256(ref h) => return path(|p| p.hash_locks.push(HashLock::Sha256(h.clone()))),
+        Semantic::Hash256(ref h) => {
+            return path(|p| p.hash_locks.push(HashLock::Hash256(h.clone())))
+        }
+        Semantic::Ripemd160(ref h) => {
+            return path(|p| p.hash_locks.push(HashLock::Ripemd160(h.clone())))
+        }
+        Semantic::Hash160(ref h) => {
+            return path(|p| p.hash_locks.push(HashLock::Hash160(h.clone())))
+        }
+        Semantic::Thresh(ref thresh) => thresh,
+    };
+
+    let keys = thresh
+        .iter()
+        .map(|sub| match **sub {
+            Semantic::Key(ref pk) => Some(pk.clone()),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>();
+    if let (Some(keys), false) = (keys, thresh.is_or()) {
+        return path(|p| {
+            if thresh.is_and() {
+                p.signers.extend(keys.into_iter().map(Signers::One));
+            } else {
+                p.signers.push(Signers::Threshold { k: thresh.k(), keys });
+            }
+        });
+    }
+
+    let subs = thresh.iter().map(|sub| describe(sub)).collect::<Vec<_>>();
+    if thresh.is_or() {
+        PolicyDescription::Any(subs)
+    } else if thresh.is_and() {
+        // Merge the sub-policies which are single paths into a single path
+        let mut merged = PathDescription::empty();
+        let mut rest = vec![];
+        for sub in subs {
+            match sub {
+                PolicyDescription::Path(path) => merged.merge(path),
+                sub => rest.push(sub),
+            }
+        }
+        if rest.is_empty() {
+            PolicyDescription::Path(merged)
+        } else {
+            if merged != PathDescription::empty() {
+                rest.insert(0, PolicyDescription::Path(merged));
+            }
+            PolicyDescription::All(rest)
+        }
+    } else {
+        PolicyDescription::Threshold(thresh.k(), subs)
+    }
+}
+
+impl<Pk: MiniscriptKey> Semantic<Pk> {
+    /// Returns a human-readable description of who can spend under the policy, and when.
+    ///
+    /// The policy is normalized first. The description renders in plain English, e.g.
+    /// `Spendable by: A alone after 2025-01-01, OR 2 of {B, C, D} at any time`.
+    pub fn describe(&self) -> PolicyDescription<Pk> { describe(&self.clone().normalized()) }
+}
+
+impl<Pk: MiniscriptKey> Descriptor<Pk> {
+    /// Returns a human-readable description of who can spend the descriptor, and when.
+    ///
+    /// See [`Semantic::describe`] for details.
+    pub fn describe(&self) -> Result<PolicyDescription<Pk>, Error> { Ok(self.lift()?.describe()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+
+    fn describe(s: &str) -> String {
+        Semantic::<String>::from_str(s)
+            .unwrap()
+            .describe()
+            .to_string()
+    }
+
+    #[test]
+    fn date_time() {
+        assert_eq!(DateTime::from_unix_timestamp(1735689600).to_string(), "2025-01-01");
+        assert_eq!(DateTime::from_unix_timestamp(951782400).to_string(), "2000-02-29");
+        assert_eq!(
+            DateTime::from_unix_timestamp(1700000000).to_string(),
+            "2023-11-14 22:13:20 UTC"
+        );
+    }
+
+    #[test]
+    fn describe_policies() {
+        assert_eq!(
+            describe("or(and(pk(Alice),after(1735689600)),thresh(2,pk(Bob),pk(Carol),pk(Dave)))"),
+            "Spendable by: Alice alone after 2025-01-01, OR 2 of {Bob, Carol, Dave} at any time"
+        );
+        assert_eq!(describe("pk(A)"), "Spendable by: A alone at any time");
+        assert_eq!(describe("and(pk(A),pk(B))"), "Spendable by: A and B at any time");
+        assert_eq!(
+            describe("and(pk(A),and(older(144),after(800000)))"),
+            "Spendable by: A alone once the coins are 144 blocks old and after block 800000"
+        );
+        assert_eq!(
+            describe("and(sha256(H),older(4194529))"),
+            "Spendable by: anyone revealing the SHA256 preimage of H \
+             once the coins are 32 hours old"
+        );
+        assert_eq!(
+            describe("and(pk(A),or(pk(B),pk(C)))"),
+            "Spendable by: all of (A; any of (B; C))"
+        );
+        assert_eq!(describe("UNSATISFIABLE"), "Spendable by: nobody");
+        assert_eq!(describe("TRIVIAL"), "Spendable by: anyone");
+    }
+
+    #[test]
+    fn describe_structure() {
+        let policy = Semantic::<String>::from_str("or(pk(A),and(pk(B),older(1000)))").unwrap();
+        assert_eq!(
+            policy.describe(),
+            PolicyDescription::Any(vec![
+                PolicyDescription::Path(PathDescription {
+                    signers: vec![Signers::One("A".into())],
+                    hash_locks: vec![],
+                    timelocks: vec![],
+                }),
+                PolicyDescription::Path(PathDescription {
+                    signers: vec![Signers::One("B".into())],
+                    hash_locks: vec![],
+                    timelocks: vec![TimeCondition::AfterBlocks(1000)],
+                }),
+            ])
+        );
+
+        let desc = Descriptor::<String>::from_str("wsh(sortedmulti(2,A,B,C))").unwrap();
+        assert_eq!(
+            desc.describe().unwrap().to_string(),
+            "Spendable by: 2 of {A, B, C} at any time"
+        );
+    }
+}
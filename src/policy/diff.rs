@@ -0,0 +1,330 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Policy Diffs
+//!
+//! Compares two semantic policies, e.g. those of a descriptor and of its
+//! replacement, and reports how their spending conditions differ.
+//!
+
+use core::{cmp, fmt};
+
+use super::{HashLock, Liftable, Semantic, TemplateTimelock};
+use crate::prelude::*;
+use crate::{Descriptor, Error, MiniscriptKey};
+
+/// A single difference between two policies.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum PolicyChange<Pk: MiniscriptKey> {
+    /// A key was added.
+    KeyAdded(Pk),
+    /// A key was removed.
+    KeyRemoved(Pk),
+    /// A threshold changed its number of required or of total sub-policies.
+    ThresholdChanged {
+        /// The previous `(k, n)` of the threshold.
+        from: (usize, usize),
+        /// The new `(k, n)` of the threshold.
+        to: (usize, usize),
+    },
+    /// A timelock was added.
+    TimelockAdded(TemplateTimelock),
+    /// A timelock was removed.
+    TimelockRemoved(TemplateTimelock),
+    /// A timelock was changed to another of the same kind.
+    TimelockChanged {
+        /// The previous timelock.
+        from: TemplateTimelock,
+        /// The new timelock.
+        to: TemplateTimelock,
+    },
+    /// A hash lock was added.
+    HashLockAdded(HashLock<Pk>),
+    /// A hash lock was removed.
+    HashLockRemoved(HashLock<Pk>),
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for PolicyChange<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PolicyChange::KeyAdded(ref pk) => write!(f, "added key {}", pk),
+            PolicyChange::KeyRemoved(ref pk) => write!(f, "removed key {}", pk),
+            PolicyChange::ThresholdChanged { from, to } => {
+                write!(f, "changed threshold from {}-of-{} to {}-of-{}", from.0, from.1, to.0, to.1)
+            }
+            PolicyChange::TimelockAdded(t) => write!(f, "added timelock {}", t),
+            PolicyChange::TimelockRemoved(t) => write!(f, "removed timelock {}", t),
+            PolicyChange::TimelockChanged { from, to } => {
+                write!(f, "changed timelock from {} to {}", from, to)
+            }
+            PolicyChange::HashLockAdded(ref h) => write!(f, "added requirement for {}", h),
+            PolicyChange::HashLockRemoved(ref h) => write!(f, "removed requirement for {}", h),
+        }
+    }
+}
+
+/// The differences between two policies, as returned by [`Semantic::diff`].
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct PolicyDiff<Pk: MiniscriptKey> {
+    /// The changes, in the order they were found by walking both policies.
+    pub changes: Vec<PolicyChange<Pk>>,
+}
+
+impl<Pk: MiniscriptKey> PolicyDiff<Pk> {
+    /// Whether the policies are semantically equivalent, up to normalization.
+    pub fn is_empty(&self) -> bool { self.changes.is_empty() }
+
+    /// The keys which were added.
+    pub fn keys_added(&self) -> impl Iterator<Item = &Pk> {
+        self.changes.iter().filter_map(|change| match *change {
+            PolicyChange::KeyAdded(ref pk) => Some(pk),
+            _ => None,
+        })
+    }
+
+    /// The keys which were removed.
+    pub fn keys_removed(&self) -> impl Iterator<Item = &Pk> {
+        self.changes.iter().filter_map(|change| match *change {
+            PolicyChange::KeyRemoved(ref pk) => Some(pk),
+            _ => None,
+        })
+    }
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for PolicyDiff<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.changes.is_empty() {
+            return f.write_str("no changes");
+        }
+        for (i, change) in self.changes.iter().enumerate() {
+            if i > 0 {
+                f.write_str("\n")?;
+            }
+            write!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether the two policies are of the same kind, so that they should be compared with
+/// each other rather than reported as removed and added.
+fn same_kind<Pk: MiniscriptKey>(a: &Semantic<Pk>, b: &Semantic<Pk>) -> bool {
+    matches!(
+        (a, b),
+        (Semantic::Key(_), Semantic::Key(_))
+            | (Semantic::After(_), Semantic::After(_))
+            | (Semantic::Older(_), Semantic::Older(_))
+            | (Semantic::Thresh(_), Semantic::Thresh(_))
+    )
+}
+
+/// Records the leaves of `policy` as added or removed.
+fn record<Pk: MiniscriptKey>(
+    policy: &Semantic<Pk>,
+    added: bool,
+    changes: &mut Vec<PolicyChange<Pk>>,
+) {
+    let change = match (policy, added) {
+        (Semantic::Unsatisfiable, _) | (Semantic::Trivial, _) => return,
+        (Semantic::Thresh(thresh), _) => {
+            for sub in thresh.iter() {
+                record(sub, added, changes);
+            }
+            return;
+        }
+        (Semantic::Key(pk), true) => PolicyChange::KeyAdded(pk.clone()),
+        (Semantic::Key(pk), false) => PolicyChange::KeyRemoved(pk.clone()),
+        (Semantic::After(t), true) => PolicyChange::TimelockAdded(TemplateTimelock::Absolute(*t)),
+        (Semantic::After(t), false) => {
+            PolicyChange::TimelockRemoved(TemplateTimelock::Absolute(*t))
+        }
+        (Semantic::Older(t), true) => PolicyChange::TimelockAdded(TemplateTimelock::Relative(*t)),
+        (Semantic::Older(t), false) => {
+            PolicyChange::TimelockRemoved(TemplateTimelock::Relative(*t))
+        }
+        (_, added) => {
+            let hash_lock = match *policy {
+                Semantic::Sha256(ref h) => HashLock::Sha256(h.clone()),
+                Semantic::Hash256(ref h) => HashLock::Hash256(h.clone()),
+                Semantic::Ripemd160(ref h) => HashLock::Ripemd160(h.clone()),
+                Semantic::Hash160(ref h) => HashLock::Hash160(h.clone()),
+                _ => unreachable!("other policies handled above"),
+            };
+            if added {
+                PolicyChange::HashLockAdded(hash_lock)
+            } else {
+                PolicyChange::HashLockRemoved(hash_lock)
+            }
+        }
+    };
+    changes.push(change);
+}
+
+/// Walks both policies in parallel, recording their differences.
+fn walk<Pk: MiniscriptKey>(
+    old: &Semantic<Pk>,
+    new: &Semantic<Pk>,
+    changes: &mut Vec<PolicyChange<Pk>>,
+) {
+    if old == new {
+        return;
+    }
+    match (old, new) {
+        (Semantic::After(a), Semantic::After(b)) => changes.push(PolicyChange::TimelockChanged {
+            from: TemplateTimelock::Absolute(*a),
+            to: TemplateTimelock::Absolute(*b),
+        }),
+        (Semantic::Older(a), Semantic::Older(b)) => changes.push(PolicyChange::TimelockChanged {
+            from: TemplateTimelock::Relative(*a),
+            to: TemplateTimelock::Relative(*b),
+        }),
+        (Semantic::Thresh(a), Semantic::Thresh(b)) => {
+            if (a.k(), a.n()) != (b.k(), b.n()) {
+                changes.push(PolicyChange::ThresholdChanged {
+                    from: (a.k(), a.n()),
+                    to: (b.k(), b.n()),
+                });
+            }
+            // Set aside the sub-policies present in both, then pair up the remaining
+            // ones of the same kind, in order.
+            let mut news = b.iter().map(Some).collect::<Vec<_>>();
+            let mut olds = vec![];
+            for sub in a.iter() {
+                match news.iter().position(|n| *n == Some(sub)) {
+                    Some(i) => news[i] = None,
+                    None => olds.push(sub),
+                }
+            }
+            for sub in olds {
+                let pos = news
+                    .iter()
+                    .position(|n| n.map_or(false, |n| same_kind(sub, n)));
+                match pos.and_then(|i| news[i].take()) {
+                    Some(n) => walk(sub, n, changes),
+                    None => record(sub, false, changes),
+                }
+            }
+            for sub in news.into_iter().flatten() {
+                record(sub, true, changes);
+            }
+        }
+        _ => {
+            record(old, false, changes);
+            record(new, true, changes);
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey> Semantic<Pk> {
+    /// Returns the differences between this policy and `new`.
+    ///
+    /// Both policies are normalized first. Sub-policies are matched up structurally,
+    /// so that e.g. a changed timelock is reported as such rather than as a removal and
+    /// an addition. Keys, timelocks and hash locks which merely moved within the policy
+    /// are not reported.
+    pub fn diff(&self, new: &Semantic<Pk>) -> PolicyDiff<Pk> {
+        let mut changes = vec![];
+        walk(&self.clone().normalized(), &new.clone().normalized(), &mut changes);
+
+        // Drop leaves which were removed in one place and added in another
+        let mut i = 0;
+        while i < changes.len() {
+            let opposite = match changes[i] {
+                PolicyChange::KeyAdded(ref pk) => PolicyChange::KeyRemoved(pk.clone()),
+                PolicyChange::KeyRemoved(ref pk) => PolicyChange::KeyAdded(pk.clone()),
+                PolicyChange::TimelockAdded(t) => PolicyChange::TimelockRemoved(t),
+                PolicyChange::TimelockRemoved(t) => PolicyChange::TimelockAdded(t),
+                PolicyChange::HashLockAdded(ref h) => PolicyChange::HashLockRemoved(h.clone()),
+                PolicyChange::HashLockRemoved(ref h) => PolicyChange::HashLockAdded(h.clone()),
+                _ => {
+                    i += 1;
+                    continue;
+                }
+            };
+            match changes.iter().position(|c| *c == opposite) {
+                Some(j) => {
+                    changes.remove(cmp::max(i, j));
+                    changes.remove(cmp::min(i, j));
+                }
+                None => i += 1,
+            }
+        }
+        PolicyDiff { changes }
+    }
+}
+
+impl<Pk: MiniscriptKey> Descriptor<Pk> {
+    /// Returns the differences between the policies of this descriptor and of `new`.
+    ///
+    /// See [`Semantic::diff`] for details.
+    pub fn policy_diff(&self, new: &Descriptor<Pk>) -> Result<PolicyDiff<Pk>, Error> {
+        Ok(self.lift()?.diff(&new.lift()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+    use crate::RelLockTime;
+
+    fn diff(old: &str, new: &str) -> PolicyDiff<String> {
+        let old = Semantic::<String>::from_str(old).unwrap();
+        old.diff(&Semantic::from_str(new).unwrap())
+    }
+
+    #[test]
+    fn diff_policies() {
+        assert!(diff("or(pk(A),pk(B))", "or(pk(B),pk(A))").is_empty());
+
+        let changes = diff("thresh(2,pk(A),pk(B),pk(C))", "thresh(3,pk(A),pk(B),pk(D),pk(E))");
+        assert_eq!(
+            changes.changes,
+            vec![
+                PolicyChange::ThresholdChanged { from: (2, 3), to: (3, 4) },
+                PolicyChange::KeyRemoved("C".into()),
+                PolicyChange::KeyAdded("D".into()),
+                PolicyChange::KeyAdded("E".into()),
+            ]
+        );
+        assert_eq!(changes.keys_added().collect::<Vec<_>>(), vec!["D", "E"]);
+        assert_eq!(changes.keys_removed().collect::<Vec<_>>(), vec!["C"]);
+        assert_eq!(
+            changes.to_string(),
+            "changed threshold from 2-of-3 to 3-of-4\nremoved key C\nadded key D\nadded key E"
+        );
+
+        assert_eq!(
+            diff("or(pk(A),and(pk(B),older(144)))", "or(pk(A),and(pk(B),older(1000)))").changes,
+            vec![PolicyChange::TimelockChanged {
+                from: TemplateTimelock::Relative(RelLockTime::from_height(144)),
+                to: TemplateTimelock::Relative(RelLockTime::from_height(1000)),
+            }]
+        );
+
+        // A key moving between branches is not reported as removed and added
+        assert_eq!(
+            diff("or(pk(A),and(pk(B),older(144)))", "or(pk(B),and(pk(A),older(144)))").changes,
+            vec![]
+        );
+
+        assert_eq!(
+            diff("or(pk(A),pk(B))", "or(pk(A),and(pk(B),sha256(H)))").to_string(),
+            "added requirement for the SHA256 preimage of H"
+        );
+        assert_eq!(diff("pk(A)", "pk(A)").to_string(), "no changes");
+    }
+
+    #[test]
+    fn diff_descriptors() {
+        let old = Descriptor::<String>::from_str("wsh(sortedmulti(2,A,B,C))").unwrap();
+        let new = Descriptor::<String>::from_str("tr(A,multi_a(2,B,C,D))").unwrap();
+        assert_eq!(
+            old.policy_diff(&new).unwrap().changes,
+            vec![
+                PolicyChange::ThresholdChanged { from: (2, 3), to: (1, 2) },
+                PolicyChange::KeyAdded("D".into()),
+            ]
+        );
+    }
+}
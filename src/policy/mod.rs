@@ -16,16 +16,23 @@ use std::error;
 #[cfg(feature = "compiler")]
 pub mod compiler;
 pub mod concrete;
+mod describe;
+mod diff;
 pub mod semantic;
+mod template;
 
 pub use self::concrete::Policy as Concrete;
+pub use self::describe::{
+    DateTime, HashLock, PathDescription, PolicyDescription, Signers, TimeCondition,
+};
+pub use self::diff::{PolicyChange, PolicyDiff};
 pub use self::semantic::Policy as Semantic;
-use crate::descriptor::Descriptor;
+pub use self::template::{PolicyTemplate, TemplateTimelock};
+use crate::descriptor::{Descriptor, ShInner, TapTree, WshInner};
 use crate::iter::TreeLike as _;
 use crate::miniscript::{Miniscript, ScriptContext};
+use crate::prelude::*;
 use crate::sync::Arc;
-#[cfg(all(not(feature = "std"), not(test)))]
-use crate::Vec;
 use crate::{Error, MiniscriptKey, Terminal, Threshold};
 
 /// Policy entailment algorithm maximum number of terminals allowed.
@@ -47,6 +54,9 @@ const ENTAILMENT_MAX_TERMINALS: usize = 20;
 /// exceed resource limits for any compilation but cannot detect such policies
 /// while lifting. Note that our compiler would not succeed for any such
 /// policies.
+///
+/// To find out which information was discarded, use the `lift_report` methods
+/// of [`Miniscript`], [`Descriptor`] and [`Concrete`].
 pub trait Liftable<Pk: MiniscriptKey> {
     /// Converts this object into an abstract policy.
     fn lift(&self) -> Result<Semantic<Pk>, Error>;
@@ -61,6 +71,12 @@ pub enum LiftError {
     BranchExceedResourceLimits,
     /// Cannot lift raw descriptors.
     RawDescriptorLift,
+    /// A PSBT input has neither a witness script, a redeem script nor taproot fields.
+    MissingScript,
+    /// The key of a pay-to-witness-pubkey-hash redeem script is not in the PSBT input.
+    MissingKey,
+    /// Cannot lift tap leaves of versions other than tapscript.
+    UnknownLeafVersion(bitcoin::taproot::LeafVersion),
 }
 
 impl fmt::Display for LiftError {
@@ -73,17 +89,116 @@ impl fmt::Display for LiftError {
                 "Cannot lift policies containing one branch that exceeds resource limits",
             ),
             LiftError::RawDescriptorLift => f.write_str("Cannot lift raw descriptors"),
+            LiftError::MissingScript => f.write_str("PSBT input has no script to lift"),
+            LiftError::MissingKey => {
+                f.write_str("PSBT input does not contain the key of its p2wpkh script")
+            }
+            LiftError::UnknownLeafVersion(ver) => {
+                write!(f, "Cannot lift tap leaves of version {}", ver)
+            }
         }
     }
 }
 
 #[cfg(feature = "std")]
 impl error::Error for LiftError {
-    fn cause(&self) -> Option<&dyn error::Error> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         use self::LiftError::*;
 
         match self {
-            HeightTimelockCombination | BranchExceedResourceLimits | RawDescriptorLift => None,
+            HeightTimelockCombination
+            | BranchExceedResourceLimits
+            | RawDescriptorLift
+            | MissingScript
+            | MissingKey
+            | UnknownLeafVersion(_) => None,
+        }
+    }
+}
+
+/// A kind of information which is discarded when lifting into a [`Semantic`] policy.
+///
+/// Lifting does not collapse duplicate branches, merge timelocks or reorder
+/// branches, so these differences remain visible in the lifted policy.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum LiftLoss {
+    /// How the policy is encoded in Script, e.g. the choice of fragments and
+    /// wrappers, `pk` versus `pkh` or `multi` versus `sortedmulti`.
+    ScriptEncoding,
+    /// The type of the descriptor's output, e.g. `sh`, `wsh` or `tr`.
+    OutputType,
+    /// Which key is the Taproot internal key, and the shape of the Taproot tree.
+    TapTreeShape,
+    /// The probabilities of the branches of a concrete `or`.
+    Probabilities,
+    /// Nested thresholds which were merged into their parent, e.g. `and(A,and(B,C))`
+    /// becoming `and(A,B,C)`, and single-branch thresholds replaced by their branch.
+    Flattening,
+    /// Branches which were removed because they are trivially satisfied or unsatisfiable.
+    TrivialBranches,
+}
+
+impl fmt::Display for LiftLoss {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LiftLoss::ScriptEncoding => f.write_str("script encoding"),
+            LiftLoss::OutputType => f.write_str("output type"),
+            LiftLoss::TapTreeShape => f.write_str("taproot tree shape"),
+            LiftLoss::Probabilities => f.write_str("branch probabilities"),
+            LiftLoss::Flattening => f.write_str("nested threshold structure"),
+            LiftLoss::TrivialBranches => f.write_str("trivial or unsatisfiable branches"),
+        }
+    }
+}
+
+/// A lifted [`Semantic`] policy, together with the information discarded while lifting it.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct LiftReport<Pk: MiniscriptKey> {
+    policy: Semantic<Pk>,
+    losses: BTreeSet<LiftLoss>,
+}
+
+impl<Pk: MiniscriptKey> LiftReport<Pk> {
+    fn new(policy: Semantic<Pk>, losses: BTreeSet<LiftLoss>) -> Self {
+        LiftReport { policy, losses }
+    }
+
+    /// The lifted policy, as returned by [`Liftable::lift`].
+    pub fn policy(&self) -> &Semantic<Pk> { &self.policy }
+
+    /// Converts into the lifted policy.
+    pub fn into_policy(self) -> Semantic<Pk> { self.policy }
+
+    /// The kinds of information which were discarded.
+    pub fn losses(&self) -> &BTreeSet<LiftLoss> { &self.losses }
+
+    /// Whether the given kind of information was discarded.
+    pub fn discards(&self, loss: LiftLoss) -> bool { self.losses.contains(&loss) }
+}
+
+/// Adds the losses incurred by normalizing `raw`, an unnormalized lifted policy.
+fn normalization_losses<Pk: MiniscriptKey>(raw: &Semantic<Pk>, losses: &mut BTreeSet<LiftLoss>) {
+    for data in raw.pre_order_iter() {
+        if let Semantic::Thresh(ref thresh) = *data {
+            let is_and = thresh.k() == thresh.n();
+            let is_or = thresh.k() == 1;
+            if thresh.n() == 1 {
+                losses.insert(LiftLoss::Flattening);
+            }
+            for sub in thresh.iter() {
+                match **sub {
+                    Semantic::Trivial | Semantic::Unsatisfiable => {
+                        losses.insert(LiftLoss::TrivialBranches);
+                    }
+                    Semantic::Thresh(ref subthresh)
+                        if (is_and && subthresh.k() == subthresh.n())
+                            || (is_or && subthresh.k() == 1) =>
+                    {
+                        losses.insert(LiftLoss::Flattening);
+                    }
+                    _ => {}
+                }
+            }
         }
     }
 }
@@ -105,14 +220,19 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
             Ok(())
         }
     }
-}
 
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> Liftable<Pk> for Miniscript<Pk, Ctx> {
-    fn lift(&self) -> Result<Semantic<Pk>, Error> {
-        // check whether the root miniscript can have a spending path that is
-        // a combination of heightlock and timelock
+    /// Lifts this miniscript into a [`Semantic`] policy, reporting which
+    /// information was discarded.
+    pub fn lift_report(&self) -> Result<LiftReport<Pk>, Error> {
         self.lift_check()?;
+        let raw = self.lift_unnormalized()?;
+        let mut losses = BTreeSet::from([LiftLoss::ScriptEncoding]);
+        normalization_losses(&raw, &mut losses);
+        Ok(LiftReport::new(raw.normalized(), losses))
+    }
 
+    /// Lifts this miniscript without checking or normalizing the result.
+    fn lift_unnormalized(&self) -> Result<Semantic<Pk>, Error> {
         let mut stack = vec![];
         for item in self.rtl_post_order_iter() {
             let new_term = match item.node.node {
@@ -166,10 +286,72 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Liftable<Pk> for Miniscript<Pk, Ctx>
                         .map_ref(|key| Arc::new(Semantic::Key(key.clone())))
                         .forget_maximum(),
                 )),
+                Terminal::Ext(ref e) => match *e {},
             };
             stack.push(new_term)
         }
-        Ok(Arc::try_unwrap(stack.pop().unwrap()).unwrap().normalized())
+        Ok(Arc::try_unwrap(stack.pop().unwrap()).unwrap())
+    }
+}
+
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> Liftable<Pk> for Miniscript<Pk, Ctx> {
+    fn lift(&self) -> Result<Semantic<Pk>, Error> {
+        // check whether the root miniscript can have a spending path that is
+        // a combination of heightlock and timelock
+        self.lift_check()?;
+        Ok(self.lift_unnormalized()?.normalized())
+    }
+}
+
+impl<Pk: MiniscriptKey> Descriptor<Pk> {
+    /// Lifts this descriptor into a [`Semantic`] policy, reporting which
+    /// information was discarded.
+    pub fn lift_report(&self) -> Result<LiftReport<Pk>, Error> {
+        fn tree_unnormalized<Pk: MiniscriptKey>(tree: &TapTree<Pk>) -> Result<Semantic<Pk>, Error> {
+            match *tree {
+                TapTree::Tree { ref left, ref right, height: _ } => {
+                    Ok(Semantic::Thresh(Threshold::or(
+                        Arc::new(tree_unnormalized(left)?),
+                        Arc::new(tree_unnormalized(right)?),
+                    )))
+                }
+                TapTree::Leaf(ref ms) => {
+                    ms.lift_check()?;
+                    ms.lift_unnormalized()
+                }
+            }
+        }
+
+        let policy = self.lift()?;
+        let mut losses = BTreeSet::from([LiftLoss::ScriptEncoding, LiftLoss::OutputType]);
+        // Only the miniscripts and the taproot tree are normalized when lifting.
+        let raw = match *self {
+            Descriptor::Bare(ref bare) => Some(bare.as_inner().lift_unnormalized()?),
+            Descriptor::Wsh(ref wsh) => match wsh.as_inner() {
+                WshInner::Ms(ms) => Some(ms.lift_unnormalized()?),
+                WshInner::SortedMulti(_) => None,
+            },
+            Descriptor::Sh(ref sh) => match sh.as_inner() {
+                ShInner::Ms(ms) => Some(ms.lift_unnormalized()?),
+                ShInner::Wsh(wsh) => match wsh.as_inner() {
+                    WshInner::Ms(ms) => Some(ms.lift_unnormalized()?),
+                    WshInner::SortedMulti(_) => None,
+                },
+                ShInner::Wpkh(_) | ShInner::SortedMulti(_) => None,
+            },
+            Descriptor::Tr(ref tr) => match tr.tap_tree() {
+                Some(tree) => {
+                    losses.insert(LiftLoss::TapTreeShape);
+                    Some(tree_unnormalized(tree)?)
+                }
+                None => None,
+            },
+            Descriptor::Pkh(_) | Descriptor::Wpkh(_) => None,
+        };
+        if let Some(raw) = raw {
+            normalization_losses(&raw, &mut losses);
+        }
+        Ok(LiftReport::new(policy, losses))
     }
 }
 
@@ -190,11 +372,26 @@ impl<Pk: MiniscriptKey> Liftable<Pk> for Semantic<Pk> {
     fn lift(&self) -> Result<Semantic<Pk>, Error> { Ok(self.clone()) }
 }
 
-impl<Pk: MiniscriptKey> Liftable<Pk> for Concrete<Pk> {
-    fn lift(&self) -> Result<Semantic<Pk>, Error> {
-        // do not lift if there is a possible satisfaction
-        // involving combination of timelocks and heightlocks
+impl<Pk: MiniscriptKey> Concrete<Pk> {
+    /// Lifts this policy into a [`Semantic`] policy, reporting which
+    /// information was discarded.
+    pub fn lift_report(&self) -> Result<LiftReport<Pk>, Error> {
         self.check_timelocks().map_err(Error::ConcretePolicy)?;
+        let raw = self.lift_unnormalized()?;
+        let mut losses = BTreeSet::new();
+        for data in self.pre_order_iter() {
+            if let Concrete::Or(ref subs) = *data {
+                if subs.iter().any(|(prob, _)| *prob != subs[0].0) {
+                    losses.insert(LiftLoss::Probabilities);
+                }
+            }
+        }
+        normalization_losses(&raw, &mut losses);
+        Ok(LiftReport::new(raw.normalized(), losses))
+    }
+
+    /// Lifts this policy without checking or normalizing the result.
+    fn lift_unnormalized(&self) -> Result<Semantic<Pk>, Error> {
         let ret = match *self {
             Concrete::Unsatisfiable => Semantic::Unsatisfiable,
             Concrete::Trivial => Semantic::Trivial,
@@ -207,24 +404,34 @@ impl<Pk: MiniscriptKey> Liftable<Pk> for Concrete<Pk> {
             Concrete::Hash160(ref h) => Semantic::Hash160(h.clone()),
             Concrete::And(ref subs) => {
                 let semantic_subs: Result<Vec<Semantic<Pk>>, Error> =
-                    subs.iter().map(Liftable::lift).collect();
+                    subs.iter().map(|sub| sub.lift_unnormalized()).collect();
                 let semantic_subs = semantic_subs?.into_iter().map(Arc::new).collect();
                 Semantic::Thresh(Threshold::new(2, semantic_subs).unwrap())
             }
             Concrete::Or(ref subs) => {
-                let semantic_subs: Result<Vec<Semantic<Pk>>, Error> =
-                    subs.iter().map(|(_p, sub)| sub.lift()).collect();
+                let semantic_subs: Result<Vec<Semantic<Pk>>, Error> = subs
+                    .iter()
+                    .map(|(_p, sub)| sub.lift_unnormalized())
+                    .collect();
                 let semantic_subs = semantic_subs?.into_iter().map(Arc::new).collect();
                 Semantic::Thresh(Threshold::new(1, semantic_subs).unwrap())
             }
             Concrete::Thresh(ref thresh) => {
-                Semantic::Thresh(thresh.translate_ref(|sub| Liftable::lift(sub).map(Arc::new))?)
+                Semantic::Thresh(thresh.translate_ref(|sub| sub.lift_unnormalized().map(Arc::new))?)
             }
-        }
-        .normalized();
+        };
         Ok(ret)
     }
 }
+
+impl<Pk: MiniscriptKey> Liftable<Pk> for Concrete<Pk> {
+    fn lift(&self) -> Result<Semantic<Pk>, Error> {
+        // do not lift if there is a possible satisfaction
+        // involving combination of timelocks and heightlocks
+        self.check_timelocks().map_err(Error::ConcretePolicy)?;
+        Ok(self.lift_unnormalized()?.normalized())
+    }
+}
 impl<Pk: MiniscriptKey> Liftable<Pk> for Arc<Concrete<Pk>> {
     fn lift(&self) -> Result<Semantic<Pk>, Error> { self.as_ref().lift() }
 }
@@ -237,7 +444,6 @@ mod tests {
     #[cfg(feature = "compiler")]
     use crate::descriptor::Tr;
     use crate::miniscript::context::Segwitv0;
-    use crate::prelude::*;
     use crate::RelLockTime;
     #[cfg(feature = "compiler")]
     use crate::{descriptor::TapTree, Tap};
@@ -365,6 +571,56 @@ mod tests {
         );
     }
 
+    #[test]
+    fn lift_report() {
+        let losses = |v: &[LiftLoss]| v.iter().copied().collect::<BTreeSet<_>>();
+
+        // Different encodings of the same policy lift identically
+        let or_d = Descriptor::<String>::from_str("wsh(or_d(pk(A),pk(B)))").unwrap();
+        let or_b = Descriptor::<String>::from_str("sh(or_b(pk(A),s:pk(B)))").unwrap();
+        let report = or_d.lift_report().unwrap();
+        assert_eq!(report.policy(), &or_b.lift().unwrap());
+        assert_eq!(report.losses(), &losses(&[LiftLoss::ScriptEncoding, LiftLoss::OutputType]));
+        assert_eq!(or_b.lift_report().unwrap().losses(), report.losses());
+
+        let nested = Descriptor::<String>::from_str("wsh(and_v(v:pk(A),and_v(v:pk(B),pk(C))))")
+            .unwrap()
+            .lift_report()
+            .unwrap();
+        assert!(nested.discards(LiftLoss::Flattening));
+        assert_eq!(nested.into_policy(), SemanticPol::from_str("and(pk(A),pk(B),pk(C))").unwrap());
+
+        let tr = Descriptor::<String>::from_str("tr(A,{pk(B),{pk(C),pk(D)}})").unwrap();
+        let report = tr.lift_report().unwrap();
+        assert!(report.discards(LiftLoss::TapTreeShape));
+        assert!(report.discards(LiftLoss::Flattening));
+        assert_eq!(report.policy(), &tr.lift().unwrap());
+        let key_only = Descriptor::<String>::from_str("tr(A)")
+            .unwrap()
+            .lift_report()
+            .unwrap();
+        assert!(!key_only.discards(LiftLoss::TapTreeShape));
+
+        let ms = Miniscript::<String, Segwitv0>::from_str("or_i(pk(A),pk(B))").unwrap();
+        let report = ms.lift_report().unwrap();
+        assert_eq!(report.losses(), &losses(&[LiftLoss::ScriptEncoding]));
+        assert_eq!(report.policy(), &ms.lift().unwrap());
+
+        // Concrete policies only lose probabilities and structure
+        let pol = ConcretePol::from_str("or(pk(A),pk(B))").unwrap();
+        assert!(pol.lift_report().unwrap().losses().is_empty());
+        let pol = ConcretePol::from_str("or(9@pk(A),1@pk(B))").unwrap();
+        let report = pol.lift_report().unwrap();
+        assert_eq!(report.losses(), &losses(&[LiftLoss::Probabilities]));
+        assert_eq!(report.policy(), &pol.lift().unwrap());
+        let pol = ConcretePol::from_str("and(pk(A),or(pk(B),TRIVIAL))").unwrap();
+        let report = pol.lift_report().unwrap();
+        assert!(report.discards(LiftLoss::TrivialBranches));
+        assert_eq!(report.into_policy(), SemanticPol::from_str("pk(A)").unwrap());
+
+        assert_eq!(LiftLoss::TapTreeShape.to_string(), "taproot tree shape");
+    }
+
     #[test]
     #[cfg(feature = "compiler")]
     fn taproot_compile() {
@@ -11,11 +11,12 @@ use bitcoin::{absolute, relative};
 
 use super::ENTAILMENT_MAX_TERMINALS;
 use crate::iter::{Tree, TreeLike};
+use crate::plan::{AssetProvider as _, Assets};
 use crate::prelude::*;
 use crate::sync::Arc;
 use crate::{
-    expression, AbsLockTime, Error, ForEachKey, FromStrKey, MiniscriptKey, RelLockTime, Threshold,
-    Translator,
+    expression, AbsLockTime, DefiniteDescriptorKey, Error, ForEachKey, FromStrKey, MiniscriptKey,
+    RelLockTime, Threshold, Translator,
 };
 
 /// Abstract policy which corresponds to the semantics of a miniscript and
@@ -569,6 +570,33 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
         policy.normalized()
     }
 
+    /// Filters a policy by eliminating the given keys, e.g. because they have been lost or
+    /// compromised, leaving only the spending paths which do not need them.
+    ///
+    /// This composes with [`Policy::at_age`] and [`Policy::at_lock_time`] to determine what
+    /// remains spendable at a given point in time.
+    pub fn without_keys(self, keys: &[Pk]) -> Policy<Pk> {
+        use Policy::*;
+
+        let mut without_keys = vec![];
+        for data in Arc::new(self).rtl_post_order_iter() {
+            let new_policy = match data.node.as_ref() {
+                Key(ref pk) if keys.contains(pk) => Some(Unsatisfiable),
+                Thresh(ref thresh) => Some(Thresh(thresh.map_ref(|_| without_keys.pop().unwrap()))),
+                _ => None,
+            };
+            match new_policy {
+                Some(new_policy) => without_keys.push(Arc::new(new_policy)),
+                None => without_keys.push(Arc::clone(data.node)),
+            }
+        }
+        // Unwrap is ok because we know we processed at least one node.
+        let root_node = without_keys.pop().unwrap();
+        // Unwrap is ok because we know `root_node` is the only strong reference.
+        let policy = Arc::try_unwrap(root_node).unwrap();
+        policy.normalized()
+    }
+
     /// Counts the number of public keys and keyhashes referenced in a policy.
     /// Duplicate keys will be double-counted.
     pub fn n_keys(&self) -> usize {
@@ -611,6 +639,348 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
         // Ok to unwrap because we know we processed at least one node.
         minimum_n_keys.pop().unwrap()
     }
+
+    /// Whether an absolute timelock has expired at the given block height and time.
+    fn after_expired(t: AbsLockTime, at_height: absolute::Height, at_time: absolute::Time) -> bool {
+        match absolute::LockTime::from(t) {
+            absolute::LockTime::Blocks(height) => height <= at_height,
+            absolute::LockTime::Seconds(time) => time <= at_time,
+        }
+    }
+
+    /// Whether signatures for `keys` are enough to satisfy the policy once the chain has
+    /// reached both `at_height` and `at_time`.
+    ///
+    /// Hash preimages are assumed to be unavailable and relative timelocks are assumed to
+    /// have matured; use [`Policy::at_age`] first to restrict the latter.
+    pub fn spendable_by(
+        &self,
+        keys: &[Pk],
+        at_height: absolute::Height,
+        at_time: absolute::Time,
+    ) -> bool {
+        use Policy::*;
+
+        let mut spendable = vec![];
+        for data in self.rtl_post_order_iter() {
+            let node_spendable = match data.node {
+                Unsatisfiable | Sha256(..) | Hash256(..) | Ripemd160(..) | Hash160(..) => false,
+                Trivial | Older(..) => true,
+                Key(ref pk) => keys.contains(pk),
+                After(t) => Self::after_expired(*t, at_height, at_time),
+                Thresh(ref thresh) => {
+                    let n_spendable = (0..thresh.n()).filter(|_| spendable.pop().unwrap()).count();
+                    n_spendable >= thresh.k()
+                }
+            };
+            spendable.push(node_spendable);
+        }
+        // Ok to unwrap because we know we processed at least one node.
+        spendable.pop().unwrap()
+    }
+
+    /// Returns the minimal sets of keys whose signatures are enough to satisfy the policy
+    /// once the chain has reached both `at_height` and `at_time`, i.e. every combination
+    /// of signers who can spend at that point, without any redundant signers.
+    ///
+    /// Hash preimages are assumed to be unavailable and relative timelocks are assumed to
+    /// have matured; use [`Policy::at_age`] first to restrict the latter. The sets are
+    /// ordered by size, then by their keys.
+    ///
+    /// The number of sets can grow exponentially with the size of the thresholds in the
+    /// policy.
+    pub fn spending_key_sets(
+        &self,
+        at_height: absolute::Height,
+        at_time: absolute::Time,
+    ) -> Vec<BTreeSet<Pk>> {
+        use Policy::*;
+
+        let mut key_sets = vec![];
+        for data in self.rtl_post_order_iter() {
+            let node_key_sets = match data.node {
+                Unsatisfiable | Sha256(..) | Hash256(..) | Ripemd160(..) | Hash160(..) => vec![],
+                Trivial | Older(..) => vec![BTreeSet::new()],
+                Key(ref pk) => vec![core::iter::once(pk.clone()).collect()],
+                After(t) if Self::after_expired(*t, at_height, at_time) => vec![BTreeSet::new()],
+                After(..) => vec![],
+                Thresh(ref thresh) => {
+                    let children = key_sets.split_off(key_sets.len() - thresh.n());
+                    thresh_sets(thresh.k(), children, usize::MAX).expect("no limit")
+                }
+            };
+            key_sets.push(node_key_sets);
+        }
+        // Ok to unwrap because we know we processed at least one node.
+        let mut ret = key_sets.pop().unwrap();
+        minimize_sets(&mut ret);
+        ret
+    }
+
+    /// Converts the policy to disjunctive normal form.
+    ///
+    /// Returns the minimal sets of terminals, i.e. keys, hashes and timelocks, any of which
+    /// satisfies the policy when all of its terminals are satisfied. The sets are ordered by
+    /// size, then by their terminals. A trivial policy has a single empty set, and an
+    /// unsatisfiable one has none.
+    ///
+    /// The number of sets can grow exponentially with the size of the thresholds in the
+    /// policy. Returns `None` if any sub-policy has more than `max_clauses` of them.
+    pub fn to_dnf(&self, max_clauses: usize) -> Option<Vec<BTreeSet<Policy<Pk>>>> {
+        use Policy::*;
+
+        let mut clauses = vec![];
+        for data in self.rtl_post_order_iter() {
+            let node_clauses = match data.node {
+                Unsatisfiable => vec![],
+                Trivial => vec![BTreeSet::new()],
+                Thresh(ref thresh) => {
+                    let children = clauses.split_off(clauses.len() - thresh.n());
+                    thresh_sets(thresh.k(), children, max_clauses)?
+                }
+                terminal => vec![core::iter::once(terminal.clone()).collect()],
+            };
+            if node_clauses.len() > max_clauses {
+                return None;
+            }
+            clauses.push(node_clauses);
+        }
+        // Ok to unwrap because we know we processed at least one node.
+        let mut ret = clauses.pop().unwrap();
+        minimize_sets(&mut ret);
+        Some(ret)
+    }
+
+    /// Returns the minimal sets of assets, any of which is enough to satisfy the policy,
+    /// e.g. to audit which quorums of signers can spend a descriptor.
+    ///
+    /// These are the clauses of [`Policy::to_dnf`], with their timelocks combined. Clauses
+    /// mixing block heights and times in their absolute or relative timelocks cannot be
+    /// satisfied and are removed, as are clauses needing greater timelocks than another one
+    /// with the same keys and preimages.
+    ///
+    /// Returns `None` if any sub-policy has more than `max_covers` clauses.
+    pub fn asset_covers(&self, max_covers: usize) -> Option<Vec<AssetCover<Pk>>> {
+        let covers: Vec<_> = self
+            .to_dnf(max_covers)?
+            .into_iter()
+            .filter_map(AssetCover::from_clause)
+            .collect();
+        let minimal = covers
+            .iter()
+            .enumerate()
+            .filter(|&(i, cover)| {
+                !covers.iter().enumerate().any(|(j, other)| {
+                    j != i && other.is_implied_by(cover) && (j < i || !cover.is_implied_by(other))
+                })
+            })
+            .map(|(_, cover)| cover.clone())
+            .collect();
+        Some(minimal)
+    }
+}
+
+/// A set of assets enough to satisfy a policy, see [`Policy::asset_covers`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct AssetCover<Pk: MiniscriptKey> {
+    /// Keys which must sign.
+    pub keys: BTreeSet<Pk>,
+    /// SHA256 hashes whose preimages must be revealed.
+    pub sha256_preimages: BTreeSet<Pk::Sha256>,
+    /// HASH256 hashes whose preimages must be revealed.
+    pub hash256_preimages: BTreeSet<Pk::Hash256>,
+    /// RIPEMD160 hashes whose preimages must be revealed.
+    pub ripemd160_preimages: BTreeSet<Pk::Ripemd160>,
+    /// HASH160 hashes whose preimages must be revealed.
+    pub hash160_preimages: BTreeSet<Pk::Hash160>,
+    /// The absolute timelock which must have expired.
+    pub absolute_timelock: Option<AbsLockTime>,
+    /// The relative timelock which must have expired.
+    pub relative_timelock: Option<RelLockTime>,
+}
+
+impl<Pk: MiniscriptKey> AssetCover<Pk> {
+    // Collects the terminals of a clause of the disjunctive normal form, or
+    // returns `None` if its timelocks cannot be satisfied together
+    fn from_clause(clause: BTreeSet<Policy<Pk>>) -> Option<Self> {
+        let mut cover = AssetCover {
+            keys: BTreeSet::new(),
+            sha256_preimages: BTreeSet::new(),
+            hash256_preimages: BTreeSet::new(),
+            ripemd160_preimages: BTreeSet::new(),
+            hash160_preimages: BTreeSet::new(),
+            absolute_timelock: None,
+            relative_timelock: None,
+        };
+        for terminal in clause {
+            match terminal {
+                Policy::Key(pk) => {
+                    cover.keys.insert(pk);
+                }
+                Policy::Sha256(hash) => {
+                    cover.sha256_preimages.insert(hash);
+                }
+                Policy::Hash256(hash) => {
+                    cover.hash256_preimages.insert(hash);
+                }
+                Policy::Ripemd160(hash) => {
+                    cover.ripemd160_preimages.insert(hash);
+                }
+                Policy::Hash160(hash) => {
+                    cover.hash160_preimages.insert(hash);
+                }
+                Policy::After(t) => match cover.absolute_timelock {
+                    Some(other) if other.is_block_height() != t.is_block_height() => return None,
+                    other => cover.absolute_timelock = other.max(Some(t)),
+                },
+                Policy::Older(t) => match cover.relative_timelock {
+                    Some(other) if other.is_height_locked() != t.is_height_locked() => return None,
+                    other => cover.relative_timelock = other.max(Some(t)),
+                },
+                Policy::Trivial | Policy::Unsatisfiable | Policy::Thresh(..) => {
+                    unreachable!("clauses only contain terminals")
+                }
+            }
+        }
+        Some(cover)
+    }
+
+    // Whether the assets of `other` are enough to satisfy this cover
+    fn is_implied_by(&self, other: &Self) -> bool {
+        let abs_implied = match (self.absolute_timelock, other.absolute_timelock) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(t), Some(u)) => t.is_block_height() == u.is_block_height() && t <= u,
+        };
+        let rel_implied = match (self.relative_timelock, other.relative_timelock) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(t), Some(u)) => t.is_height_locked() == u.is_height_locked() && t <= u,
+        };
+        abs_implied
+            && rel_implied
+            && self.keys.is_subset(&other.keys)
+            && self.sha256_preimages.is_subset(&other.sha256_preimages)
+            && self.hash256_preimages.is_subset(&other.hash256_preimages)
+            && self
+                .ripemd160_preimages
+                .is_subset(&other.ripemd160_preimages)
+            && self.hash160_preimages.is_subset(&other.hash160_preimages)
+    }
+}
+
+/// Given the minimal sets satisfying each child of a threshold, returns the minimal sets
+/// satisfying `k` of them, or `None` if there are more than `max` of them at any point.
+fn thresh_sets<T: Ord + Clone>(
+    k: usize,
+    children: Vec<Vec<BTreeSet<T>>>,
+    max: usize,
+) -> Option<Vec<BTreeSet<T>>> {
+    // `by_count[j]` holds the sets satisfying `j` of the children so far
+    let mut by_count = vec![vec![BTreeSet::new()]];
+    for child_sets in children {
+        for j in (0..by_count.len().min(k)).rev() {
+            let mut extended = vec![];
+            for set in &by_count[j] {
+                for child_set in &child_sets {
+                    extended.push(set.union(child_set).cloned().collect());
+                }
+            }
+            if j + 1 == by_count.len() {
+                by_count.push(vec![]);
+            }
+            by_count[j + 1].extend(extended);
+            minimize_sets(&mut by_count[j + 1]);
+            if by_count[j + 1].len() > max {
+                return None;
+            }
+        }
+    }
+    Some(by_count.into_iter().nth(k).unwrap_or_default())
+}
+
+/// Removes every set which is a superset of another, and sorts the remaining ones by
+/// size, then by their elements.
+fn minimize_sets<T: Ord>(sets: &mut Vec<BTreeSet<T>>) {
+    sets.sort_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+    sets.dedup();
+    let mut minimal: Vec<BTreeSet<T>> = Vec::with_capacity(sets.len());
+    for set in sets.drain(..) {
+        if !minimal.iter().any(|smaller| smaller.is_subset(&set)) {
+            minimal.push(set);
+        }
+    }
+    *sets = minimal;
+}
+
+impl Policy<DefiniteDescriptorKey> {
+    /// Whether the policy can be satisfied with the given assets.
+    ///
+    /// See [`Policy::satisfiable_branch`] for details.
+    pub fn is_satisfiable_with(&self, assets: &Assets) -> bool {
+        self.satisfiable_branch(assets).is_some()
+    }
+
+    /// Returns the keys, hashes and timelocks of a branch of the policy which can be
+    /// satisfied with the given assets, or `None` if there is no such branch.
+    ///
+    /// This is much cheaper than planning a spend, as the policy carries no information
+    /// about script sizes. Since it does not know how keys are used, a key counts as
+    /// available if the assets can sign for it in any way. Thresholds prefer the children
+    /// which need the fewest conditions, then the leftmost ones.
+    pub fn satisfiable_branch(
+        &self,
+        assets: &Assets,
+    ) -> Option<BTreeSet<Policy<DefiniteDescriptorKey>>> {
+        use Policy::*;
+
+        let mut branches = vec![];
+        for data in self.rtl_post_order_iter() {
+            let available = match data.node {
+                Unsatisfiable => false,
+                Trivial => true,
+                Key(ref pk) => assets.has_key(pk),
+                After(t) => assets.check_after((*t).into()),
+                Older(t) => assets.check_older((*t).into()),
+                Sha256(ref hash) => {
+                    assets.provider_lookup_sha256(hash)
+                        || assets.provider_lookup_pending_sha256(hash)
+                }
+                Hash256(ref hash) => {
+                    assets.provider_lookup_hash256(hash)
+                        || assets.provider_lookup_pending_hash256(hash)
+                }
+                Ripemd160(ref hash) => {
+                    assets.provider_lookup_ripemd160(hash)
+                        || assets.provider_lookup_pending_ripemd160(hash)
+                }
+                Hash160(ref hash) => {
+                    assets.provider_lookup_hash160(hash)
+                        || assets.provider_lookup_pending_hash160(hash)
+                }
+                Thresh(ref thresh) => {
+                    // Children are visited right to left
+                    let children = branches.split_off(branches.len() - thresh.n());
+                    let mut satisfied = children.into_iter().rev().flatten().collect::<Vec<_>>();
+                    let branch = if satisfied.len() < thresh.k() {
+                        None
+                    } else {
+                        satisfied.sort_by_key(BTreeSet::len);
+                        Some(satisfied.into_iter().take(thresh.k()).flatten().collect())
+                    };
+                    branches.push(branch);
+                    continue;
+                }
+            };
+            let branch = match data.node {
+                Trivial => BTreeSet::new(),
+                terminal => core::iter::once(terminal.clone()).collect(),
+            };
+            branches.push(Some(branch).filter(|_| available));
+        }
+        // Ok to unwrap because we know we processed at least one node.
+        branches.pop().unwrap()
+    }
 }
 
 impl<Pk: MiniscriptKey> Policy<Pk> {
@@ -1034,4 +1404,187 @@ mod tests {
         }));
         assert_eq!(count, 17);
     }
+    #[test]
+    fn temporal_queries() {
+        let height = |n| absolute::Height::from_consensus(n).unwrap();
+        let time = |n| absolute::Time::from_consensus(n).unwrap();
+        let set = |keys: &[&str]| keys.iter().map(|k| k.to_string()).collect::<BTreeSet<_>>();
+
+        // Two-of-three, with a recovery key after block 1000 and an heir after a timestamp
+        let policy = StringPolicy::from_str(
+            "or(thresh(2,pk(A),pk(B),pk(C)),\
+             or(and(pk(R),after(1000)),and(pk(H),after(1700000000))))",
+        )
+        .unwrap();
+        let early = (height(999), time(1_600_000_000));
+        let late = (height(1000), time(1_700_000_000));
+
+        assert_eq!(
+            policy.spending_key_sets(early.0, early.1),
+            vec![set(&["A", "B"]), set(&["A", "C"]), set(&["B", "C"])]
+        );
+        assert_eq!(
+            policy.spending_key_sets(late.0, late.1),
+            vec![
+                set(&["H"]),
+                set(&["R"]),
+                set(&["A", "B"]),
+                set(&["A", "C"]),
+                set(&["B", "C"])
+            ]
+        );
+        assert_eq!(policy.spending_key_sets(height(1000), early.1).len(), 4);
+
+        let keys = |keys: &[&str]| keys.iter().map(|k| k.to_string()).collect::<Vec<_>>();
+        assert!(policy.spendable_by(&keys(&["A", "C"]), early.0, early.1));
+        assert!(!policy.spendable_by(&keys(&["A", "R"]), early.0, early.1));
+        assert!(policy.spendable_by(&keys(&["A", "R"]), late.0, late.1));
+        assert!(!policy.spendable_by(&keys(&["A"]), late.0, late.1));
+
+        // Hash preimages are not available, relative timelocks have matured
+        let policy = StringPolicy::from_str("or(sha256(H),and(pk(A),older(144)))").unwrap();
+        assert_eq!(policy.spending_key_sets(early.0, early.1), vec![set(&["A"])]);
+        assert!(!policy.spendable_by(&[], early.0, early.1));
+
+        let policy = StringPolicy::from_str("thresh(2,pk(A),pk(A),pk(B))").unwrap();
+        assert_eq!(policy.spending_key_sets(early.0, early.1), vec![set(&["A"])]);
+        assert!(StringPolicy::Unsatisfiable
+            .spending_key_sets(late.0, late.1)
+            .is_empty());
+        assert_eq!(StringPolicy::Trivial.spending_key_sets(late.0, late.1), vec![set(&[])]);
+    }
+    #[test]
+    fn dnf() {
+        let terms = |s: &[&str]| {
+            s.iter()
+                .map(|t| StringPolicy::from_str(t).unwrap())
+                .collect::<BTreeSet<_>>()
+        };
+
+        let policy =
+            StringPolicy::from_str("or(thresh(2,pk(A),pk(B),sha256(H)),and(pk(C),after(100)))")
+                .unwrap();
+        assert_eq!(
+            policy.to_dnf(10).unwrap(),
+            vec![
+                terms(&["pk(A)", "pk(B)"]),
+                terms(&["pk(A)", "sha256(H)"]),
+                terms(&["pk(B)", "sha256(H)"]),
+                terms(&["pk(C)", "after(100)"]),
+            ]
+        );
+        assert_eq!(policy.to_dnf(3), None);
+
+        // Redundant clauses are removed
+        let policy = StringPolicy::from_str("or(pk(A),and(pk(A),older(10)))").unwrap();
+        assert_eq!(policy.to_dnf(10).unwrap(), vec![terms(&["pk(A)"])]);
+
+        assert_eq!(StringPolicy::Trivial.to_dnf(0), None);
+        assert_eq!(StringPolicy::Trivial.to_dnf(1).unwrap(), vec![BTreeSet::new()]);
+        assert!(StringPolicy::Unsatisfiable.to_dnf(0).unwrap().is_empty());
+    }
+    #[test]
+    fn asset_covers() {
+        let keys = |s: &[&str]| s.iter().map(|k| k.to_string()).collect::<BTreeSet<_>>();
+        let policy = StringPolicy::from_str(
+            "or(thresh(2,pk(A),pk(B),sha256(H)),or(and(pk(C),after(100)),and(pk(C),after(200))))",
+        )
+        .unwrap();
+        let covers = policy.asset_covers(10).unwrap();
+        assert_eq!(
+            covers.iter().map(|c| c.keys.clone()).collect::<Vec<_>>(),
+            vec![keys(&["A", "B"]), keys(&["A"]), keys(&["B"]), keys(&["C"])]
+        );
+        assert_eq!(covers[1].sha256_preimages, keys(&["H"]));
+        assert_eq!(covers[3].absolute_timelock, Some(AbsLockTime::from_consensus(100).unwrap()));
+        assert_eq!(policy.asset_covers(3), None);
+
+        // Block heights and times cannot be mixed
+        let policy = StringPolicy::from_str("and(pk(A),and(after(100),after(500000001)))").unwrap();
+        assert!(policy.asset_covers(10).unwrap().is_empty());
+        let policy = StringPolicy::from_str("and(pk(A),and(older(10),older(20)))").unwrap();
+        assert_eq!(
+            policy.asset_covers(10).unwrap()[0].relative_timelock,
+            Some(RelLockTime::from_height(20))
+        );
+    }
+    #[test]
+    fn satisfiable_with_assets() {
+        let keys = [
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+            "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+            "03500a2b48b0f66c8183cc0d6645ab21cc19c7fad8a33ff04d41c3ece54b0bc1c5",
+        ];
+        let key = |i: usize| crate::DescriptorPublicKey::from_str(keys[i]).unwrap();
+        let policy = Policy::<DefiniteDescriptorKey>::from_str(&format!(
+            "or(thresh(2,pk({}),pk({}),pk({})),and(pk({}),older(144)))",
+            keys[0], keys[1], keys[2], keys[2]
+        ))
+        .unwrap();
+        let term = |s: String| Policy::<DefiniteDescriptorKey>::from_str(&s).unwrap();
+
+        assert!(!policy.is_satisfiable_with(&Assets::new()));
+        assert!(!policy.is_satisfiable_with(&Assets::new().add(key(0))));
+        assert!(!policy.is_satisfiable_with(&Assets::new().add(key(2))));
+
+        let assets = Assets::new().add(vec![key(0), key(1)]);
+        let branch = policy.satisfiable_branch(&assets).unwrap();
+        let expected = [
+            term(format!("pk({})", keys[0])),
+            term(format!("pk({})", keys[1])),
+        ];
+        assert_eq!(branch, expected.into_iter().collect());
+
+        let assets = Assets::new()
+            .add(key(2))
+            .older(relative::LockTime::from_height(200));
+        let branch = policy.satisfiable_branch(&assets).unwrap();
+        let expected = [
+            term(format!("pk({})", keys[2])),
+            term("older(144)".to_owned()),
+        ];
+        assert_eq!(branch, expected.into_iter().collect());
+
+        // Among branches with as many conditions, the first is preferred
+        let assets = assets.add(key(0));
+        let branch = policy.satisfiable_branch(&assets).unwrap();
+        let expected = [
+            term(format!("pk({})", keys[0])),
+            term(format!("pk({})", keys[2])),
+        ];
+        assert_eq!(branch, expected.into_iter().collect());
+
+        assert_eq!(Policy::Trivial.satisfiable_branch(&Assets::new()), Some(BTreeSet::new()));
+    }
+
+    #[test]
+    fn without_keys() {
+        let keys = |keys: &[&str]| keys.iter().map(|k| k.to_string()).collect::<Vec<_>>();
+
+        // Two-of-three, with a recovery key after 1000 blocks
+        let policy =
+            StringPolicy::from_str("or(thresh(2,pk(A),pk(B),pk(C)),and(pk(R),older(1000)))")
+                .unwrap();
+        assert_eq!(policy.clone().without_keys(&[]), policy.clone().normalized());
+        assert_eq!(
+            policy.clone().without_keys(&keys(&["A"])),
+            StringPolicy::from_str("or(and(pk(B),pk(C)),and(pk(R),older(1000)))").unwrap()
+        );
+        assert_eq!(
+            policy.clone().without_keys(&keys(&["A", "B"])),
+            StringPolicy::from_str("and(pk(R),older(1000))").unwrap()
+        );
+        assert_eq!(
+            policy
+                .clone()
+                .without_keys(&keys(&["A", "B"]))
+                .at_age(RelLockTime::from_height(999).into()),
+            Policy::Unsatisfiable
+        );
+        assert_eq!(
+            policy.clone().without_keys(&keys(&["A", "R"])),
+            StringPolicy::from_str("and(pk(B),pk(C))").unwrap()
+        );
+        assert_eq!(policy.without_keys(&keys(&["A", "B", "R"])), Policy::Unsatisfiable);
+    }
 }
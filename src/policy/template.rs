@@ -0,0 +1,249 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Policy Templates
+//!
+//! Recognizes common wallet setups, such as multisigs and timelocked recovery
+//! keys, from their semantic policies, so that descriptors can be classified
+//! without looking at their scripts.
+//!
+
+use core::fmt;
+
+use super::{Liftable, Semantic};
+use crate::prelude::*;
+use crate::{AbsLockTime, Descriptor, MiniscriptKey, RelLockTime};
+
+/// A timelock parameter of a [`PolicyTemplate`].
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum TemplateTimelock {
+    /// An absolute timelock, as in `after`.
+    Absolute(AbsLockTime),
+    /// A relative timelock, as in `older`.
+    Relative(RelLockTime),
+}
+
+impl fmt::Display for TemplateTimelock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TemplateTimelock::Absolute(t) => write!(f, "after({})", t),
+            TemplateTimelock::Relative(t) => write!(f, "older({})", t),
+        }
+    }
+}
+
+/// A well-known wallet policy, with its roles and parameters.
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub enum PolicyTemplate<Pk: MiniscriptKey> {
+    /// A single key, e.g. `pk(A)`.
+    SingleKey(Pk),
+    /// Any `k` of the keys, e.g. `thresh(2,pk(A),pk(B),pk(C))`.
+    Multisig {
+        /// The number of keys required.
+        k: usize,
+        /// The keys.
+        keys: Vec<Pk>,
+    },
+    /// A primary key, or a recovery key once a timelock has expired, e.g.
+    /// `or(pk(A),and(pk(B),older(144)))`.
+    TimelockedRecovery {
+        /// The key which can spend at any time.
+        primary: Pk,
+        /// The key which can spend once `timelock` has expired.
+        recovery: Pk,
+        /// The timelock of the recovery key.
+        timelock: TemplateTimelock,
+    },
+    /// A multisig which needs one key fewer as each timelock expires, e.g.
+    /// `thresh(3,pk(A),pk(B),pk(C),older(1000),older(2000))`.
+    DecayingMultisig {
+        /// The number of keys required before any timelock has expired.
+        k: usize,
+        /// The keys.
+        keys: Vec<Pk>,
+        /// The timelocks, each of which lowers the number of keys required by one.
+        timelocks: Vec<TemplateTimelock>,
+    },
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for PolicyTemplate<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PolicyTemplate::SingleKey(ref pk) => write!(f, "single key {}", pk),
+            PolicyTemplate::Multisig { k, ref keys } => {
+                write!(f, "{}-of-{} multisig", k, keys.len())
+            }
+            PolicyTemplate::TimelockedRecovery { ref primary, ref recovery, timelock } => {
+                write!(f, "key {} with recovery key {} after {}", primary, recovery, timelock)
+            }
+            PolicyTemplate::DecayingMultisig { k, ref keys, ref timelocks } => write!(
+                f,
+                "{}-of-{} multisig decaying to {}-of-{}",
+                k,
+                keys.len(),
+                k - timelocks.len(),
+                keys.len()
+            ),
+        }
+    }
+}
+
+fn as_key<Pk: MiniscriptKey>(policy: &Semantic<Pk>) -> Option<&Pk> {
+    match *policy {
+        Semantic::Key(ref pk) => Some(pk),
+        _ => None,
+    }
+}
+
+fn as_timelock<Pk: MiniscriptKey>(policy: &Semantic<Pk>) -> Option<TemplateTimelock> {
+    match *policy {
+        Semantic::After(t) => Some(TemplateTimelock::Absolute(t)),
+        Semantic::Older(t) => Some(TemplateTimelock::Relative(t)),
+        _ => None,
+    }
+}
+
+/// Matches `and(pk(recovery),timelock)`, in either order.
+fn as_recovery<Pk: MiniscriptKey>(policy: &Semantic<Pk>) -> Option<(&Pk, TemplateTimelock)> {
+    match *policy {
+        Semantic::Thresh(ref thresh) if thresh.is_and() && thresh.n() == 2 => {
+            let (a, b) = (&thresh.data()[0], &thresh.data()[1]);
+            match (as_key(a), as_timelock(b)) {
+                (Some(pk), Some(t)) => Some((pk, t)),
+                _ => Some((as_key(b)?, as_timelock(a)?)),
+            }
+        }
+        _ => None,
+    }
+}
+
+impl<Pk: MiniscriptKey> Semantic<Pk> {
+    /// Returns the [`PolicyTemplate`] the policy is an instance of, if any.
+    ///
+    /// The policy is normalized first, so that e.g. nested disjunctions are recognized.
+    /// Each template is only recognized in the form given in its documentation, up to the
+    /// order of the sub-policies.
+    pub fn match_template(&self) -> Option<PolicyTemplate<Pk>> {
+        let policy = self.clone().normalized();
+        let thresh = match policy {
+            Semantic::Key(pk) => return Some(PolicyTemplate::SingleKey(pk)),
+            Semantic::Thresh(ref thresh) => thresh,
+            _ => return None,
+        };
+
+        if thresh.is_or() && thresh.n() == 2 {
+            let (a, b) = (&thresh.data()[0], &thresh.data()[1]);
+            let (primary, (recovery, timelock)) = match (as_key(a), as_recovery(b)) {
+                (Some(primary), Some(recovery)) => (primary, recovery),
+                _ => (as_key(b)?, as_recovery(a)?),
+            };
+            return Some(PolicyTemplate::TimelockedRecovery {
+                primary: primary.clone(),
+                recovery: recovery.clone(),
+                timelock,
+            });
+        }
+
+        let mut keys = vec![];
+        let mut timelocks = vec![];
+        for sub in thresh.iter() {
+            match (as_key(sub), as_timelock(sub)) {
+                (Some(pk), _) => keys.push(pk.clone()),
+                (_, Some(t)) => timelocks.push(t),
+                _ => return None,
+            }
+        }
+        if keys.len() < 2 {
+            None
+        } else if timelocks.is_empty() {
+            Some(PolicyTemplate::Multisig { k: thresh.k(), keys })
+        } else if thresh.k() > timelocks.len() {
+            timelocks.sort();
+            Some(PolicyTemplate::DecayingMultisig { k: thresh.k(), keys, timelocks })
+        } else {
+            None
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey> Descriptor<Pk> {
+    /// Returns the [`PolicyTemplate`] the policy of the descriptor is an instance of, if any.
+    ///
+    /// See [`Semantic::match_template`] for details.
+    pub fn match_template(&self) -> Option<PolicyTemplate<Pk>> {
+        self.lift().ok()?.match_template()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+
+    fn template(s: &str) -> Option<PolicyTemplate<String>> {
+        Semantic::<String>::from_str(s).unwrap().match_template()
+    }
+
+    fn keys(keys: &[&str]) -> Vec<String> { keys.iter().map(|k| k.to_string()).collect() }
+
+    #[test]
+    fn match_policy_templates() {
+        assert_eq!(template("pk(A)"), Some(PolicyTemplate::SingleKey("A".into())));
+        assert_eq!(
+            template("thresh(2,pk(A),pk(B),pk(C))"),
+            Some(PolicyTemplate::Multisig { k: 2, keys: keys(&["A", "B", "C"]) })
+        );
+        assert_eq!(
+            template("and(pk(A),pk(B))"),
+            Some(PolicyTemplate::Multisig { k: 2, keys: keys(&["A", "B"]) })
+        );
+
+        let recovery = PolicyTemplate::TimelockedRecovery {
+            primary: "A".into(),
+            recovery: "B".into(),
+            timelock: TemplateTimelock::Relative(RelLockTime::from_height(144)),
+        };
+        assert_eq!(template("or(pk(A),and(pk(B),older(144)))").as_ref(), Some(&recovery));
+        assert_eq!(template("or(and(older(144),pk(B)),pk(A))").as_ref(), Some(&recovery));
+        assert_eq!(recovery.to_string(), "key A with recovery key B after older(144)");
+
+        let decaying =
+            template("thresh(3,pk(A),pk(B),pk(C),pk(D),pk(E),older(2000),older(1000))").unwrap();
+        assert_eq!(
+            decaying,
+            PolicyTemplate::DecayingMultisig {
+                k: 3,
+                keys: keys(&["A", "B", "C", "D", "E"]),
+                timelocks: vec![
+                    TemplateTimelock::Relative(RelLockTime::from_height(1000)),
+                    TemplateTimelock::Relative(RelLockTime::from_height(2000)),
+                ],
+            }
+        );
+        assert_eq!(decaying.to_string(), "3-of-5 multisig decaying to 1-of-5");
+
+        assert_eq!(template("and(pk(A),older(144))"), None);
+        assert_eq!(template("or(pk(A),and(pk(B),sha256(H)))"), None);
+        assert_eq!(template("thresh(2,pk(A),pk(B),older(10),older(20))"), None);
+        assert_eq!(template("UNSATISFIABLE"), None);
+    }
+
+    #[test]
+    fn match_descriptor_templates() {
+        let desc = Descriptor::<String>::from_str("wsh(sortedmulti(2,A,B,C))").unwrap();
+        assert_eq!(
+            desc.match_template(),
+            Some(PolicyTemplate::Multisig { k: 2, keys: keys(&["A", "B", "C"]) })
+        );
+
+        let desc = Descriptor::<String>::from_str("tr(A,and_v(v:pk(B),after(800000)))").unwrap();
+        assert_eq!(
+            desc.match_template(),
+            Some(PolicyTemplate::TimelockedRecovery {
+                primary: "A".into(),
+                recovery: "B".into(),
+                timelock: TemplateTimelock::Absolute(AbsLockTime::from_consensus(800000).unwrap()),
+            })
+        );
+    }
+}
@@ -263,6 +263,124 @@ impl<T: fmt::Debug, const MAX: usize> Threshold<T, MAX> {
     }
 }
 
+/// Structure representing a threshold on the total weight of the satisfied
+/// elements of a collection of weighted objects `T`.
+///
+/// It is satisfied when elements whose weights add up to at least `k` are.
+/// Elements of weight 0 never count towards the threshold.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct WeightedThreshold<T> {
+    k: usize,
+    inner: Vec<(usize, T)>,
+}
+
+impl<T> WeightedThreshold<T> {
+    /// Constructs a weighted threshold from a threshold value and a collection
+    /// of elements with their weights.
+    ///
+    /// The threshold value must be positive and at most the total weight.
+    pub fn new(k: usize, inner: Vec<(usize, T)>) -> Result<Self, ThresholdError> {
+        let total = inner.iter().map(|(weight, _)| *weight).sum();
+        validate_k_n::<0>(k, total)?;
+        Ok(WeightedThreshold { k, inner })
+    }
+
+    /// The threshold value.
+    pub fn k(&self) -> usize { self.k }
+
+    /// The total weight of the elements.
+    pub fn total_weight(&self) -> usize { self.inner.iter().map(|(weight, _)| weight).sum() }
+
+    /// Accessor for the underlying data, with the weight of each element.
+    pub fn data(&self) -> &[(usize, T)] { &self.inner }
+
+    /// Accessor for the underlying data, with the weight of each element.
+    pub fn into_data(self) -> Vec<(usize, T)> { self.inner }
+
+    /// Whether all elements have the same weight, in which case the weighted
+    /// threshold is equivalent to a single unweighted threshold.
+    pub fn is_uniform(&self) -> bool { self.inner.windows(2).all(|pair| pair[0].0 == pair[1].0) }
+
+    /// Expands the weighted threshold into nested unweighted thresholds which
+    /// are satisfied exactly when it is.
+    ///
+    /// Each element is converted with `leaf`, as many times as it appears in
+    /// the expansion, and each threshold is built with `thresh`. Elements are
+    /// taken from the heaviest down; the expansion is a single threshold when
+    /// the weights are uniform, but may grow exponentially with the number of
+    /// distinct weights otherwise. Returns `None`, without finishing the
+    /// expansion, if it would have more than `max_leaves` leaves.
+    pub fn expand<U, L, F>(&self, max_leaves: usize, mut leaf: L, mut thresh: F) -> Option<U>
+    where
+        L: FnMut(&T) -> U,
+        F: FnMut(Threshold<U, 0>) -> U,
+    {
+        let mut items: Vec<(usize, &T)> = self
+            .inner
+            .iter()
+            .filter(|(weight, _)| *weight > 0)
+            .map(|(weight, t)| (*weight, t))
+            .collect();
+        items.sort_by_key(|(weight, _)| cmp::Reverse(*weight));
+        let mut budget = max_leaves;
+        expand_weighted(self.k, &items, &mut budget, &mut leaf, &mut thresh)
+    }
+}
+
+// Expands a threshold of `k` on the total weight of `items`, sorted by
+// decreasing weight, with `0 < k <= total weight`. Each leaf is taken out
+// of `budget`, and the expansion stops once it runs out.
+fn expand_weighted<T, U, L, F>(
+    k: usize,
+    items: &[(usize, &T)],
+    budget: &mut usize,
+    leaf: &mut L,
+    thresh: &mut F,
+) -> Option<U>
+where
+    L: FnMut(&T) -> U,
+    F: FnMut(Threshold<U, 0>) -> U,
+{
+    let (weight, first) = items[0];
+    let min_weight = items[items.len() - 1].0;
+    let total: usize = items.iter().map(|(w, _)| w).sum();
+    // Uniform weights, any element or all of them make a single threshold
+    let single_k = if weight == min_weight {
+        Some((k + weight - 1) / weight)
+    } else if k <= min_weight {
+        Some(1)
+    } else if total - min_weight < k {
+        Some(items.len())
+    } else {
+        None
+    };
+    if let Some(k) = single_k {
+        *budget = budget.checked_sub(items.len())?;
+        if items.len() == 1 {
+            return Some(leaf(first));
+        }
+        let inner = items.iter().map(|(_, t)| leaf(t)).collect();
+        return Some(thresh(Threshold { k, inner }));
+    }
+
+    // Either the heaviest element is satisfied and the others make up the
+    // rest of the weight, or the others reach the threshold without it
+    let rest = &items[1..];
+    *budget = budget.checked_sub(1)?;
+    let with_first = if k <= weight {
+        leaf(first)
+    } else {
+        let rest = expand_weighted(k - weight, rest, budget, leaf, thresh)?;
+        thresh(Threshold::and(leaf(first), rest))
+    };
+    if total - weight < k {
+        Some(with_first)
+    } else {
+        let without_first = expand_weighted(k, rest, budget, leaf, thresh)?;
+        Some(thresh(Threshold::or(with_first, without_first)))
+    }
+}
+
 struct ThreshDisplay<'t, 's, T, const MAX: usize> {
     name: &'s str,
     thresh: &'t Threshold<T, MAX>,
@@ -314,3 +432,42 @@ where
         f.write_char(')')
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weighted_threshold() {
+        assert!(WeightedThreshold::new(0, vec![(1, 'a')]).is_err());
+        assert!(WeightedThreshold::new(4, vec![(1, 'a'), (2, 'b')]).is_err());
+
+        let expand = |thresh: &WeightedThreshold<char>| {
+            thresh
+                .expand(10, |c| c.to_string(), |thresh| thresh.display("thresh", true).to_string())
+                .unwrap()
+        };
+
+        let uniform = WeightedThreshold::new(3, vec![(2, 'a'), (2, 'b'), (2, 'c')]).unwrap();
+        assert!(uniform.is_uniform());
+        assert_eq!(uniform.total_weight(), 6);
+        assert_eq!(expand(&uniform), "thresh(2,a,b,c)");
+
+        // The chair alone, or two members
+        let board =
+            WeightedThreshold::new(2, vec![(1, 'a'), (2, 'c'), (1, 'b'), (0, 'x')]).unwrap();
+        assert!(!board.is_uniform());
+        assert_eq!(expand(&board), "thresh(1,c,thresh(2,a,b))");
+
+        // The chair and one member
+        let board = WeightedThreshold::new(3, vec![(1, 'a'), (2, 'c'), (1, 'b')]).unwrap();
+        assert_eq!(expand(&board), "thresh(2,c,thresh(1,a,b))");
+
+        let board =
+            WeightedThreshold::new(4, vec![(3, 'a'), (2, 'b'), (1, 'c'), (1, 'd')]).unwrap();
+        assert_eq!(expand(&board), "thresh(1,thresh(2,a,thresh(1,b,c,d)),thresh(3,b,c,d))");
+        // The expansion above has 7 leaves
+        assert!(board.expand(7, |_| (), |_| ()).is_some());
+        assert!(board.expand(6, |_| (), |_| ()).is_none());
+    }
+}
@@ -0,0 +1,496 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # PSBT Combiner
+//!
+//! This module implements the Combiner role defined in BIP 174. Unlike the
+//! combiner of the `bitcoin` crate, which picks arbitrarily between conflicting
+//! values, it rejects conflicts and checks the combined inputs against the
+//! descriptors controlling them.
+//!
+
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error;
+
+use bitcoin::bip32::KeySource;
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::psbt::{self, Psbt};
+use bitcoin::secp256k1::{self, Message, Secp256k1};
+use bitcoin::sighash::{Prevouts, SighashCache};
+use bitcoin::taproot::{ControlBlock, TapLeafHash};
+
+use super::{finalizer, PsbtExt, PsbtInputExt, SighashError};
+use crate::descriptor::ConversionError;
+use crate::prelude::*;
+use crate::util::with_verify_context;
+use crate::{DefiniteDescriptorKey, Descriptor, DescriptorPublicKey, ForEachKey};
+
+pub(super) fn combine_with_descriptors(
+    psbt: &mut Psbt,
+    other: Psbt,
+    descriptors: &[Descriptor<DescriptorPublicKey>],
+) -> Result<(), CombineError> {
+    if psbt.unsigned_tx != other.unsigned_tx {
+        return Err(CombineError::DifferentTransactions);
+    }
+    let mut tap_key_origins = Vec::with_capacity(psbt.inputs.len());
+    for (index, (ours, theirs)) in psbt.inputs.iter().zip(&other.inputs).enumerate() {
+        check_conflicts(ours, theirs).map_err(|field| CombineError::Conflict { index, field })?;
+        // The stock combiner keeps a single entry, merge the leaves signed by each key
+        let mut origins = ours.tap_key_origins.clone();
+        for (pk, (leaves, _)) in &theirs.tap_key_origins {
+            if let Some((ours, _)) = origins.get_mut(pk) {
+                let new: Vec<_> = leaves.iter().filter(|leaf| !ours.contains(leaf)).collect();
+                ours.extend(new);
+            }
+        }
+        tap_key_origins.push(origins);
+    }
+
+    let mut combined = psbt.clone();
+    combined.combine(other).map_err(CombineError::Psbt)?;
+    for (input, origins) in combined.inputs.iter_mut().zip(tap_key_origins) {
+        for (pk, origin) in origins {
+            input.tap_key_origins.insert(pk, origin);
+        }
+    }
+
+    let mut cache = SighashCache::new(&combined.unsigned_tx);
+    with_verify_context(|secp| {
+        for (index, input_match) in combined.match_inputs(descriptors).into_iter().enumerate() {
+            if let Some(input_match) = input_match {
+                check_input(&combined, index, &input_match.descriptor, &mut cache, secp)?;
+            }
+        }
+        Ok(())
+    })?;
+    *psbt = combined;
+    Ok(())
+}
+
+// Returns the first field for which the inputs hold different values
+fn check_conflicts(ours: &psbt::Input, theirs: &psbt::Input) -> Result<(), InputField> {
+    fn check_map<K: Ord + Clone, V: PartialEq>(
+        ours: &BTreeMap<K, V>,
+        theirs: &BTreeMap<K, V>,
+        field: impl Fn(K) -> InputField,
+    ) -> Result<(), InputField> {
+        match theirs
+            .iter()
+            .find(|(k, v)| ours.get(k).map_or(false, |ours| ours != *v))
+        {
+            Some((k, _)) => Err(field(k.clone())),
+            None => Ok(()),
+        }
+    }
+    fn check_option<T: PartialEq>(
+        ours: &Option<T>,
+        theirs: &Option<T>,
+        field: InputField,
+    ) -> Result<(), InputField> {
+        match (ours, theirs) {
+            (Some(ours), Some(theirs)) if ours != theirs => Err(field),
+            _ => Ok(()),
+        }
+    }
+
+    check_map(&ours.partial_sigs, &theirs.partial_sigs, InputField::PartialSig)?;
+    check_map(&ours.tap_script_sigs, &theirs.tap_script_sigs, |(pk, leaf)| {
+        InputField::TapScriptSig(pk, leaf)
+    })?;
+    check_map(&ours.tap_scripts, &theirs.tap_scripts, InputField::TapScript)?;
+    check_map(&ours.bip32_derivation, &theirs.bip32_derivation, InputField::Bip32Derivation)?;
+    let sources = |origins: &BTreeMap<XOnlyPublicKey, (Vec<TapLeafHash>, KeySource)>| {
+        origins
+            .iter()
+            .map(|(pk, (_, source))| (*pk, source.clone()))
+            .collect::<BTreeMap<_, _>>()
+    };
+    check_map(
+        &sources(&ours.tap_key_origins),
+        &sources(&theirs.tap_key_origins),
+        InputField::TapKeyOrigin,
+    )?;
+    check_option(&ours.tap_key_sig, &theirs.tap_key_sig, InputField::TapKeySig)?;
+    check_option(&ours.tap_internal_key, &theirs.tap_internal_key, InputField::TapInternalKey)?;
+    check_option(&ours.tap_merkle_root, &theirs.tap_merkle_root, InputField::TapMerkleRoot)?;
+    check_option(&ours.redeem_script, &theirs.redeem_script, InputField::RedeemScript)?;
+    check_option(&ours.witness_script, &theirs.witness_script, InputField::WitnessScript)?;
+    check_option(&ours.sighash_type, &theirs.sighash_type, InputField::SighashType)
+}
+
+// Checks the fields of a combined input against the descriptor it spends, and its signatures
+fn check_input<C: secp256k1::Verification>(
+    psbt: &Psbt,
+    index: usize,
+    descriptor: &Descriptor<DefiniteDescriptorKey>,
+    cache: &mut SighashCache<&bitcoin::Transaction>,
+    secp: &Secp256k1<C>,
+) -> Result<(), CombineError> {
+    let input = &psbt.inputs[index];
+    let mismatch = |field| CombineError::DescriptorMismatch { index, field };
+    let invalid = |field| CombineError::InvalidSignature { index, field };
+    let sighash_err = |e: SighashError| CombineError::Sighash(e, index);
+
+    let (derived, mismatches) = field_mismatches(input, descriptor)
+        .expect("the descriptor was derived when matching the input");
+    if let Some(field) = mismatches.into_iter().next() {
+        return Err(mismatch(field));
+    }
+
+    let utxo = finalizer::get_utxo(psbt, index)
+        .map_err(|_| sighash_err(SighashError::MissingInputUtxo))?;
+    for (pk, sig) in &input.partial_sigs {
+        let script_code = derived
+            .script_code()
+            .expect("partial signatures of taproot inputs are mismatches");
+        let msg = if derived.desc_type().segwit_version().is_some() {
+            let msg = cache
+                .p2wsh_signature_hash(index, &script_code, utxo.value, sig.sighash_type)
+                .map_err(|e| sighash_err(e.into()))?;
+            Message::from(msg)
+        } else {
+            let msg = cache
+                .legacy_signature_hash(index, &script_code, sig.sighash_type.to_u32())
+                .map_err(|e| sighash_err(e.into()))?;
+            Message::from(msg)
+        };
+        secp.verify_ecdsa(&msg, &sig.signature, &pk.inner)
+            .map_err(|_| invalid(InputField::PartialSig(*pk)))?;
+    }
+
+    if input.tap_key_sig.is_none() && input.tap_script_sigs.is_empty() {
+        return Ok(());
+    }
+    let prevouts =
+        finalizer::prevouts(psbt).map_err(|_| sighash_err(SighashError::MissingSpendUtxos))?;
+    let prevouts = Prevouts::All(&prevouts);
+    if let Some(sig) = input.tap_key_sig {
+        let output_key = XOnlyPublicKey::from_slice(&utxo.script_pubkey.as_bytes()[2..])
+            .expect("key spend signatures of non-taproot inputs are mismatches");
+        let msg = cache
+            .taproot_key_spend_signature_hash(index, &prevouts, sig.sighash_type)
+            .map_err(|e| sighash_err(e.into()))?;
+        secp.verify_schnorr(&sig.signature, &Message::from(msg), &output_key)
+            .map_err(|_| invalid(InputField::TapKeySig))?;
+    }
+    for (&(pk, leaf_hash), sig) in &input.tap_script_sigs {
+        let field = InputField::TapScriptSig(pk, leaf_hash);
+        let msg = cache
+            .taproot_script_spend_signature_hash(index, &prevouts, leaf_hash, sig.sighash_type)
+            .map_err(|e| sighash_err(e.into()))?;
+        secp.verify_schnorr(&sig.signature, &Message::from(msg), &pk)
+            .map_err(|_| invalid(field))?;
+    }
+    Ok(())
+}
+
+// Returns the fields of the input whose values are not those produced by the descriptor,
+// along with the descriptor with its keys derived.
+//
+// Fields the descriptor produces but which are absent from the input are not mismatches.
+// Signatures are mismatches if they are not made by a key of the descriptor, or for a leaf
+// the key is not in, but are not verified.
+pub(super) fn field_mismatches(
+    input: &psbt::Input,
+    descriptor: &Descriptor<DefiniteDescriptorKey>,
+) -> Result<(Descriptor<bitcoin::PublicKey>, Vec<InputField>), ConversionError> {
+    let mut expected = psbt::Input::default();
+    let derived = expected.update_with_descriptor_unchecked(descriptor)?;
+    let is_tr = matches!(derived, Descriptor::Tr(_));
+    let mut mismatches = vec![];
+
+    for (pk, source) in &input.bip32_derivation {
+        if expected.bip32_derivation.get(pk) != Some(source) {
+            mismatches.push(InputField::Bip32Derivation(*pk));
+        }
+    }
+    for (pk, (leaves, source)) in &input.tap_key_origins {
+        match expected.tap_key_origins.get(pk) {
+            Some((expected_leaves, expected_source))
+                if expected_source == source
+                    && leaves.iter().all(|leaf| expected_leaves.contains(leaf)) => {}
+            _ => mismatches.push(InputField::TapKeyOrigin(*pk)),
+        }
+    }
+    for (control_block, script) in &input.tap_scripts {
+        if expected.tap_scripts.get(control_block) != Some(script) {
+            mismatches.push(InputField::TapScript(control_block.clone()));
+        }
+    }
+    let mut check = |mismatch: bool, field| {
+        if mismatch {
+            mismatches.push(field);
+        }
+    };
+    check(
+        input.tap_internal_key.is_some() && input.tap_internal_key != expected.tap_internal_key,
+        InputField::TapInternalKey,
+    );
+    check(
+        input.tap_merkle_root.is_some() && input.tap_merkle_root != expected.tap_merkle_root,
+        InputField::TapMerkleRoot,
+    );
+    check(
+        input.redeem_script.is_some() && input.redeem_script != expected.redeem_script,
+        InputField::RedeemScript,
+    );
+    check(
+        input.witness_script.is_some() && input.witness_script != expected.witness_script,
+        InputField::WitnessScript,
+    );
+    check(input.tap_key_sig.is_some() && !is_tr, InputField::TapKeySig);
+
+    let mut keys = BTreeSet::new();
+    derived.for_each_key(|pk| {
+        keys.insert(*pk);
+        true
+    });
+    for pk in input.partial_sigs.keys() {
+        if is_tr || !keys.contains(pk) {
+            mismatches.push(InputField::PartialSig(*pk));
+        }
+    }
+    for &(pk, leaf_hash) in input.tap_script_sigs.keys() {
+        match expected.tap_key_origins.get(&pk) {
+            Some((leaves, _)) if leaves.contains(&leaf_hash) => {}
+            _ => mismatches.push(InputField::TapScriptSig(pk, leaf_hash)),
+        }
+    }
+    Ok((derived, mismatches))
+}
+
+/// A field of a PSBT input, as reported by [`CombineError`] and
+/// [`InputMismatch`](super::InputMismatch).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputField {
+    /// The ECDSA signature of a key, in `partial_sigs`.
+    PartialSig(bitcoin::PublicKey),
+    /// The taproot key spend signature, `tap_key_sig`.
+    TapKeySig,
+    /// The signature of a key for a leaf, in `tap_script_sigs`.
+    TapScriptSig(XOnlyPublicKey, TapLeafHash),
+    /// The leaf script of a control block, in `tap_scripts`.
+    TapScript(ControlBlock),
+    /// The origin of a key, in `bip32_derivation`.
+    Bip32Derivation(secp256k1::PublicKey),
+    /// The origin and leaves of a key, in `tap_key_origins`.
+    TapKeyOrigin(XOnlyPublicKey),
+    /// The taproot internal key, `tap_internal_key`.
+    TapInternalKey,
+    /// The taproot merkle root, `tap_merkle_root`.
+    TapMerkleRoot,
+    /// The redeem script, `redeem_script`.
+    RedeemScript,
+    /// The witness script, `witness_script`.
+    WitnessScript,
+    /// The sighash type, `sighash_type`.
+    SighashType,
+}
+
+impl fmt::Display for InputField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputField::PartialSig(pk) => write!(f, "signature of {}", pk),
+            InputField::TapKeySig => f.write_str("key spend signature"),
+            InputField::TapScriptSig(pk, leaf) => {
+                write!(f, "signature of {} for leaf {}", pk, leaf)
+            }
+            InputField::TapScript(cb) => {
+                write!(f, "script of leaf with control block {:x?}", cb.serialize())
+            }
+            InputField::Bip32Derivation(pk) => write!(f, "origin of {}", pk),
+            InputField::TapKeyOrigin(pk) => write!(f, "taproot origin of {}", pk),
+            InputField::TapInternalKey => f.write_str("taproot internal key"),
+            InputField::TapMerkleRoot => f.write_str("taproot merkle root"),
+            InputField::RedeemScript => f.write_str("redeem script"),
+            InputField::WitnessScript => f.write_str("witness script"),
+            InputField::SighashType => f.write_str("sighash type"),
+        }
+    }
+}
+
+/// Error returned by [`PsbtExt::combine_with_descriptors`].
+#[derive(Debug)]
+pub enum CombineError {
+    /// The PSBTs are not for the same unsigned transaction.
+    DifferentTransactions,
+    /// The PSBTs hold different values for the same field of an input.
+    Conflict {
+        /// The index of the input
+        index: usize,
+        /// The conflicting field
+        field: InputField,
+    },
+    /// A field of an input does not match the descriptor controlling it.
+    DescriptorMismatch {
+        /// The index of the input
+        index: usize,
+        /// The mismatching field
+        field: InputField,
+    },
+    /// A signature of an input does not verify.
+    InvalidSignature {
+        /// The index of the input
+        index: usize,
+        /// The field holding the signature
+        field: InputField,
+    },
+    /// The sighash needed to verify a signature of the input at the index could not be
+    /// computed.
+    Sighash(SighashError, usize),
+    /// The global fields could not be combined.
+    Psbt(psbt::Error),
+}
+
+impl fmt::Display for CombineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CombineError::DifferentTransactions => {
+                f.write_str("cannot combine PSBTs for different transactions")
+            }
+            CombineError::Conflict { index, field } => {
+                write!(f, "input {}: conflicting {}", index, field)
+            }
+            CombineError::DescriptorMismatch { index, field } => {
+                write!(f, "input {}: {} does not match the descriptor", index, field)
+            }
+            CombineError::InvalidSignature { index, field } => {
+                write!(f, "input {}: invalid {}", index, field)
+            }
+            CombineError::Sighash(e, index) => write!(f, "input {}: {}", index, e),
+            CombineError::Psbt(e) => write!(f, "combining PSBTs: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for CombineError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        use self::CombineError::*;
+
+        match self {
+            DifferentTransactions
+            | Conflict { .. }
+            | DescriptorMismatch { .. }
+            | InvalidSignature { .. } => None,
+            Sighash(e, _) => Some(e),
+            Psbt(e) => Some(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{absolute, transaction, Amount, OutPoint, ScriptBuf, Transaction, TxIn, TxOut};
+
+    use super::*;
+    use crate::descriptor::KeyMap;
+    use crate::psbt::sign::sign;
+
+    #[test]
+    fn combine_signatures_checked_against_descriptors() {
+        let secp = Secp256k1::new();
+        let tprv = "tprv8ZgxMBicQKsPcwcD4gSnMti126ZiETsuX7qwrtMypr6FBwAP65puFn4v6c3jrN9VwtMRMph6nyT63NrfUL4C3nBzPcduzVSuHD7zbX2JKVc";
+        let mut keys = KeyMap::new();
+        let mut descs = vec![];
+        for desc in [
+            format!("wsh(multi(2,{}/0/*,{}/1/*))", tprv, tprv),
+            format!("tr({}/2/*,pk({}/3/*))", tprv, tprv),
+        ] {
+            let (desc, key_map) = Descriptor::parse_descriptor(&secp, &desc).unwrap();
+            keys.extend(key_map);
+            descs.push(desc);
+        }
+        // Each party holds the first or the second key of each descriptor
+        let (keys_a, keys_b): (KeyMap, KeyMap) = keys.into_iter().partition(|(pk, _)| {
+            let pk = pk.to_string();
+            pk.ends_with("/0/*") || pk.ends_with("/2/*")
+        });
+
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn { previous_output: OutPoint::default(), ..TxIn::default() }; 2],
+            output: vec![TxOut {
+                value: Amount::from_sat(15_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        for (index, desc) in descs.iter().enumerate() {
+            let desc = desc.at_derivation_index(7).unwrap();
+            psbt.inputs[index].witness_utxo = Some(TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: desc.script_pubkey(),
+            });
+            psbt.update_input_with_descriptor(index, &desc).unwrap();
+        }
+        let mut a = psbt.clone();
+        sign(&mut a, &keys_a, &secp).unwrap();
+        let mut b = psbt;
+        sign(&mut b, &keys_b, &secp).unwrap();
+
+        let mut combined = a.clone();
+        combined
+            .combine_with_descriptors(b.clone(), &descs)
+            .unwrap();
+        assert_eq!(combined.inputs[0].partial_sigs.len(), 2);
+        assert!(combined.inputs[1].tap_key_sig.is_some());
+        assert_eq!(combined.inputs[1].tap_script_sigs.len(), 1);
+        // Combining is idempotent
+        let mut again = combined.clone();
+        again.combine_with_descriptors(b.clone(), &descs).unwrap();
+        assert_eq!(again, combined);
+        combined.finalize_mut(&secp).unwrap();
+
+        let combine = |other: Psbt| {
+            let mut ours = a.clone();
+            let res = ours.combine_with_descriptors(other, &descs);
+            if res.is_err() {
+                assert_eq!(ours, a);
+            }
+            res
+        };
+
+        let mut other = b.clone();
+        other.unsigned_tx.lock_time = absolute::LockTime::from_consensus(1);
+        assert!(matches!(combine(other), Err(CombineError::DifferentTransactions)));
+
+        let mut other = b.clone();
+        other.inputs[0].witness_script = Some(ScriptBuf::new());
+        assert!(matches!(
+            combine(other),
+            Err(CombineError::Conflict { index: 0, field: InputField::WitnessScript }),
+        ));
+
+        // A key foreign to the descriptor
+        let foreign = secp256k1::SecretKey::from_slice(&[1; 32])
+            .unwrap()
+            .public_key(&secp);
+        let mut other = b.clone();
+        other.inputs[0]
+            .bip32_derivation
+            .insert(foreign, Default::default());
+        assert!(matches!(
+            combine(other),
+            Err(CombineError::DescriptorMismatch {
+                index: 0,
+                field: InputField::Bip32Derivation(pk),
+            }) if pk == foreign,
+        ));
+
+        // A leaf signature actually made for the key spend
+        let mut other = b.clone();
+        let key_sig = a.inputs[1].tap_key_sig.unwrap();
+        let (&(pk, leaf_hash), sig) = other.inputs[1].tap_script_sigs.iter_mut().next().unwrap();
+        sig.signature = key_sig.signature;
+        assert!(matches!(
+            combine(other),
+            Err(CombineError::InvalidSignature {
+                index: 1,
+                field: InputField::TapScriptSig(p, l),
+            }) if p == pk && l == leaf_hash,
+        ));
+    }
+}
@@ -20,7 +20,7 @@ use bitcoin::sighash::Prevouts;
 use bitcoin::taproot::LeafVersion;
 use bitcoin::{PublicKey, Script, ScriptBuf, TxOut, Witness};
 
-use super::{sanity_check, Error, InputError, Psbt, PsbtInputSatisfier};
+use super::{musig2, sanity_check, Error, InputError, Psbt, PsbtInputSatisfier};
 use crate::prelude::*;
 use crate::util::witness_size;
 use crate::{
@@ -32,6 +32,10 @@ use crate::{
 // descriptor from psbt because the information about all the scripts might not
 // be present. Also, currently the spec does not support hidden branches, so
 // inferring a descriptor is not possible
+//
+// The key spend is used if its signature is present. Otherwise every leaf is
+// tried, and of those whose signatures are present and whose timelocks the
+// transaction satisfies, the one with the smallest witness is used.
 fn construct_tap_witness(
     spk: &Script,
     sat: &PsbtInputSatisfier,
@@ -89,13 +93,11 @@ fn construct_tap_witness(
             };
             wit.push(ms.encode().into_bytes());
             wit.push(control_block.serialize());
-            let wit_len = Some(witness_size(&wit));
-            if min_wit_len.is_some() && wit_len > min_wit_len {
-                continue;
-            } else {
-                // store the minimum
+            // store the minimum, keeping the first leaf found on a tie
+            let wit_len = witness_size(&wit);
+            if min_wit_len.map_or(true, |min_len| wit_len < min_len) {
                 min_wit = Some(wit);
-                min_wit_len = wit_len;
+                min_wit_len = Some(wit_len);
             }
         }
         min_wit.ok_or(InputError::CouldNotSatisfyTr)
@@ -407,9 +409,15 @@ fn finalize_input_helper<C: secp256k1::Verification>(
         let sat = PsbtInputSatisfier::new(psbt, index);
 
         if spk.is_p2tr() {
+            // A MuSig2 internal key signs the key spend once its partial signatures are aggregated
+            let musig2_sig = musig2::aggregate_key_spend_sig(psbt, index, secp)
+                .map_err(|e| Error::InputError(InputError::Musig2(e), index))?;
             // Deal with tr case separately, unfortunately we cannot infer the full descriptor for Tr
-            let wit = construct_tap_witness(&spk, &sat, allow_mall)
-                .map_err(|e| Error::InputError(e, index))?;
+            let wit = match musig2_sig {
+                Some(sig) => vec![sig.to_vec()],
+                None => construct_tap_witness(&spk, &sat, allow_mall)
+                    .map_err(|e| Error::InputError(e, index))?,
+            };
             (wit, ScriptBuf::new())
         } else {
             // Get a descriptor for this input.
@@ -481,4 +489,146 @@ mod tests {
         let expected = Psbt::deserialize(&Vec::<u8>::from_hex("70736274ff01009a020000000258e87a21b56daf0c23be8e7070456c336f7cbaa5c8757924f545887bb2abdd750000000000ffffffff838d0427d0ec650a68aa46bb0b098aea4422c071b2ca78352a077959d07cea1d0100000000ffffffff0270aaf00800000000160014d85c2b71d0060b09c9886aeb815e50991dda124d00e1f5050000000016001400aea9a2e5f0f876a588df5546e8742d1d87008f00000000000100bb0200000001aad73931018bd25f84ae400b68848be09db706eac2ac18298babee71ab656f8b0000000048473044022058f6fc7c6a33e1b31548d481c826c015bd30135aad42cd67790dab66d2ad243b02204a1ced2604c6735b6393e5b41691dd78b00f0c5942fb9f751856faa938157dba01feffffff0280f0fa020000000017a9140fb9463421696b82c833af241c78c17ddbde493487d0f20a270100000017a91429ca74f8a08f81999428185c97b5d852e4063f6187650000000107da00473044022074018ad4180097b873323c0015720b3684cc8123891048e7dbcd9b55ad679c99022073d369b740e3eb53dcefa33823c8070514ca55a7dd9544f157c167913261118c01483045022100f61038b308dc1da865a34852746f015772934208c6d24454393cd99bdf2217770220056e675a675a6d0a02b85b14e5e29074d8a25a9b5760bea2816f661910a006ea01475221029583bf39ae0a609747ad199addd634fa6108559d6c5cd39b4c2183f1ab96e07f2102dab61ff49a14db6a7d02b0cd1fbb78fc4b18312b5b4e54dae4dba2fbfef536d752ae0001012000c2eb0b0000000017a914b7f5faf40e3d40a5a459b1db3535f2b72fa921e8870107232200208c2353173743b595dfb4a07b72ba8e42e3797da74e87fe7d9d7497e3b20289030108da0400473044022062eb7a556107a7c73f45ac4ab5a1dddf6f7075fb1275969a7f383efff784bcb202200c05dbb7470dbf2f08557dd356c7325c1ed30913e996cd3840945db12228da5f01473044022065f45ba5998b59a27ffe1a7bed016af1f1f90d54b3aa8f7450aa5f56a25103bd02207f724703ad1edb96680b284b56d4ffcb88f7fb759eabbe08aa30f29b851383d20147522103089dc10c7ac6db54f91329af617333db388cead0c231f723379d1b99030b02dc21023add904f3d6dcf59ddb906b0dee23529b7ffb9ed50e5e86151926860221f0e7352ae00220203a9a4c37f5996d3aa25dbac6b570af0650394492942460b354753ed9eeca5877110d90c6a4f000000800000008004000080002202027f6399757d2eff55a136ad02c684b1838b6556e5f1b6b34282a94b6b5005109610d90c6a4f00000080000000800500008000").unwrap()).unwrap();
         assert_eq!(psbt, expected);
     }
+
+    #[test]
+    fn finalize_rejects_invalid_signatures() {
+        use bitcoin::{absolute, transaction, Amount, OutPoint, Transaction, TxIn};
+
+        use crate::descriptor::KeyMap;
+        use crate::psbt::sign;
+
+        let secp = Secp256k1::new();
+        let tprv = "tprv8ZgxMBicQKsPcwcD4gSnMti126ZiETsuX7qwrtMypr6FBwAP65puFn4v6c3jrN9VwtMRMph6nyT63NrfUL4C3nBzPcduzVSuHD7zbX2JKVc";
+        let mut keys = KeyMap::new();
+        let mut descs = vec![];
+        for desc in ["wpkh({}/84'/1'/0'/0/0)", "tr({}/86'/1'/0'/0/0)"] {
+            let desc = desc.replace("{}", tprv);
+            let (desc, key_map) = Descriptor::parse_descriptor(&secp, &desc).unwrap();
+            keys.extend(key_map);
+            descs.push(desc.at_derivation_index(0).unwrap());
+        }
+
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn { previous_output: OutPoint::default(), ..TxIn::default() }; 2],
+            output: vec![TxOut {
+                value: Amount::from_sat(15_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        for (idx, desc) in descs.iter().enumerate() {
+            psbt.inputs[idx].witness_utxo = Some(TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: desc.script_pubkey(),
+            });
+            psbt.update_input_with_descriptor(idx, desc).unwrap();
+        }
+        sign::sign(&mut psbt, &keys, &secp).unwrap();
+
+        // Changing the transaction invalidates the signatures
+        let mut tampered = psbt.clone();
+        tampered.unsigned_tx.output[0].value = Amount::from_sat(14_000);
+        let errors = tampered.clone().finalize_mut(&secp).unwrap_err();
+        assert_eq!(errors.len(), 2);
+        for (idx, error) in errors.iter().enumerate() {
+            match error {
+                Error::InputError(
+                    InputError::Interpreter(interpreter::Error::PkEvaluationError(_)),
+                    err_idx,
+                ) => assert_eq!(*err_idx, idx),
+                e => panic!("unexpected error {}", e),
+            }
+        }
+        // The failing inputs are left untouched
+        let original = tampered.clone();
+        assert!(tampered.finalize_inp_mut(&secp, 0).is_err());
+        assert_eq!(tampered, original);
+
+        psbt.finalize_mut(&secp).unwrap();
+    }
+
+    #[test]
+    fn finalize_cheapest_tap_leaf() {
+        use core::str::FromStr;
+
+        use bitcoin::secp256k1::{Keypair, SecretKey};
+        use bitcoin::sighash::{SighashCache, TapSighashType};
+        use bitcoin::taproot::{self, TapLeafHash};
+        use bitcoin::{absolute, transaction, Amount, OutPoint, Sequence, Transaction, TxIn};
+
+        use crate::DefiniteDescriptorKey;
+
+        let secp = Secp256k1::new();
+        let keypairs: Vec<Keypair> = (1..=4)
+            .map(|i| Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[i; 32]).unwrap()))
+            .collect();
+        let keys: Vec<XOnlyPublicKey> = keypairs
+            .iter()
+            .map(|keypair| keypair.x_only_public_key().0)
+            .collect();
+        let leaves = [
+            format!("and_v(v:pk({}),pk({}))", keys[1], keys[2]),
+            format!("and_v(v:pk({}),older(10))", keys[3]),
+        ];
+        let desc = format!("tr({},{{{},{}}})", keys[0], leaves[0], leaves[1]);
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&desc).unwrap();
+
+        // Finalizes with signatures from all keys but the internal one, returning the leaf used
+        let finalize = |sequence: Sequence| {
+            let tx = Transaction {
+                version: transaction::Version::TWO,
+                lock_time: absolute::LockTime::ZERO,
+                input: vec![TxIn {
+                    previous_output: OutPoint::default(),
+                    sequence,
+                    ..TxIn::default()
+                }],
+                output: vec![TxOut {
+                    value: Amount::from_sat(9_000),
+                    script_pubkey: ScriptBuf::new(),
+                }],
+            };
+            let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+            psbt.inputs[0].witness_utxo = Some(TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: desc.script_pubkey(),
+            });
+            psbt.update_input_with_descriptor(0, &desc).unwrap();
+
+            let mut cache = SighashCache::new(psbt.unsigned_tx.clone());
+            let leaf_hashes: Vec<_> = psbt.inputs[0]
+                .tap_scripts
+                .values()
+                .map(|(script, ver)| TapLeafHash::from_script(script, *ver))
+                .collect();
+            for leaf_hash in leaf_hashes {
+                let msg = psbt
+                    .sighash_msg(0, &mut cache, Some(leaf_hash))
+                    .unwrap()
+                    .to_secp_msg();
+                for keypair in &keypairs[1..] {
+                    let sig = taproot::Signature {
+                        signature: secp.sign_schnorr_no_aux_rand(&msg, keypair),
+                        sighash_type: TapSighashType::Default,
+                    };
+                    let key = keypair.x_only_public_key().0;
+                    psbt.inputs[0].tap_script_sigs.insert((key, leaf_hash), sig);
+                }
+            }
+            psbt.finalize_mut(&secp).unwrap();
+            let witness = psbt.inputs[0].final_script_witness.as_ref().unwrap();
+            witness.taproot_leaf_script().unwrap().script.to_owned()
+        };
+        let leaf_script = |leaf: &str| {
+            Miniscript::<XOnlyPublicKey, Tap>::from_str(leaf)
+                .unwrap()
+                .encode()
+        };
+
+        // The timelocked leaf is cheaper, but can only be used once the sequence allows it
+        assert_eq!(finalize(Sequence::MAX), leaf_script(&leaves[0]));
+        assert_eq!(finalize(Sequence::from_height(10)), leaf_script(&leaves[1]));
+    }
 }
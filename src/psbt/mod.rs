@@ -6,6 +6,8 @@
 //! BIP 174, PSBT, described at
 //! `https://github.com/bitcoin/bips/blob/master/bip-0174.mediawiki`
 //!
+//! A software Signer for descriptor private keys is provided in [`sign`].
+//!
 
 use core::convert::TryFrom;
 use core::fmt;
@@ -13,25 +15,38 @@ use core::fmt;
 use std::error;
 
 use bitcoin::hashes::{hash160, sha256d, Hash};
+use bitcoin::key::XOnlyPublicKey;
 use bitcoin::psbt::{self, Psbt};
 #[cfg(not(test))] // https://github.com/rust-lang/rust/issues/121684
 use bitcoin::secp256k1;
-use bitcoin::secp256k1::{Secp256k1, VerifyOnly};
+use bitcoin::secp256k1::Secp256k1;
 use bitcoin::sighash::{self, SighashCache};
-use bitcoin::taproot::{self, ControlBlock, LeafVersion, TapLeafHash};
-use bitcoin::{absolute, bip32, relative, transaction, Script, ScriptBuf};
+use bitcoin::taproot::{self, ControlBlock, LeafVersion, TapLeafHash, TapNodeHash};
+use bitcoin::{absolute, bip32, relative, transaction, Amount, FeeRate, Script, ScriptBuf, Weight};
 
+use crate::descriptor::{SinglePub, SinglePubKey, TapTree};
 use crate::miniscript::context::SigType;
+use crate::plan::Plan;
+use crate::policy::{LiftError, Liftable, Semantic};
 use crate::prelude::*;
+use crate::sync::Arc;
+use crate::util::with_verify_context;
 use crate::{
-    descriptor, interpreter, DefiniteDescriptorKey, Descriptor, DescriptorPublicKey, MiniscriptKey,
-    Preimage32, Satisfier, ToPublicKey, Translator,
+    descriptor, interpreter, BareCtx, DefiniteDescriptorKey, Descriptor, DescriptorPublicKey,
+    ExtParams, Legacy, Miniscript, MiniscriptKey, Preimage32, Satisfier, Segwitv0, Tap, Threshold,
+    ToPublicKey, TranslateErr, Translator,
 };
 
+mod combiner;
 mod finalizer;
+pub mod musig2;
+mod proprietary;
+pub mod sign;
 
+pub use self::combiner::{CombineError, InputField};
 #[allow(deprecated)]
 pub use self::finalizer::{finalize, finalize_mall, interpreter_check};
+pub use self::proprietary::{PlanDecodeError, PSBT_IN_PLAN, PSBT_PROPRIETARY_PREFIX};
 
 /// Error type for entire Psbt
 #[derive(Debug)]
@@ -72,7 +87,7 @@ impl fmt::Display for Error {
 
 #[cfg(feature = "std")]
 impl error::Error for Error {
-    fn cause(&self) -> Option<&dyn error::Error> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         use self::Error::*;
 
         match self {
@@ -96,6 +111,11 @@ pub enum InputError {
     CouldNotSatisfyTr,
     /// Error doing an interpreter-check on a finalized psbt
     Interpreter(interpreter::Error),
+    /// The taproot leaves do not form a complete tree of tapscript leaves, or
+    /// do not match the internal key or merkle root of the input
+    InvalidTapTree,
+    /// Invalid MuSig2 fields, or failure to aggregate their partial signatures
+    Musig2(musig2::Musig2Error),
     /// Redeem script does not match the p2sh hash
     InvalidRedeemScript {
         /// Redeem script
@@ -148,11 +168,12 @@ pub enum InputError {
 
 #[cfg(feature = "std")]
 impl error::Error for InputError {
-    fn cause(&self) -> Option<&dyn error::Error> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         use self::InputError::*;
 
         match self {
             CouldNotSatisfyTr
+            | InvalidTapTree
             | InvalidRedeemScript { .. }
             | InvalidWitnessScript { .. }
             | InvalidSignature { .. }
@@ -168,6 +189,7 @@ impl error::Error for InputError {
             SecpErr(e) => Some(e),
             KeyErr(e) => Some(e),
             Interpreter(e) => Some(e),
+            Musig2(e) => Some(e),
             MiniscriptError(e) => Some(e),
         }
     }
@@ -181,6 +203,7 @@ impl fmt::Display for InputError {
             }
             InputError::KeyErr(ref e) => write!(f, "Key Err: {}", e),
             InputError::Interpreter(ref e) => write!(f, "Interpreter: {}", e),
+            InputError::Musig2(ref e) => write!(f, "MuSig2: {}", e),
             InputError::SecpErr(ref e) => write!(f, "Secp Err: {}", e),
             InputError::InvalidRedeemScript { ref redeem, ref p2sh_expected } => write!(
                 f,
@@ -213,6 +236,9 @@ impl fmt::Display for InputError {
                 pubkey, got, required
             ),
             InputError::CouldNotSatisfyTr => write!(f, "Could not satisfy Tr descriptor"),
+            InputError::InvalidTapTree => {
+                write!(f, "PSBT taproot leaves do not form a complete and consistent tap tree")
+            }
             InputError::NonStandardSighashType(ref e) => {
                 write!(f, "Non-standard sighash type {}", e)
             }
@@ -424,6 +450,16 @@ pub trait PsbtExt {
     /// that it cannot finalize. Also performs a sanity interpreter check on the
     /// finalized psbt which involves checking the signatures/ preimages/timelocks.
     ///
+    /// The interpreter check runs on each input's final script sig and witness before they
+    /// are set, against the utxo the input spends. Signatures are checked against the sighash
+    /// of the input, computed with the utxos of all inputs, and timelocks against the unsigned
+    /// transaction. An input failing the check is left untouched and reported with
+    /// [`InputError::Interpreter`], so a finalized input is always valid.
+    ///
+    /// A taproot input whose internal key is listed as a MuSig2 aggregate (see
+    /// [`PsbtInputExt::set_musig2_participants`]) is finalized with a key spend once every
+    /// participant has provided its public nonce and partial signature.
+    ///
     /// Input finalization also fails if it is not possible to satisfy any of the inputs non-malleably
     /// See [finalizer::finalize_mall] if you want to allow malleable satisfactions
     ///
@@ -487,6 +523,20 @@ pub trait PsbtExt {
         index: usize,
     ) -> Result<Psbt, (Psbt, Error)>;
 
+    /// Finalize the psbt, allowing malleable satisfactions only for the inputs which have no
+    /// non-malleable one.
+    ///
+    /// Each input is finalized as with [`PsbtExt::finalize_inp_mut`] if possible, and otherwise
+    /// as with [`PsbtExt::finalize_inp_mall_mut`]. Inputs that can't be finalized either way are
+    /// left untouched.
+    ///
+    /// Returns how each input was finalized, in input order, along with the reasons the
+    /// non-malleable (and malleable) finalization failed.
+    fn finalize_mall_fallback_mut<C: secp256k1::Verification>(
+        &mut self,
+        secp: &Secp256k1<C>,
+    ) -> Vec<InputFinalization>;
+
     /// Same as [`PsbtExt::finalize_inp_mut`], but allows for malleable satisfactions
     fn finalize_inp_mall_mut<C: secp256k1::Verification>(
         &mut self,
@@ -538,6 +588,31 @@ pub trait PsbtExt {
         descriptor: &Descriptor<DefiniteDescriptorKey>,
     ) -> Result<(), UtxoUpdateError>;
 
+    /// Checks that the input at `input_index` spends `descriptor` at `derivation_index`.
+    ///
+    /// A signer should run this before signing an input it claims to control, so that a
+    /// maliciously crafted PSBT can't make it sign for other scripts or misstate the amount
+    /// spent. Checks that:
+    ///
+    /// - the `witness_utxo` and `non_witness_utxo` are consistent with each other and with the
+    ///   outpoint spent, and pay to the descriptor. Legacy inputs must have a `non_witness_utxo`,
+    ///   as only it commits to the amount spent.
+    /// - the scripts, taproot fields and key origins of the input are those
+    ///   [`PsbtInputExt::update_with_descriptor_unchecked`] would set. Fields it would set
+    ///   but which are absent are not mismatches.
+    /// - the signatures are made by keys of the descriptor, for leaves containing those keys.
+    ///   They are not verified.
+    ///
+    /// Returns every mismatch found, none if the input is consistent with the descriptor. Only
+    /// the [`UtxoUpdateError::IndexOutOfBounds`], [`UtxoUpdateError::MissingInputUtxo`] and
+    /// [`UtxoUpdateError::DerivationError`] errors are returned.
+    fn check_input_descriptor(
+        &self,
+        input_index: usize,
+        descriptor: &Descriptor<DescriptorPublicKey>,
+        derivation_index: u32,
+    ) -> Result<Vec<InputMismatch>, UtxoUpdateError>;
+
     /// Update PSBT output with a descriptor and check consistency of the output's `script_pubkey`
     ///
     /// This is the checked version of [`update_with_descriptor_unchecked`]. It checks that the
@@ -553,6 +628,24 @@ pub trait PsbtExt {
         descriptor: &Descriptor<DefiniteDescriptorKey>,
     ) -> Result<(), OutputUpdateError>;
 
+    /// Update the PSBT outputs which pay to a change descriptor
+    ///
+    /// The descriptor is derived at each index in `indices`, and every output whose
+    /// `script_pubkey` matches one of the derived descriptors is updated as with
+    /// [`update_output_with_descriptor`]. This populates the witness and redeem scripts,
+    /// the taproot tree and the key origins of the change outputs, so that signers can
+    /// verify them. Returns the indices of the updated outputs.
+    ///
+    /// The `descriptor` must not be multipath. Use [`Descriptor::into_single_descriptors`]
+    /// to obtain its change branch.
+    ///
+    /// [`update_output_with_descriptor`]: PsbtExt::update_output_with_descriptor
+    fn update_change_outputs(
+        &mut self,
+        descriptor: &Descriptor<DescriptorPublicKey>,
+        indices: core::ops::Range<u32>,
+    ) -> Result<Vec<usize>, OutputUpdateError>;
+
     /// Get the sighash message(data to sign) at input index `idx`.
     ///
     /// Based on the sighash
@@ -580,6 +673,84 @@ pub trait PsbtExt {
         cache: &mut SighashCache<T>,
         tapleaf_hash: Option<TapLeafHash>,
     ) -> Result<PsbtSighashMsg, SighashError>;
+
+    /// Get the sighash message at input index `idx` for an input spending `descriptor`, along with
+    /// the sighash type it commits to.
+    ///
+    /// Unlike [`PsbtExt::sighash_msg`] the script code is computed from the descriptor instead of
+    /// being read from the `redeem_script` and `witness_script` fields of the input, so signers
+    /// (e.g. hardware signers) can be driven from the descriptor alone. The descriptor must match
+    /// the `script_pubkey` of the spent utxo. The sighash type is chosen as in
+    /// [`PsbtExt::sighash_msg`].
+    ///
+    /// For taproot descriptors, `tapleaf_hash` selects the leaf to compute the script spend hash
+    /// for, and must be the hash of one of the descriptor's leaves. The key spend hash is computed
+    /// if it is [`None`]. If `annex` is set it is committed to, as specified in BIP 341. Both must
+    /// be [`None`] for other descriptors.
+    fn sighash_msg_for_descriptor<T: Borrow<bitcoin::Transaction>>(
+        &self,
+        idx: usize,
+        cache: &mut SighashCache<T>,
+        descriptor: &Descriptor<DefiniteDescriptorKey>,
+        tapleaf_hash: Option<TapLeafHash>,
+        annex: Option<sighash::Annex>,
+    ) -> Result<InputSighash, SighashError>;
+
+    /// Estimate the weight of the transaction once all of its inputs are finalized.
+    ///
+    /// Finalized inputs are counted with their `final_script_sig` and `final_script_witness`.
+    /// Every other input is matched to the descriptor in `descriptors` with the `script_pubkey`
+    /// of the utxo it spends, and counted with [`Descriptor::max_weight_to_satisfy`]. The
+    /// estimate is therefore an upper bound, which is exact once all inputs are finalized.
+    fn estimate_weight(
+        &self,
+        descriptors: &[Descriptor<DefiniteDescriptorKey>],
+    ) -> Result<Weight, EstimateError>;
+
+    /// Estimate the fee rate of the transaction once all of its inputs are finalized.
+    ///
+    /// The fee is the value of the utxos spent by the inputs minus the value of the outputs,
+    /// and the weight is estimated as in [`PsbtExt::estimate_weight`]. Since the weight is an
+    /// upper bound, the fee rate is a lower bound.
+    fn estimate_fee_rate(
+        &self,
+        descriptors: &[Descriptor<DefiniteDescriptorKey>],
+    ) -> Result<FeeRate, EstimateError>;
+
+    /// Find the descriptor controlling each input, among `descriptors`.
+    ///
+    /// Ranged descriptors are derived at the indices found in the key origins of the input,
+    /// i.e. the last step of the paths in its `bip32_derivation` and `tap_key_origins`, and
+    /// the derived descriptor is checked against the `script_pubkey` of the utxo the input
+    /// spends. Descriptors without wildcards are checked against it directly. Multipath
+    /// descriptors are split into their single path descriptors.
+    ///
+    /// Returns the match for each input, in input order, or `None` if no descriptor matches
+    /// (including when the input has no utxo). Inputs with no key origins can still be matched
+    /// by scanning derivation indices with [`Descriptor::find_derivation_index_for_spk`].
+    fn match_inputs(
+        &self,
+        descriptors: &[Descriptor<DescriptorPublicKey>],
+    ) -> Vec<Option<InputMatch>>;
+
+    /// Combines `other`, a PSBT for the same transaction, into this one.
+    ///
+    /// Unlike [`Psbt::combine`], which picks arbitrarily when the PSBTs hold different values
+    /// for the same input field, this returns a [`CombineError::Conflict`] naming the field.
+    /// The leaves listed for a key in `tap_key_origins` are merged.
+    ///
+    /// The combined inputs controlled by one of `descriptors` (see [`PsbtExt::match_inputs`])
+    /// are then checked against it: their key origins, leaf scripts, scripts and taproot keys
+    /// must be those the descriptor produces, their signatures must be made by keys of the
+    /// descriptor (for the leaves of those keys) and must verify. Other inputs are only checked
+    /// for conflicts.
+    ///
+    /// This PSBT is left untouched if an error is returned.
+    fn combine_with_descriptors(
+        &mut self,
+        other: Psbt,
+        descriptors: &[Descriptor<DescriptorPublicKey>],
+    ) -> Result<(), CombineError>;
 }
 
 impl PsbtExt for Psbt {
@@ -666,6 +837,25 @@ impl PsbtExt for Psbt {
         }
     }
 
+    fn finalize_mall_fallback_mut<C: secp256k1::Verification>(
+        &mut self,
+        secp: &Secp256k1<C>,
+    ) -> Vec<InputFinalization> {
+        (0..self.inputs.len())
+            .map(|index| {
+                let non_malleable =
+                    match finalizer::finalize_input(self, index, secp, /*allow_mall*/ false) {
+                        Ok(()) => return InputFinalization::NonMalleable,
+                        Err(e) => e,
+                    };
+                match finalizer::finalize_input(self, index, secp, /*allow_mall*/ true) {
+                    Ok(()) => InputFinalization::Malleable(non_malleable),
+                    Err(malleable) => InputFinalization::Failed { non_malleable, malleable },
+                }
+            })
+            .collect()
+    }
+
     fn finalize_inp_mall_mut<C: secp256k1::Verification>(
         &mut self,
         secp: &secp256k1::Secp256k1<C>,
@@ -674,7 +864,7 @@ impl PsbtExt for Psbt {
         if index >= self.inputs.len() {
             return Err(Error::InputIdxOutofBounds { psbt_inp: self.inputs.len(), index });
         }
-        finalizer::finalize_input(self, index, secp, /*allow_mall*/ false)
+        finalizer::finalize_input(self, index, secp, /*allow_mall*/ true)
     }
 
     fn finalize_inp_mall<C: secp256k1::Verification>(
@@ -777,6 +967,73 @@ impl PsbtExt for Psbt {
         Ok(())
     }
 
+    fn check_input_descriptor(
+        &self,
+        input_index: usize,
+        descriptor: &Descriptor<DescriptorPublicKey>,
+        derivation_index: u32,
+    ) -> Result<Vec<InputMismatch>, UtxoUpdateError> {
+        let n_inputs = self.inputs.len();
+        let input = self
+            .inputs
+            .get(input_index)
+            .ok_or(UtxoUpdateError::IndexOutOfBounds(input_index, n_inputs))?;
+        let txin = self
+            .unsigned_tx
+            .input
+            .get(input_index)
+            .ok_or(UtxoUpdateError::MissingInputUtxo)?;
+        let descriptor = descriptor
+            .at_derivation_index(derivation_index)
+            .map_err(UtxoUpdateError::DerivationError)?;
+        let (derived, fields) = combiner::field_mismatches(input, &descriptor)
+            .map_err(UtxoUpdateError::DerivationError)?;
+
+        let mut mismatches = vec![];
+        let spent_output = match input.non_witness_utxo {
+            Some(ref tx) if tx.compute_txid() == txin.previous_output.txid => {
+                tx.output.get(txin.previous_output.vout as usize)
+            }
+            Some(_) | None => None,
+        };
+        if input.non_witness_utxo.is_some() && spent_output.is_none() {
+            mismatches.push(InputMismatch::NonWitnessUtxo);
+        }
+        match (&input.witness_utxo, spent_output) {
+            (Some(witness_utxo), Some(spent_output)) if witness_utxo != spent_output => {
+                mismatches.push(InputMismatch::WitnessUtxo)
+            }
+            (None, _) if input.non_witness_utxo.is_none() => {
+                mismatches.push(InputMismatch::MissingUtxo)
+            }
+            (Some(_), _)
+                if input.non_witness_utxo.is_none()
+                    && derived.desc_type().segwit_version().is_none() =>
+            {
+                mismatches.push(InputMismatch::MissingNonWitnessUtxo)
+            }
+            _ => {}
+        }
+        let expected = derived.script_pubkey();
+        let mut found: Vec<_> = input
+            .witness_utxo
+            .iter()
+            .chain(spent_output)
+            .map(|utxo| &utxo.script_pubkey)
+            .filter(|spk| **spk != expected)
+            .collect();
+        found.dedup();
+        for spk in found {
+            mismatches.push(InputMismatch::ScriptPubkey {
+                expected: expected.clone(),
+                found: spk.clone(),
+            });
+        }
+
+        mismatches.extend(fields.into_iter().map(InputMismatch::Field));
+        Ok(mismatches)
+    }
+
     fn update_output_with_descriptor(
         &mut self,
         output_index: usize,
@@ -804,6 +1061,40 @@ impl PsbtExt for Psbt {
         Ok(())
     }
 
+    fn update_change_outputs(
+        &mut self,
+        descriptor: &Descriptor<DescriptorPublicKey>,
+        indices: core::ops::Range<u32>,
+    ) -> Result<Vec<usize>, OutputUpdateError> {
+        // Without wildcards, every index gives the same descriptor.
+        let indices = if descriptor.has_wildcard() {
+            indices
+        } else {
+            0..1
+        };
+        let mut derived = BTreeMap::new();
+        for index in indices {
+            let desc = descriptor
+                .at_derivation_index(index)
+                .map_err(OutputUpdateError::DerivationError)?;
+            derived.insert(desc.script_pubkey(), desc);
+        }
+
+        let matching: Vec<_> = self
+            .unsigned_tx
+            .output
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, txout)| derived.get(&txout.script_pubkey).map(|desc| (idx, desc)))
+            .collect();
+        let mut updated = Vec::with_capacity(matching.len());
+        for (idx, desc) in matching {
+            self.update_output_with_descriptor(idx, desc)?;
+            updated.push(idx);
+        }
+        Ok(updated)
+    }
+
     fn sighash_msg<T: Borrow<bitcoin::Transaction>>(
         &self,
         idx: usize,
@@ -891,6 +1182,210 @@ impl PsbtExt for Psbt {
             }
         }
     }
+
+    fn sighash_msg_for_descriptor<T: Borrow<bitcoin::Transaction>>(
+        &self,
+        idx: usize,
+        cache: &mut SighashCache<T>,
+        descriptor: &Descriptor<DefiniteDescriptorKey>,
+        tapleaf_hash: Option<TapLeafHash>,
+        annex: Option<sighash::Annex>,
+    ) -> Result<InputSighash, SighashError> {
+        if idx >= self.inputs.len() {
+            return Err(SighashError::IndexOutOfBounds(idx, self.inputs.len()));
+        }
+        let inp = &self.inputs[idx];
+        let utxo = finalizer::get_utxo(self, idx).map_err(|_e| SighashError::MissingInputUtxo)?;
+        let derived = with_verify_context(|secp| descriptor.derived_descriptor(secp))
+            .map_err(SighashError::Conversion)?;
+        if derived.script_pubkey() != utxo.script_pubkey {
+            return Err(SighashError::DescriptorMismatch);
+        }
+
+        if let Descriptor::Tr(ref tr) = derived {
+            let hash_ty = inp
+                .sighash_type
+                .map(|sighash_type| sighash_type.taproot_hash_ty())
+                .unwrap_or(Ok(sighash::TapSighashType::Default))
+                .map_err(|_e| SighashError::InvalidSighashType)?;
+            let prevouts =
+                finalizer::prevouts(self).map_err(|_e| SighashError::MissingSpendUtxos)?;
+            let prevouts = bitcoin::sighash::Prevouts::All(&prevouts);
+            let leaf_hash_code_separator = match tapleaf_hash {
+                Some(leaf_hash) => {
+                    let is_leaf = |ms: &Miniscript<_, Tap>| {
+                        TapLeafHash::from_script(&ms.encode(), LeafVersion::TapScript) == leaf_hash
+                    };
+                    if !tr.iter_scripts().any(|(_, ms)| is_leaf(ms)) {
+                        return Err(SighashError::UnknownTapLeaf(leaf_hash));
+                    }
+                    // Miniscript never uses OP_CODESEPARATOR
+                    Some((leaf_hash, u32::MAX))
+                }
+                None => None,
+            };
+            let msg = cache.taproot_signature_hash(
+                idx,
+                &prevouts,
+                annex,
+                leaf_hash_code_separator,
+                hash_ty,
+            )?;
+            return Ok(InputSighash {
+                msg: PsbtSighashMsg::TapSighash(msg),
+                sighash_type: hash_ty.into(),
+            });
+        }
+
+        if let Some(leaf_hash) = tapleaf_hash {
+            return Err(SighashError::UnknownTapLeaf(leaf_hash));
+        }
+        if annex.is_some() {
+            return Err(SighashError::UnexpectedAnnex);
+        }
+        let hash_ty = inp
+            .sighash_type
+            .map(|sighash_type| sighash_type.ecdsa_hash_ty())
+            .unwrap_or(Ok(sighash::EcdsaSighashType::All))
+            .map_err(|_e| SighashError::InvalidSighashType)?;
+        let script_code = derived
+            .script_code()
+            .expect("Only taproot descriptors have no script code");
+        let msg = if derived.desc_type().segwit_version().is_some() {
+            // The BIP 143 digest only depends on the script code, whichever the output type
+            let msg = cache.p2wsh_signature_hash(idx, &script_code, utxo.value, hash_ty)?;
+            PsbtSighashMsg::SegwitV0Sighash(msg)
+        } else {
+            let msg = cache.legacy_signature_hash(idx, &script_code, hash_ty.to_u32())?;
+            PsbtSighashMsg::LegacySighash(msg)
+        };
+        Ok(InputSighash { msg, sighash_type: hash_ty.into() })
+    }
+
+    fn estimate_weight(
+        &self,
+        descriptors: &[Descriptor<DefiniteDescriptorKey>],
+    ) -> Result<Weight, EstimateError> {
+        let descriptors: BTreeMap<_, _> = descriptors
+            .iter()
+            .map(|desc| (desc.script_pubkey(), desc))
+            .collect();
+
+        let mut tx = self.unsigned_tx.clone();
+        let mut pending = Weight::ZERO;
+        let mut pending_segwit = false;
+        for (idx, (txin, inp)) in tx.input.iter_mut().zip(&self.inputs).enumerate() {
+            if inp.final_script_sig.is_some() || inp.final_script_witness.is_some() {
+                txin.script_sig = inp.final_script_sig.clone().unwrap_or_default();
+                txin.witness = inp.final_script_witness.clone().unwrap_or_default();
+                continue;
+            }
+            let utxo =
+                finalizer::get_utxo(self, idx).map_err(|e| EstimateError::InputError(e, idx))?;
+            let desc = descriptors
+                .get(&utxo.script_pubkey)
+                .ok_or(EstimateError::MissingDescriptor(idx))?;
+            pending += desc
+                .max_weight_to_satisfy()
+                .map_err(|_e| EstimateError::Unsatisfiable(idx))?;
+            pending_segwit |= desc.desc_type().segwit_version().is_some();
+        }
+
+        let mut weight = tx.weight() + pending;
+        if pending_segwit && tx.input.iter().all(|txin| txin.witness.is_empty()) {
+            // The segwit marker and flag, and the witness item count of every input
+            weight += Weight::from_wu(2 + tx.input.len() as u64);
+        }
+        Ok(weight)
+    }
+
+    fn estimate_fee_rate(
+        &self,
+        descriptors: &[Descriptor<DefiniteDescriptorKey>],
+    ) -> Result<FeeRate, EstimateError> {
+        let mut input_value = Amount::ZERO;
+        for idx in 0..self.inputs.len() {
+            let utxo =
+                finalizer::get_utxo(self, idx).map_err(|e| EstimateError::InputError(e, idx))?;
+            input_value = input_value
+                .checked_add(utxo.value)
+                .ok_or(EstimateError::ValueOverflow)?;
+        }
+        let mut output_value = Amount::ZERO;
+        for txout in &self.unsigned_tx.output {
+            output_value = output_value
+                .checked_add(txout.value)
+                .ok_or(EstimateError::ValueOverflow)?;
+        }
+        let fee = input_value
+            .checked_sub(output_value)
+            .ok_or(EstimateError::NegativeFee)?;
+
+        let weight = self.estimate_weight(descriptors)?;
+        // Values are at most 21e14 sats, so this can't overflow
+        Ok(FeeRate::from_sat_per_kwu(fee.to_sat() * 1000 / weight.to_wu()))
+    }
+
+    fn match_inputs(
+        &self,
+        descriptors: &[Descriptor<DescriptorPublicKey>],
+    ) -> Vec<Option<InputMatch>> {
+        let descriptors: Vec<_> = descriptors
+            .iter()
+            .enumerate()
+            .flat_map(|(descriptor_index, desc)| {
+                let single = desc.clone().into_single_descriptors().unwrap_or_default();
+                single.into_iter().map(move |desc| (descriptor_index, desc))
+            })
+            .collect();
+
+        (0..self.inputs.len())
+            .map(|idx| {
+                let spk = finalizer::get_scriptpubkey(self, idx).ok()?;
+                let inp = &self.inputs[idx];
+                let sources = inp
+                    .bip32_derivation
+                    .values()
+                    .chain(inp.tap_key_origins.values().map(|(_, source)| source));
+                let indices: BTreeSet<u32> = sources
+                    .filter_map(|(_, path)| path.into_iter().last())
+                    .map(|child| match *child {
+                        bip32::ChildNumber::Normal { index }
+                        | bip32::ChildNumber::Hardened { index } => index,
+                    })
+                    .collect();
+
+                descriptors.iter().find_map(|(descriptor_index, desc)| {
+                    let derivation_indices = if desc.has_wildcard() {
+                        indices.iter().map(|index| Some(*index)).collect()
+                    } else {
+                        vec![None]
+                    };
+                    derivation_indices.into_iter().find_map(|derivation_index| {
+                        let derived = desc
+                            .at_derivation_index(derivation_index.unwrap_or(0))
+                            .ok()?;
+                        if derived.script_pubkey() != spk {
+                            return None;
+                        }
+                        Some(InputMatch {
+                            descriptor_index: *descriptor_index,
+                            derivation_index,
+                            descriptor: derived,
+                        })
+                    })
+                })
+            })
+            .collect()
+    }
+
+    fn combine_with_descriptors(
+        &mut self,
+        other: Psbt,
+        descriptors: &[Descriptor<DescriptorPublicKey>],
+    ) -> Result<(), CombineError> {
+        combiner::combine_with_descriptors(self, other, descriptors)
+    }
 }
 
 /// Extension trait for PSBT inputs
@@ -901,6 +1396,12 @@ pub trait PsbtInputExt {
     /// descriptor an error will be returned. The descriptor *can* (and should) have extended keys in
     /// it so PSBT fields like `bip32_derivation` and `tap_key_origins` can be populated.
     ///
+    /// For taproot descriptors this sets all the BIP-371 input fields: `tap_internal_key`,
+    /// `tap_merkle_root`, a `tap_scripts` entry (with its control block) for every leaf, and a
+    /// `tap_key_origins` entry for every key listing the hashes of the leaves it appears in. For
+    /// other descriptors the `redeem_script`, `witness_script` and `bip32_derivation` fields are
+    /// set instead.
+    ///
     /// Note that his method doesn't check that the `witness_utxo` or `non_witness_utxo` is
     /// consistent with the descriptor. To do that see [`update_input_with_descriptor`].
     ///
@@ -915,6 +1416,48 @@ pub trait PsbtInputExt {
         &mut self,
         descriptor: &Descriptor<DefiniteDescriptorKey>,
     ) -> Result<Descriptor<bitcoin::PublicKey>, descriptor::ConversionError>;
+
+    /// Stores a [`Plan`] in the proprietary field with subtype [`PSBT_IN_PLAN`], replacing any
+    /// plan already stored there.
+    ///
+    /// This lets the party choosing how an input is spent share that choice (the spending path,
+    /// its witness template and thus its expected weight) with the signers and finalizers of the
+    /// PSBT, which can recover it with [`PsbtInputExt::plan`].
+    fn set_plan(&mut self, plan: &Plan);
+
+    /// Returns the [`Plan`] stored by [`PsbtInputExt::set_plan`], if any.
+    fn plan(&self) -> Result<Option<Plan>, PlanDecodeError>;
+
+    /// Returns the BIP-373 MuSig2 participant keys, indexed by the key they aggregate to.
+    fn musig2_participants(
+        &self,
+    ) -> Result<BTreeMap<secp256k1::PublicKey, Vec<secp256k1::PublicKey>>, musig2::Musig2Error>;
+
+    /// Lists the MuSig2 participant keys aggregating to `aggregate`, in aggregation order.
+    ///
+    /// When the taproot internal key of the input is such an aggregate, the finalizer
+    /// aggregates the partial signatures of its participants into the key spend signature.
+    fn set_musig2_participants(
+        &mut self,
+        aggregate: secp256k1::PublicKey,
+        participants: &[secp256k1::PublicKey],
+    );
+
+    /// Returns the BIP-373 MuSig2 public nonces of the participants.
+    fn musig2_pub_nonces(
+        &self,
+    ) -> Result<BTreeMap<musig2::Musig2Key, musig2::PubNonce>, musig2::Musig2Error>;
+
+    /// Adds the MuSig2 public nonce of a participant.
+    fn set_musig2_pub_nonce(&mut self, key: &musig2::Musig2Key, nonce: &musig2::PubNonce);
+
+    /// Returns the BIP-373 MuSig2 partial signatures of the participants.
+    fn musig2_partial_sigs(
+        &self,
+    ) -> Result<BTreeMap<musig2::Musig2Key, secp256k1::Scalar>, musig2::Musig2Error>;
+
+    /// Adds the MuSig2 partial signature of a participant.
+    fn set_musig2_partial_sig(&mut self, key: &musig2::Musig2Key, sig: &secp256k1::Scalar);
 }
 
 impl PsbtInputExt for psbt::Input {
@@ -925,6 +1468,333 @@ impl PsbtInputExt for psbt::Input {
         let (derived, _) = update_item_with_descriptor_helper(self, descriptor, None)?;
         Ok(derived)
     }
+
+    fn set_plan(&mut self, plan: &Plan) {
+        self.proprietary
+            .insert(proprietary::plan_key(), proprietary::encode_plan(plan));
+    }
+
+    fn plan(&self) -> Result<Option<Plan>, PlanDecodeError> {
+        self.proprietary
+            .get(&proprietary::plan_key())
+            .map(|value| proprietary::decode_plan(value))
+            .transpose()
+    }
+
+    fn musig2_participants(
+        &self,
+    ) -> Result<BTreeMap<secp256k1::PublicKey, Vec<secp256k1::PublicKey>>, musig2::Musig2Error>
+    {
+        musig2::participants(&self.unknown, musig2::PSBT_IN_MUSIG2_PARTICIPANT_PUBKEYS)
+    }
+
+    fn set_musig2_participants(
+        &mut self,
+        aggregate: secp256k1::PublicKey,
+        participants: &[secp256k1::PublicKey],
+    ) {
+        musig2::set_participants(
+            &mut self.unknown,
+            musig2::PSBT_IN_MUSIG2_PARTICIPANT_PUBKEYS,
+            aggregate,
+            participants,
+        )
+    }
+
+    fn musig2_pub_nonces(
+        &self,
+    ) -> Result<BTreeMap<musig2::Musig2Key, musig2::PubNonce>, musig2::Musig2Error> {
+        musig2::pub_nonces(&self.unknown)
+    }
+
+    fn set_musig2_pub_nonce(&mut self, key: &musig2::Musig2Key, nonce: &musig2::PubNonce) {
+        musig2::set_pub_nonce(&mut self.unknown, key, nonce)
+    }
+
+    fn musig2_partial_sigs(
+        &self,
+    ) -> Result<BTreeMap<musig2::Musig2Key, secp256k1::Scalar>, musig2::Musig2Error> {
+        musig2::partial_sigs(&self.unknown)
+    }
+
+    fn set_musig2_partial_sig(&mut self, key: &musig2::Musig2Key, sig: &secp256k1::Scalar) {
+        musig2::set_partial_sig(&mut self.unknown, key, sig)
+    }
+}
+
+/// Lifts the scripts of a PSBT input into the policy of the output it spends.
+///
+/// The policy is read from the taproot internal key and leaf scripts if present,
+/// and otherwise from the witness script or the redeem script. X-only keys are lifted
+/// as full keys with an even y-coordinate, and raw public key hashes are replaced by the
+/// keys in `bip32_derivation` and `partial_sigs` where possible.
+///
+/// The scripts are not checked against the `witness_utxo` or `non_witness_utxo` of the
+/// input.
+impl Liftable<bitcoin::PublicKey> for psbt::Input {
+    fn lift(&self) -> Result<Semantic<bitcoin::PublicKey>, crate::Error> {
+        if self.tap_internal_key.is_some() || !self.tap_scripts.is_empty() {
+            let mut branches = vec![];
+            if let Some(key) = self.tap_internal_key {
+                branches.push(Arc::new(Semantic::Key(key.to_public_key())));
+            }
+            for (script, ver) in self.tap_scripts.values() {
+                if *ver != LeafVersion::TapScript {
+                    return Err(LiftError::UnknownLeafVersion(*ver).into());
+                }
+                let ms = Miniscript::<XOnlyPublicKey, Tap>::parse_with_ext(
+                    script,
+                    &ExtParams::allow_all(),
+                )?;
+                match ms.lift()?.translate_pk(&mut XOnlyToFullKey) {
+                    Ok(policy) => branches.push(Arc::new(policy)),
+                    Err(e) => match e {},
+                }
+            }
+            return Ok(Semantic::Thresh(Threshold::or_n(branches)).normalized());
+        }
+
+        let mut key_map = BTreeMap::new();
+        let keys = self
+            .bip32_derivation
+            .keys()
+            .map(|key| bitcoin::PublicKey::new(*key));
+        for key in keys.chain(self.partial_sigs.keys().copied()) {
+            key_map.insert(key.pubkey_hash().to_raw_hash(), key);
+        }
+        match (&self.witness_script, &self.redeem_script) {
+            (Some(witness_script), _) => {
+                let ms = Miniscript::<bitcoin::PublicKey, Segwitv0>::parse_with_ext(
+                    witness_script,
+                    &ExtParams::allow_all(),
+                )?;
+                ms.substitute_raw_pkh(&key_map).lift()
+            }
+            (None, Some(redeem_script)) if redeem_script.is_p2wpkh() => {
+                let hash = hash160::Hash::from_slice(&redeem_script.as_bytes()[2..])
+                    .expect("p2wpkh scripts contain a 20-byte hash");
+                match key_map.get(&hash) {
+                    Some(key) => Ok(Semantic::Key(*key)),
+                    None => Err(LiftError::MissingKey.into()),
+                }
+            }
+            (None, Some(redeem_script)) => {
+                let ms = Miniscript::<bitcoin::PublicKey, Legacy>::parse_with_ext(
+                    redeem_script,
+                    &ExtParams::allow_all(),
+                )?;
+                ms.substitute_raw_pkh(&key_map).lift()
+            }
+            (None, None) => Err(LiftError::MissingScript.into()),
+        }
+    }
+}
+
+// Converts x-only keys to full keys with an even y-coordinate
+struct XOnlyToFullKey;
+
+impl Translator<XOnlyPublicKey> for XOnlyToFullKey {
+    type TargetPk = bitcoin::PublicKey;
+    type Error = core::convert::Infallible;
+
+    fn pk(&mut self, pk: &XOnlyPublicKey) -> Result<bitcoin::PublicKey, Self::Error> {
+        Ok(pk.to_public_key())
+    }
+
+    translate_hash_clone!(XOnlyPublicKey, bitcoin::PublicKey, Self::Error);
+}
+
+/// Reconstructs the descriptor of the output spent by a PSBT input from its fields.
+///
+/// This lets a signer lift and display the policy it is asked to sign for without knowing the
+/// wallet descriptor. The descriptor is read from the taproot internal key and leaf scripts if
+/// present, otherwise from the witness and redeem scripts, and for single key outputs from the
+/// key in `bip32_derivation` or `partial_sigs` whose hash the `witness_utxo` (or, for
+/// `sh(wpkh)`, the redeem script) commits to. Each key has the origin listed for it in
+/// `bip32_derivation` or `tap_key_origins`, or no origin if there is none.
+///
+/// The tap tree is rebuilt from the control blocks, which only commit to the shape of the tree:
+/// the branches of each node are ordered by hash, as in the merkle root computation. It is an
+/// error for some leaves to be missing: [`TapTree`] has no hidden nodes, so a partially disclosed
+/// tree is rejected rather than rebuilt with made up leaves or without its hidden branches. All
+/// the control blocks must be for the internal key, and the rebuilt tree must have the
+/// `tap_merkle_root` of the input if it has one.
+///
+/// The descriptor is not checked against the `witness_utxo` or `non_witness_utxo` of the input.
+pub fn descriptor_from_psbt_input(
+    input: &psbt::Input,
+) -> Result<Descriptor<DefiniteDescriptorKey>, InputError> {
+    let mut key_map = BTreeMap::new();
+    let keys = input
+        .bip32_derivation
+        .keys()
+        .map(|key| bitcoin::PublicKey::new(*key));
+    for key in keys.chain(input.partial_sigs.keys().copied()) {
+        key_map.insert(key.pubkey_hash().to_raw_hash(), key);
+    }
+    let key_for_hash = |hash: &[u8]| {
+        let hash = hash160::Hash::from_slice(hash).expect("20-byte hash");
+        key_map.get(&hash).copied().ok_or(InputError::MissingPubkey)
+    };
+
+    let desc = if let Some(internal_key) = input.tap_internal_key {
+        let mut leaves = Vec::with_capacity(input.tap_scripts.len());
+        let mut root = None;
+        for (control_block, (script, ver)) in &input.tap_scripts {
+            if *ver != LeafVersion::TapScript || control_block.internal_key != internal_key {
+                return Err(InputError::InvalidTapTree);
+            }
+            let ms =
+                Miniscript::<XOnlyPublicKey, Tap>::parse_with_ext(script, &ExtParams::allow_all())?;
+            // The hashes of the nodes from the leaf up to the root
+            let mut path = vec![TapNodeHash::from(TapLeafHash::from_script(script, *ver))];
+            for branch in control_block.merkle_branch.iter() {
+                let node = TapNodeHash::from_node_hashes(*path.last().expect("non-empty"), *branch);
+                path.push(node);
+            }
+            path.reverse();
+            // All the leaves must be in the same tree
+            if *root.get_or_insert(path[0]) != path[0] {
+                return Err(InputError::InvalidTapTree);
+            }
+            leaves.push((path, ms));
+        }
+        let tree = if leaves.is_empty() {
+            None
+        } else {
+            Some(tap_tree_from_leaves(leaves, 0)?)
+        };
+        let desc = Descriptor::new_tr(internal_key, tree)?;
+        if let (Some(merkle_root), Descriptor::Tr(ref tr)) = (input.tap_merkle_root, &desc) {
+            // A merkle root without leaves is a tree which was not disclosed
+            if tr.spend_info().merkle_root() != Some(merkle_root) {
+                return Err(InputError::InvalidTapTree);
+            }
+        }
+        desc.translate_pk(&mut OriginKeys(input))
+    } else {
+        let desc = match (&input.witness_script, &input.redeem_script) {
+            (Some(witness_script), redeem_script) => {
+                let ms = Miniscript::<bitcoin::PublicKey, Segwitv0>::parse_with_ext(
+                    witness_script,
+                    &ExtParams::allow_all(),
+                )?
+                .substitute_raw_pkh(&key_map);
+                match redeem_script {
+                    None => Descriptor::new_wsh(ms)?,
+                    Some(redeem_script) if *redeem_script == witness_script.to_p2wsh() => {
+                        Descriptor::new_sh_wsh(ms)?
+                    }
+                    Some(redeem_script) => {
+                        return Err(InputError::InvalidWitnessScript {
+                            witness_script: witness_script.clone(),
+                            p2wsh_expected: redeem_script.clone(),
+                        })
+                    }
+                }
+            }
+            (None, Some(redeem_script)) if redeem_script.is_p2wpkh() => {
+                Descriptor::new_sh_wpkh(key_for_hash(&redeem_script.as_bytes()[2..])?)?
+            }
+            (None, Some(redeem_script)) => {
+                let ms = Miniscript::<bitcoin::PublicKey, Legacy>::parse_with_ext(
+                    redeem_script,
+                    &ExtParams::allow_all(),
+                )?;
+                Descriptor::new_sh(ms.substitute_raw_pkh(&key_map))?
+            }
+            (None, None) => {
+                let spk = match input.witness_utxo {
+                    Some(ref utxo) => &utxo.script_pubkey,
+                    None => return Err(InputError::MissingUtxo),
+                };
+                if spk.is_p2wpkh() {
+                    Descriptor::new_wpkh(key_for_hash(&spk.as_bytes()[2..])?)?
+                } else if spk.is_p2pkh() {
+                    Descriptor::new_pkh(key_for_hash(&spk.as_bytes()[3..23])?)?
+                } else if spk.is_p2wsh() || spk.is_p2sh() {
+                    return Err(InputError::MissingWitnessScript);
+                } else {
+                    let ms = Miniscript::<bitcoin::PublicKey, BareCtx>::parse_with_ext(
+                        spk,
+                        &ExtParams::allow_all(),
+                    )?;
+                    Descriptor::new_bare(ms.substitute_raw_pkh(&key_map))?
+                }
+            }
+        };
+        desc.translate_pk(&mut OriginKeys(input))
+    };
+    match desc {
+        Ok(desc) => Ok(desc),
+        Err(TranslateErr::TranslatorErr(e)) => match e {},
+        Err(TranslateErr::OuterError(e)) => Err(InputError::MiniscriptError(e)),
+    }
+}
+
+// Builds the subtree of the leaves under a node at `depth`, given the hashes of the nodes on
+// the path from the root to each leaf
+fn tap_tree_from_leaves(
+    leaves: Vec<(Vec<TapNodeHash>, Miniscript<XOnlyPublicKey, Tap>)>,
+    depth: usize,
+) -> Result<TapTree<XOnlyPublicKey>, InputError> {
+    let mut leaves = leaves;
+    if leaves.len() == 1 && leaves[0].0.len() == depth + 1 {
+        let (_, ms) = leaves.pop().expect("one leaf");
+        return Ok(TapTree::Leaf(Arc::new(ms)));
+    }
+    let mut children = BTreeMap::new();
+    for (path, ms) in leaves {
+        // A leaf at this depth must be alone under its node
+        let child = *path.get(depth + 1).ok_or(InputError::InvalidTapTree)?;
+        children
+            .entry(child)
+            .or_insert_with(Vec::new)
+            .push((path, ms));
+    }
+    // A node with a single known child has a hidden branch
+    if children.len() != 2 {
+        return Err(InputError::InvalidTapTree);
+    }
+    let mut children = children.into_values();
+    let left = tap_tree_from_leaves(children.next().expect("two children"), depth + 1)?;
+    let right = tap_tree_from_leaves(children.next().expect("two children"), depth + 1)?;
+    Ok(TapTree::combine(left, right))
+}
+
+// Gives keys the origin listed for them in a PSBT input
+struct OriginKeys<'a>(&'a psbt::Input);
+
+impl OriginKeys<'_> {
+    fn key(origin: Option<&bip32::KeySource>, key: SinglePubKey) -> DefiniteDescriptorKey {
+        DescriptorPublicKey::Single(SinglePub { origin: origin.cloned(), key })
+            .at_derivation_index(0)
+            .expect("single keys have no wildcard")
+    }
+}
+
+impl Translator<bitcoin::PublicKey> for OriginKeys<'_> {
+    type TargetPk = DefiniteDescriptorKey;
+    type Error = core::convert::Infallible;
+
+    fn pk(&mut self, pk: &bitcoin::PublicKey) -> Result<DefiniteDescriptorKey, Self::Error> {
+        let origin = self.0.bip32_derivation.get(&pk.inner);
+        Ok(Self::key(origin, SinglePubKey::FullKey(*pk)))
+    }
+
+    translate_hash_clone!(bitcoin::PublicKey, DefiniteDescriptorKey, Self::Error);
+}
+
+impl Translator<XOnlyPublicKey> for OriginKeys<'_> {
+    type TargetPk = DefiniteDescriptorKey;
+    type Error = core::convert::Infallible;
+
+    fn pk(&mut self, pk: &XOnlyPublicKey) -> Result<DefiniteDescriptorKey, Self::Error> {
+        let origin = self.0.tap_key_origins.get(pk).map(|(_, origin)| origin);
+        Ok(Self::key(origin, SinglePubKey::XOnly(*pk)))
+    }
+
+    translate_hash_clone!(XOnlyPublicKey, DefiniteDescriptorKey, Self::Error);
 }
 
 /// Extension trait for PSBT outputs
@@ -949,6 +1819,18 @@ pub trait PsbtOutputExt {
         &mut self,
         descriptor: &Descriptor<DefiniteDescriptorKey>,
     ) -> Result<Descriptor<bitcoin::PublicKey>, descriptor::ConversionError>;
+
+    /// Returns the BIP-373 MuSig2 participant keys, indexed by the key they aggregate to.
+    fn musig2_participants(
+        &self,
+    ) -> Result<BTreeMap<secp256k1::PublicKey, Vec<secp256k1::PublicKey>>, musig2::Musig2Error>;
+
+    /// Lists the MuSig2 participant keys aggregating to `aggregate`, in aggregation order.
+    fn set_musig2_participants(
+        &mut self,
+        aggregate: secp256k1::PublicKey,
+        participants: &[secp256k1::PublicKey],
+    );
 }
 
 impl PsbtOutputExt for psbt::Output {
@@ -959,16 +1841,36 @@ impl PsbtOutputExt for psbt::Output {
         let (derived, _) = update_item_with_descriptor_helper(self, descriptor, None)?;
         Ok(derived)
     }
+
+    fn musig2_participants(
+        &self,
+    ) -> Result<BTreeMap<secp256k1::PublicKey, Vec<secp256k1::PublicKey>>, musig2::Musig2Error>
+    {
+        musig2::participants(&self.unknown, musig2::PSBT_OUT_MUSIG2_PARTICIPANT_PUBKEYS)
+    }
+
+    fn set_musig2_participants(
+        &mut self,
+        aggregate: secp256k1::PublicKey,
+        participants: &[secp256k1::PublicKey],
+    ) {
+        musig2::set_participants(
+            &mut self.unknown,
+            musig2::PSBT_OUT_MUSIG2_PARTICIPANT_PUBKEYS,
+            aggregate,
+            participants,
+        )
+    }
 }
 
 // Traverse the pkh lookup while maintaining a reverse map for storing the map
 // hash160 -> (XonlyPublicKey)/PublicKey
-struct KeySourceLookUp(
+struct KeySourceLookUp<'a, C: secp256k1::Verification>(
     pub BTreeMap<secp256k1::PublicKey, bip32::KeySource>,
-    pub secp256k1::Secp256k1<VerifyOnly>,
+    pub &'a secp256k1::Secp256k1<C>,
 );
 
-impl Translator<DefiniteDescriptorKey> for KeySourceLookUp {
+impl<C: secp256k1::Verification> Translator<DefiniteDescriptorKey> for KeySourceLookUp<'_, C> {
     type TargetPk = bitcoin::PublicKey;
     type Error = descriptor::ConversionError;
 
@@ -976,7 +1878,7 @@ impl Translator<DefiniteDescriptorKey> for KeySourceLookUp {
         &mut self,
         xpk: &DefiniteDescriptorKey,
     ) -> Result<bitcoin::PublicKey, descriptor::ConversionError> {
-        let derived = xpk.derive_public_key(&self.1)?;
+        let derived = xpk.derive_public_key(self.1)?;
         self.0.insert(
             derived.to_public_key().inner,
             (
@@ -1077,10 +1979,19 @@ fn update_item_with_descriptor_helper<F: PsbtFields>(
     // One needs the derived descriptor and the other needs to know whether the script_pubkey check
     // failed.
 ) -> Result<(Descriptor<bitcoin::PublicKey>, bool), descriptor::ConversionError> {
-    let secp = secp256k1::Secp256k1::verification_only();
+    with_verify_context(|secp| {
+        update_item_with_descriptor_secp(item, descriptor, check_script, secp)
+    })
+}
 
+fn update_item_with_descriptor_secp<F: PsbtFields, C: secp256k1::Verification>(
+    item: &mut F,
+    descriptor: &Descriptor<DefiniteDescriptorKey>,
+    check_script: Option<&Script>,
+    secp: &Secp256k1<C>,
+) -> Result<(Descriptor<bitcoin::PublicKey>, bool), descriptor::ConversionError> {
     let derived = if let Descriptor::Tr(_) = &descriptor {
-        let derived = descriptor.derived_descriptor(&secp)?;
+        let derived = descriptor.derived_descriptor(secp)?;
 
         if let Some(check_script) = check_script {
             if check_script != &derived.script_pubkey() {
@@ -1090,25 +2001,25 @@ fn update_item_with_descriptor_helper<F: PsbtFields>(
 
         // NOTE: they will both always be Tr
         if let (Descriptor::Tr(tr_derived), Descriptor::Tr(tr_xpk)) = (&derived, descriptor) {
-            let spend_info = tr_derived.spend_info();
+            let spend_info = tr_derived.spend_info_with_secp(secp);
             let ik_derived = spend_info.internal_key();
             let ik_xpk = tr_xpk.internal_key();
             if let Some(merkle_root) = item.tap_merkle_root() {
                 *merkle_root = spend_info.merkle_root();
             }
             *item.tap_internal_key() = Some(ik_derived);
-            item.tap_key_origins().insert(
-                ik_derived,
-                (
-                    vec![],
-                    (
-                        ik_xpk.master_fingerprint(),
-                        ik_xpk
-                            .full_derivation_path()
-                            .ok_or(descriptor::ConversionError::MultiKey)?,
-                    ),
-                ),
+            // Keep any leaf hashes already recorded for the internal key, it may also appear in
+            // the leaves of this or a previously applied descriptor.
+            let ik_origin = (
+                ik_xpk.master_fingerprint(),
+                ik_xpk
+                    .full_derivation_path()
+                    .ok_or(descriptor::ConversionError::MultiKey)?,
             );
+            item.tap_key_origins()
+                .entry(ik_derived)
+                .and_modify(|(_, origin)| *origin = ik_origin.clone())
+                .or_insert_with(|| (vec![], ik_origin));
 
             let mut builder = taproot::TaprootBuilder::new();
 
@@ -1173,7 +2084,7 @@ fn update_item_with_descriptor_helper<F: PsbtFields>(
 
         derived
     } else {
-        let mut bip32_derivation = KeySourceLookUp(BTreeMap::new(), Secp256k1::verification_only());
+        let mut bip32_derivation = KeySourceLookUp(BTreeMap::new(), secp);
         let derived = descriptor
             .translate_pk(&mut bip32_derivation)
             .map_err(|e| e.expect_translator_err("No Outer Context errors in translations"))?;
@@ -1208,6 +2119,28 @@ fn update_item_with_descriptor_helper<F: PsbtFields>(
     Ok((derived, true))
 }
 
+/// How an input was finalized by [`PsbtExt::finalize_mall_fallback_mut`]
+#[derive(Debug)]
+pub enum InputFinalization {
+    /// The input was finalized with a non-malleable satisfaction
+    NonMalleable,
+    /// The input was finalized with a malleable satisfaction, since the non-malleable
+    /// finalization failed with the given error
+    Malleable(Error),
+    /// The input could not be finalized, and was left untouched
+    Failed {
+        /// The error of the non-malleable finalization
+        non_malleable: Error,
+        /// The error of the malleable finalization
+        malleable: Error,
+    },
+}
+
+impl InputFinalization {
+    /// Whether the input was finalized.
+    pub fn is_finalized(&self) -> bool { !matches!(self, InputFinalization::Failed { .. }) }
+}
+
 /// Return error type for [`PsbtExt::update_input_with_descriptor`]
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 pub enum UtxoUpdateError {
@@ -1247,7 +2180,7 @@ impl fmt::Display for UtxoUpdateError {
 
 #[cfg(feature = "std")]
 impl error::Error for UtxoUpdateError {
-    fn cause(&self) -> Option<&dyn error::Error> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         use self::UtxoUpdateError::*;
 
         match self {
@@ -1289,7 +2222,7 @@ impl fmt::Display for OutputUpdateError {
 
 #[cfg(feature = "std")]
 impl error::Error for OutputUpdateError {
-    fn cause(&self) -> Option<&dyn error::Error> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         use self::OutputUpdateError::*;
 
         match self {
@@ -1299,6 +2232,49 @@ impl error::Error for OutputUpdateError {
     }
 }
 
+/// Return error type for [`PsbtExt::estimate_weight`] and [`PsbtExt::estimate_fee_rate`]
+#[derive(Debug)]
+pub enum EstimateError {
+    /// The utxo spent by the input at the index is missing or invalid
+    InputError(InputError, usize),
+    /// No descriptor matches the utxo spent by the input at the index
+    MissingDescriptor(usize),
+    /// The descriptor of the input at the index can't be satisfied
+    Unsatisfiable(usize),
+    /// The input or output values overflow
+    ValueOverflow,
+    /// The outputs spend more than the inputs
+    NegativeFee,
+}
+
+impl fmt::Display for EstimateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EstimateError::InputError(e, idx) => write!(f, "{} at index {}", e, idx),
+            EstimateError::MissingDescriptor(idx) => {
+                write!(f, "No descriptor for the utxo of input {}", idx)
+            }
+            EstimateError::Unsatisfiable(idx) => {
+                write!(f, "The descriptor of input {} can't be satisfied", idx)
+            }
+            EstimateError::ValueOverflow => write!(f, "Input or output values overflow"),
+            EstimateError::NegativeFee => write!(f, "The outputs spend more than the inputs"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for EstimateError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        use self::EstimateError::*;
+
+        match self {
+            InputError(e, _) => Some(e),
+            MissingDescriptor(_) | Unsatisfiable(_) | ValueOverflow | NegativeFee => None,
+        }
+    }
+}
+
 /// Return error type for [`PsbtExt::sighash_msg`]
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SighashError {
@@ -1320,6 +2296,14 @@ pub enum SighashError {
     MissingWitnessScript,
     /// Missing Redeem script,
     MissingRedeemScript,
+    /// The descriptor keys could not be derived.
+    Conversion(descriptor::ConversionError),
+    /// The descriptor does not match the script pubkey of the input utxo.
+    DescriptorMismatch,
+    /// The taproot leaf hash is not one of the descriptor's leaves.
+    UnknownTapLeaf(TapLeafHash),
+    /// An annex was given for a non-taproot input.
+    UnexpectedAnnex,
 }
 
 impl fmt::Display for SighashError {
@@ -1336,13 +2320,21 @@ impl fmt::Display for SighashError {
             SighashError::SighashTaproot(ref e) => write!(f, "sighash taproot: {}", e),
             SighashError::SighashP2wpkh(ref e) => write!(f, "sighash p2wpkh: {}", e),
             SighashError::TransactionInputsIndex(ref e) => write!(f, "tx inputs index: {}", e),
+            SighashError::Conversion(ref e) => write!(f, "descriptor conversion: {}", e),
+            SighashError::DescriptorMismatch => {
+                write!(f, "Descriptor does not match the input utxo")
+            }
+            SighashError::UnknownTapLeaf(ref hash) => {
+                write!(f, "Tap leaf {} not in the descriptor", hash)
+            }
+            SighashError::UnexpectedAnnex => write!(f, "Annex given for a non-taproot input"),
         }
     }
 }
 
 #[cfg(feature = "std")]
 impl error::Error for SighashError {
-    fn cause(&self) -> Option<&dyn error::Error> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         use self::SighashError::*;
 
         match self {
@@ -1351,7 +2343,11 @@ impl error::Error for SighashError {
             | MissingSpendUtxos
             | InvalidSighashType
             | MissingWitnessScript
-            | MissingRedeemScript => None,
+            | MissingRedeemScript
+            | DescriptorMismatch
+            | UnknownTapLeaf(_)
+            | UnexpectedAnnex => None,
+            Conversion(ref e) => Some(e),
             SighashTaproot(ref e) => Some(e),
             SighashP2wpkh(ref e) => Some(e),
             TransactionInputsIndex(ref e) => Some(e),
@@ -1397,6 +2393,71 @@ impl PsbtSighashMsg {
     }
 }
 
+/// An inconsistency between a psbt input and a descriptor, as returned by
+/// [`PsbtExt::check_input_descriptor`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum InputMismatch {
+    /// The input has neither a `witness_utxo` nor a `non_witness_utxo`.
+    MissingUtxo,
+    /// The input spends a legacy output but has no `non_witness_utxo`, which is the only
+    /// commitment to the amount spent.
+    MissingNonWitnessUtxo,
+    /// The `non_witness_utxo` is not the transaction whose output the input spends.
+    NonWitnessUtxo,
+    /// The `witness_utxo` is not the output the input spends in the `non_witness_utxo`.
+    WitnessUtxo,
+    /// The output spent does not pay to the descriptor.
+    ScriptPubkey {
+        /// The script pubkey of the descriptor
+        expected: ScriptBuf,
+        /// The script pubkey of the output spent
+        found: ScriptBuf,
+    },
+    /// A field of the input holds a value the descriptor does not produce.
+    Field(InputField),
+}
+
+impl fmt::Display for InputMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputMismatch::MissingUtxo => f.write_str("missing utxo"),
+            InputMismatch::MissingNonWitnessUtxo => f.write_str("missing non-witness utxo"),
+            InputMismatch::NonWitnessUtxo => {
+                f.write_str("non-witness utxo is not the transaction spent")
+            }
+            InputMismatch::WitnessUtxo => {
+                f.write_str("witness utxo does not match the non-witness utxo")
+            }
+            InputMismatch::ScriptPubkey { expected, found } => {
+                write!(f, "script pubkey {} is not the descriptor's {}", found, expected)
+            }
+            InputMismatch::Field(field) => write!(f, "{} does not match the descriptor", field),
+        }
+    }
+}
+
+/// The descriptor controlling a psbt input, as returned by [`PsbtExt::match_inputs`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct InputMatch {
+    /// The position of the matching descriptor in the descriptors searched.
+    pub descriptor_index: usize,
+    /// The derivation index of the descriptor, or `None` if it has no wildcards.
+    pub derivation_index: Option<u32>,
+    /// The descriptor at the derivation index, whose script pubkey is the one spent by the
+    /// input. For multipath descriptors this is on the path that matched.
+    pub descriptor: Descriptor<DefiniteDescriptorKey>,
+}
+
+/// Sighash message of a psbt input along with the sighash type it commits to, as returned by
+/// [`PsbtExt::sighash_msg_for_descriptor`].
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct InputSighash {
+    /// The sighash message.
+    pub msg: PsbtSighashMsg,
+    /// The sighash type committed to by `msg`.
+    pub sighash_type: psbt::PsbtSighashType,
+}
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
@@ -1521,6 +2582,530 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_update_input_tr_bip371_fields() {
+        let root_xpub = Xpub::from_str("xpub661MyMwAqRbcFkPHucMnrGNzDwb6teAX1RbKQmqtEF8kK3Z7LZ59qafCjB9eCRLiTVG3uxBxgKvRgbubRhqSKXnGGb1aoaqLrpMBDrVxga8").unwrap();
+        let fingerprint = root_xpub.fingerprint();
+        let xpub = format!("[{}/86'/0'/0']xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ", fingerprint);
+        // The internal key is reused in the second leaf.
+        let desc =
+            format!("tr({}/0/0,{{pk({}/0/1),and_v(v:pk({}/0/0),older(144))}})", xpub, xpub, xpub);
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&desc).unwrap();
+
+        let mut psbt_input = psbt::Input::default();
+        let derived = psbt_input.update_with_descriptor_unchecked(&desc).unwrap();
+        let tr = match derived {
+            Descriptor::Tr(ref tr) => tr.clone(),
+            _ => unreachable!(),
+        };
+        let spend_info = tr.spend_info();
+        let secp = Secp256k1::verification_only();
+
+        assert_eq!(psbt_input.tap_internal_key, Some(spend_info.internal_key()));
+        assert_eq!(psbt_input.tap_merkle_root, spend_info.merkle_root());
+        assert_eq!(psbt_input.tap_scripts.len(), 2);
+        for (control_block, (script, ver)) in &psbt_input.tap_scripts {
+            assert!(control_block.verify_taproot_commitment(
+                &secp,
+                spend_info.output_key().to_x_only_public_key(),
+                script
+            ));
+            assert_eq!(*ver, LeafVersion::TapScript);
+        }
+
+        let leaf_hashes: Vec<_> = tr
+            .iter_scripts()
+            .map(|(_, ms)| TapLeafHash::from_script(&ms.encode(), LeafVersion::TapScript))
+            .collect();
+        let (ik_leaves, (ik_fingerprint, ik_path)) = psbt_input
+            .tap_key_origins
+            .get(&spend_info.internal_key())
+            .unwrap();
+        assert_eq!(ik_leaves, &vec![leaf_hashes[1]]);
+        assert_eq!(*ik_fingerprint, fingerprint);
+        assert_eq!(ik_path.to_string(), "86'/0'/0'/0/0");
+        assert_eq!(psbt_input.tap_key_origins.len(), 2);
+
+        // Updating again does not drop or duplicate any leaf hashes.
+        let before = psbt_input.clone();
+        psbt_input.update_with_descriptor_unchecked(&desc).unwrap();
+        assert_eq!(psbt_input, before);
+    }
+
+    #[test]
+    fn test_sighash_msg_for_descriptor() {
+        let secp = Secp256k1::new();
+        let keys: Vec<_> = (1..=2)
+            .map(|i| {
+                let sk = secp256k1::SecretKey::from_slice(&[i; 32]).unwrap();
+                PublicKey::from_secret_key(&secp, &sk)
+            })
+            .collect();
+        let descs = [
+            format!("sh(wsh(multi(1,{},{})))", keys[0], keys[1]),
+            format!("pkh({})", keys[0]),
+            format!("tr({},pk({}))", keys[0], keys[1]),
+        ];
+        let descs: Vec<_> = descs
+            .iter()
+            .map(|desc| Descriptor::<DefiniteDescriptorKey>::from_str(desc).unwrap())
+            .collect();
+
+        let tx = bitcoin::Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn::default(); 3],
+            output: vec![TxOut {
+                value: Amount::from_sat(25_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        for (input, desc) in psbt.inputs.iter_mut().zip(&descs) {
+            input.witness_utxo = Some(TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: desc.script_pubkey(),
+            });
+        }
+
+        // The messages only depend on the descriptor, not on the other input fields
+        let mut cache = SighashCache::new(&psbt.unsigned_tx);
+        let leaf_hash = match descs[2].derived_descriptor(&secp).unwrap() {
+            Descriptor::Tr(tr) => {
+                let (_, ms) = tr.iter_scripts().next().unwrap();
+                TapLeafHash::from_script(&ms.encode(), LeafVersion::TapScript)
+            }
+            _ => unreachable!(),
+        };
+        let mut msgs = vec![];
+        for (idx, desc) in descs.iter().enumerate() {
+            msgs.push(
+                psbt.sighash_msg_for_descriptor(idx, &mut cache, desc, None, None)
+                    .unwrap(),
+            );
+        }
+        let leaf_msg = psbt
+            .sighash_msg_for_descriptor(2, &mut cache, &descs[2], Some(leaf_hash), None)
+            .unwrap();
+        assert_eq!(msgs[0].sighash_type, sighash::EcdsaSighashType::All.into());
+        assert_eq!(msgs[2].sighash_type, sighash::TapSighashType::Default.into());
+        assert!(matches!(msgs[0].msg, PsbtSighashMsg::SegwitV0Sighash(_)));
+        assert!(matches!(msgs[1].msg, PsbtSighashMsg::LegacySighash(_)));
+
+        let mut updated = psbt.clone();
+        for (idx, desc) in descs.iter().enumerate() {
+            updated.inputs[idx]
+                .update_with_descriptor_unchecked(desc)
+                .unwrap();
+            assert_eq!(updated.sighash_msg(idx, &mut cache, None).unwrap(), msgs[idx].msg);
+        }
+        assert_eq!(updated.sighash_msg(2, &mut cache, Some(leaf_hash)).unwrap(), leaf_msg.msg);
+
+        let annex = sighash::Annex::new(&[0x50, 0x01]).unwrap();
+        let annex_msg = psbt
+            .sighash_msg_for_descriptor(2, &mut cache, &descs[2], None, Some(annex.clone()))
+            .unwrap();
+        assert_ne!(annex_msg.msg, msgs[2].msg);
+
+        assert_eq!(
+            psbt.sighash_msg_for_descriptor(0, &mut cache, &descs[0], Some(leaf_hash), None),
+            Err(SighashError::UnknownTapLeaf(leaf_hash))
+        );
+        assert_eq!(
+            psbt.sighash_msg_for_descriptor(0, &mut cache, &descs[0], None, Some(annex)),
+            Err(SighashError::UnexpectedAnnex)
+        );
+        assert_eq!(
+            psbt.sighash_msg_for_descriptor(2, &mut cache, &descs[0], None, None),
+            Err(SighashError::DescriptorMismatch)
+        );
+    }
+
+    #[test]
+    fn test_estimate_weight() {
+        let secp = Secp256k1::new();
+        let tprv = "tprv8ZgxMBicQKsPcwcD4gSnMti126ZiETsuX7qwrtMypr6FBwAP65puFn4v6c3jrN9VwtMRMph6nyT63NrfUL4C3nBzPcduzVSuHD7zbX2JKVc";
+        let mut keys = descriptor::KeyMap::new();
+        let mut descs = vec![];
+        for desc in [
+            "pkh({}/44'/1'/0'/0/0)",
+            "wpkh({}/84'/1'/0'/0/0)",
+            "tr({}/86'/1'/0'/0/0)",
+        ] {
+            let desc = desc.replace("{}", tprv);
+            let (desc, key_map) = Descriptor::parse_descriptor(&secp, &desc).unwrap();
+            keys.extend(key_map);
+            descs.push(desc.at_derivation_index(0).unwrap());
+        }
+
+        let tx = bitcoin::Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn::default(); 3],
+            output: vec![TxOut {
+                value: Amount::from_sat(25_000),
+                script_pubkey: descs[1].script_pubkey(),
+            }],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        for (idx, desc) in descs.iter().enumerate() {
+            psbt.inputs[idx].witness_utxo = Some(TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: desc.script_pubkey(),
+            });
+            psbt.inputs[idx]
+                .update_with_descriptor_unchecked(desc)
+                .unwrap();
+        }
+
+        assert!(matches!(
+            psbt.estimate_weight(&descs[1..]),
+            Err(EstimateError::MissingDescriptor(0))
+        ));
+        let estimate = psbt.estimate_weight(&descs).unwrap();
+        let fee_rate = psbt.estimate_fee_rate(&descs).unwrap();
+        assert_eq!(fee_rate, FeeRate::from_sat_per_kwu(5_000 * 1000 / estimate.to_wu()));
+
+        // Once an input is final, its actual weight is used
+        sign::sign(&mut psbt, &keys, &secp).unwrap();
+        psbt.finalize_inp_mut(&secp, 1).unwrap();
+        let partial = psbt.estimate_weight(&descs).unwrap();
+        assert!(partial <= estimate);
+        psbt.finalize_inp_mut(&secp, 0).unwrap();
+        psbt.finalize_inp_mut(&secp, 2).unwrap();
+        let actual = psbt.estimate_weight(&[]).unwrap();
+        assert!(actual <= partial);
+        // ECDSA signatures are estimated to be at most 2 bytes larger
+        assert!(estimate - actual <= Weight::from_wu(4 * 2 + 2));
+        assert_eq!(actual, psbt.extract(&secp).unwrap().weight());
+    }
+
+    #[test]
+    fn test_match_inputs() {
+        let xpub = "[d34db33f/86'/0'/0']xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ";
+        let descriptors: Vec<Descriptor<DescriptorPublicKey>> = [
+            format!("wpkh({}/0/*)", xpub),
+            format!("tr({}/<0;1>/*)", xpub),
+            "pkh(02e6642fd69bd211f93f7f1f36ca51a26a5290eb2dd1b0d8279a87bb0d480c8443)".to_owned(),
+        ]
+        .iter()
+        .map(|desc| Descriptor::from_str(desc).unwrap())
+        .collect();
+        let change = descriptors[1].clone().into_single_descriptors().unwrap()[1].clone();
+        let spent = [
+            descriptors[0].at_derivation_index(5).unwrap(),
+            change.at_derivation_index(2).unwrap(),
+            descriptors[2].at_derivation_index(0).unwrap(),
+            descriptors[0].at_derivation_index(6).unwrap(),
+        ];
+
+        let tx = bitcoin::Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn::default(); 5],
+            output: vec![],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        for (idx, desc) in spent.iter().enumerate() {
+            psbt.inputs[idx].witness_utxo = Some(TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: desc.script_pubkey(),
+            });
+        }
+        // The last spent input has no key origins, and the last input no utxo
+        for (input, desc) in psbt.inputs.iter_mut().zip(&spent[..3]) {
+            input.update_with_descriptor_unchecked(desc).unwrap();
+        }
+
+        let matches = psbt.match_inputs(&descriptors);
+        assert_eq!(matches.len(), 5);
+        let expected = [(0, Some(5)), (1, Some(2)), (2, None)];
+        for (idx, (descriptor_index, derivation_index)) in expected.iter().enumerate() {
+            assert_eq!(
+                matches[idx],
+                Some(InputMatch {
+                    descriptor_index: *descriptor_index,
+                    derivation_index: *derivation_index,
+                    descriptor: spent[idx].clone(),
+                })
+            );
+        }
+        assert_eq!(matches[3], None);
+        assert_eq!(matches[4], None);
+    }
+
+    #[test]
+    fn test_descriptor_from_psbt_input() {
+        use crate::ForEachKey;
+
+        let xpub = "[d34db33f/86'/0'/0']xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ";
+        let descriptors = [
+            format!("wsh(multi(2,{}/0/*,{}/1/*))", xpub, xpub),
+            format!("sh(wpkh({}/0/*))", xpub),
+            format!("pkh({}/0/*)", xpub),
+            format!(
+                "tr({}/0/*,{{pk({}/1/*),{{pk({}/2/*),and_v(v:pk({}/3/*),older(10))}}}})",
+                xpub, xpub, xpub, xpub
+            ),
+        ];
+        let origins = |desc: &Descriptor<DefiniteDescriptorKey>| {
+            let mut origins = vec![];
+            desc.for_each_key(|pk| {
+                origins.push((pk.master_fingerprint(), pk.full_derivation_path().unwrap()));
+                true
+            });
+            origins.sort();
+            origins
+        };
+
+        for desc in &descriptors {
+            let desc = Descriptor::<DescriptorPublicKey>::from_str(desc)
+                .unwrap()
+                .at_derivation_index(3)
+                .unwrap();
+            let mut input = psbt::Input {
+                witness_utxo: Some(TxOut {
+                    value: Amount::from_sat(10_000),
+                    script_pubkey: desc.script_pubkey(),
+                }),
+                ..Default::default()
+            };
+            input.update_with_descriptor_unchecked(&desc).unwrap();
+
+            let inferred = descriptor_from_psbt_input(&input).unwrap();
+            assert_eq!(inferred.script_pubkey(), desc.script_pubkey());
+            assert_eq!(origins(&inferred), origins(&desc));
+
+            if let Descriptor::Tr(ref tr) = desc {
+                // The output tree has the leaves of the descriptor at their depths, nothing more
+                let mut output = psbt::Output::default();
+                output.update_with_descriptor_unchecked(&desc).unwrap();
+                let mut leaves: Vec<_> = output
+                    .tap_tree
+                    .unwrap()
+                    .script_leaves()
+                    .map(|leaf| (leaf.merkle_branch().len() as u8, leaf.script().to_owned()))
+                    .collect();
+                let mut expected: Vec<_> = tr
+                    .iter_scripts()
+                    .map(|(depth, ms)| (depth, ms.encode()))
+                    .collect();
+                leaves.sort();
+                expected.sort();
+                assert_eq!(leaves, expected);
+            }
+
+            if input.tap_scripts.len() > 1 {
+                let invalid = |input: &psbt::Input| {
+                    matches!(descriptor_from_psbt_input(input), Err(InputError::InvalidTapTree))
+                };
+
+                // The merkle root must be the one of the tree
+                let mut wrong_root = input.clone();
+                wrong_root.tap_merkle_root = Some(TapNodeHash::from_byte_array([1; 32]));
+                assert!(invalid(&wrong_root));
+
+                // The control blocks must be for the internal key
+                let mut wrong_key = input.clone();
+                let mut control_block = input.tap_scripts.keys().next().unwrap().clone();
+                let leaf = wrong_key.tap_scripts.remove(&control_block).unwrap();
+                control_block.internal_key = *input
+                    .tap_key_origins
+                    .keys()
+                    .find(|key| Some(**key) != input.tap_internal_key)
+                    .unwrap();
+                wrong_key.tap_scripts.insert(control_block, leaf);
+                assert!(invalid(&wrong_key));
+
+                // Without one of its leaves, the tree can't be rebuilt
+                let control_block = input.tap_scripts.keys().next().unwrap().clone();
+                input.tap_scripts.remove(&control_block);
+                assert!(invalid(&input));
+
+                // Nor without any of them, if there is a merkle root
+                input.tap_scripts.clear();
+                assert!(input.tap_merkle_root.is_some());
+                assert!(invalid(&input));
+            }
+        }
+
+        // A single key output can't be inferred without the key
+        let input = psbt::Input {
+            witness_utxo: Some(TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: Descriptor::<DefiniteDescriptorKey>::from_str(
+                    "wpkh(02e6642fd69bd211f93f7f1f36ca51a26a5290eb2dd1b0d8279a87bb0d480c8443)",
+                )
+                .unwrap()
+                .script_pubkey(),
+            }),
+            ..Default::default()
+        };
+        assert!(matches!(descriptor_from_psbt_input(&input), Err(InputError::MissingPubkey)));
+    }
+
+    #[test]
+    fn test_check_input_descriptor() {
+        let xpub = "[d34db33f/86'/0'/0']xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ";
+        let wsh = Descriptor::<DescriptorPublicKey>::from_str(&format!(
+            "wsh(multi(2,{}/0/*,{}/1/*))",
+            xpub, xpub
+        ))
+        .unwrap();
+        let pkh =
+            Descriptor::<DescriptorPublicKey>::from_str(&format!("pkh({}/2/*)", xpub)).unwrap();
+        let prev_tx = bitcoin::Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: pkh.at_derivation_index(0).unwrap().script_pubkey(),
+            }],
+        };
+        let tx = bitcoin::Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![
+                TxIn::default(),
+                TxIn {
+                    previous_output: OutPoint { txid: prev_tx.compute_txid(), vout: 0 },
+                    ..Default::default()
+                },
+            ],
+            output: vec![],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        let wsh_3 = wsh.at_derivation_index(3).unwrap();
+        psbt.inputs[0].witness_utxo =
+            Some(TxOut { value: Amount::from_sat(10_000), script_pubkey: wsh_3.script_pubkey() });
+        psbt.inputs[0]
+            .update_with_descriptor_unchecked(&wsh_3)
+            .unwrap();
+        psbt.inputs[1].witness_utxo = Some(prev_tx.output[0].clone());
+        psbt.inputs[1]
+            .update_with_descriptor_unchecked(&pkh.at_derivation_index(0).unwrap())
+            .unwrap();
+
+        assert_eq!(psbt.check_input_descriptor(0, &wsh, 3).unwrap(), vec![]);
+        // At another index, neither the utxo nor the fields match
+        let mismatches = psbt.check_input_descriptor(0, &wsh, 4).unwrap();
+        assert_eq!(
+            mismatches[0],
+            InputMismatch::ScriptPubkey {
+                expected: wsh.at_derivation_index(4).unwrap().script_pubkey(),
+                found: wsh_3.script_pubkey(),
+            }
+        );
+        assert!(mismatches.contains(&InputMismatch::Field(InputField::WitnessScript)));
+        assert_eq!(
+            mismatches
+                .iter()
+                .filter(|m| matches!(m, InputMismatch::Field(InputField::Bip32Derivation(_))))
+                .count(),
+            2
+        );
+
+        // A signature by a key foreign to the descriptor
+        let secp = Secp256k1::new();
+        let sk = secp256k1::SecretKey::from_slice(&[1; 32]).unwrap();
+        let foreign = bitcoin::PublicKey::new(sk.public_key(&secp));
+        let sig = bitcoin::ecdsa::Signature::sighash_all(
+            secp.sign_ecdsa(&secp256k1::Message::from_digest([1; 32]), &sk),
+        );
+        let mut tampered = psbt.clone();
+        tampered.inputs[0].partial_sigs.insert(foreign, sig);
+        assert_eq!(
+            tampered.check_input_descriptor(0, &wsh, 3).unwrap(),
+            vec![InputMismatch::Field(InputField::PartialSig(foreign))]
+        );
+
+        // Legacy inputs must commit to the amount spent through the previous transaction
+        assert_eq!(
+            psbt.check_input_descriptor(1, &pkh, 0).unwrap(),
+            vec![InputMismatch::MissingNonWitnessUtxo]
+        );
+        psbt.inputs[1].non_witness_utxo = Some(prev_tx.clone());
+        assert_eq!(psbt.check_input_descriptor(1, &pkh, 0).unwrap(), vec![]);
+        let mut other_tx = prev_tx;
+        other_tx.output[0].value = Amount::from_sat(20_000);
+        psbt.inputs[1].non_witness_utxo = Some(other_tx);
+        assert_eq!(
+            psbt.check_input_descriptor(1, &pkh, 0).unwrap(),
+            vec![InputMismatch::NonWitnessUtxo]
+        );
+
+        assert!(matches!(
+            psbt.check_input_descriptor(2, &pkh, 0),
+            Err(UtxoUpdateError::IndexOutOfBounds(2, 2))
+        ));
+    }
+
+    #[test]
+    fn test_finalize_mall_fallback() {
+        use bitcoin::hashes::sha256;
+
+        let secp = Secp256k1::new();
+        let sk = secp256k1::SecretKey::from_slice(&[1; 32]).unwrap();
+        let pk = bitcoin::PublicKey::new(PublicKey::from_secret_key(&secp, &sk));
+        let preimage = [2; 32];
+        let hash = sha256::Hash::hash(&preimage);
+        // Taking the timelock branch requires a malleable dissatisfaction of the hash
+        let ms = Miniscript::<bitcoin::PublicKey, Segwitv0>::from_str_ext(
+            &format!("and_v(v:pk({}),or_d(sha256({}),older(10)))", pk, hash),
+            &ExtParams::allow_all(),
+        )
+        .unwrap();
+        let witness_script = ms.encode();
+
+        let mut tx = bitcoin::Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![
+                TxIn { sequence: bitcoin::Sequence::from_height(10), ..TxIn::default() };
+                3
+            ],
+            output: vec![],
+        };
+        // Only the first input can use the hash branch, and not the timelock one
+        tx.input[0].sequence = bitcoin::Sequence::MAX;
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        for input in &mut psbt.inputs {
+            input.witness_utxo = Some(TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: witness_script.to_p2wsh(),
+            });
+            input.witness_script = Some(witness_script.clone());
+        }
+        let mut cache = SighashCache::new(psbt.unsigned_tx.clone());
+        for idx in 0..2 {
+            let msg = psbt
+                .sighash_msg(idx, &mut cache, None)
+                .unwrap()
+                .to_secp_msg();
+            let sig = bitcoin::ecdsa::Signature::sighash_all(secp.sign_ecdsa(&msg, &sk));
+            psbt.inputs[idx].partial_sigs.insert(pk, sig);
+        }
+        psbt.inputs[0]
+            .sha256_preimages
+            .insert(hash, preimage.to_vec());
+
+        let mut finalized = psbt.clone();
+        let outcome = finalized.finalize_mall_fallback_mut(&secp);
+        assert!(matches!(outcome[0], InputFinalization::NonMalleable));
+        assert!(matches!(
+            outcome[1],
+            InputFinalization::Malleable(Error::InputError(InputError::MiniscriptError(_), 1))
+        ));
+        assert!(matches!(outcome[2], InputFinalization::Failed { .. }));
+        assert!(!outcome[2].is_finalized());
+        assert!(finalized.inputs[1].final_script_witness.is_some());
+        assert_eq!(finalized.inputs[2], psbt.inputs[2]);
+
+        assert!(psbt.clone().finalize_inp_mut(&secp, 1).is_err());
+        assert!(psbt.finalize_inp_mall_mut(&secp, 1).is_ok());
+    }
+
     #[test]
     fn test_update_item_non_tr_multi() {
         // values taken from https://github.com/bitcoin/bips/blob/master/bip-0084.mediawiki (after removing zpub thingy)
@@ -1688,4 +3273,73 @@ mod tests {
             "output script_pubkey no longer matches"
         );
     }
+
+    #[test]
+    fn test_update_change_outputs() {
+        let desc = "tr([73c5da0a/86'/0'/0']xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ/1/*,pk([73c5da0a/86'/0'/0']xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ/2/*))";
+        let desc = Descriptor::<DescriptorPublicKey>::from_str(desc).unwrap();
+        let change = desc.at_derivation_index(3).unwrap();
+
+        let txout = |script_pubkey| TxOut { value: Amount::from_sat(1_000), script_pubkey };
+        let tx = bitcoin::Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![
+                txout(ScriptBuf::new_op_return([])),
+                txout(change.script_pubkey()),
+            ],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+
+        assert_eq!(psbt.update_change_outputs(&desc, 0..3), Ok(vec![]));
+        assert_eq!(psbt.outputs[1], psbt::Output::default());
+
+        assert_eq!(psbt.update_change_outputs(&desc, 0..10), Ok(vec![1]));
+        let mut expected = psbt::Output::default();
+        expected.update_with_descriptor_unchecked(&change).unwrap();
+        assert_eq!(psbt.outputs[1], expected);
+        assert!(psbt.outputs[1].tap_tree.is_some());
+        assert_eq!(psbt.outputs[1].tap_key_origins.len(), 2);
+        assert_eq!(psbt.outputs[0], psbt::Output::default());
+
+        let multipath = Descriptor::<DescriptorPublicKey>::from_str(
+            "wpkh(xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ/<0;1>/*)",
+        )
+        .unwrap();
+        assert!(matches!(
+            psbt.update_change_outputs(&multipath, 0..1),
+            Err(OutputUpdateError::DerivationError(_))
+        ));
+    }
+
+    #[test]
+    fn lift_psbt_input() {
+        let keys = [
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+            "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5",
+            "02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9",
+        ];
+        for desc in [
+            "wsh(or_d(pk(A),and_v(v:pk(B),older(144))))",
+            "sh(wsh(multi(2,A,B,C)))",
+            "sh(wpkh(A))",
+            "sh(or_d(pk(A),pkh(B)))",
+            "tr(A,{pk(B),and_v(v:pk(C),after(10))})",
+        ] {
+            let desc = desc
+                .replace('A', keys[0])
+                .replace('B', keys[1])
+                .replace('C', keys[2]);
+            let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&desc).unwrap();
+            let mut psbt_input = psbt::Input::default();
+            let derived = psbt_input.update_with_descriptor_unchecked(&desc).unwrap();
+            assert_eq!(psbt_input.lift().unwrap(), derived.lift().unwrap().normalized());
+        }
+
+        assert!(matches!(
+            psbt::Input::default().lift(),
+            Err(crate::Error::LiftError(LiftError::MissingScript))
+        ));
+    }
 }
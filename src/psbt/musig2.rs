@@ -0,0 +1,562 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # MuSig2 PSBT fields
+//!
+//! Support for the MuSig2 fields defined in BIP 373, used to produce a taproot
+//! key spend signature with an internal key aggregated from several participant
+//! keys, as described in BIP 327.
+//!
+//! The fields are read and written through the [`super::PsbtInputExt`] and
+//! [`super::PsbtOutputExt`] traits. Once every participant has added its public
+//! nonce and partial signature, the finalizer aggregates the partial signatures
+//! into the key spend signature of the input.
+//!
+
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error;
+
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::psbt::{raw, Psbt};
+use bitcoin::secp256k1::{self, constants, schnorr, PublicKey, Scalar, Secp256k1, SecretKey};
+use bitcoin::sighash::{self, SighashCache};
+use bitcoin::taproot::{self, TapLeafHash, TapTweakHash};
+
+use super::{PsbtExt, PsbtSighashMsg, SighashError};
+use crate::prelude::*;
+
+/// Type of the input field listing the participant keys of an aggregate key.
+pub const PSBT_IN_MUSIG2_PARTICIPANT_PUBKEYS: u8 = 0x1a;
+/// Type of the input field holding the public nonce of a participant.
+pub const PSBT_IN_MUSIG2_PUB_NONCE: u8 = 0x1b;
+/// Type of the input field holding the partial signature of a participant.
+pub const PSBT_IN_MUSIG2_PARTIAL_SIG: u8 = 0x1c;
+/// Type of the output field listing the participant keys of an aggregate key.
+pub const PSBT_OUT_MUSIG2_PARTICIPANT_PUBKEYS: u8 = 0x08;
+
+/// The key of the public nonce and partial signature fields: the participant
+/// signing, the aggregate key it signs for and, for a script spend, the leaf.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Musig2Key {
+    /// The key of the participant.
+    pub participant: PublicKey,
+    /// The aggregate key the participant contributes to.
+    pub aggregate: PublicKey,
+    /// The hash of the leaf being signed, `None` for a key spend.
+    pub leaf_hash: Option<TapLeafHash>,
+}
+
+impl Musig2Key {
+    fn from_slice(bytes: &[u8]) -> Result<Self, Musig2Error> {
+        let leaf_hash = match bytes.len() {
+            66 => None,
+            98 => Some(TapLeafHash::from_slice(&bytes[66..]).expect("32 bytes")),
+            _ => return Err(Musig2Error::InvalidLength),
+        };
+        Ok(Musig2Key {
+            participant: PublicKey::from_slice(&bytes[..33]).map_err(Musig2Error::Secp)?,
+            aggregate: PublicKey::from_slice(&bytes[33..66]).map_err(Musig2Error::Secp)?,
+            leaf_hash,
+        })
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(98);
+        bytes.extend_from_slice(&self.participant.serialize());
+        bytes.extend_from_slice(&self.aggregate.serialize());
+        if let Some(leaf_hash) = self.leaf_hash {
+            bytes.extend_from_slice(leaf_hash.as_ref());
+        }
+        bytes
+    }
+}
+
+/// The public nonce of a MuSig2 participant, made of two points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PubNonce {
+    /// The first nonce point.
+    pub r1: PublicKey,
+    /// The second nonce point.
+    pub r2: PublicKey,
+}
+
+impl PubNonce {
+    /// Parses a 66-byte public nonce.
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, Musig2Error> {
+        if bytes.len() != 66 {
+            return Err(Musig2Error::InvalidLength);
+        }
+        Ok(PubNonce {
+            r1: PublicKey::from_slice(&bytes[..33]).map_err(Musig2Error::Secp)?,
+            r2: PublicKey::from_slice(&bytes[33..]).map_err(Musig2Error::Secp)?,
+        })
+    }
+
+    /// Serializes the nonce as its two compressed points.
+    pub fn serialize(&self) -> [u8; 66] {
+        let mut bytes = [0; 66];
+        bytes[..33].copy_from_slice(&self.r1.serialize());
+        bytes[33..].copy_from_slice(&self.r2.serialize());
+        bytes
+    }
+}
+
+pub(super) fn participants(
+    unknown: &BTreeMap<raw::Key, Vec<u8>>,
+    type_value: u8,
+) -> Result<BTreeMap<PublicKey, Vec<PublicKey>>, Musig2Error> {
+    let mut ret = BTreeMap::new();
+    for (key, value) in unknown.iter().filter(|(k, _)| k.type_value == type_value) {
+        if key.key.len() != 33 || value.is_empty() || value.len() % 33 != 0 {
+            return Err(Musig2Error::InvalidLength);
+        }
+        let aggregate = PublicKey::from_slice(&key.key).map_err(Musig2Error::Secp)?;
+        let participants = value
+            .chunks(33)
+            .map(PublicKey::from_slice)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Musig2Error::Secp)?;
+        ret.insert(aggregate, participants);
+    }
+    Ok(ret)
+}
+
+pub(super) fn set_participants(
+    unknown: &mut BTreeMap<raw::Key, Vec<u8>>,
+    type_value: u8,
+    aggregate: PublicKey,
+    participants: &[PublicKey],
+) {
+    let key = raw::Key { type_value, key: aggregate.serialize().to_vec() };
+    unknown.insert(key, participants.iter().flat_map(|pk| pk.serialize()).collect());
+}
+
+pub(super) fn pub_nonces(
+    unknown: &BTreeMap<raw::Key, Vec<u8>>,
+) -> Result<BTreeMap<Musig2Key, PubNonce>, Musig2Error> {
+    unknown
+        .iter()
+        .filter(|(k, _)| k.type_value == PSBT_IN_MUSIG2_PUB_NONCE)
+        .map(|(k, v)| Ok((Musig2Key::from_slice(&k.key)?, PubNonce::from_slice(v)?)))
+        .collect()
+}
+
+pub(super) fn set_pub_nonce(
+    unknown: &mut BTreeMap<raw::Key, Vec<u8>>,
+    key: &Musig2Key,
+    nonce: &PubNonce,
+) {
+    let key = raw::Key { type_value: PSBT_IN_MUSIG2_PUB_NONCE, key: key.serialize() };
+    unknown.insert(key, nonce.serialize().to_vec());
+}
+
+pub(super) fn partial_sigs(
+    unknown: &BTreeMap<raw::Key, Vec<u8>>,
+) -> Result<BTreeMap<Musig2Key, Scalar>, Musig2Error> {
+    unknown
+        .iter()
+        .filter(|(k, _)| k.type_value == PSBT_IN_MUSIG2_PARTIAL_SIG)
+        .map(|(k, v)| {
+            let bytes = <[u8; 32]>::try_from(&v[..]).map_err(|_| Musig2Error::InvalidLength)?;
+            let sig = Scalar::from_be_bytes(bytes).map_err(|_| Musig2Error::InvalidPartialSig)?;
+            Ok((Musig2Key::from_slice(&k.key)?, sig))
+        })
+        .collect()
+}
+
+pub(super) fn set_partial_sig(
+    unknown: &mut BTreeMap<raw::Key, Vec<u8>>,
+    key: &Musig2Key,
+    sig: &Scalar,
+) {
+    let key = raw::Key { type_value: PSBT_IN_MUSIG2_PARTIAL_SIG, key: key.serialize() };
+    unknown.insert(key, sig.to_be_bytes().to_vec());
+}
+
+/// Aggregates the participant keys as in the `KeyAgg` algorithm of BIP 327.
+///
+/// Returns the aggregate key, along with the coefficient each participant key
+/// is multiplied by.
+pub fn key_agg<C: secp256k1::Verification>(
+    secp: &Secp256k1<C>,
+    participants: &[PublicKey],
+) -> Result<(PublicKey, Vec<Scalar>), Musig2Error> {
+    let serialized: Vec<[u8; 33]> = participants.iter().map(PublicKey::serialize).collect();
+    let list: Vec<&[u8]> = serialized.iter().map(|pk| &pk[..]).collect();
+    let list_hash = tagged_hash(b"KeyAgg list", &list);
+    let second = serialized.iter().find(|pk| Some(*pk) != serialized.first());
+
+    let mut coefficients = Vec::with_capacity(participants.len());
+    let mut points = Vec::with_capacity(participants.len());
+    for (pk, ser) in participants.iter().zip(&serialized) {
+        let coefficient = if Some(ser) == second {
+            Scalar::ONE
+        } else {
+            scalar_from_hash(tagged_hash(b"KeyAgg coefficient", &[&list_hash, ser]))
+        };
+        points.push(
+            pk.mul_tweak(secp, &coefficient)
+                .map_err(|_| Musig2Error::Degenerate)?,
+        );
+        coefficients.push(coefficient);
+    }
+    let aggregate = PublicKey::combine_keys(&points.iter().collect::<Vec<_>>())
+        .map_err(|_| Musig2Error::Degenerate)?;
+    Ok((aggregate, coefficients))
+}
+
+/// Aggregates the MuSig2 partial signatures of a taproot input into its key spend signature.
+///
+/// Returns `None` if the internal key of the input has no participants listed, or if some
+/// of them have not yet provided their public nonce and partial signature.
+pub(super) fn aggregate_key_spend_sig<C: secp256k1::Verification>(
+    psbt: &Psbt,
+    index: usize,
+    secp: &Secp256k1<C>,
+) -> Result<Option<taproot::Signature>, Musig2Error> {
+    let input = &psbt.inputs[index];
+    let internal_key = match input.tap_internal_key {
+        Some(key) => key,
+        None => return Ok(None),
+    };
+    let all_participants = participants(&input.unknown, PSBT_IN_MUSIG2_PARTICIPANT_PUBKEYS)?;
+    let (aggregate, participants) = match all_participants
+        .into_iter()
+        .find(|(agg, _)| agg.x_only_public_key().0 == internal_key)
+    {
+        Some(entry) => entry,
+        None => return Ok(None),
+    };
+    if key_agg(secp, &participants)?.0 != aggregate {
+        return Err(Musig2Error::AggregateMismatch(aggregate));
+    }
+
+    let nonces = pub_nonces(&input.unknown)?;
+    let sigs = partial_sigs(&input.unknown)?;
+    let mut r1s = Vec::with_capacity(participants.len());
+    let mut r2s = Vec::with_capacity(participants.len());
+    let mut s = Scalar::ZERO;
+    for participant in &participants {
+        let key = Musig2Key { participant: *participant, aggregate, leaf_hash: None };
+        match (nonces.get(&key), sigs.get(&key)) {
+            (Some(nonce), Some(sig)) => {
+                r1s.push(nonce.r1);
+                r2s.push(nonce.r2);
+                s = scalar_add(s, *sig);
+            }
+            _ => return Ok(None),
+        }
+    }
+
+    let hash_ty = input
+        .sighash_type
+        .map(|sighash_type| sighash_type.taproot_hash_ty())
+        .unwrap_or(Ok(sighash::TapSighashType::Default))
+        .map_err(|_| Musig2Error::Sighash(SighashError::InvalidSighashType))?;
+    let msg = match psbt
+        .sighash_msg(index, &mut SighashCache::new(&psbt.unsigned_tx), None)
+        .map_err(Musig2Error::Sighash)?
+    {
+        PsbtSighashMsg::TapSighash(msg) => msg.to_byte_array(),
+        PsbtSighashMsg::LegacySighash(_) | PsbtSighashMsg::SegwitV0Sighash(_) => return Ok(None),
+    };
+
+    // The taproot tweak is applied on top of the aggregate key, so only its
+    // accumulated value is needed here
+    let tweak = TapTweakHash::from_key_and_tweak(internal_key, input.tap_merkle_root).to_scalar();
+    let (output_key, parity) = internal_key
+        .add_tweak(secp, &tweak)
+        .map_err(|_| Musig2Error::Degenerate)?;
+
+    let combine = |points: &[PublicKey]| {
+        PublicKey::combine_keys(&points.iter().collect::<Vec<_>>())
+            .map_err(|_| Musig2Error::Degenerate)
+    };
+    let (r1, r2) = (combine(&r1s)?, combine(&r2s)?);
+    let b = scalar_from_hash(tagged_hash(
+        b"MuSig/noncecoef",
+        &[
+            &r1.serialize(),
+            &r2.serialize(),
+            &output_key.serialize(),
+            &msg,
+        ],
+    ));
+    let r = combine(&[
+        r1,
+        r2.mul_tweak(secp, &b)
+            .map_err(|_| Musig2Error::Degenerate)?,
+    ])?;
+    let r_x = r.x_only_public_key().0.serialize();
+    let e =
+        scalar_from_hash(tagged_hash(b"BIP0340/challenge", &[&r_x, &output_key.serialize(), &msg]));
+
+    // s = sum(s_i) + e * g * tacc, where g negates if the output key has an odd y
+    let tacc = match parity {
+        secp256k1::Parity::Even => tweak,
+        secp256k1::Parity::Odd => scalar_negate(tweak),
+    };
+    s = scalar_add(s, scalar_mul(e, tacc));
+
+    let mut sig = [0; 64];
+    sig[..32].copy_from_slice(&r_x);
+    sig[32..].copy_from_slice(&s.to_be_bytes());
+    let signature = schnorr::Signature::from_slice(&sig).expect("64 bytes");
+    Ok(Some(taproot::Signature { signature, sighash_type: hash_ty }))
+}
+
+fn tagged_hash(tag: &[u8], data: &[&[u8]]) -> [u8; 32] {
+    let tag_hash = sha256::Hash::hash(tag);
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag_hash.as_ref());
+    engine.input(tag_hash.as_ref());
+    for bytes in data {
+        engine.input(bytes);
+    }
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+// Interprets a hash as an integer modulo the curve order
+fn scalar_from_hash(mut bytes: [u8; 32]) -> Scalar {
+    if let Ok(scalar) = Scalar::from_be_bytes(bytes) {
+        return scalar;
+    }
+    // The hash is between the curve order and 2^256, subtracting the order once is enough
+    let mut borrow = 0;
+    for (byte, order) in bytes.iter_mut().zip(constants::CURVE_ORDER.iter()).rev() {
+        let diff = i16::from(*byte) - i16::from(*order) - borrow;
+        borrow = i16::from(diff < 0);
+        *byte = (diff + (borrow << 8)) as u8;
+    }
+    Scalar::from_be_bytes(bytes).expect("reduced below the curve order")
+}
+
+// Scalar arithmetic through `SecretKey`, which cannot hold zero
+fn scalar_add(a: Scalar, b: Scalar) -> Scalar {
+    match SecretKey::from_slice(&a.to_be_bytes()) {
+        Ok(a) => a.add_tweak(&b).map(Scalar::from).unwrap_or(Scalar::ZERO),
+        Err(_) => b,
+    }
+}
+
+fn scalar_mul(a: Scalar, b: Scalar) -> Scalar {
+    match SecretKey::from_slice(&a.to_be_bytes()) {
+        Ok(a) => a.mul_tweak(&b).map(Scalar::from).unwrap_or(Scalar::ZERO),
+        Err(_) => Scalar::ZERO,
+    }
+}
+
+fn scalar_negate(a: Scalar) -> Scalar {
+    match SecretKey::from_slice(&a.to_be_bytes()) {
+        Ok(a) => Scalar::from(a.negate()),
+        Err(_) => Scalar::ZERO,
+    }
+}
+
+/// Error reading the MuSig2 fields of a PSBT or aggregating their signatures.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Musig2Error {
+    /// A field key or value has an invalid length.
+    InvalidLength,
+    /// A field contains an invalid public key or nonce point.
+    Secp(secp256k1::Error),
+    /// A partial signature is not below the curve order.
+    InvalidPartialSig,
+    /// The participant keys do not aggregate to the key they are listed under.
+    AggregateMismatch(PublicKey),
+    /// An aggregated point is the point at infinity.
+    Degenerate,
+    /// The sighash of the input could not be computed.
+    Sighash(SighashError),
+}
+
+impl fmt::Display for Musig2Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Musig2Error::InvalidLength => f.write_str("invalid MuSig2 field length"),
+            Musig2Error::Secp(e) => write!(f, "invalid MuSig2 point: {}", e),
+            Musig2Error::InvalidPartialSig => f.write_str("MuSig2 partial signature out of range"),
+            Musig2Error::AggregateMismatch(pk) => {
+                write!(f, "MuSig2 participants do not aggregate to {}", pk)
+            }
+            Musig2Error::Degenerate => f.write_str("MuSig2 aggregation reached infinity"),
+            Musig2Error::Sighash(e) => write!(f, "MuSig2 sighash: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for Musig2Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        use self::Musig2Error::*;
+
+        match self {
+            InvalidLength | InvalidPartialSig | AggregateMismatch(_) | Degenerate => None,
+            Secp(e) => Some(e),
+            Sighash(e) => Some(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use bitcoin::key::XOnlyPublicKey;
+    use bitcoin::{
+        absolute, transaction, Amount, OutPoint, Sequence, Transaction, TxIn, TxOut, Witness,
+    };
+
+    use super::*;
+    use crate::psbt::{PsbtExt, PsbtInputExt};
+    use crate::{DefiniteDescriptorKey, Descriptor};
+
+    #[test]
+    fn key_agg_bip327_vectors() {
+        let secp = Secp256k1::verification_only();
+        let keys = [
+            "02F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9",
+            "03DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+            "023590A94E768F8E1815C2F24B4D80A8E3149316C3518CE7B7AD338368D038CA66",
+        ]
+        .map(|s| PublicKey::from_str(s).unwrap());
+        let vectors: [(&[usize], &str); 4] = [
+            (&[0, 1, 2], "90539EEDE565F5D054F32CC0C220126889ED1E5D193BAF15AEF344FE59D4610C"),
+            (&[2, 1, 0], "6204DE8B083426DC6EAF9502D27024D53FC826BF7D2012148A0575435DF54B2B"),
+            (&[0, 0, 0], "B436E3BAD62B8CD409969A224731C193D051162D8C5AE8B109306127DA3AA935"),
+            (
+                &[0, 0, 1, 1],
+                "69BC22BFA5D106306E48A20679DE1D7389386124D07571D0D872686028C26A3E",
+            ),
+        ];
+        for (indices, expected) in vectors {
+            let participants: Vec<_> = indices.iter().map(|i| keys[*i]).collect();
+            let (aggregate, _) = key_agg(&secp, &participants).unwrap();
+            let expected = XOnlyPublicKey::from_str(expected).unwrap();
+            assert_eq!(aggregate.x_only_public_key().0, expected);
+        }
+    }
+
+    #[test]
+    fn finalize_musig2_key_spend() {
+        let secp = Secp256k1::new();
+        let secret_keys: Vec<_> = (1..=3)
+            .map(|i| SecretKey::from_slice(&[i; 32]).unwrap())
+            .collect();
+        let participants: Vec<_> = secret_keys.iter().map(|sk| sk.public_key(&secp)).collect();
+        let (aggregate, coefficients) = key_agg(&secp, &participants).unwrap();
+        let (internal_key, internal_parity) = aggregate.x_only_public_key();
+
+        let desc = format!("tr({})", internal_key);
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&desc).unwrap();
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: bitcoin::ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(9_000),
+                script_pubkey: desc.script_pubkey(),
+            }],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo =
+            Some(TxOut { value: Amount::from_sat(10_000), script_pubkey: desc.script_pubkey() });
+        psbt.update_input_with_descriptor(0, &desc).unwrap();
+        psbt.inputs[0].set_musig2_participants(aggregate, &participants);
+
+        // Each participant publishes its nonce
+        let secret_nonces: Vec<_> = (0..3)
+            .map(|i| {
+                let k1 = SecretKey::from_slice(&[0x10 + i; 32]).unwrap();
+                let k2 = SecretKey::from_slice(&[0x20 + i; 32]).unwrap();
+                (k1, k2)
+            })
+            .collect();
+        for (pk, (k1, k2)) in participants.iter().zip(&secret_nonces) {
+            let key = Musig2Key { participant: *pk, aggregate, leaf_hash: None };
+            let nonce = PubNonce { r1: k1.public_key(&secp), r2: k2.public_key(&secp) };
+            psbt.inputs[0].set_musig2_pub_nonce(&key, &nonce);
+        }
+        let mut psbt = Psbt::deserialize(&psbt.serialize()).unwrap();
+        assert_eq!(psbt.inputs[0].musig2_participants().unwrap()[&aggregate], participants);
+        assert_eq!(psbt.inputs[0].musig2_pub_nonces().unwrap().len(), 3);
+        // Without the partial signatures there is no way to spend the output
+        assert!(matches!(
+            psbt.clone().finalize_inp_mut(&secp, 0),
+            Err(crate::psbt::Error::InputError(crate::psbt::InputError::CouldNotSatisfyTr, 0)),
+        ));
+
+        // Then signs, as in the `Sign` algorithm of BIP 327
+        let msg = match psbt
+            .sighash_msg(0, &mut SighashCache::new(&psbt.unsigned_tx), None)
+            .unwrap()
+        {
+            PsbtSighashMsg::TapSighash(msg) => msg.to_byte_array(),
+            _ => unreachable!("taproot input"),
+        };
+        let tweak = TapTweakHash::from_key_and_tweak(internal_key, None).to_scalar();
+        let (output_key, output_parity) = internal_key.add_tweak(&secp, &tweak).unwrap();
+        let nonces = psbt.inputs[0].musig2_pub_nonces().unwrap();
+        let r1s: Vec<_> = nonces.values().map(|n| n.r1).collect();
+        let r2s: Vec<_> = nonces.values().map(|n| n.r2).collect();
+        let r1 = PublicKey::combine_keys(&r1s.iter().collect::<Vec<_>>()).unwrap();
+        let r2 = PublicKey::combine_keys(&r2s.iter().collect::<Vec<_>>()).unwrap();
+        let b = scalar_from_hash(tagged_hash(
+            b"MuSig/noncecoef",
+            &[
+                &r1.serialize(),
+                &r2.serialize(),
+                &output_key.serialize(),
+                &msg,
+            ],
+        ));
+        let r = r1.combine(&r2.mul_tweak(&secp, &b).unwrap()).unwrap();
+        let (r_x, r_parity) = r.x_only_public_key();
+        let e = scalar_from_hash(tagged_hash(
+            b"BIP0340/challenge",
+            &[&r_x.serialize(), &output_key.serialize(), &msg],
+        ));
+        let negate_key = (internal_parity == secp256k1::Parity::Odd)
+            != (output_parity == secp256k1::Parity::Odd);
+        for (i, (k1, k2)) in secret_nonces.iter().enumerate() {
+            let (k1, k2) = match r_parity {
+                secp256k1::Parity::Even => (*k1, *k2),
+                secp256k1::Parity::Odd => (k1.negate(), k2.negate()),
+            };
+            let d = if negate_key {
+                secret_keys[i].negate()
+            } else {
+                secret_keys[i]
+            };
+            let s = scalar_add(
+                scalar_add(Scalar::from(k1), scalar_mul(b, Scalar::from(k2))),
+                scalar_mul(scalar_mul(e, coefficients[i]), Scalar::from(d)),
+            );
+            let key = Musig2Key { participant: participants[i], aggregate, leaf_hash: None };
+            psbt.inputs[0].set_musig2_partial_sig(&key, &s);
+        }
+        let mut psbt = Psbt::deserialize(&psbt.serialize()).unwrap();
+        let sigs = psbt.inputs[0].musig2_partial_sigs().unwrap();
+        assert_eq!(sigs.len(), 3);
+
+        // A wrong partial signature is caught by the interpreter check
+        let mut tampered = psbt.clone();
+        let (key, sig) = sigs.iter().next().unwrap();
+        tampered.inputs[0].set_musig2_partial_sig(key, &scalar_add(*sig, Scalar::ONE));
+        assert!(matches!(
+            tampered.finalize_inp_mut(&secp, 0),
+            Err(crate::psbt::Error::InputError(crate::psbt::InputError::Interpreter(_), 0)),
+        ));
+
+        psbt.finalize_mut(&secp).unwrap();
+        let witness = psbt.inputs[0].final_script_witness.as_ref().unwrap();
+        assert_eq!(witness.len(), 1);
+        assert_eq!(witness.nth(0).unwrap().len(), 64);
+        assert!(psbt.inputs[0].unknown.is_empty());
+    }
+}
@@ -0,0 +1,472 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Proprietary PSBT fields
+//!
+//! Encoding of the data this crate stores in proprietary PSBT fields, i.e. fields whose
+//! key has the [`PSBT_PROPRIETARY_PREFIX`] prefix.
+//!
+//! A [`Plan`] is stored in an input under the key with subtype [`PSBT_IN_PLAN`] and empty
+//! key data, so that the spending path chosen by one party (e.g. a coordinator) can be
+//! recovered by the others (e.g. signers and finalizers). Its value is made of:
+//!
+//! - the descriptor string, as a compact size length followed by its bytes,
+//! - the absolute and relative timelocks, each either a `0x00` byte if there is none or a
+//!   `0x01` byte followed by its consensus value as a little-endian `u32`,
+//! - the number of placeholders in the witness template as a compact size, followed by each
+//!   placeholder as a tag byte and its fields. Keys, scripts and control blocks are prefixed
+//!   by their compact size length, hashes are written as is and sizes as compact sizes.
+//!
+
+use core::str::FromStr;
+use core::{cmp, fmt};
+#[cfg(feature = "std")]
+use std::error;
+
+use bitcoin::consensus::encode::VarInt;
+use bitcoin::consensus::{Decodable, Encodable};
+use bitcoin::hashes::{hash160, Hash};
+use bitcoin::psbt::raw::ProprietaryKey;
+use bitcoin::taproot::{self, ControlBlock, TapLeafHash, TapNodeHash};
+use bitcoin::{absolute, relative, ScriptBuf};
+
+use crate::descriptor::DescriptorKeyParseError;
+use crate::miniscript::satisfy::{Placeholder, SchnorrSigType};
+use crate::plan::Plan;
+use crate::prelude::*;
+use crate::{DefiniteDescriptorKey, Descriptor};
+
+/// Prefix of the keys of the proprietary PSBT fields used by this crate.
+pub const PSBT_PROPRIETARY_PREFIX: &[u8] = b"miniscript";
+
+/// Subtype of the proprietary PSBT input field storing a [`Plan`].
+pub const PSBT_IN_PLAN: u8 = 0x00;
+
+// The key of the proprietary input field storing a plan
+pub(super) fn plan_key() -> ProprietaryKey {
+    ProprietaryKey { prefix: PSBT_PROPRIETARY_PREFIX.to_vec(), subtype: PSBT_IN_PLAN, key: vec![] }
+}
+
+// Placeholder tags, in the same order as the serde variants
+const TAG_PUBKEY: u8 = 0;
+const TAG_PUBKEY_HASH: u8 = 1;
+const TAG_ECDSA_SIG_PK: u8 = 2;
+const TAG_ECDSA_SIG_PKH: u8 = 3;
+const TAG_SCHNORR_SIG_PK: u8 = 4;
+const TAG_SCHNORR_SIG_PKH: u8 = 5;
+const TAG_SHA256: u8 = 6;
+const TAG_HASH256: u8 = 7;
+const TAG_RIPEMD160: u8 = 8;
+const TAG_HASH160: u8 = 9;
+const TAG_HASH_DISSAT: u8 = 10;
+const TAG_PUSH_ONE: u8 = 11;
+const TAG_PUSH_ZERO: u8 = 12;
+const TAG_TAP_SCRIPT: u8 = 13;
+const TAG_TAP_CONTROL_BLOCK: u8 = 14;
+const TAG_PENDING_SHA256: u8 = 15;
+const TAG_PENDING_HASH256: u8 = 16;
+const TAG_PENDING_RIPEMD160: u8 = 17;
+const TAG_PENDING_HASH160: u8 = 18;
+
+// Schnorr signature type tags
+const TAG_KEY_SPEND: u8 = 0;
+const TAG_KEY_SPEND_MERKLE_ROOT: u8 = 1;
+const TAG_SCRIPT_SPEND: u8 = 2;
+
+/// Encodes a plan as the value of its proprietary PSBT input field.
+pub(super) fn encode_plan(plan: &Plan) -> Vec<u8> {
+    let mut w = Writer(vec![]);
+    w.var_bytes(plan.descriptor.to_string().as_bytes());
+    w.timelock(plan.absolute_timelock.map(|lt| lt.to_consensus_u32()));
+    w.timelock(plan.relative_timelock.map(|lt| lt.to_consensus_u32()));
+    w.compact_size(plan.template.len());
+    for placeholder in &plan.template {
+        w.placeholder(placeholder);
+    }
+    w.0
+}
+
+/// Decodes a plan from the value of its proprietary PSBT input field.
+pub(super) fn decode_plan(bytes: &[u8]) -> Result<Plan, PlanDecodeError> {
+    let mut r = Reader(bytes);
+    let descriptor = Descriptor::from_str(r.str()?).map_err(PlanDecodeError::Descriptor)?;
+    let absolute_timelock = r.timelock()?.map(absolute::LockTime::from_consensus);
+    let relative_timelock = match r.timelock()? {
+        Some(n) => Some(
+            relative::LockTime::from_consensus(n)
+                .map_err(|_| PlanDecodeError::RelativeTimelock(n))?,
+        ),
+        None => None,
+    };
+    let len = r.compact_size()?;
+    // Every placeholder takes at least a byte, don't let the length allocate more than that
+    let mut template = Vec::with_capacity(cmp::min(len, r.0.len()));
+    for _ in 0..len {
+        template.push(r.placeholder()?);
+    }
+    if !r.0.is_empty() {
+        return Err(PlanDecodeError::TrailingData);
+    }
+    Ok(Plan { template, absolute_timelock, relative_timelock, descriptor })
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn compact_size(&mut self, n: usize) {
+        VarInt(n as u64)
+            .consensus_encode(&mut self.0)
+            .expect("Writing to a vector can't fail");
+    }
+
+    fn var_bytes(&mut self, bytes: &[u8]) {
+        self.compact_size(bytes.len());
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn hash<H: Hash>(&mut self, hash: &H) { self.0.extend_from_slice(&hash[..]); }
+
+    fn key(&mut self, pk: &DefiniteDescriptorKey) { self.var_bytes(pk.to_string().as_bytes()); }
+
+    fn timelock(&mut self, n: Option<u32>) {
+        match n {
+            Some(n) => {
+                self.0.push(1);
+                self.0.extend_from_slice(&n.to_le_bytes());
+            }
+            None => self.0.push(0),
+        }
+    }
+
+    fn placeholder(&mut self, placeholder: &Placeholder<DefiniteDescriptorKey>) {
+        use Placeholder::*;
+
+        match placeholder {
+            Pubkey(pk, size) => {
+                self.0.push(TAG_PUBKEY);
+                self.key(pk);
+                self.compact_size(*size);
+            }
+            PubkeyHash(hash, size) => {
+                self.0.push(TAG_PUBKEY_HASH);
+                self.hash(hash);
+                self.compact_size(*size);
+            }
+            EcdsaSigPk(pk) => {
+                self.0.push(TAG_ECDSA_SIG_PK);
+                self.key(pk);
+            }
+            EcdsaSigPkHash(hash) => {
+                self.0.push(TAG_ECDSA_SIG_PKH);
+                self.hash(hash);
+            }
+            SchnorrSigPk(pk, sig_type, size) => {
+                self.0.push(TAG_SCHNORR_SIG_PK);
+                self.key(pk);
+                match sig_type {
+                    SchnorrSigType::KeySpend { merkle_root: None } => self.0.push(TAG_KEY_SPEND),
+                    SchnorrSigType::KeySpend { merkle_root: Some(root) } => {
+                        self.0.push(TAG_KEY_SPEND_MERKLE_ROOT);
+                        self.hash(root);
+                    }
+                    SchnorrSigType::ScriptSpend { leaf_hash } => {
+                        self.0.push(TAG_SCRIPT_SPEND);
+                        self.hash(leaf_hash);
+                    }
+                }
+                self.compact_size(*size);
+            }
+            SchnorrSigPkHash(hash, leaf_hash, size) => {
+                self.0.push(TAG_SCHNORR_SIG_PKH);
+                self.hash(hash);
+                self.hash(leaf_hash);
+                self.compact_size(*size);
+            }
+            Sha256Preimage(hash) => {
+                self.0.push(TAG_SHA256);
+                self.hash(hash);
+            }
+            Hash256Preimage(hash) => {
+                self.0.push(TAG_HASH256);
+                self.hash(hash);
+            }
+            Ripemd160Preimage(hash) => {
+                self.0.push(TAG_RIPEMD160);
+                self.hash(hash);
+            }
+            Hash160Preimage(hash) => {
+                self.0.push(TAG_HASH160);
+                self.hash(hash);
+            }
+            PendingSha256Preimage(hash) => {
+                self.0.push(TAG_PENDING_SHA256);
+                self.hash(hash);
+            }
+            PendingHash256Preimage(hash) => {
+                self.0.push(TAG_PENDING_HASH256);
+                self.hash(hash);
+            }
+            PendingRipemd160Preimage(hash) => {
+                self.0.push(TAG_PENDING_RIPEMD160);
+                self.hash(hash);
+            }
+            PendingHash160Preimage(hash) => {
+                self.0.push(TAG_PENDING_HASH160);
+                self.hash(hash);
+            }
+            HashDissatisfaction => self.0.push(TAG_HASH_DISSAT),
+            PushOne => self.0.push(TAG_PUSH_ONE),
+            PushZero => self.0.push(TAG_PUSH_ZERO),
+            TapScript(script) => {
+                self.0.push(TAG_TAP_SCRIPT);
+                self.var_bytes(script.as_bytes());
+            }
+            TapControlBlock(control_block) => {
+                self.0.push(TAG_TAP_CONTROL_BLOCK);
+                self.var_bytes(&control_block.serialize());
+            }
+        }
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], PlanDecodeError> {
+        if self.0.len() < len {
+            return Err(PlanDecodeError::UnexpectedEnd);
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, PlanDecodeError> { Ok(self.bytes(1)?[0]) }
+
+    fn compact_size(&mut self) -> Result<usize, PlanDecodeError> {
+        let n =
+            VarInt::consensus_decode(&mut self.0).map_err(|_| PlanDecodeError::UnexpectedEnd)?;
+        usize::try_from(n.0).map_err(|_| PlanDecodeError::UnexpectedEnd)
+    }
+
+    fn var_bytes(&mut self) -> Result<&'a [u8], PlanDecodeError> {
+        let len = self.compact_size()?;
+        self.bytes(len)
+    }
+
+    fn str(&mut self) -> Result<&'a str, PlanDecodeError> {
+        core::str::from_utf8(self.var_bytes()?).map_err(|_| PlanDecodeError::InvalidUtf8)
+    }
+
+    fn hash<H: Hash>(&mut self) -> Result<H, PlanDecodeError> {
+        Ok(H::from_slice(self.bytes(H::LEN)?).expect("Slice has the hash length"))
+    }
+
+    fn key(&mut self) -> Result<DefiniteDescriptorKey, PlanDecodeError> {
+        DefiniteDescriptorKey::from_str(self.str()?).map_err(PlanDecodeError::Key)
+    }
+
+    fn timelock(&mut self) -> Result<Option<u32>, PlanDecodeError> {
+        match self.byte()? {
+            0 => Ok(None),
+            1 => {
+                let bytes = self.bytes(4)?;
+                Ok(Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])))
+            }
+            tag => Err(PlanDecodeError::UnknownTag(tag)),
+        }
+    }
+
+    fn placeholder(&mut self) -> Result<Placeholder<DefiniteDescriptorKey>, PlanDecodeError> {
+        use Placeholder::*;
+
+        let placeholder = match self.byte()? {
+            TAG_PUBKEY => Pubkey(self.key()?, self.compact_size()?),
+            TAG_PUBKEY_HASH => PubkeyHash(self.hash::<hash160::Hash>()?, self.compact_size()?),
+            TAG_ECDSA_SIG_PK => EcdsaSigPk(self.key()?),
+            TAG_ECDSA_SIG_PKH => EcdsaSigPkHash(self.hash()?),
+            TAG_SCHNORR_SIG_PK => {
+                let pk = self.key()?;
+                let sig_type = match self.byte()? {
+                    TAG_KEY_SPEND => SchnorrSigType::KeySpend { merkle_root: None },
+                    TAG_KEY_SPEND_MERKLE_ROOT => {
+                        SchnorrSigType::KeySpend { merkle_root: Some(self.hash::<TapNodeHash>()?) }
+                    }
+                    TAG_SCRIPT_SPEND => {
+                        SchnorrSigType::ScriptSpend { leaf_hash: self.hash::<TapLeafHash>()? }
+                    }
+                    tag => return Err(PlanDecodeError::UnknownTag(tag)),
+                };
+                SchnorrSigPk(pk, sig_type, self.compact_size()?)
+            }
+            TAG_SCHNORR_SIG_PKH => {
+                SchnorrSigPkHash(self.hash()?, self.hash()?, self.compact_size()?)
+            }
+            TAG_SHA256 => Sha256Preimage(self.hash()?),
+            TAG_HASH256 => Hash256Preimage(self.hash()?),
+            TAG_RIPEMD160 => Ripemd160Preimage(self.hash()?),
+            TAG_HASH160 => Hash160Preimage(self.hash()?),
+            TAG_PENDING_SHA256 => PendingSha256Preimage(self.hash()?),
+            TAG_PENDING_HASH256 => PendingHash256Preimage(self.hash()?),
+            TAG_PENDING_RIPEMD160 => PendingRipemd160Preimage(self.hash()?),
+            TAG_PENDING_HASH160 => PendingHash160Preimage(self.hash()?),
+            TAG_HASH_DISSAT => HashDissatisfaction,
+            TAG_PUSH_ONE => PushOne,
+            TAG_PUSH_ZERO => PushZero,
+            TAG_TAP_SCRIPT => TapScript(ScriptBuf::from_bytes(self.var_bytes()?.to_vec())),
+            TAG_TAP_CONTROL_BLOCK => TapControlBlock(
+                ControlBlock::decode(self.var_bytes()?).map_err(PlanDecodeError::ControlBlock)?,
+            ),
+            tag => return Err(PlanDecodeError::UnknownTag(tag)),
+        };
+        Ok(placeholder)
+    }
+}
+
+/// Error decoding a [`Plan`] stored in a PSBT input, see [`super::PsbtInputExt::plan`].
+#[derive(Debug)]
+pub enum PlanDecodeError {
+    /// The value ended before the plan did
+    UnexpectedEnd,
+    /// The value continues after the plan
+    TrailingData,
+    /// Unknown placeholder, timelock or signature type tag
+    UnknownTag(u8),
+    /// A string is not valid UTF-8
+    InvalidUtf8,
+    /// The descriptor failed to parse
+    Descriptor(crate::Error),
+    /// A key failed to parse
+    Key(DescriptorKeyParseError),
+    /// The relative timelock is disabled
+    RelativeTimelock(u32),
+    /// A control block failed to parse
+    ControlBlock(taproot::TaprootError),
+}
+
+impl fmt::Display for PlanDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlanDecodeError::UnexpectedEnd => f.write_str("unexpected end of plan data"),
+            PlanDecodeError::TrailingData => f.write_str("trailing data after plan"),
+            PlanDecodeError::UnknownTag(tag) => write!(f, "unknown tag {} in plan", tag),
+            PlanDecodeError::InvalidUtf8 => f.write_str("invalid UTF-8 string in plan"),
+            PlanDecodeError::Descriptor(e) => write!(f, "plan descriptor: {}", e),
+            PlanDecodeError::Key(e) => write!(f, "plan key: {}", e),
+            PlanDecodeError::RelativeTimelock(n) => {
+                write!(f, "plan relative timelock {:#x} is disabled", n)
+            }
+            PlanDecodeError::ControlBlock(e) => write!(f, "plan control block: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for PlanDecodeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        use self::PlanDecodeError::*;
+
+        match self {
+            UnexpectedEnd | TrailingData | UnknownTag(_) | InvalidUtf8 | RelativeTimelock(_) => {
+                None
+            }
+            Descriptor(e) => Some(e),
+            Key(e) => Some(e),
+            ControlBlock(e) => Some(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::sha256;
+    use bitcoin::psbt::Psbt;
+    use bitcoin::{transaction, Amount, OutPoint, Sequence, Transaction, TxIn, TxOut, Witness};
+
+    use super::*;
+    use crate::plan::Assets;
+    use crate::psbt::PsbtInputExt;
+    use crate::DescriptorPublicKey;
+
+    const G: &str = "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    const G2: &str = "c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+    const G3: &str = "f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9";
+
+    fn psbt() -> Psbt {
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut { value: Amount::from_sat(1000), script_pubkey: ScriptBuf::new() }],
+        };
+        Psbt::from_unsigned_tx(tx).unwrap()
+    }
+
+    #[test]
+    fn plan_roundtrip() {
+        let hash = sha256::Hash::hash(&[1; 32]);
+        let desc = format!("tr({},{{pk({}),and_v(v:pk({}),sha256({}))}})", G, G2, G3, hash);
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&desc).unwrap();
+        let leaf_plan = desc
+            .clone()
+            .plan(
+                &Assets::new()
+                    .add(DescriptorPublicKey::from_str(G3).unwrap())
+                    .add(hash),
+            )
+            .unwrap();
+        let key_plan = desc
+            .plan(&Assets::new().add(DescriptorPublicKey::from_str(G).unwrap()))
+            .unwrap();
+        let wsh_plan = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(and_v(v:pk(02{}),and_v(v:after(100),older(144))))",
+            G
+        ))
+        .unwrap()
+        .plan(
+            &Assets::new()
+                .add(DescriptorPublicKey::from_str(&format!("02{}", G)).unwrap())
+                .after(absolute::LockTime::from_height(100).unwrap())
+                .older(relative::LockTime::from_height(144)),
+        )
+        .unwrap();
+
+        for plan in [leaf_plan, key_plan, wsh_plan] {
+            let mut psbt = psbt();
+            assert!(psbt.inputs[0].plan().unwrap().is_none());
+            psbt.inputs[0].set_plan(&plan);
+
+            let psbt = Psbt::deserialize(&psbt.serialize()).unwrap();
+            let decoded = psbt.inputs[0].plan().unwrap().unwrap();
+            assert_eq!(decoded, plan);
+            assert_eq!(decoded.satisfaction_weight(), plan.satisfaction_weight());
+        }
+    }
+
+    #[test]
+    fn plan_decode_errors() {
+        let plan = Descriptor::<DefiniteDescriptorKey>::from_str(&format!("tr({})", G))
+            .unwrap()
+            .plan(&Assets::new().add(DescriptorPublicKey::from_str(G).unwrap()))
+            .unwrap();
+        let bytes = encode_plan(&plan);
+
+        assert!(matches!(
+            decode_plan(&bytes[..bytes.len() - 1]),
+            Err(PlanDecodeError::UnexpectedEnd)
+        ));
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(matches!(decode_plan(&trailing), Err(PlanDecodeError::TrailingData)));
+
+        let mut psbt = psbt();
+        psbt.inputs[0]
+            .proprietary
+            .insert(plan_key(), vec![0x02, b'x', b'y']);
+        assert!(matches!(psbt.inputs[0].plan(), Err(PlanDecodeError::Descriptor(_))));
+    }
+}
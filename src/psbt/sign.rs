@@ -0,0 +1,294 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # PSBT Signer
+//!
+//! This module implements a software Signer role, as defined in BIP 174, for
+//! the private keys of a descriptor.
+//!
+//! Keys are matched against the `bip32_derivation` and `tap_key_origins` fields
+//! of each input, so the inputs should first be updated with their descriptors
+//! (see [`super::PsbtExt::update_input_with_descriptor`]).
+//!
+
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error;
+
+use bitcoin::key::{TapTweak, XOnlyPublicKey};
+use bitcoin::psbt::{self, Psbt};
+use bitcoin::secp256k1::{self, Keypair, Secp256k1, Signing, Verification};
+use bitcoin::sighash::{self, SighashCache};
+use bitcoin::taproot::{self, TapLeafHash};
+use bitcoin::{bip32, ecdsa};
+
+use super::{PsbtExt, PsbtSighashMsg, SighashError};
+use crate::descriptor::{DescriptorSecretKey, KeyMap, Wildcard};
+use crate::prelude::*;
+
+/// The signatures added to a single PSBT input by [`sign`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputSignatures {
+    /// ECDSA signatures, added to `partial_sigs`.
+    pub ecdsa: BTreeMap<bitcoin::PublicKey, ecdsa::Signature>,
+    /// Taproot key spend signature, added to `tap_key_sig`.
+    pub tap_key_spend: Option<taproot::Signature>,
+    /// Taproot script spend signatures, added to `tap_script_sigs`.
+    pub tap_script: BTreeMap<(XOnlyPublicKey, TapLeafHash), taproot::Signature>,
+}
+
+impl InputSignatures {
+    /// Whether no signature was produced for the input.
+    pub fn is_empty(&self) -> bool {
+        self.ecdsa.is_empty() && self.tap_key_spend.is_none() && self.tap_script.is_empty()
+    }
+}
+
+/// Signs every input of the PSBT for which `keys` holds a private key.
+///
+/// The keys of an input are those listed in its `bip32_derivation` field, which
+/// are signed with ECDSA, and in its `tap_key_origins` field, which are signed
+/// with Schnorr. The taproot internal key signs the key spend, tweaked with the
+/// input's `tap_merkle_root`, and every key signs the leaves whose hashes are
+/// listed next to it. The sighash type of the input is used if set, otherwise
+/// `SIGHASH_ALL` (or `SIGHASH_DEFAULT` for taproot).
+///
+/// Returns the signatures added to each input, in input order. Nothing is added
+/// to the PSBT if an error is returned.
+pub fn sign<C: Signing + Verification>(
+    psbt: &mut Psbt,
+    keys: &KeyMap,
+    secp: &Secp256k1<C>,
+) -> Result<Vec<InputSignatures>, SignError> {
+    let mut report = Vec::with_capacity(psbt.inputs.len());
+    let mut cache = SighashCache::new(&psbt.unsigned_tx);
+    for (index, input) in psbt.inputs.iter().enumerate() {
+        report.push(sign_input(psbt, index, input, &mut cache, keys, secp)?);
+    }
+
+    for (input, sigs) in psbt.inputs.iter_mut().zip(&report) {
+        input
+            .partial_sigs
+            .extend(sigs.ecdsa.iter().map(|(pk, sig)| (*pk, *sig)));
+        if let Some(sig) = sigs.tap_key_spend {
+            input.tap_key_sig = Some(sig);
+        }
+        input
+            .tap_script_sigs
+            .extend(sigs.tap_script.iter().map(|(k, sig)| (*k, *sig)));
+    }
+    Ok(report)
+}
+
+fn sign_input<C: Signing + Verification>(
+    psbt: &Psbt,
+    index: usize,
+    input: &psbt::Input,
+    cache: &mut SighashCache<&bitcoin::Transaction>,
+    keys: &KeyMap,
+    secp: &Secp256k1<C>,
+) -> Result<InputSignatures, SignError> {
+    let mut sigs = InputSignatures::default();
+
+    for (pk, source) in &input.bip32_derivation {
+        let sk = match find_secret_key(keys, source, secp, |sk| sk.inner.public_key(secp) == *pk) {
+            Some(sk) => sk,
+            None => continue,
+        };
+        let msg = match sighash_msg(psbt, index, cache, None)? {
+            PsbtSighashMsg::TapSighash(_) => return Err(SignError::SighashMismatch(index)),
+            msg => msg.to_secp_msg(),
+        };
+        let sighash_type = input
+            .sighash_type
+            .map(|ty| ty.ecdsa_hash_ty())
+            .unwrap_or(Ok(sighash::EcdsaSighashType::All))
+            .map_err(|_| SignError::Sighash(SighashError::InvalidSighashType, index))?;
+        let signature = secp.sign_ecdsa(&msg, &sk.inner);
+        sigs.ecdsa
+            .insert(sk.public_key(secp), ecdsa::Signature { signature, sighash_type });
+    }
+
+    for (xonly, (leaf_hashes, source)) in &input.tap_key_origins {
+        let is_key = |sk: &bitcoin::PrivateKey| sk.inner.x_only_public_key(secp).0 == *xonly;
+        let sk = match find_secret_key(keys, source, secp, is_key) {
+            Some(sk) => sk,
+            None => continue,
+        };
+        let sighash_type = input
+            .sighash_type
+            .map(|ty| ty.taproot_hash_ty())
+            .unwrap_or(Ok(sighash::TapSighashType::Default))
+            .map_err(|_| SignError::Sighash(SighashError::InvalidSighashType, index))?;
+        let keypair = Keypair::from_secret_key(secp, &sk.inner);
+
+        if input.tap_internal_key == Some(*xonly) {
+            let msg = tap_sighash_msg(psbt, index, cache, None)?;
+            let tweaked = keypair.tap_tweak(secp, input.tap_merkle_root).to_keypair();
+            let signature = secp.sign_schnorr_no_aux_rand(&msg, &tweaked);
+            sigs.tap_key_spend = Some(taproot::Signature { signature, sighash_type });
+        }
+        for leaf_hash in leaf_hashes {
+            let msg = tap_sighash_msg(psbt, index, cache, Some(*leaf_hash))?;
+            let signature = secp.sign_schnorr_no_aux_rand(&msg, &keypair);
+            sigs.tap_script
+                .insert((*xonly, *leaf_hash), taproot::Signature { signature, sighash_type });
+        }
+    }
+    Ok(sigs)
+}
+
+fn sighash_msg(
+    psbt: &Psbt,
+    index: usize,
+    cache: &mut SighashCache<&bitcoin::Transaction>,
+    leaf_hash: Option<TapLeafHash>,
+) -> Result<PsbtSighashMsg, SignError> {
+    psbt.sighash_msg(index, cache, leaf_hash)
+        .map_err(|e| SignError::Sighash(e, index))
+}
+
+fn tap_sighash_msg(
+    psbt: &Psbt,
+    index: usize,
+    cache: &mut SighashCache<&bitcoin::Transaction>,
+    leaf_hash: Option<TapLeafHash>,
+) -> Result<secp256k1::Message, SignError> {
+    match sighash_msg(psbt, index, cache, leaf_hash)? {
+        msg @ PsbtSighashMsg::TapSighash(_) => Ok(msg.to_secp_msg()),
+        _ => Err(SignError::SighashMismatch(index)),
+    }
+}
+
+// Finds the private key in `keys` for the key with origin `source`, checking that it is the
+// expected key with `is_key`
+fn find_secret_key<C, F>(
+    keys: &KeyMap,
+    source: &bip32::KeySource,
+    secp: &Secp256k1<C>,
+    is_key: F,
+) -> Option<bitcoin::PrivateKey>
+where
+    C: Signing,
+    F: Fn(&bitcoin::PrivateKey) -> bool,
+{
+    keys.values()
+        .flat_map(|sk| sk.clone().into_single_keys())
+        .filter_map(|sk| match sk {
+            DescriptorSecretKey::Single(single) => Some(single.key),
+            DescriptorSecretKey::XPrv(xprv) => {
+                xprv.matches(source, secp)?;
+                let path = match xprv.wildcard {
+                    Wildcard::None => xprv.derivation_path.clone(),
+                    Wildcard::Unhardened | Wildcard::Hardened => {
+                        xprv.derivation_path.child(*source.1.as_ref().last()?)
+                    }
+                };
+                let derived = xprv.xkey.derive_priv(secp, &path).ok()?;
+                Some(derived.to_priv())
+            }
+            DescriptorSecretKey::MultiXPrv(_) => unreachable!("single keys"),
+        })
+        .find(is_key)
+}
+
+/// Error returned by [`sign`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignError {
+    /// The sighash of an input could not be computed.
+    Sighash(SighashError, usize),
+    /// The key fields of an input do not match the type of output it spends, for
+    /// example `tap_key_origins` set for a segwit v0 output.
+    SighashMismatch(usize),
+}
+
+impl fmt::Display for SignError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SignError::Sighash(ref e, index) => write!(f, "{} at index {}", e, index),
+            SignError::SighashMismatch(index) => {
+                write!(f, "keys of input {} do not match the type of the output it spends", index)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for SignError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            SignError::Sighash(ref e, _) => Some(e),
+            SignError::SighashMismatch(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use bitcoin::{absolute, transaction, Amount, OutPoint, ScriptBuf, Transaction, TxIn, TxOut};
+
+    use super::*;
+    use crate::{DefiniteDescriptorKey, Descriptor};
+
+    #[test]
+    fn sign_ecdsa_and_taproot() {
+        let secp = Secp256k1::new();
+        let tprv = "tprv8ZgxMBicQKsPcwcD4gSnMti126ZiETsuX7qwrtMypr6FBwAP65puFn4v6c3jrN9VwtMRMph6nyT63NrfUL4C3nBzPcduzVSuHD7zbX2JKVc";
+        let mut keys = KeyMap::new();
+        let mut parse = |desc: &str, index: u32| {
+            let (desc, key_map) = Descriptor::parse_descriptor(&secp, desc).unwrap();
+            keys.extend(key_map);
+            desc.at_derivation_index(index).unwrap()
+        };
+        let wpkh = parse(&format!("wpkh({}/84'/1'/0'/0/*)", tprv), 3);
+        let tr = parse(&format!("tr({}/86'/1'/0'/0/*,pk({}/86'/1'/0'/1/*))", tprv, tprv), 0);
+        // No private key is known for this one
+        let other = Descriptor::<DefiniteDescriptorKey>::from_str(
+            "wpkh(02e6642fd69bd211f93f7f1f36ca51a26a5290eb2dd1b0d8279a87bb0d480c8443)",
+        )
+        .unwrap();
+
+        let descs = [wpkh, tr, other];
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn { previous_output: OutPoint::default(), ..TxIn::default() }; 3],
+            output: vec![TxOut {
+                value: Amount::from_sat(25_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        for (index, desc) in descs.iter().enumerate() {
+            psbt.inputs[index].witness_utxo = Some(TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: desc.script_pubkey(),
+            });
+            psbt.update_input_with_descriptor(index, desc).unwrap();
+        }
+
+        let report = sign(&mut psbt, &keys, &secp).unwrap();
+        assert_eq!(report.len(), 3);
+        assert_eq!(report[0].ecdsa.len(), 1);
+        assert!(report[0].tap_key_spend.is_none() && report[0].tap_script.is_empty());
+        assert!(report[1].ecdsa.is_empty());
+        assert!(report[1].tap_key_spend.is_some());
+        assert_eq!(report[1].tap_script.len(), 1);
+        assert!(report[2].is_empty());
+
+        // The script spend signature is made by the leaf key for the leaf sighash
+        let (&(leaf_key, leaf_hash), sig) = report[1].tap_script.iter().next().unwrap();
+        assert_ne!(psbt.inputs[1].tap_internal_key, Some(leaf_key));
+        let mut cache = SighashCache::new(&psbt.unsigned_tx);
+        let msg = psbt.sighash_msg(1, &mut cache, Some(leaf_hash)).unwrap();
+        secp.verify_schnorr(&sig.signature, &msg.to_secp_msg(), &leaf_key)
+            .unwrap();
+        assert_eq!(psbt.inputs[1].tap_script_sigs.len(), 1);
+
+        // The signed inputs can be finalized, checking their signatures
+        psbt.finalize_inp_mut(&secp, 0).unwrap();
+        psbt.finalize_inp_mut(&secp, 1).unwrap();
+        assert!(psbt.finalize_inp_mut(&secp, 2).is_err());
+    }
+}
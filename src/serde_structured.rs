@@ -0,0 +1,298 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Structured Serde Support
+//!
+//! By default, all the recursive structures in this library (descriptors,
+//! miniscripts, taproot trees and policies) are serialized with serde as
+//! their string representation. This module provides an alternate,
+//! structured, representation which exposes the shape of the underlying
+//! expression tree to the serialization format. It is intended to be used
+//! with serde's `with` attribute:
+//!
+//! ```rust,ignore
+//! #[derive(Serialize, Deserialize)]
+//! struct Wallet {
+//!     #[serde(with = "miniscript::serde_structured")]
+//!     spending_policy: Miniscript<DescriptorPublicKey, Segwitv0>,
+//! }
+//! ```
+//!
+//! Each node of the expression tree is encoded as follows:
+//!
+//! * a node without children (a key, hash, number, etc.) is a string;
+//! * a node `X(s_1,...,s_n)` is a map with the single entry `X: [s_1, ..., s_n]`;
+//! * a taproot branch `{s_1,s_2}` is a sequence `[s_1, s_2]`.
+//!
+//! So, for example, `and_v(v:pk(A),older(144))` is encoded in JSON as
+//! `{"and_v":[{"v:pk":["A"]},{"older":["144"]}]}`.
+//!
+//! Checksums are not part of the structured representation. They are
+//! ignored on serialization and cannot be provided on deserialization.
+//!
+
+use core::fmt;
+use core::str::FromStr;
+
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Error as _, Serialize, SerializeMap, SerializeSeq, Serializer};
+
+use crate::expression::{self, Parens, TreeIterItem};
+use crate::prelude::*;
+
+/// Serializes an object in its structured form.
+///
+/// The object is serialized by parsing its `Display` output as an expression
+/// tree and encoding that tree as described in the [module documentation](self).
+pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: fmt::Display,
+    S: Serializer,
+{
+    let s = value.to_string();
+    let tree = expression::Tree::from_str(&s).map_err(S::Error::custom)?;
+    StructuredNode(tree.root()).serialize(serializer)
+}
+
+/// Deserializes an object from its structured form.
+///
+/// The structured form is converted back into an expression string, which is
+/// then parsed using the object's `FromStr` implementation.
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+    D: Deserializer<'de>,
+{
+    let s = ExpressionString::deserialize(deserializer)?;
+    T::from_str(&s.0).map_err(de::Error::custom)
+}
+
+/// Wrapper around an expression tree node which serializes in structured form.
+struct StructuredNode<'s>(TreeIterItem<'s>);
+
+impl Serialize for StructuredNode<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0.parens() {
+            Parens::None => serializer.serialize_str(self.0.name()),
+            Parens::Round => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry(self.0.name(), &StructuredChildren(self.0))?;
+                map.end()
+            }
+            Parens::Curly => {
+                if !self.0.name().is_empty() {
+                    return Err(S::Error::custom(format_args!(
+                        "cannot serialize named curly-brace node {}",
+                        self.0.name()
+                    )));
+                }
+                StructuredChildren(self.0).serialize(serializer)
+            }
+        }
+    }
+}
+
+/// Wrapper around an expression tree node which serializes its children as a sequence.
+struct StructuredChildren<'s>(TreeIterItem<'s>);
+
+impl Serialize for StructuredChildren<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.0.n_children()))?;
+        for child in self.0.children() {
+            seq.serialize_element(&StructuredNode(child))?;
+        }
+        seq.end()
+    }
+}
+
+/// An expression string reassembled from its structured form.
+struct ExpressionString(String);
+
+impl ExpressionString {
+    /// Appends a comma-separated list of child expressions, surrounded by the given delimiters.
+    fn push_children<'de, A: SeqAccess<'de>>(
+        &mut self,
+        mut seq: A,
+        open: char,
+        close: char,
+    ) -> Result<usize, A::Error> {
+        self.0.push(open);
+        let mut n_children = 0;
+        while let Some(child) = seq.next_element::<ExpressionString>()? {
+            if n_children > 0 {
+                self.0.push(',');
+            }
+            self.0.push_str(&child.0);
+            n_children += 1;
+        }
+        self.0.push(close);
+        Ok(n_children)
+    }
+}
+
+impl<'de> Deserialize<'de> for ExpressionString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Clone, Copy)]
+        struct NodeVisitor;
+
+        impl<'de> Visitor<'de> for NodeVisitor {
+            type Value = ExpressionString;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a string, a single-entry map or a sequence of expression nodes")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                if v.contains(['(', ')', '{', '}', ',', '#']) {
+                    return Err(E::invalid_value(de::Unexpected::Str(v), &"an expression name"));
+                }
+                Ok(ExpressionString(v.to_owned()))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let name = map
+                    .next_key::<String>()?
+                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let mut ret = self.visit_str::<A::Error>(&name)?;
+                ret = map.next_value_seed(ChildrenSeed(ret))?;
+                if map.next_key::<de::IgnoredAny>()?.is_some() {
+                    return Err(de::Error::invalid_length(2, &self));
+                }
+                Ok(ret)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+                let mut ret = ExpressionString(String::new());
+                let n_children = ret.push_children(seq, '{', '}')?;
+                if n_children != 2 {
+                    return Err(de::Error::invalid_length(n_children, &"a taproot branch"));
+                }
+                Ok(ret)
+            }
+        }
+
+        deserializer.deserialize_any(NodeVisitor)
+    }
+}
+
+/// Seed which appends a sequence of children, in round parentheses, to an expression string.
+struct ChildrenSeed(ExpressionString);
+
+impl<'de> de::DeserializeSeed<'de> for ChildrenSeed {
+    type Value = ExpressionString;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        struct ChildrenVisitor(ExpressionString);
+
+        impl<'de> Visitor<'de> for ChildrenVisitor {
+            type Value = ExpressionString;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a sequence of expression nodes")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(mut self, seq: A) -> Result<Self::Value, A::Error> {
+                self.0.push_children(seq, '(', ')')?;
+                Ok(self.0)
+            }
+        }
+
+        deserializer.deserialize_seq(ChildrenVisitor(self.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use serde_test::{assert_de_tokens_error, assert_tokens, Token};
+
+    use crate::descriptor::TapTree;
+    use crate::{Miniscript, Segwitv0};
+
+    #[derive(Debug, PartialEq)]
+    struct Structured<T>(T);
+
+    impl<T: core::fmt::Display> Serialize for Structured<T> {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            super::serialize(&self.0, s)
+        }
+    }
+
+    impl<'de, T: FromStr> Deserialize<'de> for Structured<T>
+    where
+        T::Err: core::fmt::Display,
+    {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            super::deserialize(d).map(Structured)
+        }
+    }
+
+    #[test]
+    fn miniscript_structured() {
+        let ms = Miniscript::<String, Segwitv0>::from_str("and_v(v:pk(A),older(144))").unwrap();
+        assert_tokens(
+            &Structured(ms),
+            &[
+                Token::Map { len: Some(1) },
+                Token::Str("and_v"),
+                Token::Seq { len: Some(2) },
+                Token::Map { len: Some(1) },
+                Token::Str("v:pk"),
+                Token::Seq { len: Some(1) },
+                Token::Str("A"),
+                Token::SeqEnd,
+                Token::MapEnd,
+                Token::Map { len: Some(1) },
+                Token::Str("older"),
+                Token::Seq { len: Some(1) },
+                Token::Str("144"),
+                Token::SeqEnd,
+                Token::MapEnd,
+                Token::SeqEnd,
+                Token::MapEnd,
+            ],
+        );
+
+        assert_de_tokens_error::<Structured<Miniscript<String, Segwitv0>>>(
+            &[Token::Str("pk(A)")],
+            "invalid value: string \"pk(A)\", expected an expression name",
+        );
+    }
+
+    #[test]
+    fn taptree_structured() {
+        let tree = TapTree::<String>::from_str("{pk(A),{pk(B),pk(C)}}").unwrap();
+        assert_tokens(
+            &Structured(tree.clone()),
+            &[
+                Token::Seq { len: Some(2) },
+                Token::Map { len: Some(1) },
+                Token::Str("pk"),
+                Token::Seq { len: Some(1) },
+                Token::Str("A"),
+                Token::SeqEnd,
+                Token::MapEnd,
+                Token::Seq { len: Some(2) },
+                Token::Map { len: Some(1) },
+                Token::Str("pk"),
+                Token::Seq { len: Some(1) },
+                Token::Str("B"),
+                Token::SeqEnd,
+                Token::MapEnd,
+                Token::Map { len: Some(1) },
+                Token::Str("pk"),
+                Token::Seq { len: Some(1) },
+                Token::Str("C"),
+                Token::SeqEnd,
+                Token::MapEnd,
+                Token::SeqEnd,
+                Token::SeqEnd,
+            ],
+        );
+
+        // The unstructured form is the plain string.
+        assert_tokens(&tree, &[Token::String("{pk(A),{pk(B),pk(C)}}")]);
+    }
+}
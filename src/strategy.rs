@@ -0,0 +1,245 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Proptest strategies
+//!
+//! With the `proptest` feature, this module provides [`Strategy`]s generating
+//! keys, sane Miniscripts of each script context, descriptors and assets, to
+//! property-test code built on this library, such as satisfaction or fee
+//! estimation logic, against realistic inputs.
+//!
+//! Miniscripts and descriptors are generated with keys from a strategy given
+//! by the caller, so that any key type can be used. They only use keys and
+//! block-height timelocks, and are rejected if they are not sane, which
+//! happens when the key strategy yields the same key twice.
+
+use bitcoin::secp256k1::{Secp256k1, SecretKey, SignOnly};
+use bitcoin::{absolute, bip32, relative};
+use proptest::collection::vec;
+use proptest::prelude::*;
+use proptest::sample::subsequence;
+
+use crate::descriptor::{
+    DefiniteDescriptorKey, Descriptor, DescriptorPublicKey, DescriptorXKey, SinglePub,
+    SinglePubKey, Wildcard,
+};
+use crate::plan::Assets;
+use crate::sync::Arc;
+use crate::{
+    AbsLockTime, ForEachKey, Legacy, Miniscript, MiniscriptKey, RelLockTime, ScriptContext,
+    Segwitv0, SigType, Tap, Terminal, Threshold,
+};
+
+/// Maximum number of keys of the multisigs generated by [`miniscript`]
+const MAX_MULTI_KEYS: usize = 3;
+
+// Secret keys are only used to generate public keys
+fn secret_key() -> impl Strategy<Value = SecretKey> {
+    any::<[u8; 32]>().prop_filter_map("invalid secret key", |b| SecretKey::from_slice(&b).ok())
+}
+
+/// Strategy generating compressed public keys.
+pub fn public_key() -> impl Strategy<Value = bitcoin::PublicKey> {
+    let secp = Arc::new(Secp256k1::signing_only());
+    secret_key().prop_map(move |sk| bitcoin::PublicKey::new(sk.public_key(&secp)))
+}
+
+/// Strategy generating descriptor public keys, which are either single
+/// compressed keys or extended keys derived at `0/*`, with or without origin.
+pub fn descriptor_public_key() -> impl Strategy<Value = DescriptorPublicKey> {
+    let secp = Arc::new(Secp256k1::signing_only());
+    let origin = proptest::option::of(any::<[u8; 4]>().prop_map(|fingerprint| {
+        let path = bip32::DerivationPath::from(vec![bip32::ChildNumber::from(0)]);
+        (bip32::Fingerprint::from(fingerprint), path)
+    }));
+    let single = (origin.clone(), public_key()).prop_map(|(origin, key)| {
+        DescriptorPublicKey::Single(SinglePub { origin, key: SinglePubKey::FullKey(key) })
+    });
+    let xpub = (origin, any::<[u8; 32]>()).prop_map(move |(origin, seed)| {
+        DescriptorPublicKey::XPub(DescriptorXKey {
+            origin,
+            xkey: xpub_from_seed(&secp, &seed),
+            derivation_path: bip32::DerivationPath::from(vec![bip32::ChildNumber::from(0)]),
+            wildcard: Wildcard::Unhardened,
+        })
+    });
+    prop_oneof![single, xpub]
+}
+
+// The master extended public key of a seed
+fn xpub_from_seed(secp: &Secp256k1<SignOnly>, seed: &[u8]) -> bip32::Xpub {
+    let xpriv = bip32::Xpriv::new_master(bitcoin::NetworkKind::Main, seed)
+        .expect("32 bytes seeds are valid");
+    bip32::Xpub::from_priv(secp, &xpriv)
+}
+
+// Builds a Miniscript which is well-typed by construction
+fn node<Pk: MiniscriptKey, Ctx: ScriptContext>(term: Terminal<Pk, Ctx>) -> Miniscript<Pk, Ctx> {
+    Miniscript::from_ast(term).expect("well-typed by construction")
+}
+
+// The `and_v(v:X,Y)` of two `B` Miniscripts
+fn and_v<Pk: MiniscriptKey, Ctx: ScriptContext>(
+    x: Miniscript<Pk, Ctx>,
+    y: Miniscript<Pk, Ctx>,
+) -> Miniscript<Pk, Ctx> {
+    let x = node(Terminal::Verify(Arc::new(x)));
+    node(Terminal::AndV(Arc::new(x), Arc::new(y)))
+}
+
+// The leaves of generated Miniscripts, all of which require a signature
+fn leaf<Pk, Ctx>(keys: BoxedStrategy<Pk>) -> BoxedStrategy<Miniscript<Pk, Ctx>>
+where
+    Pk: MiniscriptKey + 'static,
+    Ctx: ScriptContext + 'static,
+{
+    let multi = vec(keys.clone(), 1..=MAX_MULTI_KEYS)
+        .prop_flat_map(|pks| (1..=pks.len(), Just(pks)))
+        .prop_map(|(k, pks)| match Ctx::sig_type() {
+            SigType::Ecdsa => node(Terminal::Multi(Threshold::new(k, pks).unwrap())),
+            SigType::Schnorr => node(Terminal::MultiA(Threshold::new(k, pks).unwrap())),
+        });
+    let older = (keys.clone(), 1..=u16::MAX).prop_map(|(pk, height)| {
+        and_v(Miniscript::pk(pk), Miniscript::older(RelLockTime::from_height(height)))
+    });
+    let after = (keys.clone(), 1..absolute::LOCK_TIME_THRESHOLD).prop_map(|(pk, height)| {
+        let height = AbsLockTime::from_consensus(height).expect("non-zero");
+        and_v(Miniscript::pk(pk), Miniscript::after(height))
+    });
+    prop_oneof![
+        keys.clone().prop_map(Miniscript::pk),
+        keys.prop_map(Miniscript::pkh),
+        multi,
+        older,
+        after,
+    ]
+    .boxed()
+}
+
+/// Strategy generating sane Miniscripts of the script context `Ctx`, with
+/// keys from `keys`.
+///
+/// The keys must be valid in `Ctx`, for instance compressed in Segwit v0, or
+/// all Miniscripts are rejected.
+pub fn miniscript<Pk, Ctx>(
+    keys: impl Strategy<Value = Pk> + 'static,
+) -> impl Strategy<Value = Miniscript<Pk, Ctx>>
+where
+    Pk: MiniscriptKey + 'static,
+    Ctx: ScriptContext + 'static,
+{
+    let keys = keys.boxed();
+    leaf(keys.clone())
+        .prop_recursive(4, 24, 3, move |inner| {
+            let or_i = (inner.clone(), inner.clone())
+                .prop_map(|(x, y)| node(Terminal::OrI(Arc::new(x), Arc::new(y))));
+            let or_d = (keys.clone(), inner.clone())
+                .prop_map(|(pk, y)| node(Terminal::OrD(Arc::new(Miniscript::pk(pk)), Arc::new(y))));
+            let andor = (keys.clone(), inner.clone(), inner.clone()).prop_map(|(pk, y, z)| {
+                node(Terminal::AndOr(Arc::new(Miniscript::pk(pk)), Arc::new(y), Arc::new(z)))
+            });
+            prop_oneof![
+                (inner.clone(), inner).prop_map(|(x, y)| and_v(x, y)),
+                or_i,
+                or_d,
+                andor
+            ]
+        })
+        .prop_filter("insane Miniscript", |ms| {
+            ms.sanity_check().is_ok() && Ctx::check_global_validity(ms).is_ok()
+        })
+}
+
+/// Strategy generating descriptors of all types but bare ones, with keys
+/// from `keys`.
+///
+/// The keys must be compressed, and valid in all script contexts.
+pub fn descriptor<Pk>(
+    keys: impl Strategy<Value = Pk> + 'static,
+) -> impl Strategy<Value = Descriptor<Pk>>
+where
+    Pk: MiniscriptKey + 'static,
+{
+    let keys = keys.boxed();
+    let tr = (keys.clone(), vec(miniscript::<Pk, Tap>(keys.clone()), 0..4)).prop_map(
+        |(internal_key, leaves)| {
+            leaves
+                .into_iter()
+                .fold(Descriptor::builder().tr(internal_key), |tr, leaf| tr.leaf(leaf))
+                .build()
+        },
+    );
+    prop_oneof![
+        keys.clone().prop_map(Descriptor::new_pkh),
+        keys.clone().prop_map(Descriptor::new_wpkh),
+        keys.clone().prop_map(Descriptor::new_sh_wpkh),
+        miniscript::<Pk, Legacy>(keys.clone()).prop_map(Descriptor::new_sh),
+        miniscript::<Pk, Segwitv0>(keys.clone()).prop_map(Descriptor::new_wsh),
+        miniscript::<Pk, Segwitv0>(keys).prop_map(Descriptor::new_sh_wsh),
+        tr,
+    ]
+    .prop_filter_map("invalid descriptor", Result::ok)
+}
+
+/// Strategy generating assets to plan spends of `descriptor`, made of a
+/// subset of its keys and of block-height timelocks.
+pub fn assets(descriptor: &Descriptor<DefiniteDescriptorKey>) -> impl Strategy<Value = Assets> {
+    let mut keys = vec![];
+    descriptor.for_each_key(|pk| {
+        keys.push(pk.as_descriptor_public_key().clone());
+        true
+    });
+    let n_keys = keys.len();
+    let older = proptest::option::of(any::<u16>().prop_map(relative::LockTime::from_height));
+    let after = proptest::option::of(
+        (0..absolute::LOCK_TIME_THRESHOLD).prop_map(absolute::LockTime::from_consensus),
+    );
+    (subsequence(keys, 0..=n_keys), older, after).prop_map(|(keys, older, after)| {
+        let mut assets = keys.into_iter().fold(Assets::new(), Assets::add);
+        if let Some(older) = older {
+            assets = assets.older(older);
+        }
+        if let Some(after) = after {
+            assets = assets.after(after);
+        }
+        assets
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn generated_miniscripts(ms in miniscript::<_, Segwitv0>(public_key())) {
+            prop_assert_eq!(Miniscript::from_str(&ms.to_string()).unwrap(), ms);
+        }
+
+        #[test]
+        fn generated_descriptors(desc in descriptor(descriptor_public_key())) {
+            desc.sanity_check().unwrap();
+            prop_assert_eq!(Descriptor::from_str(&desc.to_string()).unwrap(), desc);
+        }
+
+        #[test]
+        fn generated_assets(
+            (desc, assets) in descriptor(descriptor_public_key()).prop_flat_map(|desc| {
+                let desc = desc.at_derivation_index(0).unwrap();
+                (Just(desc.clone()), assets(&desc))
+            })
+        ) {
+            if let Ok(plan) = desc.plan(&assets) {
+                // All spending paths require a signature
+                prop_assert!(!assets.keys.is_empty());
+                if let Some(lock_time) = plan.required_locktime() {
+                    let max = assets.absolute_timelock;
+                    prop_assert!(max.map_or(false, |max| lock_time.is_implied_by(max)));
+                }
+            }
+        }
+    }
+}
@@ -1,11 +1,13 @@
 // SPDX-License-Identifier: CC0-1.0
 
 use core::convert::TryFrom;
+use core::mem;
 
 use bitcoin::constants::MAX_SCRIPT_ELEMENT_SIZE;
 use bitcoin::hashes::Hash;
 use bitcoin::script::{self, PushBytes, ScriptBuf};
-use bitcoin::PubkeyHash;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{opcodes, PubkeyHash, ScriptHash, WScriptHash};
 
 use crate::miniscript::context;
 use crate::miniscript::satisfy::Placeholder;
@@ -13,6 +15,52 @@ use crate::prelude::*;
 use crate::{MiniscriptKey, ScriptContext, ToPublicKey};
 pub(crate) fn varint_len(n: usize) -> usize { bitcoin::VarInt(n as u64).size() }
 
+// Takes the bytes of `script`, emptied, to build another script reusing its
+// allocation
+pub(crate) fn take_script_bytes(script: &mut ScriptBuf) -> Vec<u8> {
+    let mut bytes = mem::take(script).into_bytes();
+    bytes.clear();
+    bytes
+}
+
+// Replaces the redeem script in `bytes` by the P2SH script pubkey paying to it
+pub(crate) fn redeem_script_to_p2sh(mut bytes: Vec<u8>) -> ScriptBuf {
+    let hash = ScriptHash::hash(&bytes);
+    bytes.clear();
+    script::Builder::from(bytes)
+        .push_opcode(opcodes::all::OP_HASH160)
+        .push_slice(hash)
+        .push_opcode(opcodes::all::OP_EQUAL)
+        .into_script()
+}
+
+// Replaces the witness script in `bytes` by the P2WSH script pubkey paying to it
+pub(crate) fn witness_script_to_p2wsh(mut bytes: Vec<u8>) -> ScriptBuf {
+    let hash = WScriptHash::hash(&bytes);
+    bytes.clear();
+    script::Builder::from(bytes)
+        .push_opcode(opcodes::all::OP_PUSHBYTES_0)
+        .push_slice(hash)
+        .into_script()
+}
+
+// The secp256k1 context used where none is provided by the caller
+#[cfg(feature = "global-context")]
+pub(crate) type VerifyContext = bitcoin::secp256k1::All;
+#[cfg(not(feature = "global-context"))]
+pub(crate) type VerifyContext = bitcoin::secp256k1::VerifyOnly;
+
+// Calls `f` with the global secp256k1 context if the `global-context` feature
+// is enabled, or with a newly created verification context otherwise
+#[cfg(feature = "global-context")]
+pub(crate) fn with_verify_context<R>(f: impl FnOnce(&Secp256k1<VerifyContext>) -> R) -> R {
+    f(bitcoin::secp256k1::SECP256K1)
+}
+#[cfg(not(feature = "global-context"))]
+pub(crate) fn with_verify_context<R>(f: impl FnOnce(&Secp256k1<VerifyContext>) -> R) -> R {
+    f(&Secp256k1::verification_only())
+}
+
 pub(crate) trait ItemSize {
     fn size(&self) -> usize;
 }
@@ -30,7 +78,11 @@ impl<Pk: MiniscriptKey> ItemSize for Placeholder<Pk> {
             | Placeholder::Sha256Preimage(_)
             | Placeholder::Hash256Preimage(_)
             | Placeholder::Ripemd160Preimage(_)
-            | Placeholder::Hash160Preimage(_) => 33,
+            | Placeholder::Hash160Preimage(_)
+            | Placeholder::PendingSha256Preimage(_)
+            | Placeholder::PendingHash256Preimage(_)
+            | Placeholder::PendingRipemd160Preimage(_)
+            | Placeholder::PendingHash160Preimage(_) => 33,
             Placeholder::PushOne => 2, // On legacy this should be 1 ?
             Placeholder::PushZero => 1,
             Placeholder::TapScript(s) => s.len(),
@@ -106,3 +158,65 @@ impl MsKeyBuilder for script::Builder {
         }
     }
 }
+
+/// Serializes a value as its `Display` form, and deserializes it with `FromStr`.
+#[cfg(feature = "serde")]
+pub(crate) struct StrForm<T>(pub T);
+
+#[cfg(feature = "serde")]
+impl<T: core::fmt::Display> serde::Serialize for StrForm<&T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self.0)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T> serde::Deserialize<'de> for StrForm<T>
+where
+    T: core::str::FromStr,
+    T::Err: core::fmt::Display,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        T::from_str(&s)
+            .map(StrForm)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Deserializes the name or index of an enum variant or struct field into its index
+/// in the given list of names.
+#[cfg(feature = "serde")]
+pub(crate) struct Identifier(pub &'static [&'static str]);
+
+#[cfg(feature = "serde")]
+impl<'de> serde::de::DeserializeSeed<'de> for Identifier {
+    type Value = usize;
+
+    fn deserialize<D: serde::Deserializer<'de>>(self, deserializer: D) -> Result<usize, D::Error> {
+        deserializer.deserialize_identifier(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::de::Visitor<'de> for Identifier {
+    type Value = usize;
+
+    fn expecting(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "one of {:?}", self.0)
+    }
+
+    fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<usize, E> {
+        match usize::try_from(v) {
+            Ok(idx) if idx < self.0.len() => Ok(idx),
+            _ => Err(E::invalid_value(serde::de::Unexpected::Unsigned(v), &self)),
+        }
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<usize, E> {
+        self.0
+            .iter()
+            .position(|name| *name == v)
+            .ok_or_else(|| E::invalid_value(serde::de::Unexpected::Str(v), &self))
+    }
+}